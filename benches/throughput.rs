@@ -0,0 +1,136 @@
+//! Pipelined SET and GET throughput from one connection and from several at
+//! once, which only scales while commands on different keys don't share a
+//! lock, and the rate a single deep pipeline is answered at, which rests on
+//! its replies going out in a few large writes. There is no stable bench
+//! harness, so these run as ignored tests:
+//!
+//! ```text
+//! cargo test --release --bench throughput -- --ignored --nocapture
+//! ```
+
+use std::time::{Duration, Instant};
+
+use redis_starter_rust::{server, store::Store};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Commands written before reading any of their replies.
+const PIPELINE: usize = 100;
+const ROUNDS: usize = 200;
+/// Commands in each of `deep_pipeline`'s writes.
+const DEEP_PIPELINE: usize = 10_000;
+
+/// A store saving under the system temp dir, since the writes here set off
+/// the default save rules.
+fn store(name: &str) -> Store {
+    let dir = std::env::temp_dir().join(format!("redis-rust-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let store = Store::new();
+    store.config().set_dir(dir.display().to_string());
+    store
+}
+
+fn request(args: &[&str]) -> Vec<u8> {
+    let mut request = format!("*{}\r\n", args.len());
+    for arg in args {
+        request.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+    }
+    request.into_bytes()
+}
+
+/// Sends `ROUNDS` pipelines of SETs, each followed by GETs of the same keys,
+/// and returns how many commands were answered.
+async fn client(addr: std::net::SocketAddr, id: usize) -> usize {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.set_nodelay(true).unwrap();
+    let value = "v".repeat(32);
+    let mut sets = vec![];
+    let mut gets = vec![];
+    for i in 0..PIPELINE {
+        let key = format!("client-{}:key-{}", id, i);
+        sets.extend(request(&["SET", &key, &value]));
+        gets.extend(request(&["GET", &key]));
+    }
+    let set_replies = PIPELINE * "+OK\r\n".len();
+    let get_replies = PIPELINE * format!("$32\r\n{}\r\n", value).len();
+    let mut replies = vec![0; set_replies.max(get_replies)];
+    for _ in 0..ROUNDS {
+        stream.write_all(&sets).await.unwrap();
+        stream
+            .read_exact(&mut replies[..set_replies])
+            .await
+            .unwrap();
+        stream.write_all(&gets).await.unwrap();
+        stream
+            .read_exact(&mut replies[..get_replies])
+            .await
+            .unwrap();
+    }
+    2 * PIPELINE * ROUNDS
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "a benchmark, see the module docs"]
+async fn pipelined_set_and_get() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server::run(
+        listener,
+        store("pipelined"),
+        std::future::pending::<()>(),
+    ));
+
+    for connections in [1, 2, 4, 8, 16] {
+        let started = Instant::now();
+        let clients: Vec<_> = (0..connections)
+            .map(|id| tokio::spawn(client(addr, id)))
+            .collect();
+        let mut commands = 0;
+        for client in clients {
+            commands += client.await.unwrap();
+        }
+        let elapsed = started.elapsed().max(Duration::from_micros(1));
+        println!(
+            "{:>2} connections: {:>9.0} commands/s",
+            connections,
+            commands as f64 / elapsed.as_secs_f64()
+        );
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "a benchmark, see the module docs"]
+async fn deep_pipeline() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server::run(
+        listener,
+        store("deep-pipeline"),
+        std::future::pending::<()>(),
+    ));
+
+    let stream = TcpStream::connect(addr).await.unwrap();
+    stream.set_nodelay(true).unwrap();
+    let (mut reader, mut writer) = stream.into_split();
+    let rounds = ROUNDS / 10;
+    let started = Instant::now();
+    // replies are read as they come, so the server never waits on the client
+    let writes = tokio::spawn(async move {
+        let pings = request(&["PING"]).repeat(DEEP_PIPELINE);
+        for _ in 0..rounds {
+            writer.write_all(&pings).await.unwrap();
+        }
+        writer
+    });
+    let mut replies = vec![0; DEEP_PIPELINE * "+PONG\r\n".len()];
+    for _ in 0..rounds {
+        reader.read_exact(&mut replies).await.unwrap();
+    }
+    writes.await.unwrap();
+    let elapsed = started.elapsed().max(Duration::from_micros(1));
+    println!(
+        "pipeline of {}: {:>9.0} commands/s",
+        DEEP_PIPELINE,
+        (rounds * DEEP_PIPELINE) as f64 / elapsed.as_secs_f64()
+    );
+}
@@ -0,0 +1,230 @@
+//! A typed API for using the keyspace as an in-process cache, without going
+//! through RESP.
+//!
+//! ```
+//! use redis_starter_rust::cache::Cache;
+//!
+//! let cache = Cache::new();
+//! cache.set("greeting", "hello")?;
+//! assert_eq!(cache.get_str("greeting")?.as_deref(), Some("hello"));
+//!
+//! cache.zadd("scores", "ann", 12.0)?;
+//! cache.zadd("scores", "bob", 7.5)?;
+//! assert_eq!(cache.zrange("scores")?, [("bob".into(), 7.5), ("ann".into(), 12.0)]);
+//! # anyhow::Ok(())
+//! ```
+//!
+//! Writes behave as the matching commands do: they raise keyspace events,
+//! count towards the save rules and go to the AOF and any replicas.
+//!
+//! `Cache` is the stable surface for embedding: its methods only change in
+//! a breaking release. `Store` is shared with the server and changes with it.
+
+use bytes::Bytes;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::{
+    publisher::{publish_now, Action},
+    store::{notify::EventClass, Store, WrongType, DATABASES},
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct Cache {
+    store: Store,
+}
+
+impl Cache {
+    /// An empty cache of its own.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A cache over `store`'s keyspace, e.g. one a server is also serving,
+    /// starting out on database 0.
+    pub fn from_store(store: &Store) -> Self {
+        Self {
+            store: store.for_client(),
+        }
+    }
+
+    /// The store underneath, for what this API doesn't cover.
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    /// Switches this cache, and its clones, to database `db`.
+    ///
+    /// Panics if `db` isn't below `DATABASES`.
+    pub fn select(&self, db: usize) {
+        assert!(db < DATABASES, "database {} is out of range", db);
+        self.store.select(db);
+    }
+
+    pub fn get(&self, key: impl Into<Bytes>) -> Result<Option<Bytes>, WrongType> {
+        self.store.get_string(&key.into())
+    }
+
+    /// The string at `key`, or None if there's none or it isn't UTF-8.
+    pub fn get_str(&self, key: impl Into<Bytes>) -> Result<Option<String>, WrongType> {
+        Ok(self
+            .get(key)?
+            .and_then(|value| String::from_utf8(value.into()).ok()))
+    }
+
+    /// Sets `key` to `value`, which never expires.
+    pub fn set(&self, key: impl Into<Bytes>, value: impl Into<Bytes>) -> anyhow::Result<()> {
+        self.set_string(key.into(), value.into(), None)
+    }
+
+    /// Sets `key` to `value`, expiring after `ttl`.
+    ///
+    /// ```
+    /// use redis_starter_rust::cache::Cache;
+    /// use std::time::Duration;
+    ///
+    /// let cache = Cache::new();
+    /// cache.set_ex("session", "token", Duration::from_secs(60))?;
+    /// let ttl = cache.ttl("session").flatten().unwrap();
+    /// assert!(ttl <= Duration::from_secs(60) && ttl > Duration::from_secs(59));
+    /// # anyhow::Ok(())
+    /// ```
+    pub fn set_ex(
+        &self,
+        key: impl Into<Bytes>,
+        value: impl Into<Bytes>,
+        ttl: Duration,
+    ) -> anyhow::Result<()> {
+        self.set_string(key.into(), value.into(), Some(ttl))
+    }
+
+    fn set_string(&self, key: Bytes, value: Bytes, ttl: Option<Duration>) -> anyhow::Result<()> {
+        let millis = ttl.map(|ttl| (ttl.as_millis() as u64).max(1));
+        self.store.set(
+            key.clone(),
+            value.clone(),
+            millis.map(Duration::from_millis),
+        );
+        self.store.notify(EventClass::String, "set", &key);
+        publish_now(
+            &self.store,
+            Action::Set {
+                key,
+                value,
+                expiry: millis,
+            },
+        )
+    }
+
+    /// Removes `key`, returning whether it existed.
+    pub fn del(&self, key: impl Into<Bytes>) -> anyhow::Result<bool> {
+        let key = key.into();
+        if !self.store.del(key.clone()) {
+            return Ok(false);
+        }
+        publish_now(&self.store, Action::Del { key })?;
+        Ok(true)
+    }
+
+    pub fn exists(&self, key: impl Into<Bytes>) -> bool {
+        self.store.expiry(&key.into()).is_some()
+    }
+
+    /// Makes `key` expire at `at`, returning false if there's no such key.
+    ///
+    /// ```
+    /// use redis_starter_rust::cache::Cache;
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// let cache = Cache::new();
+    /// cache.set("k", "v")?;
+    /// assert!(cache.expire_at("k", SystemTime::now() - Duration::from_secs(1))?);
+    /// assert!(!cache.exists("k"));
+    /// assert!(!cache.expire_at("missing", SystemTime::now())?);
+    /// # anyhow::Ok(())
+    /// ```
+    pub fn expire_at(&self, key: impl Into<Bytes>, at: SystemTime) -> anyhow::Result<bool> {
+        let key = key.into();
+        let expires_at = at
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+        if !self.store.set_expiry(&key, Some(expires_at)) {
+            return Ok(false);
+        }
+        self.store.notify(EventClass::Generic, "expire", &key);
+        publish_now(&self.store, Action::PExpireAt { key, expires_at })?;
+        Ok(true)
+    }
+
+    /// Makes `key` never expire, returning false if there's no such key.
+    pub fn persist(&self, key: impl Into<Bytes>) -> anyhow::Result<bool> {
+        let key = key.into();
+        if !self.store.set_expiry(&key, None) {
+            return Ok(false);
+        }
+        self.store.notify(EventClass::Generic, "persist", &key);
+        publish_now(&self.store, Action::Persist { key })?;
+        Ok(true)
+    }
+
+    /// How long `key` has left: None if there's no such key, Some(None) if
+    /// it never expires.
+    pub fn ttl(&self, key: impl Into<Bytes>) -> Option<Option<Duration>> {
+        let expiry = self.store.expiry(&key.into())?;
+        Some(expiry.map(|at| at.saturating_duration_since(Instant::now())))
+    }
+
+    /// Sets `member`'s score in the sorted set at `key`, creating the set if
+    /// needed. Returns whether the member is new. Fails with `WrongType` if
+    /// `key` holds another type.
+    pub fn zadd(
+        &self,
+        key: impl Into<Bytes>,
+        member: impl Into<Bytes>,
+        score: f64,
+    ) -> anyhow::Result<bool> {
+        let (key, member) = (key.into(), member.into());
+        let added = self
+            .store
+            .with_sorted_set_mut(key.clone(), |zset| zset.insert(member.clone(), score))?;
+        self.store.notify(EventClass::SortedSet, "zadd", &key);
+        publish_now(
+            &self.store,
+            Action::ZAdd {
+                key,
+                members: vec![(score, member)],
+            },
+        )?;
+        Ok(added)
+    }
+
+    pub fn zscore(
+        &self,
+        key: impl Into<Bytes>,
+        member: impl AsRef<[u8]>,
+    ) -> Result<Option<f64>, WrongType> {
+        self.store
+            .with_sorted_set(&key.into(), |zset| zset.score(member.as_ref()))
+    }
+
+    /// Every member of the sorted set at `key` with its score, lowest first.
+    pub fn zrange(&self, key: impl Into<Bytes>) -> Result<Vec<(Bytes, f64)>, WrongType> {
+        self.store.with_sorted_set(&key.into(), |zset| {
+            zset.iter()
+                .map(|(member, score)| (member.clone(), score))
+                .collect()
+        })
+    }
+
+    /// The keys in the selected database as of now. Iterating holds no lock,
+    /// so the cache can change meanwhile.
+    pub fn keys(&self) -> impl Iterator<Item = Bytes> {
+        let db = self.store.db();
+        let snapshot = self.store.snapshot();
+        let keys: Vec<Bytes> = snapshot
+            .iter()
+            .filter(|(key_db, ..)| *key_db == db)
+            .map(|(_, key, ..)| key.clone())
+            .collect();
+        keys.into_iter()
+    }
+}
@@ -1,6 +1,23 @@
+use anyhow::Context;
 use clap::Parser;
 
-use crate::info::Info;
+use crate::{
+    cluster::BUS_PORT_OFFSET,
+    info::Info,
+    log::Level,
+    publisher::DEFAULT_BACKLOG_SIZE,
+    store::{
+        clients::DEFAULT_MAXCLIENTS,
+        config::{
+            parse_save_rules, SaveRule, DEFAULT_APPENDFILENAME, DEFAULT_DBFILENAME, DEFAULT_DIR,
+            DEFAULT_ENABLE_DEBUG_COMMAND, DEFAULT_SAVE,
+        },
+        notify::NotifyFlags,
+        sentinel::{Address, DEFAULT_DOWN_AFTER},
+        DEFAULT_REPL_TIMEOUT,
+    },
+    tls::{AuthClients, TlsFiles},
+};
 
 #[derive(Parser, Debug)]
 #[clap(name = "redis-rust", version, author, about = "A limited Redis server")]
@@ -10,6 +27,110 @@ pub struct Cli {
 
     #[clap(long, value_delimiter = ' ', num_args = 2)]
     pub replicaof: Option<Vec<String>>,
+
+    /// Keyspace notification classes to publish, e.g. "KEA"
+    #[clap(long, default_value = "")]
+    pub notify_keyspace_events: String,
+
+    /// Directory the RDB file is kept in
+    #[clap(long, default_value = DEFAULT_DIR)]
+    pub dir: String,
+
+    /// Name of the RDB file loaded at startup
+    #[clap(long, default_value = DEFAULT_DBFILENAME)]
+    pub dbfilename: String,
+
+    /// Whether writes are logged to the append-only file
+    #[clap(long, default_value = "no", value_parser = ["yes", "no"])]
+    pub appendonly: String,
+
+    /// Name of the append-only file, in --dir
+    #[clap(long, default_value = DEFAULT_APPENDFILENAME)]
+    pub appendfilename: String,
+
+    /// File ACL users are loaded from at startup and saved to by ACL SAVE
+    #[clap(long, default_value = "")]
+    pub aclfile: String,
+
+    /// Who may run DEBUG: anyone, local connections only, or nobody
+    #[clap(long, default_value = DEFAULT_ENABLE_DEBUG_COMMAND, value_parser = ["yes", "local", "no"])]
+    pub enable_debug_command: String,
+
+    /// RDB save rules as "<seconds> <changes>" pairs, or "" for none
+    #[clap(long, default_value = DEFAULT_SAVE)]
+    pub save: String,
+
+    /// Seconds a replica waits on its master before dropping the link
+    #[clap(long, default_value_t = DEFAULT_REPL_TIMEOUT.as_secs())]
+    pub repl_timeout: u64,
+
+    /// Seconds an idle client is kept connected; 0 keeps it forever
+    #[clap(long, default_value_t = 0)]
+    pub timeout: u64,
+
+    /// How many clients may be connected at once
+    #[clap(long, default_value_t = DEFAULT_MAXCLIENTS)]
+    pub maxclients: usize,
+
+    /// Bytes of the replication stream kept for replicas that reconnect
+    #[clap(long, default_value_t = DEFAULT_BACKLOG_SIZE)]
+    pub repl_backlog_size: usize,
+
+    /// Whether the server answers CLUSTER commands as a cluster node
+    #[clap(long, default_value = "no", value_parser = ["yes", "no"])]
+    pub cluster_enabled: String,
+
+    /// Port the cluster bus listens on; 0 for the client port plus 10000
+    #[clap(long, default_value_t = 0)]
+    pub cluster_port: u16,
+
+    /// Port TLS clients connect to; 0 for no TLS listener
+    #[clap(long, default_value_t = 0)]
+    pub tls_port: u16,
+
+    /// PEM certificate chain this server presents over TLS
+    #[clap(long, default_value = "")]
+    pub tls_cert_file: String,
+
+    /// PEM private key of --tls-cert-file
+    #[clap(long, default_value = "")]
+    pub tls_key_file: String,
+
+    /// PEM CA certificates that client and master certificates must chain to
+    #[clap(long, default_value = "")]
+    pub tls_ca_cert_file: String,
+
+    /// Whether TLS clients must present a certificate
+    #[clap(long, default_value = "yes", value_parser = ["yes", "no", "optional"])]
+    pub tls_auth_clients: String,
+
+    /// Whether a replica connects to its master over TLS
+    #[clap(long, default_value = "no", value_parser = ["yes", "no"])]
+    pub tls_replication: String,
+
+    /// Port to serve Prometheus metrics over HTTP on; 0 for none
+    #[clap(long, default_value_t = 0)]
+    pub metrics_port: u16,
+
+    /// How much to log: debug, verbose, notice or warning
+    #[clap(long, default_value = "notice", value_parser = ["debug", "verbose", "notice", "warning"])]
+    pub loglevel: String,
+
+    /// File to append the log to; empty for stderr
+    #[clap(long, default_value = "")]
+    pub logfile: String,
+
+    /// Run as a sentinel, watching the master --sentinel-monitor names
+    #[clap(long, requires = "sentinel_monitor")]
+    pub sentinel: bool,
+
+    /// The master a sentinel watches, as "<name> <host> <port>"
+    #[clap(long, value_delimiter = ' ', num_args = 3)]
+    pub sentinel_monitor: Option<Vec<String>>,
+
+    /// Milliseconds the master may go unanswered before a sentinel fails it over
+    #[clap(long, default_value_t = DEFAULT_DOWN_AFTER.as_millis() as u64)]
+    pub down_after_milliseconds: u64,
 }
 
 impl Cli {
@@ -26,6 +147,56 @@ impl Cli {
             .replication_role(Some(role.into()))
             .build()
     }
+
+    /// The port the cluster bus listens on.
+    pub fn cluster_bus_port(&self) -> anyhow::Result<u16> {
+        match self.cluster_port {
+            0 => self
+                .port
+                .checked_add(BUS_PORT_OFFSET)
+                .context("no cluster bus port above the client port, set --cluster-port"),
+            port => Ok(port),
+        }
+    }
+
+    /// The name and address of the master to watch, when running as a
+    /// sentinel.
+    pub fn sentinel_master(&self) -> anyhow::Result<Option<(String, Address)>> {
+        match &self.sentinel_monitor {
+            Some(monitor) if self.sentinel => Ok(Some((
+                monitor[0].clone(),
+                Address {
+                    host: monitor[1].clone(),
+                    port: monitor[2]
+                        .parse()
+                        .context("invalid --sentinel-monitor port")?,
+                },
+            ))),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn save_rules(&self) -> anyhow::Result<Vec<SaveRule>> {
+        parse_save_rules(&self.save).context("invalid save rules")
+    }
+
+    pub fn tls_files(&self) -> TlsFiles {
+        TlsFiles {
+            cert_file: self.tls_cert_file.clone(),
+            key_file: self.tls_key_file.clone(),
+            ca_cert_file: self.tls_ca_cert_file.clone(),
+            auth_clients: AuthClients::parse(&self.tls_auth_clients).unwrap_or_default(),
+        }
+    }
+
+    pub fn log_level(&self) -> Level {
+        Level::parse(&self.loglevel).unwrap_or(Level::Notice)
+    }
+
+    pub fn notify_flags(&self) -> anyhow::Result<NotifyFlags> {
+        NotifyFlags::parse(&self.notify_keyspace_events)
+            .context("invalid notify-keyspace-events flags")
+    }
 }
 
 #[cfg(test)]
@@ -40,28 +211,164 @@ mod tests {
 
     #[test]
     fn test_custom_port() {
-        let cli = Cli::parse_from(&["redis-rust", "--port", "1234"]);
+        let cli = Cli::parse_from(["redis-rust", "--port", "1234"]);
         assert_eq!(cli.port, 1234);
     }
 
     #[test]
     fn test_with_invalid_port() {
-        let result = Cli::try_parse_from(&["redis-rust", "--port", "not-a-number"]);
+        let result = Cli::try_parse_from(["redis-rust", "--port", "not-a-number"]);
         assert!(result.is_err());
     }
 
     #[test]
     fn test_replica_of() {
-        let cli = Cli::parse_from(&["redis-rust", "--replicaof", "host.com", "4321"]);
+        let cli = Cli::parse_from(["redis-rust", "--replicaof", "host.com", "4321"]);
         assert_eq!(
             cli.replicaof,
             Some(vec!["host.com".to_string(), "4321".to_string()])
         );
     }
 
+    #[test]
+    fn test_notify_keyspace_events() {
+        let cli = Cli::parse_from(["redis-rust", "--notify-keyspace-events", "Kx"]);
+        assert_eq!(cli.notify_flags().unwrap().to_string(), "xK");
+        let cli = Cli::parse_from(["redis-rust", "--notify-keyspace-events", "?"]);
+        assert!(cli.notify_flags().is_err());
+    }
+
+    #[test]
+    fn test_rdb_file() {
+        let cli = Cli::parse_from(["redis-rust"]);
+        assert_eq!(
+            (cli.dir.as_str(), cli.dbfilename.as_str()),
+            (".", "dump.rdb")
+        );
+        let cli = Cli::parse_from([
+            "redis-rust",
+            "--dir",
+            "/tmp/redis-files",
+            "--dbfilename",
+            "data.rdb",
+        ]);
+        assert_eq!(cli.dir, "/tmp/redis-files");
+        assert_eq!(cli.dbfilename, "data.rdb");
+    }
+
+    #[test]
+    fn test_save_rules() -> anyhow::Result<()> {
+        let cli = Cli::parse_from(["redis-rust"]);
+        assert_eq!(cli.save_rules()?.len(), 3);
+        let cli = Cli::parse_from(["redis-rust", "--save", ""]);
+        assert_eq!(cli.save_rules()?, vec![]);
+        let cli = Cli::parse_from(["redis-rust", "--save", "10 1 5"]);
+        assert!(cli.save_rules().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_timeout() {
+        let cli = Cli::parse_from(["redis-rust"]);
+        assert_eq!(cli.timeout, 0);
+        let cli = Cli::parse_from(["redis-rust", "--timeout", "300"]);
+        assert_eq!(cli.timeout, 300);
+    }
+
+    #[test]
+    fn test_tls_files() {
+        let cli = Cli::parse_from(["redis-rust"]);
+        assert_eq!(
+            (cli.tls_port, cli.tls_files().auth_clients),
+            (0, AuthClients::Yes)
+        );
+        let cli = Cli::parse_from([
+            "redis-rust",
+            "--tls-port",
+            "6380",
+            "--tls-cert-file",
+            "redis.crt",
+            "--tls-auth-clients",
+            "optional",
+        ]);
+        let files = cli.tls_files();
+        assert_eq!(cli.tls_port, 6380);
+        assert_eq!(files.cert_file, "redis.crt");
+        assert_eq!(files.auth_clients, AuthClients::Optional);
+    }
+
+    #[test]
+    fn test_log_options() {
+        let cli = Cli::parse_from(["redis-rust"]);
+        assert_eq!((cli.log_level(), cli.logfile.as_str()), (Level::Notice, ""));
+        let cli = Cli::parse_from(["redis-rust", "--loglevel", "debug"]);
+        assert_eq!(cli.log_level(), Level::Debug);
+        assert!(Cli::try_parse_from(["redis-rust", "--loglevel", "loud"]).is_err());
+        assert!(Cli::try_parse_from(["redis-rust", "--tls-auth-clients", "maybe"]).is_err());
+    }
+
+    #[test]
+    fn test_repl_timeout() {
+        let cli = Cli::parse_from(["redis-rust"]);
+        assert_eq!(cli.repl_timeout, 60);
+        let cli = Cli::parse_from(["redis-rust", "--repl-timeout", "5"]);
+        assert_eq!(cli.repl_timeout, 5);
+    }
+
+    #[test]
+    fn test_repl_backlog_size() {
+        let cli = Cli::parse_from(["redis-rust"]);
+        assert_eq!(cli.repl_backlog_size, DEFAULT_BACKLOG_SIZE);
+        let cli = Cli::parse_from(["redis-rust", "--repl-backlog-size", "1024"]);
+        assert_eq!(cli.repl_backlog_size, 1024);
+    }
+
+    #[test]
+    fn test_cluster_enabled() {
+        let cli = Cli::parse_from(["redis-rust"]);
+        assert_eq!(cli.cluster_enabled, "no");
+        let cli = Cli::parse_from(["redis-rust", "--cluster-enabled", "yes"]);
+        assert_eq!(cli.cluster_enabled, "yes");
+        let result = Cli::try_parse_from(["redis-rust", "--cluster-enabled", "maybe"]);
+        assert!(result.is_err());
+        assert_eq!(cli.cluster_bus_port().unwrap(), 16379);
+        let cli = Cli::parse_from(["redis-rust", "--cluster-port", "7100"]);
+        assert_eq!(cli.cluster_bus_port().unwrap(), 7100);
+        let cli = Cli::parse_from(["redis-rust", "--port", "60000"]);
+        assert!(cli.cluster_bus_port().is_err());
+    }
+
+    #[test]
+    fn test_sentinel() -> anyhow::Result<()> {
+        let cli = Cli::parse_from(["redis-rust"]);
+        assert_eq!(cli.sentinel_master()?, None);
+        assert_eq!(cli.down_after_milliseconds, 30000);
+        assert!(Cli::try_parse_from(["redis-rust", "--sentinel"]).is_err());
+        let cli = Cli::parse_from([
+            "redis-rust",
+            "--sentinel",
+            "--sentinel-monitor",
+            "mymaster",
+            "127.0.0.1",
+            "6380",
+            "--down-after-milliseconds",
+            "500",
+        ]);
+        let address = Address {
+            host: "127.0.0.1".to_string(),
+            port: 6380,
+        };
+        assert_eq!(
+            cli.sentinel_master()?,
+            Some(("mymaster".to_string(), address))
+        );
+        assert_eq!(cli.down_after_milliseconds, 500);
+        Ok(())
+    }
+
     #[test]
     fn test_to_info() {
-        let cli = Cli::parse_from(&[
+        let cli = Cli::parse_from([
             "redis-rust",
             "--port",
             "1234",
@@ -0,0 +1,134 @@
+//! A client for this server, or any other that speaks RESP2, built on the
+//! same `Connection` and `Frame` code the server uses.
+
+use anyhow::{anyhow, bail};
+use bytes::Bytes;
+use tokio::net::{
+    tcp::{OwnedReadHalf, OwnedWriteHalf},
+    TcpStream, ToSocketAddrs,
+};
+
+use crate::{comms::Comms, connection::Connection, frame::Frame};
+
+pub struct Client {
+    connection: Connection<OwnedReadHalf, OwnedWriteHalf>,
+}
+
+/// A message published to a channel a `Subscriber` listens on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub channel: String,
+    pub content: Bytes,
+}
+
+/// A client in subscriber mode, which only receives messages.
+pub struct Subscriber {
+    client: Client,
+    channels: Vec<String>,
+}
+
+impl Client {
+    pub async fn connect(addr: impl ToSocketAddrs) -> anyhow::Result<Client> {
+        let (reader, writer) = TcpStream::connect(addr).await?.into_split();
+        Ok(Client {
+            connection: Connection::new(reader, writer),
+        })
+    }
+
+    /// Sends `args`, the command name first, and returns the reply as it
+    /// came, error replies included.
+    pub async fn call<A: AsRef<[u8]>>(&mut self, args: &[A]) -> anyhow::Result<Frame> {
+        self.send(args).await?;
+        self.read_reply().await
+    }
+
+    pub async fn get(&mut self, key: &str) -> anyhow::Result<Option<Bytes>> {
+        match reply(self.call(&["GET", key]).await?)? {
+            Frame::Bulk(value) => Ok(Some(value)),
+            Frame::Null => Ok(None),
+            frame => Err(unexpected(frame)),
+        }
+    }
+
+    pub async fn set(&mut self, key: &str, value: impl AsRef<[u8]>) -> anyhow::Result<()> {
+        let args: [&[u8]; 3] = [b"SET", key.as_bytes(), value.as_ref()];
+        match reply(self.call(&args).await?)? {
+            Frame::OK => Ok(()),
+            Frame::Simple(status) if status == "OK" => Ok(()),
+            frame => Err(unexpected(frame)),
+        }
+    }
+
+    /// Subscribes to `channels`, which turns the client into a `Subscriber`.
+    pub async fn subscribe(mut self, channels: &[&str]) -> anyhow::Result<Subscriber> {
+        let mut args = vec!["SUBSCRIBE"];
+        args.extend_from_slice(channels);
+        self.send(&args).await?;
+        // each channel is confirmed with a reply of its own
+        for _ in channels {
+            match reply(self.read_reply().await?)? {
+                Frame::Array(parts) if parts.first().is_some_and(|kind| *kind == "subscribe") => {}
+                frame => return Err(unexpected(frame)),
+            }
+        }
+        Ok(Subscriber {
+            client: self,
+            channels: channels.iter().map(|channel| channel.to_string()).collect(),
+        })
+    }
+
+    async fn send<A: AsRef<[u8]>>(&mut self, args: &[A]) -> anyhow::Result<()> {
+        let request = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_ref())))
+                .collect(),
+        );
+        Ok(self.connection.write_frame(&request).await?)
+    }
+
+    async fn read_reply(&mut self) -> anyhow::Result<Frame> {
+        self.connection
+            .read_frame()
+            .await?
+            .ok_or_else(|| anyhow!("connection closed by the server"))
+    }
+}
+
+impl Subscriber {
+    pub fn channels(&self) -> &[String] {
+        &self.channels
+    }
+
+    /// Waits for the next message, or returns `None` once the server hangs
+    /// up.
+    pub async fn next_message(&mut self) -> anyhow::Result<Option<Message>> {
+        let Some(frame) = self.client.connection.read_frame().await? else {
+            return Ok(None);
+        };
+        match frame {
+            Frame::Array(parts) if parts.len() == 3 && parts[0] == "message" => {
+                let mut parts = parts.into_iter().skip(1);
+                match (parts.next(), parts.next()) {
+                    (Some(Frame::Bulk(channel)), Some(Frame::Bulk(content))) => Ok(Some(Message {
+                        channel: String::from_utf8_lossy(&channel).into_owned(),
+                        content,
+                    })),
+                    _ => bail!("malformed message"),
+                }
+            }
+            frame => Err(unexpected(frame)),
+        }
+    }
+}
+
+/// `frame`, unless it is an error reply.
+fn reply(frame: Frame) -> anyhow::Result<Frame> {
+    match frame {
+        Frame::Error(message) => Err(anyhow!(message)),
+        frame => Ok(frame),
+    }
+}
+
+fn unexpected(frame: Frame) -> anyhow::Error {
+    anyhow!("unexpected reply {:?}", frame)
+}
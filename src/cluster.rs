@@ -0,0 +1,232 @@
+//! The cluster bus: a second listener over which nodes tell each other who
+//! they are, which slots they serve and which other nodes they know. Every
+//! node pings each node it knows once a second and takes in the gossip in
+//! the reply, so nodes introduced with CLUSTER MEET soon know of each other
+//! and of everyone either knew.
+//!
+//! Messages are RESP arrays of bulk strings:
+//! `type id host port bus-port epoch slots [id host port bus-port ...]`,
+//! where type is MEET, PING or PONG and slots lists ranges as `0-8191,9000`.
+
+use anyhow::{bail, Context};
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+
+use crate::{
+    comms::Comms,
+    connection::Connection,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    store::{
+        cluster::{Gossip, Node},
+        Store,
+    },
+};
+
+/// What the bus port is by default: the client port plus this.
+pub const BUS_PORT_OFFSET: u16 = 10000;
+
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a node has to answer before the link to it is dropped.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+type Link = Connection<OwnedReadHalf, OwnedWriteHalf>;
+
+/// Serves the bus on `listener` and keeps in touch with the other nodes.
+pub async fn run(listener: TcpListener, store: Store) -> anyhow::Result<()> {
+    store.cluster().set_bus_port(listener.local_addr()?.port());
+    let pinger = store.clone();
+    tokio::spawn(async move { ping_nodes(pinger).await });
+    loop {
+        let (socket, _) = listener.accept().await?;
+        let store = store.clone();
+        tokio::spawn(async move {
+            if let Err(err) = serve(socket, &store).await {
+                log_warning!("cluster bus error: {:#}", err);
+            }
+        });
+    }
+}
+
+/// Introduces this node to the one whose bus is at `host:bus_port`, and
+/// takes in what it knows.
+pub async fn meet(store: &Store, host: &str, bus_port: u16) -> anyhow::Result<()> {
+    let mut link = connect(&format!("{}:{}", host, bus_port)).await?;
+    exchange(store, &mut link, "MEET").await
+}
+
+/// Answers another node's link: a PONG for every MEET or PING.
+async fn serve(socket: TcpStream, store: &Store) -> anyhow::Result<()> {
+    let (reader, writer) = socket.into_split();
+    let mut link = Connection::new(reader, writer);
+    while let Some(frame) = link.read_frame().await? {
+        let (kind, gossip) = decode(frame)?;
+        if kind == "PONG" {
+            continue;
+        }
+        store.cluster().receive(gossip);
+        link.write_frame(&encode("PONG", &gossip_of(store))).await?;
+    }
+    Ok(())
+}
+
+/// Pings every known node each `PING_INTERVAL`, over links kept open
+/// between rounds and reopened when they fail.
+async fn ping_nodes(store: Store) {
+    let mut links: HashMap<Arc<str>, Link> = HashMap::new();
+    loop {
+        tokio::time::sleep(PING_INTERVAL).await;
+        for node in store.cluster().nodes() {
+            let mut link = match links.remove(&node.id) {
+                Some(link) => link,
+                None => match connect(&format!("{}:{}", node.host, node.bus_port)).await {
+                    Ok(link) => link,
+                    // the node stays unheard from, and is failing in time
+                    Err(_) => continue,
+                },
+            };
+            if exchange(&store, &mut link, "PING").await.is_ok() {
+                links.insert(node.id, link);
+            }
+        }
+    }
+}
+
+async fn connect(address: &str) -> anyhow::Result<Link> {
+    let socket = timeout(REPLY_TIMEOUT, TcpStream::connect(address))
+        .await
+        .with_context(|| format!("timed out connecting to {}", address))?
+        .with_context(|| format!("connecting to {}", address))?;
+    let (reader, writer) = socket.into_split();
+    Ok(Connection::new(reader, writer))
+}
+
+/// Sends our gossip as `kind` and takes in the PONG that comes back.
+async fn exchange(store: &Store, link: &mut Link, kind: &str) -> anyhow::Result<()> {
+    link.write_frame(&encode(kind, &gossip_of(store))).await?;
+    let reply = timeout(REPLY_TIMEOUT, link.read_frame())
+        .await
+        .context("timed out waiting for PONG")??;
+    let Some(frame) = reply else {
+        bail!("node closed the link");
+    };
+    match decode(frame)? {
+        (kind, gossip) if kind == "PONG" => {
+            store.cluster().receive(gossip);
+            Ok(())
+        }
+        (kind, _) => bail!("expected PONG, got {}", kind),
+    }
+}
+
+fn gossip_of(store: &Store) -> Gossip {
+    let config = store.config();
+    store.cluster().gossip(config.host(), config.port())
+}
+
+fn bulk(s: impl Into<Bytes>) -> Frame {
+    Frame::Bulk(s.into())
+}
+
+fn push_node(parts: &mut Vec<Frame>, node: &Node) {
+    parts.push(bulk(node.id.to_string()));
+    parts.push(bulk(node.host.clone()));
+    parts.push(bulk(node.port.to_string()));
+    parts.push(bulk(node.bus_port.to_string()));
+}
+
+fn encode(kind: &str, gossip: &Gossip) -> Frame {
+    let mut parts = vec![bulk(kind.to_string())];
+    push_node(&mut parts, &gossip.sender);
+    parts.push(bulk(gossip.epoch.to_string()));
+    let slots: Vec<String> = gossip
+        .slots
+        .iter()
+        .map(|(first, last)| {
+            if first == last {
+                first.to_string()
+            } else {
+                format!("{}-{}", first, last)
+            }
+        })
+        .collect();
+    parts.push(bulk(slots.join(",")));
+    for node in &gossip.nodes {
+        push_node(&mut parts, node);
+    }
+    Frame::Array(parts)
+}
+
+fn next_node(parse: &mut Parse) -> Result<Node, ParseError> {
+    Ok(Node {
+        id: parse.next_string()?.into(),
+        host: parse.next_string()?,
+        port: parse.next_int()? as u16,
+        bus_port: parse.next_int()? as u16,
+    })
+}
+
+fn parse_slots(slots: &str) -> anyhow::Result<Vec<(u16, u16)>> {
+    slots
+        .split(',')
+        .filter(|range| !range.is_empty())
+        .map(|range| {
+            let (first, last) = range.split_once('-').unwrap_or((range, range));
+            Ok((first.parse()?, last.parse()?))
+        })
+        .collect()
+}
+
+fn decode(frame: Frame) -> anyhow::Result<(String, Gossip)> {
+    let mut parse = Parse::new(frame)?;
+    let kind = parse.next_string()?.to_uppercase();
+    let sender = next_node(&mut parse)?;
+    let epoch = parse.next_int()?;
+    let slots = parse_slots(&parse.next_string()?).context("invalid slot ranges")?;
+    let mut nodes = vec![];
+    loop {
+        match next_node(&mut parse) {
+            Ok(node) => nodes.push(node),
+            Err(ParseError::EndOfStream) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let gossip = Gossip {
+        sender,
+        epoch,
+        slots,
+        nodes,
+    };
+    Ok((kind, gossip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gossip_round_trips_through_a_message() -> anyhow::Result<()> {
+        let node = |id: &str, port| Node {
+            id: id.repeat(40).into(),
+            host: "127.0.0.1".to_string(),
+            port,
+            bus_port: port + BUS_PORT_OFFSET,
+        };
+        let gossip = Gossip {
+            sender: node("a", 7000),
+            epoch: 3,
+            slots: vec![(0, 99), (200, 200)],
+            nodes: vec![node("b", 7001), node("c", 7002)],
+        };
+        let (kind, decoded) = decode(encode("PING", &gossip))?;
+        assert_eq!((kind.as_str(), decoded), ("PING", gossip));
+        assert!(parse_slots("5-x").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+//! RESP framing, so a `Connection`, or anything else reading RESP off a
+//! stream, shares one implementation. With the `codec` feature it is a
+//! tokio-util `Decoder` and `Encoder` as well:
+//!
+//! ```
+//! # #[cfg(feature = "codec")]
+//! # {
+//! use futures::{SinkExt, StreamExt};
+//! use redis_starter_rust::{codec::RespCodec, frame::Frame};
+//! use tokio_util::codec::Framed;
+//!
+//! # tokio_test::block_on(async {
+//! let (client, server) = tokio::io::duplex(64);
+//! let mut client = Framed::new(client, RespCodec::default());
+//! let mut server = Framed::new(server, RespCodec::default());
+//!
+//! client.send(Frame::Simple("PING".into())).await?;
+//! assert_eq!(server.next().await.transpose()?, Some(Frame::Simple("PING".into())));
+//! # anyhow::Ok(())
+//! # }).unwrap();
+//! # }
+//! ```
+
+use bytes::{Buf, BytesMut};
+use std::io::Cursor;
+#[cfg(feature = "codec")]
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::frame::{Error, Frame, Limits};
+
+#[derive(Debug, Clone)]
+pub struct RespCodec {
+    limits: Limits,
+}
+
+impl Default for RespCodec {
+    fn default() -> Self {
+        RespCodec::new(Limits::NONE)
+    }
+}
+
+impl RespCodec {
+    pub fn new(limits: Limits) -> RespCodec {
+        RespCodec { limits }
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Whether `decode` would return straight away: `src` starts with a whole
+    /// frame, or with one that can't be read.
+    pub fn has_frame(&self, src: &[u8]) -> bool {
+        !matches!(
+            Frame::check_within(&mut Cursor::new(src), &self.limits),
+            Err(Error::Incomplete)
+        )
+    }
+
+    /// Takes the first frame off `src`, or leaves it be while it holds only
+    /// part of one. A frame over the limits fails as soon as its header
+    /// arrives.
+    pub fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, Error> {
+        let mut buf = Cursor::new(&src[..]);
+        match Frame::check_within(&mut buf, &self.limits) {
+            Ok(()) => {
+                let len = buf.position() as usize;
+                buf.set_position(0);
+                let frame = Frame::parse(&mut buf)?;
+                src.advance(len);
+                Ok(Some(frame))
+            }
+            Err(Error::Incomplete) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Appends `frame` to `dst` as it goes on the wire.
+    pub fn encode(&mut self, frame: &Frame, dst: &mut BytesMut) -> Result<(), Error> {
+        frame.encode(dst);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "codec")]
+impl Decoder for RespCodec {
+    type Item = Frame;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, Error> {
+        RespCodec::decode(self, src)
+    }
+}
+
+#[cfg(feature = "codec")]
+impl Encoder<&Frame> for RespCodec {
+    type Error = Error;
+
+    fn encode(&mut self, frame: &Frame, dst: &mut BytesMut) -> Result<(), Error> {
+        RespCodec::encode(self, frame, dst)
+    }
+}
+
+#[cfg(feature = "codec")]
+impl Encoder<Frame> for RespCodec {
+    type Error = Error;
+
+    fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> Result<(), Error> {
+        RespCodec::encode(self, &frame, dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    #[test]
+    fn frames_are_decoded_once_whole() {
+        let mut codec = RespCodec::default();
+        let mut src = BytesMut::new();
+        let ping = Frame::Array(vec![Frame::Bulk(Bytes::from("PING"))]);
+        codec.encode(&ping, &mut src).unwrap();
+        codec.encode(&Frame::Integer(7), &mut src).unwrap();
+        let mut partial = src.split_to(5);
+
+        assert_eq!(codec.decode(&mut partial).unwrap(), None);
+        assert!(!codec.has_frame(&partial));
+        partial.unsplit(src);
+        assert!(codec.has_frame(&partial));
+        assert_eq!(codec.decode(&mut partial).unwrap(), Some(ping));
+        assert_eq!(codec.decode(&mut partial).unwrap(), Some(Frame::Integer(7)));
+        assert!(partial.is_empty());
+    }
+
+    #[test]
+    fn frames_over_the_limits_fail_at_their_header() {
+        let mut codec = RespCodec::new(Limits {
+            multibulk_len: 1,
+            ..Limits::NONE
+        });
+        let mut src = BytesMut::from(&b"*2\r\n"[..]);
+        assert!(codec.has_frame(&src));
+        assert!(codec.decode(&mut src).is_err());
+    }
+}
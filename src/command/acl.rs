@@ -0,0 +1,167 @@
+use anyhow::bail;
+use bytes::Bytes;
+use std::path::Path;
+
+use crate::{
+    command::table,
+    error::Error,
+    frame::Frame,
+    parse::Parse,
+    store::{acl::DEFAULT_USER, Store},
+};
+
+const ERR_NO_ACLFILE: &str = "ERR This Redis instance is not configured to use an ACL file. You may want to specify users via the ACL SETUSER command and then issue a CONFIG REWRITE (assuming you have a Redis configuration file set) in order to store users in the Redis configuration.";
+
+fn bulk(s: impl Into<String>) -> Frame {
+    Frame::Bulk(Bytes::from(s.into()))
+}
+
+#[derive(Debug)]
+enum AclOp {
+    SetUser { name: String, rules: Vec<String> },
+    GetUser(String),
+    DelUser(Vec<String>),
+    Users,
+    List,
+    WhoAmI,
+    Cat(Option<String>),
+    Save,
+    Load,
+}
+
+/// ACL SETUSER / GETUSER / DELUSER / USERS / LIST / WHOAMI / CAT / SAVE / LOAD
+#[derive(Debug)]
+pub struct Acl {
+    op: AclOp,
+}
+
+impl Acl {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Acl> {
+        let subcommand = parse.next_string()?;
+        let mut args = parse.remaining_strings()?;
+        let op = match (subcommand.to_uppercase().as_str(), args.len()) {
+            ("SETUSER", 1..) => AclOp::SetUser {
+                name: args.remove(0),
+                rules: args,
+            },
+            ("GETUSER", 1) => AclOp::GetUser(args.remove(0)),
+            ("DELUSER", 1..) => AclOp::DelUser(args),
+            ("USERS", 0) => AclOp::Users,
+            ("LIST", 0) => AclOp::List,
+            ("WHOAMI", 0) => AclOp::WhoAmI,
+            ("CAT", 0..=1) => AclOp::Cat(args.pop()),
+            ("SAVE", 0) => AclOp::Save,
+            ("LOAD", 0) => AclOp::Load,
+            (
+                "SETUSER" | "GETUSER" | "DELUSER" | "USERS" | "LIST" | "WHOAMI" | "CAT" | "SAVE"
+                | "LOAD",
+                _,
+            ) => bail!(
+                "ERR wrong number of arguments for 'acl|{}' command",
+                subcommand.to_lowercase()
+            ),
+            _ => bail!("ERR unknown subcommand '{}'. Try ACL HELP.", subcommand),
+        };
+        Ok(Acl { op })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let acl = store.acl();
+        let response = match self.op {
+            AclOp::SetUser { name, rules } => match acl.set_user(&name, &rules) {
+                Ok(()) => Frame::OK,
+                Err(error) => Frame::Error(error),
+            },
+            AclOp::GetUser(name) => match acl.get_user(&name) {
+                Some(user) => Frame::Array(vec![
+                    bulk("flags"),
+                    Frame::Array(user.flags().into_iter().map(bulk).collect()),
+                    bulk("passwords"),
+                    Frame::Array(user.passwords().cloned().map(bulk).collect()),
+                    bulk("commands"),
+                    bulk(user.describe_commands()),
+                    bulk("keys"),
+                    bulk(user.describe_keys()),
+                ]),
+                None => Frame::Null,
+            },
+            AclOp::DelUser(names) => {
+                if names.iter().any(|name| name == DEFAULT_USER) {
+                    return Ok(Frame::Error(
+                        "ERR The 'default' user cannot be removed".to_string(),
+                    ));
+                }
+                let removed = names.iter().filter(|name| acl.del_user(name)).count();
+                Frame::Integer(removed as u64)
+            }
+            AclOp::Users => Frame::Array(acl.usernames().into_iter().map(bulk).collect()),
+            AclOp::List => Frame::Array(acl.list().into_iter().map(bulk).collect()),
+            AclOp::WhoAmI => bulk(store.user().unwrap_or_default()),
+            AclOp::Cat(None) => {
+                Frame::Array(table::categories().iter().map(|c| bulk(*c)).collect())
+            }
+            AclOp::Cat(Some(category)) => match table::commands_in(&category) {
+                Some(commands) => Frame::Array(commands.into_iter().map(bulk).collect()),
+                None => Frame::Error(format!("ERR Unknown category '{}'", category)),
+            },
+            AclOp::Save => {
+                let path = store.config().aclfile();
+                if path.is_empty() {
+                    return Ok(Frame::Error(ERR_NO_ACLFILE.to_string()));
+                }
+                match acl.save(Path::new(&path)) {
+                    Ok(()) => Frame::OK,
+                    Err(err) => Frame::Error(format!("ERR There was an error trying to save the ACLs. Please check the server logs for more information: {:#}", err)),
+                }
+            }
+            AclOp::Load => {
+                let path = store.config().aclfile();
+                if path.is_empty() {
+                    return Ok(Frame::Error(ERR_NO_ACLFILE.to_string()));
+                }
+                match acl.load(Path::new(&path)) {
+                    Ok(()) => Frame::OK,
+                    Err(error) => Frame::Error(error),
+                }
+            }
+        };
+        Ok(response)
+    }
+}
+
+/// AUTH [username] password: logs the connection in, as the default user if
+/// no username is given.
+#[derive(Debug)]
+pub struct Auth {
+    username: Option<String>,
+    password: String,
+}
+
+impl Auth {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Auth> {
+        let mut args = parse.remaining_strings()?;
+        let password = match args.pop() {
+            Some(password) if args.len() <= 1 => password,
+            Some(_) => bail!(Error::Syntax),
+            None => bail!(Error::WrongArity("auth".to_string())),
+        };
+        Ok(Auth {
+            username: args.pop(),
+            password,
+        })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        if self.username.is_none() && store.acl().is_open() {
+            return Ok(Frame::Error("ERR AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?".to_string()));
+        }
+        let username = self.username.unwrap_or_else(|| DEFAULT_USER.to_string());
+        if !store.acl().authenticate(&username, &self.password) {
+            return Ok(Frame::Error(
+                "WRONGPASS invalid username-password pair or user is disabled.".to_string(),
+            ));
+        }
+        store.set_user(Some(username));
+        Ok(Frame::OK)
+    }
+}
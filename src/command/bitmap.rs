@@ -0,0 +1,482 @@
+use anyhow::bail;
+use bytes::{Bytes, BytesMut};
+use std::convert::Infallible;
+
+use crate::{
+    error::Error,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    publisher::{publish, Action},
+    store::{notify::EventClass, Store},
+};
+
+const ERR_BIT_OFFSET: &str = "ERR bit offset is not an integer or out of range";
+const ERR_BIT: &str = "ERR bit is not an integer or out of range";
+const ERR_NOT_ONE_SOURCE: &str = "ERR BITOP NOT must be called with a single source key.";
+const ERR_BITPOS_BIT: &str = "ERR The bit argument must be 1 or 0.";
+
+/// The last bit a string can have, keeping it within 512MB.
+const MAX_BIT_OFFSET: u64 = 512 * 1024 * 1024 * 8 - 1;
+
+fn parse_offset(offset: &str) -> Option<u64> {
+    offset
+        .parse::<u64>()
+        .ok()
+        .filter(|offset| *offset <= MAX_BIT_OFFSET)
+}
+
+/// Bit `offset` of `bytes`, counting from the most significant bit of the
+/// first byte. Bits past the end are 0.
+fn get_bit(bytes: &[u8], offset: u64) -> bool {
+    let byte = (offset / 8) as usize;
+    bytes
+        .get(byte)
+        .is_some_and(|byte| byte & (0x80 >> (offset % 8)) != 0)
+}
+
+/// Sets bit `offset` of `buffer`, padding it with zeros to reach it, and
+/// returns what the bit was.
+fn set_bit(buffer: &mut BytesMut, offset: u64, bit: bool) -> bool {
+    let byte = (offset / 8) as usize;
+    if buffer.len() <= byte {
+        buffer.resize(byte + 1, 0);
+    }
+    let mask = 0x80 >> (offset % 8);
+    let was = buffer[byte] & mask != 0;
+    if bit {
+        buffer[byte] |= mask;
+    } else {
+        buffer[byte] &= !mask;
+    }
+    was
+}
+
+/// What the range of BITCOUNT and BITPOS counts in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unit {
+    Byte,
+    Bit,
+}
+
+impl Unit {
+    fn parse(unit: &str) -> Option<Unit> {
+        match unit.to_uppercase().as_str() {
+            "BYTE" => Some(Unit::Byte),
+            "BIT" => Some(Unit::Bit),
+            _ => None,
+        }
+    }
+}
+
+/// The bits from `start` to `end` inclusive, in `unit`s of a string `len`
+/// bytes long, with negative indexes counting back from the end. None if
+/// the range is empty.
+fn bit_range(start: i64, end: i64, unit: Unit, len: usize) -> Option<(u64, u64)> {
+    let total = match unit {
+        Unit::Byte => len as i64,
+        Unit::Bit => len as i64 * 8,
+    };
+    let resolve = |index: i64| {
+        if index < 0 {
+            (total + index).max(0)
+        } else {
+            index
+        }
+    };
+    let (start, end) = (resolve(start), resolve(end).min(total - 1));
+    if total == 0 || start > end {
+        return None;
+    }
+    let (start, end) = (start as u64, end as u64);
+    Some(match unit {
+        Unit::Byte => (start * 8, end * 8 + 7),
+        Unit::Bit => (start, end),
+    })
+}
+
+/// How many bits are set from bit `first` to bit `last` inclusive.
+fn count_bits(bytes: &[u8], first: u64, last: u64) -> u64 {
+    let (first_byte, last_byte) = ((first / 8) as usize, (last / 8) as usize);
+    bytes[first_byte..=last_byte]
+        .iter()
+        .enumerate()
+        .map(|(i, byte)| {
+            let mut byte = *byte;
+            if i == 0 {
+                byte &= 0xff >> (first % 8);
+            }
+            if first_byte + i == last_byte {
+                byte &= 0xff << (7 - last % 8);
+            }
+            u64::from(byte.count_ones())
+        })
+        .sum()
+}
+
+/// The first bit from bit `first` to bit `last` inclusive that is `bit`.
+fn find_bit(bytes: &[u8], bit: bool, first: u64, last: u64) -> Option<u64> {
+    // whole bytes without the bit can be skipped
+    let skip = if bit { 0x00 } else { 0xff };
+    let mut offset = first;
+    while offset <= last {
+        if offset.is_multiple_of(8) && offset + 7 <= last && bytes[(offset / 8) as usize] == skip {
+            offset += 8;
+            continue;
+        }
+        if get_bit(bytes, offset) == bit {
+            return Some(offset);
+        }
+        offset += 1;
+    }
+    None
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BitOperation {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+impl BitOperation {
+    fn name(self) -> &'static str {
+        match self {
+            BitOperation::And => "and",
+            BitOperation::Or => "or",
+            BitOperation::Xor => "xor",
+            BitOperation::Not => "not",
+        }
+    }
+
+    /// `sources` combined byte by byte, the shorter ones padded with zeros
+    /// to the length of the longest.
+    fn apply(self, sources: &[&[u8]]) -> Bytes {
+        let len = sources.iter().map(|source| source.len()).max().unwrap_or(0);
+        let byte = |source: &[u8], i: usize| source.get(i).copied().unwrap_or(0);
+        (0..len)
+            .map(|i| {
+                let mut bytes = sources.iter().map(|source| byte(source, i));
+                match self {
+                    BitOperation::And => bytes.fold(0xff, |acc, byte| acc & byte),
+                    BitOperation::Or => bytes.fold(0, |acc, byte| acc | byte),
+                    BitOperation::Xor => bytes.fold(0, |acc, byte| acc ^ byte),
+                    BitOperation::Not => !bytes.next().unwrap_or(0),
+                }
+            })
+            .collect::<Vec<u8>>()
+            .into()
+    }
+}
+
+/// SETBIT key offset value
+#[derive(Debug)]
+pub struct SetBit {
+    key: Bytes,
+    offset: String,
+    value: String,
+}
+
+impl SetBit {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<SetBit> {
+        let key = parse.next_bytes()?;
+        let offset = parse.next_string()?;
+        let value = parse.next_string()?;
+        Ok(SetBit { key, offset, value })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let Some(offset) = parse_offset(&self.offset) else {
+            return Ok(Frame::Error(ERR_BIT_OFFSET.to_string()));
+        };
+        let bit = match self.value.as_str() {
+            "0" => false,
+            "1" => true,
+            _ => return Ok(Frame::Error(ERR_BIT.to_string())),
+        };
+        let was =
+            match store.with_string_mut(self.key.clone(), |buffer| set_bit(buffer, offset, bit)) {
+                Ok(was) => was,
+                Err(e) => return Ok(Frame::Error(e.to_string())),
+            };
+        store.notify(EventClass::String, "setbit", &self.key);
+        publish(
+            store,
+            Action::SetBit {
+                key: self.key,
+                offset,
+                bit,
+            },
+        )
+        .await?;
+        Ok(Frame::Integer(was as u64))
+    }
+}
+
+/// GETBIT key offset
+#[derive(Debug)]
+pub struct GetBit {
+    key: Bytes,
+    offset: String,
+}
+
+impl GetBit {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<GetBit> {
+        let key = parse.next_bytes()?;
+        let offset = parse.next_string()?;
+        Ok(GetBit { key, offset })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let Some(offset) = parse_offset(&self.offset) else {
+            return Ok(Frame::Error(ERR_BIT_OFFSET.to_string()));
+        };
+        let response = match store.with_string(&self.key, |bytes| get_bit(bytes, offset)) {
+            Ok(bit) => Frame::Integer(bit as u64),
+            Err(e) => Frame::Error(e.to_string()),
+        };
+        Ok(response)
+    }
+}
+
+/// BITCOUNT key [start end [BYTE | BIT]]
+#[derive(Debug)]
+pub struct BitCount {
+    key: Bytes,
+    range: Option<(String, String, Unit)>,
+}
+
+impl BitCount {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<BitCount> {
+        let key = parse.next_bytes()?;
+        let mut args = parse.remaining_strings()?;
+        let range = match args.len() {
+            0 => None,
+            2 | 3 => {
+                let unit = match args.get(2) {
+                    Some(unit) => Unit::parse(unit),
+                    None => Some(Unit::Byte),
+                };
+                let Some(unit) = unit else {
+                    bail!(Error::Syntax);
+                };
+                args.truncate(2);
+                let end = args.pop().expect("two arguments");
+                let start = args.pop().expect("two arguments");
+                Some((start, end, unit))
+            }
+            _ => bail!(Error::Syntax),
+        };
+        Ok(BitCount { key, range })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let range = match &self.range {
+            None => None,
+            Some((start, end, unit)) => match (start.parse::<i64>(), end.parse::<i64>()) {
+                (Ok(start), Ok(end)) => Some((start, end, *unit)),
+                _ => return Ok(Error::NotAnInteger.into()),
+            },
+        };
+        let counted = store.with_string(&self.key, |bytes| {
+            let (start, end, unit) = range.unwrap_or((0, -1, Unit::Byte));
+            bit_range(start, end, unit, bytes.len())
+                .map_or(0, |(first, last)| count_bits(bytes, first, last))
+        });
+        let response = match counted {
+            Ok(count) => Frame::Integer(count),
+            Err(e) => Frame::Error(e.to_string()),
+        };
+        Ok(response)
+    }
+}
+
+/// BITOP AND | OR | XOR | NOT destkey key [key ...]
+#[derive(Debug)]
+pub struct BitOp {
+    operation: BitOperation,
+    destination: Bytes,
+    keys: Vec<Bytes>,
+}
+
+impl BitOp {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<BitOp> {
+        let operation = match parse.next_string()?.to_uppercase().as_str() {
+            "AND" => BitOperation::And,
+            "OR" => BitOperation::Or,
+            "XOR" => BitOperation::Xor,
+            "NOT" => BitOperation::Not,
+            _ => bail!(Error::Syntax),
+        };
+        let destination = parse.next_bytes()?;
+        let mut keys = vec![parse.next_bytes()?];
+        loop {
+            match parse.next_bytes() {
+                Ok(key) => keys.push(key),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(BitOp {
+            operation,
+            destination,
+            keys,
+        })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        if self.operation == BitOperation::Not && self.keys.len() != 1 {
+            return Ok(Frame::Error(ERR_NOT_ONE_SOURCE.to_string()));
+        }
+        let stored = store.store_string(self.destination.clone(), &self.keys, |sources| {
+            Ok::<_, Infallible>(self.operation.apply(sources))
+        });
+        let result = match stored {
+            Ok(Ok(result)) => result,
+            Err(e) => return Ok(Frame::Error(e.to_string())),
+        };
+        if !result.is_empty() {
+            store.notify(EventClass::String, "set", &self.destination);
+        }
+        // the sources are on the replica too, so it can redo the operation
+        publish(
+            store,
+            Action::BitOp {
+                operation: self.operation.name(),
+                destination: self.destination,
+                keys: self.keys,
+            },
+        )
+        .await?;
+        Ok(Frame::Integer(result.len() as u64))
+    }
+}
+
+/// BITPOS key bit [start [end [BYTE | BIT]]]
+#[derive(Debug)]
+pub struct BitPos {
+    key: Bytes,
+    bit: String,
+    start: Option<String>,
+    end: Option<String>,
+    unit: Unit,
+}
+
+impl BitPos {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<BitPos> {
+        let key = parse.next_bytes()?;
+        let bit = parse.next_string()?;
+        let mut args = parse.remaining_strings()?.into_iter();
+        let (start, end) = (args.next(), args.next());
+        let unit = match args.next() {
+            None => Unit::Byte,
+            Some(unit) => match Unit::parse(&unit) {
+                Some(unit) => unit,
+                None => bail!(Error::Syntax),
+            },
+        };
+        if args.next().is_some() {
+            bail!(Error::Syntax);
+        }
+        Ok(BitPos {
+            key,
+            bit,
+            start,
+            end,
+            unit,
+        })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let bit = match self.bit.as_str() {
+            "0" => false,
+            "1" => true,
+            _ => return Ok(Frame::Error(ERR_BITPOS_BIT.to_string())),
+        };
+        let parse = |index: &Option<String>| match index {
+            None => Some(None),
+            Some(index) => index.parse::<i64>().ok().map(Some),
+        };
+        let (Some(start), Some(end)) = (parse(&self.start), parse(&self.end)) else {
+            return Ok(Error::NotAnInteger.into());
+        };
+        let found = store.with_string(&self.key, |bytes| {
+            if bytes.is_empty() {
+                return if bit { -1 } else { 0 };
+            }
+            let range = bit_range(
+                start.unwrap_or(0),
+                end.unwrap_or(-1),
+                self.unit,
+                bytes.len(),
+            );
+            let Some((first, last)) = range else {
+                return -1;
+            };
+            match find_bit(bytes, bit, first, last) {
+                Some(offset) => offset as i64,
+                // without an end, the string counts as padded with clear bits
+                None if !bit && end.is_none() => last as i64 + 1,
+                None => -1,
+            }
+        });
+        let response = match found {
+            Ok(offset) => Frame::integer(offset),
+            Err(e) => Frame::Error(e.to_string()),
+        };
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_bit_pads_with_zeros_and_reports_the_old_bit() {
+        let mut buffer = BytesMut::new();
+        assert!(!set_bit(&mut buffer, 7, true));
+        assert!(!set_bit(&mut buffer, 17, true));
+        assert_eq!(&buffer[..], [0x01, 0x00, 0x40]);
+        assert!(set_bit(&mut buffer, 7, false));
+        assert_eq!(&buffer[..], [0x00, 0x00, 0x40]);
+        assert!(get_bit(&buffer, 17));
+        assert!(!get_bit(&buffer, 1000));
+    }
+
+    #[test]
+    fn counts_bits_in_byte_and_bit_ranges() {
+        // "foobar", as in the BITCOUNT docs
+        let bytes = b"foobar";
+        let count = |start, end, unit| {
+            bit_range(start, end, unit, bytes.len())
+                .map_or(0, |(first, last)| count_bits(bytes, first, last))
+        };
+        assert_eq!(count(0, -1, Unit::Byte), 26);
+        assert_eq!(count(0, 0, Unit::Byte), 4);
+        assert_eq!(count(1, 1, Unit::Byte), 6);
+        assert_eq!(count(5, 30, Unit::Bit), 17);
+        assert_eq!(count(-2, -1, Unit::Byte), 7);
+        assert_eq!(count(3, 1, Unit::Byte), 0);
+        assert_eq!(count(-100, 100, Unit::Bit), 26);
+    }
+
+    #[test]
+    fn bit_operations_pad_shorter_sources_with_zeros() {
+        let sources: [&[u8]; 2] = [&[0xf0, 0xff], &[0x3c]];
+        assert_eq!(BitOperation::And.apply(&sources), [0x30, 0x00][..]);
+        assert_eq!(BitOperation::Or.apply(&sources), [0xfc, 0xff][..]);
+        assert_eq!(BitOperation::Xor.apply(&sources), [0xcc, 0xff][..]);
+        assert_eq!(BitOperation::Not.apply(&sources[..1]), [0x0f, 0x00][..]);
+        assert!(BitOperation::Or.apply(&[&[], &[]]).is_empty());
+    }
+
+    #[test]
+    fn finds_the_first_matching_bit_in_range() {
+        let bytes = [0xff, 0xf0, 0x00];
+        assert_eq!(find_bit(&bytes, false, 0, 23), Some(12));
+        assert_eq!(find_bit(&bytes, true, 13, 23), None);
+        assert_eq!(find_bit(&bytes, true, 2, 23), Some(2));
+        assert_eq!(find_bit(&[0xff, 0xff], false, 0, 15), None);
+    }
+}
@@ -0,0 +1,116 @@
+use bytes::Bytes;
+use std::time::Duration;
+use tokio::time::{timeout_at, Instant};
+
+use crate::{
+    command::zpop::Side,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    publisher::{publish, Action},
+    store::{notify::EventClass, sorted_set::format_score, Store},
+};
+
+/// BZPOPMIN / BZPOPMAX
+#[derive(Debug)]
+pub struct BZPop {
+    side: Side,
+    keys: Vec<Bytes>,
+    timeout: String,
+    /// False inside a transaction, where an empty pop replies straight away.
+    block: bool,
+}
+
+impl BZPop {
+    pub fn new(side: Side, keys: Vec<Bytes>, timeout: String) -> Self {
+        Self {
+            side,
+            keys,
+            timeout,
+            block: true,
+        }
+    }
+
+    pub(crate) fn without_blocking(self) -> Self {
+        Self {
+            block: false,
+            ..self
+        }
+    }
+
+    pub(crate) fn parse_frames(side: Side, parse: &mut Parse) -> anyhow::Result<BZPop> {
+        let mut keys = vec![parse.next_bytes()?];
+        loop {
+            match parse.next_bytes() {
+                Ok(key) => keys.push(key),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        // the last argument is the timeout, which requires at least one key before it
+        let timeout = keys.pop().unwrap();
+        if keys.is_empty() {
+            return Err(ParseError::EndOfStream.into());
+        }
+        let timeout = String::from_utf8(timeout.to_vec())?;
+        Ok(BZPop::new(side, keys, timeout))
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let deadline = match self.timeout.parse::<f64>() {
+            Ok(secs) if secs < 0.0 => {
+                return Ok(Frame::Error("ERR timeout is negative".to_string()));
+            }
+            Ok(secs) if secs.is_finite() => {
+                (secs > 0.0).then(|| Instant::now() + Duration::from_secs_f64(secs))
+            }
+            _ => {
+                return Ok(Frame::Error(
+                    "ERR timeout is not a float or out of range".into(),
+                ));
+            }
+        };
+
+        // register before the first attempt so a write racing with it still wakes us
+        let guard = store.block_on(&self.keys);
+        let response = loop {
+            if let Some(response) = self.try_pop(store).await? {
+                break response;
+            }
+            match deadline {
+                _ if !self.block => break Frame::NullArray,
+                Some(deadline) => {
+                    if timeout_at(deadline, guard.wait()).await.is_err() {
+                        break Frame::NullArray;
+                    }
+                }
+                None => guard.wait().await,
+            }
+        };
+        Ok(response)
+    }
+
+    async fn try_pop(&self, store: &Store) -> anyhow::Result<Option<Frame>> {
+        for key in &self.keys {
+            match store.with_sorted_set_mut(key.clone(), |zset| self.side.pop(zset, 1)) {
+                Ok(popped) => {
+                    if let Some((member, score)) = popped.into_iter().next() {
+                        store.notify(EventClass::SortedSet, self.side.event(), key);
+                        let action = Action::ZPop {
+                            side: self.side,
+                            key: key.clone(),
+                            count: 1,
+                        };
+                        publish(store, action).await?;
+                        let mut array = Frame::array();
+                        array.push_bulk(key.clone())?;
+                        array.push_bulk(member)?;
+                        array.push_bulk(format_score(score).into())?;
+                        return Ok(Some(array));
+                    }
+                }
+                Err(e) => return Ok(Some(Frame::Error(e.to_string()))),
+            }
+        }
+        Ok(None)
+    }
+}
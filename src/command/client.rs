@@ -0,0 +1,336 @@
+use anyhow::bail;
+use bytes::Bytes;
+use std::time::Duration;
+
+use crate::{
+    error::Error,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    store::{client_state::ReplyMode, tracking::Options, Store},
+};
+
+pub(crate) const ERR_INVALID_NAME: &str =
+    "ERR Client names cannot contain spaces, newlines or special characters.";
+
+/// Whether `name` may name a client: names are space separated fields in
+/// CLIENT LIST.
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    name.bytes().all(|b| (b'!'..=b'~').contains(&b))
+}
+
+#[derive(Debug)]
+enum ClientOp {
+    /// Every client, or only those with the given ids.
+    List(Option<Vec<String>>),
+    Info,
+    Id,
+    SetName(String),
+    GetName,
+    Kill(Kill),
+    Pause {
+        millis: String,
+        writes_only: bool,
+    },
+    Unpause,
+    NoEvict(bool),
+    NoTouch(bool),
+    Tracking(Tracking),
+    GetRedir,
+    Reply(ReplyMode),
+}
+
+/// CLIENT TRACKING's arguments, checked when it runs.
+#[derive(Debug, Default)]
+struct Tracking {
+    on: bool,
+    redirect: Option<String>,
+    bcast: bool,
+    prefixes: Vec<Bytes>,
+    noloop: bool,
+}
+
+impl Tracking {
+    fn parse(parse: &mut Parse) -> anyhow::Result<Tracking> {
+        let mut tracking = Tracking {
+            on: parse_switch(parse)?,
+            ..Default::default()
+        };
+        loop {
+            match parse.next_string() {
+                Ok(option) => match option.to_uppercase().as_str() {
+                    "REDIRECT" => tracking.redirect = Some(parse.next_string()?),
+                    "BCAST" => tracking.bcast = true,
+                    "PREFIX" => tracking.prefixes.push(parse.next_bytes()?),
+                    "NOLOOP" => tracking.noloop = true,
+                    _ => bail!(Error::Syntax),
+                },
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(tracking)
+    }
+}
+
+/// Which clients CLIENT KILL closes: those matching every filter given.
+#[derive(Debug, Default)]
+struct Kill {
+    id: Option<String>,
+    addr: Option<String>,
+    laddr: Option<String>,
+    /// Leave the calling client alone; off for the old single address form.
+    skipme: bool,
+    /// Given as the old `CLIENT KILL addr`, which replies OK or an error
+    /// rather than a count.
+    legacy: bool,
+}
+
+impl Kill {
+    fn parse(args: Vec<String>) -> anyhow::Result<Kill> {
+        if let [addr] = &args[..] {
+            return Ok(Kill {
+                addr: Some(addr.clone()),
+                legacy: true,
+                ..Default::default()
+            });
+        }
+        if args.is_empty() || !args.len().is_multiple_of(2) {
+            bail!(Error::Syntax);
+        }
+        let mut kill = Kill {
+            skipme: true,
+            ..Default::default()
+        };
+        for pair in args.chunks(2) {
+            let value = pair[1].clone();
+            match pair[0].to_uppercase().as_str() {
+                "ID" => kill.id = Some(value),
+                "ADDR" => kill.addr = Some(value),
+                "LADDR" => kill.laddr = Some(value),
+                "SKIPME" => match value.to_lowercase().as_str() {
+                    "yes" => kill.skipme = true,
+                    "no" => kill.skipme = false,
+                    _ => bail!(Error::Syntax),
+                },
+                _ => bail!(Error::Syntax),
+            }
+        }
+        Ok(kill)
+    }
+}
+
+fn parse_switch(parse: &mut Parse) -> anyhow::Result<bool> {
+    match parse.next_string()?.to_lowercase().as_str() {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => bail!(Error::Syntax),
+    }
+}
+
+/// CLIENT LIST [ID id ...] / INFO / ID / SETNAME name / GETNAME /
+/// KILL filter value ... / PAUSE timeout [WRITE|ALL] / UNPAUSE /
+/// NO-EVICT on|off / NO-TOUCH on|off /
+/// TRACKING on|off [REDIRECT id] [BCAST] [PREFIX prefix ...] [NOLOOP] /
+/// GETREDIR / REPLY on|off|skip
+#[derive(Debug)]
+pub struct Client {
+    op: ClientOp,
+}
+
+impl Client {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Client> {
+        let subcommand = parse.next_string()?;
+        let op = match subcommand.to_uppercase().as_str() {
+            "LIST" => match parse.next_string() {
+                Err(ParseError::EndOfStream) => ClientOp::List(None),
+                Ok(option) if option.eq_ignore_ascii_case("ID") => {
+                    let mut ids = vec![parse.next_string()?];
+                    loop {
+                        match parse.next_string() {
+                            Ok(id) => ids.push(id),
+                            Err(ParseError::EndOfStream) => break,
+                            Err(e) => return Err(e.into()),
+                        }
+                    }
+                    ClientOp::List(Some(ids))
+                }
+                Ok(_) => bail!(Error::Syntax),
+                Err(e) => return Err(e.into()),
+            },
+            "INFO" => ClientOp::Info,
+            "ID" => ClientOp::Id,
+            "SETNAME" => ClientOp::SetName(parse.next_string()?),
+            "GETNAME" => ClientOp::GetName,
+            "KILL" => ClientOp::Kill(Kill::parse(parse.remaining_strings()?)?),
+            "PAUSE" => {
+                let millis = parse.next_string()?;
+                let writes_only = match parse.next_string() {
+                    Err(ParseError::EndOfStream) => false,
+                    Ok(mode) if mode.eq_ignore_ascii_case("ALL") => false,
+                    Ok(mode) if mode.eq_ignore_ascii_case("WRITE") => true,
+                    Ok(_) => bail!(Error::Syntax),
+                    Err(e) => return Err(e.into()),
+                };
+                ClientOp::Pause {
+                    millis,
+                    writes_only,
+                }
+            }
+            "UNPAUSE" => ClientOp::Unpause,
+            "NO-EVICT" => ClientOp::NoEvict(parse_switch(parse)?),
+            "NO-TOUCH" => ClientOp::NoTouch(parse_switch(parse)?),
+            "TRACKING" => ClientOp::Tracking(Tracking::parse(parse)?),
+            "GETREDIR" => ClientOp::GetRedir,
+            "REPLY" => ClientOp::Reply(match parse.next_string()?.to_lowercase().as_str() {
+                "on" => ReplyMode::On,
+                "off" => ReplyMode::Off,
+                "skip" => ReplyMode::Skip,
+                _ => bail!(Error::Syntax),
+            }),
+            _ => bail!("ERR unknown subcommand '{}'. Try CLIENT HELP.", subcommand),
+        };
+        Ok(Client { op })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let clients = store.clients();
+        let Some(id) = store.client_id() else {
+            return Ok(Frame::Error(
+                "ERR CLIENT is only available to clients".to_string(),
+            ));
+        };
+        let response = match self.op {
+            ClientOp::List(ids) => {
+                let ids: Option<Vec<u64>> = match ids {
+                    Some(ids) => match ids.iter().map(|id| id.parse::<u64>()).collect() {
+                        Ok(ids) => Some(ids),
+                        Err(_) => return Ok(Frame::Error("ERR Invalid client ID".to_string())),
+                    },
+                    None => None,
+                };
+                let list: String = clients
+                    .list()
+                    .iter()
+                    .filter(|client| ids.as_ref().is_none_or(|ids| ids.contains(&client.id)))
+                    .map(|client| client.describe() + "\n")
+                    .collect();
+                Frame::Bulk(Bytes::from(list))
+            }
+            ClientOp::Info => match clients.get(id) {
+                Some(client) => Frame::Bulk(Bytes::from(client.describe() + "\n")),
+                None => Frame::Null,
+            },
+            ClientOp::Id => Frame::Integer(id),
+            ClientOp::SetName(name) => {
+                if !is_valid_name(&name) {
+                    return Ok(Frame::Error(ERR_INVALID_NAME.to_string()));
+                }
+                clients.update(id, |client| client.name = name);
+                Frame::OK
+            }
+            ClientOp::GetName => match clients.get(id) {
+                Some(client) if !client.name.is_empty() => Frame::Bulk(Bytes::from(client.name)),
+                _ => Frame::Null,
+            },
+            ClientOp::Kill(kill) => {
+                let wanted = match kill.id.as_deref().map(str::parse::<u64>) {
+                    Some(Ok(wanted)) if wanted > 0 => Some(wanted),
+                    Some(_) => {
+                        return Ok(Frame::Error(
+                            "ERR client-id should be greater than 0".to_string(),
+                        ))
+                    }
+                    None => None,
+                };
+                let victims: Vec<_> = clients
+                    .list()
+                    .into_iter()
+                    .filter(|client| {
+                        wanted.is_none_or(|wanted| client.id == wanted)
+                            && kill
+                                .addr
+                                .as_ref()
+                                .is_none_or(|a| *a == client.addr.to_string())
+                            && kill
+                                .laddr
+                                .as_ref()
+                                .is_none_or(|a| *a == client.laddr.to_string())
+                            && !(kill.skipme && client.id == id)
+                    })
+                    .collect();
+                for victim in &victims {
+                    clients.kill(victim.id);
+                }
+                match (kill.legacy, victims.len()) {
+                    (true, 0) => Frame::Error("ERR No such client".to_string()),
+                    (true, _) => Frame::OK,
+                    (false, killed) => Frame::Integer(killed as u64),
+                }
+            }
+            ClientOp::Pause {
+                millis,
+                writes_only,
+            } => match millis.parse::<u64>() {
+                Ok(millis) => {
+                    clients.pause(Duration::from_millis(millis), writes_only);
+                    Frame::OK
+                }
+                Err(_) => Frame::Error("ERR timeout is not an integer or out of range".to_string()),
+            },
+            ClientOp::Unpause => {
+                clients.unpause();
+                Frame::OK
+            }
+            // clients are never evicted, so this is only recorded
+            ClientOp::NoEvict(on) => {
+                clients.update(id, |client| client.no_evict = on);
+                Frame::OK
+            }
+            ClientOp::NoTouch(on) => {
+                store.set_no_touch(on);
+                clients.update(id, |client| client.no_touch = on);
+                Frame::OK
+            }
+            ClientOp::Tracking(tracking) if !tracking.on => {
+                store.tracking().disable(id);
+                clients.update(id, |client| client.tracking = false);
+                Frame::OK
+            }
+            ClientOp::Tracking(tracking) => {
+                if !tracking.prefixes.is_empty() && !tracking.bcast {
+                    return Ok(Frame::Error(
+                        "ERR PREFIX option requires BCAST mode to be enabled".to_string(),
+                    ));
+                }
+                let redirect = match tracking.redirect.map(|target| target.parse::<u64>()) {
+                    Some(Ok(target)) => Some(target),
+                    Some(Err(_)) => return Ok(Error::NotAnInteger.into()),
+                    None => None,
+                };
+                let options = Options {
+                    redirect,
+                    bcast: tracking.bcast,
+                    prefixes: tracking.prefixes,
+                    noloop: tracking.noloop,
+                };
+                if let Err(error) = store.tracking().enable(id, options) {
+                    return Ok(Frame::Error(error.to_string()));
+                }
+                clients.update(id, |client| client.tracking = true);
+                Frame::OK
+            }
+            ClientOp::GetRedir => match store.tracking().options(id) {
+                Some(options) => Frame::Integer(options.redirect.unwrap_or(0)),
+                None => Frame::Signed(-1),
+            },
+            // replies that are off stay off until turned on
+            ClientOp::Reply(ReplyMode::Skip) if store.reply_mode() == ReplyMode::Off => Frame::OK,
+            // the server holds back the OK to OFF and SKIP
+            ClientOp::Reply(mode) => {
+                store.set_reply_mode(mode);
+                Frame::OK
+            }
+        };
+        Ok(response)
+    }
+}
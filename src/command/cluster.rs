@@ -0,0 +1,415 @@
+use anyhow::bail;
+use bytes::Bytes;
+use std::collections::HashSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    cluster::{meet, BUS_PORT_OFFSET},
+    frame::Frame,
+    parse::{Parse, ParseError},
+    store::{
+        cluster::{key_slot, Migration, Owner, ERR_DISABLED, SLOTS},
+        Store,
+    },
+};
+
+fn bulk(s: impl Into<String>) -> Frame {
+    Frame::Bulk(Bytes::from(s.into()))
+}
+
+#[derive(Debug)]
+enum ClusterOp {
+    Info,
+    MyId,
+    Slots,
+    Shards,
+    KeySlot(Bytes),
+    Meet {
+        host: String,
+        port: String,
+        bus_port: Option<String>,
+    },
+    Nodes,
+    BumpEpoch,
+    SetSlot {
+        slot: String,
+        action: String,
+        node: Option<String>,
+    },
+    GetKeysInSlot {
+        slot: String,
+        count: String,
+    },
+    CountKeysInSlot(String),
+}
+
+/// CLUSTER INFO / MYID / SLOTS / SHARDS / NODES / KEYSLOT key /
+/// MEET host port [bus-port] / BUMPEPOCH /
+/// SETSLOT slot IMPORTING|MIGRATING|NODE node-id / SETSLOT slot STABLE /
+/// GETKEYSINSLOT slot count / COUNTKEYSINSLOT slot
+#[derive(Debug)]
+pub struct Cluster {
+    op: ClusterOp,
+}
+
+/// How a node is described in CLUSTER SLOTS: address, then id.
+fn slots_node(host: String, port: u16, id: &str) -> Frame {
+    Frame::Array(vec![
+        bulk(host),
+        Frame::Integer(u64::from(port)),
+        bulk(id),
+        Frame::Array(vec![]),
+    ])
+}
+
+/// How a node is described in CLUSTER SHARDS.
+fn shards_node(host: String, port: u16, id: &str, offset: u64, health: &str) -> Frame {
+    Frame::Array(vec![
+        bulk("id"),
+        bulk(id),
+        bulk("port"),
+        Frame::Integer(u64::from(port)),
+        bulk("ip"),
+        bulk(host.clone()),
+        bulk("endpoint"),
+        bulk(host),
+        bulk("role"),
+        bulk("master"),
+        bulk("replication-offset"),
+        Frame::Integer(offset),
+        bulk("health"),
+        bulk(health),
+    ])
+}
+
+/// The slots in `ranges` that `owner` serves, as CLUSTER NODES lists them.
+fn slots_of(ranges: &[(u16, u16, Owner)], owner: &Owner) -> String {
+    ranges
+        .iter()
+        .filter(|(.., o)| o == owner)
+        .map(|(first, last, _)| {
+            if first == last {
+                format!(" {}", first)
+            } else {
+                format!(" {}-{}", first, last)
+            }
+        })
+        .collect()
+}
+
+/// Slots on the move, as CLUSTER NODES lists them after our own slots.
+fn migrations_of(migrations: Vec<(u16, Migration)>) -> String {
+    migrations
+        .into_iter()
+        .map(|(slot, migration)| match migration {
+            Migration::Migrating(node) => format!(" [{}->-{}]", slot, node.id),
+            Migration::Importing(node) => format!(" [{}-<-{}]", slot, node.id),
+        })
+        .collect()
+}
+
+/// A slot number as given, if it's in range.
+fn parse_slot(slot: &str) -> Option<u16> {
+    slot.parse().ok().filter(|slot| *slot < SLOTS)
+}
+
+/// A line per node: id, addresses, flags, master, last ping sent, last
+/// pong received, config epoch, link state and slots.
+fn nodes(store: &Store, ranges: &[(u16, u16, Owner)]) -> String {
+    let cluster = store.cluster();
+    let mut lines = format!(
+        "{} {}:{}@{} myself,master - 0 0 {} connected{}{}\n",
+        cluster.id(),
+        store.config().host(),
+        store.config().port(),
+        cluster.bus_port(),
+        cluster.my_epoch(),
+        slots_of(ranges, &Owner::Myself),
+        migrations_of(cluster.migrations()),
+    );
+    let now = SystemTime::now();
+    for peer in cluster.peers() {
+        let seen = (now - peer.last_seen.elapsed())
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |at| at.as_millis());
+        let node = &peer.node;
+        lines.push_str(&format!(
+            "{} {}:{}@{} master{} - 0 {} {} connected{}\n",
+            node.id,
+            node.host,
+            node.port,
+            node.bus_port,
+            if peer.is_failing() { ",fail?" } else { "" },
+            seen,
+            peer.epoch,
+            slots_of(ranges, &Owner::Node(node.clone())),
+        ));
+    }
+    lines
+}
+
+impl Cluster {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Cluster> {
+        let subcommand = parse.next_string()?;
+        let op = match subcommand.to_uppercase().as_str() {
+            "INFO" => ClusterOp::Info,
+            "MYID" => ClusterOp::MyId,
+            "SLOTS" => ClusterOp::Slots,
+            "SHARDS" => ClusterOp::Shards,
+            "KEYSLOT" => ClusterOp::KeySlot(parse.next_bytes()?),
+            "MEET" => {
+                let host = parse.next_string()?;
+                let port = parse.next_string()?;
+                let bus_port = match parse.next_string() {
+                    Ok(bus_port) => Some(bus_port),
+                    Err(ParseError::EndOfStream) => None,
+                    Err(e) => return Err(e.into()),
+                };
+                ClusterOp::Meet {
+                    host,
+                    port,
+                    bus_port,
+                }
+            }
+            "NODES" => ClusterOp::Nodes,
+            "BUMPEPOCH" => ClusterOp::BumpEpoch,
+            "SETSLOT" => {
+                let slot = parse.next_string()?;
+                let action = parse.next_string()?;
+                let node = match parse.next_string() {
+                    Ok(node) => Some(node),
+                    Err(ParseError::EndOfStream) => None,
+                    Err(e) => return Err(e.into()),
+                };
+                ClusterOp::SetSlot { slot, action, node }
+            }
+            "GETKEYSINSLOT" => ClusterOp::GetKeysInSlot {
+                slot: parse.next_string()?,
+                count: parse.next_string()?,
+            },
+            "COUNTKEYSINSLOT" => ClusterOp::CountKeysInSlot(parse.next_string()?),
+            _ => bail!("ERR unknown subcommand '{}'. Try CLUSTER HELP.", subcommand),
+        };
+        parse.finish()?;
+        Ok(Cluster { op })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let cluster = store.cluster();
+        if !cluster.is_enabled() {
+            return Ok(Frame::Error(ERR_DISABLED.to_string()));
+        }
+        let (host, port) = (store.config().host(), store.config().port());
+        // unassigned slots aren't reported
+        let ranges: Vec<_> = cluster
+            .ranges()
+            .into_iter()
+            .filter(|(.., owner)| *owner != Owner::Unassigned)
+            .collect();
+        let response = match self.op {
+            ClusterOp::Info => {
+                let assigned: u32 = ranges
+                    .iter()
+                    .map(|(first, last, _)| u32::from(last - first) + 1)
+                    .sum();
+                let state = if assigned == u32::from(SLOTS) {
+                    "ok"
+                } else {
+                    "fail"
+                };
+                let owners: HashSet<_> = ranges
+                    .iter()
+                    .map(|(.., owner)| match owner {
+                        Owner::Node(node) => Some(node.id.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                let fields = [
+                    ("cluster_state", state.to_string()),
+                    ("cluster_slots_assigned", assigned.to_string()),
+                    ("cluster_slots_ok", assigned.to_string()),
+                    ("cluster_slots_pfail", "0".to_string()),
+                    ("cluster_slots_fail", "0".to_string()),
+                    (
+                        "cluster_known_nodes",
+                        (cluster.nodes().len() + 1).to_string(),
+                    ),
+                    ("cluster_size", owners.len().to_string()),
+                    ("cluster_current_epoch", cluster.current_epoch().to_string()),
+                    ("cluster_my_epoch", cluster.my_epoch().to_string()),
+                ];
+                let info: String = fields
+                    .iter()
+                    .map(|(name, value)| format!("{}:{}\r\n", name, value))
+                    .collect();
+                bulk(info)
+            }
+            ClusterOp::MyId => bulk(cluster.id()),
+            ClusterOp::Slots => Frame::Array(
+                ranges
+                    .into_iter()
+                    .map(|(first, last, owner)| {
+                        let node = match owner {
+                            Owner::Node(node) => slots_node(node.host, node.port, &node.id),
+                            _ => slots_node(host.clone(), port, cluster.id()),
+                        };
+                        Frame::Array(vec![
+                            Frame::Integer(u64::from(first)),
+                            Frame::Integer(u64::from(last)),
+                            node,
+                        ])
+                    })
+                    .collect(),
+            ),
+            ClusterOp::Shards => {
+                // a shard per node, ourselves first, with all its ranges
+                let mut shards: Vec<(Owner, &str, Vec<Frame>)> =
+                    vec![(Owner::Myself, "online", vec![])];
+                for peer in cluster.peers() {
+                    let health = if peer.is_failing() { "fail" } else { "online" };
+                    shards.push((Owner::Node(peer.node), health, vec![]));
+                }
+                for (first, last, owner) in ranges {
+                    if let Some((.., slots)) = shards.iter_mut().find(|(o, ..)| *o == owner) {
+                        slots.push(Frame::Integer(u64::from(first)));
+                        slots.push(Frame::Integer(u64::from(last)));
+                    }
+                }
+                let shards = shards
+                    .into_iter()
+                    .map(|(owner, health, slots)| {
+                        let node = match owner {
+                            Owner::Node(node) => {
+                                shards_node(node.host, node.port, &node.id, 0, health)
+                            }
+                            _ => shards_node(
+                                host.clone(),
+                                port,
+                                cluster.id(),
+                                store.replicas().offset(),
+                                health,
+                            ),
+                        };
+                        Frame::Array(vec![
+                            bulk("slots"),
+                            Frame::Array(slots),
+                            bulk("nodes"),
+                            Frame::Array(vec![node]),
+                        ])
+                    })
+                    .collect();
+                Frame::Array(shards)
+            }
+            ClusterOp::KeySlot(key) => Frame::Integer(u64::from(key_slot(&key))),
+            ClusterOp::Meet {
+                host,
+                port,
+                bus_port,
+            } => {
+                let port = port.parse::<u16>().ok();
+                let bus_port = match bus_port {
+                    Some(bus_port) => bus_port.parse::<u16>().ok(),
+                    None => port.and_then(|port| port.checked_add(BUS_PORT_OFFSET)),
+                };
+                let Some(bus_port) = bus_port.filter(|_| port.is_some()) else {
+                    return Ok(Frame::Error(
+                        "ERR Invalid node address specified".to_string(),
+                    ));
+                };
+                match meet(store, &host, bus_port).await {
+                    Ok(()) => Frame::OK,
+                    Err(err) => Frame::Error(format!("ERR Unable to meet the node: {:#}", err)),
+                }
+            }
+            ClusterOp::Nodes => bulk(nodes(store, &ranges)),
+            ClusterOp::BumpEpoch => match cluster.bump_epoch() {
+                (true, epoch) => Frame::Simple(format!("BUMPED {}", epoch)),
+                (false, epoch) => Frame::Simple(format!("STILL {}", epoch)),
+            },
+            ClusterOp::SetSlot { slot, action, node } => {
+                let Some(slot) = parse_slot(&slot) else {
+                    return Ok(Frame::Error("ERR Invalid or out of range slot".to_string()));
+                };
+                let result = match (action.to_uppercase().as_str(), node) {
+                    ("MIGRATING", Some(id)) => cluster.set_migrating(slot, &id),
+                    ("IMPORTING", Some(id)) => cluster.set_importing(slot, &id),
+                    ("STABLE", None) => {
+                        cluster.set_stable(slot);
+                        Ok(())
+                    }
+                    ("NODE", Some(id)) => {
+                        let giving_away = cluster.owner(slot) == Owner::Myself && id != cluster.id();
+                        if giving_away && !store.keys_in_slot(slot, 1).is_empty() {
+                            Err(format!("ERR Can't assign hashslot {} to a different node while I still hold keys for this hash slot.", slot))
+                        } else {
+                            cluster.set_node(slot, &id)
+                        }
+                    }
+                    _ => Err("ERR Invalid CLUSTER SETSLOT action or number of arguments. Try CLUSTER HELP".to_string()),
+                };
+                match result {
+                    Ok(()) => Frame::OK,
+                    Err(error) => Frame::Error(error),
+                }
+            }
+            ClusterOp::GetKeysInSlot { slot, count } => {
+                let (Some(slot), Ok(count)) = (parse_slot(&slot), count.parse::<usize>()) else {
+                    return Ok(Frame::Error(
+                        "ERR Invalid slot or number of keys".to_string(),
+                    ));
+                };
+                Frame::Array(
+                    store
+                        .keys_in_slot(slot, count)
+                        .into_iter()
+                        .map(Frame::Bulk)
+                        .collect(),
+                )
+            }
+            ClusterOp::CountKeysInSlot(slot) => match parse_slot(&slot) {
+                Some(slot) => Frame::Integer(store.keys_in_slot(slot, usize::MAX).len() as u64),
+                None => Frame::Error("ERR Invalid slot".to_string()),
+            },
+        };
+        Ok(response)
+    }
+}
+
+/// ASKING: lets the client's next command at a slot this node is importing,
+/// after an ASK redirect. The flag lives with the connection, see
+/// `server::Handler`.
+#[derive(Debug)]
+pub struct Asking;
+
+impl Asking {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Asking> {
+        parse.finish()?;
+        Ok(Asking)
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        if !store.cluster().is_enabled() {
+            return Ok(Frame::Error(ERR_DISABLED.to_string()));
+        }
+        Ok(Frame::OK)
+    }
+}
+
+/// READONLY / READWRITE, which cluster clients send to say whether they
+/// will read from replicas. Every node here is a master, so both are no-ops.
+#[derive(Debug)]
+pub struct ReadMode;
+
+impl ReadMode {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<ReadMode> {
+        parse.finish()?;
+        Ok(ReadMode)
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        if !store.cluster().is_enabled() {
+            return Ok(Frame::Error(ERR_DISABLED.to_string()));
+        }
+        Ok(Frame::OK)
+    }
+}
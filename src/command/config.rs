@@ -0,0 +1,396 @@
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::bail;
+use bytes::Bytes;
+
+use crate::{
+    frame::{Frame, Limits},
+    glob,
+    log::{self, Level},
+    parse::{Parse, ParseError},
+    store::{
+        config::{format_save_rules, parse_memory, parse_save_rules, SaveRule},
+        evict::Policy,
+        notify::NotifyFlags,
+        output::{parse_output_limits, Class, OutputLimit},
+        Store, DATABASES,
+    },
+};
+
+/// The least `proto-max-bulk-len` and `client-query-buffer-limit` may be.
+const MIN_LIMIT: u64 = 1024 * 1024;
+const ERR_LIMIT_RANGE: &str = "argument must be between 1048576 and 9223372036854775807 inclusive";
+
+/// The parameters CONFIG GET and SET know about, in the order GET lists them.
+const PARAMETERS: [&str; 27] = [
+    "dir",
+    "dbfilename",
+    "save",
+    "maxmemory",
+    "maxmemory-policy",
+    "maxmemory-samples",
+    "appendonly",
+    "appendfilename",
+    "auto-aof-rewrite-percentage",
+    "auto-aof-rewrite-min-size",
+    "notify-keyspace-events",
+    "repl-backlog-size",
+    "repl-timeout",
+    "databases",
+    "aclfile",
+    "enable-debug-command",
+    "cluster-enabled",
+    "timeout",
+    "maxclients",
+    "proto-max-bulk-len",
+    "proto-max-multibulk-len",
+    "client-query-buffer-limit",
+    "client-output-buffer-limit",
+    "loglevel",
+    "logfile",
+    "latency-monitor-threshold",
+    "lua-time-limit",
+];
+
+/// A parameter's new value, checked but not yet applied.
+#[derive(Debug)]
+enum Setting {
+    Dir(String),
+    DbFilename(String),
+    Save(Vec<SaveRule>),
+    MaxMemory(u64),
+    MaxMemoryPolicy(Policy),
+    MaxMemorySamples(usize),
+    AppendOnly(bool),
+    AutoAofRewritePercentage(u64),
+    AutoAofRewriteMinSize(u64),
+    NotifyKeyspaceEvents(NotifyFlags),
+    ReplBacklogSize(usize),
+    ReplTimeout(Duration),
+    Timeout(u64),
+    MaxClients(usize),
+    ProtoMaxBulkLen(u64),
+    ProtoMaxMultibulkLen(u64),
+    ClientQueryBufferLimit(usize),
+    ClientOutputBufferLimit(Vec<(Class, OutputLimit)>),
+    LogLevel(Level),
+    LatencyMonitorThreshold(u64),
+    LuaTimeLimit(u64),
+}
+
+impl Setting {
+    /// Checks `value` for the parameter `name`, returning the error to reply
+    /// with when either is invalid.
+    fn parse(name: &str, value: &str) -> Result<Setting, String> {
+        let invalid = |reason: &str| {
+            format!(
+                "ERR CONFIG SET failed (possibly related to argument '{}') - {}",
+                name, reason
+            )
+        };
+        let memory =
+            || parse_memory(value).ok_or_else(|| invalid("argument must be a memory value"));
+        let setting = match name {
+            "dir" if Path::new(value).is_dir() => Setting::Dir(value.to_string()),
+            "dir" => return Err(invalid("No such file or directory")),
+            "dbfilename" if value.contains('/') => {
+                return Err(invalid("dbfilename can't be a path, just a filename"))
+            }
+            "dbfilename" => Setting::DbFilename(value.to_string()),
+            "save" => match parse_save_rules(value) {
+                Some(rules) => Setting::Save(rules),
+                None => return Err(invalid("Invalid save parameters")),
+            },
+            "maxmemory" => Setting::MaxMemory(memory()?),
+            "maxmemory-policy" => match Policy::parse(value) {
+                Some(policy) => Setting::MaxMemoryPolicy(policy),
+                None => return Err(invalid("argument(s) must be one of the following: volatile-lru, allkeys-lru, volatile-lfu, allkeys-lfu, volatile-random, allkeys-random, volatile-ttl, noeviction")),
+            },
+            "maxmemory-samples" => match value.parse::<usize>() {
+                Ok(samples) if (1..=64).contains(&samples) => Setting::MaxMemorySamples(samples),
+                _ => return Err(invalid("argument must be between 1 and 64 inclusive")),
+            },
+            "appendonly" => match value.to_ascii_lowercase().as_str() {
+                "yes" => Setting::AppendOnly(true),
+                "no" => Setting::AppendOnly(false),
+                _ => return Err(invalid("argument must be 'yes' or 'no'")),
+            },
+            // the file being appended to can't move
+            "appendfilename" | "databases" | "aclfile" | "enable-debug-command"
+            | "cluster-enabled" | "logfile" => {
+                return Err(invalid("can't set immutable config"))
+            }
+            "auto-aof-rewrite-percentage" => match value.parse() {
+                Ok(percentage) => Setting::AutoAofRewritePercentage(percentage),
+                Err(_) => return Err(invalid("argument couldn't be parsed into an integer")),
+            },
+            "auto-aof-rewrite-min-size" => Setting::AutoAofRewriteMinSize(memory()?),
+            "notify-keyspace-events" => match NotifyFlags::parse(value) {
+                Some(flags) => Setting::NotifyKeyspaceEvents(flags),
+                None => {
+                    return Err(invalid(
+                        "Invalid event class character. Use 'Ag$lshzxeKEtmdn'.",
+                    ))
+                }
+            },
+            "repl-backlog-size" => Setting::ReplBacklogSize(memory()? as usize),
+            "repl-timeout" => match value.parse::<u64>() {
+                Ok(secs) if secs > 0 => Setting::ReplTimeout(Duration::from_secs(secs)),
+                _ => {
+                    return Err(invalid(
+                        "argument must be between 1 and 2147483647 inclusive",
+                    ))
+                }
+            },
+            "timeout" => match value.parse::<u64>() {
+                Ok(secs) if secs <= i32::MAX as u64 => Setting::Timeout(secs),
+                _ => {
+                    return Err(invalid(
+                        "argument must be between 0 and 2147483647 inclusive",
+                    ))
+                }
+            },
+            "maxclients" => match value.parse::<usize>() {
+                Ok(max) if (1..=u32::MAX as usize).contains(&max) => Setting::MaxClients(max),
+                _ => {
+                    return Err(invalid(
+                        "argument must be between 1 and 4294967295 inclusive",
+                    ))
+                }
+            },
+            "proto-max-bulk-len" => match memory()? {
+                len if len >= MIN_LIMIT => Setting::ProtoMaxBulkLen(len),
+                _ => return Err(invalid(ERR_LIMIT_RANGE)),
+            },
+            "proto-max-multibulk-len" => match value.parse::<u64>() {
+                Ok(len) if (1..=i32::MAX as u64).contains(&len) => {
+                    Setting::ProtoMaxMultibulkLen(len)
+                }
+                _ => {
+                    return Err(invalid(
+                        "argument must be between 1 and 2147483647 inclusive",
+                    ))
+                }
+            },
+            "client-query-buffer-limit" => match memory()? {
+                len if len >= MIN_LIMIT => {
+                    Setting::ClientQueryBufferLimit(usize::try_from(len).unwrap_or(usize::MAX))
+                }
+                _ => return Err(invalid(ERR_LIMIT_RANGE)),
+            },
+            "client-output-buffer-limit" => match parse_output_limits(value) {
+                Ok(limits) => Setting::ClientOutputBufferLimit(limits),
+                Err(reason) => return Err(invalid(reason)),
+            },
+            "loglevel" => match Level::parse(value) {
+                Some(level) => Setting::LogLevel(level),
+                None => {
+                    return Err(invalid(
+                        "argument(s) must be one of the following: debug, verbose, notice, warning",
+                    ))
+                }
+            },
+            "latency-monitor-threshold" => match value.parse::<u64>() {
+                Ok(ms) if ms <= i64::MAX as u64 => Setting::LatencyMonitorThreshold(ms),
+                _ => {
+                    return Err(invalid(
+                        "argument must be between 0 and 9223372036854775807 inclusive",
+                    ))
+                }
+            },
+            "lua-time-limit" => match value.parse::<u64>() {
+                Ok(ms) if ms <= i64::MAX as u64 => Setting::LuaTimeLimit(ms),
+                _ => {
+                    return Err(invalid(
+                        "argument must be between 0 and 9223372036854775807 inclusive",
+                    ))
+                }
+            },
+            _ => {
+                return Err(format!(
+                    "ERR Unknown option or number of arguments for CONFIG SET - '{}'",
+                    name
+                ))
+            }
+        };
+        Ok(setting)
+    }
+
+    fn apply(self, store: &Store) {
+        let config = store.config();
+        match self {
+            Setting::Dir(dir) => config.set_dir(dir),
+            Setting::DbFilename(dbfilename) => config.set_dbfilename(dbfilename),
+            Setting::Save(rules) => config.set_save(rules),
+            Setting::MaxMemory(maxmemory) => config.set_maxmemory(maxmemory),
+            Setting::MaxMemoryPolicy(policy) => config.set_maxmemory_policy(policy),
+            Setting::MaxMemorySamples(samples) => config.set_maxmemory_samples(samples),
+            Setting::AppendOnly(appendonly) => {
+                config.set_appendonly(appendonly);
+                if !appendonly {
+                    store.aof().close();
+                } else if !store.aof().is_open() {
+                    // the file starts from a rewrite, which opens it when done
+                    store.bgrewriteaof();
+                }
+            }
+            Setting::AutoAofRewritePercentage(percentage) => {
+                config.set_auto_aof_rewrite_percentage(percentage)
+            }
+            Setting::AutoAofRewriteMinSize(size) => config.set_auto_aof_rewrite_min_size(size),
+            Setting::NotifyKeyspaceEvents(flags) => store.set_notify_keyspace_events(flags),
+            Setting::ReplBacklogSize(size) => store.replicas().set_backlog_size(size),
+            Setting::ReplTimeout(timeout) => store.set_repl_timeout(timeout),
+            Setting::Timeout(secs) => config.set_timeout(secs),
+            Setting::MaxClients(max) => store.clients().set_max_clients(max),
+            Setting::ProtoMaxBulkLen(len) => config.set_limits(Limits {
+                bulk_len: len,
+                ..config.limits()
+            }),
+            Setting::ProtoMaxMultibulkLen(len) => config.set_limits(Limits {
+                multibulk_len: len,
+                ..config.limits()
+            }),
+            Setting::ClientQueryBufferLimit(len) => config.set_limits(Limits {
+                query_buffer: len,
+                ..config.limits()
+            }),
+            Setting::ClientOutputBufferLimit(limits) => {
+                for (class, limit) in limits {
+                    match class {
+                        Class::Normal => config.set_output_limit(limit),
+                        Class::Replica => store.replicas().set_output_limit(limit),
+                        Class::PubSub => store.pubsub().set_output_limit(limit),
+                    }
+                }
+            }
+            Setting::LogLevel(level) => log::set_level(level),
+            Setting::LatencyMonitorThreshold(ms) => store.latency().set_threshold(ms),
+            Setting::LuaTimeLimit(ms) => config.set_lua_time_limit(ms),
+        }
+    }
+}
+
+/// The current value of a parameter from `PARAMETERS`.
+fn get(store: &Store, name: &str) -> String {
+    let config = store.config();
+    match name {
+        "dir" => config.dir(),
+        "dbfilename" => config.dbfilename(),
+        "save" => format_save_rules(&config.save()),
+        "maxmemory" => config.maxmemory().to_string(),
+        "maxmemory-policy" => config.maxmemory_policy().name().to_string(),
+        "maxmemory-samples" => config.maxmemory_samples().to_string(),
+        "appendonly" => if config.appendonly() { "yes" } else { "no" }.to_string(),
+        "appendfilename" => config.appendfilename(),
+        "databases" => DATABASES.to_string(),
+        "aclfile" => config.aclfile(),
+        "enable-debug-command" => config.enable_debug_command(),
+        "cluster-enabled" => if store.cluster().is_enabled() {
+            "yes"
+        } else {
+            "no"
+        }
+        .to_string(),
+        "auto-aof-rewrite-percentage" => config.auto_aof_rewrite_percentage().to_string(),
+        "auto-aof-rewrite-min-size" => config.auto_aof_rewrite_min_size().to_string(),
+        "notify-keyspace-events" => store.notify_keyspace_events().to_string(),
+        "repl-backlog-size" => store.replicas().backlog_size().to_string(),
+        "repl-timeout" => store.repl_timeout().as_secs().to_string(),
+        "timeout" => config.timeout().to_string(),
+        "maxclients" => store.clients().max_clients().to_string(),
+        "proto-max-bulk-len" => config.limits().bulk_len.to_string(),
+        "proto-max-multibulk-len" => config.limits().multibulk_len.to_string(),
+        "client-query-buffer-limit" => config.limits().query_buffer.to_string(),
+        "client-output-buffer-limit" => format!(
+            "normal {} slave {} pubsub {}",
+            config.output_limit(),
+            store.replicas().output_limit(),
+            store.pubsub().output_limit()
+        ),
+        "loglevel" => log::level().name().to_string(),
+        "logfile" => log::logfile(),
+        "latency-monitor-threshold" => store.latency().threshold().to_string(),
+        "lua-time-limit" => config.lua_time_limit().to_string(),
+        _ => unreachable!("unknown parameter {}", name),
+    }
+}
+
+#[derive(Debug)]
+enum ConfigOp {
+    Get(Vec<String>),
+    Set(Vec<(String, String)>),
+}
+
+/// CONFIG GET pattern [pattern ...] / CONFIG SET parameter value [parameter value ...]
+#[derive(Debug)]
+pub struct Config {
+    op: ConfigOp,
+}
+
+impl Config {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Config> {
+        let subcommand = parse.next_string()?;
+        let mut args = vec![];
+        loop {
+            match parse.next_string() {
+                Ok(arg) => args.push(arg),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let op = match subcommand.to_uppercase().as_str() {
+            "GET" if !args.is_empty() => ConfigOp::Get(args),
+            "SET" if !args.is_empty() && args.len() % 2 == 0 => ConfigOp::Set(
+                args.chunks(2)
+                    .map(|pair| (pair[0].to_lowercase(), pair[1].clone()))
+                    .collect(),
+            ),
+            "GET" | "SET" => bail!(
+                "ERR wrong number of arguments for 'config|{}' command",
+                subcommand.to_lowercase()
+            ),
+            _ => bail!("ERR unknown subcommand '{}'. Try CONFIG HELP.", subcommand),
+        };
+        Ok(Config { op })
+    }
+
+    /// GET replies with each matching parameter and its value, as a map for
+    /// RESP3 clients. SET applies either every change or, if any is invalid, none.
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        match self.op {
+            ConfigOp::Get(patterns) => {
+                let mut response = vec![];
+                for name in PARAMETERS {
+                    let matched = patterns
+                        .iter()
+                        .any(|pattern| glob::matches(pattern.as_bytes(), name.as_bytes(), true));
+                    if matched {
+                        response.push((
+                            Frame::Bulk(Bytes::from(name)),
+                            Frame::Bulk(get(store, name).into()),
+                        ));
+                    }
+                }
+                Ok(Frame::map(response, store.resp3()))
+            }
+            ConfigOp::Set(pairs) => {
+                let mut settings = vec![];
+                for (i, (name, value)) in pairs.iter().enumerate() {
+                    if pairs[..i].iter().any(|(other, _)| other == name) {
+                        return Ok(Frame::Error(format!("ERR Duplicate parameter - {}", name)));
+                    }
+                    match Setting::parse(name, value) {
+                        Ok(setting) => settings.push(setting),
+                        Err(error) => return Ok(Frame::Error(error)),
+                    }
+                }
+                for setting in settings {
+                    setting.apply(store);
+                }
+                Ok(Frame::OK)
+            }
+        }
+    }
+}
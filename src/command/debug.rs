@@ -0,0 +1,138 @@
+use anyhow::bail;
+use bytes::Bytes;
+use std::time::Duration;
+
+use crate::{
+    error::Error,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    rdb,
+    store::{config::parse_memory, Store},
+};
+
+const ERR_NOT_ALLOWED: &str = "ERR DEBUG command not allowed. If the enable-debug-command option is set to \"local\", you can run it from a local connection, otherwise you need to set this option in the configuration file, and then restart the server.";
+
+#[derive(Debug)]
+enum DebugOp {
+    Reload { save: bool },
+    Object(Bytes),
+    Sleep(String),
+    SetActiveExpire(String),
+    QuicklistPackedThreshold(String),
+    ChangeReplId,
+}
+
+/// DEBUG RELOAD / OBJECT / SLEEP seconds / SET-ACTIVE-EXPIRE 0|1 /
+/// QUICKLIST-PACKED-THRESHOLD size / CHANGE-REPL-ID, for tests. Only runs as far as the
+/// enable-debug-command parameter allows.
+#[derive(Debug)]
+pub struct Debug {
+    op: DebugOp,
+}
+
+impl Debug {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Debug> {
+        let subcommand = parse.next_string()?;
+        let op = match subcommand.to_uppercase().as_str() {
+            "RELOAD" => {
+                let mut save = true;
+                loop {
+                    match parse.next_string() {
+                        Ok(option) if option.eq_ignore_ascii_case("NOSAVE") => save = false,
+                        Ok(_) => bail!("ERR DEBUG RELOAD only supports the NOSAVE option"),
+                        Err(ParseError::EndOfStream) => break,
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                DebugOp::Reload { save }
+            }
+            "OBJECT" => DebugOp::Object(parse.next_bytes()?),
+            "SLEEP" => DebugOp::Sleep(parse.next_string()?),
+            "SET-ACTIVE-EXPIRE" => DebugOp::SetActiveExpire(parse.next_string()?),
+            "QUICKLIST-PACKED-THRESHOLD" => DebugOp::QuicklistPackedThreshold(parse.next_string()?),
+            "CHANGE-REPL-ID" => DebugOp::ChangeReplId,
+            _ => bail!("ERR unknown subcommand '{}'. Try DEBUG HELP.", subcommand),
+        };
+        Ok(Debug { op })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        if !is_allowed(store) {
+            return Ok(Frame::Error(ERR_NOT_ALLOWED.to_string()));
+        }
+        match self.op {
+            // saves the keyspace to the RDB file, unless told not to, and
+            // replaces it with what the file holds
+            DebugOp::Reload { save } => {
+                if save {
+                    if let Err(err) = store.save().await {
+                        log_warning!("DEBUG RELOAD failed to save: {:#}", err);
+                        return Ok(Frame::Error("ERR Error trying to save the DB".to_string()));
+                    }
+                }
+                if let Err(err) = store.load_rdb_file().await {
+                    log_warning!("DEBUG RELOAD failed to load: {:#}", err);
+                    return Ok(Frame::Error(
+                        "ERR Error trying to load the RDB dump, check server logs.".to_string(),
+                    ));
+                }
+                Ok(Frame::OK)
+            }
+            DebugOp::Object(key) => {
+                let described = store.with_value(&key, |value, idle| {
+                    format!(
+                        "Value at:{:p} refcount:1 encoding:{} serializedlength:{} lru_seconds_idle:{}",
+                        value,
+                        value.encoding(),
+                        rdb::serialized_len(value),
+                        idle.as_secs()
+                    )
+                });
+                Ok(match described {
+                    Some(description) => Frame::Simple(description),
+                    None => Frame::Error("ERR no such key".to_string()),
+                })
+            }
+            // holds up only this client, where Redis would stall them all
+            DebugOp::Sleep(secs) => match secs.parse::<f64>().map(Duration::try_from_secs_f64) {
+                Ok(Ok(duration)) => {
+                    tokio::time::sleep(duration).await;
+                    Ok(Frame::OK)
+                }
+                _ => Ok(Frame::Error("ERR value is not a valid float".to_string())),
+            },
+            DebugOp::SetActiveExpire(active) => match active.as_str() {
+                "0" | "1" => {
+                    store.set_active_expire(active == "1");
+                    Ok(Frame::OK)
+                }
+                _ => Ok(Error::NotAnInteger.into()),
+            },
+            // there are no lists, so nothing is packed; the size is only checked
+            DebugOp::QuicklistPackedThreshold(size) => match parse_memory(&size) {
+                Some(_) => Ok(Frame::OK),
+                None => Ok(Frame::Error(
+                    "ERR argument must be a memory value".to_string(),
+                )),
+            },
+            // no replica will be able to resume the stream as it was
+            DebugOp::ChangeReplId => {
+                store.replicas().change_replid();
+                Ok(Frame::OK)
+            }
+        }
+    }
+}
+
+/// Whether enable-debug-command lets this client run DEBUG. The server's
+/// own handle counts as local.
+fn is_allowed(store: &Store) -> bool {
+    match store.config().enable_debug_command().as_str() {
+        "yes" => true,
+        "local" => store
+            .client_id()
+            .and_then(|id| store.clients().get(id))
+            .is_none_or(|client| client.addr.ip().is_loopback()),
+        _ => false,
+    }
+}
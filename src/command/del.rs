@@ -0,0 +1,43 @@
+use bytes::Bytes;
+
+use crate::{
+    frame::Frame,
+    parse::{Parse, ParseError},
+    publisher::{publish, Action},
+    store::Store,
+};
+
+#[derive(Debug, Default)]
+pub struct Del {
+    keys: Vec<Bytes>,
+}
+
+impl Del {
+    pub fn new(keys: Vec<Bytes>) -> Self {
+        Self { keys }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Del> {
+        let mut keys = vec![parse.next_bytes()?];
+        loop {
+            match parse.next_bytes() {
+                Ok(key) => keys.push(key),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(Del::new(keys))
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let mut deleted = 0;
+        for key in self.keys {
+            if store.del(key.clone()) {
+                deleted += 1;
+                publish(store, Action::Del { key }).await?;
+            }
+        }
+
+        Ok(Frame::Integer(deleted))
+    }
+}
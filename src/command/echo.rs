@@ -1,6 +1,6 @@
 use bytes::Bytes;
 
-use crate::{comms::Comms, frame::Frame, parse::Parse};
+use crate::{frame::Frame, parse::Parse};
 
 #[derive(Debug, Default)]
 pub struct Echo {
@@ -17,11 +17,7 @@ impl Echo {
         Ok(Echo::new(msg.into()))
     }
 
-    pub(crate) async fn apply<C: Comms>(self, comms: &mut C) -> anyhow::Result<()> {
-        let response = Frame::Bulk(self.msg.clone());
-
-        comms.write_frame(&response).await?;
-
-        Ok(())
+    pub(crate) async fn apply(self) -> anyhow::Result<Frame> {
+        Ok(Frame::Bulk(self.msg.clone()))
     }
 }
@@ -0,0 +1,90 @@
+use bytes::Bytes;
+
+use crate::{
+    error::Error,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    scripting,
+    store::Store,
+};
+
+/// The script to run: its body for EVAL, or the SHA1 of a cached one for EVALSHA.
+#[derive(Debug)]
+enum Script {
+    Body(Bytes),
+    Sha(String),
+}
+
+/// EVAL / EVALSHA
+#[derive(Debug)]
+pub struct Eval {
+    script: Script,
+    numkeys: String,
+    /// The keys followed by the arguments; `numkeys` says where they split.
+    keys_and_args: Vec<Bytes>,
+}
+
+impl Eval {
+    pub(crate) fn parse_frames(by_sha: bool, parse: &mut Parse) -> anyhow::Result<Eval> {
+        let script = match by_sha {
+            true => Script::Sha(parse.next_string()?),
+            false => Script::Body(parse.next_bytes()?),
+        };
+        let numkeys = parse.next_string()?;
+        let mut keys_and_args = vec![];
+        loop {
+            match parse.next_bytes() {
+                Ok(arg) => keys_and_args.push(arg),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(Eval {
+            script,
+            numkeys,
+            keys_and_args,
+        })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let (keys, args) = match split_keys(&self.numkeys, self.keys_and_args) {
+            Ok(split) => split,
+            Err(reply) => return Ok(reply),
+        };
+        let body = match self.script {
+            Script::Body(body) => {
+                store.scripts().load(body.clone());
+                body
+            }
+            Script::Sha(sha) => match store.scripts().get(&sha) {
+                Some(body) => body,
+                None => return Ok(Error::NoScript.into()),
+            },
+        };
+        scripting::run(store, body, keys, args).await
+    }
+}
+
+/// Splits a script's trailing arguments into its keys and the rest, or returns
+/// the error reply for a bad `numkeys`.
+pub(crate) fn split_keys(
+    numkeys: &str,
+    mut keys_and_args: Vec<Bytes>,
+) -> Result<(Vec<Bytes>, Vec<Bytes>), Frame> {
+    let numkeys = match numkeys.parse::<i64>() {
+        Ok(numkeys) if numkeys < 0 => {
+            return Err(Frame::Error(
+                "ERR Number of keys can't be negative".to_string(),
+            ))
+        }
+        Ok(numkeys) if numkeys as usize > keys_and_args.len() => {
+            return Err(Frame::Error(
+                "ERR Number of keys can't be greater than number of args".to_string(),
+            ))
+        }
+        Ok(numkeys) => numkeys as usize,
+        Err(_) => return Err(Error::NotAnInteger.into()),
+    };
+    let args = keys_and_args.split_off(numkeys);
+    Ok((keys_and_args, args))
+}
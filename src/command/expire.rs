@@ -0,0 +1,71 @@
+use bytes::Bytes;
+
+use crate::{
+    error::Error,
+    frame::Frame,
+    parse::Parse,
+    publisher::{publish, Action},
+    store::{notify::EventClass, Store},
+};
+
+/// PEXPIREAT key unix-time-milliseconds: makes a key expire at the given
+/// time, which may have passed already.
+#[derive(Debug)]
+pub struct PExpireAt {
+    key: Bytes,
+    expires_at: String,
+}
+
+impl PExpireAt {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<PExpireAt> {
+        Ok(PExpireAt {
+            key: parse.next_bytes()?,
+            expires_at: parse.next_string()?,
+        })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let Ok(expires_at) = self.expires_at.parse::<i64>() else {
+            return Ok(Error::NotAnInteger.into());
+        };
+        let expires_at = expires_at.max(0) as u64;
+        if !store.set_expiry(&self.key, Some(expires_at)) {
+            return Ok(Frame::Integer(0));
+        }
+        store.notify(EventClass::Generic, "expire", &self.key);
+        publish(
+            store,
+            Action::PExpireAt {
+                key: self.key,
+                expires_at,
+            },
+        )
+        .await?;
+        Ok(Frame::Integer(1))
+    }
+}
+
+/// PERSIST key: makes a key never expire. Replies 0 if there's no such key
+/// or it had no expiry.
+#[derive(Debug)]
+pub struct Persist {
+    key: Bytes,
+}
+
+impl Persist {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Persist> {
+        Ok(Persist {
+            key: parse.next_bytes()?,
+        })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        if !matches!(store.expiry(&self.key), Some(Some(_))) {
+            return Ok(Frame::Integer(0));
+        }
+        store.set_expiry(&self.key, None);
+        store.notify(EventClass::Generic, "persist", &self.key);
+        publish(store, Action::Persist { key: self.key }).await?;
+        Ok(Frame::Integer(1))
+    }
+}
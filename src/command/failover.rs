@@ -0,0 +1,171 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::bail;
+use tokio::time::{timeout_at, Instant};
+
+use crate::{
+    error::Error,
+    frame::Frame,
+    parse::Parse,
+    replicator,
+    store::{clients::PAUSE_UNTIL_UNPAUSED, role::FailoverState, Store},
+};
+
+#[derive(Debug)]
+enum FailoverOp {
+    Start {
+        to: Option<(String, String)>,
+        timeout: Option<String>,
+    },
+    Abort,
+}
+
+/// FAILOVER [TO host port] [TIMEOUT ms] / FAILOVER ABORT: hands the master
+/// role over to a replica. Writes are paused until the replica has all of
+/// them, then it is asked to take over and this server follows it instead.
+/// Replies straight away; INFO shows how far the failover has got.
+#[derive(Debug)]
+pub struct Failover {
+    op: FailoverOp,
+}
+
+impl Failover {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Failover> {
+        let mut options = parse.remaining_strings()?.into_iter();
+        let (mut to, mut timeout, mut abort) = (None, None, false);
+        while let Some(option) = options.next() {
+            match option.to_uppercase().as_str() {
+                "TO" if to.is_none() => match (options.next(), options.next()) {
+                    (Some(host), Some(port)) => to = Some((host, port)),
+                    _ => bail!("ERR syntax error"),
+                },
+                "TIMEOUT" if timeout.is_none() => match options.next() {
+                    Some(ms) => timeout = Some(ms),
+                    None => bail!("ERR syntax error"),
+                },
+                "ABORT" if !abort => abort = true,
+                _ => bail!("ERR syntax error"),
+            }
+        }
+        let op = match abort {
+            true if to.is_some() || timeout.is_some() => {
+                bail!("ERR FAILOVER ABORT can't be combined with other options")
+            }
+            true => FailoverOp::Abort,
+            false => FailoverOp::Start { to, timeout },
+        };
+        Ok(Failover { op })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let (to, timeout) = match self.op {
+            FailoverOp::Abort => return Ok(abort(store)),
+            FailoverOp::Start { to, timeout } => (to, timeout),
+        };
+        let to = match to {
+            Some((host, port)) => match port.parse::<u16>() {
+                Ok(port) => Some((host, port)),
+                Err(_) => return Ok(Error::NotAnInteger.into()),
+            },
+            None => None,
+        };
+        let timeout = match timeout.map(|ms| ms.parse::<i64>()) {
+            Some(Ok(ms)) if ms <= 0 => {
+                return Ok(Frame::Error(
+                    "ERR FAILOVER timeout must be greater than 0".to_string(),
+                ))
+            }
+            Some(Ok(ms)) => Some(Duration::from_millis(ms as u64)),
+            Some(Err(_)) => return Ok(Error::NotAnInteger.into()),
+            None => None,
+        };
+
+        if store.is_replica() {
+            return Ok(Frame::Error(
+                "ERR FAILOVER is not valid when server is a replica.".to_string(),
+            ));
+        }
+        let replicas = store.replicas().statuses();
+        if replicas.is_empty() {
+            return Ok(Frame::Error(
+                "ERR FAILOVER requires connected replicas.".to_string(),
+            ));
+        }
+        // without a target, the replica furthest along is the least behind
+        let target = match to {
+            Some((host, port)) => replicas.iter().find(|replica| {
+                replica.address.ip().to_string() == host && replica.address.port() == port
+            }),
+            None => replicas.iter().max_by_key(|replica| replica.offset),
+        };
+        let Some(target) = target.map(|replica| replica.address) else {
+            return Ok(Frame::Error(
+                "ERR FAILOVER target HOST and PORT is not a replica.".to_string(),
+            ));
+        };
+        if !store.role().begin_failover() {
+            return Ok(Frame::Error(
+                "ERR FAILOVER already in progress.".to_string(),
+            ));
+        }
+
+        log_notice!("FAILOVER requested to {}.", target);
+        store
+            .clients()
+            .pause(timeout.unwrap_or(PAUSE_UNTIL_UNPAUSED), true);
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let server = store.clone();
+        let task = tokio::spawn(async move {
+            if let Err(err) = fail_over(&server, target, deadline).await {
+                log_warning!("FAILOVER to {} failed: {:#}", target, err);
+                if server.role().end_failover() == FailoverState::InProgress {
+                    replicator::unfollow(&server);
+                }
+            }
+            server.clients().unpause();
+        });
+        store.role().set_failover_task(task.abort_handle());
+        Ok(Frame::OK)
+    }
+}
+
+/// Waits for `target` to catch up, then hands the master role over to it.
+async fn fail_over(
+    store: &Store,
+    target: SocketAddr,
+    deadline: Option<Instant>,
+) -> anyhow::Result<()> {
+    if !store.replicas().wait_for_replica(target, deadline).await? {
+        bail!("the replica didn't catch up in time");
+    }
+    store.role().set_failover_state(FailoverState::InProgress);
+    let agreed = replicator::hand_over(store, target.ip().to_string(), target.port())?;
+    let agreed = match deadline {
+        Some(deadline) => match timeout_at(deadline, agreed).await {
+            Ok(agreed) => agreed,
+            Err(_) => bail!("the replica didn't take over in time"),
+        },
+        None => agreed.await,
+    };
+    agreed.unwrap_or_else(|_| bail!("the link to the replica closed"))?;
+    store.role().end_failover();
+    log_notice!("Failover to {} succeeded.", target);
+    Ok(())
+}
+
+/// Stops a failover under way, going back to being a master if the replica
+/// was already being asked to take over.
+fn abort(store: &Store) -> Frame {
+    match store.role().abort_failover() {
+        FailoverState::None => Frame::Error("ERR No failover in progress.".to_string()),
+        state => {
+            if state == FailoverState::InProgress {
+                replicator::unfollow(store);
+            }
+            store.clients().unpause();
+            log_notice!("FAILOVER aborted.");
+            Frame::OK
+        }
+    }
+}
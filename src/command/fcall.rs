@@ -0,0 +1,48 @@
+use bytes::Bytes;
+
+use crate::{
+    command::eval::split_keys,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    scripting,
+    store::Store,
+};
+
+/// FCALL function numkeys [key ...] [arg ...]
+#[derive(Debug)]
+pub struct FCall {
+    function: String,
+    numkeys: String,
+    keys_and_args: Vec<Bytes>,
+}
+
+impl FCall {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<FCall> {
+        let function = parse.next_string()?;
+        let numkeys = parse.next_string()?;
+        let mut keys_and_args = vec![];
+        loop {
+            match parse.next_bytes() {
+                Ok(arg) => keys_and_args.push(arg),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(FCall {
+            function,
+            numkeys,
+            keys_and_args,
+        })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let (keys, args) = match split_keys(&self.numkeys, self.keys_and_args) {
+            Ok(split) => split,
+            Err(reply) => return Ok(reply),
+        };
+        let Some(library) = store.functions().find(&self.function) else {
+            return Ok(Frame::Error("ERR Function not found".to_string()));
+        };
+        scripting::call_function(store, library, self.function, keys, args).await
+    }
+}
@@ -0,0 +1,180 @@
+use anyhow::bail;
+use bytes::Bytes;
+
+use crate::{
+    frame::Frame,
+    parse::{Parse, ParseError},
+    publisher::{publish, Action},
+    scripting,
+    store::{functions::Library, Store},
+};
+
+#[derive(Debug)]
+enum FunctionOp {
+    Load { replace: bool, code: Bytes },
+    Delete(String),
+    List { with_code: bool },
+    Dump,
+    Flush,
+    Kill,
+}
+
+/// FUNCTION LOAD / DELETE / LIST / DUMP / FLUSH, managing the libraries FCALL
+/// runs from, and FUNCTION KILL, stopping the function running now.
+#[derive(Debug)]
+pub struct Function {
+    op: FunctionOp,
+}
+
+impl Function {
+    /// Whether the subcommand changes the loaded libraries.
+    pub(crate) fn is_write(&self) -> bool {
+        matches!(
+            self.op,
+            FunctionOp::Load { .. } | FunctionOp::Delete(_) | FunctionOp::Flush
+        )
+    }
+
+    /// FUNCTION KILL runs while the function it stops holds the transaction
+    /// lock, so it mustn't wait for it.
+    pub(crate) fn is_kill(&self) -> bool {
+        matches!(self.op, FunctionOp::Kill)
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Function> {
+        let subcommand = parse.next_string()?;
+        let op = match subcommand.to_uppercase().as_str() {
+            "LOAD" => {
+                let mut code = parse.next_bytes()?;
+                let replace = code.eq_ignore_ascii_case(b"REPLACE");
+                if replace {
+                    code = parse.next_bytes()?;
+                }
+                FunctionOp::Load { replace, code }
+            }
+            "DELETE" => FunctionOp::Delete(parse.next_string()?),
+            "LIST" => {
+                let mut with_code = false;
+                loop {
+                    match parse.next_string() {
+                        Ok(option) if option.eq_ignore_ascii_case("WITHCODE") => with_code = true,
+                        Ok(option) => bail!("ERR Unknown argument {}", option),
+                        Err(ParseError::EndOfStream) => break,
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                FunctionOp::List { with_code }
+            }
+            "DUMP" => FunctionOp::Dump,
+            "FLUSH" => {
+                // libraries are dropped straight away either way
+                match parse.next_string() {
+                    Ok(mode) if ["ASYNC", "SYNC"].contains(&mode.to_uppercase().as_str()) => {}
+                    Err(ParseError::EndOfStream) => {}
+                    Ok(_) => bail!("ERR FUNCTION FLUSH only supports SYNC|ASYNC option"),
+                    Err(e) => return Err(e.into()),
+                }
+                FunctionOp::Flush
+            }
+            "KILL" => FunctionOp::Kill,
+            _ => bail!(
+                "ERR unknown subcommand '{}'. Try FUNCTION HELP.",
+                subcommand
+            ),
+        };
+        Ok(Function { op })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let functions = store.functions();
+        let response = match self.op {
+            FunctionOp::Load { replace, code } => {
+                let loaded = scripting::load_library(code.clone())
+                    .await
+                    .and_then(|library| {
+                        let name = library.name.clone();
+                        functions.load(library, replace).map(|()| name)
+                    });
+                match loaded {
+                    Ok(name) => {
+                        let mut args = vec![code];
+                        if replace {
+                            args.insert(0, "REPLACE".into());
+                        }
+                        let action = Action::Function {
+                            subcommand: "LOAD",
+                            args,
+                        };
+                        publish(store, action).await?;
+                        Frame::Bulk(name.into())
+                    }
+                    Err(message) => Frame::Error(message),
+                }
+            }
+            FunctionOp::Delete(name) => match functions.delete(&name) {
+                true => {
+                    let action = Action::Function {
+                        subcommand: "DELETE",
+                        args: vec![name.into()],
+                    };
+                    publish(store, action).await?;
+                    Frame::OK
+                }
+                false => Frame::Error("ERR Library not found".to_string()),
+            },
+            FunctionOp::List { with_code } => Frame::Array(
+                functions
+                    .list()
+                    .into_iter()
+                    .map(|library| library_frame(library, with_code))
+                    .collect(),
+            ),
+            FunctionOp::Dump => Frame::Bulk(functions.dump()),
+            FunctionOp::Flush => {
+                functions.flush();
+                let action = Action::Function {
+                    subcommand: "FLUSH",
+                    args: vec![],
+                };
+                publish(store, action).await?;
+                Frame::OK
+            }
+            FunctionOp::Kill => match store.scripts().kill(false) {
+                Ok(()) => Frame::OK,
+                Err(err) => Frame::Error(err.to_string()),
+            },
+        };
+        Ok(response)
+    }
+}
+
+/// One FUNCTION LIST entry, as the flat field/value array RESP2 uses for maps.
+fn library_frame(library: Library, with_code: bool) -> Frame {
+    let functions = library
+        .functions
+        .into_iter()
+        .map(|name| {
+            Frame::Array(vec![
+                Frame::Bulk("name".into()),
+                Frame::Bulk(name.into()),
+                Frame::Bulk("description".into()),
+                Frame::Null,
+                Frame::Bulk("flags".into()),
+                Frame::array(),
+            ])
+        })
+        .collect();
+    let mut fields = vec![
+        Frame::Bulk("library_name".into()),
+        Frame::Bulk(library.name.into()),
+        Frame::Bulk("engine".into()),
+        Frame::Bulk("LUA".into()),
+        Frame::Bulk("functions".into()),
+        Frame::Array(functions),
+    ];
+    if with_code {
+        fields.push(Frame::Bulk("library_code".into()));
+        fields.push(Frame::Bulk(library.code));
+    }
+    Frame::Array(fields)
+}
@@ -0,0 +1,426 @@
+use anyhow::bail;
+use bytes::Bytes;
+
+use crate::{
+    error::Error,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    publisher::{publish, Action},
+    store::{
+        geo::{self, Point, Shape, ERR_UNIT},
+        notify::EventClass,
+        sorted_set::{parse_score, ERR_NOT_A_FLOAT},
+        Store,
+    },
+};
+
+fn float(s: &str) -> Result<f64, String> {
+    parse_score(s).ok_or_else(|| ERR_NOT_A_FLOAT.to_string())
+}
+
+fn unit(s: &str) -> Result<f64, String> {
+    geo::unit(s).ok_or_else(|| ERR_UNIT.to_string())
+}
+
+fn point(longitude: &str, latitude: &str) -> Result<Point, String> {
+    let point = (float(longitude)?, float(latitude)?);
+    if !geo::is_valid(point) {
+        return Err(format!(
+            "ERR invalid longitude,latitude pair {:.6},{:.6}",
+            point.0, point.1
+        ));
+    }
+    Ok(point)
+}
+
+fn coordinates(point: Point) -> Frame {
+    Frame::Array(vec![
+        Frame::Bulk(geo::format_coordinate(point.0).into()),
+        Frame::Bulk(geo::format_coordinate(point.1).into()),
+    ])
+}
+
+fn distance(metres: f64, unit: f64) -> Frame {
+    Frame::Bulk(format!("{:.4}", metres / unit).into())
+}
+
+/// GEOADD key [NX | XX] [CH] longitude latitude member [...]
+#[derive(Debug, Default)]
+pub struct GeoAdd {
+    key: Bytes,
+    nx: bool,
+    xx: bool,
+    ch: bool,
+    members: Vec<(String, String, Bytes)>,
+}
+
+impl GeoAdd {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<GeoAdd> {
+        let mut add = GeoAdd {
+            key: parse.next_bytes()?,
+            ..Default::default()
+        };
+        let mut longitude = loop {
+            let option = parse.next_string()?;
+            match option.to_uppercase().as_str() {
+                "NX" => add.nx = true,
+                "XX" => add.xx = true,
+                "CH" => add.ch = true,
+                _ => break option,
+            }
+        };
+        loop {
+            let (latitude, member) = match (parse.next_string(), parse.next_bytes()) {
+                (Ok(latitude), Ok(member)) => (latitude, member),
+                (Err(ParseError::EndOfStream), _) | (_, Err(ParseError::EndOfStream)) => {
+                    bail!(Error::Syntax)
+                }
+                (Err(e), _) | (_, Err(e)) => return Err(e.into()),
+            };
+            add.members.push((longitude, latitude, member));
+            longitude = match parse.next_string() {
+                Ok(longitude) => longitude,
+                Err(ParseError::EndOfStream) => return Ok(add),
+                Err(e) => return Err(e.into()),
+            };
+        }
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        if self.nx && self.xx {
+            return Ok(Frame::Error(
+                "ERR XX and NX options at the same time are not compatible".to_string(),
+            ));
+        }
+        let members = match self
+            .members
+            .into_iter()
+            .map(|(longitude, latitude, member)| Ok((point(&longitude, &latitude)?, member)))
+            .collect::<Result<Vec<_>, String>>()
+        {
+            Ok(members) => members,
+            Err(e) => return Ok(Frame::Error(e)),
+        };
+
+        let written = store.with_sorted_set_mut(self.key.clone(), |zset| {
+            let (mut added, mut written) = (0, vec![]);
+            for (point, member) in members {
+                let score = geo::encode(point) as f64;
+                match zset.score(&member) {
+                    Some(_) if self.nx => continue,
+                    Some(old) if old == score => continue,
+                    None if self.xx => continue,
+                    None => added += 1,
+                    Some(_) => {}
+                }
+                zset.insert(member.clone(), score);
+                written.push((score, member));
+            }
+            (added, written)
+        });
+        let (added, members) = match written {
+            Ok(written) => written,
+            Err(e) => return Ok(Frame::Error(e.to_string())),
+        };
+        let reply = if self.ch { members.len() } else { added };
+        if !members.is_empty() {
+            store.notify(EventClass::SortedSet, "zadd", &self.key);
+            publish(
+                store,
+                Action::ZAdd {
+                    key: self.key,
+                    members,
+                },
+            )
+            .await?;
+        }
+        Ok(Frame::Integer(reply as u64))
+    }
+}
+
+/// GEOPOS key [member ...]
+#[derive(Debug)]
+pub struct GeoPos {
+    key: Bytes,
+    members: Vec<Bytes>,
+}
+
+impl GeoPos {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<GeoPos> {
+        let key = parse.next_bytes()?;
+        let mut members = vec![];
+        loop {
+            match parse.next_bytes() {
+                Ok(member) => members.push(member),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(GeoPos { key, members })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let positions = store.with_sorted_set(&self.key, |zset| {
+            self.members
+                .iter()
+                .map(|member| match zset.score(member) {
+                    Some(score) => coordinates(geo::decode(score as u64)),
+                    None => Frame::NullArray,
+                })
+                .collect()
+        });
+        let response = match positions {
+            Ok(positions) => Frame::Array(positions),
+            Err(e) => Frame::Error(e.to_string()),
+        };
+        Ok(response)
+    }
+}
+
+/// GEODIST key member1 member2 [M | KM | FT | MI]
+#[derive(Debug)]
+pub struct GeoDist {
+    key: Bytes,
+    from: Bytes,
+    to: Bytes,
+    unit: Option<String>,
+}
+
+impl GeoDist {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<GeoDist> {
+        let key = parse.next_bytes()?;
+        let from = parse.next_bytes()?;
+        let to = parse.next_bytes()?;
+        let unit = match parse.next_string() {
+            Ok(unit) => Some(unit),
+            Err(ParseError::EndOfStream) => None,
+            Err(e) => return Err(e.into()),
+        };
+        if parse.finish().is_err() {
+            bail!(Error::Syntax);
+        }
+        Ok(GeoDist {
+            key,
+            from,
+            to,
+            unit,
+        })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let unit = match self.unit.as_deref().map(unit).unwrap_or(Ok(1.0)) {
+            Ok(unit) => unit,
+            Err(e) => return Ok(Frame::Error(e)),
+        };
+        let between = store.with_sorted_set(&self.key, |zset| {
+            let from = geo::decode(zset.score(&self.from)? as u64);
+            let to = geo::decode(zset.score(&self.to)? as u64);
+            Some(geo::distance(from, to))
+        });
+        let response = match between {
+            Ok(Some(metres)) => distance(metres, unit),
+            Ok(None) => Frame::Null,
+            Err(e) => Frame::Error(e.to_string()),
+        };
+        Ok(response)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Order {
+    Asc,
+    Desc,
+}
+
+/// GEOSEARCH key FROMMEMBER member | FROMLONLAT longitude latitude
+/// BYRADIUS radius unit | BYBOX width height unit [ASC | DESC]
+/// [COUNT count [ANY]] [WITHCOORD] [WITHDIST] [WITHHASH]
+///
+/// The arguments are kept as given and checked by `resolve`.
+#[derive(Debug, Default)]
+pub struct GeoSearch {
+    key: Bytes,
+    from_member: Option<Bytes>,
+    from_lonlat: Option<(String, String)>,
+    by_radius: Option<(String, String)>,
+    by_box: Option<(String, String, String)>,
+    order: Option<Order>,
+    count: Option<String>,
+    any: bool,
+    with_coord: bool,
+    with_dist: bool,
+    with_hash: bool,
+}
+
+#[derive(Debug)]
+enum Centre<'a> {
+    Member(&'a Bytes),
+    Point(Point),
+}
+
+/// A `GeoSearch` whose arguments have been checked.
+#[derive(Debug)]
+struct Resolved<'a> {
+    centre: Centre<'a>,
+    /// In metres.
+    shape: Shape,
+    /// Metres per unit of the shape, which distances are replied in.
+    unit: f64,
+    count: Option<usize>,
+}
+
+impl GeoSearch {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<GeoSearch> {
+        let mut search = GeoSearch {
+            key: parse.next_bytes()?,
+            ..Default::default()
+        };
+        loop {
+            let option = match parse.next_string() {
+                Ok(option) => option.to_uppercase(),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            };
+            match option.as_str() {
+                "FROMMEMBER" => search.from_member = Some(parse.next_bytes()?),
+                "FROMLONLAT" => {
+                    search.from_lonlat = Some((parse.next_string()?, parse.next_string()?))
+                }
+                "BYRADIUS" => search.by_radius = Some((parse.next_string()?, parse.next_string()?)),
+                "BYBOX" => {
+                    search.by_box = Some((
+                        parse.next_string()?,
+                        parse.next_string()?,
+                        parse.next_string()?,
+                    ))
+                }
+                "ASC" => search.order = Some(Order::Asc),
+                "DESC" => search.order = Some(Order::Desc),
+                "COUNT" => search.count = Some(parse.next_string()?),
+                "ANY" => search.any = true,
+                "WITHCOORD" => search.with_coord = true,
+                "WITHDIST" => search.with_dist = true,
+                "WITHHASH" => search.with_hash = true,
+                _ => bail!(Error::Syntax),
+            }
+        }
+        Ok(search)
+    }
+
+    fn resolve(&self) -> Result<Resolved<'_>, String> {
+        let centre =
+            match (&self.from_member, &self.from_lonlat) {
+                (Some(member), None) => Centre::Member(member),
+                (None, Some((longitude, latitude))) => Centre::Point(point(longitude, latitude)?),
+                _ => return Err(
+                    "ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH"
+                        .to_string(),
+                ),
+            };
+        let (shape, unit) = match (&self.by_radius, &self.by_box) {
+            (Some((radius, radius_unit)), None) => {
+                let radius = float(radius)?;
+                if radius < 0.0 {
+                    return Err("ERR radius cannot be negative".to_string());
+                }
+                let unit = unit(radius_unit)?;
+                (Shape::Radius(radius * unit), unit)
+            }
+            (None, Some((width, height, box_unit))) => {
+                let (width, height) = (float(width)?, float(height)?);
+                if width < 0.0 || height < 0.0 {
+                    return Err("ERR height or width cannot be negative".to_string());
+                }
+                let unit = unit(box_unit)?;
+                let shape = Shape::Box {
+                    width: width * unit,
+                    height: height * unit,
+                };
+                (shape, unit)
+            }
+            _ => {
+                return Err(
+                    "ERR exactly one of BYRADIUS and BYBOX can be specified for GEOSEARCH"
+                        .to_string(),
+                )
+            }
+        };
+        if self.any && self.count.is_none() {
+            return Err("ERR the ANY argument requires COUNT argument".to_string());
+        }
+        let count = match &self.count {
+            Some(count) => match count.parse::<i64>() {
+                Ok(count) if count > 0 => Some(count as usize),
+                Ok(_) => return Err("ERR COUNT must be > 0".to_string()),
+                Err(_) => return Err(Error::NotAnInteger.to_string()),
+            },
+            None => None,
+        };
+        Ok(Resolved {
+            centre,
+            shape,
+            unit,
+            count,
+        })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let resolved = match self.resolve() {
+            Ok(resolved) => resolved,
+            Err(e) => return Ok(Frame::Error(e)),
+        };
+        let any = resolved.count.filter(|_| self.any);
+        let found = store.with_sorted_set(&self.key, |zset| {
+            let centre = match resolved.centre {
+                Centre::Point(point) => point,
+                Centre::Member(member) => geo::decode(zset.score(member)? as u64),
+            };
+            Some(geo::search(zset, centre, resolved.shape, any))
+        });
+        let mut found = match found {
+            Ok(Some(found)) => found,
+            Ok(None) => {
+                return Ok(Frame::Error(
+                    "ERR could not decode requested zset member".to_string(),
+                ))
+            }
+            Err(e) => return Ok(Frame::Error(e.to_string())),
+        };
+
+        // a COUNT without ANY wants the nearest
+        let order = match self.order {
+            None if resolved.count.is_some() && !self.any => Some(Order::Asc),
+            order => order,
+        };
+        match order {
+            Some(Order::Asc) => found.sort_by(|a, b| a.distance.total_cmp(&b.distance)),
+            Some(Order::Desc) => found.sort_by(|a, b| b.distance.total_cmp(&a.distance)),
+            None => {}
+        }
+        if let Some(count) = resolved.count {
+            found.truncate(count);
+        }
+
+        let plain = !(self.with_coord || self.with_dist || self.with_hash);
+        let replies = found
+            .into_iter()
+            .map(|found| {
+                if plain {
+                    return Frame::Bulk(found.member);
+                }
+                let mut reply = vec![Frame::Bulk(found.member)];
+                if self.with_dist {
+                    reply.push(distance(found.distance, resolved.unit));
+                }
+                if self.with_hash {
+                    reply.push(Frame::Integer(found.hash));
+                }
+                if self.with_coord {
+                    reply.push(coordinates(found.point));
+                }
+                Frame::Array(reply)
+            })
+            .collect();
+        Ok(Frame::Array(replies))
+    }
+}
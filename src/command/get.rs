@@ -1,6 +1,6 @@
 use bytes::Bytes;
 
-use crate::{comms::Comms, frame::Frame, parse::Parse, store::Store};
+use crate::{frame::Frame, parse::Parse, store::Store};
 
 #[derive(Debug, Default)]
 pub struct Get {
@@ -17,17 +17,12 @@ impl Get {
         Ok(Get::new(msg.into()))
     }
 
-    pub(crate) async fn apply<C: Comms>(self, comms: &mut C, store: &Store) -> anyhow::Result<()> {
-        let value = store.get(self.key);
-        match value {
-            Some(value) => {
-                let response = Frame::Bulk(value);
-                comms.write_frame(&response).await.map_err(|e| e.into())
-            }
-            None => {
-                let response = Frame::Null;
-                comms.write_frame(&response).await.map_err(|e| e.into())
-            }
-        }
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let response = match store.get_string(&self.key) {
+            Ok(Some(value)) => Frame::Bulk(value),
+            Ok(None) => Frame::Null,
+            Err(e) => Frame::Error(e.to_string()),
+        };
+        Ok(response)
     }
 }
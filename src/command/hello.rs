@@ -0,0 +1,107 @@
+use anyhow::bail;
+use bytes::Bytes;
+
+use crate::{
+    command::{
+        client::{is_valid_name, ERR_INVALID_NAME},
+        info::REDIS_VERSION,
+    },
+    frame::Frame,
+    parse::Parse,
+    store::Store,
+};
+
+/// HELLO [protover [AUTH username password] [SETNAME clientname]]: switches
+/// the connection to RESP2 or RESP3, authenticating and naming it first if
+/// asked, and replies with what the server is.
+#[derive(Debug)]
+pub struct Hello {
+    protover: Option<String>,
+    auth: Option<(String, String)>,
+    setname: Option<String>,
+}
+
+impl Hello {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Hello> {
+        let mut args = parse.remaining_strings()?.into_iter();
+        let mut hello = Hello {
+            protover: args.next(),
+            auth: None,
+            setname: None,
+        };
+        while let Some(option) = args.next() {
+            match (option.to_uppercase().as_str(), args.len()) {
+                ("AUTH", 2..) => {
+                    hello.auth = args.next().zip(args.next());
+                }
+                ("SETNAME", 1..) => hello.setname = args.next(),
+                _ => bail!("ERR Syntax error in HELLO option '{}'", option),
+            }
+        }
+        Ok(hello)
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let resp3 = match self.protover.as_deref().map(str::parse::<i64>) {
+            None => store.resp3(),
+            Some(Ok(2)) => false,
+            Some(Ok(3)) => true,
+            Some(Ok(_)) => {
+                return Ok(Frame::Error(
+                    "NOPROTO unsupported protocol version".to_string(),
+                ))
+            }
+            Some(Err(_)) => {
+                return Ok(Frame::Error(
+                    "ERR Protocol version is not an integer or out of range".to_string(),
+                ))
+            }
+        };
+        if let Some(name) = &self.setname {
+            if !is_valid_name(name) {
+                return Ok(Frame::Error(ERR_INVALID_NAME.to_string()));
+            }
+        }
+        match self.auth {
+            Some((username, password)) => {
+                if !store.acl().authenticate(&username, &password) {
+                    return Ok(Frame::Error(
+                        "WRONGPASS invalid username-password pair or user is disabled.".to_string(),
+                    ));
+                }
+                store.set_user(Some(username));
+            }
+            None if store.user().is_none() => {
+                return Ok(Frame::Error("NOAUTH HELLO must be called with the client already authenticated, otherwise the HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and select the RESP protocol version at the same time".to_string()));
+            }
+            None => {}
+        }
+        let id = store.client_id().unwrap_or_default();
+        if let Some(name) = self.setname {
+            store.clients().update(id, |client| client.name = name);
+        }
+        store.set_resp3(resp3);
+
+        let mode = if store.cluster().is_enabled() {
+            "cluster"
+        } else {
+            "standalone"
+        };
+        let role = if store.is_replica() {
+            "replica"
+        } else {
+            "master"
+        };
+        let fields = [
+            ("server", Frame::Bulk(Bytes::from("redis"))),
+            ("version", Frame::Bulk(Bytes::from(REDIS_VERSION))),
+            ("proto", Frame::Integer(if resp3 { 3 } else { 2 })),
+            ("id", Frame::Integer(id)),
+            ("mode", Frame::Bulk(Bytes::from(mode))),
+            ("role", Frame::Bulk(Bytes::from(role))),
+            ("modules", Frame::Array(vec![])),
+        ]
+        .map(|(field, value)| (Frame::Bulk(Bytes::from(field)), value));
+        Ok(Frame::map(fields.into(), resp3))
+    }
+}
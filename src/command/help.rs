@@ -0,0 +1,19 @@
+use crate::{command::table, frame::Frame};
+
+/// `<container> HELP`, listing the subcommands of a container command such
+/// as CLIENT or CONFIG.
+#[derive(Debug)]
+pub struct Help {
+    reply: Frame,
+}
+
+impl Help {
+    /// The HELP of `command`, lowercase, if it is a container command.
+    pub(crate) fn of(command: &str) -> Option<Help> {
+        table::help(command).map(|reply| Help { reply })
+    }
+
+    pub(crate) async fn apply(self) -> anyhow::Result<Frame> {
+        Ok(self.reply)
+    }
+}
@@ -0,0 +1,134 @@
+use bytes::Bytes;
+
+use crate::{
+    frame::Frame,
+    parse::{Parse, ParseError},
+    publisher::{publish, Action},
+    store::{hyperloglog, notify::EventClass, Store},
+};
+
+fn remaining_keys(parse: &mut Parse) -> anyhow::Result<Vec<Bytes>> {
+    let mut keys = vec![];
+    loop {
+        match parse.next_bytes() {
+            Ok(key) => keys.push(key),
+            Err(ParseError::EndOfStream) => return Ok(keys),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// PFADD key [element ...]
+#[derive(Debug)]
+pub struct PfAdd {
+    key: Bytes,
+    elements: Vec<Bytes>,
+}
+
+impl PfAdd {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<PfAdd> {
+        let key = parse.next_bytes()?;
+        let elements = remaining_keys(parse)?;
+        Ok(PfAdd { key, elements })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let added = store.with_string_mut(self.key.clone(), |value| {
+            hyperloglog::add(value, &self.elements)
+        });
+        let changed = match added {
+            Ok(Ok(changed)) => changed,
+            Ok(Err(e)) => return Ok(Frame::Error(e.to_string())),
+            Err(e) => return Ok(Frame::Error(e.to_string())),
+        };
+        if changed {
+            store.notify(EventClass::String, "pfadd", &self.key);
+            publish(
+                store,
+                Action::PfAdd {
+                    key: self.key,
+                    elements: self.elements,
+                },
+            )
+            .await?;
+        }
+        Ok(Frame::Integer(changed as u64))
+    }
+}
+
+/// PFCOUNT key [key ...]
+#[derive(Debug)]
+pub struct PfCount {
+    keys: Vec<Bytes>,
+}
+
+impl PfCount {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<PfCount> {
+        let mut keys = vec![parse.next_bytes()?];
+        keys.extend(remaining_keys(parse)?);
+        Ok(PfCount { keys })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let counted = store.with_strings(&self.keys, |values| {
+            // the estimate a single HLL was last counted at still holds
+            // unless it changed since
+            if let [value] = values {
+                if let Some(count) = hyperloglog::cached_count(value) {
+                    return Ok(count);
+                }
+            }
+            hyperloglog::merge(values).map(|union| hyperloglog::count(&union))
+        });
+        let response = match counted {
+            Ok(Ok(count)) => Frame::Integer(count),
+            Ok(Err(e)) => Frame::Error(e.to_string()),
+            Err(e) => Frame::Error(e.to_string()),
+        };
+        Ok(response)
+    }
+}
+
+/// PFMERGE destkey [sourcekey ...]
+#[derive(Debug)]
+pub struct PfMerge {
+    destination: Bytes,
+    sources: Vec<Bytes>,
+}
+
+impl PfMerge {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<PfMerge> {
+        let destination = parse.next_bytes()?;
+        let sources = remaining_keys(parse)?;
+        Ok(PfMerge {
+            destination,
+            sources,
+        })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        // the destination's own registers are part of the union
+        let inputs: Vec<Bytes> = [self.destination.clone()]
+            .into_iter()
+            .chain(self.sources.iter().cloned())
+            .collect();
+        let stored = store.store_string(self.destination.clone(), &inputs, |values| {
+            hyperloglog::merge(values).map(|union| hyperloglog::dense(&union))
+        });
+        match stored {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => return Ok(Frame::Error(e.to_string())),
+            Err(e) => return Ok(Frame::Error(e.to_string())),
+        }
+        store.notify(EventClass::String, "pfadd", &self.destination);
+        publish(
+            store,
+            Action::PfMerge {
+                destination: self.destination,
+                sources: self.sources,
+            },
+        )
+        .await?;
+        Ok(Frame::OK)
+    }
+}
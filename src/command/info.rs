@@ -1,41 +1,298 @@
 use anyhow::bail;
-use bytes::Bytes;
 
-use crate::{comms::Comms, frame::Frame, parse::Parse, store::Store};
+use crate::{
+    frame::Frame,
+    parse::{Parse, ParseError},
+    store::{role::LinkState, Store},
+};
 
+/// The sections INFO knows, in the order it lists them.
+const SECTIONS: [&str; 9] = [
+    "server",
+    "clients",
+    "memory",
+    "persistence",
+    "stats",
+    "replication",
+    "cluster",
+    "keyspace",
+    "sentinel",
+];
+
+/// The Redis release whose behaviour the server follows, for clients that
+/// check `redis_version`.
+pub(crate) const REDIS_VERSION: &str = "7.2.0";
+
+/// INFO [section ...]
 #[derive(Debug, Default)]
 pub struct Info {
-    kind: Bytes,
+    sections: Vec<String>,
 }
 
 impl Info {
-    pub fn new(kind: Bytes) -> Self {
-        Self { kind }
+    pub fn new(sections: Vec<String>) -> Self {
+        Self { sections }
     }
 
     pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Info> {
-        let kind = parse.next_string()?;
-        Ok(Info::new(kind.into()))
-    }
-
-    pub(crate) async fn apply<C: Comms>(self, comms: &mut C, store: &Store) -> anyhow::Result<()> {
-        let info = crate::info::Info::from_store(store)?;
-
-        let bulk_string = match info.replication.role.as_str() {
-            "master" => {
-                format!(
-                    "role:master\r\nmaster_replid:{}\r\nmaster_repl_offset:{}\r\n",
-                    info.replication
-                        .master_replid
-                        .as_ref()
-                        .unwrap_or(&"".to_string()),
-                    info.replication.master_repl_offset.as_ref().unwrap_or(&0)
-                )
+        let mut sections = vec![];
+        loop {
+            match parse.next_string() {
+                Ok(section) => sections.push(section.to_lowercase()),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(Info::new(sections))
+    }
+
+    /// A lone section is returned bare; several, or all of them when none or
+    /// `all`/`default`/`everything` is asked for, each get a `# Name` header.
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let everything = self.sections.is_empty()
+            || self
+                .sections
+                .iter()
+                .any(|section| ["all", "default", "everything"].contains(&section.as_str()));
+        // only a sentinel has anything to say about one
+        let sentinel = store.sentinel().is_enabled();
+        let names: Vec<_> = SECTIONS
+            .into_iter()
+            .filter(|name| *name != "sentinel" || sentinel)
+            .filter(|name| everything || self.sections.iter().any(|section| section == name))
+            .collect();
+        let response = if let [name] = names[..] {
+            section(store, name)?
+        } else {
+            let mut sections = vec![];
+            for name in names {
+                let mut title = name.to_string();
+                title[..1].make_ascii_uppercase();
+                sections.push(format!("# {}\r\n{}", title, section(store, name)?));
             }
-            "slave" => "role:slave".to_string(),
-            _ => bail!("Invalid role"),
+            sections.join("\r\n")
         };
-        let response = Frame::Bulk(bulk_string.into());
-        comms.write_frame(&response).await.map_err(|e| e.into())
+        Ok(Frame::Bulk(response.into()))
+    }
+}
+
+fn section(store: &Store, name: &str) -> anyhow::Result<String> {
+    match name {
+        "server" => Ok(server(store)),
+        "clients" => Ok(clients(store)),
+        "memory" => Ok(memory(store)),
+        "persistence" => Ok(persistence(store)),
+        "stats" => Ok(stats(store)),
+        "replication" => replication(store),
+        "cluster" => Ok(format!(
+            "cluster_enabled:{}\r\n",
+            store.cluster().is_enabled() as u8
+        )),
+        "keyspace" => Ok(keyspace(store)),
+        "sentinel" => Ok(sentinel(store)),
+        _ => unreachable!("unknown INFO section {}", name),
+    }
+}
+
+fn server(store: &Store) -> String {
+    let uptime = store.stats().uptime().as_secs();
+    format!(
+        "redis_version:{}\r\nredis_mode:{}\r\narch_bits:{}\r\nprocess_id:{}\r\ntcp_port:{}\r\nuptime_in_seconds:{}\r\nuptime_in_days:{}\r\n",
+        REDIS_VERSION,
+        if store.cluster().is_enabled() {
+            "cluster"
+        } else if store.sentinel().is_enabled() {
+            "sentinel"
+        } else {
+            "standalone"
+        },
+        usize::BITS,
+        std::process::id(),
+        store.config().port(),
+        uptime,
+        uptime / (24 * 60 * 60),
+    )
+}
+
+fn clients(store: &Store) -> String {
+    // replicas are counted under replication instead
+    format!(
+        "connected_clients:{}\r\nmaxclients:{}\r\n",
+        store.clients().connected(),
+        store.clients().max_clients()
+    )
+}
+
+fn memory(store: &Store) -> String {
+    let used = store.used_memory() as u64;
+    let maxmemory = store.config().maxmemory();
+    format!(
+        "used_memory:{}\r\nused_memory_human:{}\r\nmaxmemory:{}\r\nmaxmemory_human:{}\r\nmaxmemory_policy:{}\r\n",
+        used,
+        human_bytes(used),
+        maxmemory,
+        human_bytes(maxmemory),
+        store.config().maxmemory_policy().name(),
+    )
+}
+
+/// `bytes` the way Redis prints memory sizes, as in `1.50M`.
+fn human_bytes(bytes: u64) -> String {
+    let units = [("G", 1u64 << 30), ("M", 1 << 20), ("K", 1 << 10)];
+    match units.iter().find(|(_, size)| bytes >= *size) {
+        Some((unit, size)) => format!("{:.2}{}", bytes as f64 / *size as f64, unit),
+        None => format!("{}B", bytes),
+    }
+}
+
+fn stats(store: &Store) -> String {
+    let stats = store.stats();
+    format!(
+        "total_connections_received:{}\r\ntotal_commands_processed:{}\r\nrejected_connections:{}\r\nexpired_keys:{}\r\nevicted_keys:{}\r\nkeyspace_hits:{}\r\nkeyspace_misses:{}\r\n",
+        stats.connections_received(),
+        stats.commands_processed(),
+        stats.rejected_connections(),
+        stats.expired_keys(),
+        stats.evicted_keys(),
+        stats.keyspace_hits(),
+        stats.keyspace_misses(),
+    )
+}
+
+/// A line per database that holds any keys, ending with how many hold each
+/// type.
+fn keyspace(store: &Store) -> String {
+    let types = store.type_stats();
+    store
+        .db_stats()
+        .iter()
+        .zip(&types)
+        .enumerate()
+        .filter(|(_, (db, _))| db.keys > 0)
+        .map(|(i, (db, types))| {
+            let mut line = format!(
+                "db{}:keys={},expires={},avg_ttl={}",
+                i, db.keys, db.expires, db.avg_ttl
+            );
+            for (name, keys, _) in types.held() {
+                line.push_str(&format!(",{}={}", name, keys));
+            }
+            line + "\r\n"
+        })
+        .collect()
+}
+
+fn sentinel(store: &Store) -> String {
+    let masters = store.sentinel().master();
+    let mut lines = format!("sentinel_masters:{}\r\n", masters.iter().len());
+    for (i, master) in masters.iter().enumerate() {
+        lines.push_str(&format!(
+            "master{}:name={},status={},address={},slaves={},sentinels=1\r\n",
+            i,
+            master.name,
+            if master.down { "sdown" } else { "ok" },
+            master.address,
+            master.replicas.len()
+        ));
+    }
+    lines
+}
+
+fn persistence(store: &Store) -> String {
+    let persistence = store.persistence();
+    let aof = store.aof();
+    let secs = |duration: Option<std::time::Duration>| {
+        duration.map_or(-1, |duration| duration.as_secs() as i64)
+    };
+    let status = |ok: bool| if ok { "ok" } else { "err" };
+    let mut lines = format!(
+        "loading:{}\r\nrdb_changes_since_last_save:{}\r\nrdb_bgsave_in_progress:{}\r\nrdb_last_save_time:{}\r\nrdb_saves:{}\r\nrdb_last_bgsave_status:{}\r\nrdb_last_bgsave_time_sec:{}\r\nrdb_current_bgsave_time_sec:{}\r\n",
+        persistence.is_loading() as u8,
+        store.dirty(),
+        persistence.bgsave_running_for().is_some() as u8,
+        persistence.lastsave(),
+        persistence.saves(),
+        status(persistence.last_bgsave_ok()),
+        secs(persistence.last_bgsave_duration()),
+        secs(persistence.bgsave_running_for()),
+    );
+    lines.push_str(&format!(
+        "aof_enabled:{}\r\naof_rewrite_in_progress:{}\r\naof_last_rewrite_time_sec:{}\r\naof_current_rewrite_time_sec:{}\r\naof_last_bgrewrite_status:{}\r\naof_last_write_status:{}\r\naof_rewrites:{}\r\n",
+        store.config().appendonly() as u8,
+        aof.rewrite_running_for().is_some() as u8,
+        secs(aof.last_rewrite_duration()),
+        secs(aof.rewrite_running_for()),
+        status(aof.last_rewrite_ok()),
+        status(aof.last_write_ok()),
+        aof.rewrites(),
+    ));
+    if aof.is_open() {
+        lines.push_str(&format!(
+            "aof_current_size:{}\r\naof_base_size:{}\r\n",
+            aof.size(),
+            aof.base_size()
+        ));
+    }
+    lines
+}
+
+fn replication(store: &Store) -> anyhow::Result<String> {
+    let info = crate::info::Info::from_store(store)?;
+
+    let bulk_string = match info.replication.role.as_str() {
+        "master" => {
+            // Redis shows an id of zeros and an offset of -1 when there's no
+            // previous id
+            let (replid2, second_offset) = match store.replicas().replid2() {
+                Some((replid, offset)) => (replid, (offset + 1).to_string()),
+                None => ("0".repeat(40), "-1".to_string()),
+            };
+            format!(
+                "role:master\r\n{}master_failover_state:{}\r\nmaster_replid:{}\r\nmaster_replid2:{}\r\nmaster_repl_offset:{}\r\nsecond_repl_offset:{}\r\n",
+                connected_slaves(store),
+                store.role().failover_state(),
+                info.replication
+                    .master_replid
+                    .as_ref()
+                    .unwrap_or(&"".to_string()),
+                replid2,
+                info.replication.master_repl_offset.as_ref().unwrap_or(&0),
+                second_offset
+            )
+        }
+        "slave" => {
+            let link_state = store.role().link_state();
+            let link_status = match link_state {
+                LinkState::Connected => "up",
+                _ => "down",
+            };
+            format!(
+                "role:slave\r\nmaster_host:{}\r\nmaster_port:{}\r\nmaster_link_status:{}\r\nmaster_link_state:{}\r\n{}",
+                info.replication.replication_of_host.unwrap_or_default(),
+                info.replication.replication_of_port.unwrap_or_default(),
+                link_status,
+                link_state,
+                connected_slaves(store)
+            )
+        }
+        _ => bail!("Invalid role"),
+    };
+    Ok(bulk_string)
+}
+
+/// The `connected_slaves` count and a `slaveN` line per attached replica.
+fn connected_slaves(store: &Store) -> String {
+    let replicas = store.replicas().statuses();
+    let mut lines = format!("connected_slaves:{}\r\n", replicas.len());
+    for (i, replica) in replicas.iter().enumerate() {
+        lines.push_str(&format!(
+            "slave{}:ip={},port={},state=online,offset={},lag={}\r\n",
+            i,
+            replica.address.ip(),
+            replica.address.port(),
+            replica.offset,
+            replica.lag.as_secs()
+        ));
     }
+    lines
 }
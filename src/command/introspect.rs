@@ -0,0 +1,149 @@
+use anyhow::bail;
+use bytes::Bytes;
+
+use crate::{
+    command::table::{self, CommandSpec},
+    error::Error,
+    frame::Frame,
+    parse::Parse,
+};
+
+fn bulk(s: impl Into<String>) -> Frame {
+    Frame::Bulk(Bytes::from(s.into()))
+}
+
+#[derive(Debug)]
+enum IntrospectOp {
+    /// Every command, or only those named.
+    Info(Option<Vec<String>>),
+    Count,
+    Docs(Vec<String>),
+}
+
+/// COMMAND [COUNT | INFO [name ...] | DOCS [name ...]]
+#[derive(Debug)]
+pub struct Introspect {
+    op: IntrospectOp,
+}
+
+impl Introspect {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Introspect> {
+        let mut args = parse.remaining_strings()?;
+        if args.is_empty() {
+            return Ok(Introspect {
+                op: IntrospectOp::Info(None),
+            });
+        }
+        let subcommand = args.remove(0);
+        let op = match (subcommand.to_uppercase().as_str(), args.len()) {
+            ("COUNT", 0) => IntrospectOp::Count,
+            ("INFO", 0) => IntrospectOp::Info(None),
+            ("INFO", _) => IntrospectOp::Info(Some(args)),
+            ("DOCS", _) => IntrospectOp::Docs(args),
+            ("COUNT", _) => bail!(Error::WrongArity("command|count".to_string())),
+            _ => bail!("ERR unknown subcommand '{}'. Try COMMAND HELP.", subcommand),
+        };
+        Ok(Introspect { op })
+    }
+
+    pub(crate) async fn apply(self) -> anyhow::Result<Frame> {
+        let response = match self.op {
+            IntrospectOp::Count => Frame::Integer(table::commands().count() as u64),
+            IntrospectOp::Info(None) => Frame::Array(table::commands().map(info).collect()),
+            IntrospectOp::Info(Some(names)) => Frame::Array(
+                names
+                    .iter()
+                    .map(|name| match top_level(name) {
+                        Some(spec) => info(spec),
+                        None => Frame::Null,
+                    })
+                    .collect(),
+            ),
+            IntrospectOp::Docs(names) => {
+                let specs: Vec<_> = if names.is_empty() {
+                    table::commands().collect()
+                } else {
+                    names.iter().filter_map(|name| top_level(name)).collect()
+                };
+                Frame::Array(
+                    specs
+                        .into_iter()
+                        .flat_map(|spec| [bulk(spec.name), docs(spec)])
+                        .collect(),
+                )
+            }
+        };
+        Ok(response)
+    }
+}
+
+fn top_level(name: &str) -> Option<&'static CommandSpec> {
+    table::find(&name.to_lowercase()).filter(|spec| !spec.name.contains('|'))
+}
+
+/// A command as COMMAND INFO describes it: name, arity, flags, first key,
+/// last key, step, ACL categories, tips, key specs and subcommands.
+fn info(spec: &CommandSpec) -> Frame {
+    let mut flags: Vec<Frame> = spec
+        .flags
+        .iter()
+        .map(|flag| Frame::Simple(flag.to_string()))
+        .collect();
+    if spec.keys.is_movable() {
+        flags.push(Frame::Simple("movablekeys".to_string()));
+    }
+    let (first, last, step) = spec.keys.positions();
+    Frame::Array(vec![
+        bulk(spec.name),
+        Frame::integer(spec.arity),
+        Frame::Array(flags),
+        Frame::integer(first),
+        Frame::integer(last),
+        Frame::integer(step),
+        Frame::Array(
+            spec.categories
+                .iter()
+                .map(|category| Frame::Simple(format!("@{}", category)))
+                .collect(),
+        ),
+        Frame::Array(vec![]),
+        Frame::Array(vec![]),
+        Frame::Array(table::subcommands(spec.name).map(info).collect()),
+    ])
+}
+
+/// A command as COMMAND DOCS describes it, as field and value pairs.
+fn docs(spec: &CommandSpec) -> Frame {
+    let mut fields = vec![
+        bulk("summary"),
+        bulk(spec.summary),
+        bulk("group"),
+        bulk(group(spec)),
+    ];
+    let subcommands: Vec<Frame> = table::subcommands(spec.name)
+        .flat_map(|sub| [bulk(sub.name), docs(sub)])
+        .collect();
+    if !subcommands.is_empty() {
+        fields.push(bulk("subcommands"));
+        fields.push(Frame::Array(subcommands));
+    }
+    Frame::Array(fields)
+}
+
+/// The group the Redis documentation files the command under.
+fn group(spec: &CommandSpec) -> &'static str {
+    let groups = [
+        ("string", "string"),
+        ("sortedset", "sorted-set"),
+        ("stream", "stream"),
+        ("pubsub", "pubsub"),
+        ("transaction", "transactions"),
+        ("scripting", "scripting"),
+        ("connection", "connection"),
+        ("keyspace", "generic"),
+    ];
+    groups
+        .iter()
+        .find(|(category, _)| spec.categories.contains(category))
+        .map_or("server", |(_, group)| group)
+}
@@ -0,0 +1,134 @@
+use anyhow::bail;
+use bytes::Bytes;
+
+use crate::{
+    frame::Frame,
+    parse::Parse,
+    store::{
+        latency::{self, Series},
+        Store,
+    },
+};
+
+#[derive(Debug)]
+enum LatencyOp {
+    Latest,
+    History(String),
+    Reset(Vec<String>),
+    Doctor,
+}
+
+/// LATENCY LATEST / HISTORY event / RESET [event ...] / DOCTOR
+#[derive(Debug)]
+pub struct Latency {
+    op: LatencyOp,
+}
+
+impl Latency {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Latency> {
+        let subcommand = parse.next_string()?;
+        let op = match subcommand.to_uppercase().as_str() {
+            "LATEST" => LatencyOp::Latest,
+            "HISTORY" => LatencyOp::History(parse.next_string()?.to_lowercase()),
+            "RESET" => LatencyOp::Reset(
+                parse
+                    .remaining_strings()?
+                    .iter()
+                    .map(|event| event.to_lowercase())
+                    .collect(),
+            ),
+            "DOCTOR" => LatencyOp::Doctor,
+            _ => bail!("ERR unknown subcommand '{}'. Try LATENCY HELP.", subcommand),
+        };
+        Ok(Latency { op })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let latency = store.latency();
+        let response = match self.op {
+            LatencyOp::Latest => Frame::Array(
+                latency
+                    .events()
+                    .into_iter()
+                    .filter_map(|(event, series)| {
+                        let latest = series.latest()?;
+                        Some(Frame::Array(vec![
+                            Frame::Bulk(Bytes::from(event)),
+                            Frame::Integer(latest.time),
+                            Frame::Integer(latest.latency),
+                            Frame::Integer(series.max),
+                        ]))
+                    })
+                    .collect(),
+            ),
+            LatencyOp::History(event) => Frame::Array(
+                latency
+                    .history(&event)
+                    .into_iter()
+                    .map(|sample| {
+                        Frame::Array(vec![
+                            Frame::Integer(sample.time),
+                            Frame::Integer(sample.latency),
+                        ])
+                    })
+                    .collect(),
+            ),
+            LatencyOp::Reset(events) => Frame::Integer(latency.reset(&events) as u64),
+            LatencyOp::Doctor => {
+                Frame::Bulk(Bytes::from(doctor(latency.threshold(), &latency.events())))
+            }
+        };
+        Ok(response)
+    }
+}
+
+/// A report on the spikes recorded, in the words Redis uses.
+fn doctor(threshold: u64, events: &[(&'static str, Series)]) -> String {
+    if threshold == 0 {
+        return "I'm sorry, Dave, I can't do that. Latency monitoring is disabled in this Redis instance. You may use \"CONFIG SET latency-monitor-threshold <milliseconds>.\" in order to enable it.\n".to_string();
+    }
+    if events.is_empty() {
+        return "Dave, no latency spike was observed during the lifetime of this Redis instance, not in the slightest bit. I honestly think you ought to sleep tonight.\n".to_string();
+    }
+    let mut report = "Dave, I have observed latency spikes in this Redis instance. You don't mind talking about it, do you Dave?\n\n".to_string();
+    for (i, (event, series)) in events.iter().enumerate() {
+        let samples: Vec<u64> = series.history.iter().map(|sample| sample.latency).collect();
+        let avg = samples.iter().sum::<u64>() as f64 / samples.len().max(1) as f64;
+        let deviation = samples
+            .iter()
+            .map(|&latency| (latency as f64 - avg).abs())
+            .sum::<f64>()
+            / samples.len().max(1) as f64;
+        let period = match (series.history.front(), series.history.back()) {
+            (Some(first), Some(last)) if samples.len() > 1 => {
+                (last.time - first.time) as f64 / (samples.len() - 1) as f64
+            }
+            _ => 0.0,
+        };
+        report.push_str(&format!(
+            "{}. {}: {} latency spikes (average {}ms, mean deviation {}ms, period {:.2} sec). Worst all time event {}ms.\n",
+            i + 1,
+            event,
+            samples.len(),
+            avg.round(),
+            deviation.round(),
+            period,
+            series.max
+        ));
+    }
+    report.push_str("\nI have a few advices for you:\n\n");
+    let seen = |name: &str| events.iter().any(|(event, _)| *event == name);
+    if seen(latency::COMMAND) || seen(latency::FAST_COMMAND) {
+        report.push_str("- Commands that work on many elements, such as KEYS or a large ZRANGE, block the server while they run; prefer their incremental forms.\n");
+    }
+    if seen(latency::FAST_COMMAND) {
+        report.push_str("- Commands that should run in constant time were slow, which points at the system rather than the dataset: check for swapping and an overloaded CPU.\n");
+    }
+    if seen(latency::EXPIRE_CYCLE) {
+        report.push_str("- Expiring keys took a while. Many keys expiring at the same moment make the expire cycle long; spread their TTLs out.\n");
+    }
+    if seen(latency::FORK) {
+        report.push_str("- Copying the dataset for a background save was slow. The larger the dataset, the longer it takes; save less often or keep less data.\n");
+    }
+    report
+}
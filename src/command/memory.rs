@@ -0,0 +1,107 @@
+use anyhow::bail;
+use bytes::Bytes;
+
+use crate::{
+    error::Error,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    store::{memory::DEFAULT_SAMPLES, Store},
+};
+
+fn bulk(s: impl Into<String>) -> Frame {
+    Frame::Bulk(Bytes::from(s.into()))
+}
+
+#[derive(Debug)]
+enum MemoryOp {
+    Usage { key: Bytes, samples: String },
+    Stats,
+}
+
+/// MEMORY USAGE key [SAMPLES count] / STATS
+#[derive(Debug)]
+pub struct Memory {
+    op: MemoryOp,
+}
+
+impl Memory {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Memory> {
+        let subcommand = parse.next_string()?;
+        let op = match subcommand.to_uppercase().as_str() {
+            "USAGE" => {
+                let key = parse.next_bytes()?;
+                let samples = match parse.next_string() {
+                    Err(ParseError::EndOfStream) => DEFAULT_SAMPLES.to_string(),
+                    Ok(option) if option.eq_ignore_ascii_case("SAMPLES") => parse.next_string()?,
+                    Ok(_) => bail!(Error::Syntax),
+                    Err(e) => return Err(e.into()),
+                };
+                MemoryOp::Usage { key, samples }
+            }
+            "STATS" => MemoryOp::Stats,
+            _ => bail!("ERR unknown subcommand '{}'. Try MEMORY HELP.", subcommand),
+        };
+        Ok(Memory { op })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let response = match self.op {
+            MemoryOp::Usage { key, samples } => {
+                let Ok(samples) = samples.parse::<usize>() else {
+                    return Ok(Frame::Error(
+                        "ERR value is out of range, must be positive".to_string(),
+                    ));
+                };
+                match store.memory_usage(&key, samples) {
+                    Some(bytes) => Frame::Integer(bytes as u64),
+                    None => Frame::Null,
+                }
+            }
+            MemoryOp::Stats => {
+                let stats = store.memory_stats();
+                let types = store.type_stats();
+                let total = stats.total();
+                let mut fields = vec![bulk("total.allocated"), Frame::Integer(total as u64)];
+                for (db, main, expires) in &stats.dbs {
+                    fields.push(bulk(format!("db.{}", db)));
+                    let mut db_fields = vec![
+                        bulk("overhead.hashtable.main"),
+                        Frame::Integer(*main as u64),
+                        bulk("overhead.hashtable.expires"),
+                        Frame::Integer(*expires as u64),
+                    ];
+                    // keys and size by type: bytes, members or entries
+                    for (name, keys, size) in types[*db].held() {
+                        db_fields.extend([
+                            bulk(format!("keys.{}", name)),
+                            Frame::Integer(keys as u64),
+                            bulk(format!("size.{}", name)),
+                            Frame::Integer(size as u64),
+                        ]);
+                    }
+                    fields.push(Frame::Array(db_fields));
+                }
+                let per_key = (total / stats.keys.max(1)) as u64;
+                let percentage = if total == 0 {
+                    0.0
+                } else {
+                    stats.dataset as f64 * 100.0 / total as f64
+                };
+                fields.extend([
+                    bulk("overhead.total"),
+                    Frame::Integer(stats.overhead() as u64),
+                    bulk("keys.count"),
+                    Frame::Integer(stats.keys as u64),
+                    bulk("keys.bytes-per-key"),
+                    Frame::Integer(per_key),
+                    bulk("dataset.bytes"),
+                    Frame::Integer(stats.dataset as u64),
+                    bulk("dataset.percentage"),
+                    bulk(format!("{:.2}", percentage)),
+                ]);
+                Frame::Array(fields)
+            }
+        };
+        Ok(response)
+    }
+}
@@ -0,0 +1,259 @@
+use anyhow::bail;
+use bytes::Bytes;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+use crate::{
+    comms::Comms,
+    connection::Connection,
+    error::Error,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    publisher::{publish, Action},
+    rdb,
+    store::{notify::EventClass, Store},
+};
+
+fn unix_now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_millis() as u64)
+}
+
+/// DUMP key: the key's value serialized for RESTORE.
+#[derive(Debug)]
+pub struct Dump {
+    key: Bytes,
+}
+
+impl Dump {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Dump> {
+        Ok(Dump {
+            key: parse.next_bytes()?,
+        })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        match store.dump(&self.key) {
+            Some((payload, _)) => Ok(Frame::Bulk(payload)),
+            None => Ok(Frame::Null),
+        }
+    }
+}
+
+/// RESTORE key ttl payload [REPLACE] [ABSTTL]: creates a key from a DUMP
+/// payload, expiring after `ttl` milliseconds, or at unix time `ttl` with
+/// ABSTTL. A `ttl` of 0 never expires.
+#[derive(Debug)]
+pub struct Restore {
+    key: Bytes,
+    ttl: String,
+    payload: Bytes,
+    replace: bool,
+    absttl: bool,
+}
+
+impl Restore {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Restore> {
+        let mut restore = Restore {
+            key: parse.next_bytes()?,
+            ttl: parse.next_string()?,
+            payload: parse.next_bytes()?,
+            replace: false,
+            absttl: false,
+        };
+        loop {
+            match parse.next_string() {
+                Ok(option) => match option.to_uppercase().as_str() {
+                    "REPLACE" => restore.replace = true,
+                    "ABSTTL" => restore.absttl = true,
+                    _ => bail!(Error::Syntax),
+                },
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(restore)
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let Ok(ttl) = self.ttl.parse::<i64>() else {
+            return Ok(Error::NotAnInteger.into());
+        };
+        let Ok(ttl) = u64::try_from(ttl) else {
+            return Ok(Frame::Error(
+                "ERR Invalid TTL value, must be >= 0".to_string(),
+            ));
+        };
+        let Ok(value) = rdb::restore(&self.payload) else {
+            return Ok(Frame::Error(
+                "ERR DUMP payload version or checksum are wrong".to_string(),
+            ));
+        };
+        let expires_at = match ttl {
+            0 => None,
+            ttl if self.absttl => Some(ttl),
+            ttl => Some(unix_now_millis().saturating_add(ttl)),
+        };
+        if !store.restore(self.key.clone(), value, expires_at, self.replace) {
+            return Ok(Frame::Error(
+                "BUSYKEY Target key name already exists.".to_string(),
+            ));
+        }
+        store.notify(EventClass::Generic, "restore", &self.key);
+        publish(
+            store,
+            Action::Restore {
+                key: self.key,
+                payload: self.payload,
+                expires_at,
+            },
+        )
+        .await?;
+        Ok(Frame::OK)
+    }
+}
+
+/// MIGRATE host port key destination-db timeout [COPY] [REPLACE]: moves a
+/// key to another server by sending it a RESTORE of its DUMP, waiting up to
+/// `timeout` milliseconds for each step. The key stays here with COPY.
+#[derive(Debug)]
+pub struct Migrate {
+    host: String,
+    port: String,
+    key: Bytes,
+    db: String,
+    timeout: String,
+    copy: bool,
+    replace: bool,
+}
+
+/// Why a migration failed: the target couldn't be reached or didn't answer
+/// in time, or it refused the key.
+enum Failure {
+    Connect,
+    Read,
+    Target(String),
+}
+
+fn request(args: Vec<Bytes>) -> Frame {
+    Frame::Array(args.into_iter().map(Frame::Bulk).collect())
+}
+
+impl Migrate {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Migrate> {
+        let mut migrate = Migrate {
+            host: parse.next_string()?,
+            port: parse.next_string()?,
+            key: parse.next_bytes()?,
+            db: parse.next_string()?,
+            timeout: parse.next_string()?,
+            copy: false,
+            replace: false,
+        };
+        loop {
+            match parse.next_string() {
+                Ok(option) => match option.to_uppercase().as_str() {
+                    "COPY" => migrate.copy = true,
+                    "REPLACE" => migrate.replace = true,
+                    _ => bail!(Error::Syntax),
+                },
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(migrate)
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let (Ok(db), Ok(wait), Ok(port)) = (
+            self.db.parse::<u64>(),
+            self.timeout.parse::<i64>(),
+            self.port.parse::<u16>(),
+        ) else {
+            return Ok(Error::NotAnInteger.into());
+        };
+        // as with Redis, no timeout means a second
+        let wait = u64::try_from(wait).ok().filter(|wait| *wait > 0);
+        let wait = Duration::from_millis(wait.unwrap_or(1000));
+        let Some((payload, expires_at)) = store.dump(&self.key) else {
+            return Ok(Frame::Simple("NOKEY".to_string()));
+        };
+        let ttl = expires_at.map_or(0, |at| at.saturating_sub(unix_now_millis()).max(1));
+
+        let mut requests = vec![request(vec![
+            Bytes::from("SELECT"),
+            Bytes::from(db.to_string()),
+        ])];
+        // a target importing the key's slot only takes it when asked
+        if store.cluster().is_enabled() {
+            requests.push(request(vec![Bytes::from("ASKING")]));
+        }
+        let mut restore = vec![
+            Bytes::from("RESTORE"),
+            self.key.clone(),
+            Bytes::from(ttl.to_string()),
+            payload,
+        ];
+        if self.replace {
+            restore.push(Bytes::from("REPLACE"));
+        }
+        requests.push(request(restore));
+
+        let address = format!("{}:{}", self.host, port);
+        match send(&address, &requests, wait).await {
+            Ok(()) => {}
+            Err(Failure::Connect) => {
+                return Ok(Frame::Error(
+                    "IOERR error or timeout connecting to the client".to_string(),
+                ))
+            }
+            Err(Failure::Read) => {
+                return Ok(Frame::Error(
+                    "IOERR error or timeout reading to target instance".to_string(),
+                ))
+            }
+            Err(Failure::Target(message)) => {
+                return Ok(Frame::Error(format!(
+                    "ERR Target instance replied with error: {}",
+                    message
+                )))
+            }
+        }
+        if !self.copy && store.del(self.key.clone()) {
+            publish(store, Action::Del { key: self.key }).await?;
+        }
+        Ok(Frame::OK)
+    }
+}
+
+/// Sends `requests` to the server at `address` and checks that none of them
+/// failed.
+async fn send(address: &str, requests: &[Frame], wait: Duration) -> Result<(), Failure> {
+    let socket = timeout(wait, TcpStream::connect(address))
+        .await
+        .ok()
+        .and_then(Result::ok)
+        .ok_or(Failure::Connect)?;
+    let (reader, writer) = socket.into_split();
+    let mut target = Connection::new(reader, writer);
+    for request in requests {
+        target
+            .write_frame(request)
+            .await
+            .map_err(|_| Failure::Connect)?;
+    }
+    for _ in requests {
+        let reply = timeout(wait, target.read_frame())
+            .await
+            .map_err(|_| Failure::Read)?
+            .map_err(|_| Failure::Read)?;
+        match reply {
+            Some(Frame::Error(message)) => return Err(Failure::Target(message)),
+            Some(_) => {}
+            None => return Err(Failure::Read),
+        }
+    }
+    Ok(())
+}
@@ -1,48 +1,353 @@
-use crate::{comms::Comms, frame::Frame, parse::Parse, store::Store};
+use crate::{
+    error::Error,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    store::Store,
+};
 pub mod ping;
 use anyhow::Context;
 use ping::Ping;
 pub mod echo;
 use echo::Echo;
+pub mod hello;
+use hello::Hello;
 pub mod unknown;
 use unknown::Unknown;
 pub mod get;
 use get::Get;
 pub mod set;
 use set::Set;
+pub mod bitmap;
+use bitmap::{BitCount, BitOp, BitPos, GetBit, SetBit};
+pub mod geo;
+use geo::{GeoAdd, GeoDist, GeoPos, GeoSearch};
+pub mod hyperloglog;
+use hyperloglog::{PfAdd, PfCount, PfMerge};
 pub mod info;
 use info::Info;
 pub mod repl_conf;
 use repl_conf::ReplConf;
 pub mod psync;
 use psync::Psync;
+pub mod zincrby;
+use zincrby::ZIncrBy;
+pub mod zcard;
+use zcard::ZCard;
+pub mod zcount;
+use zcount::ZCount;
+pub mod zlexcount;
+use zlexcount::ZLexCount;
+pub mod zpop;
+use zpop::{Side, ZPop};
+pub mod bzpop;
+use bzpop::BZPop;
+pub mod del;
+use del::Del;
+pub mod expire;
+use expire::{PExpireAt, Persist};
+pub mod zadd;
+use zadd::ZAdd;
+pub mod zstore;
+use zstore::ZStore;
+pub mod zrange;
+use zrange::ZRange;
+pub mod zrangestore;
+use zrangestore::ZRangeStore;
+pub mod xadd;
+use xadd::XAdd;
+pub mod xrange;
+use xrange::XRange;
+pub mod xlen;
+use xlen::XLen;
+pub mod xdel;
+use xdel::XDel;
+pub mod xtrim;
+use xtrim::XTrim;
+pub mod xgroup;
+use xgroup::XGroup;
+pub mod xreadgroup;
+use xreadgroup::XReadGroup;
+pub mod xack;
+use xack::XAck;
+pub mod xpending;
+use xpending::XPending;
+pub mod xclaim;
+use xclaim::XClaim;
+pub mod xautoclaim;
+use xautoclaim::XAutoClaim;
+pub mod subscribe;
+use subscribe::{Subscribe, Unsubscribe};
+pub mod publish;
+use publish::Publish;
+pub mod multi;
+use multi::{Discard, Exec, Multi};
+pub mod reset;
+use reset::Reset;
+pub mod watch;
+use watch::{Unwatch, Watch};
+pub mod eval;
+use eval::Eval;
+pub mod script;
+use script::Script;
+pub mod fcall;
+use fcall::FCall;
+pub mod function;
+use function::Function;
+pub mod wait;
+use wait::Wait;
+pub mod replicaof;
+use replicaof::ReplicaOf;
+pub mod failover;
+use failover::Failover;
+pub mod sentinel;
+use sentinel::Sentinel;
+pub mod config;
+use config::Config;
+pub mod save;
+use save::{BgRewriteAof, BgSave, LastSave, Save};
+pub mod debug;
+use debug::Debug;
+pub mod select;
+use select::{Move, Select, SwapDb};
+pub mod scan;
+use scan::{Scan, ZScan};
+pub mod acl;
+use acl::{Acl, Auth};
+pub mod client;
+use client::Client;
+pub mod introspect;
+use introspect::Introspect;
+pub mod latency;
+use latency::Latency;
+pub mod memory;
+use memory::Memory;
+pub mod cluster;
+use cluster::{Asking, Cluster, ReadMode};
+pub mod migrate;
+use migrate::{Dump, Migrate, Restore};
+pub mod shutdown;
+use shutdown::Shutdown;
+pub mod help;
+use help::Help;
+pub mod table;
+
+use crate::store::sorted_set::SetOp;
 
 #[derive(Debug)]
 pub enum Command {
     Ping(Ping),
     Echo(Echo),
+    Hello(Hello),
     Unknown(Unknown),
     Get(Get),
+    SetBit(SetBit),
+    GetBit(GetBit),
+    BitCount(BitCount),
+    BitOp(BitOp),
+    BitPos(BitPos),
+    PfAdd(PfAdd),
+    PfCount(PfCount),
+    PfMerge(PfMerge),
+    GeoAdd(GeoAdd),
+    GeoPos(GeoPos),
+    GeoDist(GeoDist),
+    GeoSearch(GeoSearch),
     Set(Set),
     Info(Info),
     ReplConf(ReplConf),
     Psync(Psync),
+    ZIncrBy(ZIncrBy),
+    ZCard(ZCard),
+    ZCount(ZCount),
+    ZLexCount(ZLexCount),
+    ZPop(ZPop),
+    BZPop(BZPop),
+    Del(Del),
+    PExpireAt(PExpireAt),
+    Persist(Persist),
+    ZAdd(ZAdd),
+    ZStore(ZStore),
+    ZRange(ZRange),
+    ZRangeStore(ZRangeStore),
+    XAdd(XAdd),
+    XRange(XRange),
+    XLen(XLen),
+    XDel(XDel),
+    XTrim(XTrim),
+    XGroup(XGroup),
+    XReadGroup(XReadGroup),
+    XAck(XAck),
+    XPending(XPending),
+    XClaim(XClaim),
+    XAutoClaim(XAutoClaim),
+    Subscribe(Subscribe),
+    Unsubscribe(Unsubscribe),
+    Publish(Publish),
+    Multi(Multi),
+    Exec(Exec),
+    Discard(Discard),
+    Reset(Reset),
+    Watch(Watch),
+    Unwatch(Unwatch),
+    Eval(Eval),
+    Script(Script),
+    FCall(FCall),
+    Function(Function),
+    Wait(Wait),
+    ReplicaOf(ReplicaOf),
+    Failover(Failover),
+    Sentinel(Sentinel),
+    Config(Config),
+    Save(Save),
+    BgSave(BgSave),
+    LastSave(LastSave),
+    BgRewriteAof(BgRewriteAof),
+    Debug(Debug),
+    Select(Select),
+    SwapDb(SwapDb),
+    Move(Move),
+    Scan(Scan),
+    ZScan(ZScan),
+    Acl(Acl),
+    Auth(Auth),
+    Client(Client),
+    Introspect(Introspect),
+    Memory(Memory),
+    Latency(Latency),
+    Cluster(Cluster),
+    ReadMode(ReadMode),
+    Asking(Asking),
+    Dump(Dump),
+    Restore(Restore),
+    Migrate(Migrate),
+    Shutdown(Shutdown),
+    Help(Help),
 }
 
 impl Command {
+    /// The command `frame` asks for. Arguments that don't fit the command's
+    /// arity, or that run out while parsing, give `Error::WrongArity`.
     pub fn from_frame(frame: Frame) -> anyhow::Result<Command> {
+        let args = table::request_args(&frame);
+        let spec = table::lookup(&args);
+        let name = spec.map_or_else(
+            || String::from_utf8_lossy(args.first().copied().unwrap_or_default()).to_lowercase(),
+            |spec| spec.name.to_string(),
+        );
+        if spec.is_some_and(|spec| !spec.accepts(args.len())) {
+            return Err(Error::WrongArity(name).into());
+        }
+        if let [_, subcommand] = args[..] {
+            if subcommand.eq_ignore_ascii_case(b"HELP") {
+                if let Some(help) = Help::of(&name) {
+                    return Ok(Command::Help(help));
+                }
+            }
+        }
+        Self::parse(frame).map_err(|err| match err.downcast_ref::<ParseError>() {
+            Some(ParseError::EndOfStream) => Error::WrongArity(name).into(),
+            _ => err,
+        })
+    }
+
+    fn parse(frame: Frame) -> anyhow::Result<Command> {
         let mut parse = Parse::new(frame).context("erroring parsing frame")?;
-        let command_name = parse.next_string()?.to_lowercase();
+        let command_name = parse.next_string()?;
         let command = match command_name.to_lowercase().as_str() {
             "ping" => Command::Ping(Ping::parse_frames(&mut parse)?),
             "echo" => Command::Echo(Echo::parse_frames(&mut parse)?),
+            "hello" => Command::Hello(Hello::parse_frames(&mut parse)?),
             "get" => Command::Get(Get::parse_frames(&mut parse)?),
             "set" => Command::Set(Set::parse_frames(&mut parse)?),
+            "setbit" => Command::SetBit(SetBit::parse_frames(&mut parse)?),
+            "getbit" => Command::GetBit(GetBit::parse_frames(&mut parse)?),
+            "bitcount" => Command::BitCount(BitCount::parse_frames(&mut parse)?),
+            "bitop" => Command::BitOp(BitOp::parse_frames(&mut parse)?),
+            "bitpos" => Command::BitPos(BitPos::parse_frames(&mut parse)?),
+            "pfadd" => Command::PfAdd(PfAdd::parse_frames(&mut parse)?),
+            "pfcount" => Command::PfCount(PfCount::parse_frames(&mut parse)?),
+            "pfmerge" => Command::PfMerge(PfMerge::parse_frames(&mut parse)?),
+            "geoadd" => Command::GeoAdd(GeoAdd::parse_frames(&mut parse)?),
+            "geopos" => Command::GeoPos(GeoPos::parse_frames(&mut parse)?),
+            "geodist" => Command::GeoDist(GeoDist::parse_frames(&mut parse)?),
+            "geosearch" => Command::GeoSearch(GeoSearch::parse_frames(&mut parse)?),
             "info" => Command::Info(Info::parse_frames(&mut parse)?),
             "replconf" => Command::ReplConf(ReplConf::parse_frames(&mut parse)?),
             "psync" => Command::Psync(Psync::parse_frames(&mut parse)?),
+            "zincrby" => Command::ZIncrBy(ZIncrBy::parse_frames(&mut parse)?),
+            "zcard" => Command::ZCard(ZCard::parse_frames(&mut parse)?),
+            "zcount" => Command::ZCount(ZCount::parse_frames(&mut parse)?),
+            "zlexcount" => Command::ZLexCount(ZLexCount::parse_frames(&mut parse)?),
+            "zpopmin" => Command::ZPop(ZPop::parse_frames(Side::Min, &mut parse)?),
+            "zpopmax" => Command::ZPop(ZPop::parse_frames(Side::Max, &mut parse)?),
+            "bzpopmin" => Command::BZPop(BZPop::parse_frames(Side::Min, &mut parse)?),
+            "bzpopmax" => Command::BZPop(BZPop::parse_frames(Side::Max, &mut parse)?),
+            "del" => Command::Del(Del::parse_frames(&mut parse)?),
+            "pexpireat" => Command::PExpireAt(PExpireAt::parse_frames(&mut parse)?),
+            "persist" => Command::Persist(Persist::parse_frames(&mut parse)?),
+            "zadd" => Command::ZAdd(ZAdd::parse_frames(&mut parse)?),
+            "zunionstore" => Command::ZStore(ZStore::parse_frames(SetOp::Union, &mut parse)?),
+            "zinterstore" => Command::ZStore(ZStore::parse_frames(SetOp::Inter, &mut parse)?),
+            "zdiffstore" => Command::ZStore(ZStore::parse_frames(SetOp::Diff, &mut parse)?),
+            "zrange" => Command::ZRange(ZRange::parse_frames(&mut parse)?),
+            "zrangestore" => Command::ZRangeStore(ZRangeStore::parse_frames(&mut parse)?),
+            "xadd" => Command::XAdd(XAdd::parse_frames(&mut parse)?),
+            "xrange" => Command::XRange(XRange::parse_frames(false, &mut parse)?),
+            "xrevrange" => Command::XRange(XRange::parse_frames(true, &mut parse)?),
+            "xlen" => Command::XLen(XLen::parse_frames(&mut parse)?),
+            "xdel" => Command::XDel(XDel::parse_frames(&mut parse)?),
+            "xtrim" => Command::XTrim(XTrim::parse_frames(&mut parse)?),
+            "xgroup" => Command::XGroup(XGroup::parse_frames(&mut parse)?),
+            "xreadgroup" => Command::XReadGroup(XReadGroup::parse_frames(&mut parse)?),
+            "xack" => Command::XAck(XAck::parse_frames(&mut parse)?),
+            "xpending" => Command::XPending(XPending::parse_frames(&mut parse)?),
+            "xclaim" => Command::XClaim(XClaim::parse_frames(&mut parse)?),
+            "xautoclaim" => Command::XAutoClaim(XAutoClaim::parse_frames(&mut parse)?),
+            "subscribe" => Command::Subscribe(Subscribe::parse_frames(&mut parse)?),
+            "unsubscribe" => Command::Unsubscribe(Unsubscribe::parse_frames(&mut parse)?),
+            "publish" => Command::Publish(Publish::parse_frames(&mut parse)?),
+            "multi" => Command::Multi(Multi::parse_frames(&mut parse)?),
+            "exec" => Command::Exec(Exec::parse_frames(&mut parse)?),
+            "discard" => Command::Discard(Discard::parse_frames(&mut parse)?),
+            "reset" => Command::Reset(Reset::parse_frames(&mut parse)?),
+            "watch" => Command::Watch(Watch::parse_frames(&mut parse)?),
+            "unwatch" => Command::Unwatch(Unwatch::parse_frames(&mut parse)?),
+            "eval" => Command::Eval(Eval::parse_frames(false, &mut parse)?),
+            "evalsha" => Command::Eval(Eval::parse_frames(true, &mut parse)?),
+            "script" => Command::Script(Script::parse_frames(&mut parse)?),
+            "fcall" => Command::FCall(FCall::parse_frames(&mut parse)?),
+            "function" => Command::Function(Function::parse_frames(&mut parse)?),
+            "wait" => Command::Wait(Wait::parse_frames(&mut parse)?),
+            "replicaof" | "slaveof" => Command::ReplicaOf(ReplicaOf::parse_frames(&mut parse)?),
+            "failover" => Command::Failover(Failover::parse_frames(&mut parse)?),
+            "sentinel" => Command::Sentinel(Sentinel::parse_frames(&mut parse)?),
+            "config" => Command::Config(Config::parse_frames(&mut parse)?),
+            "save" => Command::Save(Save::parse_frames(&mut parse)?),
+            "bgsave" => Command::BgSave(BgSave::parse_frames(&mut parse)?),
+            "lastsave" => Command::LastSave(LastSave::parse_frames(&mut parse)?),
+            "bgrewriteaof" => Command::BgRewriteAof(BgRewriteAof::parse_frames(&mut parse)?),
+            "debug" => Command::Debug(Debug::parse_frames(&mut parse)?),
+            "select" => Command::Select(Select::parse_frames(&mut parse)?),
+            "swapdb" => Command::SwapDb(SwapDb::parse_frames(&mut parse)?),
+            "move" => Command::Move(Move::parse_frames(&mut parse)?),
+            "scan" => Command::Scan(Scan::parse_frames(&mut parse)?),
+            "zscan" => Command::ZScan(ZScan::parse_frames(&mut parse)?),
+            "acl" => Command::Acl(Acl::parse_frames(&mut parse)?),
+            "auth" => Command::Auth(Auth::parse_frames(&mut parse)?),
+            "client" => Command::Client(Client::parse_frames(&mut parse)?),
+            "command" => Command::Introspect(Introspect::parse_frames(&mut parse)?),
+            "memory" => Command::Memory(Memory::parse_frames(&mut parse)?),
+            "latency" => Command::Latency(Latency::parse_frames(&mut parse)?),
+            "cluster" => Command::Cluster(Cluster::parse_frames(&mut parse)?),
+            "readonly" | "readwrite" => Command::ReadMode(ReadMode::parse_frames(&mut parse)?),
+            "asking" => Command::Asking(Asking::parse_frames(&mut parse)?),
+            "dump" => Command::Dump(Dump::parse_frames(&mut parse)?),
+            "restore" => Command::Restore(Restore::parse_frames(&mut parse)?),
+            "migrate" => Command::Migrate(Migrate::parse_frames(&mut parse)?),
+            "shutdown" => Command::Shutdown(Shutdown::parse_frames(&mut parse)?),
             _ => {
-                return Ok(Command::Unknown(Unknown::new(command_name)));
+                let args = parse.remaining_strings().unwrap_or_default();
+                return Ok(Command::Unknown(Unknown::new(command_name, args)));
             }
         };
         parse.finish()?; // if any remaining frames, return an error
@@ -50,16 +355,234 @@ impl Command {
         Ok(command)
     }
 
-    pub async fn apply<C: Comms>(self, store: &Store, comms: &mut C) -> anyhow::Result<()> {
+    /// Whether the command may run while the connection is in subscriber mode.
+    pub fn is_allowed_when_subscribed(&self) -> bool {
+        matches!(
+            self,
+            Command::Subscribe(_) | Command::Unsubscribe(_) | Command::Ping(_) | Command::Reset(_)
+        )
+    }
+
+    /// Whether the command may wait for other clients' writes, in which case
+    /// it must not hold the store's command lock.
+    pub fn is_blocking(&self) -> bool {
+        match self {
+            Command::BZPop(_) | Command::Wait(_) => true,
+            Command::XReadGroup(cmd) => cmd.is_blocking(),
+            _ => false,
+        }
+    }
+
+    /// Whether the command changes the dataset. Each of these publishes the
+    /// effect of a successful write to replicas as it applies.
+    pub fn is_write(&self) -> bool {
         match self {
-            Command::Echo(cmd) => cmd.apply(comms).await,
-            Command::Unknown(cmd) => cmd.apply(comms).await,
-            Command::Get(cmd) => cmd.apply(comms, store).await,
-            Command::Set(cmd) => cmd.apply(comms, store).await,
-            Command::Info(cmd) => cmd.apply(comms, store).await,
-            Command::ReplConf(cmd) => cmd.apply(comms, store).await,
-            Command::Ping(cmd) => cmd.apply(comms).await,
-            Command::Psync(cmd) => cmd.apply(comms, store).await,
+            Command::Set(_)
+            | Command::SetBit(_)
+            | Command::BitOp(_)
+            | Command::PfAdd(_)
+            | Command::PfMerge(_)
+            | Command::GeoAdd(_)
+            | Command::Del(_)
+            | Command::PExpireAt(_)
+            | Command::Persist(_)
+            | Command::ZAdd(_)
+            | Command::ZIncrBy(_)
+            | Command::ZPop(_)
+            | Command::BZPop(_)
+            | Command::ZStore(_)
+            | Command::ZRangeStore(_)
+            | Command::XAdd(_)
+            | Command::XDel(_)
+            | Command::XTrim(_)
+            | Command::XGroup(_)
+            | Command::XReadGroup(_)
+            | Command::XAck(_)
+            | Command::XClaim(_)
+            | Command::XAutoClaim(_)
+            | Command::SwapDb(_)
+            | Command::Move(_)
+            | Command::Restore(_)
+            | Command::Migrate(_) => true,
+            Command::Function(cmd) => cmd.is_write(),
+            _ => false,
+        }
+    }
+
+    /// Whether the command runs a script, which must not interleave with other
+    /// clients' commands.
+    pub fn is_script(&self) -> bool {
+        matches!(self, Command::Eval(_) | Command::FCall(_))
+    }
+
+    /// Whether a script may issue the command with `redis.call`.
+    pub fn is_allowed_in_script(&self) -> bool {
+        !matches!(
+            self,
+            Command::Eval(_)
+                | Command::Script(_)
+                | Command::FCall(_)
+                | Command::Function(_)
+                | Command::Wait(_)
+                | Command::Multi(_)
+                | Command::Exec(_)
+                | Command::Discard(_)
+                | Command::Reset(_)
+                | Command::Watch(_)
+                | Command::Unwatch(_)
+                | Command::Subscribe(_)
+                | Command::Unsubscribe(_)
+                | Command::Psync(_)
+                | Command::ReplConf(_)
+                | Command::ReplicaOf(_)
+                | Command::Failover(_)
+                | Command::Sentinel(_)
+                | Command::Config(_)
+                | Command::Save(_)
+                | Command::BgSave(_)
+                | Command::BgRewriteAof(_)
+                | Command::Debug(_)
+                | Command::Acl(_)
+                | Command::Auth(_)
+                | Command::Client(_)
+                | Command::Shutdown(_)
+        )
+    }
+
+    /// The command as it runs inside a transaction or script, where nothing blocks.
+    pub fn without_blocking(self) -> Command {
+        match self {
+            Command::BZPop(cmd) => Command::BZPop(cmd.without_blocking()),
+            Command::XReadGroup(cmd) => Command::XReadGroup(cmd.without_blocking()),
+            Command::Wait(cmd) => Command::Wait(cmd.without_blocking()),
+            command => command,
+        }
+    }
+
+    /// Whether the command controls the transaction itself rather than being
+    /// queued by it.
+    pub fn is_transaction_control(&self) -> bool {
+        matches!(
+            self,
+            Command::Multi(_)
+                | Command::Exec(_)
+                | Command::Discard(_)
+                | Command::Watch(_)
+                | Command::Reset(_)
+        )
+    }
+
+    /// Runs the command for its effect alone, as a replica does with the
+    /// master's stream: the reply is dropped, and a failure is only logged
+    /// since there is no client to tell.
+    pub async fn apply_quietly(self, store: &Store) {
+        match self.without_blocking().apply(store).await {
+            Ok(Frame::Error(message)) => log_warning!("replicated command failed: {}", message),
+            Ok(_) => {}
+            Err(err) => log_warning!("replicated command failed: {:#}", err),
+        }
+    }
+
+    /// Runs the command and returns its reply; the caller decides where, or
+    /// whether, to send it.
+    pub async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        match self {
+            Command::Echo(cmd) => cmd.apply().await,
+            Command::Hello(cmd) => cmd.apply(store).await,
+            Command::Unknown(cmd) => cmd.apply().await,
+            Command::Get(cmd) => cmd.apply(store).await,
+            Command::Set(cmd) => cmd.apply(store).await,
+            Command::SetBit(cmd) => cmd.apply(store).await,
+            Command::GetBit(cmd) => cmd.apply(store).await,
+            Command::BitCount(cmd) => cmd.apply(store).await,
+            Command::BitOp(cmd) => cmd.apply(store).await,
+            Command::BitPos(cmd) => cmd.apply(store).await,
+            Command::PfAdd(cmd) => cmd.apply(store).await,
+            Command::PfCount(cmd) => cmd.apply(store).await,
+            Command::PfMerge(cmd) => cmd.apply(store).await,
+            Command::GeoAdd(cmd) => cmd.apply(store).await,
+            Command::GeoPos(cmd) => cmd.apply(store).await,
+            Command::GeoDist(cmd) => cmd.apply(store).await,
+            Command::GeoSearch(cmd) => cmd.apply(store).await,
+            Command::Info(cmd) => cmd.apply(store).await,
+            Command::ReplConf(cmd) => cmd.apply(store).await,
+            Command::Ping(cmd) => cmd.apply().await,
+            Command::ZIncrBy(cmd) => cmd.apply(store).await,
+            Command::ZCard(cmd) => cmd.apply(store).await,
+            Command::ZCount(cmd) => cmd.apply(store).await,
+            Command::ZLexCount(cmd) => cmd.apply(store).await,
+            Command::ZPop(cmd) => cmd.apply(store).await,
+            Command::BZPop(cmd) => cmd.apply(store).await,
+            Command::Del(cmd) => cmd.apply(store).await,
+            Command::PExpireAt(cmd) => cmd.apply(store).await,
+            Command::Persist(cmd) => cmd.apply(store).await,
+            Command::ZAdd(cmd) => cmd.apply(store).await,
+            Command::ZStore(cmd) => cmd.apply(store).await,
+            Command::ZRange(cmd) => cmd.apply(store).await,
+            Command::ZRangeStore(cmd) => cmd.apply(store).await,
+            Command::XAdd(cmd) => cmd.apply(store).await,
+            Command::XRange(cmd) => cmd.apply(store).await,
+            Command::XLen(cmd) => cmd.apply(store).await,
+            Command::XDel(cmd) => cmd.apply(store).await,
+            Command::XTrim(cmd) => cmd.apply(store).await,
+            Command::XGroup(cmd) => cmd.apply(store).await,
+            Command::XReadGroup(cmd) => cmd.apply(store).await,
+            Command::XAck(cmd) => cmd.apply(store).await,
+            Command::XPending(cmd) => cmd.apply(store).await,
+            Command::XClaim(cmd) => cmd.apply(store).await,
+            Command::XAutoClaim(cmd) => cmd.apply(store).await,
+            Command::Publish(cmd) => cmd.apply(store).await,
+            Command::Eval(cmd) => cmd.apply(store).await,
+            Command::Script(cmd) => cmd.apply(store).await,
+            Command::FCall(cmd) => cmd.apply(store).await,
+            Command::Function(cmd) => cmd.apply(store).await,
+            Command::Wait(cmd) => cmd.apply(store).await,
+            Command::ReplicaOf(cmd) => cmd.apply(store).await,
+            Command::Failover(cmd) => cmd.apply(store).await,
+            Command::Sentinel(cmd) => cmd.apply(store).await,
+            Command::Config(cmd) => cmd.apply(store).await,
+            Command::Save(cmd) => cmd.apply(store).await,
+            Command::BgSave(cmd) => cmd.apply(store).await,
+            Command::LastSave(cmd) => cmd.apply(store).await,
+            Command::BgRewriteAof(cmd) => cmd.apply(store).await,
+            Command::Debug(cmd) => cmd.apply(store).await,
+            Command::Select(cmd) => cmd.apply(store).await,
+            Command::SwapDb(cmd) => cmd.apply(store).await,
+            Command::Move(cmd) => cmd.apply(store).await,
+            Command::Scan(cmd) => cmd.apply(store).await,
+            Command::ZScan(cmd) => cmd.apply(store).await,
+            Command::Acl(cmd) => cmd.apply(store).await,
+            Command::Auth(cmd) => cmd.apply(store).await,
+            Command::Client(cmd) => cmd.apply(store).await,
+            Command::Introspect(cmd) => cmd.apply().await,
+            Command::Memory(cmd) => cmd.apply(store).await,
+            Command::Latency(cmd) => cmd.apply(store).await,
+            Command::Cluster(cmd) => cmd.apply(store).await,
+            Command::ReadMode(cmd) => cmd.apply(store).await,
+            Command::Asking(cmd) => cmd.apply(store).await,
+            Command::Dump(cmd) => cmd.apply(store).await,
+            Command::Restore(cmd) => cmd.apply(store).await,
+            Command::Migrate(cmd) => cmd.apply(store).await,
+            Command::Shutdown(cmd) => cmd.apply(store).await,
+            Command::Help(cmd) => cmd.apply().await,
+            Command::Psync(_) => {
+                // the replica takes over the client connection, see `server::Handler`
+                Ok(Frame::Error("ERR PSYNC is not available here".to_string()))
+            }
+            Command::Subscribe(_) | Command::Unsubscribe(_) => {
+                // subscriptions belong to a client connection, see `server::Handler`
+                Ok(Frame::Error(
+                    "ERR pub/sub is not available here".to_string(),
+                ))
+            }
+            // queued inside MULTI; EXEC unwatches everything anyway
+            Command::Unwatch(_) => Ok(Frame::OK),
+            Command::Multi(_) | Command::Exec(_) | Command::Discard(_) | Command::Watch(_) => {
+                // transactions belong to a client connection, see `server::Handler`
+                Ok(Frame::Error("ERR MULTI is not available here".to_string()))
+            }
+            // the state it resets belongs to a client connection too
+            Command::Reset(_) => Ok(Frame::Error("ERR RESET is not available here".to_string())),
         }
     }
 }
@@ -92,6 +615,69 @@ macro_rules! count_redis_input_command_args {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn request(args: &[&'static str]) -> Frame {
+        Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::from(*arg)))
+                .collect(),
+        )
+    }
+
+    #[tokio::test]
+    async fn apply_returns_the_reply() -> anyhow::Result<()> {
+        let store = Store::new();
+        let set = Command::from_frame(request(&["SET", "k", "v"]))?;
+        assert_eq!(set.apply(&store).await?, Frame::OK);
+        let get = Command::from_frame(request(&["GET", "k"]))?;
+        assert_eq!(get.apply(&store).await?, Frame::Bulk("v".into()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn writes_advance_the_replication_offset() -> anyhow::Result<()> {
+        let store = Store::new();
+        let mut writes: Vec<&[&'static str]> = vec![
+            &["SET", "k", "v"],
+            &["DEL", "k"],
+            &["ZADD", "z", "1", "a", "2", "b", "3", "c"],
+            &["ZINCRBY", "z", "1", "a"],
+            &["ZPOPMIN", "z"],
+            &["BZPOPMAX", "z", "0"],
+            &["ZUNIONSTORE", "u", "1", "z"],
+            &["ZRANGESTORE", "r", "z", "0", "-1"],
+            &["XADD", "s", "1-1", "f", "v"],
+            &["XGROUP", "CREATE", "s", "g", "0"],
+            &["XREADGROUP", "GROUP", "g", "c", "STREAMS", "s", ">"],
+            &["XACK", "s", "g", "1-1"],
+            &["XDEL", "s", "1-1"],
+            &["FUNCTION", "FLUSH"],
+        ];
+        if cfg!(feature = "scripting") {
+            writes.extend([
+                &[
+                    "FUNCTION",
+                    "LOAD",
+                    "#!lua name=lib\nredis.register_function('f', function() return 1 end)",
+                ][..],
+                &["FUNCTION", "DELETE", "lib"],
+            ]);
+        }
+        for args in writes {
+            let command = Command::from_frame(request(args))?;
+            assert!(command.is_write(), "{:?} is a write", args);
+            let offset = store.master_repl_offset();
+            command.apply(&store).await?;
+            assert!(store.master_repl_offset() > offset, "{:?} replicated", args);
+        }
+
+        let read = Command::from_frame(request(&["FUNCTION", "LIST"]))?;
+        assert!(!read.is_write());
+        Ok(())
+    }
+
     #[test]
     fn test_array_of_bulks() {
         assert_eq!(
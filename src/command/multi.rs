@@ -0,0 +1,114 @@
+use crate::{
+    command::Command,
+    error::Error,
+    frame::Frame,
+    parse::Parse,
+    store::{watch::WatchedKeys, Store},
+};
+
+/// Commands queued by a client between MULTI and EXEC.
+#[derive(Debug, Default)]
+pub struct Transaction {
+    queued: Vec<Command>,
+    /// Set when a command failed to queue; EXEC then discards everything.
+    aborted: bool,
+}
+
+impl Transaction {
+    pub fn queue(&mut self, command: Command) {
+        self.queued.push(command);
+    }
+
+    /// How many commands are waiting for EXEC.
+    pub fn queued(&self) -> usize {
+        self.queued.len()
+    }
+
+    pub fn abort(&mut self) {
+        self.aborted = true;
+    }
+
+    /// Runs every queued command while holding the store's transaction lock,
+    /// returning their replies in order, or a null reply if a watched key
+    /// changed. A command that fails only fails its own slot; the rest still
+    /// run, as there is no rollback.
+    async fn exec(self, store: &Store, watched: &WatchedKeys) -> Frame {
+        if self.aborted {
+            return Error::ExecAbort.into();
+        }
+        let _lock = store.transaction_lock().await;
+        if watched.is_dirty() {
+            return Frame::NullArray;
+        }
+        let mut replies = Vec::with_capacity(self.queued.len());
+        for command in self.queued {
+            let reply = match command.without_blocking().apply(store).await {
+                Ok(reply) => reply,
+                Err(err) => Frame::from_error(&err),
+            };
+            replies.push(reply);
+        }
+        Frame::Array(replies)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Multi;
+
+impl Multi {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> anyhow::Result<Multi> {
+        Ok(Multi)
+    }
+
+    pub(crate) fn apply(self, transaction: &mut Option<Transaction>) -> Frame {
+        if transaction.is_some() {
+            return Frame::Error("ERR MULTI calls can not be nested".to_string());
+        }
+        *transaction = Some(Transaction::default());
+        Frame::OK
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Exec;
+
+impl Exec {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> anyhow::Result<Exec> {
+        Ok(Exec)
+    }
+
+    pub(crate) async fn apply(
+        self,
+        store: &Store,
+        transaction: &mut Option<Transaction>,
+        watched: &mut WatchedKeys,
+    ) -> Frame {
+        let Some(transaction) = transaction.take() else {
+            return Frame::Error("ERR EXEC without MULTI".to_string());
+        };
+        let response = transaction.exec(store, watched).await;
+        watched.unwatch();
+        response
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Discard;
+
+impl Discard {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> anyhow::Result<Discard> {
+        Ok(Discard)
+    }
+
+    pub(crate) fn apply(
+        self,
+        transaction: &mut Option<Transaction>,
+        watched: &mut WatchedKeys,
+    ) -> Frame {
+        if transaction.take().is_none() {
+            return Frame::Error("ERR DISCARD without MULTI".to_string());
+        }
+        watched.unwatch();
+        Frame::OK
+    }
+}
@@ -1,7 +1,6 @@
 use bytes::Bytes;
 
 use crate::{
-    comms::Comms,
     frame::Frame,
     parse::{Parse, ParseError},
 };
@@ -25,14 +24,21 @@ impl Ping {
         }
     }
 
-    pub(crate) async fn apply<C: Comms>(self, comms: &mut C) -> anyhow::Result<()> {
+    pub(crate) async fn apply(self) -> anyhow::Result<Frame> {
         let response = match self.msg {
             None => Frame::Simple("PONG".to_string()),
             Some(msg) => Frame::Bulk(msg),
         };
 
-        comms.write_frame(&response).await?;
+        Ok(response)
+    }
 
-        Ok(())
+    /// In subscriber mode PING replies like a pushed message: `["pong", msg]`.
+    pub(crate) async fn apply_subscribed(self) -> anyhow::Result<Frame> {
+        let response = Frame::Array(vec![
+            Frame::Bulk(Bytes::from("pong")),
+            Frame::Bulk(self.msg.unwrap_or_default()),
+        ]);
+        Ok(response)
     }
 }
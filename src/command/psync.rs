@@ -1,46 +1,97 @@
-use crate::{comms::Comms, frame::Frame, parse::Parse, store::Store};
+use std::net::SocketAddr;
+
+use anyhow::bail;
+
+use crate::{
+    frame::Frame,
+    parse::{Parse, ParseError},
+    publisher::Attached,
+    replicator,
+    store::Store,
+};
 
 #[derive(Debug, Default)]
 pub struct Psync {
-    /// Comes from the follower. It must be sending the master_replid so we can confirm it's us
+    /// The id of the master the replica last synced from, or `?` if none.
     master_replid: String,
-    master_repl_offset: Option<i64>,
+    /// The offset the replica has processed up to, unless it sent -1.
+    master_repl_offset: Option<u64>,
+    /// Sent by our master during FAILOVER, for us to take over from it.
+    failover: bool,
 }
 
 impl Psync {
     pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Psync> {
         let master_replid = parse.next_string()?;
-        // -1 initially
-        let _master_repl_offset = parse.next_string()?;
+        // -1, or anything else that isn't an offset, asks for a full resync
+        let master_repl_offset = parse.next_string()?.parse::<u64>().ok();
+        let failover = match parse.next_string() {
+            Ok(option) if option.eq_ignore_ascii_case("FAILOVER") => true,
+            Ok(_) => bail!("ERR syntax error"),
+            Err(ParseError::EndOfStream) => false,
+            Err(e) => return Err(e.into()),
+        };
 
         Ok(Psync {
             master_replid,
-            master_repl_offset: None,
+            master_repl_offset,
+            failover,
         })
     }
 
-    pub(crate) async fn apply<C: Comms>(self, comms: &mut C, store: &Store) -> anyhow::Result<()> {
-        let info = crate::info::Info::from_store(store)?;
-
-        if info.is_replica() {
-            let error = Frame::Error("Not a master server".to_string());
-            comms
-                .write_frame(&error)
-                .await
-                .map_err(anyhow::Error::from)?;
-            return Ok(());
+    /// Continues a replica's stream from the backlog when it was synced from
+    /// this stream and hasn't missed more than the backlog holds. Otherwise
+    /// starts a full resync: replies FULLRESYNC followed by an RDB snapshot.
+    /// Either way the replica is attached so later writes are streamed to it
+    /// once the connection is handed over.
+    /// `address` is where the replica says it can be reached, for INFO.
+    pub(crate) async fn apply(
+        self,
+        store: &Store,
+        address: SocketAddr,
+    ) -> anyhow::Result<(Vec<Frame>, Option<Attached>)> {
+        if self.failover {
+            let error = if !store.is_replica() {
+                Some("ERR PSYNC FAILOVER can't be sent to a master.")
+            } else if self.master_replid != store.replicas().replid() {
+                Some("ERR PSYNC FAILOVER replid must match my replid.")
+            } else {
+                None
+            };
+            if let Some(error) = error {
+                return Ok((vec![Frame::Error(error.to_string())], None));
+            }
+            log_notice!(
+                "Failover request received for replid {}.",
+                self.master_replid
+            );
+            // the old master carries on as our replica, from the offset it's at
+            replicator::unfollow(store);
+        }
+        // a replica passes on its master's stream, once it has one
+        if store.is_replica() && store.replicas().master_replid().is_none() {
+            let error = "NOMASTERLINK Can't SYNC while not connected with my master";
+            return Ok((vec![Frame::Error(error.to_string())], None));
         }
 
-        let response = Frame::Simple(format!(
-            "FULLRESYNC {} 0",
-            info.replication.master_replid.unwrap_or_default()
-        ));
-
-        comms
-            .write_frame(&response)
-            .await
-            .map_err(anyhow::Error::from)?;
+        let replid = store.replicas().replid();
+        let resumed = match self.master_repl_offset {
+            Some(offset) if store.replicas().can_resume(&self.master_replid, offset) => {
+                store.replicas().resume(address, offset)
+            }
+            _ => None,
+        };
+        if let Some((missed, attached)) = resumed {
+            let mut responses = vec![Frame::Simple(format!("CONTINUE {}", replid))];
+            responses.extend(missed);
+            return Ok((responses, Some(attached)));
+        }
 
-        Ok(())
+        let (offset, rdb, attached) = store.replicas().attach(store, address).await?;
+        let responses = vec![
+            Frame::Simple(format!("FULLRESYNC {} {}", replid, offset)),
+            Frame::RdbFile(rdb),
+        ];
+        Ok((responses, Some(attached)))
     }
 }
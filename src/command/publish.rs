@@ -0,0 +1,41 @@
+use bytes::Bytes;
+
+use crate::{
+    frame::Frame,
+    parse::Parse,
+    publisher::{publish, Action},
+    store::Store,
+};
+
+#[derive(Debug, Default)]
+pub struct Publish {
+    channel: Bytes,
+    message: Bytes,
+}
+
+impl Publish {
+    pub fn new(channel: Bytes, message: Bytes) -> Self {
+        Self { channel, message }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Publish> {
+        let channel = parse.next_bytes()?;
+        let message = parse.next_bytes()?;
+        Ok(Publish::new(channel, message))
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let receivers = store.pubsub().publish(&self.channel, self.message.clone());
+        // like Redis, messages are propagated so subscribers on replicas get them too
+        publish(
+            store,
+            Action::Publish {
+                channel: self.channel,
+                message: self.message,
+            },
+        )
+        .await?;
+
+        Ok(Frame::Integer(receivers as u64))
+    }
+}
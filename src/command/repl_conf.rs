@@ -1,63 +1,69 @@
-use anyhow::bail;
-
-use crate::{array_of_bulks, comms::Comms, frame::Frame, parse::Parse, store::Store};
+use crate::{
+    frame::Frame,
+    parse::{ArgSpec, Parse},
+    store::Store,
+};
 
 #[derive(Debug, Default)]
 pub struct ReplConf {
     /// The replication server listening port
     listening_port: Option<u16>,
-    capabilities: Vec<String>,
     getack_option: Option<String>,
+    /// The offset a replica reports in `REPLCONF ACK <offset>`
+    ack_offset: Option<u64>,
 }
 
 impl ReplConf {
     pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<ReplConf> {
-        let mut listening_port = None;
-        let mut capabilities = vec![];
-        let mut getack_option = None;
-
-        while let Ok(arg) = parse.next_string() {
-            match arg.to_lowercase().as_str() {
-                "listening-port" => {
-                    let port = parse
-                        .next_string()
-                        .map_err(|_| anyhow::anyhow!("expecting port"))?;
-                    listening_port = Some(port.parse()?);
-                }
-                "capa" => {
-                    let cap = parse
-                        .next_string()
-                        .map_err(|_| anyhow::anyhow!("expecting cap"))?;
-                    capabilities.push(cap);
-                }
-                "getack" => {
-                    let getack = parse
-                        .next_string()
-                        .map_err(|_| anyhow::anyhow!("expecting getack option"))?;
-                    getack_option = Some(getack);
-                }
-                _ => bail!("expecting listening-port or cap, but got {:?}", arg),
-            }
-        }
+        let options = ArgSpec::default()
+            .token("LISTENING-PORT", 1)
+            // capabilities are accepted, though none change what is sent
+            .token("CAPA", 1)
+            .token("GETACK", 1)
+            .token("ACK", 1)
+            // newer replicas send options such as FACK and rdb-only
+            .skip_unknown(1)
+            .parse(parse)?;
 
         Ok(ReplConf {
-            listening_port,
-            capabilities,
-            getack_option,
+            listening_port: options
+                .value("LISTENING-PORT")
+                .map(str::parse)
+                .transpose()?,
+            getack_option: options.value("GETACK").map(str::to_string),
+            ack_offset: options.value("ACK").map(str::parse).transpose()?,
         })
     }
 
-    pub(crate) async fn apply<C: Comms>(self, comms: &mut C, _store: &Store) -> anyhow::Result<()> {
+    /// The port a replica announces it listens on during the handshake.
+    pub(crate) fn listening_port(&self) -> Option<u16> {
+        self.listening_port
+    }
+
+    pub(crate) fn ack_offset(&self) -> Option<u64> {
+        self.ack_offset
+    }
+
+    /// Whether this is the master asking for the replica's offset.
+    pub(crate) fn is_getack(&self) -> bool {
+        self.getack_option.is_some()
+    }
+
+    /// A replica's answer to GETACK: how many bytes of the replication
+    /// stream it has processed.
+    pub(crate) fn ack(offset: u64) -> Frame {
+        Frame::Array(vec![
+            Frame::Bulk("REPLCONF".into()),
+            Frame::Bulk("ACK".into()),
+            Frame::Bulk(offset.to_string().into()),
+        ])
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
         match self.getack_option {
-            Some(_) => {
-                let response = Frame::Array(vec![
-                    Frame::Bulk("REPLCONF".into()),
-                    Frame::Bulk("ACK".into()),
-                    Frame::Bulk("0".into()),
-                ]);
-                comms.write_frame(&response).await.map_err(|e| e.into())
-            }
-            None => comms.write_frame(&Frame::OK).await.map_err(|e| e.into()),
+            // the replicator answers for replicas; a master reports its own offset
+            Some(_) => Ok(ReplConf::ack(store.master_repl_offset())),
+            None => Ok(Frame::OK),
         }
     }
 }
@@ -0,0 +1,52 @@
+use crate::{
+    frame::Frame,
+    parse::Parse,
+    replicator,
+    store::{role::Role, Store},
+};
+
+/// REPLICAOF host port / REPLICAOF NO ONE, also known as SLAVEOF.
+#[derive(Debug)]
+pub struct ReplicaOf {
+    host: String,
+    port: String,
+}
+
+impl ReplicaOf {
+    pub fn new(host: String, port: String) -> Self {
+        Self { host, port }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<ReplicaOf> {
+        let host = parse.next_string()?;
+        let port = parse.next_string()?;
+        Ok(ReplicaOf::new(host, port))
+    }
+
+    fn is_no_one(&self) -> bool {
+        self.host.eq_ignore_ascii_case("no") && self.port.eq_ignore_ascii_case("one")
+    }
+
+    /// Starts following the given master, resyncing from it, or with NO ONE
+    /// stops following and takes writes as a master again, keeping the data.
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        if self.is_no_one() {
+            replicator::unfollow(store);
+            return Ok(Frame::OK);
+        }
+        let Ok(port) = self.port.parse::<u16>() else {
+            return Ok(Frame::Error("ERR Invalid master port".to_string()));
+        };
+        let following = matches!(
+            store.role().get(),
+            Role::Replica { host, port: current } if host == self.host && current == port
+        );
+        if following {
+            return Ok(Frame::Simple(
+                "OK Already connected to specified master".to_string(),
+            ));
+        }
+        replicator::follow(store, self.host, port)?;
+        Ok(Frame::OK)
+    }
+}
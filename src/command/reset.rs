@@ -0,0 +1,43 @@
+use crate::{
+    command::multi::Transaction,
+    frame::Frame,
+    parse::Parse,
+    store::{pubsub::Subscriptions, watch::WatchedKeys, Store},
+};
+
+/// RESET: puts the connection back as it was when the client connected. It
+/// leaves MULTI, unwatches every key, unsubscribes from every channel, turns
+/// tracking, no-evict and no-touch off, returns to RESP2 and database 0 and
+/// logs back in as the default user, or out if that needs a password.
+#[derive(Debug, Default)]
+pub struct Reset;
+
+impl Reset {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> anyhow::Result<Reset> {
+        Ok(Reset)
+    }
+
+    pub(crate) fn apply(
+        self,
+        store: &Store,
+        transaction: &mut Option<Transaction>,
+        watched: &mut WatchedKeys,
+        subscriptions: &mut Subscriptions,
+    ) -> Frame {
+        *transaction = None;
+        watched.unwatch();
+        for channel in subscriptions.channels().to_vec() {
+            subscriptions.unsubscribe(&channel);
+        }
+        if let Some(id) = store.client_id() {
+            store.tracking().disable(id);
+            store.clients().update(id, |client| {
+                client.tracking = false;
+                client.no_evict = false;
+                client.no_touch = false;
+            });
+        }
+        store.reset_client();
+        Frame::Simple("RESET".to_string())
+    }
+}
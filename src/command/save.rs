@@ -0,0 +1,76 @@
+use crate::{frame::Frame, parse::Parse, store::Store};
+
+const ERR_BGSAVE_IN_PROGRESS: &str = "ERR Background save already in progress";
+
+/// SAVE: writes the keyspace to the RDB file before replying.
+#[derive(Debug)]
+pub struct Save;
+
+impl Save {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> anyhow::Result<Save> {
+        Ok(Save)
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        if store.persistence().bgsave_running_for().is_some() {
+            return Ok(Frame::Error(ERR_BGSAVE_IN_PROGRESS.to_string()));
+        }
+        match store.save().await {
+            Ok(()) => Ok(Frame::OK),
+            Err(err) => Ok(Frame::Error(format!("ERR {:#}", err))),
+        }
+    }
+}
+
+/// BGSAVE: writes a copy of the keyspace to the RDB file in the background.
+#[derive(Debug)]
+pub struct BgSave;
+
+impl BgSave {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> anyhow::Result<BgSave> {
+        Ok(BgSave)
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        if !store.bgsave() {
+            return Ok(Frame::Error(ERR_BGSAVE_IN_PROGRESS.to_string()));
+        }
+        Ok(Frame::Simple("Background saving started".to_string()))
+    }
+}
+
+/// BGREWRITEAOF: rewrites the append-only file from the keyspace in the
+/// background.
+#[derive(Debug)]
+pub struct BgRewriteAof;
+
+impl BgRewriteAof {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> anyhow::Result<BgRewriteAof> {
+        Ok(BgRewriteAof)
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        if !store.bgrewriteaof() {
+            return Ok(Frame::Error(
+                "ERR Background append only file rewriting already in progress".to_string(),
+            ));
+        }
+        Ok(Frame::Simple(
+            "Background append only file rewriting started".to_string(),
+        ))
+    }
+}
+
+/// LASTSAVE: the unix time of the last successful save.
+#[derive(Debug)]
+pub struct LastSave;
+
+impl LastSave {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> anyhow::Result<LastSave> {
+        Ok(LastSave)
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        Ok(Frame::Integer(store.persistence().lastsave()))
+    }
+}
@@ -0,0 +1,129 @@
+use bytes::Bytes;
+
+use crate::{
+    error::Error,
+    frame::Frame,
+    glob,
+    parse::{ArgSpec, Parse},
+    store::{sorted_set::format_score, Store},
+};
+
+/// SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]: a few keys of the
+/// selected database at a time. A key there for the whole iteration is
+/// returned exactly once, see `store::scan`.
+#[derive(Debug)]
+pub struct Scan {
+    cursor: String,
+    pattern: Option<String>,
+    count: Option<String>,
+    value_type: Option<String>,
+}
+
+impl Scan {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Scan> {
+        let cursor = parse.next_string()?;
+        let options = ArgSpec::default()
+            .token("MATCH", 1)
+            .token("COUNT", 1)
+            .token("TYPE", 1)
+            .parse(parse)?;
+        Ok(Scan {
+            cursor,
+            pattern: options.value("MATCH").map(str::to_string),
+            count: options.value("COUNT").map(str::to_string),
+            value_type: options.value("TYPE").map(|name| name.to_lowercase()),
+        })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let (cursor, count) = match cursor_and_count(&self.cursor, self.count.as_deref()) {
+            Ok(parsed) => parsed,
+            Err(error) => return Ok(error),
+        };
+        let (next, keys) = store.scan(cursor, count, |key, value| {
+            self.pattern
+                .as_ref()
+                .is_none_or(|pattern| glob::matches(pattern.as_bytes(), key, false))
+                && self
+                    .value_type
+                    .as_ref()
+                    .is_none_or(|name| value.type_name() == name)
+        });
+        Ok(Frame::Array(vec![
+            Frame::Bulk(Bytes::from(next.to_string())),
+            Frame::Array(keys.into_iter().map(Frame::Bulk).collect()),
+        ]))
+    }
+}
+
+/// ZSCAN key cursor [MATCH pattern] [COUNT count] [NOSCORES]: a few members
+/// of a sorted set at a time, with their scores unless NOSCORES is given.
+/// Members are visited in hash order as SCAN visits keys.
+#[derive(Debug)]
+pub struct ZScan {
+    key: Bytes,
+    cursor: String,
+    pattern: Option<String>,
+    count: Option<String>,
+    no_scores: bool,
+}
+
+impl ZScan {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<ZScan> {
+        let key = parse.next_bytes()?;
+        let cursor = parse.next_string()?;
+        let options = ArgSpec::default()
+            .token("MATCH", 1)
+            .token("COUNT", 1)
+            .flag("NOSCORES")
+            .parse(parse)?;
+        Ok(ZScan {
+            key,
+            cursor,
+            pattern: options.value("MATCH").map(str::to_string),
+            count: options.value("COUNT").map(str::to_string),
+            no_scores: options.has("NOSCORES"),
+        })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let (cursor, count) = match cursor_and_count(&self.cursor, self.count.as_deref()) {
+            Ok(parsed) => parsed,
+            Err(error) => return Ok(error),
+        };
+        let scanned = store.zscan(&self.key, cursor, count, |member| {
+            self.pattern
+                .as_ref()
+                .is_none_or(|pattern| glob::matches(pattern.as_bytes(), member, false))
+        });
+        let (next, members) = match scanned {
+            Ok(scanned) => scanned,
+            Err(e) => return Ok(Frame::Error(e.to_string())),
+        };
+        let mut page = vec![];
+        for (member, score) in members {
+            page.push(Frame::Bulk(member));
+            if !self.no_scores {
+                page.push(Frame::Bulk(format_score(score).into()));
+            }
+        }
+        Ok(Frame::Array(vec![
+            Frame::Bulk(Bytes::from(next.to_string())),
+            Frame::Array(page),
+        ]))
+    }
+}
+
+/// The cursor and COUNT the SCAN family takes, or the error to reply with.
+fn cursor_and_count(cursor: &str, count: Option<&str>) -> Result<(u64, usize), Frame> {
+    let Ok(cursor) = cursor.parse::<u64>() else {
+        return Err(Frame::Error("ERR invalid cursor".to_string()));
+    };
+    let count = match count.map(|count| count.parse::<i64>()) {
+        Some(Ok(count)) if count < 1 => return Err(Error::Syntax.into()),
+        Some(Ok(count)) => count as usize,
+        Some(Err(_)) => return Err(Error::NotAnInteger.into()),
+        None => 10,
+    };
+    Ok((cursor, count))
+}
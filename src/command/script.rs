@@ -0,0 +1,83 @@
+use anyhow::bail;
+use bytes::Bytes;
+
+use crate::{
+    frame::Frame,
+    parse::{Parse, ParseError},
+    store::Store,
+};
+
+#[derive(Debug)]
+enum ScriptOp {
+    Load(Bytes),
+    Exists(Vec<String>),
+    Flush,
+    Kill,
+}
+
+/// SCRIPT LOAD / EXISTS / FLUSH, managing the cache EVALSHA runs from, and
+/// SCRIPT KILL, stopping the script running now.
+#[derive(Debug)]
+pub struct Script {
+    op: ScriptOp,
+}
+
+impl Script {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Script> {
+        let subcommand = parse.next_string()?;
+        let op = match subcommand.to_uppercase().as_str() {
+            "LOAD" => ScriptOp::Load(parse.next_bytes()?),
+            "EXISTS" => {
+                let mut shas = vec![parse.next_string()?];
+                loop {
+                    match parse.next_string() {
+                        Ok(sha) => shas.push(sha),
+                        Err(ParseError::EndOfStream) => break,
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                ScriptOp::Exists(shas)
+            }
+            "FLUSH" => {
+                // scripts are dropped straight away either way
+                match parse.next_string() {
+                    Ok(mode) if ["ASYNC", "SYNC"].contains(&mode.to_uppercase().as_str()) => {}
+                    Err(ParseError::EndOfStream) => {}
+                    Ok(_) => bail!("ERR SCRIPT FLUSH only support SYNC|ASYNC option"),
+                    Err(e) => return Err(e.into()),
+                }
+                ScriptOp::Flush
+            }
+            "KILL" => ScriptOp::Kill,
+            _ => bail!("ERR unknown subcommand '{}'. Try SCRIPT HELP.", subcommand),
+        };
+        Ok(Script { op })
+    }
+
+    /// SCRIPT KILL runs while the script it stops holds the transaction
+    /// lock, so it mustn't wait for it.
+    pub(crate) fn is_kill(&self) -> bool {
+        matches!(self.op, ScriptOp::Kill)
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let scripts = store.scripts();
+        let response = match self.op {
+            ScriptOp::Load(body) => Frame::Bulk(scripts.load(body).into()),
+            ScriptOp::Exists(shas) => Frame::Array(
+                shas.iter()
+                    .map(|sha| Frame::Integer(scripts.exists(sha) as u64))
+                    .collect(),
+            ),
+            ScriptOp::Flush => {
+                scripts.flush();
+                Frame::OK
+            }
+            ScriptOp::Kill => match scripts.kill(false) {
+                Ok(()) => Frame::OK,
+                Err(err) => Frame::Error(err.to_string()),
+            },
+        };
+        Ok(response)
+    }
+}
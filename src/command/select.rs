@@ -0,0 +1,111 @@
+use bytes::Bytes;
+
+use crate::{
+    error::Error,
+    frame::Frame,
+    parse::Parse,
+    publisher::{publish, Action},
+    store::{Store, DATABASES},
+};
+
+const ERR_OUT_OF_RANGE: &str = "ERR DB index is out of range";
+
+/// A database index as given, checked when the command runs so a bad one is
+/// an error reply rather than a protocol error.
+fn parse_index(index: &str, not_an_integer: &str) -> Result<usize, Frame> {
+    let index: i64 = index
+        .parse()
+        .map_err(|_| Frame::Error(not_an_integer.to_string()))?;
+    match usize::try_from(index) {
+        Ok(index) if index < DATABASES => Ok(index),
+        _ => Err(Frame::Error(ERR_OUT_OF_RANGE.to_string())),
+    }
+}
+
+/// SELECT index: switches the client to another database.
+#[derive(Debug)]
+pub struct Select {
+    index: String,
+}
+
+impl Select {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Select> {
+        Ok(Select {
+            index: parse.next_string()?,
+        })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        match parse_index(&self.index, &Error::NotAnInteger.to_string()) {
+            Ok(db) => {
+                store.select(db);
+                Ok(Frame::OK)
+            }
+            Err(error) => Ok(error),
+        }
+    }
+}
+
+/// SWAPDB index1 index2: exchanges two databases for every client.
+#[derive(Debug)]
+pub struct SwapDb {
+    a: String,
+    b: String,
+}
+
+impl SwapDb {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<SwapDb> {
+        Ok(SwapDb {
+            a: parse.next_string()?,
+            b: parse.next_string()?,
+        })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let a = match parse_index(&self.a, "ERR invalid first DB index") {
+            Ok(a) => a,
+            Err(error) => return Ok(error),
+        };
+        let b = match parse_index(&self.b, "ERR invalid second DB index") {
+            Ok(b) => b,
+            Err(error) => return Ok(error),
+        };
+        store.swap_db(a, b);
+        publish(store, Action::SwapDb { a, b }).await?;
+        Ok(Frame::OK)
+    }
+}
+
+/// MOVE key db: moves a key from the selected database to another one, unless
+/// it already exists there.
+#[derive(Debug)]
+pub struct Move {
+    key: Bytes,
+    db: String,
+}
+
+impl Move {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Move> {
+        Ok(Move {
+            key: parse.next_bytes()?,
+            db: parse.next_string()?,
+        })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let db = match parse_index(&self.db, &Error::NotAnInteger.to_string()) {
+            Ok(db) => db,
+            Err(error) => return Ok(error),
+        };
+        if db == store.db() {
+            return Ok(Frame::Error(
+                "ERR source and destination objects are the same".to_string(),
+            ));
+        }
+        if !store.move_key(&self.key, db) {
+            return Ok(Frame::Integer(0));
+        }
+        publish(store, Action::Move { key: self.key, db }).await?;
+        Ok(Frame::Integer(1))
+    }
+}
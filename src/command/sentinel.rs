@@ -0,0 +1,145 @@
+use anyhow::bail;
+use bytes::Bytes;
+
+use crate::{
+    frame::Frame,
+    parse::Parse,
+    store::{
+        sentinel::{Master, Replica, ERR_DISABLED},
+        Store,
+    },
+};
+
+#[derive(Debug)]
+enum SentinelOp {
+    Masters,
+    Master(String),
+    Replicas(String),
+    GetMasterAddrByName(String),
+}
+
+/// SENTINEL MASTERS / MASTER name / REPLICAS name / GET-MASTER-ADDR-BY-NAME
+/// name, answered by a server in sentinel mode.
+#[derive(Debug)]
+pub struct Sentinel {
+    op: SentinelOp,
+}
+
+impl Sentinel {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Sentinel> {
+        let subcommand = parse.next_string()?;
+        let op = match subcommand.to_uppercase().as_str() {
+            "MASTERS" => SentinelOp::Masters,
+            "MASTER" => SentinelOp::Master(parse.next_string()?),
+            "REPLICAS" | "SLAVES" => SentinelOp::Replicas(parse.next_string()?),
+            "GET-MASTER-ADDR-BY-NAME" => SentinelOp::GetMasterAddrByName(parse.next_string()?),
+            _ => bail!(
+                "ERR unknown subcommand '{}'. Try SENTINEL HELP.",
+                subcommand
+            ),
+        };
+        Ok(Sentinel { op })
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        if !store.sentinel().is_enabled() {
+            return Ok(Frame::Error(ERR_DISABLED.to_string()));
+        }
+        let resp3 = store.resp3();
+        let named = |name: &str| {
+            store
+                .sentinel()
+                .master_named(name)
+                .ok_or_else(|| Frame::Error("ERR No such master with that name".to_string()))
+        };
+        let response = match self.op {
+            SentinelOp::Masters => Frame::Array(
+                store
+                    .sentinel()
+                    .master()
+                    .iter()
+                    .map(|master| master_fields(master, resp3))
+                    .collect(),
+            ),
+            SentinelOp::Master(name) => match named(&name) {
+                Ok(master) => master_fields(&master, resp3),
+                Err(error) => error,
+            },
+            SentinelOp::Replicas(name) => match named(&name) {
+                Ok(master) => Frame::Array(
+                    master
+                        .replicas
+                        .iter()
+                        .map(|replica| replica_fields(replica, &master, resp3))
+                        .collect(),
+                ),
+                Err(error) => error,
+            },
+            // a master nobody knows of is no error here
+            SentinelOp::GetMasterAddrByName(name) => match store.sentinel().master_named(&name) {
+                Some(master) => {
+                    Frame::Array(vec![bulk(master.address.host), bulk(master.address.port)])
+                }
+                None => Frame::Null,
+            },
+        };
+        Ok(response)
+    }
+}
+
+fn bulk(value: impl ToString) -> Frame {
+    Frame::Bulk(Bytes::from(value.to_string()))
+}
+
+fn fields(pairs: Vec<(&str, String)>, resp3: bool) -> Frame {
+    Frame::map(
+        pairs
+            .into_iter()
+            .map(|(name, value)| (bulk(name), bulk(value)))
+            .collect(),
+        resp3,
+    )
+}
+
+fn master_fields(master: &Master, resp3: bool) -> Frame {
+    let flags = if master.down {
+        "master,s_down"
+    } else {
+        "master"
+    };
+    fields(
+        vec![
+            ("name", master.name.clone()),
+            ("ip", master.address.host.clone()),
+            ("port", master.address.port.to_string()),
+            ("flags", flags.to_string()),
+            (
+                "last-ok-ping-reply",
+                master.last_reply.elapsed().as_millis().to_string(),
+            ),
+            (
+                "down-after-milliseconds",
+                master.down_after.as_millis().to_string(),
+            ),
+            ("num-slaves", master.replicas.len().to_string()),
+            ("quorum", "1".to_string()),
+            ("failover-count", master.failovers.to_string()),
+        ],
+        resp3,
+    )
+}
+
+fn replica_fields(replica: &Replica, master: &Master, resp3: bool) -> Frame {
+    fields(
+        vec![
+            ("name", replica.address.to_string()),
+            ("ip", replica.address.host.clone()),
+            ("port", replica.address.port.to_string()),
+            ("flags", "slave".to_string()),
+            ("master-host", master.address.host.clone()),
+            ("master-port", master.address.port.to_string()),
+            ("slave-repl-offset", replica.offset.to_string()),
+        ],
+        resp3,
+    )
+}
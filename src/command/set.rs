@@ -3,11 +3,11 @@ use std::time::Duration;
 use bytes::Bytes;
 
 use crate::{
-    comms::Comms,
+    error::Error,
     frame::Frame,
-    parse::Parse,
+    parse::{ArgSpec, Parse},
     publisher::{publish, Action},
-    store::{Store, DEFAULT_EXPIRY},
+    store::{notify::EventClass, Store},
 };
 
 #[derive(Debug, Default, Clone, PartialEq)]
@@ -15,46 +15,76 @@ pub struct Set {
     key: Bytes,
     value: Bytes,
     expiry: Option<u64>,
+    /// NX: only set a key that doesn't exist.
+    nx: bool,
+    /// XX: only set a key that exists.
+    xx: bool,
 }
 
 impl Set {
     pub fn new(key: Bytes, value: Bytes, expiry: Option<u64>) -> Self {
-        Self { key, value, expiry }
+        Self {
+            key,
+            value,
+            expiry,
+            ..Self::default()
+        }
     }
 
     pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Set> {
         let key = parse.next_string()?;
         let value = parse.next_string()?;
-        let mut expiry = None;
-
-        match parse.next_string() {
-            Ok(s) if s.to_uppercase() == "PX" => {
-                expiry = Some(parse.next_int()?);
-            }
-            _ => {}
-        }
+        let options = ArgSpec::default()
+            .flag("NX")
+            .flag("XX")
+            .token("EX", 1)
+            .token("PX", 1)
+            .exclusive(&["NX", "XX"])
+            .exclusive(&["EX", "PX"])
+            .parse(parse)?;
+        let expiry = match (options.value("EX"), options.value("PX")) {
+            (Some(secs), _) => Some(expire_time(secs)?.saturating_mul(1000)),
+            (_, Some(millis)) => Some(expire_time(millis)?),
+            _ => None,
+        };
 
-        Ok(Set::new(key.into(), value.into(), expiry))
+        Ok(Set {
+            nx: options.has("NX"),
+            xx: options.has("XX"),
+            ..Set::new(key.into(), value.into(), expiry)
+        })
     }
 
-    pub(crate) async fn apply<C: Comms>(self, comms: &mut C, store: &Store) -> anyhow::Result<()> {
-        let ttl = self.expiry.unwrap_or(DEFAULT_EXPIRY);
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        if self.nx || self.xx {
+            let exists = store.expiry(&self.key).is_some();
+            if exists != self.xx {
+                return Ok(Frame::Null);
+            }
+        }
         let cloned_self = self.clone();
 
-        store.set(self.key, self.value, Duration::from_millis(ttl));
+        store.set(self.key, self.value, self.expiry.map(Duration::from_millis));
+        store.notify(EventClass::String, "set", &cloned_self.key);
 
         let action = Action::Set {
             key: cloned_self.key,
             value: cloned_self.value,
             expiry: cloned_self.expiry,
         };
-        publish(action).await?;
+        publish(store, action).await?;
 
-        if !comms.is_follower_receiving_sync_request() {
-            let response = Frame::OK;
-            comms.write_frame(&response).await.map_err(|e| e.into())
-        } else {
-            Ok(())
-        }
+        Ok(Frame::OK)
+    }
+}
+
+/// An EX or PX argument, which must be a positive integer.
+fn expire_time(value: &str) -> Result<u64, Error> {
+    match value.parse::<i64>() {
+        Ok(time) if time > 0 => Ok(time as u64),
+        Ok(_) => Err(Error::Err(
+            "invalid expire time in 'set' command".to_string(),
+        )),
+        Err(_) => Err(Error::NotAnInteger),
     }
 }
@@ -0,0 +1,40 @@
+use anyhow::bail;
+
+use crate::{error::Error, frame::Frame, parse::Parse, store::Store};
+
+/// SHUTDOWN [NOSAVE|SAVE]: saves unless told not to, then stops the server.
+/// The client gets no reply if it worked, since the connection just closes.
+#[derive(Debug)]
+pub struct Shutdown {
+    /// Whether to save, if given; otherwise only when save rules are set.
+    save: Option<bool>,
+}
+
+impl Shutdown {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Shutdown> {
+        let save = match &parse.remaining_strings()?[..] {
+            [] => None,
+            [mode] if mode.eq_ignore_ascii_case("NOSAVE") => Some(false),
+            [mode] if mode.eq_ignore_ascii_case("SAVE") => Some(true),
+            _ => bail!(Error::Syntax),
+        };
+        Ok(Shutdown { save })
+    }
+
+    /// Whether this stops the server without saving, which it may do even
+    /// while a script is busy.
+    pub(crate) fn is_nosave(&self) -> bool {
+        self.save == Some(false)
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        if let Err(err) = store.prepare_shutdown(self.save).await {
+            log_warning!("Errors trying to shut down the server: {:#}", err);
+            return Ok(Frame::Error(
+                "ERR Errors trying to SHUTDOWN. Check logs.".to_string(),
+            ));
+        }
+        store.begin_shutdown();
+        Ok(Frame::OK)
+    }
+}
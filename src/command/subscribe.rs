@@ -0,0 +1,98 @@
+use bytes::Bytes;
+
+use crate::{
+    frame::Frame,
+    parse::{Parse, ParseError},
+    store::pubsub::Subscriptions,
+};
+
+/// Subscribes the connection to one or more channels. While subscribed, the
+/// connection only accepts a restricted set of commands (see `server::Handler`).
+#[derive(Debug, Default)]
+pub struct Subscribe {
+    channels: Vec<Bytes>,
+}
+
+/// Unsubscribes from the given channels, or from all of them if none are given.
+#[derive(Debug, Default)]
+pub struct Unsubscribe {
+    channels: Vec<Bytes>,
+}
+
+impl Subscribe {
+    pub fn new(channels: Vec<Bytes>) -> Self {
+        Self { channels }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Subscribe> {
+        let mut channels = vec![parse.next_bytes()?];
+        channels.extend(parse_channels(parse)?);
+        Ok(Subscribe::new(channels))
+    }
+
+    /// Replies once per channel with the running subscription count.
+    pub(crate) fn apply(self, subscriptions: &mut Subscriptions) -> Vec<Frame> {
+        self.channels
+            .into_iter()
+            .map(|channel| {
+                let count = subscriptions.subscribe(channel.clone());
+                subscription_frame("subscribe", Some(channel), count)
+            })
+            .collect()
+    }
+}
+
+impl Unsubscribe {
+    pub fn new(channels: Vec<Bytes>) -> Self {
+        Self { channels }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Unsubscribe> {
+        Ok(Unsubscribe::new(parse_channels(parse)?))
+    }
+
+    pub(crate) fn apply(self, subscriptions: &mut Subscriptions) -> Vec<Frame> {
+        let channels = match self.channels.is_empty() {
+            true => subscriptions.channels().to_vec(),
+            false => self.channels,
+        };
+        if channels.is_empty() {
+            return vec![subscription_frame("unsubscribe", None, 0)];
+        }
+        channels
+            .into_iter()
+            .map(|channel| {
+                let count = subscriptions.unsubscribe(&channel);
+                subscription_frame("unsubscribe", Some(channel), count)
+            })
+            .collect()
+    }
+}
+
+fn parse_channels(parse: &mut Parse) -> anyhow::Result<Vec<Bytes>> {
+    let mut channels = vec![];
+    loop {
+        match parse.next_bytes() {
+            Ok(channel) => channels.push(channel),
+            Err(ParseError::EndOfStream) => return Ok(channels),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+fn subscription_frame(kind: &'static str, channel: Option<Bytes>, count: usize) -> Frame {
+    Frame::Array(vec![
+        Frame::Bulk(Bytes::from(kind)),
+        channel.map_or(Frame::Null, Frame::Bulk),
+        Frame::Integer(count as u64),
+    ])
+}
+
+/// The push frame delivered to subscribers for each published message.
+pub(crate) fn message_frame(channel: Bytes, message: Bytes) -> Frame {
+    Frame::Array(vec![
+        Frame::Bulk(Bytes::from("message")),
+        Frame::Bulk(channel),
+        Frame::Bulk(message),
+    ])
+}
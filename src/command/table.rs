@@ -0,0 +1,1471 @@
+//! What the server knows about each command it dispatches: its arity, flags,
+//! ACL categories and where its keys are. COMMAND reports it and ACL checks
+//! requests against it.
+
+use crate::frame::Frame;
+
+/// Where a command's keys are among its arguments, counting the name as 0.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Keys {
+    None,
+    /// Every `step`th argument from `first` to `last`; a negative `last`
+    /// counts back from the end.
+    Range(usize, isize, usize),
+    /// A key count at 2 followed by that many keys, after a destination key
+    /// at 1 if `dest` is set.
+    Counted {
+        dest: bool,
+    },
+    /// The first half of the arguments after `STREAMS`.
+    Streams,
+}
+
+impl Keys {
+    pub(crate) fn of<'a>(self, args: &[&'a [u8]]) -> Vec<&'a [u8]> {
+        match self {
+            Keys::None => vec![],
+            Keys::Range(first, last, step) => {
+                let last = if last < 0 {
+                    args.len() as isize + last
+                } else {
+                    last
+                };
+                if last < first as isize {
+                    return vec![];
+                }
+                let last = (last as usize).min(args.len().saturating_sub(1));
+                (first..=last).step_by(step).map(|i| args[i]).collect()
+            }
+            Keys::Counted { dest } => {
+                let count = args
+                    .get(2)
+                    .and_then(|n| std::str::from_utf8(n).ok())
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .unwrap_or(0);
+                let keys = args.iter().skip(3).take(count).copied();
+                match args.get(1) {
+                    Some(&dest_key) if dest => std::iter::once(dest_key).chain(keys).collect(),
+                    _ => keys.collect(),
+                }
+            }
+            Keys::Streams => {
+                let Some(at) = args
+                    .iter()
+                    .position(|arg| arg.eq_ignore_ascii_case(b"streams"))
+                else {
+                    return vec![];
+                };
+                let rest = &args[at + 1..];
+                rest[..rest.len() / 2].to_vec()
+            }
+        }
+    }
+
+    /// The first key, last key and step COMMAND reports. Keys that can only be
+    /// found by reading other arguments are reported as movable instead.
+    pub(crate) fn positions(self) -> (i64, i64, i64) {
+        match self {
+            Keys::Range(first, last, step) => (first as i64, last as i64, step as i64),
+            Keys::Counted { dest: true } => (1, 1, 1),
+            Keys::None | Keys::Counted { dest: false } | Keys::Streams => (0, 0, 0),
+        }
+    }
+
+    pub(crate) fn is_movable(self) -> bool {
+        matches!(self, Keys::Counted { .. } | Keys::Streams)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct CommandSpec {
+    /// The command name, or `name|subcommand` for a subcommand that differs
+    /// from its command, say in its categories.
+    pub name: &'static str,
+    /// The number of arguments, counting the name; negative for at least that
+    /// many.
+    pub arity: i64,
+    pub flags: &'static [&'static str],
+    pub categories: &'static [&'static str],
+    pub keys: Keys,
+    pub summary: &'static str,
+}
+
+impl CommandSpec {
+    /// Whether `len` arguments, counting the name, fit the arity.
+    pub(crate) fn accepts(&self, len: usize) -> bool {
+        let len = len as i64;
+        if self.arity < 0 {
+            len >= -self.arity
+        } else {
+            len == self.arity
+        }
+    }
+}
+
+const fn spec(
+    name: &'static str,
+    arity: i64,
+    flags: &'static [&'static str],
+    categories: &'static [&'static str],
+    keys: Keys,
+    summary: &'static str,
+) -> CommandSpec {
+    CommandSpec {
+        name,
+        arity,
+        flags,
+        categories,
+        keys,
+        summary,
+    }
+}
+
+/// The categories `+@category` rules can name, as ACL CAT lists them.
+pub(crate) const CATEGORIES: [&str; 16] = [
+    "keyspace",
+    "read",
+    "write",
+    "string",
+    "bitmap",
+    "hyperloglog",
+    "sortedset",
+    "geo",
+    "stream",
+    "pubsub",
+    "admin",
+    "dangerous",
+    "connection",
+    "transaction",
+    "scripting",
+    "blocking",
+];
+
+const READ_BITMAP: &[&str] = &["read", "bitmap"];
+const WRITE_BITMAP: &[&str] = &["write", "bitmap"];
+const READ_HLL: &[&str] = &["read", "hyperloglog"];
+const WRITE_HLL: &[&str] = &["write", "hyperloglog"];
+const READ_ZSET: &[&str] = &["read", "sortedset"];
+const WRITE_ZSET: &[&str] = &["write", "sortedset"];
+const READ_GEO: &[&str] = &["read", "geo"];
+const WRITE_GEO: &[&str] = &["write", "geo"];
+const READ_STREAM: &[&str] = &["read", "stream"];
+const WRITE_STREAM: &[&str] = &["write", "stream"];
+const ADMIN: &[&str] = &["admin", "dangerous"];
+const FIRST_KEY: Keys = Keys::Range(1, 1, 1);
+
+const FAST_READ: &[&str] = &["readonly", "fast"];
+const READ: &[&str] = &["readonly"];
+const FAST_WRITE: &[&str] = &["write", "denyoom", "fast"];
+const WRITE: &[&str] = &["write", "denyoom"];
+/// Writes that only ever free memory.
+const FAST_REMOVE: &[&str] = &["write", "fast"];
+const BLOCKING_REMOVE: &[&str] = &["write", "fast", "blocking"];
+const CONNECTION: &[&str] = &["fast", "loading", "stale"];
+const TRANSACTION: &[&str] = &["noscript", "loading", "stale", "fast", "allow_busy"];
+const SCRIPT: &[&str] = &["noscript", "stale", "skip_monitor", "may_replicate"];
+const SERVER: &[&str] = &["admin", "noscript", "loading", "stale"];
+
+pub(crate) const COMMANDS: &[CommandSpec] = &[
+    spec(
+        "ping",
+        -1,
+        CONNECTION,
+        &["connection"],
+        Keys::None,
+        "Returns the server's liveliness response.",
+    ),
+    spec(
+        "echo",
+        2,
+        CONNECTION,
+        &["connection"],
+        Keys::None,
+        "Returns the given string.",
+    ),
+    spec(
+        "auth",
+        -2,
+        &["noscript", "loading", "stale", "fast", "no_auth"],
+        &["connection"],
+        Keys::None,
+        "Authenticates the connection.",
+    ),
+    spec(
+        "hello",
+        -1,
+        &["noscript", "loading", "stale", "fast", "no_auth", "allow_busy"],
+        &["connection"],
+        Keys::None,
+        "Handshakes with the Redis server.",
+    ),
+    spec(
+        "reset",
+        1,
+        &["noscript", "loading", "stale", "fast", "no_auth", "allow_busy"],
+        &["connection"],
+        Keys::None,
+        "Resets the connection.",
+    ),
+    spec(
+        "select",
+        2,
+        CONNECTION,
+        &["connection"],
+        Keys::None,
+        "Changes the selected database.",
+    ),
+    spec(
+        "wait",
+        3,
+        &["noscript"],
+        &["connection"],
+        Keys::None,
+        "Blocks until the asynchronous replication of all preceding write commands sent by the connection is completed.",
+    ),
+    spec(
+        "command",
+        -1,
+        &["loading", "stale"],
+        &["connection"],
+        Keys::None,
+        "Returns detailed information about all commands.",
+    ),
+    spec(
+        "get",
+        2,
+        FAST_READ,
+        &["read", "string"],
+        FIRST_KEY,
+        "Returns the string value of a key.",
+    ),
+    spec(
+        "set",
+        -3,
+        WRITE,
+        &["write", "string"],
+        FIRST_KEY,
+        "Sets the string value of a key, ignoring its type. The key is created if it doesn't exist.",
+    ),
+    spec(
+        "setbit",
+        4,
+        WRITE,
+        WRITE_BITMAP,
+        FIRST_KEY,
+        "Sets or clears the bit at offset of the string value. Creates the key if it doesn't exist.",
+    ),
+    spec(
+        "getbit",
+        3,
+        FAST_READ,
+        READ_BITMAP,
+        FIRST_KEY,
+        "Returns a bit value by offset.",
+    ),
+    spec(
+        "bitcount",
+        -2,
+        READ,
+        READ_BITMAP,
+        FIRST_KEY,
+        "Counts the number of set bits (population counting) in a string.",
+    ),
+    spec(
+        "bitop",
+        -4,
+        WRITE,
+        WRITE_BITMAP,
+        Keys::Range(2, -1, 1),
+        "Performs bitwise operations on multiple strings, and stores the result.",
+    ),
+    spec(
+        "bitpos",
+        -3,
+        READ,
+        READ_BITMAP,
+        FIRST_KEY,
+        "Finds the first set (1) or clear (0) bit in a string.",
+    ),
+    spec(
+        "pfadd",
+        -2,
+        FAST_WRITE,
+        WRITE_HLL,
+        FIRST_KEY,
+        "Adds elements to a HyperLogLog key. Creates the key if it doesn't exist.",
+    ),
+    spec(
+        "pfcount",
+        -2,
+        READ,
+        READ_HLL,
+        Keys::Range(1, -1, 1),
+        "Returns the approximated cardinality of the set(s) observed by the HyperLogLog key(s).",
+    ),
+    spec(
+        "pfmerge",
+        -2,
+        WRITE,
+        WRITE_HLL,
+        Keys::Range(1, -1, 1),
+        "Merges one or more HyperLogLog values into a single key.",
+    ),
+    spec(
+        "del",
+        -2,
+        &["write"],
+        &["keyspace", "write"],
+        Keys::Range(1, -1, 1),
+        "Deletes one or more keys.",
+    ),
+    spec(
+        "pexpireat",
+        3,
+        FAST_REMOVE,
+        &["keyspace", "write"],
+        FIRST_KEY,
+        "Sets the expiration time of a key to a Unix milliseconds timestamp.",
+    ),
+    spec(
+        "persist",
+        2,
+        FAST_REMOVE,
+        &["keyspace", "write"],
+        FIRST_KEY,
+        "Removes the expiration time of a key.",
+    ),
+    spec(
+        "move",
+        3,
+        FAST_REMOVE,
+        &["keyspace", "write"],
+        FIRST_KEY,
+        "Moves a key to another database.",
+    ),
+    spec(
+        "dump",
+        2,
+        READ,
+        &["keyspace", "read"],
+        FIRST_KEY,
+        "Returns a serialized representation of the value stored at a key.",
+    ),
+    spec(
+        "restore",
+        -4,
+        WRITE,
+        &["keyspace", "write", "dangerous"],
+        FIRST_KEY,
+        "Creates a key from the serialized representation of a value.",
+    ),
+    spec(
+        "migrate",
+        -6,
+        &["write"],
+        &["keyspace", "write", "dangerous"],
+        Keys::Range(3, 3, 1),
+        "Atomically transfers a key from one Redis instance to another.",
+    ),
+    spec(
+        "scan",
+        -2,
+        READ,
+        &["keyspace", "read"],
+        Keys::None,
+        "Iterates over the key names in the database.",
+    ),
+    spec(
+        "swapdb",
+        3,
+        FAST_REMOVE,
+        &["keyspace", "write", "dangerous"],
+        Keys::None,
+        "Swaps two Redis databases.",
+    ),
+    spec(
+        "zadd",
+        -4,
+        FAST_WRITE,
+        WRITE_ZSET,
+        FIRST_KEY,
+        "Adds one or more members to a sorted set, or updates their scores. Creates the key if it doesn't exist.",
+    ),
+    spec(
+        "zincrby",
+        4,
+        FAST_WRITE,
+        WRITE_ZSET,
+        FIRST_KEY,
+        "Increments the score of a member in a sorted set.",
+    ),
+    spec(
+        "zpopmin",
+        -2,
+        FAST_REMOVE,
+        WRITE_ZSET,
+        FIRST_KEY,
+        "Returns the lowest-scoring members from a sorted set after removing them. Deletes the sorted set if the last member was popped.",
+    ),
+    spec(
+        "zpopmax",
+        -2,
+        FAST_REMOVE,
+        WRITE_ZSET,
+        FIRST_KEY,
+        "Returns the highest-scoring members from a sorted set after removing them. Deletes the sorted set if the last member was popped.",
+    ),
+    spec(
+        "bzpopmin",
+        -3,
+        BLOCKING_REMOVE,
+        &["write", "sortedset", "blocking"],
+        Keys::Range(1, -2, 1),
+        "Removes and returns the member with the lowest score from one or more sorted sets. Blocks until a member is available otherwise.",
+    ),
+    spec(
+        "bzpopmax",
+        -3,
+        BLOCKING_REMOVE,
+        &["write", "sortedset", "blocking"],
+        Keys::Range(1, -2, 1),
+        "Removes and returns the member with the highest score from one or more sorted sets. Blocks until a member is available otherwise.",
+    ),
+    spec(
+        "zunionstore",
+        -4,
+        WRITE,
+        WRITE_ZSET,
+        Keys::Counted { dest: true },
+        "Stores the union of multiple sorted sets in a key.",
+    ),
+    spec(
+        "zinterstore",
+        -4,
+        WRITE,
+        WRITE_ZSET,
+        Keys::Counted { dest: true },
+        "Stores the intersect of multiple sorted sets in a key.",
+    ),
+    spec(
+        "zdiffstore",
+        -4,
+        WRITE,
+        WRITE_ZSET,
+        Keys::Counted { dest: true },
+        "Stores the difference of multiple sorted sets in a key.",
+    ),
+    spec(
+        "zrangestore",
+        -5,
+        WRITE,
+        WRITE_ZSET,
+        Keys::Range(1, 2, 1),
+        "Stores a range of members from sorted set in a key.",
+    ),
+    spec(
+        "zcard",
+        2,
+        FAST_READ,
+        READ_ZSET,
+        FIRST_KEY,
+        "Returns the number of members in a sorted set.",
+    ),
+    spec(
+        "zcount",
+        4,
+        FAST_READ,
+        READ_ZSET,
+        FIRST_KEY,
+        "Returns the count of members in a sorted set that have scores within a range.",
+    ),
+    spec(
+        "zlexcount",
+        4,
+        FAST_READ,
+        READ_ZSET,
+        FIRST_KEY,
+        "Returns the number of members in a sorted set within a lexicographical range.",
+    ),
+    spec(
+        "zrange",
+        -4,
+        READ,
+        READ_ZSET,
+        FIRST_KEY,
+        "Returns members in a sorted set within a range of indexes.",
+    ),
+    spec(
+        "zscan",
+        -3,
+        READ,
+        READ_ZSET,
+        FIRST_KEY,
+        "Iterates over members and scores of a sorted set.",
+    ),
+    spec(
+        "geoadd",
+        -5,
+        WRITE,
+        WRITE_GEO,
+        FIRST_KEY,
+        "Adds one or more members to a geospatial index. The key is created if it doesn't exist.",
+    ),
+    spec(
+        "geopos",
+        -2,
+        READ,
+        READ_GEO,
+        FIRST_KEY,
+        "Returns the longitude and latitude of members from a geospatial index.",
+    ),
+    spec(
+        "geodist",
+        -4,
+        READ,
+        READ_GEO,
+        FIRST_KEY,
+        "Returns the distance between two members of a geospatial index.",
+    ),
+    spec(
+        "geosearch",
+        -7,
+        READ,
+        READ_GEO,
+        FIRST_KEY,
+        "Queries a geospatial index for members inside an area of a box or a circle.",
+    ),
+    spec(
+        "xadd",
+        -5,
+        FAST_WRITE,
+        WRITE_STREAM,
+        FIRST_KEY,
+        "Appends a new message to a stream. Creates the key if it doesn't exist.",
+    ),
+    spec(
+        "xdel",
+        -3,
+        FAST_REMOVE,
+        WRITE_STREAM,
+        FIRST_KEY,
+        "Returns the number of messages after removing them from a stream.",
+    ),
+    spec(
+        "xtrim",
+        -4,
+        &["write"],
+        WRITE_STREAM,
+        FIRST_KEY,
+        "Deletes messages from the beginning of a stream.",
+    ),
+    spec(
+        "xack",
+        -4,
+        FAST_REMOVE,
+        WRITE_STREAM,
+        FIRST_KEY,
+        "Returns the number of messages that were successfully acknowledged by the consumer group member of a stream.",
+    ),
+    spec(
+        "xclaim",
+        -6,
+        FAST_REMOVE,
+        WRITE_STREAM,
+        FIRST_KEY,
+        "Changes, or acquires, ownership of a message in a consumer group, as if the message was delivered to a consumer group member.",
+    ),
+    spec(
+        "xautoclaim",
+        -6,
+        FAST_REMOVE,
+        WRITE_STREAM,
+        FIRST_KEY,
+        "Changes, or acquires, ownership of messages in a consumer group, as if the messages were delivered to a consumer group member.",
+    ),
+    spec(
+        "xgroup",
+        -2,
+        WRITE,
+        WRITE_STREAM,
+        Keys::Range(2, 2, 1),
+        "Creates, destroys and manages consumer groups and their consumers.",
+    ),
+    spec(
+        "xreadgroup",
+        -7,
+        &["write", "blocking"],
+        &["write", "stream", "blocking"],
+        Keys::Streams,
+        "Returns new or historical messages from a stream for a consumer in a group. Blocks until a message is available otherwise.",
+    ),
+    spec(
+        "xrange",
+        -4,
+        READ,
+        READ_STREAM,
+        FIRST_KEY,
+        "Returns the messages from a stream within a range of IDs.",
+    ),
+    spec(
+        "xrevrange",
+        -4,
+        READ,
+        READ_STREAM,
+        FIRST_KEY,
+        "Returns the messages from a stream within a range of IDs in reverse order.",
+    ),
+    spec(
+        "xlen",
+        2,
+        FAST_READ,
+        READ_STREAM,
+        FIRST_KEY,
+        "Return the number of messages in a stream.",
+    ),
+    spec(
+        "xpending",
+        -3,
+        READ,
+        READ_STREAM,
+        FIRST_KEY,
+        "Returns the information and entries from a stream consumer group's pending entries list.",
+    ),
+    spec(
+        "subscribe",
+        -2,
+        &["pubsub", "noscript", "loading", "stale"],
+        &["pubsub"],
+        Keys::None,
+        "Listens for messages published to channels.",
+    ),
+    spec(
+        "unsubscribe",
+        -1,
+        &["pubsub", "noscript", "loading", "stale"],
+        &["pubsub"],
+        Keys::None,
+        "Stops listening to messages posted to channels.",
+    ),
+    spec(
+        "publish",
+        3,
+        &["pubsub", "loading", "stale", "fast", "may_replicate"],
+        &["pubsub"],
+        Keys::None,
+        "Posts a message to a channel.",
+    ),
+    spec(
+        "multi",
+        1,
+        TRANSACTION,
+        &["transaction"],
+        Keys::None,
+        "Starts a transaction.",
+    ),
+    spec(
+        "exec",
+        1,
+        &["noscript", "loading", "stale", "skip_slowlog"],
+        &["transaction"],
+        Keys::None,
+        "Executes all commands in a transaction.",
+    ),
+    spec(
+        "discard",
+        1,
+        TRANSACTION,
+        &["transaction"],
+        Keys::None,
+        "Discards a transaction.",
+    ),
+    spec(
+        "watch",
+        -2,
+        TRANSACTION,
+        &["transaction"],
+        Keys::Range(1, -1, 1),
+        "Monitors changes to keys to determine the execution of a transaction.",
+    ),
+    spec(
+        "unwatch",
+        1,
+        TRANSACTION,
+        &["transaction"],
+        Keys::None,
+        "Forgets about watched keys of a transaction.",
+    ),
+    spec(
+        "eval",
+        -3,
+        SCRIPT,
+        &["scripting"],
+        Keys::Counted { dest: false },
+        "Executes a server-side Lua script.",
+    ),
+    spec(
+        "evalsha",
+        -3,
+        SCRIPT,
+        &["scripting"],
+        Keys::Counted { dest: false },
+        "Executes a server-side Lua script by SHA1 digest.",
+    ),
+    spec(
+        "fcall",
+        -3,
+        SCRIPT,
+        &["scripting"],
+        Keys::Counted { dest: false },
+        "Invokes a function.",
+    ),
+    spec(
+        "script",
+        -2,
+        &["noscript"],
+        &["scripting"],
+        Keys::None,
+        "Manages the server-side Lua script cache.",
+    ),
+    spec(
+        "function",
+        -2,
+        &["noscript"],
+        &["scripting"],
+        Keys::None,
+        "Creates, deletes and lists the server's function libraries.",
+    ),
+    spec(
+        "info",
+        -1,
+        &["loading", "stale"],
+        &["dangerous"],
+        Keys::None,
+        "Returns information and statistics about the server.",
+    ),
+    spec(
+        "replconf",
+        -1,
+        &["admin", "noscript", "loading", "stale", "allow_busy"],
+        ADMIN,
+        Keys::None,
+        "An internal command for configuring the replication stream.",
+    ),
+    spec(
+        "psync",
+        -3,
+        &["admin", "noscript", "no_async_loading", "no_multi"],
+        ADMIN,
+        Keys::None,
+        "An internal command used in replication.",
+    ),
+    spec(
+        "replicaof",
+        3,
+        &["admin", "noscript", "stale", "no_async_loading"],
+        ADMIN,
+        Keys::None,
+        "Configures a server as replica of another, or promotes it to a master.",
+    ),
+    spec(
+        "failover",
+        -1,
+        &["admin", "noscript", "stale"],
+        ADMIN,
+        Keys::None,
+        "Starts a coordinated failover from a server to one of its replicas.",
+    ),
+    spec(
+        "sentinel",
+        -2,
+        &["admin", "noscript", "stale", "loading"],
+        ADMIN,
+        Keys::None,
+        "Reports on the master a sentinel watches and its replicas.",
+    ),
+    spec(
+        "slaveof",
+        3,
+        &["admin", "noscript", "stale", "no_async_loading"],
+        ADMIN,
+        Keys::None,
+        "Sets a Redis server as a replica of another, or promotes it to being a master.",
+    ),
+    spec(
+        "config",
+        -2,
+        SERVER,
+        ADMIN,
+        Keys::None,
+        "Gets and sets the server's configuration parameters.",
+    ),
+    spec(
+        "save",
+        1,
+        &["admin", "noscript", "no_async_loading", "no_multi"],
+        ADMIN,
+        Keys::None,
+        "Synchronously saves the database(s) to disk.",
+    ),
+    spec(
+        "bgsave",
+        -1,
+        &["admin", "noscript", "no_async_loading"],
+        ADMIN,
+        Keys::None,
+        "Asynchronously saves the database(s) to disk.",
+    ),
+    spec(
+        "lastsave",
+        1,
+        &["loading", "stale", "fast"],
+        ADMIN,
+        Keys::None,
+        "Returns the Unix timestamp of the last successful save to disk.",
+    ),
+    spec(
+        "bgrewriteaof",
+        1,
+        &["admin", "noscript", "no_async_loading"],
+        ADMIN,
+        Keys::None,
+        "Asynchronously rewrites the append-only file to disk.",
+    ),
+    spec(
+        "shutdown",
+        -1,
+        &["admin", "noscript", "loading", "stale", "no_multi", "allow_busy"],
+        ADMIN,
+        Keys::None,
+        "Synchronously saves the database(s) to disk and shuts down the Redis server.",
+    ),
+    spec(
+        "memory",
+        -2,
+        READ,
+        &["read"],
+        Keys::Range(2, 2, 1),
+        "Reports how much memory the server and its keys use.",
+    ),
+    spec(
+        "latency",
+        -2,
+        &["admin", "noscript", "loading", "stale"],
+        ADMIN,
+        Keys::None,
+        "Reports the latency spikes the latency monitor has recorded.",
+    ),
+    spec(
+        "debug",
+        -2,
+        &["admin", "noscript", "loading", "stale", "protected"],
+        ADMIN,
+        Keys::None,
+        "A container for debugging commands.",
+    ),
+    spec(
+        "acl",
+        -2,
+        SERVER,
+        ADMIN,
+        Keys::None,
+        "Manages the server's users and what they may do.",
+    ),
+    spec(
+        "cluster",
+        -2,
+        &["loading", "stale"],
+        &[],
+        Keys::None,
+        "Reports on the cluster this node belongs to.",
+    ),
+    spec(
+        "cluster|meet",
+        -4,
+        &["admin", "stale"],
+        ADMIN,
+        Keys::None,
+        "Forces a node to handshake with another node.",
+    ),
+    spec(
+        "cluster|bumpepoch",
+        2,
+        &["admin", "stale"],
+        ADMIN,
+        Keys::None,
+        "Advances the cluster config epoch.",
+    ),
+    spec(
+        "cluster|setslot",
+        -4,
+        &["admin", "stale"],
+        ADMIN,
+        Keys::None,
+        "Binds a hash slot to a node.",
+    ),
+    spec(
+        "asking",
+        1,
+        CONNECTION,
+        &["connection"],
+        Keys::None,
+        "Signals that a cluster client is following an -ASK redirect.",
+    ),
+    spec(
+        "readonly",
+        1,
+        CONNECTION,
+        &["connection"],
+        Keys::None,
+        "Enables read-only queries for a connection to a Redis Cluster replica node.",
+    ),
+    spec(
+        "readwrite",
+        1,
+        CONNECTION,
+        &["connection"],
+        Keys::None,
+        "Enables read-write queries for a connection to a Redis Cluster replica node.",
+    ),
+    spec(
+        "client",
+        -2,
+        SERVER,
+        ADMIN,
+        Keys::None,
+        "Inspects and manages client connections.",
+    ),
+    // outside admin, so taking that category away leaves these
+    spec(
+        "acl|whoami",
+        2,
+        &["noscript", "loading", "stale"],
+        &[],
+        Keys::None,
+        "Returns the authenticated username of the current connection.",
+    ),
+    spec(
+        "acl|cat",
+        -2,
+        &["noscript", "loading", "stale"],
+        &[],
+        Keys::None,
+        "Lists the ACL categories, or the commands inside a category.",
+    ),
+    spec(
+        "client|info",
+        2,
+        &["noscript", "loading", "stale"],
+        &["connection"],
+        Keys::None,
+        "Returns information about the connection.",
+    ),
+    spec(
+        "client|id",
+        2,
+        &["noscript", "loading", "stale"],
+        &["connection"],
+        Keys::None,
+        "Returns the unique client ID of the connection.",
+    ),
+    spec(
+        "client|setname",
+        3,
+        &["noscript", "loading", "stale"],
+        &["connection"],
+        Keys::None,
+        "Sets the connection name.",
+    ),
+    spec(
+        "client|getname",
+        2,
+        &["noscript", "loading", "stale"],
+        &["connection"],
+        Keys::None,
+        "Returns the name of the connection.",
+    ),
+    spec(
+        "client|no-touch",
+        3,
+        &["noscript", "loading", "stale"],
+        &["connection"],
+        Keys::None,
+        "Controls whether commands sent by the client affect the LRU/LFU of accessed keys.",
+    ),
+    spec(
+        "client|tracking",
+        -3,
+        &["noscript", "loading", "stale"],
+        &["connection"],
+        Keys::None,
+        "Controls server-assisted client-side caching for the connection.",
+    ),
+    spec(
+        "client|getredir",
+        2,
+        &["noscript", "loading", "stale"],
+        &["connection"],
+        Keys::None,
+        "Returns the client ID to which the connection's tracking notifications are redirected.",
+    ),
+];
+
+/// What HELP lists for each container command: a subcommand with its
+/// arguments, and what it does.
+const USAGE: &[(&str, &[(&str, &str)])] = &[
+    (
+        "acl",
+        &[
+            (
+                "CAT [<category>]",
+                "List the ACL categories, or the commands inside <category>.",
+            ),
+            (
+                "DELUSER <username> [<username> ...]",
+                "Delete a list of users.",
+            ),
+            ("GETUSER <username>", "Get the user's details."),
+            ("LIST", "Show users details in config file format."),
+            ("LOAD", "Reload users from the ACL file."),
+            ("SAVE", "Save the current config to the ACL file."),
+            (
+                "SETUSER <username> <attribute> [<attribute> ...]",
+                "Create or modify a user with the specified attributes.",
+            ),
+            ("USERS", "List all the registered usernames."),
+            ("WHOAMI", "Return the current connection username."),
+        ],
+    ),
+    (
+        "client",
+        &[
+            ("GETNAME", "Return the name of the current connection."),
+            (
+                "GETREDIR",
+                "Return the client ID we are redirecting to when tracking is enabled.",
+            ),
+            ("ID", "Return the ID of the current connection."),
+            (
+                "INFO",
+                "Return information about the current client connection.",
+            ),
+            (
+                "KILL <option> <value> [<option> <value> [...]]",
+                "Kill connections. Options are ID, ADDR, LADDR and SKIPME (YES|NO).",
+            ),
+            (
+                "LIST [ID <id> [<id> ...]]",
+                "Return information about client connections.",
+            ),
+            (
+                "NO-EVICT (ON|OFF)",
+                "Protect the current client connection from eviction.",
+            ),
+            (
+                "NO-TOUCH (ON|OFF)",
+                "Will not touch LRU/LFU stats when this mode is on.",
+            ),
+            (
+                "PAUSE <timeout> [WRITE|ALL]",
+                "Suspend all, or just write, clients for <timeout> milliseconds.",
+            ),
+            (
+                "REPLY (ON|OFF|SKIP)",
+                "Control the replies sent to the current connection.",
+            ),
+            (
+                "SETNAME <name>",
+                "Assign the name <name> to the current connection.",
+            ),
+            (
+                "TRACKING (ON|OFF) [REDIRECT <id>] [BCAST] [PREFIX <prefix> [...]] [NOLOOP]",
+                "Control server assisted client side caching.",
+            ),
+            (
+                "UNPAUSE",
+                "Stop the current client pause, resuming traffic.",
+            ),
+        ],
+    ),
+    (
+        "cluster",
+        &[
+            ("BUMPEPOCH", "Advance the cluster config epoch."),
+            (
+                "COUNTKEYSINSLOT <slot>",
+                "Return the number of keys in <slot>.",
+            ),
+            (
+                "GETKEYSINSLOT <slot> <count>",
+                "Return key names stored by current node in a slot.",
+            ),
+            ("INFO", "Return information about the cluster."),
+            ("KEYSLOT <key>", "Return the hash slot for <key>."),
+            (
+                "MEET <ip> <port> [<bus-port>]",
+                "Connect nodes into a working cluster.",
+            ),
+            ("MYID", "Return the node id."),
+            ("NODES", "Return cluster configuration seen by node."),
+            (
+                "SETSLOT <slot> (IMPORTING <node-id>|MIGRATING <node-id>|STABLE|NODE <node-id>)",
+                "Set slot state.",
+            ),
+            (
+                "SHARDS",
+                "Return information about slot range mappings and the nodes associated with them.",
+            ),
+            ("SLOTS", "Return information about slots range mappings."),
+        ],
+    ),
+    (
+        "command",
+        &[
+            (
+                "(no subcommand)",
+                "Return details about all Redis commands.",
+            ),
+            (
+                "COUNT",
+                "Return the total number of commands in this Redis server.",
+            ),
+            (
+                "DOCS [<command-name> ...]",
+                "Return documentary information about commands.",
+            ),
+            (
+                "INFO [<command-name> ...]",
+                "Return details about multiple Redis commands.",
+            ),
+        ],
+    ),
+    (
+        "config",
+        &[
+            (
+                "GET <pattern> [<pattern> ...]",
+                "Return parameters matching the glob-like <pattern> and their values.",
+            ),
+            (
+                "SET <directive> <value> [<directive> <value> ...]",
+                "Set the configuration <directive> to <value>.",
+            ),
+        ],
+    ),
+    (
+        "debug",
+        &[
+            (
+                "CHANGE-REPL-ID",
+                "Change the replication IDs of the instance.",
+            ),
+            (
+                "OBJECT <key>",
+                "Show low level info about the <key> and associated value.",
+            ),
+            (
+                "QUICKLIST-PACKED-THRESHOLD <size>",
+                "Sets the threshold for elements to be inserted as plain vs packed nodes.",
+            ),
+            (
+                "RELOAD [NOSAVE]",
+                "Save the RDB on disk and reload it back to memory.",
+            ),
+            (
+                "SET-ACTIVE-EXPIRE <0|1>",
+                "Setting it to 0 disables expiring keys in background when they are not accessed.",
+            ),
+            (
+                "SLEEP <seconds>",
+                "Stop the server for <seconds>. Decimals allowed.",
+            ),
+        ],
+    ),
+    (
+        "function",
+        &[
+            ("DELETE <library-name>", "Delete the given library."),
+            (
+                "DUMP",
+                "Return a serialized payload representing the current libraries.",
+            ),
+            ("FLUSH [ASYNC|SYNC]", "Delete all the libraries."),
+            ("KILL", "Kill a function that is currently executing."),
+            (
+                "LIST [WITHCODE] [LIBRARYNAME <pattern>]",
+                "Return general information on all the libraries.",
+            ),
+            (
+                "LOAD [REPLACE] <function-code>",
+                "Create a new library with the given library name and code.",
+            ),
+        ],
+    ),
+    (
+        "latency",
+        &[
+            ("DOCTOR", "Return a human readable latency analysis report."),
+            (
+                "HISTORY <event>",
+                "Return time-latency samples for the <event> class.",
+            ),
+            (
+                "LATEST",
+                "Return the latest latency samples for all events.",
+            ),
+            (
+                "RESET [<event> ...]",
+                "Reset latency data of one or more <event> classes.",
+            ),
+        ],
+    ),
+    (
+        "memory",
+        &[
+            (
+                "STATS",
+                "Return information about the memory usage of the server.",
+            ),
+            (
+                "USAGE <key> [SAMPLES <count>]",
+                "Return memory in bytes used by <key> and its value.",
+            ),
+        ],
+    ),
+    (
+        "script",
+        &[
+            (
+                "EXISTS <sha1> [<sha1> ...]",
+                "Return information about the existence of the scripts in the script cache.",
+            ),
+            ("FLUSH [ASYNC|SYNC]", "Flush the Lua scripts cache."),
+            ("KILL", "Kill the currently executing Lua script."),
+            (
+                "LOAD <script>",
+                "Load a script into the scripts cache without executing it.",
+            ),
+        ],
+    ),
+    (
+        "sentinel",
+        &[
+            (
+                "GET-MASTER-ADDR-BY-NAME <master-name>",
+                "Return the ip and port number of the master with that name.",
+            ),
+            (
+                "MASTER <master-name>",
+                "Show the state and info of the specified master.",
+            ),
+            (
+                "MASTERS",
+                "Show a list of monitored masters and their state.",
+            ),
+            (
+                "REPLICAS <master-name>",
+                "Show a list of replicas for this master and their state.",
+            ),
+        ],
+    ),
+    (
+        "xgroup",
+        &[
+            (
+                "CREATE <key> <groupname> <id|$> [MKSTREAM] [ENTRIESREAD <n>]",
+                "Create a new consumer group.",
+            ),
+            (
+                "CREATECONSUMER <key> <groupname> <consumer>",
+                "Create a new consumer in the specified group.",
+            ),
+            (
+                "DELCONSUMER <key> <groupname> <consumer>",
+                "Remove the specified consumer.",
+            ),
+            ("DESTROY <key> <groupname>", "Remove the specified group."),
+            (
+                "SETID <key> <groupname> <id|$> [ENTRIESREAD <n>]",
+                "Set the current group ID and entries_read counter.",
+            ),
+        ],
+    ),
+];
+
+/// The spec for `name`, which is lowercase and may be `name|subcommand`.
+pub(crate) fn find(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS.iter().find(|spec| spec.name == name)
+}
+
+/// The arguments of `request`, the command name first.
+pub(crate) fn request_args(request: &Frame) -> Vec<&[u8]> {
+    let Frame::Array(parts) = request else {
+        return vec![];
+    };
+    parts
+        .iter()
+        .filter_map(|part| match part {
+            Frame::Bulk(bytes) => Some(&bytes[..]),
+            Frame::Simple(s) => Some(s.as_bytes()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The spec for the command in `args`, or for its subcommand where that has
+/// one of its own.
+pub(crate) fn lookup(args: &[&[u8]]) -> Option<&'static CommandSpec> {
+    let name = String::from_utf8_lossy(args.first()?).to_lowercase();
+    args.get(1)
+        .and_then(|sub| {
+            find(&format!(
+                "{}|{}",
+                name,
+                String::from_utf8_lossy(sub).to_lowercase()
+            ))
+        })
+        .or_else(|| find(&name))
+}
+
+/// The keys `request` reads, if it only reads.
+pub(crate) fn read_keys(request: &Frame) -> Vec<&[u8]> {
+    let args = request_args(request);
+    match lookup(&args) {
+        Some(spec) if spec.flags.contains(&"readonly") => spec.keys.of(&args),
+        _ => vec![],
+    }
+}
+
+/// Whether `name` may grow the data, and so is refused when eviction can't
+/// bring it under `maxmemory`.
+pub(crate) fn denies_oom(name: &str) -> bool {
+    find(name).is_some_and(|spec| spec.flags.contains(&"denyoom"))
+}
+
+/// Whether `name` is flagged as taking constant or logarithmic time.
+pub(crate) fn is_fast(name: &str) -> bool {
+    find(name).is_some_and(|spec| spec.flags.contains(&"fast"))
+}
+
+/// Every command, leaving out the subcommands listed separately.
+pub(crate) fn commands() -> impl Iterator<Item = &'static CommandSpec> {
+    COMMANDS.iter().filter(|spec| !spec.name.contains('|'))
+}
+
+/// The subcommands of `command` that have their own specs.
+pub(crate) fn subcommands(command: &str) -> impl Iterator<Item = &'static CommandSpec> + '_ {
+    COMMANDS.iter().filter(move |spec| {
+        spec.name
+            .split_once('|')
+            .is_some_and(|(parent, _)| parent == command)
+    })
+}
+
+/// The HELP reply of container `command`, lowercase, listing its
+/// subcommands the way redis-cli prints them; None if it has no
+/// subcommands.
+pub(crate) fn help(command: &str) -> Option<Frame> {
+    let (_, usage) = USAGE.iter().find(|(name, _)| *name == command)?;
+    let mut lines = vec![format!(
+        "{} <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+        command.to_uppercase()
+    )];
+    for (syntax, summary) in usage.iter().chain([&("HELP", "Print this help.")]) {
+        lines.push(syntax.to_string());
+        lines.push(format!("    {}", summary));
+    }
+    Some(Frame::Array(lines.into_iter().map(Frame::Simple).collect()))
+}
+
+/// The commands in `category`, or None if there is no such category.
+pub fn commands_in(category: &str) -> Option<Vec<&'static str>> {
+    let category = category.to_lowercase();
+    if !CATEGORIES.contains(&category.as_str()) {
+        return None;
+    }
+    let commands = COMMANDS
+        .iter()
+        .filter(|spec| spec.categories.contains(&category.as_str()))
+        .map(|spec| spec.name)
+        .collect();
+    Some(commands)
+}
+
+pub fn categories() -> &'static [&'static str] {
+    &CATEGORIES
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{command::Command, frame::Frame};
+    use bytes::Bytes;
+
+    #[test]
+    fn keys_are_found_by_command_shape() {
+        let args = |args: &[&'static str]| -> Vec<&'static [u8]> {
+            args.iter().map(|arg| arg.as_bytes()).collect()
+        };
+        let keys = |spec: Keys, request: &[&'static str]| spec.of(&args(request));
+        assert_eq!(
+            keys(Keys::Range(1, -2, 1), &["BZPOPMIN", "a", "b", "0"]),
+            args(&["a", "b"])
+        );
+        assert_eq!(
+            keys(
+                Keys::Counted { dest: true },
+                &["ZUNIONSTORE", "d", "2", "a", "b"]
+            ),
+            args(&["d", "a", "b"])
+        );
+        assert_eq!(
+            keys(
+                Keys::Streams,
+                &[
+                    "XREADGROUP",
+                    "GROUP",
+                    "g",
+                    "c",
+                    "STREAMS",
+                    "s",
+                    "t",
+                    ">",
+                    ">"
+                ]
+            ),
+            args(&["s", "t"])
+        );
+        assert_eq!(keys(Keys::Range(2, 2, 1), &["XGROUP", "HELP"]), args(&[]));
+    }
+
+    #[test]
+    fn help_lists_the_subcommands_with_their_own_specs() {
+        for spec in COMMANDS {
+            let Some((command, subcommand)) = spec.name.split_once('|') else {
+                continue;
+            };
+            let Some(Frame::Array(lines)) = help(command) else {
+                panic!("{} has no HELP", command);
+            };
+            assert!(
+                lines.iter().any(|line| matches!(line, Frame::Simple(line)
+                    if line.split(' ').next() == Some(&subcommand.to_uppercase()))),
+                "{} HELP leaves out {}",
+                command,
+                subcommand
+            );
+        }
+        assert!(help("get").is_none());
+    }
+
+    #[test]
+    fn every_command_in_the_table_is_dispatched() {
+        for spec in commands() {
+            let request = Frame::Array(vec![Frame::Bulk(Bytes::from(spec.name))]);
+            assert!(
+                !matches!(Command::from_frame(request), Ok(Command::Unknown(_))),
+                "{} is not dispatched",
+                spec.name
+            );
+        }
+    }
+}
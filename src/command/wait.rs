@@ -0,0 +1,58 @@
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::{error::Error, frame::Frame, parse::Parse, store::Store};
+
+/// WAIT numreplicas timeout
+#[derive(Debug)]
+pub struct Wait {
+    numreplicas: String,
+    timeout: String,
+    /// False inside a transaction, where the current count is returned straight away.
+    block: bool,
+}
+
+impl Wait {
+    pub fn new(numreplicas: String, timeout: String) -> Self {
+        Self {
+            numreplicas,
+            timeout,
+            block: true,
+        }
+    }
+
+    pub(crate) fn without_blocking(self) -> Self {
+        Self {
+            block: false,
+            ..self
+        }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Wait> {
+        let numreplicas = parse.next_string()?;
+        let timeout = parse.next_string()?;
+        Ok(Wait::new(numreplicas, timeout))
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let (Ok(numreplicas), Ok(timeout)) = (
+            self.numreplicas.parse::<usize>(),
+            self.timeout.parse::<i64>(),
+        ) else {
+            return Ok(Error::NotAnInteger.into());
+        };
+        let deadline = match timeout {
+            _ if !self.block => Some(Instant::now()),
+            timeout if timeout < 0 => {
+                return Ok(Frame::Error("ERR timeout is negative".to_string()));
+            }
+            0 => None,
+            timeout => Some(Instant::now() + Duration::from_millis(timeout as u64)),
+        };
+        let count = store
+            .replicas()
+            .wait_for_acks(numreplicas, deadline)
+            .await?;
+        Ok(Frame::Integer(count as u64))
+    }
+}
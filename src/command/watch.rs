@@ -0,0 +1,61 @@
+use bytes::Bytes;
+
+use crate::{
+    command::multi::Transaction,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    store::watch::WatchedKeys,
+};
+
+/// Makes the connection's next EXEC fail if any of `keys` change first.
+#[derive(Debug, Default)]
+pub struct Watch {
+    keys: Vec<Bytes>,
+}
+
+/// Forgets every watched key.
+#[derive(Debug, Default)]
+pub struct Unwatch;
+
+impl Watch {
+    pub fn new(keys: Vec<Bytes>) -> Self {
+        Self { keys }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<Watch> {
+        let mut keys = vec![parse.next_bytes()?];
+        loop {
+            match parse.next_bytes() {
+                Ok(key) => keys.push(key),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(Watch::new(keys))
+    }
+
+    /// Watches the keys in the client's selected database, `db`.
+    pub(crate) fn apply(
+        self,
+        db: usize,
+        transaction: &Option<Transaction>,
+        watched: &mut WatchedKeys,
+    ) -> Frame {
+        if transaction.is_some() {
+            return Frame::Error("ERR WATCH inside MULTI is not allowed".to_string());
+        }
+        watched.watch(db, self.keys);
+        Frame::OK
+    }
+}
+
+impl Unwatch {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> anyhow::Result<Unwatch> {
+        Ok(Unwatch)
+    }
+
+    pub(crate) fn apply(self, watched: &mut WatchedKeys) -> Frame {
+        watched.unwatch();
+        Frame::OK
+    }
+}
@@ -0,0 +1,76 @@
+use bytes::Bytes;
+
+use crate::{
+    frame::Frame,
+    parse::{Parse, ParseError},
+    publisher::{publish, Action},
+    store::{
+        stream::{StreamId, ERR_INVALID_ID},
+        Store,
+    },
+};
+
+#[derive(Debug, Default)]
+pub struct XAck {
+    key: Bytes,
+    group: Bytes,
+    ids: Vec<String>,
+}
+
+impl XAck {
+    pub fn new(key: Bytes, group: Bytes, ids: Vec<String>) -> Self {
+        Self { key, group, ids }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<XAck> {
+        let key = parse.next_bytes()?;
+        let group = parse.next_bytes()?;
+        let mut ids = vec![parse.next_string()?];
+        loop {
+            match parse.next_string() {
+                Ok(id) => ids.push(id),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(XAck::new(key, group, ids))
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let Some(ids) = self
+            .ids
+            .iter()
+            .map(|id| StreamId::parse(id, 0))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return Ok(Frame::Error(ERR_INVALID_ID.to_string()));
+        };
+
+        // a missing key or group acknowledges nothing
+        let acked = store.with_stream_mut(self.key.clone(), false, |stream| {
+            stream
+                .group_mut(&self.group)
+                .map_or(0, |group| ids.iter().filter(|id| group.ack(id)).count())
+        });
+        let response = match acked {
+            Ok(acked) => {
+                let acked = acked.unwrap_or_default();
+                if acked > 0 {
+                    publish(
+                        store,
+                        Action::XAck {
+                            key: self.key,
+                            group: self.group,
+                            ids,
+                        },
+                    )
+                    .await?;
+                }
+                Frame::Integer(acked as u64)
+            }
+            Err(e) => Frame::Error(e.to_string()),
+        };
+
+        Ok(response)
+    }
+}
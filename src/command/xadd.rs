@@ -0,0 +1,135 @@
+use anyhow::bail;
+use bytes::Bytes;
+
+use crate::{
+    command::xtrim::{trimmed_min_id, TrimArgs},
+    error::Error,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    publisher::{publish, Action},
+    store::{
+        notify::EventClass,
+        stream::{Fields, IdSpec, ERR_INVALID_ID},
+        Store,
+    },
+};
+
+#[derive(Debug, Default)]
+pub struct XAdd {
+    key: Bytes,
+    no_mkstream: bool,
+    trim: Option<TrimArgs>,
+    id: String,
+    fields: Fields,
+}
+
+impl XAdd {
+    pub fn new(
+        key: Bytes,
+        no_mkstream: bool,
+        trim: Option<TrimArgs>,
+        id: String,
+        fields: Fields,
+    ) -> Self {
+        Self {
+            key,
+            no_mkstream,
+            trim,
+            id,
+            fields,
+        }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<XAdd> {
+        let key = parse.next_bytes()?;
+        let mut no_mkstream = false;
+        let mut trim: Option<TrimArgs> = None;
+        let id = loop {
+            let arg = parse.next_string()?;
+            match arg.to_uppercase().as_str() {
+                "NOMKSTREAM" => no_mkstream = true,
+                "MAXLEN" | "MINID" => trim = Some(TrimArgs::parse_frames(&arg, parse)?),
+                "LIMIT" => match trim.as_mut() {
+                    Some(trim) => trim.parse_limit(parse)?,
+                    None => bail!(Error::Syntax),
+                },
+                _ => break arg,
+            }
+        };
+
+        let mut fields = vec![];
+        loop {
+            let field = match parse.next_bytes() {
+                Ok(field) => field,
+                Err(ParseError::EndOfStream) if !fields.is_empty() => break,
+                Err(ParseError::EndOfStream) => {
+                    bail!(Error::WrongArity("xadd".to_string()))
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let value = match parse.next_bytes() {
+                Ok(value) => value,
+                Err(ParseError::EndOfStream) => {
+                    bail!(Error::WrongArity("xadd".to_string()))
+                }
+                Err(e) => return Err(e.into()),
+            };
+            fields.push((field, value));
+        }
+
+        Ok(XAdd::new(key, no_mkstream, trim, id, fields))
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let trim = match self.trim.as_ref().map(TrimArgs::resolve).transpose() {
+            Ok(trim) => trim,
+            Err(e) => {
+                return Ok(Frame::Error(e.to_string()));
+            }
+        };
+        let Some(spec) = IdSpec::parse(&self.id) else {
+            return Ok(Frame::Error(ERR_INVALID_ID.to_string()));
+        };
+
+        let fields = self.fields.clone();
+        let added = store.with_stream_mut(self.key.clone(), !self.no_mkstream, |stream| {
+            let id = stream.add(spec, fields)?;
+            let trimmed = trim.map(|trim| stream.trim(&trim)).unwrap_or_default();
+            Ok::<_, &str>((id, trimmed, trimmed_min_id(stream)))
+        });
+        let response = match added {
+            Ok(Some(Ok((id, trimmed, min_id)))) => {
+                store.notify(EventClass::Stream, "xadd", &self.key);
+                if trimmed > 0 {
+                    store.notify(EventClass::Stream, "xtrim", &self.key);
+                }
+                // replicate the resolved ID so replicas store exactly the same entry
+                publish(
+                    store,
+                    Action::XAdd {
+                        key: self.key.clone(),
+                        id,
+                        fields: self.fields,
+                    },
+                )
+                .await?;
+                if trimmed > 0 {
+                    publish(
+                        store,
+                        Action::XTrim {
+                            key: self.key,
+                            min_id,
+                        },
+                    )
+                    .await?;
+                }
+                Frame::Bulk(id.to_string().into())
+            }
+            Ok(Some(Err(e))) => Frame::Error(e.to_string()),
+            Ok(None) => Frame::Null,
+            Err(e) => Frame::Error(e.to_string()),
+        };
+
+        Ok(response)
+    }
+}
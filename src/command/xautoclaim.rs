@@ -0,0 +1,135 @@
+use anyhow::bail;
+use bytes::Bytes;
+
+use crate::{
+    command::xclaim::{claimed_frame, no_group_error, publish_claims},
+    error::Error,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    publisher::{publish, Action},
+    store::{
+        consumer_group::ClaimOptions,
+        stream::{now_ms, StreamId},
+        Store,
+    },
+};
+
+const DEFAULT_COUNT: u64 = 100;
+
+#[derive(Debug, Default)]
+pub struct XAutoClaim {
+    key: Bytes,
+    group: Bytes,
+    consumer: Bytes,
+    min_idle: String,
+    start: String,
+    count: u64,
+    just_id: bool,
+}
+
+impl XAutoClaim {
+    pub fn new(key: Bytes, group: Bytes, consumer: Bytes, min_idle: String, start: String) -> Self {
+        Self {
+            key,
+            group,
+            consumer,
+            min_idle,
+            start,
+            count: DEFAULT_COUNT,
+            just_id: false,
+        }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<XAutoClaim> {
+        let key = parse.next_bytes()?;
+        let group = parse.next_bytes()?;
+        let consumer = parse.next_bytes()?;
+        let min_idle = parse.next_string()?;
+        let start = parse.next_string()?;
+        let mut cmd = XAutoClaim::new(key, group, consumer, min_idle, start);
+        loop {
+            match parse.next_string() {
+                Ok(option) if option.eq_ignore_ascii_case("COUNT") => {
+                    cmd.count = parse.next_int()?;
+                    if cmd.count == 0 {
+                        bail!("ERR COUNT must be > 0");
+                    }
+                }
+                Ok(option) if option.eq_ignore_ascii_case("JUSTID") => cmd.just_id = true,
+                Ok(_) => bail!(Error::Syntax),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(cmd)
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let Ok(min_idle) = self.min_idle.parse::<i64>() else {
+            return Ok(Frame::Error(
+                "ERR Invalid min-idle-time argument for XAUTOCLAIM".into(),
+            ));
+        };
+        let start = match StreamId::parse_bound(&self.start, true) {
+            Ok(start) => start,
+            Err(e) => {
+                return Ok(Frame::Error(e.to_string()));
+            }
+        };
+
+        let options = ClaimOptions {
+            min_idle: min_idle.max(0) as u64,
+            just_id: self.just_id,
+            ..Default::default()
+        };
+        let count = self.count as usize;
+        let result = store.with_stream_mut(self.key.clone(), false, |stream| {
+            stream.autoclaim(
+                &self.group,
+                &self.consumer,
+                start,
+                count,
+                &options,
+                now_ms(),
+            )
+        });
+        let response = match result {
+            Ok(Some(Some((next, claimed, deleted)))) => {
+                publish_claims(
+                    store,
+                    &self.key,
+                    &self.group,
+                    &self.consumer,
+                    &claimed,
+                    None,
+                )
+                .await?;
+                if !deleted.is_empty() {
+                    publish(
+                        store,
+                        Action::XAck {
+                            key: self.key.clone(),
+                            group: self.group.clone(),
+                            ids: deleted.clone(),
+                        },
+                    )
+                    .await?;
+                }
+                Frame::Array(vec![
+                    Frame::Bulk(next.to_string().into()),
+                    claimed_frame(claimed, self.just_id),
+                    Frame::Array(
+                        deleted
+                            .into_iter()
+                            .map(|id| Frame::Bulk(id.to_string().into()))
+                            .collect(),
+                    ),
+                ])
+            }
+            Ok(_) => no_group_error(&self.key, &self.group),
+            Err(e) => Frame::Error(e.to_string()),
+        };
+
+        Ok(response)
+    }
+}
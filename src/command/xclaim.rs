@@ -0,0 +1,186 @@
+use anyhow::bail;
+use bytes::Bytes;
+
+use crate::{
+    command::xrange::entries_frame,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    publisher::{publish, Action},
+    store::{
+        consumer_group::{ClaimOptions, Claimed},
+        stream::{now_ms, StreamId, ERR_INVALID_ID},
+        Store,
+    },
+};
+
+#[derive(Debug, Default)]
+pub struct XClaim {
+    key: Bytes,
+    group: Bytes,
+    consumer: Bytes,
+    min_idle: String,
+    ids: Vec<String>,
+    idle: Option<u64>,
+    time: Option<u64>,
+    retry_count: Option<u64>,
+    force: bool,
+    just_id: bool,
+    last_id: Option<String>,
+}
+
+impl XClaim {
+    pub fn new(
+        key: Bytes,
+        group: Bytes,
+        consumer: Bytes,
+        min_idle: String,
+        ids: Vec<String>,
+    ) -> Self {
+        Self {
+            key,
+            group,
+            consumer,
+            min_idle,
+            ids,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<XClaim> {
+        let key = parse.next_bytes()?;
+        let group = parse.next_bytes()?;
+        let consumer = parse.next_bytes()?;
+        let min_idle = parse.next_string()?;
+        let mut cmd = XClaim::new(key, group, consumer, min_idle, vec![parse.next_string()?]);
+        // IDs run until the first option
+        let mut options = false;
+        loop {
+            let arg = match parse.next_string() {
+                Ok(arg) => arg,
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            };
+            match arg.to_uppercase().as_str() {
+                "IDLE" => cmd.idle = Some(parse.next_int()?),
+                "TIME" => cmd.time = Some(parse.next_int()?),
+                "RETRYCOUNT" => cmd.retry_count = Some(parse.next_int()?),
+                "FORCE" => cmd.force = true,
+                "JUSTID" => cmd.just_id = true,
+                "LASTID" => cmd.last_id = Some(parse.next_string()?),
+                _ if !options => {
+                    cmd.ids.push(arg);
+                    continue;
+                }
+                _ => bail!("ERR Unrecognized XCLAIM option '{}'", arg),
+            }
+            options = true;
+        }
+        Ok(cmd)
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let Ok(min_idle) = self.min_idle.parse::<i64>() else {
+            return Ok(Frame::Error(
+                "ERR Invalid min-idle-time argument for XCLAIM".into(),
+            ));
+        };
+        let ids = self
+            .ids
+            .iter()
+            .map(|id| StreamId::parse(id, 0))
+            .collect::<Option<Vec<_>>>();
+        let last_id = self
+            .last_id
+            .as_deref()
+            .map(|id| StreamId::parse(id, 0).ok_or(ERR_INVALID_ID))
+            .transpose();
+        let (Some(ids), Ok(last_id)) = (ids, last_id) else {
+            return Ok(Frame::Error(ERR_INVALID_ID.to_string()));
+        };
+
+        let now = now_ms();
+        let options = ClaimOptions {
+            min_idle: min_idle.max(0) as u64,
+            delivery_time: self.time.or(self.idle.map(|idle| now.saturating_sub(idle))),
+            retry_count: self.retry_count,
+            force: self.force,
+            just_id: self.just_id,
+            last_id,
+        };
+        let claimed = store.with_stream_mut(self.key.clone(), false, |stream| {
+            stream.claim(&self.group, &self.consumer, &ids, &options, now)
+        });
+        let response = match claimed {
+            Ok(Some(Some(claimed))) => {
+                publish_claims(
+                    store,
+                    &self.key,
+                    &self.group,
+                    &self.consumer,
+                    &claimed,
+                    last_id,
+                )
+                .await?;
+                claimed_frame(claimed, self.just_id)
+            }
+            Ok(_) => no_group_error(&self.key, &self.group),
+            Err(e) => Frame::Error(e.to_string()),
+        };
+
+        Ok(response)
+    }
+}
+
+/// Encodes claimed entries in full, or just their IDs for JUSTID.
+pub(crate) fn claimed_frame(claimed: Vec<Claimed>, just_id: bool) -> Frame {
+    if just_id {
+        Frame::Array(
+            claimed
+                .into_iter()
+                .map(|claimed| Frame::Bulk(claimed.id.to_string().into()))
+                .collect(),
+        )
+    } else {
+        entries_frame(
+            claimed
+                .into_iter()
+                .map(|claimed| (claimed.id, claimed.fields)),
+        )
+    }
+}
+
+pub(crate) fn no_group_error(key: &Bytes, group: &Bytes) -> Frame {
+    Frame::Error(format!(
+        "NOGROUP No such key '{}' or consumer group '{}'",
+        String::from_utf8_lossy(key),
+        String::from_utf8_lossy(group)
+    ))
+}
+
+/// Replicates each ownership change so replicas end up with the same
+/// pending entries list.
+pub(crate) async fn publish_claims(
+    store: &Store,
+    key: &Bytes,
+    group: &Bytes,
+    consumer: &Bytes,
+    claimed: &[Claimed],
+    last_id: Option<StreamId>,
+) -> anyhow::Result<()> {
+    for claimed in claimed {
+        publish(
+            store,
+            Action::XClaim {
+                key: key.clone(),
+                group: group.clone(),
+                consumer: consumer.clone(),
+                id: claimed.id,
+                delivery_time: claimed.delivery_time,
+                delivery_count: claimed.delivery_count,
+                last_id,
+            },
+        )
+        .await?;
+    }
+    Ok(())
+}
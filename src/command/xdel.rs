@@ -0,0 +1,63 @@
+use bytes::Bytes;
+
+use crate::{
+    frame::Frame,
+    parse::{Parse, ParseError},
+    publisher::{publish, Action},
+    store::{
+        notify::EventClass,
+        stream::{StreamId, ERR_INVALID_ID},
+        Store,
+    },
+};
+
+#[derive(Debug, Default)]
+pub struct XDel {
+    key: Bytes,
+    ids: Vec<String>,
+}
+
+impl XDel {
+    pub fn new(key: Bytes, ids: Vec<String>) -> Self {
+        Self { key, ids }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<XDel> {
+        let key = parse.next_bytes()?;
+        let mut ids = vec![parse.next_string()?];
+        loop {
+            match parse.next_string() {
+                Ok(id) => ids.push(id),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(XDel::new(key, ids))
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let Some(ids) = self
+            .ids
+            .iter()
+            .map(|id| StreamId::parse(id, 0))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return Ok(Frame::Error(ERR_INVALID_ID.to_string()));
+        };
+
+        let deleted = store.with_stream_mut(self.key.clone(), false, |stream| stream.delete(&ids));
+        let response = match deleted {
+            Ok(Some(deleted)) => {
+                if deleted > 0 {
+                    store.notify(EventClass::Stream, "xdel", &self.key);
+                    publish(store, Action::XDel { key: self.key, ids }).await?;
+                }
+                Frame::Integer(deleted as u64)
+            }
+            Ok(None) => Frame::Integer(0),
+            Err(e) => Frame::Error(e.to_string()),
+        };
+
+        Ok(response)
+    }
+}
@@ -0,0 +1,163 @@
+use anyhow::bail;
+use bytes::Bytes;
+
+use crate::{
+    error::Error,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    publisher::{publish, Action},
+    store::{
+        notify::EventClass,
+        stream::{now_ms, Stream, StreamId, ERR_INVALID_ID},
+        Store,
+    },
+};
+
+const ERR_NO_KEY: &str = "ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.";
+const ERR_BUSY_GROUP: &str = "BUSYGROUP Consumer Group name already exists";
+
+#[derive(Debug)]
+pub enum XGroupOp {
+    Create { id: String, mkstream: bool },
+    SetId { id: String },
+    Destroy,
+    CreateConsumer(Bytes),
+    DelConsumer(Bytes),
+}
+
+impl XGroupOp {
+    fn subcommand(&self) -> &'static str {
+        match self {
+            XGroupOp::Create { .. } => "create",
+            XGroupOp::SetId { .. } => "setid",
+            XGroupOp::Destroy => "destroy",
+            XGroupOp::CreateConsumer(_) => "createconsumer",
+            XGroupOp::DelConsumer(_) => "delconsumer",
+        }
+    }
+}
+
+/// XGROUP CREATE / SETID / DESTROY / CREATECONSUMER / DELCONSUMER
+#[derive(Debug)]
+pub struct XGroup {
+    key: Bytes,
+    group: Bytes,
+    op: XGroupOp,
+}
+
+impl XGroup {
+    pub fn new(key: Bytes, group: Bytes, op: XGroupOp) -> Self {
+        Self { key, group, op }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<XGroup> {
+        let subcommand = parse.next_string()?;
+        let key = parse.next_bytes()?;
+        let group = parse.next_bytes()?;
+        let op = match subcommand.to_uppercase().as_str() {
+            "CREATE" | "SETID" => {
+                let id = parse.next_string()?;
+                let mut mkstream = false;
+                loop {
+                    match parse.next_string() {
+                        Ok(option) if option.eq_ignore_ascii_case("MKSTREAM") => mkstream = true,
+                        // we don't track entries-read, but accept it for compatibility
+                        Ok(option) if option.eq_ignore_ascii_case("ENTRIESREAD") => {
+                            parse.next_int()?;
+                        }
+                        Ok(_) => bail!(Error::Syntax),
+                        Err(ParseError::EndOfStream) => break,
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                if subcommand.eq_ignore_ascii_case("CREATE") {
+                    XGroupOp::Create { id, mkstream }
+                } else if mkstream {
+                    bail!(Error::Syntax)
+                } else {
+                    XGroupOp::SetId { id }
+                }
+            }
+            "DESTROY" => XGroupOp::Destroy,
+            "CREATECONSUMER" => XGroupOp::CreateConsumer(parse.next_bytes()?),
+            "DELCONSUMER" => XGroupOp::DelConsumer(parse.next_bytes()?),
+            _ => bail!("ERR unknown subcommand '{}'. Try XGROUP HELP.", subcommand),
+        };
+        Ok(XGroup::new(key, group, op))
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let create = matches!(self.op, XGroupOp::Create { mkstream: true, .. });
+        let applied = store.with_stream_mut(self.key.clone(), create, |stream| self.run(stream));
+        let response = match applied {
+            Ok(Some(Ok((response, replicate)))) => {
+                if let Some(action) = replicate {
+                    let event = format!("xgroup-{}", self.op.subcommand());
+                    store.notify(EventClass::Stream, &event, &self.key);
+                    publish(store, action).await?;
+                }
+                response
+            }
+            Ok(Some(Err(e))) => Frame::Error(e),
+            Ok(None) => Frame::Error(ERR_NO_KEY.to_string()),
+            Err(e) => Frame::Error(e.to_string()),
+        };
+
+        Ok(response)
+    }
+
+    /// Applies the subcommand, returning the reply and what to replicate.
+    fn run(&self, stream: &mut Stream) -> Result<(Frame, Option<Action>), String> {
+        let no_group = || {
+            format!(
+                "NOGROUP No such consumer group '{}' for key name '{}'",
+                String::from_utf8_lossy(&self.group),
+                String::from_utf8_lossy(&self.key)
+            )
+        };
+        let resolve = |stream: &Stream, id: &str| match id {
+            "$" => Ok(stream.last_id()),
+            _ => StreamId::parse(id, 0).ok_or(ERR_INVALID_ID.to_string()),
+        };
+        let replicate = |args: Vec<Bytes>| Action::XGroup {
+            subcommand: self.op.subcommand(),
+            key: self.key.clone(),
+            group: self.group.clone(),
+            args,
+        };
+
+        match &self.op {
+            XGroupOp::Create { id, .. } => {
+                let id = resolve(stream, id)?;
+                if !stream.create_group(self.group.clone(), id) {
+                    return Err(ERR_BUSY_GROUP.to_string());
+                }
+                let args = vec![id.to_string().into(), Bytes::from("MKSTREAM")];
+                Ok((Frame::OK, Some(replicate(args))))
+            }
+            XGroupOp::SetId { id } => {
+                let id = resolve(stream, id)?;
+                let group = stream.group_mut(&self.group).ok_or_else(no_group)?;
+                group.last_delivered = id;
+                Ok((Frame::OK, Some(replicate(vec![id.to_string().into()]))))
+            }
+            XGroupOp::Destroy => {
+                let destroyed = stream.destroy_group(&self.group);
+                let action = destroyed.then(|| replicate(vec![]));
+                Ok((Frame::Integer(destroyed as u64), action))
+            }
+            XGroupOp::CreateConsumer(consumer) => {
+                let group = stream.group_mut(&self.group).ok_or_else(no_group)?;
+                let created = group.create_consumer(consumer.clone(), now_ms());
+                let action = created.then(|| replicate(vec![consumer.clone()]));
+                Ok((Frame::Integer(created as u64), action))
+            }
+            XGroupOp::DelConsumer(consumer) => {
+                let group = stream.group_mut(&self.group).ok_or_else(no_group)?;
+                let pending = group.delete_consumer(consumer);
+                let action = Some(replicate(vec![consumer.clone()]));
+                Ok((Frame::Integer(pending as u64), action))
+            }
+        }
+    }
+}
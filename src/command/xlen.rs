@@ -0,0 +1,27 @@
+use bytes::Bytes;
+
+use crate::{frame::Frame, parse::Parse, store::Store};
+
+#[derive(Debug, Default)]
+pub struct XLen {
+    key: Bytes,
+}
+
+impl XLen {
+    pub fn new(key: Bytes) -> Self {
+        Self { key }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<XLen> {
+        let key = parse.next_bytes()?;
+        Ok(XLen::new(key))
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let response = match store.with_stream(&self.key, |stream| stream.len()) {
+            Ok(len) => Frame::Integer(len as u64),
+            Err(e) => Frame::Error(e.to_string()),
+        };
+        Ok(response)
+    }
+}
@@ -0,0 +1,156 @@
+use bytes::Bytes;
+
+use crate::{
+    command::xclaim::no_group_error,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    store::{
+        consumer_group::ConsumerGroup,
+        stream::{now_ms, StreamId},
+        Store,
+    },
+};
+
+/// The extended form's filters: `[IDLE min-idle-time] start end count [consumer]`.
+#[derive(Debug, Default)]
+pub struct PendingRange {
+    idle: Option<u64>,
+    start: String,
+    end: String,
+    count: u64,
+    consumer: Option<Bytes>,
+}
+
+#[derive(Debug, Default)]
+pub struct XPending {
+    key: Bytes,
+    group: Bytes,
+    range: Option<PendingRange>,
+}
+
+impl XPending {
+    pub fn new(key: Bytes, group: Bytes, range: Option<PendingRange>) -> Self {
+        Self { key, group, range }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<XPending> {
+        let key = parse.next_bytes()?;
+        let group = parse.next_bytes()?;
+        let mut start = match parse.next_string() {
+            Ok(start) => start,
+            Err(ParseError::EndOfStream) => return Ok(XPending::new(key, group, None)),
+            Err(e) => return Err(e.into()),
+        };
+        let mut idle = None;
+        if start.eq_ignore_ascii_case("IDLE") {
+            idle = Some(parse.next_int()?);
+            start = parse.next_string()?;
+        }
+        let end = parse.next_string()?;
+        let count = parse.next_int()?;
+        let consumer = match parse.next_bytes() {
+            Ok(consumer) => Some(consumer),
+            Err(ParseError::EndOfStream) => None,
+            Err(e) => return Err(e.into()),
+        };
+        let range = PendingRange {
+            idle,
+            start,
+            end,
+            count,
+            consumer,
+        };
+        Ok(XPending::new(key, group, Some(range)))
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let bounds = match &self.range {
+            Some(range) => StreamId::parse_bound(&range.start, true)
+                .and_then(|start| Ok((start, StreamId::parse_bound(&range.end, false)?))),
+            None => Ok((StreamId::MIN, StreamId::MAX)),
+        };
+        let (start, end) = match bounds {
+            Ok(bounds) => bounds,
+            Err(e) => {
+                return Ok(Frame::Error(e.to_string()));
+            }
+        };
+
+        let result = store.with_stream(&self.key, |stream| {
+            stream.group(&self.group).map(|group| match &self.range {
+                Some(range) => extended(group, range, start, end),
+                None => summary(group),
+            })
+        });
+        let response = match result {
+            Ok(Some(response)) => response,
+            Ok(None) => no_group_error(&self.key, &self.group),
+            Err(e) => Frame::Error(e.to_string()),
+        };
+        Ok(response)
+    }
+}
+
+/// Pending count, lowest and highest pending IDs, and per-consumer counts.
+fn summary(group: &ConsumerGroup) -> Frame {
+    let pending = group.pending();
+    let (Some((first, _)), Some((last, _))) = (pending.first_key_value(), pending.last_key_value())
+    else {
+        return Frame::Array(vec![
+            Frame::Integer(0),
+            Frame::Null,
+            Frame::Null,
+            Frame::NullArray,
+        ]);
+    };
+    let consumers = group
+        .consumers()
+        .iter()
+        .filter(|(_, consumer)| !consumer.pending.is_empty())
+        .map(|(name, consumer)| {
+            Frame::Array(vec![
+                Frame::Bulk(name.clone()),
+                Frame::Bulk(consumer.pending.len().to_string().into()),
+            ])
+        })
+        .collect();
+    Frame::Array(vec![
+        Frame::Integer(pending.len() as u64),
+        Frame::Bulk(first.to_string().into()),
+        Frame::Bulk(last.to_string().into()),
+        Frame::Array(consumers),
+    ])
+}
+
+/// One `[id, consumer, idle, delivery count]` row per matching pending entry.
+fn extended(group: &ConsumerGroup, range: &PendingRange, start: StreamId, end: StreamId) -> Frame {
+    if start > end {
+        return Frame::Array(vec![]);
+    }
+    let now = now_ms();
+    let rows = group
+        .pending()
+        .range(start..=end)
+        .filter(|(_, entry)| {
+            range
+                .consumer
+                .as_ref()
+                .is_none_or(|consumer| *consumer == entry.consumer)
+        })
+        .filter(|(_, entry)| {
+            range
+                .idle
+                .is_none_or(|idle| now.saturating_sub(entry.delivery_time) >= idle)
+        })
+        .take(range.count as usize)
+        .map(|(id, entry)| {
+            Frame::Array(vec![
+                Frame::Bulk(id.to_string().into()),
+                Frame::Bulk(entry.consumer.clone()),
+                Frame::Integer(now.saturating_sub(entry.delivery_time)),
+                Frame::Integer(entry.delivery_count),
+            ])
+        })
+        .collect();
+    Frame::Array(rows)
+}
@@ -0,0 +1,102 @@
+use anyhow::bail;
+use bytes::Bytes;
+
+use crate::{
+    error::Error,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    store::{
+        stream::{Fields, StreamId},
+        Store,
+    },
+};
+
+/// XRANGE / XREVRANGE
+#[derive(Debug, Default)]
+pub struct XRange {
+    key: Bytes,
+    start: String,
+    end: String,
+    count: Option<u64>,
+    rev: bool,
+}
+
+impl XRange {
+    pub fn new(key: Bytes, start: String, end: String, count: Option<u64>, rev: bool) -> Self {
+        Self {
+            key,
+            start,
+            end,
+            count,
+            rev,
+        }
+    }
+
+    pub(crate) fn parse_frames(rev: bool, parse: &mut Parse) -> anyhow::Result<XRange> {
+        let key = parse.next_bytes()?;
+        let (mut start, mut end) = (parse.next_string()?, parse.next_string()?);
+        if rev {
+            // XREVRANGE takes the end of the interval first
+            std::mem::swap(&mut start, &mut end);
+        }
+        let count = match parse.next_string() {
+            Ok(option) if option.eq_ignore_ascii_case("COUNT") => Some(parse.next_int()?),
+            Ok(_) => bail!(Error::Syntax),
+            Err(ParseError::EndOfStream) => None,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(XRange::new(key, start, end, count, rev))
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let bounds = StreamId::parse_bound(&self.start, true)
+            .and_then(|start| Ok((start, StreamId::parse_bound(&self.end, false)?)));
+        let (start, end) = match bounds {
+            Ok(bounds) => bounds,
+            Err(e) => {
+                return Ok(Frame::Error(e.to_string()));
+            }
+        };
+
+        let count = self.count.map_or(usize::MAX, |count| count as usize);
+        let entries = store.with_stream(&self.key, |stream| {
+            let range = stream
+                .range(start, end)
+                .map(|(id, fields)| (*id, fields.clone()));
+            if self.rev {
+                range.rev().take(count).collect::<Vec<_>>()
+            } else {
+                range.take(count).collect()
+            }
+        });
+        let response = match entries {
+            Ok(entries) => entries_frame(entries),
+            Err(e) => Frame::Error(e.to_string()),
+        };
+        Ok(response)
+    }
+}
+
+/// Encodes stream entries as an array of `[id, [field, value, ...]]` pairs.
+pub(crate) fn entries_frame(entries: impl IntoIterator<Item = (StreamId, Fields)>) -> Frame {
+    Frame::Array(
+        entries
+            .into_iter()
+            .map(|(id, fields)| entry_frame(id, Some(fields)))
+            .collect(),
+    )
+}
+
+/// Encodes one entry. `None` fields (an entry deleted while pending) become a null.
+pub(crate) fn entry_frame(id: StreamId, fields: Option<Fields>) -> Frame {
+    let fields = match fields {
+        Some(fields) => Frame::Array(
+            fields
+                .into_iter()
+                .flat_map(|(field, value)| [Frame::Bulk(field), Frame::Bulk(value)])
+                .collect(),
+        ),
+        None => Frame::NullArray,
+    };
+    Frame::Array(vec![Frame::Bulk(id.to_string().into()), fields])
+}
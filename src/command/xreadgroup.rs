@@ -0,0 +1,230 @@
+use anyhow::bail;
+use bytes::Bytes;
+use std::time::Duration;
+use tokio::time::{timeout_at, Instant};
+
+use crate::{
+    command::{
+        xclaim::{claimed_frame, publish_claims},
+        xrange::entry_frame,
+    },
+    error::Error,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    publisher::{publish, Action},
+    store::{
+        consumer_group::Claimed,
+        stream::{now_ms, StreamId, ERR_INVALID_ID},
+        Store,
+    },
+};
+
+/// Where to read from: entries never delivered to the group (`>`), or the
+/// consumer's own pending entries after an ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReadFrom {
+    New,
+    Pending(StreamId),
+}
+
+#[derive(Debug, Default)]
+pub struct XReadGroup {
+    group: Bytes,
+    consumer: Bytes,
+    count: Option<usize>,
+    block: Option<String>,
+    noack: bool,
+    keys: Vec<Bytes>,
+    ids: Vec<String>,
+}
+
+impl XReadGroup {
+    pub fn new(group: Bytes, consumer: Bytes, keys: Vec<Bytes>, ids: Vec<String>) -> Self {
+        Self {
+            group,
+            consumer,
+            keys,
+            ids,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<XReadGroup> {
+        if !parse.next_string()?.eq_ignore_ascii_case("GROUP") {
+            bail!(Error::Syntax);
+        }
+        let (group, consumer) = (parse.next_bytes()?, parse.next_bytes()?);
+        let (mut count, mut block, mut noack) = (None, None, false);
+        loop {
+            let option = parse.next_string()?.to_uppercase();
+            match option.as_str() {
+                "COUNT" => count = Some(parse.next_int()? as usize).filter(|count| *count > 0),
+                "BLOCK" => block = Some(parse.next_string()?),
+                "NOACK" => noack = true,
+                "STREAMS" => break,
+                _ => bail!(Error::Syntax),
+            }
+        }
+
+        let mut args = vec![];
+        loop {
+            match parse.next_bytes() {
+                Ok(arg) => args.push(arg),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if args.is_empty() || args.len() % 2 != 0 {
+            bail!("ERR Unbalanced 'xreadgroup' list of streams: for each stream key an ID or '>' must be specified.");
+        }
+        let ids = args
+            .split_off(args.len() / 2)
+            .into_iter()
+            .map(|id| String::from_utf8(id.to_vec()))
+            .collect::<Result<_, _>>()?;
+
+        let mut cmd = XReadGroup::new(group, consumer, args, ids);
+        cmd.count = count;
+        cmd.block = block;
+        cmd.noack = noack;
+        Ok(cmd)
+    }
+
+    pub(crate) fn is_blocking(&self) -> bool {
+        self.block.is_some()
+    }
+
+    /// Inside a transaction the read never waits for new entries.
+    pub(crate) fn without_blocking(self) -> Self {
+        Self {
+            block: None,
+            ..self
+        }
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let Some(reads) = self
+            .ids
+            .iter()
+            .map(|id| match id.as_str() {
+                ">" => Some(ReadFrom::New),
+                _ => StreamId::parse(id, 0).map(ReadFrom::Pending),
+            })
+            .collect::<Option<Vec<_>>>()
+        else {
+            return Ok(Frame::Error(ERR_INVALID_ID.to_string()));
+        };
+        let deadline = match self.block.as_deref().map(str::parse::<i64>) {
+            None => None,
+            Some(Ok(ms)) if ms < 0 => {
+                return Ok(Frame::Error("ERR timeout is negative".to_string()));
+            }
+            Some(Ok(ms)) => {
+                Some((ms > 0).then(|| Instant::now() + Duration::from_millis(ms as u64)))
+            }
+            Some(Err(_)) => {
+                let response =
+                    Frame::Error("ERR timeout is not an integer or out of range".to_string());
+                return Ok(response);
+            }
+        };
+        // history reads are answered straight away, even when empty
+        let may_block = reads.iter().all(|read| *read == ReadFrom::New);
+
+        let guard = store.block_on(&self.keys);
+        let response = loop {
+            if let Some(response) = self.try_read(store, &reads).await? {
+                break response;
+            }
+            match deadline {
+                Some(Some(deadline)) if may_block => {
+                    if timeout_at(deadline, guard.wait()).await.is_err() {
+                        break Frame::NullArray;
+                    }
+                }
+                Some(None) if may_block => guard.wait().await,
+                _ => break Frame::NullArray,
+            }
+        };
+        Ok(response)
+    }
+
+    /// Reads every stream once, returning `None` if there was nothing to deliver.
+    async fn try_read(&self, store: &Store, reads: &[ReadFrom]) -> anyhow::Result<Option<Frame>> {
+        let mut streams = vec![];
+        for (key, read) in self.keys.iter().zip(reads) {
+            let now = now_ms();
+            let result = store.with_stream_mut(key.clone(), false, |stream| match read {
+                ReadFrom::New => stream
+                    .read_group_new(&self.group, &self.consumer, self.count, self.noack, now)
+                    .map(|delivered| (None, delivered)),
+                ReadFrom::Pending(after) => stream
+                    .read_group_pending(&self.group, &self.consumer, *after, self.count, now)
+                    .map(|entries| {
+                        let entries = entries
+                            .into_iter()
+                            .map(|(id, fields)| entry_frame(id, fields))
+                            .collect();
+                        (Some(Frame::Array(entries)), vec![])
+                    }),
+            });
+            let (history, delivered) = match result {
+                Ok(Some(Some(read))) => read,
+                Ok(_) => {
+                    return Ok(Some(Frame::Error(format!(
+                        "NOGROUP No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option",
+                        String::from_utf8_lossy(key),
+                        String::from_utf8_lossy(&self.group)
+                    ))))
+                }
+                Err(e) => return Ok(Some(Frame::Error(e.to_string()))),
+            };
+
+            let entries = match history {
+                Some(history) => history,
+                None if delivered.is_empty() => continue,
+                None => {
+                    self.replicate(store, key, &delivered).await?;
+                    claimed_frame(delivered, false)
+                }
+            };
+            streams.push(Frame::Array(vec![Frame::Bulk(key.clone()), entries]));
+        }
+        Ok((!streams.is_empty()).then_some(Frame::Array(streams)))
+    }
+
+    /// Replicates a `>` read the way Redis does: a forced XCLAIM per delivered
+    /// entry, or with NOACK just the group's new position.
+    async fn replicate(
+        &self,
+        store: &Store,
+        key: &Bytes,
+        delivered: &[Claimed],
+    ) -> anyhow::Result<()> {
+        let last_delivered = delivered.last().map(|claimed| claimed.id);
+        if !self.noack {
+            return publish_claims(
+                store,
+                key,
+                &self.group,
+                &self.consumer,
+                delivered,
+                last_delivered,
+            )
+            .await;
+        }
+        if let Some(last_delivered) = last_delivered {
+            publish(
+                store,
+                Action::XGroup {
+                    subcommand: "setid",
+                    key: key.clone(),
+                    group: self.group.clone(),
+                    args: vec![last_delivered.to_string().into()],
+                },
+            )
+            .await?;
+        }
+        Ok(())
+    }
+}
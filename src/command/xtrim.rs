@@ -0,0 +1,139 @@
+use anyhow::bail;
+use bytes::Bytes;
+
+use crate::{
+    error::Error,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    publisher::{publish, Action},
+    store::{
+        notify::EventClass,
+        stream::{Stream, StreamId, Trim, TrimStrategy, ERR_INVALID_ID},
+        Store,
+    },
+};
+
+/// Unvalidated `MAXLEN|MINID [=|~] threshold [LIMIT count]` arguments, shared
+/// by XTRIM and XADD.
+#[derive(Debug, Clone, Default)]
+pub struct TrimArgs {
+    by_min_id: bool,
+    approximate: bool,
+    threshold: String,
+    limit: Option<u64>,
+}
+
+impl TrimArgs {
+    /// Parses the arguments following a `MAXLEN` or `MINID` token.
+    pub(crate) fn parse_frames(strategy: &str, parse: &mut Parse) -> anyhow::Result<TrimArgs> {
+        let by_min_id = strategy.eq_ignore_ascii_case("MINID");
+        let mut approximate = false;
+        let mut threshold = parse.next_string()?;
+        if threshold == "~" || threshold == "=" {
+            approximate = threshold == "~";
+            threshold = parse.next_string()?;
+        }
+        Ok(TrimArgs {
+            by_min_id,
+            approximate,
+            threshold,
+            limit: None,
+        })
+    }
+
+    /// Parses the count after a `LIMIT` token.
+    pub(crate) fn parse_limit(&mut self, parse: &mut Parse) -> anyhow::Result<()> {
+        if !self.approximate {
+            bail!("ERR syntax error, LIMIT cannot be used without the special ~ option");
+        }
+        self.limit = Some(parse.next_int()?);
+        Ok(())
+    }
+
+    pub(crate) fn resolve(&self) -> Result<Trim, &'static str> {
+        let strategy = if self.by_min_id {
+            TrimStrategy::MinId(StreamId::parse(&self.threshold, 0).ok_or(ERR_INVALID_ID)?)
+        } else {
+            match self.threshold.parse::<i64>() {
+                Ok(max) if max >= 0 => TrimStrategy::MaxLen(max as u64),
+                Ok(_) => return Err("ERR The MAXLEN argument must be >= 0."),
+                Err(_) => return Err("ERR value is not an integer or out of range"),
+            }
+        };
+        Ok(Trim {
+            strategy,
+            approximate: self.approximate,
+            limit: self.limit,
+        })
+    }
+}
+
+/// The first ID a trimmed stream still holds, used to replicate a trim as an
+/// exact `MINID` so replicas end up with the same entries even for `~` trims.
+pub(crate) fn trimmed_min_id(stream: &Stream) -> StreamId {
+    stream
+        .first_id()
+        .or_else(|| stream.last_id().next())
+        .unwrap_or(StreamId::MAX)
+}
+
+#[derive(Debug, Default)]
+pub struct XTrim {
+    key: Bytes,
+    args: TrimArgs,
+}
+
+impl XTrim {
+    pub fn new(key: Bytes, args: TrimArgs) -> Self {
+        Self { key, args }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<XTrim> {
+        let key = parse.next_bytes()?;
+        let strategy = parse.next_string()?;
+        if !strategy.eq_ignore_ascii_case("MAXLEN") && !strategy.eq_ignore_ascii_case("MINID") {
+            bail!(Error::Syntax);
+        }
+        let mut args = TrimArgs::parse_frames(&strategy, parse)?;
+        match parse.next_string() {
+            Ok(option) if option.eq_ignore_ascii_case("LIMIT") => args.parse_limit(parse)?,
+            Ok(_) => bail!(Error::Syntax),
+            Err(ParseError::EndOfStream) => {}
+            Err(e) => return Err(e.into()),
+        }
+        Ok(XTrim::new(key, args))
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let trim = match self.args.resolve() {
+            Ok(trim) => trim,
+            Err(e) => {
+                return Ok(Frame::Error(e.to_string()));
+            }
+        };
+
+        let trimmed = store.with_stream_mut(self.key.clone(), false, |stream| {
+            (stream.trim(&trim), trimmed_min_id(stream))
+        });
+        let response = match trimmed {
+            Ok(Some((removed, min_id))) => {
+                if removed > 0 {
+                    store.notify(EventClass::Stream, "xtrim", &self.key);
+                    publish(
+                        store,
+                        Action::XTrim {
+                            key: self.key,
+                            min_id,
+                        },
+                    )
+                    .await?;
+                }
+                Frame::Integer(removed as u64)
+            }
+            Ok(None) => Frame::Integer(0),
+            Err(e) => Frame::Error(e.to_string()),
+        };
+
+        Ok(response)
+    }
+}
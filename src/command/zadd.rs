@@ -0,0 +1,154 @@
+use anyhow::bail;
+use bytes::Bytes;
+
+use crate::{
+    error::Error,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    publisher::{publish, Action},
+    store::{
+        notify::EventClass,
+        sorted_set::{format_score, parse_score, ERR_NAN_SCORE, ERR_NOT_A_FLOAT},
+        Store,
+    },
+};
+
+/// ZADD key [NX|XX] [GT|LT] [CH] [INCR] score member [score member ...]:
+/// sets the scores of members, adding them as needed. NX only adds and XX
+/// only updates; GT and LT only update to a higher or lower score. Replies
+/// with how many members were added, or changed with CH. With INCR it adds
+/// to a single member's score like ZINCRBY, replying nil if the flags kept
+/// it from doing so.
+#[derive(Debug, Default)]
+pub struct ZAdd {
+    key: Bytes,
+    nx: bool,
+    xx: bool,
+    gt: bool,
+    lt: bool,
+    ch: bool,
+    incr: bool,
+    members: Vec<(String, Bytes)>,
+}
+
+impl ZAdd {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<ZAdd> {
+        let mut zadd = ZAdd {
+            key: parse.next_bytes()?,
+            ..ZAdd::default()
+        };
+        // flags come first, up to the first score
+        let mut score = loop {
+            let arg = parse.next_string()?;
+            match arg.to_uppercase().as_str() {
+                "NX" => zadd.nx = true,
+                "XX" => zadd.xx = true,
+                "GT" => zadd.gt = true,
+                "LT" => zadd.lt = true,
+                "CH" => zadd.ch = true,
+                "INCR" => zadd.incr = true,
+                _ => break arg,
+            }
+        };
+        loop {
+            let member = match parse.next_bytes() {
+                Ok(member) => member,
+                Err(ParseError::EndOfStream) => bail!(Error::Syntax),
+                Err(e) => return Err(e.into()),
+            };
+            zadd.members.push((score, member));
+            score = match parse.next_string() {
+                Ok(score) => score,
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            };
+        }
+        if zadd.nx && zadd.xx {
+            bail!("ERR XX and NX options at the same time are not compatible");
+        }
+        if [zadd.nx, zadd.gt, zadd.lt]
+            .iter()
+            .filter(|&&flag| flag)
+            .count()
+            > 1
+        {
+            bail!("ERR GT, LT, and/or NX options at the same time are not compatible");
+        }
+        if zadd.incr && zadd.members.len() > 1 {
+            bail!("ERR INCR option supports a single increment-element pair");
+        }
+        Ok(zadd)
+    }
+
+    /// The score `member` should end up with, given its current one, or
+    /// None if the flags leave it as it is.
+    fn new_score(&self, current: Option<f64>, score: f64) -> Option<f64> {
+        let Some(current) = current else {
+            return (!self.xx).then_some(score);
+        };
+        let score = if self.incr { current + score } else { score };
+        let keep = self.nx || (self.gt && score <= current) || (self.lt && score >= current);
+        (!keep).then_some(score)
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let Some(members) = self
+            .members
+            .iter()
+            .map(|(score, member)| parse_score(score).map(|score| (score, member.clone())))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return Ok(Frame::Error(ERR_NOT_A_FLOAT.to_string()));
+        };
+
+        let changed = store.with_sorted_set_mut(self.key.clone(), |zset| {
+            let mut added = 0;
+            let mut changed = vec![];
+            // the score INCR replies with, even when it stayed the same
+            let mut last = None;
+            for (score, member) in members {
+                let current = zset.score(&member);
+                let Some(score) = self.new_score(current, score) else {
+                    continue;
+                };
+                if score.is_nan() {
+                    return Err(ERR_NAN_SCORE);
+                }
+                if current != Some(score) && zset.insert(member.clone(), score) {
+                    added += 1;
+                }
+                let score = zset.score(&member).expect("just set");
+                last = Some(score);
+                if current != Some(score) {
+                    changed.push((score, member));
+                }
+            }
+            Ok((added, changed, last))
+        });
+        let (added, changed, last) = match changed {
+            Ok(Ok(changed)) => changed,
+            Ok(Err(e)) => return Ok(Frame::Error(e.to_string())),
+            Err(e) => return Ok(Frame::Error(e.to_string())),
+        };
+        let response = if self.incr {
+            last.map_or(Frame::Null, |score| Frame::Bulk(format_score(score).into()))
+        } else if self.ch {
+            Frame::Integer(changed.len() as u64)
+        } else {
+            Frame::Integer(added as u64)
+        };
+        if !changed.is_empty() {
+            let event = if self.incr { "zincr" } else { "zadd" };
+            store.notify(EventClass::SortedSet, event, &self.key);
+            publish(
+                store,
+                Action::ZAdd {
+                    key: self.key,
+                    members: changed,
+                },
+            )
+            .await?;
+        }
+        Ok(response)
+    }
+}
@@ -0,0 +1,27 @@
+use bytes::Bytes;
+
+use crate::{frame::Frame, parse::Parse, store::Store};
+
+#[derive(Debug, Default)]
+pub struct ZCard {
+    key: Bytes,
+}
+
+impl ZCard {
+    pub fn new(key: Bytes) -> Self {
+        Self { key }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<ZCard> {
+        let key = parse.next_bytes()?;
+        Ok(ZCard::new(key))
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let response = match store.with_sorted_set(&self.key, |zset| zset.len()) {
+            Ok(len) => Frame::Integer(len as u64),
+            Err(e) => Frame::Error(e.to_string()),
+        };
+        Ok(response)
+    }
+}
@@ -0,0 +1,43 @@
+use bytes::Bytes;
+
+use crate::{
+    frame::Frame,
+    parse::Parse,
+    store::{
+        sorted_set::{ScoreRange, ERR_SCORE_RANGE},
+        Store,
+    },
+};
+
+#[derive(Debug, Default)]
+pub struct ZCount {
+    key: Bytes,
+    min: String,
+    max: String,
+}
+
+impl ZCount {
+    pub fn new(key: Bytes, min: String, max: String) -> Self {
+        Self { key, min, max }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<ZCount> {
+        let key = parse.next_bytes()?;
+        let min = parse.next_string()?;
+        let max = parse.next_string()?;
+        Ok(ZCount::new(key, min, max))
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let response = match ScoreRange::parse(&self.min, &self.max) {
+            None => Frame::Error(ERR_SCORE_RANGE.to_string()),
+            Some(range) => {
+                match store.with_sorted_set(&self.key, |zset| zset.count_in_score_range(&range)) {
+                    Ok(count) => Frame::Integer(count as u64),
+                    Err(e) => Frame::Error(e.to_string()),
+                }
+            }
+        };
+        Ok(response)
+    }
+}
@@ -0,0 +1,65 @@
+use bytes::Bytes;
+
+use crate::{
+    frame::Frame,
+    parse::Parse,
+    publisher::{publish, Action},
+    store::{
+        notify::EventClass,
+        sorted_set::{format_score, parse_score, ERR_NOT_A_FLOAT},
+        Store,
+    },
+};
+
+#[derive(Debug, Default)]
+pub struct ZIncrBy {
+    key: Bytes,
+    increment: String,
+    member: Bytes,
+}
+
+impl ZIncrBy {
+    pub fn new(key: Bytes, increment: String, member: Bytes) -> Self {
+        Self {
+            key,
+            increment,
+            member,
+        }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<ZIncrBy> {
+        let key = parse.next_bytes()?;
+        let increment = parse.next_string()?;
+        let member = parse.next_bytes()?;
+        Ok(ZIncrBy::new(key, increment, member))
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let response = match parse_score(&self.increment) {
+            None => Frame::Error(ERR_NOT_A_FLOAT.to_string()),
+            Some(increment) => {
+                let incremented = store.with_sorted_set_mut(self.key.clone(), |zset| {
+                    zset.incr(self.member.clone(), increment)
+                });
+                match incremented {
+                    Ok(Ok(score)) => {
+                        store.notify(EventClass::SortedSet, "zincr", &self.key);
+                        // replicas set the resulting score rather than redo the sum
+                        publish(
+                            store,
+                            Action::ZAdd {
+                                key: self.key,
+                                members: vec![(score, self.member)],
+                            },
+                        )
+                        .await?;
+                        Frame::Bulk(format_score(score).into())
+                    }
+                    Ok(Err(e)) => Frame::Error(e.to_string()),
+                    Err(e) => Frame::Error(e.to_string()),
+                }
+            }
+        };
+        Ok(response)
+    }
+}
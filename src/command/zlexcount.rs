@@ -0,0 +1,43 @@
+use bytes::Bytes;
+
+use crate::{
+    frame::Frame,
+    parse::Parse,
+    store::{
+        sorted_set::{LexRange, ERR_LEX_RANGE},
+        Store,
+    },
+};
+
+#[derive(Debug, Default)]
+pub struct ZLexCount {
+    key: Bytes,
+    min: Bytes,
+    max: Bytes,
+}
+
+impl ZLexCount {
+    pub fn new(key: Bytes, min: Bytes, max: Bytes) -> Self {
+        Self { key, min, max }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<ZLexCount> {
+        let key = parse.next_bytes()?;
+        let min = parse.next_bytes()?;
+        let max = parse.next_bytes()?;
+        Ok(ZLexCount::new(key, min, max))
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let response = match LexRange::parse(&self.min, &self.max) {
+            None => Frame::Error(ERR_LEX_RANGE.to_string()),
+            Some(range) => {
+                match store.with_sorted_set(&self.key, |zset| zset.count_in_lex_range(&range)) {
+                    Ok(count) => Frame::Integer(count as u64),
+                    Err(e) => Frame::Error(e.to_string()),
+                }
+            }
+        };
+        Ok(response)
+    }
+}
@@ -0,0 +1,100 @@
+use bytes::Bytes;
+
+use crate::{
+    error::Error,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    publisher::{publish, Action},
+    store::{
+        notify::EventClass,
+        sorted_set::{format_score, SortedSet},
+        Store,
+    },
+};
+
+/// Which end of the sorted set a pop takes members from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Min,
+    Max,
+}
+
+impl Side {
+    pub(crate) fn pop(self, zset: &mut SortedSet, count: usize) -> Vec<(Bytes, f64)> {
+        match self {
+            Side::Min => zset.pop_min(count),
+            Side::Max => zset.pop_max(count),
+        }
+    }
+
+    /// The keyspace event both ZPOP and BZPOP raise.
+    pub(crate) fn event(self) -> &'static str {
+        match self {
+            Side::Min => "zpopmin",
+            Side::Max => "zpopmax",
+        }
+    }
+}
+
+/// ZPOPMIN / ZPOPMAX
+#[derive(Debug)]
+pub struct ZPop {
+    side: Side,
+    key: Bytes,
+    count: Option<String>,
+}
+
+impl ZPop {
+    pub fn new(side: Side, key: Bytes, count: Option<String>) -> Self {
+        Self { side, key, count }
+    }
+
+    pub(crate) fn parse_frames(side: Side, parse: &mut Parse) -> anyhow::Result<ZPop> {
+        let key = parse.next_bytes()?;
+        let count = match parse.next_string() {
+            Ok(count) => Some(count),
+            Err(ParseError::EndOfStream) => None,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(ZPop::new(side, key, count))
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let count = match self.count.as_deref().map(str::parse::<i64>) {
+            None => 1,
+            Some(Ok(count)) if count >= 0 => count as usize,
+            Some(Ok(_)) => {
+                return Ok(Frame::Error(
+                    "ERR value is out of range, must be positive".into(),
+                ));
+            }
+            Some(Err(_)) => {
+                let response = Error::NotAnInteger.into();
+                return Ok(response);
+            }
+        };
+
+        let response =
+            match store.with_sorted_set_mut(self.key.clone(), |zset| self.side.pop(zset, count)) {
+                Ok(popped) => {
+                    if !popped.is_empty() {
+                        store.notify(EventClass::SortedSet, self.side.event(), &self.key);
+                        let action = Action::ZPop {
+                            side: self.side,
+                            key: self.key.clone(),
+                            count: popped.len(),
+                        };
+                        publish(store, action).await?;
+                    }
+                    let mut array = Frame::array();
+                    for (member, score) in popped {
+                        array.push_bulk(member)?;
+                        array.push_bulk(format_score(score).into())?;
+                    }
+                    array
+                }
+                Err(e) => Frame::Error(e.to_string()),
+            };
+        Ok(response)
+    }
+}
@@ -0,0 +1,222 @@
+use anyhow::bail;
+use bytes::Bytes;
+
+use crate::{
+    error::Error,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    store::{
+        sorted_set::{
+            format_score, LexRange, ScoreRange, SortedSet, ERR_LEX_RANGE, ERR_SCORE_RANGE,
+        },
+        Store,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum RangeBy {
+    #[default]
+    Rank,
+    Score,
+    Lex,
+}
+
+/// The `min max [BYSCORE|BYLEX] [REV] [LIMIT offset count]` part shared by
+/// ZRANGE and ZRANGESTORE.
+#[derive(Debug, Clone, Default)]
+pub struct RangeSpec {
+    min: Bytes,
+    max: Bytes,
+    by: RangeBy,
+    rev: bool,
+    limit: Option<(i64, i64)>,
+}
+
+/// A `RangeSpec` whose bounds have been validated.
+#[derive(Debug)]
+enum Resolved {
+    Rank(i64, i64),
+    Score(ScoreRange),
+    Lex(LexRange),
+}
+
+const ERR_NOT_AN_INTEGER: &str = "ERR value is not an integer or out of range";
+
+impl RangeSpec {
+    /// Parses the range arguments. Option tokens the caller understands (such
+    /// as WITHSCORES for ZRANGE) are handed to `extra`, which returns false for
+    /// tokens it does not recognise.
+    pub(crate) fn parse_frames(
+        parse: &mut Parse,
+        mut extra: impl FnMut(&str) -> bool,
+    ) -> anyhow::Result<RangeSpec> {
+        let mut spec = RangeSpec {
+            min: parse.next_bytes()?,
+            max: parse.next_bytes()?,
+            ..Default::default()
+        };
+        loop {
+            let option = match parse.next_string() {
+                Ok(option) => option.to_uppercase(),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            };
+            match option.as_str() {
+                "BYSCORE" => spec.by = RangeBy::Score,
+                "BYLEX" => spec.by = RangeBy::Lex,
+                "REV" => spec.rev = true,
+                "LIMIT" => {
+                    let offset = parse.next_string()?.parse()?;
+                    let count = parse.next_string()?.parse()?;
+                    spec.limit = Some((offset, count));
+                }
+                other if extra(other) => {}
+                _ => bail!(Error::Syntax),
+            }
+        }
+        if spec.limit.is_some() && spec.by == RangeBy::Rank {
+            bail!("ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX");
+        }
+        Ok(spec)
+    }
+
+    pub(crate) fn is_by_lex(&self) -> bool {
+        self.by == RangeBy::Lex
+    }
+
+    /// With REV the bounds are given highest first, so swap them back.
+    fn bounds(&self) -> (&[u8], &[u8]) {
+        if self.rev && self.by != RangeBy::Rank {
+            (&self.max, &self.min)
+        } else {
+            (&self.min, &self.max)
+        }
+    }
+
+    fn resolve(&self) -> Result<Resolved, &'static str> {
+        let (min, max) = self.bounds();
+        match self.by {
+            RangeBy::Rank => {
+                let parse = |b: &[u8]| {
+                    std::str::from_utf8(b)
+                        .ok()
+                        .and_then(|s| s.parse::<i64>().ok())
+                        .ok_or(ERR_NOT_AN_INTEGER)
+                };
+                Ok(Resolved::Rank(parse(min)?, parse(max)?))
+            }
+            RangeBy::Score => {
+                let min = std::str::from_utf8(min).map_err(|_| ERR_SCORE_RANGE)?;
+                let max = std::str::from_utf8(max).map_err(|_| ERR_SCORE_RANGE)?;
+                ScoreRange::parse(min, max)
+                    .map(Resolved::Score)
+                    .ok_or(ERR_SCORE_RANGE)
+            }
+            RangeBy::Lex => LexRange::parse(min, max)
+                .map(Resolved::Lex)
+                .ok_or(ERR_LEX_RANGE),
+        }
+    }
+
+    /// Evaluates the range against `zset`. Fails only if the bounds are invalid.
+    pub(crate) fn evaluate(&self, zset: &SortedSet) -> Result<Vec<(Bytes, f64)>, &'static str> {
+        let resolved = self.resolve()?;
+        let owned = |(member, score): (&Bytes, f64)| (member.clone(), score);
+        let mut members: Vec<(Bytes, f64)> = match &resolved {
+            Resolved::Rank(start, stop) => {
+                let len = zset.len() as i64;
+                let start = if *start < 0 { start + len } else { *start }.max(0);
+                let stop = if *stop < 0 { stop + len } else { *stop }.min(len - 1);
+                if start > stop || start >= len {
+                    return Ok(vec![]);
+                }
+                let take = (stop - start + 1) as usize;
+                if self.rev {
+                    zset.iter()
+                        .rev()
+                        .skip(start as usize)
+                        .take(take)
+                        .map(owned)
+                        .collect()
+                } else {
+                    zset.iter()
+                        .skip(start as usize)
+                        .take(take)
+                        .map(owned)
+                        .collect()
+                }
+            }
+            Resolved::Score(range) => zset.range_by_score(range).map(owned).collect(),
+            Resolved::Lex(range) => zset.range_by_lex(range).map(owned).collect(),
+        };
+        if self.by != RangeBy::Rank {
+            if self.rev {
+                members.reverse();
+            }
+            if let Some((offset, count)) = self.limit {
+                if offset < 0 {
+                    return Ok(vec![]);
+                }
+                let count = if count < 0 {
+                    usize::MAX
+                } else {
+                    count as usize
+                };
+                members = members
+                    .into_iter()
+                    .skip(offset as usize)
+                    .take(count)
+                    .collect();
+            }
+        }
+        Ok(members)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ZRange {
+    key: Bytes,
+    spec: RangeSpec,
+    with_scores: bool,
+}
+
+impl ZRange {
+    pub fn new(key: Bytes, spec: RangeSpec, with_scores: bool) -> Self {
+        Self {
+            key,
+            spec,
+            with_scores,
+        }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<ZRange> {
+        let key = parse.next_bytes()?;
+        let mut with_scores = false;
+        let spec = RangeSpec::parse_frames(parse, |option| {
+            with_scores |= option == "WITHSCORES";
+            option == "WITHSCORES"
+        })?;
+        if with_scores && spec.is_by_lex() {
+            bail!("ERR syntax error, WITHSCORES not supported in combination with BYLEX");
+        }
+        Ok(ZRange::new(key, spec, with_scores))
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let response = match store.with_sorted_set(&self.key, |zset| self.spec.evaluate(zset)) {
+            Ok(Ok(members)) => {
+                let mut array = Frame::array();
+                for (member, score) in members {
+                    array.push_bulk(member)?;
+                    if self.with_scores {
+                        array.push_bulk(format_score(score).into())?;
+                    }
+                }
+                array
+            }
+            Ok(Err(e)) => Frame::Error(e.to_string()),
+            Err(e) => Frame::Error(e.to_string()),
+        };
+        Ok(response)
+    }
+}
@@ -0,0 +1,62 @@
+use bytes::Bytes;
+
+use crate::{
+    command::{zrange::RangeSpec, zstore::publish_stored_set},
+    frame::Frame,
+    parse::Parse,
+    store::{notify::EventClass, sorted_set::SortedSet, Store},
+};
+
+#[derive(Debug, Default)]
+pub struct ZRangeStore {
+    destination: Bytes,
+    source: Bytes,
+    spec: RangeSpec,
+}
+
+impl ZRangeStore {
+    pub fn new(destination: Bytes, source: Bytes, spec: RangeSpec) -> Self {
+        Self {
+            destination,
+            source,
+            spec,
+        }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> anyhow::Result<ZRangeStore> {
+        let destination = parse.next_bytes()?;
+        let source = parse.next_bytes()?;
+        let spec = RangeSpec::parse_frames(parse, |_| false)?;
+        Ok(ZRangeStore::new(destination, source, spec))
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        // validate the bounds up front so a bad range leaves the destination untouched
+        let response = match self.spec.evaluate(&SortedSet::new()) {
+            Err(e) => Frame::Error(e.to_string()),
+            Ok(_) => {
+                let sources = [self.source];
+                let stored = store.store_sorted_set(self.destination.clone(), &sources, |inputs| {
+                    let mut result = SortedSet::new();
+                    for (member, score) in self.spec.evaluate(inputs[0]).unwrap_or_default() {
+                        result.insert(member, score);
+                    }
+                    result
+                });
+                match stored {
+                    Ok(zset) => {
+                        if !zset.is_empty() {
+                            store.notify(EventClass::SortedSet, "zrangestore", &self.destination);
+                        }
+                        let len = zset.len();
+                        publish_stored_set(store, self.destination, zset).await?;
+                        Frame::Integer(len as u64)
+                    }
+                    Err(e) => Frame::Error(e.to_string()),
+                }
+            }
+        };
+
+        Ok(response)
+    }
+}
@@ -0,0 +1,156 @@
+use anyhow::bail;
+use bytes::Bytes;
+
+use crate::{
+    error::Error,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    publisher::{publish, Action},
+    rdb,
+    store::{
+        notify::EventClass,
+        sorted_set::{parse_score, Aggregate, SetOp, SortedSet},
+        Store, Value,
+    },
+};
+
+/// ZUNIONSTORE / ZINTERSTORE / ZDIFFSTORE
+#[derive(Debug)]
+pub struct ZStore {
+    op: SetOp,
+    destination: Bytes,
+    keys: Vec<Bytes>,
+    weights: Option<Vec<String>>,
+    aggregate: Aggregate,
+}
+
+impl ZStore {
+    pub fn new(
+        op: SetOp,
+        destination: Bytes,
+        keys: Vec<Bytes>,
+        weights: Option<Vec<String>>,
+        aggregate: Aggregate,
+    ) -> Self {
+        Self {
+            op,
+            destination,
+            keys,
+            weights,
+            aggregate,
+        }
+    }
+
+    pub(crate) fn parse_frames(op: SetOp, parse: &mut Parse) -> anyhow::Result<ZStore> {
+        let destination = parse.next_bytes()?;
+        let numkeys = parse.next_int()?;
+        if numkeys == 0 {
+            bail!(
+                "ERR at least 1 input key is needed for '{}' command",
+                op.command_name()
+            );
+        }
+        let mut keys = Vec::with_capacity(numkeys as usize);
+        for _ in 0..numkeys {
+            match parse.next_bytes() {
+                Ok(key) => keys.push(key),
+                Err(ParseError::EndOfStream) => bail!(Error::Syntax),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let mut weights = None;
+        let mut aggregate = Aggregate::default();
+        loop {
+            let option = match parse.next_string() {
+                Ok(option) => option,
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            };
+            match option.to_uppercase().as_str() {
+                "WEIGHTS" if op != SetOp::Diff => {
+                    let mut values = Vec::with_capacity(keys.len());
+                    for _ in 0..keys.len() {
+                        values.push(parse.next_string()?);
+                    }
+                    weights = Some(values);
+                }
+                "AGGREGATE" if op != SetOp::Diff => {
+                    aggregate = match parse.next_string()?.to_uppercase().as_str() {
+                        "SUM" => Aggregate::Sum,
+                        "MIN" => Aggregate::Min,
+                        "MAX" => Aggregate::Max,
+                        _ => bail!(Error::Syntax),
+                    };
+                }
+                _ => bail!(Error::Syntax),
+            }
+        }
+
+        Ok(ZStore::new(op, destination, keys, weights, aggregate))
+    }
+
+    pub(crate) async fn apply(self, store: &Store) -> anyhow::Result<Frame> {
+        let weights = match &self.weights {
+            None => vec![1.0; self.keys.len()],
+            Some(weights) => match weights.iter().map(|w| parse_score(w)).collect() {
+                Some(weights) => weights,
+                None => {
+                    return Ok(Frame::Error("ERR weight value is not a float".to_string()));
+                }
+            },
+        };
+
+        let stored = store.store_sorted_set(self.destination.clone(), &self.keys, |inputs| {
+            let sources = inputs.iter().copied().zip(weights).collect::<Vec<_>>();
+            SortedSet::combine(self.op, &sources, self.aggregate)
+        });
+        let response = match stored {
+            Ok(zset) => {
+                if !zset.is_empty() {
+                    store.notify(
+                        EventClass::SortedSet,
+                        self.op.command_name(),
+                        &self.destination,
+                    );
+                }
+                let len = zset.len();
+                publish_stored_set(store, self.destination, zset).await?;
+                Frame::Integer(len as u64)
+            }
+            Err(e) => Frame::Error(e.to_string()),
+        };
+
+        Ok(response)
+    }
+}
+
+/// Replicates the result of a *STORE command as its final state, in a
+/// single write so replicas never see the destination half built: a RESTORE
+/// of the whole set, or a DEL when the result is empty.
+pub(crate) async fn publish_stored_set(
+    store: &Store,
+    destination: Bytes,
+    zset: SortedSet,
+) -> anyhow::Result<()> {
+    let action = if zset.is_empty() {
+        Action::Del { key: destination }
+    } else {
+        Action::Restore {
+            key: destination,
+            payload: rdb::dump(&Value::SortedSet(zset)),
+            expires_at: None,
+        }
+    };
+    publish(store, action).await
+}
+
+impl SetOp {
+    fn command_name(self) -> &'static str {
+        match self {
+            SetOp::Union => "zunionstore",
+            SetOp::Inter => "zinterstore",
+            SetOp::Diff => "zdiffstore",
+        }
+    }
+}
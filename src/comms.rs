@@ -1,10 +1,104 @@
+use std::time::Duration;
 use tokio::io;
 
-use crate::frame::Frame;
+use crate::frame::{Frame, Limits};
+
+#[cfg(any(test, feature = "test-util"))]
+mod in_memory;
+#[cfg(any(test, feature = "test-util"))]
+pub use in_memory::InMemoryComms;
 
 #[async_trait::async_trait]
 pub trait Comms: Send + Sync {
+    /// Writes `frame` and sends it along with anything queued before it.
     async fn write_frame(&mut self, frame: &Frame) -> io::Result<()>;
+    /// Writes `frame` without sending it yet, so that the replies to a
+    /// pipeline of requests go out together on the next `flush`.
+    async fn queue_frame(&mut self, frame: &Frame) -> io::Result<()>;
+    async fn flush(&mut self) -> io::Result<()>;
     async fn read_frame(&mut self) -> anyhow::Result<Option<Frame>>;
-    fn is_follower_receiving_sync_request(&self) -> bool;
+    /// Whether a whole frame has been read already, so `read_frame` won't
+    /// wait on the peer.
+    fn has_buffered_frame(&self) -> bool;
+    /// Caps the frames read from now on.
+    fn set_limits(&mut self, limits: Limits);
+}
+
+/// Lends comms out, so that their state can be looked at once the borrower
+/// is done with them.
+#[async_trait::async_trait]
+impl<C: Comms + ?Sized> Comms for &mut C {
+    async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        (**self).write_frame(frame).await
+    }
+
+    async fn queue_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        (**self).queue_frame(frame).await
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        (**self).flush().await
+    }
+
+    async fn read_frame(&mut self) -> anyhow::Result<Option<Frame>> {
+        (**self).read_frame().await
+    }
+
+    fn has_buffered_frame(&self) -> bool {
+        (**self).has_buffered_frame()
+    }
+
+    fn set_limits(&mut self, limits: Limits) {
+        (**self).set_limits(limits)
+    }
+}
+
+/// Comms whose writes fail with `TimedOut` when the peer hasn't taken them
+/// within `limit`, so a peer that stopped reading can't hold the writer up.
+pub struct WriteTimeout<C> {
+    comms: C,
+    limit: Duration,
+}
+
+impl<C: Comms> WriteTimeout<C> {
+    pub fn new(comms: C, limit: Duration) -> Self {
+        Self { comms, limit }
+    }
+}
+
+#[async_trait::async_trait]
+impl<C: Comms> Comms for WriteTimeout<C> {
+    async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        within(self.limit, self.comms.write_frame(frame)).await
+    }
+
+    async fn queue_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        // a full buffer is written out while queueing
+        within(self.limit, self.comms.queue_frame(frame)).await
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        within(self.limit, self.comms.flush()).await
+    }
+
+    async fn read_frame(&mut self) -> anyhow::Result<Option<Frame>> {
+        self.comms.read_frame().await
+    }
+
+    fn has_buffered_frame(&self) -> bool {
+        self.comms.has_buffered_frame()
+    }
+
+    fn set_limits(&mut self, limits: Limits) {
+        self.comms.set_limits(limits)
+    }
+}
+
+async fn within(
+    limit: Duration,
+    write: impl std::future::Future<Output = io::Result<()>>,
+) -> io::Result<()> {
+    tokio::time::timeout(limit, write)
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "write timed out"))?
 }
@@ -0,0 +1,69 @@
+use std::collections::VecDeque;
+use tokio::io;
+
+use super::Comms;
+use crate::frame::{Frame, Limits};
+
+/// Comms over queues of frames instead of a socket, for driving code that
+/// takes `Comms` in tests: frames given with `push_incoming` are read in
+/// order, ending the stream once they run out, and flushed writes are kept
+/// for `written`. Built for the crate's own tests and with the `test-util`
+/// feature.
+#[derive(Debug, Default)]
+pub struct InMemoryComms {
+    incoming: VecDeque<Frame>,
+    /// Written but not yet flushed.
+    queued: Vec<Frame>,
+    written: Vec<Frame>,
+}
+
+impl InMemoryComms {
+    pub fn new(incoming: impl IntoIterator<Item = Frame>) -> Self {
+        Self {
+            incoming: incoming.into_iter().collect(),
+            ..Self::default()
+        }
+    }
+
+    pub fn push_incoming(&mut self, frame: Frame) {
+        self.incoming.push_back(frame);
+    }
+
+    /// The frames flushed so far.
+    pub fn written(&self) -> &[Frame] {
+        &self.written
+    }
+
+    pub fn take_written(&mut self) -> Vec<Frame> {
+        std::mem::take(&mut self.written)
+    }
+}
+
+#[async_trait::async_trait]
+impl Comms for InMemoryComms {
+    async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        self.queue_frame(frame).await?;
+        self.flush().await
+    }
+
+    async fn queue_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        self.queued.push(frame.clone());
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        self.written.append(&mut self.queued);
+        Ok(())
+    }
+
+    async fn read_frame(&mut self) -> anyhow::Result<Option<Frame>> {
+        Ok(self.incoming.pop_front())
+    }
+
+    fn has_buffered_frame(&self) -> bool {
+        !self.incoming.is_empty()
+    }
+
+    // frames aren't read off a wire, so there is nothing to cap
+    fn set_limits(&mut self, _limits: Limits) {}
+}
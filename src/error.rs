@@ -0,0 +1,130 @@
+//! The errors commands reply with that more than one command shares, worded
+//! exactly as Redis words them since clients match on the text.
+
+use std::fmt;
+
+use crate::frame::Frame;
+
+/// How long an argument Redis quotes in an unknown command error may be.
+const MAX_QUOTED_ARGS: usize = 128;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// A generic `ERR` with its message, less the code.
+    Err(String),
+    Syntax,
+    NotAnInteger,
+    /// The arguments don't fit the arity of the command, named in lowercase
+    /// as `name` or `name|subcommand`.
+    WrongArity(String),
+    UnknownCommand {
+        name: String,
+        args: Vec<String>,
+    },
+    WrongType,
+    NoAuth,
+    ReadOnly,
+    Oom,
+    ExecAbort,
+    NoScript,
+    /// A script has run past `lua-time-limit` and holds up everything else.
+    Busy,
+    /// The server was built without the `scripting` feature, so has no Lua.
+    NoScripting,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Err(message) => write!(f, "ERR {}", message),
+            Error::Syntax => "ERR syntax error".fmt(f),
+            Error::NotAnInteger => "ERR value is not an integer or out of range".fmt(f),
+            Error::WrongArity(name) => {
+                write!(f, "ERR wrong number of arguments for '{}' command", name)
+            }
+            Error::UnknownCommand { name, args } => {
+                write!(
+                    f,
+                    "ERR unknown command '{}', with args beginning with: ",
+                    truncate(name, MAX_QUOTED_ARGS)
+                )?;
+                // the arguments are quoted until they fill the allowance
+                let mut quoted = 0;
+                for arg in args {
+                    if quoted >= MAX_QUOTED_ARGS {
+                        break;
+                    }
+                    let arg = truncate(arg, MAX_QUOTED_ARGS - quoted);
+                    write!(f, "'{}' ", arg)?;
+                    quoted += arg.len() + 3;
+                }
+                Ok(())
+            }
+            Error::WrongType => {
+                "WRONGTYPE Operation against a key holding the wrong kind of value".fmt(f)
+            }
+            Error::NoAuth => "NOAUTH Authentication required.".fmt(f),
+            Error::ReadOnly => "READONLY You can't write against a read only replica.".fmt(f),
+            Error::Oom => "OOM command not allowed when used memory > 'maxmemory'.".fmt(f),
+            Error::ExecAbort => {
+                "EXECABORT Transaction discarded because of previous errors.".fmt(f)
+            }
+            Error::NoScript => "NOSCRIPT No matching script. Please use EVAL.".fmt(f),
+            Error::Busy => {
+                "BUSY Redis is busy running a script. You can only call SCRIPT KILL or SHUTDOWN NOSAVE.".fmt(f)
+            }
+            Error::NoScripting => {
+                "ERR This server was built without scripting support".fmt(f)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for Frame {
+    fn from(err: Error) -> Frame {
+        Frame::Error(err.to_string())
+    }
+}
+
+/// At most `len` bytes of `s`, cut back to a character boundary.
+fn truncate(s: &str, len: usize) -> &str {
+    let mut end = len.min(s.len());
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_read_as_redis_words_them() {
+        assert_eq!(
+            Frame::from(Error::WrongArity("get".to_string())),
+            Frame::Error("ERR wrong number of arguments for 'get' command".to_string())
+        );
+        assert_eq!(
+            Error::UnknownCommand {
+                name: "FOO".to_string(),
+                args: vec!["a".to_string(), "b".to_string()],
+            }
+            .to_string(),
+            "ERR unknown command 'FOO', with args beginning with: 'a' 'b' "
+        );
+        let long = Error::UnknownCommand {
+            name: "FOO".to_string(),
+            args: vec!["x".repeat(200), "y".to_string()],
+        };
+        assert_eq!(
+            long.to_string(),
+            format!(
+                "ERR unknown command 'FOO', with args beginning with: '{}' ",
+                "x".repeat(128)
+            )
+        );
+    }
+}
@@ -1,8 +1,8 @@
 use anyhow::bail;
-use bytes::{Buf, Bytes};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use std::convert::TryInto;
 use std::fmt;
-use std::io::Cursor;
+use std::io::{self, Cursor};
 use std::num::TryFromIntError;
 use std::string::FromUtf8Error;
 
@@ -11,13 +11,52 @@ pub enum Frame {
     Simple(String),
     Error(String),
     Integer(u64),
+    /// An integer reply that may be negative, see `Frame::integer`.
+    Signed(i64),
     Bulk(Bytes),
     Null,
+    NullArray,
     OK,
     Array(Vec<Frame>),
+    /// A RESP3 map, for clients that asked for RESP3 with HELLO.
+    Map(Vec<(Frame, Frame)>),
+    /// A RESP3 push: data the server sends unasked, such as invalidations.
+    Push(Vec<Frame>),
+    /// A RESP3 set, an array whose elements are unordered and unique.
+    Set(Vec<Frame>),
+    /// A RESP3 double.
+    Double(f64),
+    /// A RESP3 boolean.
+    Boolean(bool),
+    /// A RESP3 big number, its decimal digits with an optional sign.
+    BigNumber(String),
+    /// A RESP3 verbatim string: its three letter format, such as `txt` or
+    /// `mkd`, and the text.
+    Verbatim(String, Bytes),
     RdbFile(Bytes),
 }
 
+/// How large a frame a peer may send. Past these a read fails instead of
+/// buffering on, however much the frame's headers announce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Bytes in a bulk string.
+    pub bulk_len: u64,
+    /// Elements in an array.
+    pub multibulk_len: u64,
+    /// Bytes read but not yet parsed into a frame.
+    pub query_buffer: usize,
+}
+
+impl Limits {
+    /// For peers that are trusted to send anything, such as a master.
+    pub const NONE: Limits = Limits {
+        bulk_len: u64::MAX,
+        multibulk_len: u64::MAX,
+        query_buffer: usize::MAX,
+    };
+}
+
 #[derive(Debug)]
 pub enum Error {
     /// Not enough data is available to parse a message
@@ -43,44 +82,193 @@ impl Frame {
         }
     }
 
-    pub(crate) fn push_int(&mut self, value: u64) -> anyhow::Result<()> {
+    /// An error reply for `err`. Messages that don't start with an error code
+    /// such as `WRONGTYPE` get the generic `ERR` one.
+    pub fn from_error(err: &anyhow::Error) -> Frame {
+        let message = err.to_string();
+        let has_code = message
+            .split(' ')
+            .next()
+            .is_some_and(|code| code.len() > 1 && code.bytes().all(|b| b.is_ascii_uppercase()));
+        if has_code {
+            Frame::Error(message)
+        } else {
+            Frame::Error(format!("ERR {}", message))
+        }
+    }
+
+    /// A reply of `pairs`: a `Map` for RESP3 clients, flattened into an array
+    /// for RESP2 ones.
+    pub fn map(pairs: Vec<(Frame, Frame)>, resp3: bool) -> Frame {
+        if resp3 {
+            Frame::Map(pairs)
+        } else {
+            Frame::Array(pairs.into_iter().flat_map(|(k, v)| [k, v]).collect())
+        }
+    }
+
+    /// An integer reply, as `Integer` unless it is negative.
+    pub fn integer(n: i64) -> Frame {
+        u64::try_from(n).map_or(Frame::Signed(n), Frame::Integer)
+    }
+
+    /// The number of bytes the frame takes on the wire.
+    pub fn encoded_len(&self) -> usize {
+        let header = |len: usize| 1 + decimal_len(len as u64) + 2;
         match self {
-            Frame::Array(vec) => {
-                vec.push(Frame::Integer(value));
-                Ok(())
+            Frame::Simple(val) | Frame::Error(val) | Frame::BigNumber(val) => 1 + val.len() + 2,
+            Frame::Integer(val) => 1 + decimal_len(*val) + 2,
+            Frame::Signed(val) => 1 + usize::from(*val < 0) + decimal_len(val.unsigned_abs()) + 2,
+            Frame::Null | Frame::NullArray | Frame::OK => 5,
+            Frame::Double(val) => 1 + double_repr(*val).len() + 2,
+            Frame::Boolean(_) => 4,
+            Frame::Bulk(val) => header(val.len()) + val.len() + 2,
+            // no \r\n after rdb files
+            Frame::RdbFile(val) => header(val.len()) + val.len(),
+            Frame::Verbatim(format, val) => {
+                let len = format.len() + 1 + val.len();
+                header(len) + len + 2
+            }
+            Frame::Array(val) | Frame::Push(val) | Frame::Set(val) => {
+                header(val.len()) + val.iter().map(Frame::encoded_len).sum::<usize>()
+            }
+            Frame::Map(val) => {
+                header(val.len())
+                    + val
+                        .iter()
+                        .map(|(key, value)| key.encoded_len() + value.encoded_len())
+                        .sum::<usize>()
+            }
+        }
+    }
+
+    /// The frame as it is written on the wire.
+    pub fn to_bytes(&self) -> Bytes {
+        let mut out = BytesMut::new();
+        self.encode(&mut out);
+        out.freeze()
+    }
+
+    /// Appends the frame as it is written on the wire to `out`, growing it
+    /// once up front.
+    pub fn encode(&self, out: &mut BytesMut) {
+        out.reserve(self.encoded_len());
+        self.encode_value(out);
+    }
+
+    fn encode_value(&self, out: &mut BytesMut) {
+        match self {
+            Frame::Simple(val) => put_line(out, b'+', val.as_bytes()),
+            Frame::Error(val) => put_line(out, b'-', val.as_bytes()),
+            Frame::Integer(val) => put_header(out, b':', *val),
+            Frame::Signed(val) if *val < 0 => {
+                out.put(&b":-"[..]);
+                put_decimal(out, val.unsigned_abs());
+                out.put(&b"\r\n"[..]);
+            }
+            Frame::Signed(val) => put_header(out, b':', val.unsigned_abs()),
+            Frame::Null => out.put(&b"$-1\r\n"[..]),
+            Frame::NullArray => out.put(&b"*-1\r\n"[..]),
+            Frame::OK => out.put(&b"+OK\r\n"[..]),
+            Frame::BigNumber(val) => put_line(out, b'(', val.as_bytes()),
+            Frame::Double(val) => put_line(out, b',', double_repr(*val).as_bytes()),
+            Frame::Boolean(val) => put_line(out, b'#', if *val { b"t" } else { b"f" }),
+            Frame::Verbatim(format, val) => {
+                put_header(out, b'=', (format.len() + 1 + val.len()) as u64);
+                out.put(format.as_bytes());
+                out.put_u8(b':');
+                out.put(&val[..]);
+                out.put(&b"\r\n"[..]);
+            }
+            Frame::Bulk(val) => {
+                put_header(out, b'$', val.len() as u64);
+                out.put(&val[..]);
+                out.put(&b"\r\n"[..]);
+            }
+            Frame::RdbFile(val) => {
+                put_header(out, b'$', val.len() as u64);
+                out.put(&val[..]);
+            }
+            Frame::Array(val) => {
+                put_header(out, b'*', val.len() as u64);
+                for frame in val {
+                    frame.encode_value(out);
+                }
+            }
+            Frame::Push(val) | Frame::Set(val) => {
+                let kind = if matches!(self, Frame::Push(_)) {
+                    b'>'
+                } else {
+                    b'~'
+                };
+                put_header(out, kind, val.len() as u64);
+                for frame in val {
+                    frame.encode_value(out);
+                }
+            }
+            Frame::Map(val) => {
+                put_header(out, b'%', val.len() as u64);
+                for (key, value) in val {
+                    key.encode_value(out);
+                    value.encode_value(out);
+                }
             }
-            _ => bail!("not an array frame"),
         }
     }
 
     pub fn check(src: &mut Cursor<&[u8]>) -> Result<(), Error> {
+        Frame::check_within(src, &Limits::NONE)
+    }
+
+    /// Checks for a whole frame as `check` does, failing as soon as a header
+    /// announces more than `limits` allow.
+    pub fn check_within(src: &mut Cursor<&[u8]>, limits: &Limits) -> Result<(), Error> {
+        Frame::check_value(src, limits, true)
+    }
+
+    /// Only a top-level bulk string may be an RDB payload, which has no
+    /// trailing \r\n; one inside an array is incomplete until that arrives.
+    fn check_value(src: &mut Cursor<&[u8]>, limits: &Limits, top_level: bool) -> Result<(), Error> {
         match get_u8(src)? {
             b'+' => {
                 get_line(src)?;
                 Ok(())
             }
-            b'-' => {
+            b'-' | b',' | b'#' | b'(' => {
                 get_line(src)?;
                 Ok(())
             }
             b':' => {
-                let _ = get_decimal(src)?;
+                get_line(src)?;
                 Ok(())
             }
+            b'=' => {
+                let len = get_decimal(src)?;
+                if len > limits.bulk_len {
+                    return Err("Protocol error: invalid bulk length".into());
+                }
+                skip(src, len.try_into()?)?;
+                skip(src, 2)
+            }
             b'$' => {
                 if b'-' == peek_u8(src)? {
                     // Skip '-1\r\n'
                     skip(src, 4)
                 } else {
                     // Read the bulk string
-                    let len: usize = get_decimal(src)?.try_into()?;
+                    let len = get_decimal(src)?;
+                    if len > limits.bulk_len {
+                        return Err("Protocol error: invalid bulk length".into());
+                    }
+                    let len: usize = len.try_into()?;
 
                     match skip(src, len) {
                         Ok(_) => {
                             // special case for RDB, which does not have trailing \r\n
                             match peek_u8(src) {
                                 Ok(b'\r') => skip(src, 2),
-                                _ => Ok(()),
+                                _ if top_level => Ok(()),
+                                _ => skip(src, 2),
                             }
                         }
                         Err(_) => Err(Error::Incomplete),
@@ -88,14 +276,38 @@ impl Frame {
                 }
             }
             b'*' => {
+                if b'-' == peek_u8(src)? {
+                    // Skip '-1\r\n'
+                    return skip(src, 4);
+                }
+
                 let len = get_decimal(src)?;
+                if len > limits.multibulk_len {
+                    return Err("Protocol error: invalid multibulk length".into());
+                }
 
                 for _ in 0..len {
-                    Frame::check(src)?;
+                    Frame::check_value(src, limits, false)?;
                 }
 
                 Ok(())
             }
+            kind @ (b'>' | b'~' | b'%') => {
+                let len = get_decimal(src)?;
+                if len > limits.multibulk_len {
+                    return Err("Protocol error: invalid multibulk length".into());
+                }
+                // a map's entries are a key and a value each
+                let values = if kind == b'%' {
+                    len.saturating_mul(2)
+                } else {
+                    len
+                };
+                for _ in 0..values {
+                    Frame::check_value(src, limits, false)?;
+                }
+                Ok(())
+            }
             actual => Err(format!("protocol error; invalid frame type byte `{}`", actual).into()),
         }
     }
@@ -116,6 +328,12 @@ impl Frame {
 
                 Ok(Frame::Error(string))
             }
+            b':' if peek_u8(src)? == b'-' => {
+                let line = get_line(src)?;
+                atoi::atoi::<i64>(line)
+                    .map(Frame::Signed)
+                    .ok_or_else(|| "protocol error; invalid frame format".into())
+            }
             b':' => {
                 let len = get_decimal(src)?;
                 Ok(Frame::Integer(len))
@@ -135,17 +353,24 @@ impl Frame {
                     let data = Bytes::copy_from_slice(&src.chunk()[..len]);
 
                     skip(src, len)?;
-                    match peek_u8(src) {
-                        Ok(b'\r') => {
-                            skip(src, 2)?;
-                        }
-                        _ => {}
+                    if let Ok(b'\r') = peek_u8(src) {
+                        skip(src, 2)?;
                     }
 
                     Ok(Frame::Bulk(data))
                 }
             }
             b'*' => {
+                if b'-' == peek_u8(src)? {
+                    let line = get_line(src)?;
+
+                    if line != b"-1" {
+                        return Err("protocol error; invalid frame format".into());
+                    }
+
+                    return Ok(Frame::NullArray);
+                }
+
                 let len = get_decimal(src)?.try_into()?;
                 let mut out = Vec::with_capacity(len);
 
@@ -155,6 +380,60 @@ impl Frame {
 
                 Ok(Frame::Array(out))
             }
+            kind @ (b'>' | b'~') => {
+                let len = get_decimal(src)?.try_into()?;
+                let mut out = Vec::with_capacity(len);
+                for _ in 0..len {
+                    out.push(Frame::parse(src)?);
+                }
+                if kind == b'>' {
+                    Ok(Frame::Push(out))
+                } else {
+                    Ok(Frame::Set(out))
+                }
+            }
+            b',' => {
+                let line =
+                    str::from_utf8(get_line(src)?).map_err(|_| "protocol error; invalid double")?;
+                let val = line.parse().map_err(|_| "protocol error; invalid double")?;
+                Ok(Frame::Double(val))
+            }
+            b'#' => match get_line(src)? {
+                b"t" => Ok(Frame::Boolean(true)),
+                b"f" => Ok(Frame::Boolean(false)),
+                _ => Err("protocol error; invalid boolean".into()),
+            },
+            b'(' => {
+                let line = get_line(src)?.to_vec();
+                Ok(Frame::BigNumber(String::from_utf8(line)?))
+            }
+            b'=' => {
+                let len = get_decimal(src)?.try_into()?;
+                if src.remaining() < len + 2 {
+                    return Err(Error::Incomplete);
+                }
+                let data = &src.chunk()[..len];
+                let Some((format, text)) = data
+                    .split_first_chunk::<3>()
+                    .filter(|(_, rest)| rest.first() == Some(&b':'))
+                else {
+                    return Err("protocol error; invalid verbatim string".into());
+                };
+                let frame = Frame::Verbatim(
+                    String::from_utf8(format.to_vec())?,
+                    Bytes::copy_from_slice(&text[1..]),
+                );
+                skip(src, len + 2)?;
+                Ok(frame)
+            }
+            b'%' => {
+                let len = get_decimal(src)?.try_into()?;
+                let mut out = Vec::with_capacity(len);
+                for _ in 0..len {
+                    out.push((Frame::parse(src)?, Frame::parse(src)?));
+                }
+                Ok(Frame::Map(out))
+            }
             _ => unimplemented!(),
         }
     }
@@ -175,16 +454,20 @@ impl fmt::Display for Frame {
         use std::str;
 
         match self {
-            Frame::Simple(response) => response.fmt(fmt),
+            Frame::Simple(response) | Frame::BigNumber(response) => response.fmt(fmt),
             Frame::Error(msg) => write!(fmt, "error: {}", msg),
             Frame::Integer(num) => num.fmt(fmt),
+            Frame::Signed(num) => num.fmt(fmt),
             Frame::Bulk(msg) => match str::from_utf8(msg) {
                 Ok(string) => string.fmt(fmt),
                 Err(_) => write!(fmt, "{:?}", msg),
             },
-            Frame::Null => "(nil)".fmt(fmt),
+            Frame::Null | Frame::NullArray => "(nil)".fmt(fmt),
             Frame::OK => "OK".fmt(fmt),
-            Frame::Array(parts) => {
+            Frame::Double(num) => double_repr(*num).fmt(fmt),
+            Frame::Boolean(val) => write!(fmt, "({})", val),
+            Frame::Verbatim(_, text) => String::from_utf8_lossy(text).fmt(fmt),
+            Frame::Array(parts) | Frame::Push(parts) | Frame::Set(parts) => {
                 for (i, part) in parts.iter().enumerate() {
                     if i > 0 {
                         write!(fmt, " ")?;
@@ -195,6 +478,15 @@ impl fmt::Display for Frame {
 
                 Ok(())
             }
+            Frame::Map(pairs) => {
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, " ")?;
+                    }
+                    write!(fmt, "{} {}", key, value)?;
+                }
+                Ok(())
+            }
             Frame::RdbFile(_) => write!(fmt, "RDB file"),
         }
     }
@@ -266,6 +558,12 @@ impl From<FromUtf8Error> for Error {
     }
 }
 
+impl From<io::Error> for Error {
+    fn from(src: io::Error) -> Error {
+        Error::Other(src.into())
+    }
+}
+
 impl From<TryFromIntError> for Error {
     fn from(_src: TryFromIntError) -> Error {
         "protocol error; invalid frame format".into()
@@ -283,6 +581,48 @@ impl fmt::Display for Error {
     }
 }
 
+/// The number of digits in `n`.
+fn decimal_len(n: u64) -> usize {
+    n.checked_ilog10().map_or(1, |log| log as usize + 1)
+}
+
+fn put_decimal(out: &mut BytesMut, mut n: u64) {
+    let mut digits = [0u8; 20];
+    let mut start = digits.len();
+    loop {
+        start -= 1;
+        digits[start] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    out.put(&digits[start..]);
+}
+
+/// A type byte, `n` and \r\n, as integers and length headers are written.
+/// A double as RESP3 spells it, with `inf`, `-inf` and `nan` for the
+/// values that aren't numbers.
+fn double_repr(val: f64) -> String {
+    if val.is_nan() {
+        "nan".to_string()
+    } else {
+        val.to_string()
+    }
+}
+
+fn put_header(out: &mut BytesMut, kind: u8, n: u64) {
+    out.put_u8(kind);
+    put_decimal(out, n);
+    out.put(&b"\r\n"[..]);
+}
+
+fn put_line(out: &mut BytesMut, kind: u8, line: &[u8]) {
+    out.put_u8(kind);
+    out.put(line);
+    out.put(&b"\r\n"[..]);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -320,30 +660,134 @@ mod tests {
     }
 
     #[test]
-    fn push_int_when_array() -> anyhow::Result<()> {
-        let mut array = Frame::array();
-        array.push_int(42)?;
+    fn from_error_keeps_existing_code() {
+        let err = anyhow::anyhow!("WRONGTYPE Operation against a key");
+        assert_eq!(
+            Frame::from_error(&err),
+            Frame::Error("WRONGTYPE Operation against a key".to_string())
+        );
+        let err = anyhow::anyhow!("protocol error; invalid string");
+        assert_eq!(
+            Frame::from_error(&err),
+            Frame::Error("ERR protocol error; invalid string".to_string())
+        );
+    }
 
-        match array {
-            Frame::Array(vec) => {
-                assert_eq!(vec.len(), 1);
-                assert_eq!(vec[0], Frame::Integer(42));
-            }
-            _ => unreachable!(),
-        }
+    #[test]
+    fn encoded_len_counts_wire_bytes() {
+        let frame = Frame::Array(vec![
+            Frame::Bulk("set".into()),
+            Frame::Bulk("foo".into()),
+            Frame::Integer(123),
+            Frame::Null,
+        ]);
+        assert_eq!(
+            frame.encoded_len(),
+            "*4\r\n$3\r\nset\r\n$3\r\nfoo\r\n:123\r\n$-1\r\n".len()
+        );
+    }
 
-        Ok(())
+    #[test]
+    fn to_bytes_matches_the_wire_format() {
+        let frame = Frame::Array(vec![
+            Frame::Bulk("set".into()),
+            Frame::Integer(123),
+            Frame::Array(vec![Frame::Null, Frame::OK]),
+        ]);
+        assert_eq!(
+            frame.to_bytes(),
+            Bytes::from("*3\r\n$3\r\nset\r\n:123\r\n*2\r\n$-1\r\n+OK\r\n")
+        );
     }
 
     #[test]
-    fn push_int_when_not_array() -> anyhow::Result<()> {
-        let mut frame = Frame::Simple("hello".to_string());
-        let result = frame.push_int(42);
+    fn integers_encode_at_every_width() {
+        for (frame, wire) in [
+            (Frame::Integer(0), ":0\r\n".to_string()),
+            (Frame::Integer(u64::MAX), format!(":{}\r\n", u64::MAX)),
+            (Frame::Signed(-7), ":-7\r\n".to_string()),
+            (Frame::Signed(i64::MIN), format!(":{}\r\n", i64::MIN)),
+            (Frame::Signed(10), ":10\r\n".to_string()),
+        ] {
+            let mut out = BytesMut::from(&b"+OK\r\n"[..]);
+            frame.encode(&mut out);
+            assert_eq!(&out[5..], wire.as_bytes());
+            assert_eq!(frame.encoded_len(), wire.len());
+        }
+    }
 
-        assert!(result.is_err());
-        assert_eq!(frame, Frame::Simple("hello".to_string()));
+    #[test]
+    fn resp3_frames_round_trip() {
+        for (frame, wire) in [
+            (
+                Frame::Push(vec![
+                    Frame::Bulk("invalidate".into()),
+                    Frame::Array(vec![Frame::Bulk("k".into())]),
+                ]),
+                &b">2\r\n$10\r\ninvalidate\r\n*1\r\n$1\r\nk\r\n"[..],
+            ),
+            (
+                Frame::Map(vec![(Frame::Bulk("proto".into()), Frame::Integer(3))]),
+                &b"%1\r\n$5\r\nproto\r\n:3\r\n"[..],
+            ),
+            (
+                Frame::Set(vec![Frame::Bulk("a".into()), Frame::Integer(1)]),
+                &b"~2\r\n$1\r\na\r\n:1\r\n"[..],
+            ),
+            (Frame::Double(1.5), &b",1.5\r\n"[..]),
+            (Frame::Double(-3.0), &b",-3\r\n"[..]),
+            (Frame::Double(f64::INFINITY), &b",inf\r\n"[..]),
+            (Frame::Double(f64::NEG_INFINITY), &b",-inf\r\n"[..]),
+            (Frame::Boolean(true), &b"#t\r\n"[..]),
+            (Frame::Boolean(false), &b"#f\r\n"[..]),
+            (
+                Frame::BigNumber("-3492890328409238509324850943850943825024385".into()),
+                &b"(-3492890328409238509324850943850943825024385\r\n"[..],
+            ),
+            (
+                Frame::Verbatim("txt".into(), "Some string".into()),
+                &b"=15\r\ntxt:Some string\r\n"[..],
+            ),
+            (
+                Frame::Array(vec![Frame::Boolean(true), Frame::Double(0.25)]),
+                &b"*2\r\n#t\r\n,0.25\r\n"[..],
+            ),
+        ] {
+            assert_eq!(&frame.to_bytes()[..], wire);
+            assert_eq!(frame.encoded_len(), wire.len());
+            let mut cursor = Cursor::new(wire);
+            Frame::check(&mut cursor).unwrap();
+            cursor.set_position(0);
+            assert_eq!(Frame::parse(&mut cursor).unwrap(), frame);
+        }
+    }
 
-        Ok(())
+    #[test]
+    fn negative_integers_parse_as_signed() {
+        let mut cursor: Cursor<&[u8]> = Cursor::new(b":-3\r\n");
+        Frame::check(&mut cursor).unwrap();
+        cursor.set_position(0);
+        assert_eq!(Frame::parse(&mut cursor).unwrap(), Frame::Signed(-3));
+    }
+
+    #[test]
+    fn check_within_refuses_oversized_headers() {
+        let limits = Limits {
+            bulk_len: 5,
+            multibulk_len: 2,
+            query_buffer: usize::MAX,
+        };
+        let mut cursor: Cursor<&[u8]> = Cursor::new(b"*2\r\n$5\r\nhello\r\n$6\r\n");
+        let err = Frame::check_within(&mut cursor, &limits).unwrap_err();
+        assert_eq!(err.to_string(), "Protocol error: invalid bulk length");
+
+        let mut cursor: Cursor<&[u8]> = Cursor::new(b"*3\r\n");
+        let err = Frame::check_within(&mut cursor, &limits).unwrap_err();
+        assert_eq!(err.to_string(), "Protocol error: invalid multibulk length");
+
+        // a header within the limits waits on the rest of the frame
+        let mut cursor: Cursor<&[u8]> = Cursor::new(b"$99999999999\r\n");
+        assert!(matches!(Frame::check(&mut cursor), Err(Error::Incomplete)));
     }
 
     #[test]
@@ -381,6 +825,14 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn check_waits_for_the_end_of_a_bulk_in_an_array() {
+        let mut cursor: Cursor<&[u8]> = Cursor::new(b"*1\r\n$5\r\nhello");
+        assert!(matches!(Frame::check(&mut cursor), Err(Error::Incomplete)));
+        let mut cursor: Cursor<&[u8]> = Cursor::new(b"$5\r\nhello");
+        assert!(Frame::check(&mut cursor).is_ok());
+    }
+
     #[test]
     fn parse_simple() {
         let mut cursor: Cursor<&[u8]> = Cursor::new(b"+simple\r\n");
@@ -411,6 +863,14 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_null_array() {
+        let mut cursor: Cursor<&[u8]> = Cursor::new(b"*-1\r\n");
+        assert!(Frame::check(&mut cursor).is_ok());
+        cursor.set_position(0);
+        assert_eq!(Frame::parse(&mut cursor).unwrap(), Frame::NullArray);
+    }
+
     #[test]
     fn parse_integer() {
         let mut cursor: Cursor<&[u8]> = Cursor::new(b":42\r\n");
@@ -0,0 +1,125 @@
+//! Redis-style glob patterns, as used by CONFIG GET and friends: `*`, `?`,
+//! `[...]` classes with ranges and `^` negation, and `\` escapes.
+
+/// Whether `string` matches `pattern`, optionally ignoring ASCII case.
+pub fn matches(pattern: &[u8], string: &[u8], nocase: bool) -> bool {
+    let eq = |a: u8, b: u8| {
+        if nocase {
+            a.eq_ignore_ascii_case(&b)
+        } else {
+            a == b
+        }
+    };
+    let (mut p, mut s) = (0, 0);
+    while p < pattern.len() {
+        match pattern[p] {
+            b'*' => {
+                while p + 1 < pattern.len() && pattern[p + 1] == b'*' {
+                    p += 1;
+                }
+                if p + 1 == pattern.len() {
+                    return true;
+                }
+                return (s..=string.len())
+                    .any(|s| matches(&pattern[p + 1..], &string[s..], nocase));
+            }
+            b'?' => {
+                if s == string.len() {
+                    return false;
+                }
+                s += 1;
+            }
+            b'[' => {
+                let Some(&c) = string.get(s) else {
+                    return false;
+                };
+                p += 1;
+                let negate = pattern.get(p) == Some(&b'^');
+                if negate {
+                    p += 1;
+                }
+                let mut matched = false;
+                while p < pattern.len() && pattern[p] != b']' {
+                    if pattern[p] == b'\\' && p + 1 < pattern.len() {
+                        p += 1;
+                        matched |= eq(pattern[p], c);
+                    } else if p + 2 < pattern.len() && pattern[p + 1] == b'-' {
+                        let (mut start, mut end) = (pattern[p], pattern[p + 2]);
+                        if start > end {
+                            std::mem::swap(&mut start, &mut end);
+                        }
+                        let in_range = |c: u8| start <= c && c <= end;
+                        matched |= if nocase {
+                            in_range(c.to_ascii_lowercase()) || in_range(c.to_ascii_uppercase())
+                        } else {
+                            in_range(c)
+                        };
+                        p += 2;
+                    } else {
+                        matched |= eq(pattern[p], c);
+                    }
+                    p += 1;
+                }
+                if matched == negate {
+                    return false;
+                }
+                s += 1;
+            }
+            b'\\' if p + 1 < pattern.len() => {
+                p += 1;
+                if s == string.len() || !eq(pattern[p], string[s]) {
+                    return false;
+                }
+                s += 1;
+            }
+            c => {
+                if s == string.len() || !eq(c, string[s]) {
+                    return false;
+                }
+                s += 1;
+            }
+        }
+        p += 1;
+    }
+    s == string.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::matches;
+
+    #[test]
+    fn wildcards_classes_and_escapes() {
+        let cases: &[(&str, &str, bool)] = &[
+            ("*", "", true),
+            ("*", "anything", true),
+            ("h?llo", "hello", true),
+            ("h?llo", "hllo", false),
+            ("h*llo", "heeeello", true),
+            ("h*llo", "hello world", false),
+            ("h[ae]llo", "hallo", true),
+            ("h[ae]llo", "hillo", false),
+            ("h[^e]llo", "hallo", true),
+            ("h[^e]llo", "hello", false),
+            ("h[a-b]llo", "hbllo", true),
+            ("h[a-b]llo", "hcllo", false),
+            ("h\\*llo", "h*llo", true),
+            ("h\\*llo", "hello", false),
+            ("repl-*", "repl-timeout", true),
+            ("*-size", "repl-backlog-size", true),
+            ("*a*b", "xaxxb", true),
+            ("*a*b", "xaxxbc", false),
+        ];
+        for (pattern, string, expected) in cases {
+            assert_eq!(
+                matches(pattern.as_bytes(), string.as_bytes(), false),
+                *expected,
+                "{} against {}",
+                pattern,
+                string
+            );
+        }
+        assert!(matches(b"DB*", b"dbfilename", true));
+        assert!(!matches(b"DB*", b"dbfilename", false));
+    }
+}
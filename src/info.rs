@@ -1,6 +1,10 @@
 use anyhow::{ensure, Context};
 
-use crate::store::Store;
+use crate::store::{
+    config::{DEFAULT_HOST, DEFAULT_PORT},
+    role::Role,
+    Store,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Info {
@@ -18,8 +22,6 @@ pub struct Replication {
     pub master_repl_offset: Option<u64>,
 }
 
-pub const DEFAULT_MASTER_REPLID: &str = "8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb";
-
 impl Replication {
     pub fn master_address(&self) -> anyhow::Result<String> {
         ensure!(
@@ -67,9 +69,6 @@ impl Default for Replication {
 }
 
 const DEFAULT_ROLE: &str = "master";
-const DEFAULT_HOST: &str = "127.0.0.1";
-const DEFAULT_PORT: u16 = 6379;
-const STORE_PREFIX: &str = "INFO:";
 
 impl Info {
     pub fn new(self_host: String, self_port: u16, replication: Replication) -> Self {
@@ -89,60 +88,22 @@ impl Info {
     }
 
     pub fn from_store(store: &Store) -> anyhow::Result<Self> {
-        let self_host =
-            if let Some(self_host) = store.get(format!("{}SELF_HOST", STORE_PREFIX).into()) {
-                String::from_utf8(self_host.to_vec()).context("invalid self_host bytes")?
-            } else {
-                DEFAULT_HOST.to_string()
-            };
-        let self_port =
-            if let Some(self_port) = store.get(format!("{}SELF_PORT", STORE_PREFIX).into()) {
-                String::from_utf8(self_port.to_vec())
-                    .context("invalid self_port bytes")?
-                    .parse::<u16>()
-                    .context("invalid self_port u16")?
-            } else {
-                DEFAULT_PORT
-            };
-        let replication_role = if let Some(replication_role) =
-            store.get(format!("{}REPLICATION:ROLE", STORE_PREFIX).into())
+        let self_host = store.config().host();
+        let self_port = store.config().port();
+        let (replication_role, replication_of_host, replication_of_port) = match store.role().get()
         {
-            String::from_utf8(replication_role.to_vec())
-                .context("invalid replication_role bytes")?
-        } else {
-            DEFAULT_ROLE.to_string()
-        };
-        let replication_of_host = if let Some(replication_of_host) =
-            store.get(format!("{}REPLICATION:REPLICATION_OF_HOST", STORE_PREFIX).into())
-        {
-            Some(
-                String::from_utf8(replication_of_host.to_vec())
-                    .context("invalid replication_of_host bytes")?,
-            )
-        } else {
-            None
-        };
-        let replication_of_port = if let Some(replication_of_port) =
-            store.get(format!("{}REPLICATION:REPLICATION_OF_PORT", STORE_PREFIX).into())
-        {
-            Some(
-                String::from_utf8(replication_of_port.to_vec())
-                    .context("invalid replication_of_port bytes")?
-                    .parse::<u16>()
-                    .context("invalid replication_of_port u16")?,
-            )
-        } else {
-            None
+            Role::Master => (DEFAULT_ROLE.to_string(), None, None),
+            Role::Replica { host, port } => ("slave".to_string(), Some(host), Some(port)),
         };
         let master_replid = if replication_role == "slave" {
             None
         } else {
-            Some(DEFAULT_MASTER_REPLID.to_string())
+            Some(store.replicas().replid())
         };
         let master_repl_offset = if replication_role == "slave" {
             None
         } else {
-            Some(0)
+            Some(store.master_repl_offset())
         };
         let replication = Replication {
             role: replication_role,
@@ -160,30 +121,25 @@ impl Info {
     }
 
     pub fn write(&self, store: &Store) -> anyhow::Result<()> {
-        store.set_with_default_expiry(
-            format!("{}SELF_HOST", STORE_PREFIX).into(),
-            self.self_host.clone().into(),
-        );
-        store.set_with_default_expiry(
-            format!("{}SELF_PORT", STORE_PREFIX).into(),
-            self.self_port.to_string().into(),
-        );
-        store.set_with_default_expiry(
-            format!("{}REPLICATION:ROLE", STORE_PREFIX).into(),
-            self.replication.role.clone().into(),
-        );
-        if let Some(replication_of_host) = &self.replication.replication_of_host {
-            store.set_with_default_expiry(
-                format!("{}REPLICATION:REPLICATION_OF_HOST", STORE_PREFIX).into(),
-                replication_of_host.clone().into(),
-            );
-        }
-        if let Some(replication_of_port) = &self.replication.replication_of_port {
-            store.set_with_default_expiry(
-                format!("{}REPLICATION:REPLICATION_OF_PORT", STORE_PREFIX).into(),
-                replication_of_port.to_string().into(),
-            );
-        }
+        store.config().set_host(self.self_host.clone());
+        store.config().set_port(self.self_port);
+        // the role is shared state of its own, as REPLICAOF changes it
+        let role = if self.is_replica() {
+            Role::Replica {
+                host: self
+                    .replication
+                    .replication_of_host
+                    .clone()
+                    .context("expecting replication_of_host")?,
+                port: self
+                    .replication
+                    .replication_of_port
+                    .context("expecting replication_of_port")?,
+            }
+        } else {
+            Role::Master
+        };
+        store.role().set(role);
         Ok(())
     }
 }
@@ -309,6 +265,7 @@ mod tests {
 
         let saved_info = Info::from_store(&store)?;
         assert_eq!(saved_info, info);
+        assert!(store.snapshot().is_empty());
 
         Ok(())
     }
@@ -1,11 +1,33 @@
+//! A Redis-compatible server. Requests come off the wire as `frame::Frame`s
+//! through `connection::Connection`, become a `command::Command` and run
+//! against the `store::Store`; `server` ties those together per client.
+//! These are the one protocol stack the crate has.
+
+// first, so that every module has the `log_*!` macros
+#[macro_use]
+pub mod log;
+
+pub mod cache;
 pub mod cli;
+pub mod client;
+pub mod cluster;
+pub mod codec;
 pub mod command;
 pub mod comms;
 pub mod connection;
+pub mod digest;
+pub mod error;
 pub mod frame;
+pub mod glob;
 pub mod info;
+pub mod metrics;
 pub mod parse;
 pub mod publisher;
+pub mod rdb;
 pub mod replicator;
+pub mod scripting;
+pub mod sentinel;
 pub mod server;
+pub mod shutdown;
 pub mod store;
+pub mod tls;
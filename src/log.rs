@@ -0,0 +1,220 @@
+//! The server log, written the way Redis writes it: one line per event,
+//! `pid:role day month year time mark message`, kept to events at or above
+//! `loglevel` and sent to `logfile`, or stderr when there isn't one.
+//!
+//! The level and file are the process's, as they are Redis's, so the
+//! `log_*!` macros may be used from anywhere without a `Store` at hand.
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How much is logged, from the most to the least.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Debug,
+    Verbose,
+    Notice,
+    Warning,
+}
+
+impl Level {
+    pub fn parse(name: &str) -> Option<Level> {
+        match name.to_ascii_lowercase().as_str() {
+            "debug" => Some(Level::Debug),
+            "verbose" => Some(Level::Verbose),
+            "notice" => Some(Level::Notice),
+            "warning" => Some(Level::Warning),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Level::Debug => "debug",
+            Level::Verbose => "verbose",
+            Level::Notice => "notice",
+            Level::Warning => "warning",
+        }
+    }
+
+    /// The character Redis marks a line at this level with.
+    fn mark(&self) -> char {
+        match self {
+            Level::Debug => '.',
+            Level::Verbose => '-',
+            Level::Notice => '*',
+            Level::Warning => '#',
+        }
+    }
+
+    fn from_u8(level: u8) -> Level {
+        match level {
+            0 => Level::Debug,
+            1 => Level::Verbose,
+            2 => Level::Notice,
+            _ => Level::Warning,
+        }
+    }
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Notice as u8);
+/// `M` for a master, `S` for a replica.
+static ROLE: AtomicU8 = AtomicU8::new(b'M');
+static LOGFILE: Mutex<Option<(String, File)>> = Mutex::new(None);
+
+pub fn level() -> Level {
+    Level::from_u8(LEVEL.load(Ordering::Relaxed))
+}
+
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(level: Level) -> bool {
+    level >= self::level()
+}
+
+pub fn set_replica(replica: bool) {
+    ROLE.store(if replica { b'S' } else { b'M' }, Ordering::Relaxed);
+}
+
+/// Appends the log to `path` from now on; an empty path logs to stderr.
+pub fn set_logfile(path: &str) -> io::Result<()> {
+    let file = match path {
+        "" => None,
+        path => Some((
+            path.to_string(),
+            OpenOptions::new().create(true).append(true).open(path)?,
+        )),
+    };
+    *LOGFILE.lock().unwrap() = file;
+    Ok(())
+}
+
+pub fn logfile() -> String {
+    LOGFILE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|(path, _)| path.clone())
+        .unwrap_or_default()
+}
+
+/// Logs `message` if `level` is enabled. The `log_*!` macros check the level
+/// before formatting anything, so prefer them.
+pub fn write(level: Level, message: fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+    let line = format!(
+        "{}:{} {} {} {}\n",
+        std::process::id(),
+        ROLE.load(Ordering::Relaxed) as char,
+        timestamp(SystemTime::now()),
+        level.mark(),
+        message
+    );
+    // a log that can't be written has nowhere to report that
+    match LOGFILE.lock().unwrap().as_mut() {
+        Some((_, file)) => {
+            let _ = file.write_all(line.as_bytes());
+        }
+        // eprint! rather than stderr itself, so that tests capture it
+        None => eprint!("{}", line),
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// `16 Oct 2026 09:30:00.123`, in UTC.
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let secs_of_day = secs % 86400;
+    format!(
+        "{:02} {} {} {:02}:{:02}:{:02}.{:03}",
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// The year, month and day `days` after 1970-01-01, by Howard Hinnant's
+/// `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[macro_export]
+macro_rules! log_at {
+    ($level:expr, $($arg:tt)+) => {
+        if $crate::log::enabled($level) {
+            $crate::log::write($level, format_args!($($arg)+));
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)+) => { $crate::log_at!($crate::log::Level::Debug, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! log_verbose {
+    ($($arg:tt)+) => { $crate::log_at!($crate::log::Level::Verbose, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! log_notice {
+    ($($arg:tt)+) => { $crate::log_at!($crate::log::Level::Notice, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! log_warning {
+    ($($arg:tt)+) => { $crate::log_at!($crate::log::Level::Warning, $($arg)+) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn levels_order_from_debug_to_warning() {
+        assert!(Level::Debug < Level::Verbose && Level::Notice < Level::Warning);
+        assert_eq!(Level::parse("VERBOSE"), Some(Level::Verbose));
+        assert_eq!(Level::parse("loud"), None);
+        for level in [Level::Debug, Level::Verbose, Level::Notice, Level::Warning] {
+            assert_eq!(Level::from_u8(level as u8), level);
+        }
+    }
+
+    #[test]
+    fn timestamps_read_as_redis_writes_them() {
+        let at =
+            |secs, millis| UNIX_EPOCH + Duration::from_secs(secs) + Duration::from_millis(millis);
+        assert_eq!(timestamp(at(0, 0)), "01 Jan 1970 00:00:00.000");
+        // 2024 was a leap year
+        assert_eq!(timestamp(at(1709210096, 7)), "29 Feb 2024 12:34:56.007");
+    }
+}
@@ -1,14 +1,117 @@
+use anyhow::Context;
 use clap::Parser;
-use redis_starter_rust::{cli::Cli, server, store::Store};
+use redis_starter_rust::{
+    cli::Cli,
+    cluster, log, log_warning, metrics, sentinel,
+    server::{self, TlsListener},
+    store::Store,
+};
+use std::path::Path;
+use std::time::Duration;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
+    log::set_level(cli.log_level());
+    log::set_logfile(&cli.logfile).with_context(|| format!("can't open {}", cli.logfile))?;
     let info = cli.to_info();
     let store = Store::new();
     info.write(&store)?;
+    store.set_notify_keyspace_events(cli.notify_flags()?);
+    store.replicas().set_backlog_size(cli.repl_backlog_size);
+    store.set_repl_timeout(Duration::from_secs(cli.repl_timeout));
+    store.config().set_timeout(cli.timeout);
+    store.clients().set_max_clients(cli.maxclients);
+    store.config().set_dir(cli.dir.clone());
+    store.config().set_dbfilename(cli.dbfilename.clone());
+    store.config().set_save(cli.save_rules()?);
+    store.config().set_appendonly(cli.appendonly == "yes");
+    store.cluster().set_enabled(cli.cluster_enabled == "yes");
+    store
+        .config()
+        .set_appendfilename(cli.appendfilename.clone());
+    store.config().set_aclfile(cli.aclfile.clone());
+    store
+        .config()
+        .set_enable_debug_command(cli.enable_debug_command.clone());
+    if !cli.aclfile.is_empty() {
+        store
+            .acl()
+            .load(Path::new(&cli.aclfile))
+            .map_err(anyhow::Error::msg)?;
+    }
+    if cli.tls_replication == "yes" {
+        store.set_replication_tls(Some(cli.tls_files().connector()?));
+    }
+    store.load().await?;
     let listener = tokio::net::TcpListener::bind(info.bind_address()).await?;
-    server::run(listener, store.clone()).await?;
+    let tls = match cli.tls_port {
+        0 => None,
+        port => Some(TlsListener {
+            listener: tokio::net::TcpListener::bind((store.config().host(), port)).await?,
+            acceptor: cli.tls_files().acceptor()?,
+        }),
+    };
+    if store.cluster().is_enabled() {
+        let address = format!("{}:{}", store.config().host(), cli.cluster_bus_port()?);
+        let bus = tokio::net::TcpListener::bind(address).await?;
+        let store = store.clone();
+        tokio::spawn(async move {
+            if let Err(err) = cluster::run(bus, store).await {
+                log_warning!("cluster bus error: {:#}", err);
+            }
+        });
+    }
+    if let Some((name, address)) = cli.sentinel_master()? {
+        let down_after = Duration::from_millis(cli.down_after_milliseconds);
+        store.sentinel().monitor(name, address, down_after);
+        let store = store.clone();
+        tokio::spawn(async move {
+            if let Err(err) = sentinel::run(store).await {
+                log_warning!("sentinel error: {:#}", err);
+            }
+        });
+    }
+    if cli.metrics_port != 0 {
+        let metrics =
+            tokio::net::TcpListener::bind((store.config().host(), cli.metrics_port)).await?;
+        let store = store.clone();
+        tokio::spawn(async move {
+            if let Err(err) = metrics::run(metrics, store).await {
+                log_warning!("metrics listener error: {:#}", err);
+            }
+        });
+    }
+    server::run_with_tls(listener, tls, store.clone(), shutdown_signal()).await?;
 
     Ok(())
 }
+
+/// Resolves on ctrl-c, or SIGTERM where there is one.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            log_warning!("can't listen for ctrl-c: {}", err);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(err) => {
+                log_warning!("can't listen for SIGTERM: {}", err);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
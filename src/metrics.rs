@@ -0,0 +1,217 @@
+//! Serves the server's counters over HTTP at `/metrics`, in the Prometheus
+//! text format, reading the same `Stats` that INFO does.
+
+use std::fmt::Write;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::store::{stats::LATENCY_BUCKETS, Store};
+
+/// The most of a request read before it is answered regardless.
+const MAX_REQUEST: usize = 8192;
+
+/// Answers scrapes on `listener` until the server shuts down.
+pub async fn run(listener: TcpListener, store: Store) -> anyhow::Result<()> {
+    let mut shutdown = store.shutdown_listener();
+    loop {
+        let (socket, _) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = shutdown.recv() => return Ok(()),
+        };
+        let store = store.clone();
+        tokio::spawn(async move {
+            if let Err(err) = serve(socket, &store).await {
+                log_verbose!("metrics request failed: {:#}", err);
+            }
+        });
+    }
+}
+
+/// Answers one request and closes the connection.
+async fn serve(mut socket: TcpStream, store: &Store) -> anyhow::Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") && request.len() < MAX_REQUEST {
+        let n = socket.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut words = request.split_whitespace();
+    let (status, body) = match (words.next(), words.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", render(store)),
+        (Some("GET"), _) => ("404 Not Found", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "method not allowed\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await?;
+    Ok(())
+}
+
+/// Every metric, in the Prometheus text exposition format.
+pub fn render(store: &Store) -> String {
+    let stats = store.stats();
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}", name, help, name, kind);
+        for (labels, value) in samples {
+            let _ = writeln!(out, "{}{} {}", name, labels, value);
+        }
+    };
+    let single = |value: u64| [(String::new(), value.to_string())];
+
+    metric(
+        "redis_uptime_seconds",
+        "gauge",
+        "Seconds since the server started.",
+        &single(stats.uptime().as_secs()),
+    );
+    metric(
+        "redis_connected_clients",
+        "gauge",
+        "Clients connected, not counting replicas.",
+        &single(store.clients().connected() as u64),
+    );
+    metric(
+        "redis_connections_received_total",
+        "counter",
+        "Connections accepted.",
+        &single(stats.connections_received()),
+    );
+    metric(
+        "redis_rejected_connections_total",
+        "counter",
+        "Connections turned away for going over maxclients.",
+        &single(stats.rejected_connections()),
+    );
+    metric(
+        "redis_keyspace_hits_total",
+        "counter",
+        "Reads of keys that existed.",
+        &single(stats.keyspace_hits()),
+    );
+    metric(
+        "redis_keyspace_misses_total",
+        "counter",
+        "Reads of keys that didn't exist.",
+        &single(stats.keyspace_misses()),
+    );
+    metric(
+        "redis_expired_keys_total",
+        "counter",
+        "Keys removed for having expired.",
+        &single(stats.expired_keys()),
+    );
+    metric(
+        "redis_evicted_keys_total",
+        "counter",
+        "Keys evicted to stay under maxmemory.",
+        &single(stats.evicted_keys()),
+    );
+
+    let dbs: Vec<_> = store
+        .db_stats()
+        .into_iter()
+        .enumerate()
+        .filter(|(_, db)| db.keys > 0)
+        .collect();
+    let per_db = |value: fn(&crate::store::stats::DbStats) -> usize| -> Vec<_> {
+        dbs.iter()
+            .map(|(i, db)| (format!("{{db=\"db{}\"}}", i), value(db).to_string()))
+            .collect()
+    };
+    metric(
+        "redis_db_keys",
+        "gauge",
+        "Keys in each database that holds any.",
+        &per_db(|db| db.keys),
+    );
+    metric(
+        "redis_db_keys_expiring",
+        "gauge",
+        "Keys with a time to live in each database that holds any.",
+        &per_db(|db| db.expires),
+    );
+
+    let offset = store.master_repl_offset();
+    let replicas = store.replicas().statuses();
+    metric(
+        "redis_connected_slaves",
+        "gauge",
+        "Replicas attached to this server.",
+        &single(replicas.len() as u64),
+    );
+    let per_replica = |value: &dyn Fn(&crate::publisher::ReplicaStatus) -> String| -> Vec<_> {
+        replicas
+            .iter()
+            .map(|replica| {
+                (
+                    format!("{{replica=\"{}\"}}", replica.address),
+                    value(replica),
+                )
+            })
+            .collect()
+    };
+    metric(
+        "redis_replica_lag_seconds",
+        "gauge",
+        "Seconds since each replica last acknowledged the stream.",
+        &per_replica(&|replica| replica.lag.as_secs_f64().to_string()),
+    );
+    metric(
+        "redis_replica_offset_lag_bytes",
+        "gauge",
+        "Bytes of the stream each replica hasn't acknowledged.",
+        &per_replica(&|replica| offset.saturating_sub(replica.offset).to_string()),
+    );
+
+    let commands = stats.commands();
+    metric(
+        "redis_commands_total",
+        "counter",
+        "Commands run, by command.",
+        &commands
+            .iter()
+            .map(|(name, stats)| (format!("{{cmd=\"{}\"}}", name), stats.calls.to_string()))
+            .collect::<Vec<_>>(),
+    );
+    let mut latency = vec![];
+    for (name, stats) in &commands {
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(stats.buckets) {
+            cumulative += count;
+            latency.push((
+                format!("_bucket{{cmd=\"{}\",le=\"{}\"}}", name, *bound as f64 / 1e6),
+                cumulative.to_string(),
+            ));
+        }
+        latency.push((
+            format!("_bucket{{cmd=\"{}\",le=\"+Inf\"}}", name),
+            stats.calls.to_string(),
+        ));
+        latency.push((
+            format!("_sum{{cmd=\"{}\"}}", name),
+            (stats.usec as f64 / 1e6).to_string(),
+        ));
+        latency.push((
+            format!("_count{{cmd=\"{}\"}}", name),
+            stats.calls.to_string(),
+        ));
+    }
+    metric(
+        "redis_command_duration_seconds",
+        "histogram",
+        "How long commands took to run, by command.",
+        &latency,
+    );
+    out
+}
@@ -1,7 +1,8 @@
+use anyhow::bail;
 use bytes::Bytes;
 use std::{fmt, str, vec};
 
-use crate::frame::Frame;
+use crate::{error::Error, frame::Frame};
 
 #[derive(Debug)]
 pub(crate) struct Parse {
@@ -69,6 +70,18 @@ impl Parse {
         }
     }
 
+    /// Every string left, for commands that take a variable number.
+    pub(crate) fn remaining_strings(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut strings = vec![];
+        loop {
+            match self.next_string() {
+                Ok(s) => strings.push(s),
+                Err(ParseError::EndOfStream) => return Ok(strings),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
     pub(crate) fn finish(&mut self) -> Result<(), ParseError> {
         if self.parts.next().is_none() {
             Ok(())
@@ -78,6 +91,112 @@ impl Parse {
     }
 }
 
+/// The options a command takes after its fixed arguments, declared once and
+/// then read off a `Parse`: flags that stand alone, tokens followed by a set
+/// number of values, and groups of options that exclude one another.
+#[derive(Debug, Default)]
+pub(crate) struct ArgSpec {
+    /// Each option, in uppercase, with the number of values it takes.
+    options: Vec<(&'static str, usize)>,
+    exclusive: Vec<&'static [&'static str]>,
+    /// How many values follow an option that isn't declared, if those are
+    /// skipped rather than refused.
+    unknown: Option<usize>,
+}
+
+impl ArgSpec {
+    pub(crate) fn flag(self, name: &'static str) -> ArgSpec {
+        self.token(name, 0)
+    }
+
+    pub(crate) fn token(mut self, name: &'static str, values: usize) -> ArgSpec {
+        self.options.push((name, values));
+        self
+    }
+
+    /// At most one of `group` may be given.
+    pub(crate) fn exclusive(mut self, group: &'static [&'static str]) -> ArgSpec {
+        self.exclusive.push(group);
+        self
+    }
+
+    /// Skips options that aren't declared, each with the `values` after it.
+    pub(crate) fn skip_unknown(mut self, values: usize) -> ArgSpec {
+        self.unknown = Some(values);
+        self
+    }
+
+    /// Reads options, in any order and case, until `parse` runs out. One that
+    /// isn't declared, is short of values or clashes with one given before
+    /// it is a syntax error.
+    pub(crate) fn parse(&self, parse: &mut Parse) -> anyhow::Result<Args> {
+        let mut args = Args::default();
+        loop {
+            let option = match parse.next_string() {
+                Ok(option) => option.to_uppercase(),
+                Err(ParseError::EndOfStream) => return Ok(args),
+                Err(err) => return Err(err.into()),
+            };
+            let Some(&(name, len)) = self.options.iter().find(|(name, _)| *name == option) else {
+                match self.unknown {
+                    Some(len) => {
+                        Self::values(parse, len)?;
+                        continue;
+                    }
+                    None => bail!(Error::Syntax),
+                }
+            };
+            let clashes = self
+                .exclusive
+                .iter()
+                .filter(|group| group.contains(&name))
+                .any(|group| group.iter().any(|other| *other != name && args.has(other)));
+            if clashes {
+                bail!(Error::Syntax);
+            }
+            args.given.push((name, Self::values(parse, len)?));
+        }
+    }
+
+    fn values(parse: &mut Parse, len: usize) -> anyhow::Result<Vec<String>> {
+        let mut values = Vec::with_capacity(len);
+        for _ in 0..len {
+            match parse.next_string() {
+                Ok(value) => values.push(value),
+                Err(ParseError::EndOfStream) => bail!(Error::Syntax),
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(values)
+    }
+}
+
+/// The options `ArgSpec::parse` read, in the order they were given.
+#[derive(Debug, Default)]
+pub(crate) struct Args {
+    given: Vec<(&'static str, Vec<String>)>,
+}
+
+impl Args {
+    pub(crate) fn has(&self, name: &str) -> bool {
+        self.given.iter().any(|(given, _)| *given == name)
+    }
+
+    /// The values of `name`, the last time it was given.
+    pub(crate) fn values(&self, name: &str) -> Option<&[String]> {
+        self.given
+            .iter()
+            .rev()
+            .find(|(given, _)| *given == name)
+            .map(|(_, values)| &values[..])
+    }
+
+    /// The value of a token that takes one.
+    pub(crate) fn value(&self, name: &str) -> Option<&str> {
+        self.values(name)?.first().map(String::as_str)
+    }
+}
+
 impl From<String> for ParseError {
     fn from(src: String) -> ParseError {
         ParseError::Other(anyhow::Error::msg(src))
@@ -100,3 +219,54 @@ impl fmt::Display for ParseError {
 }
 
 impl std::error::Error for ParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(spec: &ArgSpec, args: &[&str]) -> anyhow::Result<Args> {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        );
+        spec.parse(&mut Parse::new(frame)?)
+    }
+
+    #[test]
+    fn options_are_read_in_any_order_and_case() -> anyhow::Result<()> {
+        let spec = ArgSpec::default()
+            .flag("NX")
+            .flag("XX")
+            .token("PX", 1)
+            .token("LIMIT", 2)
+            .exclusive(&["NX", "XX"]);
+
+        let args = parse(&spec, &["limit", "0", "10", "nx", "PX", "5"])?;
+        assert!(args.has("NX") && !args.has("XX"));
+        assert_eq!(args.value("PX"), Some("5"));
+        assert_eq!(
+            args.values("LIMIT"),
+            Some(&["0".to_string(), "10".to_string()][..])
+        );
+
+        for bad in [&["NX", "XX"][..], &["PX"], &["LIMIT", "0"], &["EX", "1"]] {
+            let err = parse(&spec, bad).unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<Error>(),
+                Some(&Error::Syntax),
+                "{:?}",
+                bad
+            );
+        }
+        Ok(())
+    }
+    #[test]
+    fn unknown_options_may_be_skipped_with_their_values() -> anyhow::Result<()> {
+        let spec = ArgSpec::default().token("ACK", 1).skip_unknown(1);
+        let args = parse(&spec, &["FACK", "7", "ack", "31", "rdb-only", "0"])?;
+        assert_eq!(args.value("ACK"), Some("31"));
+        assert!(parse(&spec, &["ACK", "31", "FACK"]).is_err());
+        Ok(())
+    }
+}
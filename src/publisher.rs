@@ -1,11 +1,205 @@
 use bytes::Bytes;
-use once_cell::sync::Lazy;
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, Notify};
+use tokio::time::{timeout_at, Instant};
 
-use crate::{comms::Comms, frame::Frame, store::Store};
+use crate::{
+    command::{zpop::Side, Command},
+    comms::Comms,
+    frame::Frame,
+    store::{
+        cluster::random_id,
+        output::{OutputLimit, Pending, REPLICA_OUTPUT_LIMIT},
+        role::RoleState,
+        sorted_set::format_score,
+        stream::{Fields, StreamId},
+        Store,
+    },
+};
+
+/// `ReplicaSet::db` when replicas may be on different databases.
+const UNKNOWN_DB: usize = usize::MAX;
+
+/// How often replicas are asked for their offset, which doubles as a
+/// liveness check.
+const REPLICA_PING_PERIOD: Duration = Duration::from_secs(10);
+
+/// Replicas that acknowledge nothing for this long are dropped.
+const REPLICA_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Bytes of the replication stream kept for partial resyncs, unless
+/// configured otherwise.
+pub const DEFAULT_BACKLOG_SIZE: usize = 1024 * 1024;
+
+/// A replica attached with PSYNC.
+#[derive(Debug)]
+struct Replica {
+    id: u64,
+    /// The replica's IP and the port it announced it listens on.
+    address: SocketAddr,
+    /// Frames for the replica's connection task to write.
+    sender: mpsc::UnboundedSender<Frame>,
+    /// The bytes of frames queued but not yet written.
+    pending: Arc<Pending>,
+    ack: Arc<Ack>,
+}
+
+/// What a replica last acknowledged, and when.
+#[derive(Debug)]
+struct Ack {
+    /// The master offset the replica has processed up to.
+    offset: AtomicU64,
+    at: Mutex<Instant>,
+}
+
+impl Ack {
+    fn new(offset: u64) -> Self {
+        Self {
+            offset: AtomicU64::new(offset),
+            at: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn record(&self, offset: u64) {
+        self.offset.store(offset, Ordering::SeqCst);
+        *self.at.lock().unwrap() = Instant::now();
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset.load(Ordering::SeqCst)
+    }
+}
+
+/// The tail of the replication stream, kept so a replica that reconnects can
+/// be sent what it missed rather than a whole new snapshot.
+#[derive(Debug)]
+struct Backlog {
+    /// Frames with the offset each starts at, oldest first.
+    frames: VecDeque<(u64, Frame)>,
+    /// Bytes the frames take on the wire.
+    len: usize,
+    size: usize,
+}
+
+impl Default for Backlog {
+    fn default() -> Self {
+        Self {
+            frames: VecDeque::new(),
+            len: 0,
+            size: DEFAULT_BACKLOG_SIZE,
+        }
+    }
+}
+
+impl Backlog {
+    fn push(&mut self, offset: u64, frame: Frame) {
+        self.len += frame.encoded_len();
+        self.frames.push_back((offset, frame));
+        self.trim();
+    }
+
+    fn resize(&mut self, size: usize) {
+        self.size = size;
+        self.trim();
+    }
+
+    fn clear(&mut self) {
+        self.frames.clear();
+        self.len = 0;
+    }
+
+    fn trim(&mut self) {
+        while self.len > self.size {
+            let Some((_, frame)) = self.frames.pop_front() else {
+                break;
+            };
+            self.len -= frame.encoded_len();
+        }
+    }
+
+    /// Every frame from `offset` up to `end`, the current end of the stream,
+    /// or None if some have already been dropped or `offset` falls inside a
+    /// frame.
+    fn since(&self, offset: u64, end: u64) -> Option<Vec<Frame>> {
+        if offset == end {
+            return Some(vec![]);
+        }
+        let start = self.frames.iter().position(|(at, _)| *at == offset)?;
+        Some(
+            self.frames
+                .range(start..)
+                .map(|(_, frame)| frame.clone())
+                .collect(),
+        )
+    }
+}
+
+/// What INFO reports about one replica.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplicaStatus {
+    pub address: SocketAddr,
+    /// The offset the replica last acknowledged.
+    pub offset: u64,
+    /// Time since the replica last acknowledged anything.
+    pub lag: Duration,
+}
+
+/// A replica registered by PSYNC whose connection hasn't been handed over
+/// yet. Dropping it detaches the replica.
+#[derive(Debug)]
+pub struct Attached {
+    id: u64,
+    receiver: mpsc::UnboundedReceiver<Frame>,
+    pending: Arc<Pending>,
+    ack: Arc<Ack>,
+}
 
-static SUBSCRIBERS: Lazy<Mutex<Vec<Arc<Mutex<dyn Comms>>>>> = Lazy::new(|| Mutex::new(Vec::new()));
+/// The replicas attached to one server, and the offset of the replication
+/// stream they are sent.
+#[derive(Debug, Clone)]
+pub struct ReplicaSet {
+    replicas: Arc<Mutex<Vec<Replica>>>,
+    next_id: Arc<AtomicU64>,
+    /// Bytes of write commands propagated so far.
+    offset: Arc<AtomicU64>,
+    /// Woken whenever a replica acknowledges an offset.
+    acks: Arc<Notify>,
+    backlog: Arc<Mutex<Backlog>>,
+    /// The id of the master whose stream is forwarded, once synced from one.
+    master_replid: Arc<Mutex<Option<String>>>,
+    /// The id replicas know the stream by: random at startup, and our
+    /// master's once we have synced from one.
+    replid: Arc<Mutex<String>>,
+    /// The id the stream went by before it last changed hands, and the offset
+    /// it changed at. Replicas of the old stream may resume from before it.
+    replid2: Arc<Mutex<Option<(String, u64)>>>,
+    /// The database the stream is on. Replicas start out on database 0.
+    db: Arc<AtomicUsize>,
+    /// How far a replica may fall behind before it is dropped, as the replica
+    /// class of `client-output-buffer-limit` says.
+    output_limit: Arc<Mutex<OutputLimit>>,
+}
+
+impl Default for ReplicaSet {
+    fn default() -> Self {
+        Self {
+            replicas: Default::default(),
+            next_id: Default::default(),
+            offset: Default::default(),
+            acks: Default::default(),
+            backlog: Default::default(),
+            master_replid: Default::default(),
+            replid: Arc::new(Mutex::new(random_id())),
+            replid2: Default::default(),
+            db: Default::default(),
+            output_limit: Arc::new(Mutex::new(REPLICA_OUTPUT_LIMIT)),
+        }
+    }
+}
 
 pub enum Action {
     Set {
@@ -13,45 +207,815 @@ pub enum Action {
         value: Bytes,
         expiry: Option<u64>,
     },
-    //Remove{key: Bytes},
+    Del {
+        key: Bytes,
+    },
+    SetBit {
+        key: Bytes,
+        offset: u64,
+        bit: bool,
+    },
+    BitOp {
+        operation: &'static str,
+        destination: Bytes,
+        keys: Vec<Bytes>,
+    },
+    PfAdd {
+        key: Bytes,
+        elements: Vec<Bytes>,
+    },
+    PfMerge {
+        destination: Bytes,
+        sources: Vec<Bytes>,
+    },
+    ZAdd {
+        key: Bytes,
+        members: Vec<(f64, Bytes)>,
+    },
+    XAdd {
+        key: Bytes,
+        id: StreamId,
+        fields: Fields,
+    },
+    XDel {
+        key: Bytes,
+        ids: Vec<StreamId>,
+    },
+    XTrim {
+        key: Bytes,
+        min_id: StreamId,
+    },
+    XGroup {
+        subcommand: &'static str,
+        key: Bytes,
+        group: Bytes,
+        args: Vec<Bytes>,
+    },
+    XAck {
+        key: Bytes,
+        group: Bytes,
+        ids: Vec<StreamId>,
+    },
+    Publish {
+        channel: Bytes,
+        message: Bytes,
+    },
+    /// Ownership of a pending entry, replicated as a forced XCLAIM the way
+    /// Redis propagates XREADGROUP and XAUTOCLAIM.
+    XClaim {
+        key: Bytes,
+        group: Bytes,
+        consumer: Bytes,
+        id: StreamId,
+        delivery_time: u64,
+        delivery_count: u64,
+        last_id: Option<StreamId>,
+    },
+    /// Pops replicated by how many members went, so BZPOP replicates as the
+    /// ZPOP that satisfied it.
+    ZPop {
+        side: Side,
+        key: Bytes,
+        count: usize,
+    },
+    Function {
+        subcommand: &'static str,
+        args: Vec<Bytes>,
+    },
+    SwapDb {
+        a: usize,
+        b: usize,
+    },
+    Move {
+        key: Bytes,
+        db: usize,
+    },
+    /// A new expiry as unix time in milliseconds.
+    PExpireAt {
+        key: Bytes,
+        expires_at: u64,
+    },
+    Persist {
+        key: Bytes,
+    },
+    /// A key restored from a DUMP payload, with its expiry as unix time in
+    /// milliseconds.
+    Restore {
+        key: Bytes,
+        payload: Bytes,
+        expires_at: Option<u64>,
+    },
+}
+
+/// SELECT `db`, which the replication stream and the AOF send before a write
+/// to a database other than the last one written to.
+pub fn select_frame(db: usize) -> Frame {
+    Frame::Array(vec![
+        Frame::Bulk(Bytes::from("select")),
+        Frame::Bulk(db.to_string().into()),
+    ])
 }
 
-pub async fn publish(action: Action) -> anyhow::Result<()> {
-    match action {
-        Action::Set { key, value, expiry } => {
-            let mut array = Frame::array();
-            array.push_bulk(Bytes::from("set"))?;
-            array.push_bulk(key.clone())?;
-            array.push_bulk(value.clone())?;
-            if let Some(expiry) = expiry {
-                array.push_bulk("PX".into())?;
-                array.push_bulk(expiry.to_string().into())?;
+/// Propagates a write that has just been applied to the store, see
+/// `Store::propagate`. Commands call this after changing the keyspace.
+pub async fn publish(store: &Store, action: Action) -> anyhow::Result<()> {
+    publish_now(store, action)
+}
+
+/// `publish`, for callers outside the async runtime.
+pub fn publish_now(store: &Store, action: Action) -> anyhow::Result<()> {
+    // replaying the AOF, which already holds the write
+    if store.persistence().is_loading() {
+        return Ok(());
+    }
+    // messages reach replicas, but aren't data to persist
+    let durable = !matches!(action, Action::Publish { .. });
+    store.propagate(action.replication_frame()?, durable)
+}
+
+impl Action {
+    /// The command replicas run to repeat the write.
+    pub fn replication_frame(self) -> anyhow::Result<Frame> {
+        let mut array = Frame::array();
+        match self {
+            Action::Set { key, value, expiry } => {
+                array.push_bulk(Bytes::from("set"))?;
+                array.push_bulk(key.clone())?;
+                array.push_bulk(value.clone())?;
+                if let Some(expiry) = expiry {
+                    array.push_bulk("PX".into())?;
+                    array.push_bulk(expiry.to_string().into())?;
+                }
+            }
+            Action::Del { key } => {
+                array.push_bulk(Bytes::from("del"))?;
+                array.push_bulk(key)?;
+            }
+            Action::SetBit { key, offset, bit } => {
+                array.push_bulk(Bytes::from("setbit"))?;
+                array.push_bulk(key)?;
+                array.push_bulk(offset.to_string().into())?;
+                array.push_bulk(Bytes::from(if bit { "1" } else { "0" }))?;
+            }
+            Action::BitOp {
+                operation,
+                destination,
+                keys,
+            } => {
+                array.push_bulk(Bytes::from("bitop"))?;
+                array.push_bulk(Bytes::from(operation))?;
+                array.push_bulk(destination)?;
+                for key in keys {
+                    array.push_bulk(key)?;
+                }
+            }
+            Action::PfAdd { key, elements } => {
+                array.push_bulk(Bytes::from("pfadd"))?;
+                array.push_bulk(key)?;
+                for element in elements {
+                    array.push_bulk(element)?;
+                }
+            }
+            Action::PfMerge {
+                destination,
+                sources,
+            } => {
+                array.push_bulk(Bytes::from("pfmerge"))?;
+                array.push_bulk(destination)?;
+                for source in sources {
+                    array.push_bulk(source)?;
+                }
+            }
+            Action::ZAdd { key, members } => {
+                array.push_bulk(Bytes::from("zadd"))?;
+                array.push_bulk(key)?;
+                for (score, member) in members {
+                    array.push_bulk(format_score(score).into())?;
+                    array.push_bulk(member)?;
+                }
+            }
+            Action::XAdd { key, id, fields } => {
+                array.push_bulk(Bytes::from("xadd"))?;
+                array.push_bulk(key)?;
+                array.push_bulk(id.to_string().into())?;
+                for (field, value) in fields {
+                    array.push_bulk(field)?;
+                    array.push_bulk(value)?;
+                }
+            }
+            Action::XDel { key, ids } => {
+                array.push_bulk(Bytes::from("xdel"))?;
+                array.push_bulk(key)?;
+                for id in ids {
+                    array.push_bulk(id.to_string().into())?;
+                }
+            }
+            Action::XTrim { key, min_id } => {
+                array.push_bulk(Bytes::from("xtrim"))?;
+                array.push_bulk(key)?;
+                array.push_bulk(Bytes::from("MINID"))?;
+                array.push_bulk(Bytes::from("="))?;
+                array.push_bulk(min_id.to_string().into())?;
+            }
+            Action::XGroup {
+                subcommand,
+                key,
+                group,
+                args,
+            } => {
+                array.push_bulk(Bytes::from("xgroup"))?;
+                array.push_bulk(Bytes::from(subcommand))?;
+                array.push_bulk(key)?;
+                array.push_bulk(group)?;
+                for arg in args {
+                    array.push_bulk(arg)?;
+                }
+            }
+            Action::XAck { key, group, ids } => {
+                array.push_bulk(Bytes::from("xack"))?;
+                array.push_bulk(key)?;
+                array.push_bulk(group)?;
+                for id in ids {
+                    array.push_bulk(id.to_string().into())?;
+                }
+            }
+            Action::Publish { channel, message } => {
+                array.push_bulk(Bytes::from("publish"))?;
+                array.push_bulk(channel)?;
+                array.push_bulk(message)?;
+            }
+            Action::XClaim {
+                key,
+                group,
+                consumer,
+                id,
+                delivery_time,
+                delivery_count,
+                last_id,
+            } => {
+                array.push_bulk(Bytes::from("xclaim"))?;
+                array.push_bulk(key)?;
+                array.push_bulk(group)?;
+                array.push_bulk(consumer)?;
+                array.push_bulk(Bytes::from("0"))?;
+                array.push_bulk(id.to_string().into())?;
+                array.push_bulk(Bytes::from("TIME"))?;
+                array.push_bulk(delivery_time.to_string().into())?;
+                array.push_bulk(Bytes::from("RETRYCOUNT"))?;
+                array.push_bulk(delivery_count.to_string().into())?;
+                array.push_bulk(Bytes::from("FORCE"))?;
+                array.push_bulk(Bytes::from("JUSTID"))?;
+                if let Some(last_id) = last_id {
+                    array.push_bulk(Bytes::from("LASTID"))?;
+                    array.push_bulk(last_id.to_string().into())?;
+                }
+            }
+            Action::ZPop { side, key, count } => {
+                array.push_bulk(Bytes::from(match side {
+                    Side::Min => "zpopmin",
+                    Side::Max => "zpopmax",
+                }))?;
+                array.push_bulk(key)?;
+                array.push_bulk(count.to_string().into())?;
+            }
+            Action::Function { subcommand, args } => {
+                array.push_bulk(Bytes::from("function"))?;
+                array.push_bulk(Bytes::from(subcommand))?;
+                for arg in args {
+                    array.push_bulk(arg)?;
+                }
+            }
+            Action::SwapDb { a, b } => {
+                array.push_bulk(Bytes::from("swapdb"))?;
+                array.push_bulk(a.to_string().into())?;
+                array.push_bulk(b.to_string().into())?;
+            }
+            Action::Move { key, db } => {
+                array.push_bulk(Bytes::from("move"))?;
+                array.push_bulk(key)?;
+                array.push_bulk(db.to_string().into())?;
+            }
+            Action::PExpireAt { key, expires_at } => {
+                array.push_bulk(Bytes::from("pexpireat"))?;
+                array.push_bulk(key)?;
+                array.push_bulk(expires_at.to_string().into())?;
+            }
+            Action::Persist { key } => {
+                array.push_bulk(Bytes::from("persist"))?;
+                array.push_bulk(key)?;
+            }
+            Action::Restore {
+                key,
+                payload,
+                expires_at,
+            } => {
+                array.push_bulk(Bytes::from("restore"))?;
+                array.push_bulk(key)?;
+                array.push_bulk(expires_at.unwrap_or(0).to_string().into())?;
+                array.push_bulk(payload)?;
+                array.push_bulk(Bytes::from("REPLACE"))?;
+                array.push_bulk(Bytes::from("ABSTTL"))?;
             }
-            publish_frame(array).await
         }
+        Ok(array)
     }
 }
 
-async fn publish_frame(frame: Frame) -> anyhow::Result<()> {
-    let subscribers = SUBSCRIBERS.lock().await;
-    for connection in subscribers.iter() {
-        let mut connection_lock = connection.lock().await;
-        connection_lock.write_frame(&frame).await?;
+impl ReplicaSet {
+    pub fn offset(&self) -> u64 {
+        self.offset.load(Ordering::SeqCst)
+    }
+
+    /// Queues `frame` for every replica and moves the offset past it, without
+    /// waiting for any of them to write it. Replicas whose connection has gone
+    /// away, or whose queue is full, are dropped rather than failing or
+    /// stalling the write that is being propagated.
+    pub(crate) fn publish(&self, frame: Frame) -> anyhow::Result<()> {
+        let mut replicas = self.replicas.lock().unwrap();
+        self.send(&mut replicas, frame);
+        Ok(())
+    }
+
+    /// Publishes a write made in database `db`, selecting it first if the
+    /// stream was last in another.
+    pub(crate) fn publish_in(&self, db: usize, frame: Frame) -> anyhow::Result<()> {
+        let mut replicas = self.replicas.lock().unwrap();
+        if self.db.load(Ordering::SeqCst) != db {
+            self.send(&mut replicas, select_frame(db));
+            self.db.store(db, Ordering::SeqCst);
+        }
+        self.send(&mut replicas, frame);
+        Ok(())
+    }
+
+    fn send(&self, replicas: &mut Vec<Replica>, frame: Frame) {
+        let len = frame.encoded_len() as u64;
+        let offset = self.offset.fetch_add(len, Ordering::SeqCst);
+        self.backlog.lock().unwrap().push(offset, frame.clone());
+        let limit = self.output_limit();
+        replicas.retain(|replica| {
+            if replica.pending.add(len, &limit) {
+                log_warning!(
+                    "dropping replica {}: output buffer over the limit",
+                    replica.id
+                );
+                return false;
+            }
+            if replica.sender.send(frame.clone()).is_err() {
+                log_warning!("dropping replica {}: connection closed", replica.id);
+                return false;
+            }
+            true
+        });
+    }
+
+    /// Registers a new replica, returning the offset its stream starts at, an
+    /// RDB snapshot of `store` as of that offset, and the handle to serve the
+    /// replica's connection with.
+    pub async fn attach(
+        &self,
+        store: &Store,
+        address: SocketAddr,
+    ) -> anyhow::Result<(u64, Bytes, Attached)> {
+        // the keyspace is locked before the replicas, as when a key expires
+        let (rdb, (offset, attached)) = store
+            .as_rdb_while(|| {
+                let mut replicas = self.replicas.lock().unwrap();
+                // the new replica starts out on database 0, whatever the stream is on
+                if self.db.load(Ordering::SeqCst) != 0 {
+                    self.db.store(UNKNOWN_DB, Ordering::SeqCst);
+                }
+                let offset = self.offset();
+                (offset, self.register(&mut replicas, address, offset))
+            })
+            .await?;
+        Ok((offset, rdb, attached))
+    }
+
+    /// Re-registers a replica that has the stream up to `offset`, returning
+    /// the frames it missed since, or None if the backlog no longer has them
+    /// and it needs a full resync.
+    pub fn resume(&self, address: SocketAddr, offset: u64) -> Option<(Vec<Frame>, Attached)> {
+        let mut replicas = self.replicas.lock().unwrap();
+        let missed = self.backlog.lock().unwrap().since(offset, self.offset())?;
+        Some((missed, self.register(&mut replicas, address, offset)))
+    }
+
+    fn register(&self, replicas: &mut Vec<Replica>, address: SocketAddr, offset: u64) -> Attached {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let pending = Arc::new(Pending::default());
+        let ack = Arc::new(Ack::new(offset));
+        replicas.push(Replica {
+            id,
+            address,
+            sender,
+            pending: pending.clone(),
+            ack: ack.clone(),
+        });
+        Attached {
+            id,
+            receiver,
+            pending,
+            ack,
+        }
+    }
+
+    pub fn replid(&self) -> String {
+        self.replid.lock().unwrap().clone()
+    }
+
+    /// The previous id and the offset it was left at, as INFO shows them.
+    pub fn replid2(&self) -> Option<(String, u64)> {
+        self.replid2.lock().unwrap().clone()
+    }
+
+    /// Whether a replica that has read `offset` bytes of the stream known as
+    /// `replid` can carry on from there, backlog permitting.
+    pub fn can_resume(&self, replid: &str, offset: u64) -> bool {
+        if *self.replid.lock().unwrap() == replid {
+            return true;
+        }
+        matches!(&*self.replid2.lock().unwrap(), Some((old, end)) if old == replid && offset <= *end)
+    }
+
+    /// Gives the stream a new id and forgets the previous one, as DEBUG
+    /// CHANGE-REPL-ID does.
+    pub fn change_replid(&self) {
+        *self.replid.lock().unwrap() = random_id();
+        *self.replid2.lock().unwrap() = None;
+    }
+
+    /// Renames the stream from here on, keeping the old id so replicas that
+    /// followed it can still resume.
+    fn shift_replid(&self, replid: String) {
+        let old = std::mem::replace(&mut *self.replid.lock().unwrap(), replid);
+        *self.replid2.lock().unwrap() = Some((old, self.offset()));
+    }
+
+    /// Makes the stream our own on becoming a master: it stops being our old
+    /// master's and gets a new id.
+    pub fn promote(&self) {
+        if self.master_replid.lock().unwrap().take().is_some() {
+            self.shift_replid(random_id());
+        }
+    }
+
+    /// Follows our master onto the new id it announced on a partial resync,
+    /// after it was promoted itself.
+    pub fn rename(&self, replid: String) {
+        let mut master_replid = self.master_replid.lock().unwrap();
+        if master_replid.as_ref() != Some(&replid) {
+            *master_replid = Some(replid.clone());
+            self.shift_replid(replid);
+        }
+    }
+
+    pub fn master_replid(&self) -> Option<String> {
+        self.master_replid.lock().unwrap().clone()
+    }
+
+    /// Starts forwarding the stream of a master that has just resynced us at
+    /// `offset`. Replicas attached so far hold data from before the resync,
+    /// so they are dropped to resync in turn.
+    pub fn resync(&self, replid: String, offset: u64) {
+        let mut replicas = self.replicas.lock().unwrap();
+        replicas.clear();
+        self.offset.store(offset, Ordering::SeqCst);
+        self.backlog.lock().unwrap().clear();
+        // our replicas follow the master's SELECTs, and may be anywhere
+        self.db.store(UNKNOWN_DB, Ordering::SeqCst);
+        *self.master_replid.lock().unwrap() = Some(replid.clone());
+        *self.replid.lock().unwrap() = replid;
+        *self.replid2.lock().unwrap() = None;
+    }
+
+    /// Passes a frame of our master's stream on to our own replicas as is,
+    /// so their offsets match the master's.
+    pub fn forward(&self, frame: Frame) -> anyhow::Result<()> {
+        self.publish(frame)
+    }
+
+    pub fn backlog_size(&self) -> usize {
+        self.backlog.lock().unwrap().size
+    }
+
+    pub fn set_backlog_size(&self, size: usize) {
+        self.backlog.lock().unwrap().resize(size);
+    }
+
+    pub fn output_limit(&self) -> OutputLimit {
+        *self.output_limit.lock().unwrap()
+    }
+
+    pub fn set_output_limit(&self, limit: OutputLimit) {
+        *self.output_limit.lock().unwrap() = limit;
+    }
+
+    /// Writes out an attached replica's queue of propagated frames while
+    /// recording the offsets it acknowledges. Returns once the replica
+    /// disconnects or is dropped.
+    pub async fn serve<C: Comms>(&self, mut comms: C, attached: Attached) -> anyhow::Result<()> {
+        let Attached {
+            id,
+            receiver,
+            pending,
+            ack,
+        } = attached;
+        let result = self.stream(&mut comms, receiver, &pending, &ack).await;
+        self.remove(id);
+        if let Err(err) = &result {
+            log_warning!("dropping replica {}: {:#}", id, err);
+        }
+        result
+    }
+
+    async fn stream<C: Comms>(
+        &self,
+        comms: &mut C,
+        mut receiver: mpsc::UnboundedReceiver<Frame>,
+        pending: &Pending,
+        ack: &Ack,
+    ) -> anyhow::Result<()> {
+        // replicas count from the snapshot they were sent
+        let synced_offset = ack.offset();
+        loop {
+            tokio::select! {
+                frame = receiver.recv() => match frame {
+                    Some(frame) => {
+                        let mut written = frame.encoded_len();
+                        comms.queue_frame(&frame).await?;
+                        // writes that piled up meanwhile go out with it
+                        while let Ok(frame) = receiver.try_recv() {
+                            written += frame.encoded_len();
+                            comms.queue_frame(&frame).await?;
+                        }
+                        comms.flush().await?;
+                        pending.remove(written as u64);
+                    }
+                    // pruned or fell too far behind
+                    None => return Ok(()),
+                },
+                frame = comms.read_frame() => {
+                    let Some(frame) = frame? else {
+                        return Ok(());
+                    };
+                    if let Ok(Command::ReplConf(cmd)) = Command::from_frame(frame) {
+                        if let Some(offset) = cmd.ack_offset() {
+                            ack.record(synced_offset + offset);
+                            self.acks.notify_waiters();
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn remove(&self, id: u64) {
+        self.replicas
+            .lock()
+            .unwrap()
+            .retain(|replica| replica.id != id);
+    }
+
+    /// Every attached replica, in the order they attached.
+    pub fn statuses(&self) -> Vec<ReplicaStatus> {
+        let replicas = self.replicas.lock().unwrap();
+        replicas
+            .iter()
+            .map(|replica| ReplicaStatus {
+                address: replica.address,
+                offset: replica.ack.offset(),
+                lag: replica.ack.at.lock().unwrap().elapsed(),
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.replicas.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops replicas that have acknowledged nothing within `timeout`.
+    fn prune(&self, timeout: Duration) {
+        let mut replicas = self.replicas.lock().unwrap();
+        replicas.retain(|replica| {
+            let alive = replica.ack.at.lock().unwrap().elapsed() < timeout;
+            if !alive {
+                log_warning!("dropping replica {}: timed out", replica.id);
+            }
+            alive
+        });
+    }
+
+    /// Periodically asks replicas for their offset and drops the ones that
+    /// stopped answering. Runs for as long as the server does.
+    pub async fn monitor(&self, role: RoleState) -> anyhow::Result<()> {
+        let start = Instant::now() + REPLICA_PING_PERIOD;
+        let mut interval = tokio::time::interval_at(start, REPLICA_PING_PERIOD);
+        loop {
+            interval.tick().await;
+            self.prune(REPLICA_TIMEOUT);
+            // a replica forwards its master's GETACKs, and must not add to the stream
+            if !self.is_empty() && !role.is_replica() {
+                self.publish(getack_frame()?)?;
+            }
+        }
+    }
+
+    /// Waits until `numreplicas` replicas have acknowledged every write
+    /// propagated so far, or until `deadline`, and returns how many have.
+    /// Replicas that are behind are asked for their offset with
+    /// `REPLCONF GETACK *`.
+    pub async fn wait_for_acks(
+        &self,
+        numreplicas: usize,
+        deadline: Option<Instant>,
+    ) -> anyhow::Result<usize> {
+        let target = self.offset();
+        let acked = || {
+            let replicas = self.replicas.lock().unwrap();
+            replicas
+                .iter()
+                .filter(|replica| replica.ack.offset() >= target)
+                .count()
+        };
+        let count = acked();
+        if count >= numreplicas || deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+            return Ok(count);
+        }
+        self.publish(getack_frame()?)?;
+        loop {
+            // registered before counting so an ack arriving in between still wakes us
+            let notified = self.acks.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            let count = acked();
+            if count >= numreplicas {
+                return Ok(count);
+            }
+            match deadline {
+                Some(deadline) => {
+                    if timeout_at(deadline, notified).await.is_err() {
+                        return Ok(acked());
+                    }
+                }
+                None => notified.await,
+            }
+        }
     }
 
-    Ok(())
+    /// Waits until the replica at `address` has acknowledged every write
+    /// propagated so far, asking it with `REPLCONF GETACK *`, and returns
+    /// whether it did by `deadline`.
+    pub async fn wait_for_replica(
+        &self,
+        address: SocketAddr,
+        deadline: Option<Instant>,
+    ) -> anyhow::Result<bool> {
+        let target = self.offset();
+        let caught_up = || {
+            let replicas = self.replicas.lock().unwrap();
+            replicas
+                .iter()
+                .any(|replica| replica.address == address && replica.ack.offset() >= target)
+        };
+        if caught_up() {
+            return Ok(true);
+        }
+        self.publish(getack_frame()?)?;
+        loop {
+            let notified = self.acks.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if caught_up() {
+                return Ok(true);
+            }
+            match deadline {
+                Some(deadline) => {
+                    if timeout_at(deadline, notified).await.is_err() {
+                        return Ok(caught_up());
+                    }
+                }
+                None => notified.await,
+            }
+        }
+    }
+}
+
+fn getack_frame() -> anyhow::Result<Frame> {
+    let mut array = Frame::array();
+    array.push_bulk(Bytes::from("REPLCONF"))?;
+    array.push_bulk(Bytes::from("GETACK"))?;
+    array.push_bulk(Bytes::from("*"))?;
+    Ok(array)
 }
 
-pub async fn add_connection<C: Comms + 'static>(comms: C, store: &Store) -> anyhow::Result<()> {
-    let mut subscribers = SUBSCRIBERS.lock().await;
-    subscribers.push(Arc::new(Mutex::new(comms)));
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attach(replicas: &ReplicaSet) -> mpsc::UnboundedReceiver<Frame> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let id = replicas.next_id.fetch_add(1, Ordering::SeqCst);
+        replicas.replicas.lock().unwrap().push(Replica {
+            id,
+            address: "127.0.0.1:6380".parse().unwrap(),
+            sender,
+            pending: Default::default(),
+            ack: Arc::new(Ack::new(0)),
+        });
+        receiver
+    }
+
+    #[test]
+    fn publish_drops_disconnected_replicas() -> anyhow::Result<()> {
+        let replicas = ReplicaSet::default();
+        let mut live = attach(&replicas);
+        drop(attach(&replicas));
+
+        replicas.publish(getack_frame()?)?;
+        assert_eq!(replicas.len(), 1);
+        assert_eq!(live.try_recv()?, getack_frame()?);
+        Ok(())
+    }
+
+    #[test]
+    fn publish_drops_replicas_that_fall_behind() -> anyhow::Result<()> {
+        let replicas = ReplicaSet::default();
+        let getack_len = getack_frame()?.encoded_len() as u64;
+        replicas.set_output_limit(OutputLimit {
+            hard: 10 * getack_len,
+            ..Default::default()
+        });
+        let _receiver = attach(&replicas);
 
-    let rdb = store.as_rdb();
-    let rdb = Frame::RdbFile(rdb);
+        for _ in 0..9 {
+            replicas.publish(getack_frame()?)?;
+        }
+        assert_eq!(replicas.len(), 1);
+        replicas.publish(getack_frame()?)?;
+        assert!(replicas.is_empty());
+        Ok(())
+    }
 
-    let mut connection = subscribers.last().unwrap().lock().await;
-    connection
-        .write_frame(&rdb)
-        .await
-        .map_err(anyhow::Error::from)
+    #[test]
+    fn publish_in_selects_a_database_when_it_changes() -> anyhow::Result<()> {
+        let replicas = ReplicaSet::default();
+        let mut receiver = attach(&replicas);
+        let ping = Frame::Array(vec![Frame::Bulk("PING".into())]);
+
+        replicas.publish_in(0, ping.clone())?;
+        replicas.publish_in(0, ping.clone())?;
+        replicas.publish_in(3, ping.clone())?;
+        let mut received = vec![];
+        while let Ok(frame) = receiver.try_recv() {
+            received.push(frame);
+        }
+        assert_eq!(
+            received,
+            [ping.clone(), ping.clone(), select_frame(3), ping]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn resume_replays_the_backlog() -> anyhow::Result<()> {
+        let replicas = ReplicaSet::default();
+        let ping = Frame::Array(vec![Frame::Bulk("PING".into())]);
+        let len = ping.encoded_len() as u64;
+        replicas.set_backlog_size(2 * len as usize);
+        for _ in 0..3 {
+            replicas.publish(ping.clone())?;
+        }
+
+        let address = "127.0.0.1:6380".parse()?;
+        let (missed, _attached) = replicas.resume(address, len).unwrap();
+        assert_eq!(missed, vec![ping.clone(), ping.clone()]);
+        let (missed, _attached) = replicas.resume(address, 3 * len).unwrap();
+        assert!(missed.is_empty());
+        assert_eq!(replicas.len(), 2);
+        assert_eq!(replicas.statuses()[1].address, address);
+        assert_eq!(replicas.statuses()[1].offset, 3 * len);
+
+        // trimmed, inside a frame, and past the end of the stream
+        assert!(replicas.resume(address, 0).is_none());
+        assert!(replicas.resume(address, len + 1).is_none());
+        assert!(replicas.resume(address, 4 * len).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn prune_drops_silent_replicas() {
+        let replicas = ReplicaSet::default();
+        let _receiver = attach(&replicas);
+
+        replicas.prune(Duration::from_secs(60));
+        assert_eq!(replicas.len(), 1);
+        replicas.prune(Duration::ZERO);
+        assert!(replicas.is_empty());
+    }
 }
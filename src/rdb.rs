@@ -0,0 +1,765 @@
+//! Encoding and decoding of RDB snapshots, the payload a master sends after
+//! FULLRESYNC.
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{bail, ensure, Context};
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::digest::crc64;
+use crate::store::{
+    consumer_group::ConsumerGroup,
+    sorted_set::SortedSet,
+    stream::{Fields, Stream, StreamId},
+    Value,
+};
+
+mod listpack;
+use listpack::Element;
+
+const MAGIC: &[u8] = b"REDIS0011";
+const RDB_VERSION: u16 = 11;
+const REDIS_VER: &str = "7.2.0";
+
+const OPCODE_AUX: u8 = 0xfa;
+const OPCODE_RESIZEDB: u8 = 0xfb;
+const OPCODE_EXPIRETIME_MS: u8 = 0xfc;
+const OPCODE_EXPIRETIME: u8 = 0xfd;
+const OPCODE_SELECTDB: u8 = 0xfe;
+const OPCODE_EOF: u8 = 0xff;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_ZSET: u8 = 3;
+const TYPE_ZSET_2: u8 = 5;
+const TYPE_STREAM_LISTPACKS: u8 = 15;
+const TYPE_STREAM_LISTPACKS_2: u8 = 19;
+const TYPE_STREAM_LISTPACKS_3: u8 = 21;
+
+/// Entries per listpack node, Redis' default stream-node-max-entries.
+const STREAM_NODE_MAX_ENTRIES: usize = 100;
+const STREAM_ITEM_FLAG_DELETED: i64 = 1;
+const STREAM_ITEM_FLAG_SAMEFIELDS: i64 = 2;
+
+/// One key of a snapshot, with its expiry as unix time in milliseconds.
+pub struct Entry<'a> {
+    pub db: usize,
+    pub key: &'a Bytes,
+    pub value: &'a Value,
+    pub expires_at: Option<u64>,
+}
+
+/// A key read from a snapshot.
+#[derive(Debug, PartialEq)]
+pub struct Loaded {
+    pub db: usize,
+    pub key: Bytes,
+    pub value: Value,
+    pub expires_at: Option<u64>,
+}
+
+/// Encodes `entries` as an RDB file, each database's keys in a section of
+/// their own.
+pub fn encode<'a>(entries: impl IntoIterator<Item = Entry<'a>>) -> Bytes {
+    let mut dbs: BTreeMap<usize, Vec<Entry>> = BTreeMap::new();
+    for entry in entries {
+        dbs.entry(entry.db).or_default().push(entry);
+    }
+
+    let mut out = BytesMut::new();
+    out.put_slice(MAGIC);
+    put_aux(&mut out, "redis-ver", REDIS_VER.as_bytes());
+    put_aux(&mut out, "redis-bits", b"64");
+
+    for (db, entries) in dbs {
+        out.put_u8(OPCODE_SELECTDB);
+        put_length(&mut out, db as u64);
+        out.put_u8(OPCODE_RESIZEDB);
+        put_length(&mut out, entries.len() as u64);
+        let expires = entries.iter().filter(|e| e.expires_at.is_some()).count();
+        put_length(&mut out, expires as u64);
+        for entry in entries {
+            put_entry(&mut out, entry);
+        }
+    }
+
+    out.put_u8(OPCODE_EOF);
+    let checksum = crc64(&out);
+    out.put_u64_le(checksum);
+    out.freeze()
+}
+
+fn put_entry(out: &mut BytesMut, entry: Entry) {
+    if let Some(expires_at) = entry.expires_at {
+        out.put_u8(OPCODE_EXPIRETIME_MS);
+        out.put_u64_le(expires_at);
+    }
+    out.put_u8(value_type(entry.value));
+    put_string(out, entry.key);
+    put_value(out, entry.value);
+}
+
+fn value_type(value: &Value) -> u8 {
+    match value {
+        Value::String(_) => TYPE_STRING,
+        Value::SortedSet(_) => TYPE_ZSET_2,
+        Value::Stream(_) => TYPE_STREAM_LISTPACKS_3,
+    }
+}
+
+/// `value` as DUMP serializes it: its type and encoding as in an RDB file,
+/// then the RDB version and a checksum of the lot.
+pub fn dump(value: &Value) -> Bytes {
+    let mut out = BytesMut::new();
+    out.put_u8(value_type(value));
+    put_value(&mut out, value);
+    out.put_u16_le(RDB_VERSION);
+    let checksum = crc64(&out);
+    out.put_u64_le(checksum);
+    out.freeze()
+}
+
+/// The value in a DUMP payload, which must be from an RDB version we can
+/// read and match its checksum.
+pub fn restore(payload: &[u8]) -> anyhow::Result<Value> {
+    ensure!(payload.len() >= 10, "DUMP payload too short");
+    let (body, checksum) = payload.split_at(payload.len() - 8);
+    let version = u16::from_le_bytes([body[body.len() - 2], body[body.len() - 1]]);
+    ensure!(
+        version <= RDB_VERSION,
+        "DUMP payload from RDB version {}",
+        version
+    );
+    ensure!(
+        u64::from_le_bytes(checksum.try_into()?) == crc64(body),
+        "DUMP payload checksum mismatch"
+    );
+    let data = &body[..body.len() - 2];
+    let mut reader = Reader { data, pos: 0 };
+    let value_type = reader.u8()?;
+    let value = reader.value(value_type)?;
+    ensure!(reader.pos == data.len(), "trailing bytes in DUMP payload");
+    Ok(value)
+}
+
+/// The bytes `value` takes in an RDB file, leaving out its key and type.
+pub fn serialized_len(value: &Value) -> usize {
+    let mut out = BytesMut::new();
+    put_value(&mut out, value);
+    out.len()
+}
+
+fn put_value(out: &mut BytesMut, value: &Value) {
+    match value {
+        Value::String(value) => put_string(out, value),
+        Value::SortedSet(zset) => {
+            put_length(out, zset.len() as u64);
+            for (member, score) in zset.iter() {
+                put_string(out, member);
+                out.put_f64_le(score);
+            }
+        }
+        Value::Stream(stream) => put_stream(out, stream),
+    }
+}
+
+/// A stream as listpack nodes of up to `STREAM_NODE_MAX_ENTRIES` entries,
+/// followed by its metadata and consumer groups.
+fn put_stream(out: &mut BytesMut, stream: &Stream) {
+    let entries: Vec<_> = stream.range(StreamId::MIN, StreamId::MAX).collect();
+    put_length(out, entries.len().div_ceil(STREAM_NODE_MAX_ENTRIES) as u64);
+    for node in entries.chunks(STREAM_NODE_MAX_ENTRIES) {
+        let master_id = *node[0].0;
+        put_string(out, &raw_id(&master_id));
+        put_string(out, &stream_node(master_id, node));
+    }
+    put_length(out, stream.len() as u64);
+    put_id(out, &stream.last_id());
+    put_id(out, &stream.first_id().unwrap_or(StreamId::MIN));
+    // the largest deleted ID and the count of entries ever added aren't tracked
+    put_id(out, &StreamId::MIN);
+    put_length(out, stream.len() as u64);
+
+    put_length(out, stream.groups().len() as u64);
+    for (name, group) in stream.groups() {
+        put_string(out, name);
+        put_id(out, &group.last_delivered);
+        // entries-read isn't tracked either; -1 marks it unknown
+        put_length(out, u64::MAX);
+        put_length(out, group.pending().len() as u64);
+        for (id, pending) in group.pending() {
+            out.put_slice(&raw_id(id));
+            out.put_u64_le(pending.delivery_time);
+            put_length(out, pending.delivery_count);
+        }
+        put_length(out, group.consumers().len() as u64);
+        for (name, consumer) in group.consumers() {
+            put_string(out, name);
+            // seen and active time
+            out.put_u64_le(consumer.seen_time);
+            out.put_u64_le(consumer.seen_time);
+            put_length(out, consumer.pending.len() as u64);
+            for id in &consumer.pending {
+                out.put_slice(&raw_id(id));
+            }
+        }
+    }
+}
+
+/// One node's listpack: a master entry naming the first entry's fields, then
+/// each entry with its ID relative to the master's. Entries with the same
+/// fields as the master store only their values.
+fn stream_node(master_id: StreamId, node: &[(&StreamId, &Fields)]) -> Bytes {
+    let master_fields = node[0].1;
+    let mut lp = listpack::Builder::new();
+    lp.push_int(node.len() as i64);
+    lp.push_int(0);
+    lp.push_int(master_fields.len() as i64);
+    for (field, _) in master_fields {
+        lp.push_bytes(field);
+    }
+    lp.push_int(0);
+
+    for (id, fields) in node {
+        let same_fields = fields.len() == master_fields.len()
+            && fields
+                .iter()
+                .zip(master_fields)
+                .all(|((field, _), (master, _))| field == master);
+        lp.push_int(if same_fields {
+            STREAM_ITEM_FLAG_SAMEFIELDS
+        } else {
+            0
+        });
+        lp.push_int(id.ms.wrapping_sub(master_id.ms) as i64);
+        lp.push_int(id.seq.wrapping_sub(master_id.seq) as i64);
+        if same_fields {
+            for (_, value) in fields.iter() {
+                lp.push_bytes(value);
+            }
+            lp.push_int(fields.len() as i64 + 3);
+        } else {
+            lp.push_int(fields.len() as i64);
+            for (field, value) in fields.iter() {
+                lp.push_bytes(field);
+                lp.push_bytes(value);
+            }
+            lp.push_int(2 * fields.len() as i64 + 4);
+        }
+    }
+    lp.finish()
+}
+
+/// An ID as 128 big-endian bits, as node keys and pending entries store it.
+fn raw_id(id: &StreamId) -> [u8; 16] {
+    let mut raw = [0; 16];
+    raw[..8].copy_from_slice(&id.ms.to_be_bytes());
+    raw[8..].copy_from_slice(&id.seq.to_be_bytes());
+    raw
+}
+
+fn parse_raw_id(raw: &[u8]) -> anyhow::Result<StreamId> {
+    ensure!(raw.len() == 16, "invalid stream ID length {}", raw.len());
+    Ok(StreamId::new(
+        u64::from_be_bytes(raw[..8].try_into()?),
+        u64::from_be_bytes(raw[8..].try_into()?),
+    ))
+}
+
+fn put_id(out: &mut BytesMut, id: &StreamId) {
+    put_length(out, id.ms);
+    put_length(out, id.seq);
+}
+
+fn put_aux(out: &mut BytesMut, key: &str, value: &[u8]) {
+    out.put_u8(OPCODE_AUX);
+    put_string(out, key.as_bytes());
+    put_string(out, value);
+}
+
+fn put_string(out: &mut BytesMut, s: &[u8]) {
+    put_length(out, s.len() as u64);
+    out.put_slice(s);
+}
+
+/// The variable-width length encoding: 6, 14, 32 or 64 bits, tagged by the
+/// top two bits of the first byte.
+fn put_length(out: &mut BytesMut, len: u64) {
+    match len {
+        0..=0x3f => out.put_u8(len as u8),
+        0x40..=0x3fff => out.put_u16(0x4000 | len as u16),
+        0x4000..=0xffff_ffff => {
+            out.put_u8(0x80);
+            out.put_u32(len as u32);
+        }
+        _ => {
+            out.put_u8(0x81);
+            out.put_u64(len);
+        }
+    }
+}
+
+/// Decodes the keys of an RDB file. Strings, sorted sets and streams are
+/// supported; any other value type is an error, since its length can't be
+/// known.
+pub fn decode(data: &[u8]) -> anyhow::Result<Vec<Loaded>> {
+    let mut reader = Reader { data, pos: 0 };
+    let magic = reader.take(MAGIC.len())?;
+    ensure!(magic.starts_with(b"REDIS"), "not an RDB file");
+
+    let mut loaded = vec![];
+    let mut db = 0;
+    let mut expires_at = None;
+    loop {
+        match reader.u8()? {
+            OPCODE_AUX => {
+                reader.string()?;
+                reader.string()?;
+            }
+            OPCODE_SELECTDB => db = reader.length()?,
+            OPCODE_RESIZEDB => {
+                reader.length()?;
+                reader.length()?;
+            }
+            OPCODE_EXPIRETIME_MS => expires_at = Some(reader.u64_le()?),
+            OPCODE_EXPIRETIME => {
+                let secs = u32::from_le_bytes(reader.take(4)?.try_into()?);
+                expires_at = Some(secs as u64 * 1000);
+            }
+            OPCODE_EOF => break,
+            value_type => {
+                let key = reader.string()?;
+                let value = reader.value(value_type)?;
+                loaded.push(Loaded {
+                    db,
+                    key,
+                    value,
+                    expires_at: expires_at.take(),
+                });
+            }
+        }
+    }
+
+    // files saved without checksums end in zeros
+    let body = &data[..reader.pos];
+    if let Ok(checksum) = reader.u64_le() {
+        ensure!(
+            checksum == 0 || checksum == crc64(body),
+            "RDB checksum mismatch"
+        );
+    }
+    Ok(loaded)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+/// A decoded length field: a plain length, or the tag of a specially
+/// encoded string.
+enum Length {
+    Len(u64),
+    Encoded(u8),
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> anyhow::Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + n)
+            .context("unexpected end of RDB file")?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64_le(&mut self) -> anyhow::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn length_or_encoding(&mut self) -> anyhow::Result<Length> {
+        let first = self.u8()?;
+        let length = match first >> 6 {
+            0 => Length::Len((first & 0x3f) as u64),
+            1 => Length::Len(((first as u64 & 0x3f) << 8) | self.u8()? as u64),
+            2 => match first {
+                0x80 => Length::Len(u32::from_be_bytes(self.take(4)?.try_into()?) as u64),
+                0x81 => Length::Len(u64::from_be_bytes(self.take(8)?.try_into()?)),
+                _ => bail!("invalid RDB length tag {:#x}", first),
+            },
+            _ => Length::Encoded(first & 0x3f),
+        };
+        Ok(length)
+    }
+
+    fn length(&mut self) -> anyhow::Result<usize> {
+        match self.length_or_encoding()? {
+            Length::Len(len) => Ok(len as usize),
+            Length::Encoded(_) => bail!("expected an RDB length"),
+        }
+    }
+
+    fn string(&mut self) -> anyhow::Result<Bytes> {
+        let string = match self.length_or_encoding()? {
+            Length::Len(len) => Bytes::copy_from_slice(self.take(len as usize)?),
+            Length::Encoded(0) => (self.u8()? as i8).to_string().into(),
+            Length::Encoded(1) => i16::from_le_bytes(self.take(2)?.try_into()?)
+                .to_string()
+                .into(),
+            Length::Encoded(2) => i32::from_le_bytes(self.take(4)?.try_into()?)
+                .to_string()
+                .into(),
+            Length::Encoded(3) => {
+                let compressed_len = self.length()?;
+                let len = self.length()?;
+                lzf_decompress(self.take(compressed_len)?, len)?.into()
+            }
+            Length::Encoded(other) => bail!("unknown RDB string encoding {}", other),
+        };
+        Ok(string)
+    }
+
+    fn value(&mut self, value_type: u8) -> anyhow::Result<Value> {
+        let value = match value_type {
+            TYPE_STRING => Value::String(self.string()?),
+            TYPE_ZSET | TYPE_ZSET_2 => {
+                let mut zset = SortedSet::new();
+                for _ in 0..self.length()? {
+                    let member = self.string()?;
+                    let score = match value_type {
+                        TYPE_ZSET_2 => f64::from_le_bytes(self.take(8)?.try_into()?),
+                        _ => self.string_double()?,
+                    };
+                    zset.insert(member, score);
+                }
+                Value::SortedSet(zset)
+            }
+            TYPE_STREAM_LISTPACKS | TYPE_STREAM_LISTPACKS_2 | TYPE_STREAM_LISTPACKS_3 => {
+                Value::Stream(self.stream(value_type)?)
+            }
+            other => bail!("unsupported RDB value type {}", other),
+        };
+        Ok(value)
+    }
+
+    /// A stream in any of the listpack layouts. Later versions add the
+    /// stream's first ID and deletion counters, each group's entries-read
+    /// and each consumer's active time; none of them are kept.
+    fn stream(&mut self, value_type: u8) -> anyhow::Result<Stream> {
+        let mut entries = BTreeMap::new();
+        for _ in 0..self.length()? {
+            let master_id = parse_raw_id(&self.string()?)?;
+            let node = listpack::decode(&self.string()?)?;
+            read_stream_node(master_id, &node, &mut entries)?;
+        }
+        self.length()?;
+        let last_id = self.id()?;
+        if value_type >= TYPE_STREAM_LISTPACKS_2 {
+            self.id()?;
+            self.id()?;
+            self.length()?;
+        }
+
+        let mut groups = BTreeMap::new();
+        for _ in 0..self.length()? {
+            let name = self.string()?;
+            let mut group = ConsumerGroup::new(self.id()?);
+            if value_type >= TYPE_STREAM_LISTPACKS_2 {
+                self.length()?;
+            }
+            let mut pending = HashMap::new();
+            for _ in 0..self.length()? {
+                let id = parse_raw_id(self.take(16)?)?;
+                let delivery_time = self.u64_le()?;
+                let delivery_count = self.length()? as u64;
+                pending.insert(id, (delivery_time, delivery_count));
+            }
+            for _ in 0..self.length()? {
+                let consumer = self.string()?;
+                let seen_time = self.u64_le()?;
+                if value_type >= TYPE_STREAM_LISTPACKS_3 {
+                    self.u64_le()?;
+                }
+                group.create_consumer(consumer.clone(), seen_time);
+                for _ in 0..self.length()? {
+                    let id = parse_raw_id(self.take(16)?)?;
+                    let (delivery_time, delivery_count) = pending
+                        .get(&id)
+                        .context("consumer owns an entry its group has no record of")?;
+                    group.claim(id, &consumer, *delivery_time, *delivery_count);
+                }
+            }
+            groups.insert(name, group);
+        }
+        Ok(Stream::restore(entries, last_id, groups))
+    }
+
+    fn id(&mut self) -> anyhow::Result<StreamId> {
+        Ok(StreamId::new(self.length()? as u64, self.length()? as u64))
+    }
+
+    /// The older text encoding of a score, with special lengths for NaN and
+    /// the infinities.
+    fn string_double(&mut self) -> anyhow::Result<f64> {
+        let score = match self.u8()? {
+            253 => f64::NAN,
+            254 => f64::INFINITY,
+            255 => f64::NEG_INFINITY,
+            len => std::str::from_utf8(self.take(len as usize)?)?.parse()?,
+        };
+        Ok(score)
+    }
+}
+
+/// Adds the live entries of a stream node's listpack to `entries`.
+fn read_stream_node(
+    master_id: StreamId,
+    node: &[Element],
+    entries: &mut BTreeMap<StreamId, Fields>,
+) -> anyhow::Result<()> {
+    let mut elements = node.iter();
+    let mut next = || elements.next().context("truncated stream node");
+    // live and deleted entries
+    let count = next()?.int()? + next()?.int()?;
+    let master_fields = (0..next()?.int()?)
+        .map(|_| Ok(next()?.bytes()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    next()?;
+
+    for _ in 0..count {
+        let flags = next()?.int()?;
+        let ms = master_id.ms.wrapping_add(next()?.int()? as u64);
+        let seq = master_id.seq.wrapping_add(next()?.int()? as u64);
+        let fields = if flags & STREAM_ITEM_FLAG_SAMEFIELDS != 0 {
+            master_fields
+                .iter()
+                .map(|field| Ok((field.clone(), next()?.bytes())))
+                .collect::<anyhow::Result<Fields>>()?
+        } else {
+            (0..next()?.int()?)
+                .map(|_| Ok((next()?.bytes(), next()?.bytes())))
+                .collect::<anyhow::Result<Fields>>()?
+        };
+        next()?;
+        if flags & STREAM_ITEM_FLAG_DELETED == 0 {
+            entries.insert(StreamId::new(ms, seq), fields);
+        }
+    }
+    Ok(())
+}
+
+fn lzf_decompress(input: &[u8], len: usize) -> anyhow::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(len);
+    let mut i = 0;
+    let byte = |i: usize| input.get(i).copied().context("truncated LZF data");
+    while i < input.len() {
+        let ctrl = input[i] as usize;
+        i += 1;
+        if ctrl < 32 {
+            // a literal run of ctrl + 1 bytes
+            let literal = input.get(i..i + ctrl + 1).context("truncated LZF data")?;
+            out.extend_from_slice(literal);
+            i += ctrl + 1;
+        } else {
+            // a back reference into what has been written so far
+            let mut run = ctrl >> 5;
+            if run == 7 {
+                run += byte(i)? as usize;
+                i += 1;
+            }
+            let distance = ((ctrl & 0x1f) << 8) + byte(i)? as usize + 1;
+            i += 1;
+            let start = out
+                .len()
+                .checked_sub(distance)
+                .context("invalid LZF back reference")?;
+            for n in 0..run + 2 {
+                out.push(out[start + n]);
+            }
+        }
+    }
+    ensure!(
+        out.len() == len,
+        "LZF data decompressed to the wrong length"
+    );
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::stream::IdSpec;
+
+    #[test]
+    fn encodes_strings_with_expiry() {
+        let key = Bytes::from("foo");
+        let value = Value::String("bar".into());
+        let rdb = encode([Entry {
+            db: 0,
+            key: &key,
+            value: &value,
+            expires_at: Some(1_700_000_000_000),
+        }]);
+
+        assert!(rdb.starts_with(b"REDIS0011\xfa\x09redis-ver\x057.2.0"));
+        let body = [
+            b"\xfe\x00\xfb\x01\x01\xfc".as_slice(),
+            &1_700_000_000_000u64.to_le_bytes(),
+            b"\x00\x03foo\x03bar\xff",
+        ]
+        .concat();
+        let (rest, checksum) = rdb.split_at(rdb.len() - 8);
+        assert!(rest.ends_with(&body));
+        assert_eq!(checksum, crc64(rest).to_le_bytes());
+    }
+
+    #[test]
+    fn length_encoding_widths() {
+        let mut out = BytesMut::new();
+        put_length(&mut out, 10);
+        put_length(&mut out, 700);
+        put_length(&mut out, 17000);
+        assert_eq!(&out[..], b"\x0a\x42\xbc\x80\x00\x00\x42\x68");
+    }
+
+    #[test]
+    fn decode_round_trips_encode() -> anyhow::Result<()> {
+        let key = Bytes::from("zset");
+        let mut zset = SortedSet::new();
+        zset.insert("a".into(), 1.5);
+        zset.insert("b".into(), -2.0);
+        let value = Value::SortedSet(zset);
+        let rdb = encode([Entry {
+            db: 0,
+            key: &key,
+            value: &value,
+            expires_at: None,
+        }]);
+
+        let loaded = decode(&rdb)?;
+        assert_eq!(
+            loaded,
+            vec![Loaded {
+                db: 0,
+                key,
+                value,
+                expires_at: None,
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn dump_payloads_round_trip_and_are_checked() -> anyhow::Result<()> {
+        let payload = dump(&Value::String("bar".into()));
+        assert_eq!(&payload[..7], b"\x00\x03bar\x0b\x00");
+        assert_eq!(restore(&payload)?, Value::String("bar".into()));
+
+        let mut zset = SortedSet::new();
+        zset.insert("a".into(), 1.5);
+        let value = Value::SortedSet(zset);
+        assert_eq!(restore(&dump(&value))?, value);
+
+        let mut corrupted = payload.to_vec();
+        corrupted[2] = b'c';
+        assert!(restore(&corrupted).is_err());
+        assert!(restore(b"\x00").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn decode_keeps_each_keys_database() -> anyhow::Result<()> {
+        let (a, b) = (Bytes::from("a"), Bytes::from("b"));
+        let value = Value::String("v".into());
+        let entry = |db, key| Entry {
+            db,
+            key,
+            value: &value,
+            expires_at: None,
+        };
+        let rdb = encode([entry(3, &a), entry(0, &b), entry(3, &b)]);
+
+        let loaded: Vec<_> = decode(&rdb)?
+            .into_iter()
+            .map(|loaded| (loaded.db, loaded.key))
+            .collect();
+        assert_eq!(loaded, [(0, b.clone()), (3, a), (3, b)]);
+        Ok(())
+    }
+
+    #[test]
+    fn decode_round_trips_streams() -> anyhow::Result<()> {
+        let key = Bytes::from("stream");
+        let mut stream = Stream::new();
+        let fields = |pairs: &[(&str, &str)]| -> Fields {
+            pairs
+                .iter()
+                .map(|(f, v)| (Bytes::from(f.to_string()), Bytes::from(v.to_string())))
+                .collect()
+        };
+        for i in 1..=150u64 {
+            let id = IdSpec::Explicit(StreamId::new(1000 + i / 3, i));
+            let entry = match i % 3 {
+                0 => fields(&[("temp", "21"), ("city", "Oslo")]),
+                1 => fields(&[("temp", &i.to_string()), ("city", "Lima")]),
+                _ => fields(&[("other", "-7"), ("x", &"y".repeat(70))]),
+            };
+            stream.add(id, entry).unwrap();
+        }
+        stream.delete(&[StreamId::new(1050, 150)]);
+        stream.create_group("group".into(), StreamId::new(1001, 4));
+        let group = stream.group_mut(b"group").unwrap();
+        group.deliver(StreamId::new(1000, 1), &"alice".into(), 1_700_000_000_000);
+        group.deliver(StreamId::new(1000, 2), &"bob".into(), 1_700_000_000_500);
+        group.deliver(StreamId::new(1000, 2), &"bob".into(), 1_700_000_000_900);
+        group.create_consumer("carol".into(), 1_700_000_001_000);
+        let value = Value::Stream(stream);
+
+        let rdb = encode([Entry {
+            db: 0,
+            key: &key,
+            value: &value,
+            expires_at: None,
+        }]);
+        let loaded = decode(&rdb)?;
+        assert_eq!(
+            loaded,
+            vec![Loaded {
+                db: 0,
+                key,
+                value,
+                expires_at: None,
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn decodes_the_empty_rdb_redis_sends() -> anyhow::Result<()> {
+        // aux fields with integer encoded values, and a real checksum
+        let rdb = b"REDIS0011\xfa\x09redis-ver\x057.2.0\xfa\x0aredis-bits\xc0\x40\xfa\x05ctime\xc2\x6d\x08\xbc\x65\xfa\x08used-mem\xc2\xb0\xc4\x10\x00\xfa\x08aof-base\xc0\x00\xff\xf0\x6e\x3b\xfe\xc0\xff\x5a\xa2";
+        assert_eq!(decode(rdb)?, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn decodes_lzf_strings_and_second_expiries() -> anyhow::Result<()> {
+        // "aaaaaaaaaa": a literal "a", then a back reference repeating it 9 times
+        let rdb =
+            b"REDIS0011\xfe\x00\xfd\x10\x00\x00\x00\x00\x01k\xc3\x05\x0a\x00a\xe0\x00\x00\xff";
+        let loaded = decode(rdb)?;
+        assert_eq!(
+            loaded,
+            vec![Loaded {
+                db: 0,
+                key: "k".into(),
+                value: Value::String("aaaaaaaaaa".into()),
+                expires_at: Some(16_000),
+            }]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+//! The listpack encoding RDB files use for stream nodes: a length-prefixed
+//! run of entries, each an integer or a string, with a back-length so the
+//! list can be walked from either end.
+
+use anyhow::{bail, ensure, Context};
+use bytes::{BufMut, Bytes, BytesMut};
+
+const HEADER_LEN: usize = 6;
+const EOF: u8 = 0xff;
+
+/// One listpack element. Strings that spell a canonical integer are stored
+/// as integers, so a reader can't tell the two apart.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Element {
+    Int(i64),
+    Str(Bytes),
+}
+
+impl Element {
+    pub fn int(&self) -> anyhow::Result<i64> {
+        match self {
+            Element::Int(n) => Ok(*n),
+            Element::Str(s) => std::str::from_utf8(s)?
+                .parse()
+                .context("expected an integer listpack element"),
+        }
+    }
+
+    pub fn bytes(&self) -> Bytes {
+        match self {
+            Element::Int(n) => n.to_string().into(),
+            Element::Str(s) => s.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Builder {
+    entries: BytesMut,
+    count: usize,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_int(&mut self, n: i64) {
+        let mut entry = BytesMut::new();
+        match n {
+            0..=127 => entry.put_u8(n as u8),
+            -4096..=4095 => entry.put_u16(0xc000 | (n as u16 & 0x1fff)),
+            -32768..=32767 => {
+                entry.put_u8(0xf1);
+                entry.put_i16_le(n as i16);
+            }
+            -8388608..=8388607 => {
+                entry.put_u8(0xf2);
+                entry.put_slice(&(n as i32).to_le_bytes()[..3]);
+            }
+            -2147483648..=2147483647 => {
+                entry.put_u8(0xf3);
+                entry.put_i32_le(n as i32);
+            }
+            _ => {
+                entry.put_u8(0xf4);
+                entry.put_i64_le(n);
+            }
+        }
+        self.push_entry(&entry);
+    }
+
+    pub fn push_bytes(&mut self, s: &[u8]) {
+        if let Some(n) = canonical_int(s) {
+            return self.push_int(n);
+        }
+        let mut entry = BytesMut::new();
+        match s.len() {
+            len @ 0..=63 => entry.put_u8(0x80 | len as u8),
+            len @ 64..=4095 => entry.put_u16(0xe000 | len as u16),
+            len => {
+                entry.put_u8(0xf0);
+                entry.put_u32_le(len as u32);
+            }
+        }
+        entry.put_slice(s);
+        self.push_entry(&entry);
+    }
+
+    fn push_entry(&mut self, entry: &[u8]) {
+        self.entries.put_slice(entry);
+        put_backlen(&mut self.entries, entry.len());
+        self.count += 1;
+    }
+
+    pub fn finish(self) -> Bytes {
+        let mut out = BytesMut::with_capacity(HEADER_LEN + self.entries.len() + 1);
+        out.put_u32_le((HEADER_LEN + self.entries.len() + 1) as u32);
+        // counts that don't fit are only known by walking the list
+        out.put_u16_le(self.count.min(u16::MAX as usize) as u16);
+        out.put(self.entries);
+        out.put_u8(EOF);
+        out.freeze()
+    }
+}
+
+/// The integer `s` spells, if it spells one exactly: no sign, leading zero
+/// or spaces that would be lost by storing it as a number.
+fn canonical_int(s: &[u8]) -> Option<i64> {
+    let n = std::str::from_utf8(s).ok()?.parse::<i64>().ok()?;
+    (n.to_string().as_bytes() == s).then_some(n)
+}
+
+/// Writes an entry's length after it, 7 bits per byte with the most
+/// significant group first, so it can be read backwards.
+fn put_backlen(out: &mut BytesMut, len: usize) {
+    let width = backlen_width(len);
+    for i in (0..width).rev() {
+        let group = ((len >> (7 * i)) & 0x7f) as u8;
+        out.put_u8(if i == width - 1 { group } else { group | 0x80 });
+    }
+}
+
+fn backlen_width(len: usize) -> usize {
+    match len {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5,
+    }
+}
+
+/// Decodes every element of a listpack.
+pub fn decode(data: &[u8]) -> anyhow::Result<Vec<Element>> {
+    ensure!(data.len() > HEADER_LEN, "listpack too short");
+    let total = u32::from_le_bytes(data[..4].try_into()?) as usize;
+    ensure!(total == data.len(), "listpack length mismatch");
+
+    let mut elements = vec![];
+    let mut pos = HEADER_LEN;
+    let take = |pos: usize, n: usize| data.get(pos..pos + n).context("unexpected end of listpack");
+    loop {
+        let first = *data.get(pos).context("unterminated listpack")?;
+        if first == EOF {
+            break;
+        }
+        let (element, len) = match first {
+            0x00..=0x7f => (Element::Int(first as i64), 1),
+            0x80..=0xbf => {
+                let len = (first & 0x3f) as usize;
+                (
+                    Element::Str(Bytes::copy_from_slice(take(pos + 1, len)?)),
+                    1 + len,
+                )
+            }
+            0xc0..=0xdf => {
+                let raw = ((first as u16 & 0x1f) << 8) | take(pos + 1, 1)?[0] as u16;
+                // sign-extend the 13-bit value
+                (Element::Int(((raw << 3) as i16 >> 3) as i64), 2)
+            }
+            0xe0..=0xef => {
+                let len = ((first as usize & 0x0f) << 8) | take(pos + 1, 1)?[0] as usize;
+                (
+                    Element::Str(Bytes::copy_from_slice(take(pos + 2, len)?)),
+                    2 + len,
+                )
+            }
+            0xf0 => {
+                let len = u32::from_le_bytes(take(pos + 1, 4)?.try_into()?) as usize;
+                (
+                    Element::Str(Bytes::copy_from_slice(take(pos + 5, len)?)),
+                    5 + len,
+                )
+            }
+            0xf1 => (
+                Element::Int(i16::from_le_bytes(take(pos + 1, 2)?.try_into()?) as i64),
+                3,
+            ),
+            0xf2 => {
+                let bytes = take(pos + 1, 3)?;
+                let n = i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8;
+                (Element::Int(n as i64), 4)
+            }
+            0xf3 => (
+                Element::Int(i32::from_le_bytes(take(pos + 1, 4)?.try_into()?) as i64),
+                5,
+            ),
+            0xf4 => (
+                Element::Int(i64::from_le_bytes(take(pos + 1, 8)?.try_into()?)),
+                9,
+            ),
+            other => bail!("invalid listpack encoding {:#x}", other),
+        };
+        elements.push(element);
+        pos += len + backlen_width(len);
+    }
+    Ok(elements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_every_encoding() -> anyhow::Result<()> {
+        let long = "x".repeat(5000);
+        let ints = [
+            0,
+            127,
+            -1,
+            -4096,
+            4095,
+            -32768,
+            32767,
+            8388607,
+            -8388608,
+            i32::MAX as i64,
+            i64::MIN,
+        ];
+        let strings = ["", "field", "007", "-0", &"y".repeat(100), &long];
+
+        let mut builder = Builder::new();
+        for n in ints {
+            builder.push_int(n);
+        }
+        for s in strings {
+            builder.push_bytes(s.as_bytes());
+        }
+        builder.push_bytes(b"12345");
+        let listpack = builder.finish();
+
+        let mut expected: Vec<_> = ints.iter().map(|n| Element::Int(*n)).collect();
+        expected.extend(strings.iter().map(|s| Element::Str(s.to_string().into())));
+        expected.push(Element::Int(12345));
+        assert_eq!(decode(&listpack)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn small_entries_match_redis() {
+        let mut builder = Builder::new();
+        builder.push_int(1);
+        builder.push_bytes(b"ab");
+        builder.push_int(-1);
+        assert_eq!(
+            &builder.finish()[..],
+            b"\x10\x00\x00\x00\x03\x00\x01\x01\x82ab\x03\xdf\xff\x02\xff"
+        );
+    }
+}
@@ -1,65 +1,283 @@
-use anyhow::{ensure, Context};
+use anyhow::{bail, ensure, Context};
 use bytes::Bytes;
-
-use crate::{comms::Comms, connection::Connection, frame::Frame, info::Info, store::Store};
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::time::timeout;
+
+use crate::{
+    command::{repl_conf::ReplConf, Command},
+    comms::Comms,
+    connection::Connection,
+    frame::Frame,
+    info::Info,
+    store::{
+        role::{LinkState, Role},
+        Store,
+    },
+};
+
+/// How long to wait before reconnecting to a master that went away.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
 
 pub struct Replicator {
     store: Store,
     info: Info,
+    /// The id of the master last synced from, and the offset of its stream
+    /// the sync started at; kept across reconnects to ask for a partial resync.
+    master: Option<(String, u64)>,
+    /// Bytes of the replication stream processed since the last sync.
+    offset: u64,
+    /// Told whether the master took over from us, when we are handing over to
+    /// it with FAILOVER.
+    failover: Option<oneshot::Sender<anyhow::Result<()>>>,
 }
 
 impl Replicator {
     pub fn new(store: Store, info: Info) -> Self {
-        Self { store, info }
+        Self {
+            store,
+            info,
+            master: None,
+            offset: 0,
+            failover: None,
+        }
     }
 
+    /// Follows the master, reconnecting whenever the link drops, until the
+    /// server shuts down.
     pub async fn run(&mut self) -> anyhow::Result<()> {
         let master_address = self.info.replication.master_address()?;
-        let socket = tokio::net::TcpStream::connect(master_address).await?;
-        let (reader, writer) = socket.into_split();
-        let comms = Connection::new(reader, writer, true);
+        let mut shutdown = self.store.shutdown_listener();
+        loop {
+            tokio::select! {
+                result = self.connect(&master_address) => match (result, self.failover.take()) {
+                    (Ok(()), _) => log_notice!("MASTER closed the replication link"),
+                    // FAILOVER decides what to do about a refused handover
+                    (Err(err), Some(failover)) => {
+                        let _ = failover.send(Err(err));
+                    }
+                    (Err(err), None) => log_warning!("replication error: {:#}", err),
+                },
+                _ = shutdown.recv() => return Ok(()),
+            }
+            // a failed handshake stays at the step that failed until the retry
+            if self.store.role().link_state() == LinkState::Connected {
+                self.store.role().set_link_state(LinkState::Connect);
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(RECONNECT_DELAY) => {}
+                _ = shutdown.recv() => return Ok(()),
+            }
+        }
+    }
 
-        self.run_replication(comms).await
+    async fn connect(&mut self, master_address: &str) -> anyhow::Result<()> {
+        let socket = self
+            .step(LinkState::Connect, async {
+                Ok(tokio::net::TcpStream::connect(master_address).await?)
+            })
+            .await?;
+        match self.store.replication_tls() {
+            Some(tls) => {
+                let host = master_address
+                    .rsplit_once(':')
+                    .map_or(master_address, |(host, _)| host);
+                let stream = self
+                    .step(LinkState::Connect, tls.connect(host, socket))
+                    .await?;
+                let (reader, writer) = tokio::io::split(stream);
+                self.run_replication(Connection::new(reader, writer)).await
+            }
+            None => {
+                let (reader, writer) = socket.into_split();
+                self.run_replication(Connection::new(reader, writer)).await
+            }
+        }
     }
 
-    async fn run_replication<C: Comms>(&mut self, mut comms: C) -> anyhow::Result<()> {
-        hand_shake(&mut comms, &ping_fame()?, Frame::Simple("PONG".into())).await?;
+    /// Runs one step of the link to the master, recording it for INFO and
+    /// giving up if the master doesn't finish it within the replication
+    /// timeout.
+    async fn step<T>(
+        &self,
+        state: LinkState,
+        step: impl Future<Output = anyhow::Result<T>>,
+    ) -> anyhow::Result<T> {
+        self.store.role().set_link_state(state);
+        match timeout(self.store.repl_timeout(), step).await {
+            Ok(result) => result.with_context(|| format!("replication failed at {}", state)),
+            Err(_) => bail!("replication timed out at {}", state),
+        }
+    }
 
-        hand_shake(
-            &mut comms,
-            &listening_port_frame(&self.info)?,
-            Frame::Simple("OK".into()),
+    async fn run_replication<C: Comms>(&mut self, mut comms: C) -> anyhow::Result<()> {
+        let ping = ping_fame()?;
+        self.step(
+            LinkState::Ping,
+            hand_shake(&mut comms, &ping, Frame::Simple("PONG".into())),
         )
         .await?;
 
-        hand_shake(&mut comms, &capability_bytes()?, Frame::Simple("OK".into())).await?;
+        let listening_port = listening_port_frame(&self.info)?;
+        self.step(
+            LinkState::ListeningPort,
+            hand_shake(&mut comms, &listening_port, Frame::Simple("OK".into())),
+        )
+        .await?;
 
-        comms.write_frame(&psync_bytes().await?).await?;
+        let capa = capability_bytes()?;
+        self.step(
+            LinkState::Capa,
+            hand_shake(&mut comms, &capa, Frame::Simple("OK".into())),
+        )
+        .await?;
 
-        match comms.read_frame().await? {
-            Some(Frame::Simple(response)) => {
-                // TODO: do something with response
+        let master = self
+            .master
+            .as_ref()
+            .map(|(replid, offset)| (replid.as_str(), offset + self.offset));
+        let psync = psync_bytes(master, self.failover.is_some()).await?;
+        let response = self
+            .step(LinkState::Psync, async {
+                comms.write_frame(&psync).await?;
+                match comms.read_frame().await? {
+                    Some(Frame::Simple(response)) => Ok(response),
+                    response => bail!("expected FULLRESYNC or CONTINUE, got {:?}", response),
+                }
+            })
+            .await?;
+        let mut words = response.split_whitespace();
+        match words.next() {
+            Some("FULLRESYNC") => {
+                let replid = words.next().context("FULLRESYNC without a replid")?;
+                let offset = words
+                    .next()
+                    .context("FULLRESYNC without an offset")?
+                    .parse()
+                    .context("invalid FULLRESYNC offset")?;
+                let rdb = self
+                    .step(LinkState::Transfer, async {
+                        match comms.read_frame().await? {
+                            Some(Frame::Bulk(rdb) | Frame::RdbFile(rdb)) => Ok(rdb),
+                            frame => bail!("expected an RDB snapshot, got {:?}", frame),
+                        }
+                    })
+                    .await?;
+                // our own replicas must not sync from a half loaded keyspace
+                let _lock = self.store.command_lock().await;
+                self.store
+                    .load_rdb(rdb)
+                    .await
+                    .context("loading the master's RDB snapshot")?;
+                // the stream after a full resync starts out on database 0
+                self.store.select(0);
+                self.store.replicas().resync(replid.to_string(), offset);
+                self.master = Some((replid.to_string(), offset));
+                self.offset = 0;
+            }
+            Some("CONTINUE") => {
+                // the stream picks up where we left off
+                let (replid, offset) = self
+                    .master
+                    .take()
+                    .context("CONTINUE without a previous sync")?;
+                let replid = words.next().map_or(replid, str::to_string);
+                self.store.replicas().rename(replid.clone());
+                self.master = Some((replid, offset + self.offset));
+                self.offset = 0;
             }
-            _ => anyhow::bail!("replicator received invalid response"),
+            _ => bail!("replicator received invalid response {:?}", response),
+        }
+        self.store.role().set_link_state(LinkState::Connected);
+        if let Some(failover) = self.failover.take() {
+            let _ = failover.send(Ok(()));
         }
 
+        // the master pings well within the timeout, so silence means it's gone
         loop {
-            if let Some(frame) = comms.read_frame().await? {
-                match &frame {
-                    Frame::Array(_) => {
-                        let command = crate::command::Command::from_frame(frame)
-                            .context("expecting update replica commands")?;
-                        command.apply(&self.store, &mut comms).await?;
-                    }
-                    _ => {
-                        eprintln!("dropping rdb file {:?}", frame);
+            let Ok(frame) = timeout(self.store.repl_timeout(), comms.read_frame()).await else {
+                bail!("no data from master for {:?}", self.store.repl_timeout());
+            };
+            let Some(frame) = frame? else {
+                break;
+            };
+            match &frame {
+                Frame::Array(_) => {
+                    let len = frame.encoded_len() as u64;
+                    let forwarded = frame.clone();
+                    let _lock = self.store.command_lock().await;
+                    // the master only expects a reply to REPLCONF GETACK
+                    match Command::from_frame(frame) {
+                        Ok(Command::ReplConf(cmd)) if cmd.is_getack() => {
+                            comms.write_frame(&ReplConf::ack(self.offset)).await?;
+                        }
+                        Ok(command) => command.apply_quietly(&self.store).await,
+                        Err(err) => {
+                            log_warning!("ignoring unparsable command from master: {:#}", err)
+                        }
                     }
+                    // a GETACK counts towards the offset only after it is answered
+                    self.offset += len;
+                    // passed on whole, GETACKs included, so offsets down the
+                    // chain match the master's
+                    self.store.replicas().forward(forwarded)?;
+                }
+                _ => {
+                    log_warning!("dropping unexpected frame from master {:?}", frame);
                 }
             }
         }
+        Ok(())
     }
 }
 
+/// Makes `store` a replica of `host:port`, replacing any master it followed
+/// before. The link runs until the role changes again.
+pub fn follow(store: &Store, host: String, port: u16) -> anyhow::Result<()> {
+    store.role().set(Role::Replica { host, port });
+    // one handle for as long as we follow, so the database the master's stream
+    // selected carries over a reconnect that resumes the stream
+    let replicator = Replicator::new(store.for_client(), Info::from_store(store)?);
+    spawn_link(store, replicator);
+    Ok(())
+}
+
+/// Makes `store`, a master, a replica of its replica at `host:port`, which
+/// has caught up with it. The first PSYNC asks the replica to take over as
+/// master and to carry on with our stream; the receiver learns whether it
+/// agreed.
+pub fn hand_over(
+    store: &Store,
+    host: String,
+    port: u16,
+) -> anyhow::Result<oneshot::Receiver<anyhow::Result<()>>> {
+    let stream = (store.replicas().replid(), store.master_repl_offset());
+    store.role().set(Role::Replica { host, port });
+    let (sender, receiver) = oneshot::channel();
+    let mut replicator = Replicator::new(store.for_client(), Info::from_store(store)?);
+    replicator.master = Some(stream);
+    replicator.failover = Some(sender);
+    spawn_link(store, replicator);
+    Ok(receiver)
+}
+
+/// Stops following any master and takes writes as a master again, keeping
+/// the data, under a replication id of our own.
+pub fn unfollow(store: &Store) {
+    store.role().set(Role::Master);
+    store.replicas().promote();
+}
+
+fn spawn_link(store: &Store, mut replicator: Replicator) {
+    let link = tokio::spawn(async move {
+        if let Err(err) = replicator.run().await {
+            log_warning!("replication error: {:#}", err);
+        }
+    });
+    store.role().set_link(link.abort_handle());
+}
+
 async fn hand_shake<C: Comms>(
     comms: &mut C,
     command: &Frame,
@@ -109,39 +327,216 @@ fn capability_bytes() -> anyhow::Result<Frame> {
     Ok(array)
 }
 
-async fn psync_bytes() -> anyhow::Result<Frame> {
+/// PSYNC asking to continue from `master`'s id and offset, or for a full
+/// resync without one, and with `failover` for the master to take over from us.
+async fn psync_bytes(master: Option<(&str, u64)>, failover: bool) -> anyhow::Result<Frame> {
+    let (replid, offset) = match master {
+        Some((replid, offset)) => (replid.to_string(), offset.to_string()),
+        None => ("?".to_string(), "-1".to_string()),
+    };
     let mut array = Frame::array();
     array.push_bulk(Bytes::from("PSYNC"))?;
-    array.push_bulk(Bytes::from("?"))?;
-    array.push_bulk(Bytes::from("-1"))?;
+    array.push_bulk(Bytes::from(replid))?;
+    array.push_bulk(Bytes::from(offset))?;
+    if failover {
+        array.push_bulk(Bytes::from("FAILOVER"))?;
+    }
     Ok(array)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::comms::InMemoryComms;
 
-    //#[tokio::test]
-    // TODO: add shutdown support
-    async fn test_run_replication() -> anyhow::Result<()> {
-        let mut replicator = Replicator::new(Store::new(), Info::default());
+    #[tokio::test]
+    async fn run_follows_the_master_until_shutdown() -> anyhow::Result<()> {
+        let master = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let store = Store::new();
+        let port = master.local_addr()?.port();
+        store.role().set(Role::Replica {
+            host: "127.0.0.1".to_string(),
+            port,
+        });
+        let mut replicator = Replicator::new(store.clone(), Info::from_store(&store)?);
+        let following = tokio::spawn(async move { replicator.run().await });
+
+        let (socket, _) = master.accept().await?;
+        let (reader, writer) = socket.into_split();
+        let mut link = Connection::new(reader, writer);
+        for reply in [
+            "PONG",
+            "OK",
+            "OK",
+            "FULLRESYNC 8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb 0",
+        ] {
+            link.read_frame().await?;
+            link.write_frame(&Frame::Simple(reply.to_string())).await?;
+        }
+        link.write_frame(&Frame::Bulk(Store::new().as_rdb().await?))
+            .await?;
+        while store.role().link_state() != LinkState::Connected {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // the link is idle, waiting on the master, when the server stops
+        store.begin_shutdown();
+        timeout(Duration::from_secs(1), following).await???;
+        assert_eq!(link.read_frame().await?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn acks_processed_offset() -> anyhow::Result<()> {
+        let store = Store::new();
+        let mut replicator = Replicator::new(store.clone(), Info::default());
+
+        let getack = b"*3\r\n$8\r\nREPLCONF\r\n$6\r\nGETACK\r\n$1\r\n*\r\n";
+        let rdb = Store::new().as_rdb().await?;
+        let rdb = [format!("${}\r\n", rdb.len()).as_bytes(), &rdb].concat();
+        let reader = tokio_test::io::Builder::new()
+            .read(b"+PONG\r\n")
+            .read(b"+OK\r\n")
+            .read(b"+OK\r\n")
+            .read(b"+FULLRESYNC 8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb 0\r\n")
+            .read(&rdb)
+            .read(getack)
+            .read(b"*1\r\n$4\r\nPING\r\n")
+            .read(b"*3\r\n$3\r\nSET\r\n$3\r\nfoo\r\n$3\r\nbar\r\n")
+            .read(getack)
+            .build();
+        let writer = tokio_test::io::Builder::new()
+            .write(b"*1\r\n$4\r\nPING\r\n")
+            .write(b"*3\r\n$8\r\nREPLCONF\r\n$14\r\nlistening-port\r\n$4\r\n6379\r\n")
+            .write(b"*3\r\n$8\r\nREPLCONF\r\n$4\r\ncapa\r\n$6\r\npsync2\r\n")
+            .write(b"*3\r\n$5\r\nPSYNC\r\n$1\r\n?\r\n$2\r\n-1\r\n")
+            .write(b"*3\r\n$8\r\nREPLCONF\r\n$3\r\nACK\r\n$1\r\n0\r\n")
+            // GETACK (37) + PING (14) + SET (31)
+            .write(b"*3\r\n$8\r\nREPLCONF\r\n$3\r\nACK\r\n$2\r\n82\r\n")
+            .build();
+
+        replicator
+            .run_replication(Connection::new(reader, writer))
+            .await?;
+        assert_eq!(store.get_string(&"foo".into()), Ok(Some("bar".into())));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn never_replies_to_replicated_commands() -> anyhow::Result<()> {
+        let store = Store::new();
+        let mut replicator = Replicator::new(store.clone(), Info::default());
 
+        let rdb = Store::new().as_rdb().await?;
+        let rdb = [format!("${}\r\n", rdb.len()).as_bytes(), &rdb].concat();
         let reader = tokio_test::io::Builder::new()
             .read(b"+PONG\r\n")
             .read(b"+OK\r\n")
             .read(b"+OK\r\n")
+            .read(b"+FULLRESYNC 8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb 0\r\n")
+            .read(&rdb)
+            .read(b"*3\r\n$3\r\nSET\r\n$3\r\nfoo\r\n$3\r\nbar\r\n")
+            // a wrong type error, an unknown command and one that fails to parse
+            .read(b"*4\r\n$4\r\nZADD\r\n$3\r\nfoo\r\n$1\r\n1\r\n$1\r\na\r\n")
+            .read(b"*2\r\n$4\r\nNOPE\r\n$3\r\nfoo\r\n")
+            .read(b"*1\r\n$3\r\nGET\r\n")
+            .read(b"*3\r\n$8\r\nREPLCONF\r\n$6\r\nGETACK\r\n$1\r\n*\r\n")
             .build();
         let writer = tokio_test::io::Builder::new()
             .write(b"*1\r\n$4\r\nPING\r\n")
             .write(b"*3\r\n$8\r\nREPLCONF\r\n$14\r\nlistening-port\r\n$4\r\n6379\r\n")
             .write(b"*3\r\n$8\r\nREPLCONF\r\n$4\r\ncapa\r\n$6\r\npsync2\r\n")
             .write(b"*3\r\n$5\r\nPSYNC\r\n$1\r\n?\r\n$2\r\n-1\r\n")
-            .read(b"+yup\r\n")
+            // SET (31) + ZADD (37) + NOPE (23) + GET (13)
+            .write(b"*3\r\n$8\r\nREPLCONF\r\n$3\r\nACK\r\n$3\r\n104\r\n")
             .build();
 
-        let connection = Connection::new(reader, writer, true);
+        replicator
+            .run_replication(Connection::new(reader, writer))
+            .await?;
+        assert_eq!(store.get_string(&"foo".into()), Ok(Some("bar".into())));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reconnects_with_a_partial_resync() -> anyhow::Result<()> {
+        let store = Store::new();
+        let mut replicator = Replicator::new(store.clone(), Info::default());
+        let handshake = || {
+            let mut reader = tokio_test::io::Builder::new();
+            reader.read(b"+PONG\r\n").read(b"+OK\r\n").read(b"+OK\r\n");
+            let mut writer = tokio_test::io::Builder::new();
+            writer
+                .write(b"*1\r\n$4\r\nPING\r\n")
+                .write(b"*3\r\n$8\r\nREPLCONF\r\n$14\r\nlistening-port\r\n$4\r\n6379\r\n")
+                .write(b"*3\r\n$8\r\nREPLCONF\r\n$4\r\ncapa\r\n$6\r\npsync2\r\n");
+            (reader, writer)
+        };
+
+        let rdb = Store::new().as_rdb().await?;
+        let rdb = [format!("${}\r\n", rdb.len()).as_bytes(), &rdb].concat();
+        let (mut reader, mut writer) = handshake();
+        reader
+            .read(b"+FULLRESYNC 8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb 100\r\n")
+            .read(&rdb)
+            .read(b"*3\r\n$3\r\nSET\r\n$3\r\nfoo\r\n$3\r\nbar\r\n");
+        writer.write(b"*3\r\n$5\r\nPSYNC\r\n$1\r\n?\r\n$2\r\n-1\r\n");
+        replicator
+            .run_replication(Connection::new(reader.build(), writer.build()))
+            .await?;
+
+        // 100 + SET (31)
+        let (mut reader, mut writer) = handshake();
+        reader
+            .read(b"+CONTINUE 8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb\r\n")
+            .read(b"*3\r\n$3\r\nSET\r\n$3\r\nfoo\r\n$3\r\nbaz\r\n")
+            .read(b"*3\r\n$8\r\nREPLCONF\r\n$6\r\nGETACK\r\n$1\r\n*\r\n");
+        writer
+            .write(b"*3\r\n$5\r\nPSYNC\r\n$40\r\n8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb\r\n$3\r\n131\r\n")
+            .write(b"*3\r\n$8\r\nREPLCONF\r\n$3\r\nACK\r\n$2\r\n31\r\n");
+        replicator
+            .run_replication(Connection::new(reader.build(), writer.build()))
+            .await?;
+        assert_eq!(store.get_string(&"foo".into()), Ok(Some("baz".into())));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn handshake_times_out_on_a_silent_master() -> anyhow::Result<()> {
+        use tokio::io::AsyncWriteExt;
+
+        let store = Store::new();
+        store.set_repl_timeout(Duration::from_millis(50));
+        let mut replicator = Replicator::new(store.clone(), Info::default());
+
+        // answers PING, then hangs
+        let (link, mut master) = tokio::io::duplex(1024);
+        master.write_all(b"+PONG\r\n").await?;
+        let (reader, writer) = tokio::io::split(link);
+        let err = replicator
+            .run_replication(Connection::new(reader, writer))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "replication timed out at listening-port");
+        assert_eq!(store.role().link_state(), LinkState::ListeningPort);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn handshake_errors_name_the_step() -> anyhow::Result<()> {
+        let mut replicator = Replicator::new(Store::new(), Info::default());
+        let mut comms = InMemoryComms::new([Frame::Error("ERR unknown command".to_string())]);
 
-        replicator.run_replication(connection).await?;
+        let err = replicator.run_replication(&mut comms).await.unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "replication failed at ping: replicator received invalid response. Expected: Simple(\"PONG\"), got: Error(\"ERR unknown command\")"
+        );
+        assert_eq!(comms.written(), [ping_fame()?]);
 
         Ok(())
     }
@@ -177,8 +572,12 @@ mod tests {
 
     #[tokio::test]
     async fn test_psync_bytes() -> anyhow::Result<()> {
-        let frame = psync_bytes().await?;
+        let frame = psync_bytes(None, false).await?;
         assert_eq!(frame.to_string(), "PSYNC ? -1");
+        let frame = psync_bytes(Some(("8371b4fb", 31)), false).await?;
+        assert_eq!(frame.to_string(), "PSYNC 8371b4fb 31");
+        let frame = psync_bytes(Some(("8371b4fb", 31)), true).await?;
+        assert_eq!(frame.to_string(), "PSYNC 8371b4fb 31 FAILOVER");
 
         Ok(())
     }
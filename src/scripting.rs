@@ -0,0 +1,156 @@
+//! Lua scripting for EVAL, EVALSHA, FCALL and FUNCTION LOAD.
+//!
+//! Each script runs in a fresh, sandboxed Lua 5.1 state on a blocking thread.
+//! `redis.call` and `redis.pcall` parse their arguments as a command and
+//! apply it through the runtime handle, so the writes a script makes are
+//! replicated one by one, exactly as if a client had sent them. Function
+//! libraries are loaded again into that fresh state on every FCALL.
+//!
+//! Scripts hold the transaction lock for as long as they run. Past
+//! `lua-time-limit`, clients waiting on it are told BUSY instead, and SCRIPT
+//! KILL or FUNCTION KILL stops a script that hasn't written yet. A library
+//! FUNCTION LOAD runs gets half a second.
+//!
+//! Lua itself comes with the `scripting` feature. Without it, every script
+//! fails with an error reply instead.
+
+use bytes::Bytes;
+use tokio::runtime::Handle;
+
+use crate::{
+    frame::Frame,
+    store::{functions::Library, Store},
+};
+
+#[cfg(feature = "scripting")]
+mod lua;
+
+/// The handle a script runs commands through: it starts on the caller's
+/// database, and a SELECT in the script leaves the caller's as it was.
+fn script_store(store: &Store) -> Store {
+    let script = store.for_client();
+    script.select(store.db());
+    script.set_user(store.user());
+    script.set_no_touch(store.no_touch());
+    script
+}
+
+/// Runs `body` with `KEYS` and `ARGV` set and returns its reply. Callers hold
+/// the store's transaction lock so the script runs atomically.
+pub async fn run(
+    store: &Store,
+    body: Bytes,
+    keys: Vec<Bytes>,
+    args: Vec<Bytes>,
+) -> anyhow::Result<Frame> {
+    let store = script_store(store);
+    let handle = Handle::current();
+    let reply =
+        tokio::task::spawn_blocking(move || lua::eval(&store, &handle, &body, keys, args)).await?;
+    Ok(reply)
+}
+
+/// Runs `function` from `library`, passing it the keys and arguments as two
+/// tables. Callers hold the store's transaction lock, as for `run`.
+pub async fn call_function(
+    store: &Store,
+    library: Library,
+    function: String,
+    keys: Vec<Bytes>,
+    args: Vec<Bytes>,
+) -> anyhow::Result<Frame> {
+    let store = script_store(store);
+    let handle = Handle::current();
+    let reply = tokio::task::spawn_blocking(move || {
+        lua::fcall(&store, &handle, &library, &function, keys, args)
+    })
+    .await?;
+    Ok(reply)
+}
+
+/// Checks a FUNCTION LOAD payload and runs it to find the functions it
+/// registers. The first line must be `#!lua name=<library>`.
+pub async fn load_library(code: Bytes) -> Result<Library, String> {
+    tokio::task::spawn_blocking(move || load(code))
+        .await
+        .unwrap_or_else(|err| Err(format!("ERR {}", err)))
+}
+
+fn load(code: Bytes) -> Result<Library, String> {
+    let header = code.split(|&b| b == b'\n').next().unwrap_or_default();
+    let name = library_name(header)?;
+    // keep the header line empty so error line numbers still match
+    let mut functions = lua::register_library(&code[header.len()..])?;
+    if functions.is_empty() {
+        return Err("ERR No functions registered".to_string());
+    }
+    functions.sort();
+    Ok(Library {
+        name,
+        code,
+        functions,
+    })
+}
+
+fn library_name(header: &[u8]) -> Result<String, String> {
+    let Some(header) = std::str::from_utf8(header)
+        .ok()
+        .and_then(|header| header.strip_prefix("#!"))
+    else {
+        return Err("ERR Missing library metadata".to_string());
+    };
+    let mut parts = header.split_whitespace();
+    let engine = parts.next().unwrap_or_default();
+    if engine != "lua" {
+        return Err(format!("ERR Engine '{}' not found", engine));
+    }
+    let mut name = None;
+    for part in parts {
+        match part.strip_prefix("name=") {
+            Some(value) => name = Some(value.to_string()),
+            None => return Err(format!("ERR Invalid metadata value given: {}", part)),
+        }
+    }
+    match name {
+        Some(name) if is_valid_name(&name) => Ok(name),
+        Some(_) => Err("ERR Library names can only contain letters, numbers, or underscores(_) and must be at least one character long".to_string()),
+        None => Err("ERR Library name was not given".to_string()),
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+/// Without the `scripting` feature there is no Lua to run scripts in, and
+/// every script fails the same way.
+#[cfg(not(feature = "scripting"))]
+mod lua {
+    use bytes::Bytes;
+    use tokio::runtime::Handle;
+
+    use crate::{
+        error::Error,
+        frame::Frame,
+        store::{functions::Library, Store},
+    };
+
+    pub fn eval(_: &Store, _: &Handle, _: &[u8], _: Vec<Bytes>, _: Vec<Bytes>) -> Frame {
+        Error::NoScripting.into()
+    }
+
+    pub fn fcall(
+        _: &Store,
+        _: &Handle,
+        _: &Library,
+        _: &str,
+        _: Vec<Bytes>,
+        _: Vec<Bytes>,
+    ) -> Frame {
+        Error::NoScripting.into()
+    }
+
+    pub fn register_library(_: &[u8]) -> Result<Vec<String>, String> {
+        Err(Error::NoScripting.to_string())
+    }
+}
@@ -0,0 +1,351 @@
+//! The Lua side of scripting: sandboxed states, the `redis` table scripts
+//! call back through, and the conversions between Lua values and replies.
+
+use bytes::Bytes;
+use mlua::{FromLua, Function, HookTriggers, Lua, LuaOptions, StdLib, Table, Value, Variadic};
+use std::fmt;
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+
+use crate::{
+    command::{table, Command},
+    error::Error,
+    frame::Frame,
+    store::{functions::Library, Store},
+};
+
+/// Registry key of the table `redis.register_function` fills in.
+const FUNCTIONS: &str = "functions";
+
+/// How many Lua instructions run between a script's checks on its time.
+const CHECK_EVERY: u32 = 1000;
+
+const ERR_KILLED: &str = "ERR Script killed by user with SCRIPT KILL...";
+
+/// How long FUNCTION LOAD lets a library's body run, as in Redis.
+const LOAD_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Runs library `code` under FUNCTION LOAD's time limit and returns the
+/// names of the functions it registers.
+pub fn register_library(code: &[u8]) -> Result<Vec<String>, String> {
+    let registering = |err| error_message(&err, "Error registering functions");
+    let lua = sandbox().map_err(registering)?;
+    let deadline = Instant::now() + LOAD_TIMEOUT;
+    set_check(&lua, move || {
+        if Instant::now() > deadline {
+            return Err("ERR FUNCTION LOAD timeout");
+        }
+        Ok(())
+    });
+    let functions = register(&lua, code).map_err(registering)?;
+    functions
+        .pairs::<String, Function>()
+        .map(|pair| pair.map(|(name, _)| name))
+        .collect::<mlua::Result<Vec<_>>>()
+        .map_err(registering)
+}
+
+/// An error reply raised by `redis.call`, passed through to the client as is.
+#[derive(Debug)]
+struct ReplyError(String);
+
+impl fmt::Display for ReplyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for ReplyError {}
+
+/// A Lua state with only the table, string and math libraries, so scripts
+/// can't reach the host's files, processes or other code.
+fn sandbox() -> mlua::Result<Lua> {
+    let lua = Lua::new_with(
+        StdLib::TABLE | StdLib::STRING | StdLib::MATH,
+        LuaOptions::default(),
+    )?;
+    for name in ["dofile", "loadfile", "require", "loadstring", "load"] {
+        lua.globals().set(name, Value::Nil)?;
+    }
+    Ok(lua)
+}
+
+/// Has `lua` call `check` every few instructions, stopping with the error
+/// it returns.
+fn set_check(lua: &Lua, check: impl Fn() -> Result<(), &'static str> + Send + 'static) {
+    let triggers = HookTriggers::new().every_nth_instruction(CHECK_EVERY);
+    lua.set_hook(triggers, move |_, _| check().map_err(reply_error));
+}
+
+/// Has `lua` check in as it runs, so other clients are told BUSY once it
+/// runs past `lua-time-limit` and SCRIPT KILL can stop it.
+fn watch(lua: &Lua, store: &Store) {
+    let limit = Duration::from_millis(store.config().lua_time_limit());
+    let scripts = store.scripts().clone();
+    set_check(lua, move || {
+        if scripts.check(limit) {
+            return Err(ERR_KILLED);
+        }
+        Ok(())
+    });
+}
+
+pub fn eval(
+    store: &Store,
+    handle: &Handle,
+    body: &[u8],
+    keys: Vec<Bytes>,
+    args: Vec<Bytes>,
+) -> Frame {
+    let _running = store.scripts().start();
+    let result = (|| {
+        let lua = sandbox()?;
+        watch(&lua, store);
+        let globals = lua.globals();
+        globals.set("KEYS", strings(&lua, keys)?)?;
+        globals.set("ARGV", strings(&lua, args)?)?;
+        let redis = redis_table(&lua)?;
+        add_calls(&lua, &redis, store, handle)?;
+        globals.set("redis", redis)?;
+        let value = lua.load(body).set_name("@user_script").eval::<Value>()?;
+        Ok(to_frame(value))
+    })();
+    result.unwrap_or_else(|err| error_frame(&err))
+}
+
+pub fn fcall(
+    store: &Store,
+    handle: &Handle,
+    library: &Library,
+    function: &str,
+    keys: Vec<Bytes>,
+    args: Vec<Bytes>,
+) -> Frame {
+    let _running = store.scripts().start();
+    let result = (|| {
+        let lua = sandbox()?;
+        watch(&lua, store);
+        let header = library.code.iter().position(|&b| b == b'\n');
+        let functions = register(&lua, &library.code[header.unwrap_or(library.code.len())..])?;
+        let redis: Table = lua.globals().get("redis")?;
+        add_calls(&lua, &redis, store, handle)?;
+        let callback: Function = functions.get(function)?;
+        let value = callback.call::<_, Value>((strings(&lua, keys)?, strings(&lua, args)?))?;
+        Ok(to_frame(value))
+    })();
+    result.unwrap_or_else(|err| error_frame(&err))
+}
+
+/// The `redis` table every script sees.
+fn redis_table(lua: &Lua) -> mlua::Result<Table<'_>> {
+    let redis = lua.create_table()?;
+    for (name, field) in [("status_reply", "ok"), ("error_reply", "err")] {
+        let function = lua.create_function(move |lua, message: mlua::String| {
+            let reply = lua.create_table()?;
+            reply.set(field, message)?;
+            Ok(reply)
+        })?;
+        redis.set(name, function)?;
+    }
+    Ok(redis)
+}
+
+/// Adds `redis.call` and `redis.pcall`, which run commands against `store`.
+fn add_calls(lua: &Lua, redis: &Table, store: &Store, handle: &Handle) -> mlua::Result<()> {
+    for (name, raise) in [("call", true), ("pcall", false)] {
+        let (store, handle) = (store.clone(), handle.clone());
+        let function = lua.create_function(move |lua, args: Variadic<Value>| {
+            match call(&store, &handle, args)? {
+                Frame::Error(message) if raise => Err(mlua::Error::external(ReplyError(message))),
+                reply => to_lua(lua, reply),
+            }
+        })?;
+        redis.set(name, function)?;
+    }
+    Ok(())
+}
+
+/// Runs library `code` with only `redis.register_function` available and
+/// returns the functions it registered, by name.
+fn register<'lua>(lua: &'lua Lua, code: &[u8]) -> mlua::Result<Table<'lua>> {
+    let functions = lua.create_table()?;
+    lua.set_named_registry_value(FUNCTIONS, &functions)?;
+    let redis = redis_table(lua)?;
+    redis.set("register_function", lua.create_function(register_function)?)?;
+    lua.globals().set("redis", redis)?;
+    lua.load(code).set_name("@user_function").exec()?;
+    Ok(functions)
+}
+
+/// `redis.register_function(name, callback)`, or the single table form with
+/// `function_name` and `callback` fields.
+fn register_function<'lua>(
+    lua: &'lua Lua,
+    (name, callback): (Value<'lua>, Option<Function<'lua>>),
+) -> mlua::Result<()> {
+    let (name, callback) = match name {
+        Value::Table(fields) => (fields.get("function_name")?, fields.get("callback")?),
+        name => match callback {
+            Some(callback) => (String::from_lua(name, lua)?, callback),
+            None => {
+                return Err(reply_error(
+                    "ERR wrong number of arguments to redis.register_function",
+                ))
+            }
+        },
+    };
+    if !super::is_valid_name(&name) {
+        return Err(reply_error("ERR Function names can only contain letters, numbers, or underscores(_) and must be at least one character long"));
+    }
+    let functions: Table = lua.named_registry_value(FUNCTIONS)?;
+    if functions.contains_key(name.as_str())? {
+        return Err(reply_error("ERR Function already exists in the library"));
+    }
+    functions.set(name, callback)
+}
+
+fn reply_error(message: &str) -> mlua::Error {
+    mlua::Error::external(ReplyError(message.to_string()))
+}
+
+fn strings(lua: &Lua, values: Vec<Bytes>) -> mlua::Result<Table<'_>> {
+    lua.create_sequence_from(
+        values
+            .iter()
+            .map(|value| lua.create_string(value))
+            .collect::<mlua::Result<Vec<_>>>()?,
+    )
+}
+
+/// Applies the command a script passed to `redis.call` and returns its reply.
+fn call(store: &Store, handle: &Handle, args: Variadic<Value>) -> mlua::Result<Frame> {
+    let parts = args
+        .into_iter()
+        .map(|arg| match arg {
+            Value::String(s) => Ok(Frame::Bulk(Bytes::copy_from_slice(s.as_bytes()))),
+            Value::Integer(n) => Ok(Frame::Bulk(n.to_string().into())),
+            Value::Number(n) => Ok(Frame::Bulk(n.to_string().into())),
+            _ => Err(reply_error(
+                "ERR Lua redis lib command arguments must be strings or integers",
+            )),
+        })
+        .collect::<mlua::Result<Vec<_>>>()?;
+    if parts.is_empty() {
+        return Err(reply_error(
+            "ERR Please specify at least one argument for this redis lib call",
+        ));
+    }
+    let name = match &parts[0] {
+        Frame::Bulk(name) => String::from_utf8_lossy(name).to_lowercase(),
+        _ => String::new(),
+    };
+    let request = Frame::Array(parts);
+    if let Err(error) = store.acl().check(store.user().as_deref(), &request) {
+        return Ok(Frame::Error(error));
+    }
+    let command = match Command::from_frame(request) {
+        Ok(command) => command,
+        Err(err) => return Ok(Frame::from_error(&err)),
+    };
+    if !command.is_allowed_in_script() {
+        return Ok(Frame::Error(
+            "ERR This Redis command is not allowed from script".to_string(),
+        ));
+    }
+    if command.is_write() && store.is_replica() {
+        return Ok(Error::ReadOnly.into());
+    }
+    if table::denies_oom(&name) && !store.evict() {
+        return Ok(Error::Oom.into());
+    }
+    if command.is_write() {
+        store.scripts().wrote();
+    }
+    let reply = handle.block_on(command.without_blocking().apply(store));
+    Ok(reply.unwrap_or_else(|err| Frame::from_error(&err)))
+}
+
+/// Converts a command reply to the Lua value `redis.call` returns.
+fn to_lua(lua: &Lua, frame: Frame) -> mlua::Result<Value<'_>> {
+    let status = |field: &str, message: String| -> mlua::Result<Value> {
+        let table = lua.create_table()?;
+        table.set(field, message)?;
+        Ok(Value::Table(table))
+    };
+    match frame {
+        Frame::Simple(message) => status("ok", message),
+        Frame::OK => status("ok", "OK".to_string()),
+        Frame::Error(message) => status("err", message),
+        Frame::Integer(n) => Ok(Value::Integer(n as i64)),
+        Frame::Signed(n) => Ok(Value::Integer(n)),
+        Frame::Bulk(bytes) | Frame::RdbFile(bytes) => Ok(Value::String(lua.create_string(&bytes)?)),
+        Frame::Null | Frame::NullArray => Ok(Value::Boolean(false)),
+        Frame::Array(frames) | Frame::Push(frames) | Frame::Set(frames) => {
+            let values = frames
+                .into_iter()
+                .map(|frame| to_lua(lua, frame))
+                .collect::<mlua::Result<Vec<_>>>()?;
+            Ok(Value::Table(lua.create_sequence_from(values)?))
+        }
+        // scripts speak RESP2, where maps are flat arrays, doubles and big
+        // numbers are strings and booleans are 1 or 0
+        Frame::Double(n) => to_lua(lua, Frame::Bulk(Bytes::from(n.to_string()))),
+        Frame::BigNumber(digits) => Ok(Value::String(lua.create_string(&digits)?)),
+        Frame::Verbatim(_, text) => Ok(Value::String(lua.create_string(&text)?)),
+        Frame::Boolean(b) => Ok(Value::Integer(i64::from(b))),
+        Frame::Map(pairs) => to_lua(
+            lua,
+            Frame::Array(pairs.into_iter().flat_map(|(k, v)| [k, v]).collect()),
+        ),
+    }
+}
+
+/// Converts a script's return value to the reply sent to the client.
+fn to_frame(value: Value) -> Frame {
+    match value {
+        Value::Boolean(true) => Frame::Integer(1),
+        Value::Integer(n) => Frame::integer(n),
+        Value::Number(n) => Frame::integer(n as i64),
+        Value::String(s) => Frame::Bulk(Bytes::copy_from_slice(s.as_bytes())),
+        Value::Table(table) => {
+            if let Ok(Some(message)) = table.raw_get::<_, Option<String>>("err") {
+                return Frame::Error(message);
+            }
+            if let Ok(Some(message)) = table.raw_get::<_, Option<String>>("ok") {
+                return Frame::Simple(message);
+            }
+            // like Redis, arrays stop at the first nil
+            Frame::Array(
+                table
+                    .sequence_values::<Value>()
+                    .map_while(Result::ok)
+                    .map(to_frame)
+                    .collect(),
+            )
+        }
+        Value::Error(err) => error_frame(&err),
+        _ => Frame::Null,
+    }
+}
+
+fn error_frame(err: &mlua::Error) -> Frame {
+    Frame::Error(error_message(err, "Error running script"))
+}
+
+/// The reply for a failed script: errors from `redis.call` pass through as
+/// they are, anything else is reported under `context`.
+fn error_message(err: &mlua::Error, context: &str) -> String {
+    match err {
+        mlua::Error::CallbackError { cause, .. } => error_message(cause, context),
+        mlua::Error::ExternalError(cause) => match cause.downcast_ref::<ReplyError>() {
+            Some(ReplyError(message)) => message.clone(),
+            None => lua_error(cause, context),
+        },
+        err => lua_error(err, context),
+    }
+}
+
+fn lua_error(err: &impl fmt::Display, context: &str) -> String {
+    let message = err.to_string().replace(['\r', '\n'], " ");
+    format!("ERR {}: {}", context, message)
+}
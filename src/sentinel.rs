@@ -0,0 +1,197 @@
+//! Sentinel mode's watch over the master: a PING and an INFO replication
+//! every second, and a failover once the master is down. See
+//! `store::sentinel` for what is watched.
+
+use anyhow::{bail, Context};
+use std::time::Duration;
+use tokio::time::timeout;
+
+use crate::{
+    client::Client,
+    frame::Frame,
+    store::{
+        sentinel::{Address, Master, Replica},
+        Store,
+    },
+};
+
+/// How often the master is pinged, unless `down-after-milliseconds` is shorter.
+const PING_PERIOD: Duration = Duration::from_secs(1);
+
+/// Watches the master until the server shuts down.
+pub async fn run(store: Store) -> anyhow::Result<()> {
+    let mut shutdown = store.shutdown_listener();
+    loop {
+        let Some(master) = store.sentinel().master() else {
+            return Ok(());
+        };
+        let period = PING_PERIOD.min(master.down_after);
+        tokio::select! {
+            _ = tokio::time::sleep(period) => {}
+            _ = shutdown.recv() => return Ok(()),
+        }
+        check(&store, master, period).await;
+    }
+}
+
+/// Pings the master, failing it over if it has been down long enough, and
+/// points masters failed over from at the new one once they are back.
+async fn check(store: &Store, master: Master, period: Duration) {
+    match timeout(period, probe(&master.address)).await {
+        Ok(Ok(info)) => {
+            if store.sentinel().replied(replicas(&info)) {
+                log_notice!("-sdown master {} {}", master.name, master.address);
+            }
+        }
+        _ => {
+            if !store.sentinel().check_down() {
+                return;
+            }
+            if !master.down {
+                log_warning!("+sdown master {} {}", master.name, master.address);
+            }
+            fail_over(store, &master, period).await;
+        }
+    }
+    for old in &master.demoted {
+        match timeout(period, follow(old, &master.address)).await {
+            Ok(Ok(())) => {
+                log_notice!("+convert-to-slave slave {} @ {}", old, master.name);
+                store.sentinel().demoted(old);
+            }
+            // still away, most likely; tried again on the next check
+            _ => log_debug!("can't demote {} yet", old),
+        }
+    }
+}
+
+/// Promotes the replica furthest along, and tells the others to follow it.
+async fn fail_over(store: &Store, master: &Master, period: Duration) {
+    log_notice!("+try-failover master {} {}", master.name, master.address);
+    let mut candidates = master.replicas.clone();
+    candidates.sort_by_key(|replica| std::cmp::Reverse(replica.offset));
+    let mut promoted = None;
+    for (i, replica) in candidates.iter().enumerate() {
+        let promote = call(&replica.address, &["REPLICAOF", "NO", "ONE"]);
+        match timeout(period, promote).await {
+            Ok(Ok(())) => {
+                promoted = Some(i);
+                break;
+            }
+            Ok(Err(err)) => log_warning!("can't promote {}: {:#}", replica.address, err),
+            Err(_) => log_warning!("can't promote {}: timed out", replica.address),
+        }
+    }
+    let Some(promoted) = promoted else {
+        log_warning!(
+            "-failover-abort-no-good-slave master {} {}",
+            master.name,
+            master.address
+        );
+        return;
+    };
+    let promoted = candidates.remove(promoted).address;
+    log_notice!("+promoted-slave slave {} @ {}", promoted, master.name);
+    let mut replicas = vec![];
+    for replica in candidates {
+        match timeout(period, follow(&replica.address, &promoted)).await {
+            Ok(Ok(())) => replicas.push(Replica {
+                offset: 0,
+                ..replica
+            }),
+            _ => log_warning!("can't point {} at {}", replica.address, promoted),
+        }
+    }
+    log_notice!(
+        "+switch-master {} {} {} {} {}",
+        master.name,
+        master.address.host,
+        master.address.port,
+        promoted.host,
+        promoted.port
+    );
+    store.sentinel().switch_master(promoted, replicas);
+}
+
+/// PINGs the instance at `address` and returns its INFO replication.
+async fn probe(address: &Address) -> anyhow::Result<String> {
+    let mut client = Client::connect((address.host.as_str(), address.port)).await?;
+    match client.call(&["PING"]).await? {
+        Frame::Simple(pong) if pong == "PONG" => {}
+        frame => bail!("unexpected reply to PING {:?}", frame),
+    }
+    match client.call(&["INFO", "replication"]).await? {
+        Frame::Bulk(info) => Ok(String::from_utf8_lossy(&info).into_owned()),
+        frame => bail!("unexpected reply to INFO {:?}", frame),
+    }
+}
+
+/// Makes the instance at `address` a replica of `master`.
+async fn follow(address: &Address, master: &Address) -> anyhow::Result<()> {
+    let port = master.port.to_string();
+    call(address, &["REPLICAOF", &master.host, &port]).await
+}
+
+/// Runs a command expecting an OK, which REPLICAOF may follow with a remark.
+async fn call(address: &Address, args: &[&str]) -> anyhow::Result<()> {
+    let mut client = Client::connect((address.host.as_str(), address.port)).await?;
+    match client.call(args).await? {
+        Frame::Simple(ok) if ok.starts_with("OK") => Ok(()),
+        frame => bail!("unexpected reply {:?}", frame),
+    }
+}
+
+/// The replicas an INFO replication lists, as
+/// `slave0:ip=127.0.0.1,port=6380,state=online,offset=0,lag=0`.
+fn replicas(info: &str) -> Vec<Replica> {
+    info.lines()
+        .filter(|line| line.starts_with("slave"))
+        .filter_map(|line| replica(line).ok())
+        .collect()
+}
+
+fn replica(line: &str) -> anyhow::Result<Replica> {
+    let (_, fields) = line.split_once(':').context("no fields")?;
+    let field = |name: &str| {
+        fields
+            .split(',')
+            .find_map(|field| field.strip_prefix(name)?.strip_prefix('='))
+            .with_context(|| format!("no {}", name))
+    };
+    Ok(Replica {
+        address: Address {
+            host: field("ip")?.to_string(),
+            port: field("port")?.parse()?,
+        },
+        offset: field("offset")?.parse()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replicas_are_read_from_info() {
+        let info = "role:master\r\nconnected_slaves:2\r\nslave0:ip=127.0.0.1,port=6380,state=online,offset=31,lag=0\r\nslave1:ip=10.0.0.2,port=6381,state=online,offset=0,lag=1\r\nmaster_failover_state:no-failover\r\n";
+        assert_eq!(
+            replicas(info),
+            vec![
+                Replica {
+                    address: Address {
+                        host: "127.0.0.1".to_string(),
+                        port: 6380
+                    },
+                    offset: 31
+                },
+                Replica {
+                    address: Address {
+                        host: "10.0.0.2".to_string(),
+                        port: 6381
+                    },
+                    offset: 0
+                },
+            ]
+        );
+    }
+}
@@ -1,65 +1,680 @@
+use anyhow::anyhow;
+use bytes::Bytes;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpListener;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
 
 use crate::{
-    command::Command, comms::Comms, connection::Connection, info::Info, publisher,
-    replicator::Replicator, store::Store,
+    command::{multi::Transaction, subscribe::message_frame, table, Command},
+    comms::{Comms, WriteTimeout},
+    connection::Connection,
+    error::Error,
+    frame::{self, Frame},
+    replicator,
+    shutdown::Shutdown,
+    store::{
+        client_state::ReplyMode,
+        clients::{ClientGuard, Seat},
+        latency,
+        output::Pending,
+        pubsub::Subscriptions,
+        role::Role,
+        tracking::Inbox,
+        watch::WatchedKeys,
+        Store,
+    },
+    tls,
 };
 
-pub async fn run(listener: TcpListener, store: Store) -> anyhow::Result<()> {
+/// Commands CLIENT LIST shows with their subcommand.
+const CONTAINER_COMMANDS: [&str; 11] = [
+    "acl", "client", "cluster", "command", "config", "debug", "function", "latency", "memory",
+    "script", "xgroup",
+];
+
+/// How long a reply may wait on a client that isn't reading before the
+/// connection is given up on.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Where RESP2 clients tracking keys hear about changes, when redirected to
+/// a client subscribed to it.
+const INVALIDATE_CHANNEL: &str = "__redis__:invalidate";
+
+/// A listener whose clients connect over TLS.
+pub struct TlsListener {
+    pub listener: TcpListener,
+    pub acceptor: tls::Acceptor,
+}
+
+/// Serves clients until `shutdown` resolves or a client sends SHUTDOWN, then
+/// waits for every connection to finish its current command and close.
+pub async fn run(listener: TcpListener, store: Store, shutdown: impl Future) -> anyhow::Result<()> {
+    run_with_tls(listener, None, store, shutdown).await
+}
+
+/// Serves clients as `run` does, and TLS clients on `tls` as well.
+pub async fn run_with_tls(
+    listener: TcpListener,
+    tls: Option<TlsListener>,
+    store: Store,
+    shutdown: impl Future,
+) -> anyhow::Result<()> {
     let subscriber_store = store.clone();
     setup_subscriber(subscriber_store).await?;
+    let replicas = store.replicas().clone();
+    let role = store.role().clone();
+    spawn_until_shutdown(&store, async move {
+        if let Err(err) = replicas.monitor(role).await {
+            log_warning!("replica monitor error: {:#}", err);
+        }
+    });
+    let saver = store.clone();
+    spawn_until_shutdown(&store, async move { saver.run_scheduled_saves().await });
+    let expirer = store.clone();
+    spawn_until_shutdown(&store, async move { expirer.run_active_expiry().await });
 
+    // every handler holds a sender, so the channel closes once they are all done
+    let (done_tx, mut done_rx) = mpsc::channel::<()>(1);
+    let mut requested = store.shutdown_listener();
+    let accept_tls = async {
+        match &tls {
+            Some(tls) => accept(&tls.listener, Some(&tls.acceptor), &store, &done_tx).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        result = accept(&listener, None, &store, &done_tx) => result?,
+        result = accept_tls => result?,
+        _ = shutdown => {
+            log_warning!("Received a shutdown signal, shutting down");
+            // going anyway: there is nobody to report the failure to
+            if let Err(err) = store.prepare_shutdown(None).await {
+                log_warning!("Errors trying to shut down the server: {:#}", err);
+            }
+            store.begin_shutdown();
+        }
+        _ = requested.recv() => {}
+    }
+    drop(done_tx);
+    let _ = done_rx.recv().await;
+    Ok(())
+}
+
+/// A server serving clients in the background, which can be shut down as
+/// SHUTDOWN or a signal would.
+pub struct Server {
+    address: SocketAddr,
+    store: Store,
+    trigger: oneshot::Sender<()>,
+    task: JoinHandle<anyhow::Result<()>>,
+}
+
+impl Server {
+    /// Starts serving clients on `listener` with `store`'s keyspace.
+    pub fn start(listener: TcpListener, store: Store) -> anyhow::Result<Server> {
+        let address = listener.local_addr()?;
+        let (trigger, triggered) = oneshot::channel();
+        let task = tokio::spawn(run(listener, store.clone(), triggered));
+        Ok(Server {
+            address,
+            store,
+            trigger,
+            task,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    /// Stops accepting clients, saving first if the config says to, and
+    /// returns once every connection has closed. Dropping the handle shuts
+    /// the server down too, without waiting.
+    pub async fn shutdown(self) -> anyhow::Result<()> {
+        // the server may have shut itself down already
+        let _ = self.trigger.send(());
+        self.task.await?
+    }
+}
+
+/// Runs `task` in the background until the server shuts down.
+fn spawn_until_shutdown(store: &Store, task: impl Future<Output = ()> + Send + 'static) {
+    let mut shutdown = store.shutdown_listener();
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = task => {}
+            _ = shutdown.recv() => {}
+        }
+    });
+}
+
+/// Hands each new connection to a `Handler` of its own, after a TLS
+/// handshake if there is a `tls` acceptor, or turns it away if `maxclients`
+/// are already connected. Only returns if accepting fails.
+async fn accept(
+    listener: &TcpListener,
+    tls: Option<&tls::Acceptor>,
+    store: &Store,
+    done: &mpsc::Sender<()>,
+) -> anyhow::Result<()> {
     loop {
+        let (socket, peer) = listener.accept().await?;
+        let seat = store.clients().seat();
+        if seat.is_none() {
+            store.stats().connection_rejected();
+        }
+        // replies are flushed one at a time, so don't hold them back for acks
+        socket.set_nodelay(true)?;
+        let local = socket.local_addr()?;
         let store = store.clone();
-        let (socket, _) = listener.accept().await?;
-        let mut handler = Handler {};
+        let tls = tls.cloned();
+        let done = done.clone();
         tokio::spawn(async move {
-            let (reader, writer) = socket.into_split();
-            if let Err(err) = handler
-                .run(store, Connection::new(reader, writer, false))
-                .await
-            {
-                eprintln!("connection error: {:?}", err);
+            let result = match tls {
+                Some(tls) => match tokio::time::timeout(WRITE_TIMEOUT, tls.accept(socket)).await {
+                    Ok(Ok(stream)) => serve(stream, seat, peer, local, store).await,
+                    Ok(Err(err)) => Err(anyhow!("TLS handshake failed: {}", err)),
+                    Err(_) => Err(anyhow!("TLS handshake timed out")),
+                },
+                None => serve(socket, seat, peer, local, store).await,
+            };
+            if let Err(err) = result {
+                log_warning!("connection error from {}: {:#}", peer, err);
             }
+            drop(done);
         });
     }
 }
 
+/// Serves the client on `stream` if it got a seat, and otherwise tells it
+/// there is no room.
+async fn serve<S>(
+    mut stream: S,
+    seat: Option<Seat>,
+    peer: SocketAddr,
+    local: SocketAddr,
+    store: Store,
+) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static,
+{
+    let Some(seat) = seat else {
+        let refusal = stream.write_all(b"-ERR max number of clients reached\r\n");
+        let _ = tokio::time::timeout(WRITE_TIMEOUT, refusal).await;
+        return Ok(());
+    };
+    let store = store.for_client();
+    let mut handler = Handler::new(&store, peer, local);
+    log_verbose!("Accepted {} id={}", peer, handler.client.id());
+    let (reader, writer) = tokio::io::split(stream);
+    let comms = WriteTimeout::new(Connection::new(reader, writer), WRITE_TIMEOUT);
+    let result = handler.run(store, comms).await;
+    log_verbose!(
+        "Client closed connection {} id={}",
+        peer,
+        handler.client.id()
+    );
+    // the seat is free by the time the client leaves CLIENT LIST
+    drop(seat);
+    result
+}
+
 async fn setup_subscriber(store: Store) -> anyhow::Result<()> {
-    let info = Info::from_store(&store)?;
-    if info.is_replica() {
-        let mut replica = Replicator::new(store, info);
-        tokio::spawn(async move {
-            if let Err(err) = replica.run().await {
-                eprintln!("replication error: {:?}", err);
-            }
-        });
+    if let Role::Replica { host, port } = store.role().get() {
+        replicator::follow(&store, host, port)?;
     }
     Ok(())
 }
 
-struct Handler {}
+/// A command's reply. A command that fails is answered with its error, and
+/// the connection goes on to the next one; even a write the AOF couldn't log
+/// is still in the keyspace.
+fn reply(result: anyhow::Result<Frame>) -> Frame {
+    result.unwrap_or_else(|err| Frame::from_error(&err))
+}
+
+struct Handler {
+    /// Pub/sub channels this client listens on; non-empty means subscriber mode.
+    subscriptions: Subscriptions,
+    /// Commands queued since MULTI, if a transaction is open.
+    transaction: Option<Transaction>,
+    /// Keys whose modification makes the next EXEC fail.
+    watched: WatchedKeys,
+    /// The client's address, with the port it announced with REPLCONF
+    /// listening-port if it is a replica.
+    address: SocketAddr,
+    /// The client's entry in the registry CLIENT LIST reads.
+    client: ClientGuard,
+    /// Closes the connection when the server shuts down.
+    shutdown: Shutdown,
+    /// Whether the client sent ASKING just before this command, letting it
+    /// at a slot this node is importing.
+    asking: bool,
+    /// Replies queued since the last flush.
+    output: Pending,
+    /// Keys this client, or one redirecting to it, is told have changed.
+    invalidations: Inbox,
+    /// Whether the replies to the command being run are dropped, as CLIENT
+    /// REPLY asks.
+    silent: bool,
+}
 
 impl Handler {
-    async fn run<C: Comms + 'static>(&mut self, store: Store, mut comms: C) -> anyhow::Result<()> {
-        let mut subscriber = false;
-        while let Some(frame) = comms.read_frame().await? {
-            let command = Command::from_frame(frame)?;
-            match &command {
-                Command::Psync(_) => {
-                    subscriber = true;
-                }
-                _ => {}
-            }
-            command.apply(&store, &mut comms).await?;
-            if subscriber {
-                let _ = publisher::add_connection(comms, &store).await;
-
-                // TODO: for some reason, if we attempt to read another frame, the replicant errors out
-                // specifically: `0 == self.stream.read_buf(&mut self.buffer).await?`
+    fn new(store: &Store, peer: SocketAddr, local: SocketAddr) -> Self {
+        store.stats().connection_received();
+        let client = store.clients().register(peer, local);
+        store.set_client_id(client.id());
+        let invalidations = store.tracking().inbox(client.id());
+        Self {
+            subscriptions: store.pubsub().subscriptions(),
+            transaction: None,
+            watched: store.watched_keys(),
+            address: peer,
+            client,
+            shutdown: store.shutdown_listener(),
+            asking: false,
+            output: Pending::default(),
+            invalidations,
+            silent: false,
+        }
+    }
+
+    /// Queues `frame` in reply to the command being run, unless the client
+    /// turned its replies off.
+    async fn queue_reply<C: Comms>(&self, comms: &mut C, frame: &Frame) -> anyhow::Result<()> {
+        if !self.silent {
+            comms.queue_frame(frame).await?;
+        }
+        Ok(())
+    }
+
+    async fn run<C: Comms>(&mut self, store: Store, mut comms: C) -> anyhow::Result<()> {
+        loop {
+            self.report(&store);
+            let frame = match self.next_frame(&store, &mut comms).await {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break,
+                Err(err) => {
+                    // as with Redis, a malformed request is answered before hanging up
+                    if err.is::<frame::Error>() {
+                        let _ = comms.write_frame(&Frame::from_error(&err)).await;
+                    }
+                    return Err(err);
+                }
+            };
+            // CLIENT REPLY may hold back this command's replies, errors and all
+            let skipped = store.take_reply_skip();
+            self.silent = skipped || store.reply_mode() != ReplyMode::On;
+            let name = command_name(&frame);
+            let cmd = full_command_name(&name, &frame);
+            log_debug!(
+                "client id={} addr={} cmd={}",
+                self.client.id(),
+                self.address,
+                cmd
+            );
+            store.clients().update(self.client.id(), |client| {
+                client.cmd = cmd;
+                client.last_interaction = Instant::now();
+            });
+            store.stats().command_processed();
+            let command = match Command::from_frame(frame.clone()) {
+                Ok(command) => command,
+                Err(err) => {
+                    // a command that can't be queued dooms the whole transaction
+                    if let Some(transaction) = self.transaction.as_mut() {
+                        transaction.abort();
+                    }
+                    self.queue_reply(&mut comms, &Frame::from_error(&err))
+                        .await?;
+                    continue;
+                }
+            };
+            if let Err(error) = store.acl().check(store.user().as_deref(), &frame) {
+                if let Some(transaction) = self.transaction.as_mut() {
+                    transaction.abort();
+                }
+                self.queue_reply(&mut comms, &Frame::Error(error)).await?;
+                continue;
+            }
+            let asking = std::mem::take(&mut self.asking);
+            let exists = |key: &[u8]| store.expiry(&Bytes::copy_from_slice(key)).is_some();
+            if let Err(redirect) = store.cluster().check(&frame, asking, exists) {
+                if let Some(transaction) = self.transaction.as_mut() {
+                    transaction.abort();
+                }
+                self.queue_reply(&mut comms, &Frame::Error(redirect.to_string()))
+                    .await?;
+                continue;
+            }
+            // RESP3 keeps pushes apart from replies, so subscribers may run anything
+            if !self.subscriptions.is_empty()
+                && !store.resp3()
+                && !command.is_allowed_when_subscribed()
+            {
+                let response = Frame::Error(format!(
+                    "ERR Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+                    name
+                ));
+                self.queue_reply(&mut comms, &response).await?;
+                continue;
+            }
+            if command.is_write() && store.is_replica() {
+                if let Some(transaction) = self.transaction.as_mut() {
+                    transaction.abort();
+                }
+                self.queue_reply(&mut comms, &Error::ReadOnly.into())
+                    .await?;
+                continue;
+            }
+            // only commands that may grow the data make room, or are refused
+            if table::denies_oom(&name) && !store.evict() {
+                if let Some(transaction) = self.transaction.as_mut() {
+                    transaction.abort();
+                }
+                self.queue_reply(&mut comms, &Error::Oom.into()).await?;
+                continue;
+            }
+            if let Some(transaction) = self.transaction.as_mut() {
+                if !command.is_transaction_control() {
+                    let response = match command {
+                        Command::Unknown(cmd) => {
+                            transaction.abort();
+                            cmd.apply().await?
+                        }
+                        command => {
+                            transaction.queue(command);
+                            Frame::Simple("QUEUED".into())
+                        }
+                    };
+                    self.queue_reply(&mut comms, &response).await?;
+                    continue;
+                }
+            }
+            if let Command::ReplConf(cmd) = &command {
+                if let Some(port) = cmd.listening_port() {
+                    self.address.set_port(port);
+                }
+            }
+            // CLIENT stays available so a pause can be lifted
+            if !matches!(command, Command::Client(_)) {
+                let write = command.is_write()
+                    || command.is_script()
+                    || matches!(command, Command::Exec(_) | Command::Publish(_));
+                if store.clients().paused_until(write).is_some() {
+                    comms.flush().await?;
+                }
+                tokio::select! {
+                    _ = store.clients().wait_unpaused(write) => {}
+                    _ = self.client.killed() => break,
+                    _ = self.shutdown.recv() => break,
+                }
+            }
+            // recorded before the read, so a write racing it still invalidates
+            if store.tracking().is_active() {
+                store
+                    .tracking()
+                    .record_reads(self.client.id(), table::read_keys(&frame));
+            }
+            // unknown names aren't counted, so clients can't make up new ones
+            let counted = !matches!(command, Command::Unknown(_));
+            let started = Instant::now();
+            let mut replica = None;
+            let responses = match command {
+                Command::Subscribe(cmd) => cmd
+                    .apply(&mut self.subscriptions)
+                    .into_iter()
+                    .map(|frame| pushed(&store, frame))
+                    .collect(),
+                Command::Unsubscribe(cmd) => cmd
+                    .apply(&mut self.subscriptions)
+                    .into_iter()
+                    .map(|frame| pushed(&store, frame))
+                    .collect(),
+                Command::Ping(cmd) if !self.subscriptions.is_empty() && !store.resp3() => {
+                    vec![cmd.apply_subscribed().await?]
+                }
+                Command::Multi(cmd) => vec![cmd.apply(&mut self.transaction)],
+                Command::Exec(cmd) => {
+                    vec![
+                        cmd.apply(&store, &mut self.transaction, &mut self.watched)
+                            .await,
+                    ]
+                }
+                Command::Discard(cmd) => {
+                    vec![cmd.apply(&mut self.transaction, &mut self.watched)]
+                }
+                Command::Watch(cmd) => {
+                    vec![cmd.apply(store.db(), &self.transaction, &mut self.watched)]
+                }
+                Command::Unwatch(cmd) => vec![cmd.apply(&mut self.watched)],
+                Command::Reset(cmd) => vec![cmd.apply(
+                    &store,
+                    &mut self.transaction,
+                    &mut self.watched,
+                    &mut self.subscriptions,
+                )],
+                Command::Asking(cmd) => {
+                    let response = reply(cmd.apply(&store).await);
+                    self.asking = response == Frame::OK;
+                    vec![response]
+                }
+                Command::Psync(cmd) => {
+                    // no command may write between the snapshot and the replica's stream
+                    let _lock = store.transaction_lock().await;
+                    let (responses, attached) = cmd.apply(&store, self.address).await?;
+                    replica = attached;
+                    responses
+                }
+                command if command.is_blocking() => {
+                    // replies queued so far shouldn't wait on this one
+                    comms.flush().await?;
+                    // nothing has changed yet while a blocking command waits
+                    tokio::select! {
+                        result = command.apply(&store) => vec![reply(result)],
+                        _ = self.client.killed() => break,
+                        _ = self.shutdown.recv() => break,
+                    }
+                }
+                Command::Shutdown(cmd) => {
+                    // nothing is saved, so a script may be stopped half done
+                    if cmd.is_nosave() {
+                        let _ = store.scripts().kill(true);
+                    }
+                    // no write may land between the final save and exiting
+                    let _lock = store.transaction_lock().await;
+                    match reply(cmd.apply(&store).await) {
+                        // the connection closing is the only answer
+                        Frame::OK => break,
+                        response => vec![response],
+                    }
+                }
+                // the script or function being killed holds the lock
+                Command::Script(cmd) if cmd.is_kill() => vec![reply(cmd.apply(&store).await)],
+                Command::Function(cmd) if cmd.is_kill() => {
+                    vec![reply(cmd.apply(&store).await)]
+                }
+                command if command.is_script() => {
+                    match store.transaction_lock_unless_busy().await {
+                        Ok(_lock) => vec![reply(command.apply(&store).await)],
+                        Err(busy) => vec![busy.into()],
+                    }
+                }
+                command => match store.command_lock_unless_busy().await {
+                    Ok(_lock) => vec![reply(command.apply(&store).await)],
+                    Err(busy) => vec![busy.into()],
+                },
+            };
+            if counted {
+                let took = started.elapsed();
+                store.stats().command_run(&name, took);
+                let event = if table::is_fast(&name) {
+                    latency::FAST_COMMAND
+                } else {
+                    latency::COMMAND
+                };
+                store.latency().record(event, took);
+            }
+            // the command may have been CLIENT REPLY itself
+            self.silent = skipped || store.reply_mode() != ReplyMode::On;
+            if !self.silent && self.over_output_limit(&store, &responses) {
+                log_warning!(
+                    "closing client {}: output buffer over the limit",
+                    self.address
+                );
                 break;
             }
+            for response in &responses {
+                self.queue_reply(&mut comms, response).await?;
+            }
+            if let Some(replica) = replica {
+                store
+                    .clients()
+                    .update(self.client.id(), |client| client.replica = true);
+                comms.flush().await?;
+                // the connection now belongs to replication until the replica goes away
+                // or the server shuts down; the replica set logs why it ended
+                tokio::select! {
+                    _ = store.replicas().serve(comms, replica) => {}
+                    _ = self.shutdown.recv() => {}
+                }
+                return Ok(());
+            }
         }
+        // the client may be gone already, with nobody left to tell
+        let _ = comms.flush().await;
         Ok(())
     }
+
+    /// Records the connection's state in its CLIENT LIST entry.
+    fn report(&self, store: &Store) {
+        store.clients().update(self.client.id(), |client| {
+            client.db = store.db();
+            client.user = store.user().unwrap_or_default();
+            client.sub = self.subscriptions.len();
+            client.multi = self.transaction.as_ref().map(Transaction::queued);
+            client.watch = self.watched.len();
+        });
+    }
+
+    /// Counts `responses` toward the replies waiting to be flushed, returning
+    /// whether that takes them over the normal clients' output limit.
+    fn over_output_limit(&self, store: &Store, responses: &[Frame]) -> bool {
+        let limit = store.config().output_limit();
+        if limit.is_unlimited() {
+            return false;
+        }
+        let len = responses.iter().map(|r| r.encoded_len() as u64).sum();
+        self.output.add(len, &limit)
+    }
+
+    /// What tells the client `key` changed: a push under RESP3, or under RESP2
+    /// a message on `__redis__:invalidate` if it subscribed to that.
+    fn invalidation(&self, store: &Store, key: Bytes) -> Option<Frame> {
+        let keys = Frame::Array(vec![Frame::Bulk(key)]);
+        if store.resp3() {
+            return Some(Frame::Push(vec![
+                Frame::Bulk(Bytes::from("invalidate")),
+                keys,
+            ]));
+        }
+        let channel = Bytes::from(INVALIDATE_CHANNEL);
+        self.subscriptions.channels().contains(&channel).then(|| {
+            Frame::Array(vec![
+                Frame::Bulk(Bytes::from("message")),
+                Frame::Bulk(channel),
+                keys,
+            ])
+        })
+    }
+
+    /// Reads the next frame, delivering published messages while waiting if
+    /// the client is subscribed to any channels. Returns None once the client
+    /// disconnects, is killed or idles past the configured timeout, or the
+    /// server shuts down. Subscribers are never idle, as with Redis.
+    async fn next_frame<C: Comms>(
+        &mut self,
+        store: &Store,
+        comms: &mut C,
+    ) -> anyhow::Result<Option<Frame>> {
+        comms.set_limits(store.config().limits());
+        // pipelined requests are all answered before their replies are sent
+        if !comms.has_buffered_frame() {
+            comms.flush().await?;
+            self.output.clear();
+        }
+        let subscribed = !self.subscriptions.is_empty();
+        let timeout = store.config().timeout();
+        let idle = tokio::time::sleep(Duration::from_secs(timeout));
+        tokio::pin!(idle);
+        loop {
+            tokio::select! {
+                frame = comms.read_frame() => return frame,
+                _ = &mut idle, if timeout > 0 && !subscribed => return Ok(None),
+                _ = self.subscriptions.overflowed(), if subscribed => {
+                    log_warning!(
+                        "closing client {}: output buffer over the limit",
+                        self.address
+                    );
+                    return Ok(None);
+                }
+                Some((channel, message)) = self.subscriptions.recv(), if subscribed => {
+                    let message = pushed(store, message_frame(channel, message));
+                    comms.write_frame(&message).await?;
+                }
+                Some(key) = self.invalidations.recv() => {
+                    if let Some(frame) = self.invalidation(store, key) {
+                        comms.write_frame(&frame).await?;
+                    }
+                }
+                _ = self.client.killed() => return Ok(None),
+                _ = self.shutdown.recv() => return Ok(None),
+            }
+        }
+    }
+}
+
+/// A pub/sub frame as the client's protocol sends it: RESP3 pushes what
+/// RESP2 sends as an array.
+fn pushed(store: &Store, frame: Frame) -> Frame {
+    match frame {
+        Frame::Array(parts) if store.resp3() => Frame::Push(parts),
+        frame => frame,
+    }
+}
+
+/// `name` followed by the request's subcommand, if it has one, as in
+/// `config|get`.
+fn full_command_name(name: &str, frame: &Frame) -> String {
+    let subcommand = match frame {
+        Frame::Array(parts) if CONTAINER_COMMANDS.contains(&name) => match parts.get(1) {
+            Some(Frame::Bulk(sub)) => Some(String::from_utf8_lossy(sub).to_lowercase()),
+            _ => None,
+        },
+        _ => None,
+    };
+    match subcommand {
+        Some(subcommand) => format!("{}|{}", name, subcommand),
+        None => name.to_string(),
+    }
+}
+
+/// The lowercased command name of a request, for error messages.
+fn command_name(frame: &Frame) -> String {
+    match frame {
+        Frame::Array(parts) => match parts.first() {
+            Some(Frame::Bulk(name)) => String::from_utf8_lossy(name).to_lowercase(),
+            Some(Frame::Simple(name)) => name.to_lowercase(),
+            _ => String::new(),
+        },
+        _ => String::new(),
+    }
 }
@@ -0,0 +1,32 @@
+use tokio::sync::broadcast;
+
+/// Listens for the server shutting down, see `Store::begin_shutdown`.
+#[derive(Debug)]
+pub struct Shutdown {
+    /// The signal has been received, so `recv` returns straight away.
+    is_shutdown: bool,
+    notify: broadcast::Receiver<()>,
+}
+
+impl Shutdown {
+    pub(crate) fn new(is_shutdown: bool, notify: broadcast::Receiver<()>) -> Shutdown {
+        Shutdown {
+            is_shutdown,
+            notify,
+        }
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.is_shutdown
+    }
+
+    /// Resolves once the server is shutting down.
+    pub async fn recv(&mut self) {
+        if self.is_shutdown {
+            return;
+        }
+        // a closed channel means the store is gone, which is as good as a signal
+        let _ = self.notify.recv().await;
+        self.is_shutdown = true;
+    }
+}
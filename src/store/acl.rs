@@ -0,0 +1,536 @@
+//! Access control lists: named users, the passwords they authenticate with,
+//! the commands they may run and the keys those commands may touch.
+//!
+//! A user's command rules are kept in the order they were given, as `+name`,
+//! `-name`, `+name|subcommand` or `+@category`, and replayed against each
+//! request so that later rules override earlier ones.
+
+use anyhow::Context;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    command::table::{find, lookup, request_args, CATEGORIES},
+    error::Error,
+    frame::Frame,
+    glob,
+};
+
+/// The user every connection starts out as.
+pub const DEFAULT_USER: &str = "default";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct User {
+    enabled: bool,
+    /// Any password is accepted.
+    nopass: bool,
+    /// Hex SHA-256 digests of the passwords the user authenticates with.
+    passwords: BTreeSet<String>,
+    /// Glob patterns of the keys the user's commands may touch.
+    keys: Vec<String>,
+    /// Lowercased `+` and `-` command rules, in the order they apply.
+    commands: Vec<String>,
+}
+
+impl User {
+    /// A user that may do anything without a password, as the default user
+    /// starts out.
+    fn open() -> Self {
+        Self {
+            enabled: true,
+            nopass: true,
+            keys: vec!["*".to_string()],
+            commands: vec!["+@all".to_string()],
+            ..Default::default()
+        }
+    }
+
+    /// Applies one ACL SETUSER rule, returning why it is invalid if it is.
+    fn apply_rule(&mut self, rule: &str) -> Result<(), &'static str> {
+        let lower = rule.to_lowercase();
+        match lower.as_str() {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.passwords.clear();
+                self.nopass = true;
+            }
+            "resetpass" => {
+                self.passwords.clear();
+                self.nopass = false;
+            }
+            "allkeys" => self.keys = vec!["*".to_string()],
+            "resetkeys" => self.keys.clear(),
+            "allcommands" | "+@all" => self.commands = vec!["+@all".to_string()],
+            "nocommands" | "-@all" => self.commands.clear(),
+            "reset" => *self = User::default(),
+            _ => {
+                match rule.split_at(rule.chars().next().map_or(0, char::len_utf8)) {
+                    (">", password) => {
+                        self.passwords.insert(sha256_hex(password.as_bytes()));
+                        self.nopass = false;
+                    }
+                    ("<", password) => {
+                        if !self.passwords.remove(&sha256_hex(password.as_bytes())) {
+                            return Err("The password you are trying to remove from the user does not exist");
+                        }
+                    }
+                    ("#", hash) => {
+                        self.passwords.insert(checked_hash(hash)?.to_string());
+                        self.nopass = false;
+                    }
+                    ("!", hash) => {
+                        if !self.passwords.remove(checked_hash(hash)?) {
+                            return Err("The password you are trying to remove from the user does not exist");
+                        }
+                    }
+                    ("~", pattern) => self.keys.push(pattern.to_string()),
+                    ("+" | "-", _) => {
+                        let target = &lower[1..];
+                        let known = match target.strip_prefix('@') {
+                            Some(category) => CATEGORIES.contains(&category),
+                            None => find(target.split('|').next().unwrap_or(target)).is_some(),
+                        };
+                        if !known {
+                            return Err("Unknown command or category name in ACL");
+                        }
+                        self.commands.push(lower);
+                    }
+                    _ => return Err("Syntax error"),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the rules let the user run `name`, given its subcommand and
+    /// categories.
+    fn allows(&self, name: &str, subcommand: Option<&str>, categories: &[&str]) -> bool {
+        let mut allowed = false;
+        for rule in &self.commands {
+            let (sign, target) = rule.split_at(1);
+            let applies = match target.strip_prefix('@') {
+                Some("all") => true,
+                Some(category) => categories.contains(&category),
+                None => match target.split_once('|') {
+                    Some((command, sub)) => command == name && subcommand == Some(sub),
+                    None => target == name,
+                },
+            };
+            if applies {
+                allowed = sign == "+";
+            }
+        }
+        allowed
+    }
+
+    fn may_access(&self, key: &[u8]) -> bool {
+        self.keys
+            .iter()
+            .any(|pattern| glob::matches(pattern.as_bytes(), key, false))
+    }
+
+    pub fn flags(&self) -> Vec<&'static str> {
+        let mut flags = vec![if self.enabled { "on" } else { "off" }];
+        if self.nopass {
+            flags.push("nopass");
+        }
+        flags
+    }
+
+    pub fn passwords(&self) -> impl Iterator<Item = &String> {
+        self.passwords.iter()
+    }
+
+    /// The command rules as one string, starting from `+@all` or `-@all`.
+    pub fn describe_commands(&self) -> String {
+        let mut rules = self.commands.clone();
+        if !matches!(rules.first().map(String::as_str), Some("+@all")) {
+            rules.insert(0, "-@all".to_string());
+        }
+        rules.join(" ")
+    }
+
+    pub fn describe_keys(&self) -> String {
+        self.keys
+            .iter()
+            .map(|pattern| format!("~{}", pattern))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// The rules that recreate the user from scratch, as ACL LIST shows them.
+    fn describe(&self) -> String {
+        let mut rules: Vec<String> = self.flags().iter().map(|flag| flag.to_string()).collect();
+        rules.extend(self.passwords.iter().map(|hash| format!("#{}", hash)));
+        if !self.keys.is_empty() {
+            rules.push(self.describe_keys());
+        }
+        rules.push(self.describe_commands());
+        rules.join(" ")
+    }
+}
+
+/// `hash` if it is a hex SHA-256 digest.
+fn checked_hash(hash: &str) -> Result<&str, &'static str> {
+    if hash.len() == 64 && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+        Ok(hash)
+    } else {
+        Err("The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters")
+    }
+}
+
+/// The request's arguments, command name first.
+/// The server's users, keyed by name. There is always a default user.
+#[derive(Debug, Clone)]
+pub struct Acl {
+    users: Arc<Mutex<BTreeMap<String, User>>>,
+}
+
+impl Default for Acl {
+    fn default() -> Self {
+        let users = BTreeMap::from([(DEFAULT_USER.to_string(), User::open())]);
+        Self {
+            users: Arc::new(Mutex::new(users)),
+        }
+    }
+}
+
+impl Acl {
+    /// Creates `name` if needed and applies `rules` to it in order; if any
+    /// rule is invalid the user is left as it was.
+    pub fn set_user(&self, name: &str, rules: &[String]) -> Result<(), String> {
+        let mut users = self.users.lock().unwrap();
+        let mut user = users.get(name).cloned().unwrap_or_default();
+        for rule in rules {
+            user.apply_rule(rule).map_err(|reason| {
+                format!("ERR Error in ACL SETUSER modifier '{}': {}", rule, reason)
+            })?;
+        }
+        users.insert(name.to_string(), user);
+        Ok(())
+    }
+
+    pub fn get_user(&self, name: &str) -> Option<User> {
+        self.users.lock().unwrap().get(name).cloned()
+    }
+
+    /// Removes `name`, returning whether it existed.
+    pub fn del_user(&self, name: &str) -> bool {
+        self.users.lock().unwrap().remove(name).is_some()
+    }
+
+    pub fn usernames(&self) -> Vec<String> {
+        self.users.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Each user as a `user <name> <rules>` line.
+    pub fn list(&self) -> Vec<String> {
+        self.users
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, user)| format!("user {} {}", name, user.describe()))
+            .collect()
+    }
+
+    /// Whether `password` lets a client log in as `name`.
+    pub fn authenticate(&self, name: &str, password: &str) -> bool {
+        let users = self.users.lock().unwrap();
+        users.get(name).is_some_and(|user| {
+            user.enabled
+                && (user.nopass || user.passwords.contains(&sha256_hex(password.as_bytes())))
+        })
+    }
+
+    /// Whether new connections are logged in as the default user without
+    /// having to AUTH.
+    pub fn is_open(&self) -> bool {
+        let users = self.users.lock().unwrap();
+        users
+            .get(DEFAULT_USER)
+            .is_some_and(|user| user.enabled && user.nopass)
+    }
+
+    /// Checks that `user`, or a connection that hasn't authenticated yet if
+    /// None, may run `request`. The error is the reply to send instead.
+    /// Commands this module doesn't know are let through, to fail on their own.
+    pub fn check(&self, user: Option<&str>, request: &Frame) -> Result<(), String> {
+        let args = request_args(request);
+        let Some(name) = args.first() else {
+            return Ok(());
+        };
+        let name = String::from_utf8_lossy(name).to_lowercase();
+        // both can log in, so neither needs a login first, and RESET logs out
+        if name == "auth" || name == "hello" || name == "reset" {
+            return Ok(());
+        }
+        let Some(user) = user else {
+            return Err(Error::NoAuth.to_string());
+        };
+        let subcommand = args
+            .get(1)
+            .map(|sub| String::from_utf8_lossy(sub).to_lowercase());
+        let Some(spec) = lookup(&args) else {
+            return Ok(());
+        };
+
+        let users = self.users.lock().unwrap();
+        let allowed = users
+            .get(user)
+            .filter(|u| u.enabled)
+            .filter(|u| u.allows(&name, subcommand.as_deref(), spec.categories));
+        let Some(allowed) = allowed else {
+            let command = if spec.name.contains('|') {
+                spec.name
+            } else {
+                &name
+            };
+            return Err(format!(
+                "NOPERM User {} has no permissions to run the '{}' command",
+                user, command
+            ));
+        };
+        if !spec
+            .keys
+            .of(&args)
+            .iter()
+            .all(|key| allowed.may_access(key))
+        {
+            return Err("NOPERM No permissions to access a key".to_string());
+        }
+        Ok(())
+    }
+
+    /// Writes every user to `path`, one `user` line each, replacing the file.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let mut contents = self.list().join("\n");
+        contents.push('\n');
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, contents)
+            .and_then(|_| fs::rename(&temp, path))
+            .with_context(|| format!("writing {}", path.display()))
+    }
+
+    /// Replaces every user with those in the file at `path`. Nothing changes
+    /// if any line is invalid. A file without a default user gets an open one.
+    pub fn load(&self, path: &Path) -> Result<(), String> {
+        let contents = fs::read_to_string(path).map_err(|err| {
+            format!(
+                "ERR Error loading ACLs, opening file '{}': {}",
+                path.display(),
+                err
+            )
+        })?;
+        let mut users = BTreeMap::new();
+        for (i, line) in contents.lines().enumerate() {
+            let invalid = |reason: &str| format!("ERR {}:{}: {}", path.display(), i + 1, reason);
+            let mut words = line.split_whitespace();
+            match words.next() {
+                None => continue,
+                Some("user") => {}
+                Some(_) => return Err(invalid("should start with user keyword")),
+            }
+            let Some(name) = words.next() else {
+                return Err(invalid("the user name is missing"));
+            };
+            let mut user = User::default();
+            for rule in words {
+                user.apply_rule(rule)
+                    .map_err(|reason| invalid(&format!("'{}': {}", rule, reason)))?;
+            }
+            if users.insert(name.to_string(), user).is_some() {
+                return Err(invalid(&format!("duplicate user '{}' found", name)));
+            }
+        }
+        users
+            .entry(DEFAULT_USER.to_string())
+            .or_insert_with(User::open);
+        *self.users.lock().unwrap() = users;
+        Ok(())
+    }
+}
+
+/// The SHA-256 digest of `data` in hex, as ACL shows passwords.
+fn sha256_hex(data: &[u8]) -> String {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+    let mut h: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = h;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = hh
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            hh = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e, f, g, hh]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+    h.iter().map(|word| format!("{:08x}", word)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn request(args: &[&'static str]) -> Frame {
+        Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::from(*arg)))
+                .collect(),
+        )
+    }
+
+    fn rules(rules: &[&str]) -> Vec<String> {
+        rules.iter().map(|rule| rule.to_string()).collect()
+    }
+
+    #[test]
+    fn sha256_digests() {
+        assert_eq!(
+            sha256_hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn later_rules_override_earlier_ones() {
+        let acl = Acl::default();
+        acl.set_user(
+            "alice",
+            &rules(&[
+                "on",
+                ">secret",
+                "~cache:*",
+                "+@read",
+                "-zrange",
+                "+config|get",
+            ]),
+        )
+        .unwrap();
+        let alice = Some("alice");
+
+        assert_eq!(acl.check(alice, &request(&["GET", "cache:1"])), Ok(()));
+        assert_eq!(
+            acl.check(alice, &request(&["GET", "other"])),
+            Err("NOPERM No permissions to access a key".to_string())
+        );
+        assert_eq!(
+            acl.check(alice, &request(&["ZRANGE", "cache:z", "0", "-1"])),
+            Err("NOPERM User alice has no permissions to run the 'zrange' command".to_string())
+        );
+        assert_eq!(
+            acl.check(alice, &request(&["CONFIG", "GET", "dir"])),
+            Ok(())
+        );
+        assert!(acl
+            .check(alice, &request(&["CONFIG", "SET", "dir", "/"]))
+            .is_err());
+        assert!(acl
+            .check(alice, &request(&["SET", "cache:1", "v"]))
+            .is_err());
+        assert_eq!(
+            acl.check(None, &request(&["GET", "cache:1"])),
+            Err(Error::NoAuth.to_string())
+        );
+
+        assert!(acl.authenticate("alice", "secret"));
+        assert!(!acl.authenticate("alice", "wrong"));
+        assert_eq!(
+            acl.list()[0],
+            format!(
+                "user alice on #{} ~cache:* -@all +@read -zrange +config|get",
+                sha256_hex(b"secret")
+            )
+        );
+    }
+
+    #[test]
+    fn users_round_trip_through_the_aclfile() {
+        let path = std::env::temp_dir().join(format!("redis-rust-acl-{}.acl", std::process::id()));
+        let acl = Acl::default();
+        acl.set_user("carol", &rules(&[">pw", "~k*", "+get", "on"]))
+            .unwrap();
+        acl.save(&path).unwrap();
+
+        let loaded = Acl::default();
+        loaded.load(&path).unwrap();
+        assert_eq!(loaded.list(), acl.list());
+        assert!(loaded.authenticate("carol", "pw"));
+
+        fs::write(&path, "user carol on\nuser carol off\n").unwrap();
+        assert!(loaded
+            .load(&path)
+            .unwrap_err()
+            .ends_with("duplicate user 'carol' found"));
+        assert_eq!(loaded.list(), acl.list());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn invalid_rules_leave_the_user_unchanged() {
+        let acl = Acl::default();
+        assert_eq!(
+            acl.set_user("bob", &rules(&["on", "+nosuchcommand"])),
+            Err("ERR Error in ACL SETUSER modifier '+nosuchcommand': Unknown command or category name in ACL".to_string())
+        );
+        assert_eq!(acl.get_user("bob"), None);
+        assert!(acl.set_user("default", &rules(&["bogus"])).is_err());
+        assert!(acl.is_open());
+    }
+}
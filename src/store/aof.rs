@@ -0,0 +1,440 @@
+//! The append-only file: every write's effect, in the form replicas are sent
+//! it, appended as it happens and replayed at startup.
+
+use anyhow::{bail, Context};
+use bytes::{Bytes, BytesMut};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::{stream::StreamId, Store, Value};
+use crate::{
+    command::Command,
+    frame::{self, Frame},
+    publisher::{select_frame, Action},
+};
+
+/// Members per ZADD when rewriting a sorted set.
+const REWRITE_ITEMS_PER_COMMAND: usize = 64;
+
+/// A write that was applied in memory but couldn't be logged to the AOF.
+#[derive(Debug)]
+pub struct WriteFailed(pub io::Error);
+
+impl fmt::Display for WriteFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MISCONF Errors writing to the AOF file: {}", self.0)
+    }
+}
+
+impl std::error::Error for WriteFailed {}
+
+#[derive(Debug)]
+struct State {
+    /// The file writes are appended to, while AOF is on.
+    file: Option<File>,
+    /// The database the file, and the rewrite buffer, last selected, if known.
+    file_db: Option<usize>,
+    buffer_db: Option<usize>,
+    /// The file's size after the last rewrite, and now.
+    base_size: u64,
+    size: u64,
+    rewrite_started: Option<Instant>,
+    /// Writes made since the running rewrite took its snapshot, which go at
+    /// the end of the rewritten file.
+    rewrite_buffer: Option<BytesMut>,
+    last_rewrite_ok: bool,
+    last_rewrite_duration: Option<Duration>,
+    last_write_ok: bool,
+    /// Successful rewrites since startup.
+    rewrites: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Aof {
+    state: Arc<Mutex<State>>,
+}
+
+impl Default for Aof {
+    fn default() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                file: None,
+                file_db: None,
+                buffer_db: None,
+                base_size: 0,
+                size: 0,
+                rewrite_started: None,
+                rewrite_buffer: None,
+                last_rewrite_ok: true,
+                last_rewrite_duration: None,
+                last_write_ok: true,
+                rewrites: 0,
+            })),
+        }
+    }
+}
+
+impl Aof {
+    pub fn is_open(&self) -> bool {
+        self.state.lock().unwrap().file.is_some()
+    }
+
+    /// Starts appending writes to `path`, creating it if needed.
+    pub fn open(&self, path: &Path) -> io::Result<()> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        let mut state = self.state.lock().unwrap();
+        state.file = Some(file);
+        state.file_db = None;
+        state.base_size = size;
+        state.size = size;
+        Ok(())
+    }
+
+    /// Makes sure everything appended so far is on disk.
+    pub fn fsync(&self) -> io::Result<()> {
+        match &self.state.lock().unwrap().file {
+            Some(file) => file.sync_all(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn close(&self) {
+        self.state.lock().unwrap().file = None;
+    }
+
+    /// Appends a write made in database `db`, if AOF is on or a rewrite will
+    /// need it, selecting the database first when the last write was in
+    /// another. A write that fails part way is cut off again, so the file
+    /// never ends mid-command.
+    pub fn append(&self, db: usize, frame: &Frame) -> io::Result<()> {
+        let state = &mut *self.state.lock().unwrap();
+        if state.file.is_none() && state.rewrite_buffer.is_none() {
+            return Ok(());
+        }
+        let with_select = |last: Option<usize>| {
+            let mut bytes = BytesMut::new();
+            if last != Some(db) {
+                select_frame(db).encode(&mut bytes);
+            }
+            frame.encode(&mut bytes);
+            bytes
+        };
+        if let Some(buffer) = &mut state.rewrite_buffer {
+            buffer.extend_from_slice(&with_select(state.buffer_db));
+            state.buffer_db = Some(db);
+        }
+        let Some(file) = &mut state.file else {
+            return Ok(());
+        };
+        let bytes = with_select(state.file_db);
+        let written = file.write_all(&bytes);
+        if written.is_err() {
+            let _ = file.set_len(state.size);
+        }
+        state.last_write_ok = written.is_ok();
+        if written.is_ok() {
+            state.size += bytes.len() as u64;
+            state.file_db = Some(db);
+        }
+        written
+    }
+
+    pub fn size(&self) -> u64 {
+        self.state.lock().unwrap().size
+    }
+
+    pub fn base_size(&self) -> u64 {
+        self.state.lock().unwrap().base_size
+    }
+
+    /// How long the running rewrite has taken so far, if there is one.
+    pub fn rewrite_running_for(&self) -> Option<Duration> {
+        let state = self.state.lock().unwrap();
+        state.rewrite_started.map(|started| started.elapsed())
+    }
+
+    pub fn last_rewrite_ok(&self) -> bool {
+        self.state.lock().unwrap().last_rewrite_ok
+    }
+
+    pub fn last_rewrite_duration(&self) -> Option<Duration> {
+        self.state.lock().unwrap().last_rewrite_duration
+    }
+
+    pub fn last_write_ok(&self) -> bool {
+        self.state.lock().unwrap().last_write_ok
+    }
+
+    pub fn rewrites(&self) -> u64 {
+        self.state.lock().unwrap().rewrites
+    }
+
+    /// Whether the open file has grown past both thresholds for an automatic
+    /// rewrite.
+    pub fn rewrite_due(&self, percentage: u64, min_size: u64) -> bool {
+        let state = self.state.lock().unwrap();
+        if state.file.is_none() || state.rewrite_started.is_some() || percentage == 0 {
+            return false;
+        }
+        let base = state.base_size.max(1);
+        state.size >= min_size && state.size.saturating_sub(base) * 100 / base >= percentage
+    }
+
+    /// Marks a rewrite as running, returning false if one already is.
+    fn start_rewrite(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.rewrite_started.is_some() {
+            return false;
+        }
+        state.rewrite_started = Some(Instant::now());
+        true
+    }
+
+    fn buffer_writes(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.rewrite_buffer = Some(BytesMut::new());
+        state.buffer_db = None;
+    }
+
+    /// Completes a rewrite whose snapshot is in `temp`: adds the writes made
+    /// since, moves it over `path` and, if AOF is on, appends to it from now on.
+    fn finish_rewrite(&self, temp: &Path, path: &Path, appendonly: bool) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let buffer = state.rewrite_buffer.take().unwrap_or_default();
+        let swapped = OpenOptions::new()
+            .append(true)
+            .open(temp)
+            .and_then(|mut file| {
+                file.write_all(&buffer)?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(temp, path))
+            .with_context(|| format!("writing {}", path.display()));
+        let reopened = swapped.and_then(|_| {
+            if appendonly {
+                let file = OpenOptions::new().append(true).open(path)?;
+                let size = file.metadata()?.len();
+                state.file = Some(file);
+                state.file_db = None;
+                state.base_size = size;
+                state.size = size;
+            }
+            Ok(())
+        });
+        finish(&mut state, reopened.is_ok());
+        reopened
+    }
+
+    fn abort_rewrite(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.rewrite_buffer = None;
+        finish(&mut state, false);
+    }
+}
+
+fn finish(state: &mut State, ok: bool) {
+    state.last_rewrite_duration = state
+        .rewrite_started
+        .take()
+        .map(|started| started.elapsed());
+    state.last_rewrite_ok = ok;
+    state.rewrites += ok as u64;
+}
+
+/// Replays the AOF at `path`, if there is one. A command cut short by a crash
+/// is dropped from the end of the file.
+pub async fn load(store: &Store, path: &Path) -> anyhow::Result<()> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err).with_context(|| format!("reading {}", path.display())),
+    };
+    store.persistence().set_loading(true);
+    let replayed = replay(store, &data).await;
+    store.persistence().set_loading(false);
+    let valid = replayed.with_context(|| format!("loading {}", path.display()))?;
+
+    if valid < data.len() {
+        log_warning!(
+            "{} ends in an incomplete command, dropping its last {} bytes",
+            path.display(),
+            data.len() - valid
+        );
+        OpenOptions::new()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_len(valid as u64))
+            .with_context(|| format!("truncating {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Runs every complete command in `data`, returning how many bytes they took.
+async fn replay(store: &Store, data: &[u8]) -> anyhow::Result<usize> {
+    // the SELECTs in the file move only the replay between databases
+    let store = &store.for_client();
+    let mut cursor = Cursor::new(data);
+    loop {
+        let start = cursor.position();
+        match Frame::check(&mut cursor) {
+            Ok(()) => {
+                cursor.set_position(start);
+                let frame = Frame::parse(&mut cursor)?;
+                Command::from_frame(frame)?.apply_quietly(store).await;
+            }
+            Err(frame::Error::Incomplete) => return Ok(start as usize),
+            Err(frame::Error::Other(err)) => bail!("invalid command at byte {}: {}", start, err),
+        }
+    }
+}
+
+/// Starts rewriting the AOF from the current keyspace on a background task,
+/// as BGREWRITEAOF does. Returns false if a rewrite is already running.
+pub fn bgrewriteaof(store: &Store) -> bool {
+    if !store.aof().start_rewrite() {
+        return false;
+    }
+    let store = store.clone();
+    tokio::spawn(async move {
+        if let Err(err) = rewrite(&store).await {
+            log_warning!("background AOF rewrite failed: {:#}", err);
+        }
+    });
+    true
+}
+
+async fn rewrite(store: &Store) -> anyhow::Result<()> {
+    let (snapshot, libraries) = {
+        // with no command half done, the snapshot and the buffered writes
+        // after it don't overlap
+        let _lock = store.transaction_lock().await;
+        store.aof().buffer_writes();
+        (store.snapshot(), store.functions().list())
+    };
+    let path = store.config().aof_path();
+    let temp = temp_path(&path);
+    let store = store.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut commands = BytesMut::new();
+        for library in libraries {
+            let action = Action::Function {
+                subcommand: "load",
+                args: vec![library.code],
+            };
+            action.replication_frame()?.encode(&mut commands);
+        }
+        let mut selected = None;
+        for (db, key, value, expires_at) in snapshot.iter() {
+            if selected != Some(db) {
+                select_frame(db).encode(&mut commands);
+                selected = Some(db);
+            }
+            for action in rewrite_actions(key.clone(), value, expires_at) {
+                action.replication_frame()?.encode(&mut commands);
+            }
+        }
+        let written = File::create(&temp).and_then(|mut file| file.write_all(&commands));
+        if let Err(err) = written {
+            let _ = fs::remove_file(&temp);
+            store.aof().abort_rewrite();
+            return Err(err).with_context(|| format!("writing {}", temp.display()));
+        }
+        store
+            .aof()
+            .finish_rewrite(&temp, &path, store.config().appendonly())
+    })
+    .await?
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    path.with_file_name(format!("temp-rewriteaof-{}.aof", std::process::id()))
+}
+
+/// The fewest writes that recreate a key.
+fn rewrite_actions(key: Bytes, value: &Value, expires_at: Option<u64>) -> Vec<Action> {
+    match value {
+        Value::String(value) => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64;
+            vec![Action::Set {
+                key,
+                value: value.clone(),
+                // SET only takes an expiry relative to when it runs
+                expiry: expires_at.map(|at| at.saturating_sub(now).max(1)),
+            }]
+        }
+        Value::SortedSet(zset) => {
+            let members: Vec<_> = zset
+                .iter()
+                .map(|(member, score)| (score, member.clone()))
+                .collect();
+            members
+                .chunks(REWRITE_ITEMS_PER_COMMAND)
+                .map(|members| Action::ZAdd {
+                    key: key.clone(),
+                    members: members.to_vec(),
+                })
+                .collect()
+        }
+        Value::Stream(stream) => {
+            let mut actions: Vec<_> = stream
+                .range(StreamId::MIN, StreamId::MAX)
+                .map(|(id, fields)| Action::XAdd {
+                    key: key.clone(),
+                    id: *id,
+                    fields: fields.clone(),
+                })
+                .collect();
+            // a last ID past the last entry is set by adding an entry there and
+            // deleting it again, as deletions never move it back
+            let last_entry = stream.range(StreamId::MIN, StreamId::MAX).next_back();
+            let last_id = stream.last_id();
+            if last_id != StreamId::MIN && last_entry.map(|(id, _)| *id) != Some(last_id) {
+                actions.push(Action::XAdd {
+                    key: key.clone(),
+                    id: last_id,
+                    fields: vec![("x".into(), "y".into())],
+                });
+                actions.push(Action::XDel {
+                    key: key.clone(),
+                    ids: vec![last_id],
+                });
+            }
+            for (name, group) in stream.groups() {
+                actions.push(Action::XGroup {
+                    subcommand: "create",
+                    key: key.clone(),
+                    group: name.clone(),
+                    args: vec![group.last_delivered.to_string().into(), "MKSTREAM".into()],
+                });
+                for consumer in group.consumers().keys() {
+                    actions.push(Action::XGroup {
+                        subcommand: "createconsumer",
+                        key: key.clone(),
+                        group: name.clone(),
+                        args: vec![consumer.clone()],
+                    });
+                }
+                for (id, pending) in group.pending() {
+                    actions.push(Action::XClaim {
+                        key: key.clone(),
+                        group: name.clone(),
+                        consumer: pending.consumer.clone(),
+                        id: *id,
+                        delivery_time: pending.delivery_time,
+                        delivery_count: pending.delivery_count,
+                        last_id: None,
+                    });
+                }
+            }
+            actions
+        }
+    }
+}
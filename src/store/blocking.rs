@@ -0,0 +1,95 @@
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+use super::DbKey;
+
+/// Registry of clients blocked on keys (BZPOPMIN and friends), by database
+/// and key.
+///
+/// Each blocked client owns a single `Notify` registered under every key it is
+/// waiting on. Writers call `wake` after changing a key; `notify_one` stores a
+/// permit, so a wakeup that lands between a failed pop and the wait is not lost.
+#[derive(Debug, Clone, Default)]
+pub struct Waiters {
+    keys: Arc<Mutex<HashMap<DbKey, Vec<Arc<Notify>>>>>,
+}
+
+/// A registration in `Waiters`, removed again when dropped.
+#[derive(Debug)]
+pub struct WaitGuard {
+    waiters: Waiters,
+    keys: Vec<DbKey>,
+    notify: Arc<Notify>,
+}
+
+impl Waiters {
+    pub fn register(&self, db: usize, keys: &[Bytes]) -> WaitGuard {
+        let notify = Arc::new(Notify::new());
+        let keys: Vec<_> = keys.iter().map(|key| (db, key.clone())).collect();
+        let mut map = self.keys.lock().unwrap();
+        for key in &keys {
+            map.entry(key.clone()).or_default().push(notify.clone());
+        }
+        WaitGuard {
+            waiters: self.clone(),
+            keys,
+            notify,
+        }
+    }
+
+    pub fn wake(&self, db: usize, key: &Bytes) {
+        let map = self.keys.lock().unwrap();
+        if let Some(notifies) = map.get(&(db, key.clone())) {
+            for notify in notifies {
+                notify.notify_one();
+            }
+        }
+    }
+}
+
+impl WaitGuard {
+    /// Resolves once one of the registered keys has been written to.
+    pub async fn wait(&self) {
+        self.notify.notified().await
+    }
+}
+
+impl Drop for WaitGuard {
+    fn drop(&mut self) {
+        let mut map = self.waiters.keys.lock().unwrap();
+        for key in &self.keys {
+            if let Some(notifies) = map.get_mut(key) {
+                notifies.retain(|n| !Arc::ptr_eq(n, &self.notify));
+                if notifies.is_empty() {
+                    map.remove(key);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn wake_before_wait_is_not_lost() {
+        let waiters = Waiters::default();
+        let guard = waiters.register(0, &["a".into(), "b".into()]);
+        waiters.wake(0, &"b".into());
+        tokio::time::timeout(Duration::from_millis(100), guard.wait())
+            .await
+            .expect("expected stored wakeup");
+    }
+
+    #[tokio::test]
+    async fn drop_deregisters() {
+        let waiters = Waiters::default();
+        let guard = waiters.register(0, &["a".into()]);
+        drop(guard);
+        assert!(waiters.keys.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,137 @@
+//! What a client's handle on the store carries for that client alone, as
+//! opposed to the data and server state every handle shares.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Which replies the client is sent, as CLIENT REPLY sets it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReplyMode {
+    #[default]
+    On,
+    Off,
+    /// Not the reply to the next command, then back to on.
+    Skip,
+}
+
+impl ReplyMode {
+    fn from_u8(mode: u8) -> ReplyMode {
+        match mode {
+            0 => ReplyMode::On,
+            1 => ReplyMode::Off,
+            _ => ReplyMode::Skip,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ClientState {
+    /// The database the client reads and writes, which SELECT changes.
+    selected: AtomicUsize,
+    /// The user the client runs commands as, or None until it authenticates.
+    user: Mutex<Option<String>>,
+    /// The id of the client, or 0 for the server's own handle.
+    id: AtomicU64,
+    /// Set by CLIENT NO-TOUCH: the client's commands leave keys' access
+    /// times alone.
+    no_touch: AtomicBool,
+    /// Set by HELLO 3: the client speaks RESP3.
+    resp3: AtomicBool,
+    /// A `ReplyMode`.
+    reply_mode: AtomicU8,
+}
+
+impl ClientState {
+    pub fn new(user: Option<String>) -> ClientState {
+        ClientState {
+            user: Mutex::new(user),
+            ..Default::default()
+        }
+    }
+
+    /// A copy of this state on database `db`.
+    pub fn in_db(&self, db: usize) -> ClientState {
+        ClientState {
+            selected: AtomicUsize::new(db),
+            user: Mutex::new(self.user()),
+            id: AtomicU64::new(self.id.load(Ordering::Relaxed)),
+            no_touch: AtomicBool::new(self.no_touch()),
+            resp3: AtomicBool::new(self.resp3()),
+            reply_mode: AtomicU8::new(self.reply_mode() as u8),
+        }
+    }
+
+    /// Puts everything but the id back as a new connection has it, logged in
+    /// as `user`.
+    pub fn reset(&self, user: Option<String>) {
+        self.select(0);
+        self.set_user(user);
+        self.set_no_touch(false);
+        self.set_resp3(false);
+        self.set_reply_mode(ReplyMode::On);
+    }
+
+    pub fn db(&self) -> usize {
+        self.selected.load(Ordering::Relaxed)
+    }
+
+    pub fn select(&self, db: usize) {
+        self.selected.store(db, Ordering::Relaxed);
+    }
+
+    pub fn user(&self) -> Option<String> {
+        self.user.lock().unwrap().clone()
+    }
+
+    pub fn set_user(&self, user: Option<String>) {
+        *self.user.lock().unwrap() = user;
+    }
+
+    pub fn id(&self) -> Option<u64> {
+        match self.id.load(Ordering::Relaxed) {
+            0 => None,
+            id => Some(id),
+        }
+    }
+
+    pub fn set_id(&self, id: u64) {
+        self.id.store(id, Ordering::Relaxed);
+    }
+
+    pub fn no_touch(&self) -> bool {
+        self.no_touch.load(Ordering::Relaxed)
+    }
+
+    pub fn set_no_touch(&self, no_touch: bool) {
+        self.no_touch.store(no_touch, Ordering::Relaxed);
+    }
+
+    pub fn resp3(&self) -> bool {
+        self.resp3.load(Ordering::Relaxed)
+    }
+
+    pub fn set_resp3(&self, resp3: bool) {
+        self.resp3.store(resp3, Ordering::Relaxed);
+    }
+
+    pub fn reply_mode(&self) -> ReplyMode {
+        ReplyMode::from_u8(self.reply_mode.load(Ordering::Relaxed))
+    }
+
+    pub fn set_reply_mode(&self, mode: ReplyMode) {
+        self.reply_mode.store(mode as u8, Ordering::Relaxed);
+    }
+
+    /// Whether the reply to the command about to run is to be skipped, turning
+    /// replies back on after it if so.
+    pub fn take_reply_skip(&self) -> bool {
+        self.reply_mode
+            .compare_exchange(
+                ReplyMode::Skip as u8,
+                ReplyMode::On as u8,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+
+/// How many clients may be connected at once unless configured otherwise.
+pub const DEFAULT_MAXCLIENTS: usize = 10000;
+
+/// A pause long enough to last until it's lifted, as FAILOVER's without a
+/// timeout does.
+pub const PAUSE_UNTIL_UNPAUSED: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+/// What CLIENT LIST shows about one connection.
+#[derive(Debug, Clone)]
+pub struct Client {
+    pub id: u64,
+    pub addr: SocketAddr,
+    /// The local address the client connected to.
+    pub laddr: SocketAddr,
+    /// Set with CLIENT SETNAME; empty if none.
+    pub name: String,
+    pub created: Instant,
+    /// When the client last sent a command.
+    pub last_interaction: Instant,
+    /// The client's last command, with its subcommand if it has one.
+    pub cmd: String,
+    /// The connection is a replica's.
+    pub replica: bool,
+    /// Set by CLIENT NO-EVICT.
+    pub no_evict: bool,
+    /// Set by CLIENT NO-TOUCH.
+    pub no_touch: bool,
+    /// Set by CLIENT TRACKING.
+    pub tracking: bool,
+    pub db: usize,
+    pub user: String,
+    /// Channels subscribed to.
+    pub sub: usize,
+    /// Commands queued since MULTI, if in a transaction.
+    pub multi: Option<usize>,
+    /// Keys watched.
+    pub watch: usize,
+    /// Tells the connection to close, see `ClientGuard::killed`.
+    kill: Arc<Notify>,
+}
+
+impl Client {
+    /// `S` for a replica, `x` in a transaction, `P` when subscribed, `e` for
+    /// no-evict, `T` for no-touch and `t` for tracking, or `N` for none of
+    /// those.
+    fn flags(&self) -> String {
+        let mut flags = String::new();
+        for (set, flag) in [
+            (self.replica, 'S'),
+            (self.multi.is_some(), 'x'),
+            (self.sub > 0, 'P'),
+            (self.no_evict, 'e'),
+            (self.no_touch, 'T'),
+            (self.tracking, 't'),
+        ] {
+            if set {
+                flags.push(flag);
+            }
+        }
+        if flags.is_empty() {
+            flags.push('N');
+        }
+        flags
+    }
+
+    /// The client as a CLIENT LIST line, without the newline.
+    pub fn describe(&self) -> String {
+        let now = Instant::now();
+        format!(
+            "id={} addr={} laddr={} name={} age={} idle={} flags={} db={} sub={} psub=0 multi={} watch={} user={} cmd={}",
+            self.id,
+            self.addr,
+            self.laddr,
+            self.name,
+            now.duration_since(self.created).as_secs(),
+            now.duration_since(self.last_interaction).as_secs(),
+            self.flags(),
+            self.db,
+            self.sub,
+            self.multi.map_or(-1, |queued| queued as i64),
+            self.watch,
+            self.user,
+            self.cmd,
+        )
+    }
+}
+
+/// A CLIENT PAUSE in effect.
+#[derive(Debug, Clone, Copy)]
+struct Pause {
+    until: Instant,
+    /// Only commands that may write are held back.
+    writes_only: bool,
+}
+
+/// The connection slots `maxclients` allows, as semaphore permits.
+#[derive(Debug)]
+struct Seats {
+    semaphore: Arc<Semaphore>,
+    max: usize,
+    /// Permits to drop as clients leave, after `max` was lowered below the
+    /// number of clients connected.
+    owed: usize,
+}
+
+impl Default for Seats {
+    fn default() -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(DEFAULT_MAXCLIENTS)),
+            max: DEFAULT_MAXCLIENTS,
+            owed: 0,
+        }
+    }
+}
+
+/// The connected clients, keyed by id. Ids count up from 1 and are never
+/// reused.
+#[derive(Debug, Clone, Default)]
+pub struct Clients {
+    next_id: Arc<AtomicU64>,
+    clients: Arc<Mutex<BTreeMap<u64, Client>>>,
+    pause: Arc<Mutex<Option<Pause>>>,
+    unpaused: Arc<Notify>,
+    seats: Arc<Mutex<Seats>>,
+}
+
+impl Clients {
+    /// A slot for a new connection, or None if `maxclients` are connected.
+    /// The slot frees up when the seat is dropped.
+    pub fn seat(&self) -> Option<Seat> {
+        let semaphore = self.seats.lock().unwrap().semaphore.clone();
+        let permit = semaphore.try_acquire_owned().ok()?;
+        Some(Seat {
+            seats: self.seats.clone(),
+            permit: Some(permit),
+        })
+    }
+
+    pub fn max_clients(&self) -> usize {
+        self.seats.lock().unwrap().max
+    }
+
+    /// Allows `max` clients at once. Clients already connected beyond a
+    /// lowered limit stay connected, and their seats go as they leave.
+    pub fn set_max_clients(&self, max: usize) {
+        let mut seats = self.seats.lock().unwrap();
+        if max >= seats.max {
+            let added = max - seats.max;
+            let repaid = added.min(seats.owed);
+            seats.owed -= repaid;
+            seats.semaphore.add_permits(added - repaid);
+        } else {
+            let removed = seats.max - max;
+            let forgotten = seats.semaphore.forget_permits(removed);
+            seats.owed += removed - forgotten;
+        }
+        seats.max = max;
+    }
+
+    /// Adds a client connected from `addr` to `laddr`; it is removed when the
+    /// guard is dropped.
+    pub fn register(&self, addr: SocketAddr, laddr: SocketAddr) -> ClientGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let now = Instant::now();
+        let client = Client {
+            id,
+            addr,
+            laddr,
+            name: String::new(),
+            created: now,
+            last_interaction: now,
+            cmd: "NULL".to_string(),
+            replica: false,
+            no_evict: false,
+            no_touch: false,
+            tracking: false,
+            db: 0,
+            user: String::new(),
+            sub: 0,
+            multi: None,
+            watch: 0,
+            kill: Default::default(),
+        };
+        let kill = client.kill.clone();
+        self.clients.lock().unwrap().insert(id, client);
+        ClientGuard {
+            clients: self.clone(),
+            id,
+            kill,
+        }
+    }
+
+    /// Asks client `id`'s connection to close once it is between commands.
+    pub fn kill(&self, id: u64) {
+        if let Some(client) = self.clients.lock().unwrap().get(&id) {
+            client.kill.notify_one();
+        }
+    }
+
+    /// Holds back clients' commands, or only those that may write, for
+    /// `duration`. A later pause replaces this one.
+    pub fn pause(&self, duration: Duration, writes_only: bool) {
+        *self.pause.lock().unwrap() = Some(Pause {
+            until: Instant::now() + duration,
+            writes_only,
+        });
+        // waiting commands pick up the new deadline
+        self.unpaused.notify_waiters();
+    }
+
+    pub fn unpause(&self) {
+        *self.pause.lock().unwrap() = None;
+        self.unpaused.notify_waiters();
+    }
+
+    /// When the pause covering a command ends, given whether it may write,
+    /// or None if no pause covers it.
+    pub fn paused_until(&self, write: bool) -> Option<Instant> {
+        match *self.pause.lock().unwrap() {
+            Some(pause) if pause.until > Instant::now() && (write || !pause.writes_only) => {
+                Some(pause.until)
+            }
+            _ => None,
+        }
+    }
+
+    /// Waits out any pause that covers a command, given whether it may write.
+    pub async fn wait_unpaused(&self, write: bool) {
+        loop {
+            let unpaused = self.unpaused.notified();
+            let Some(until) = self.paused_until(write) else {
+                return;
+            };
+            tokio::select! {
+                _ = tokio::time::sleep_until(until.into()) => {}
+                _ = unpaused => {}
+            }
+        }
+    }
+
+    pub fn get(&self, id: u64) -> Option<Client> {
+        self.clients.lock().unwrap().get(&id).cloned()
+    }
+
+    /// Every client, oldest first.
+    pub fn list(&self) -> Vec<Client> {
+        self.clients.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Clients connected other than replicas, which are counted apart.
+    pub fn connected(&self) -> usize {
+        self.clients
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|client| !client.replica)
+            .count()
+    }
+
+    /// Changes what is known about client `id`, if it is still connected.
+    pub fn update(&self, id: u64, change: impl FnOnce(&mut Client)) {
+        if let Some(client) = self.clients.lock().unwrap().get_mut(&id) {
+            change(client);
+        }
+    }
+}
+
+/// A connected client's entry in `Clients`. Dropping it removes the entry.
+#[derive(Debug)]
+pub struct ClientGuard {
+    clients: Clients,
+    id: u64,
+    kill: Arc<Notify>,
+}
+
+impl ClientGuard {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Resolves once CLIENT KILL picks this client.
+    pub async fn killed(&self) {
+        self.kill.notified().await
+    }
+}
+
+/// A connection's slot under `maxclients`, see `Clients::seat`.
+#[derive(Debug)]
+pub struct Seat {
+    seats: Arc<Mutex<Seats>>,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for Seat {
+    fn drop(&mut self) {
+        let mut seats = self.seats.lock().unwrap();
+        if seats.owed > 0 {
+            seats.owed -= 1;
+            if let Some(permit) = self.permit.take() {
+                permit.forget();
+            }
+        }
+    }
+}
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        self.clients.clients.lock().unwrap().remove(&self.id);
+    }
+}
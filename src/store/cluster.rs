@@ -0,0 +1,688 @@
+//! Cluster mode. Keys are spread over 16384 hash slots, each owned by one
+//! node; a node answers for the keys in its own slots and redirects clients
+//! elsewhere with MOVED. With `--cluster-enabled yes` a node starts out as a
+//! cluster of one owning every slot.
+//!
+//! Nodes learn about each other through `Gossip`, which the cluster bus
+//! (see `crate::cluster`) carries between them. A node's claim on a slot
+//! wins over the current owner's if its config epoch is higher, or if the
+//! epochs are equal and its id sorts first, so that nodes which all claim
+//! every slot settle on one owner.
+//!
+//! A slot moves between nodes key by key: the old owner marks it MIGRATING
+//! and the new one IMPORTING, keys go over with MIGRATE, and meanwhile the
+//! old owner sends clients asking for keys it no longer has to the new one
+//! with ASK.
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::scripts::sha1_hex;
+use crate::{
+    command::table::{lookup, request_args},
+    digest::crc16,
+    frame::Frame,
+};
+
+/// The hash slots keys are spread over.
+pub const SLOTS: u16 = 16384;
+
+pub const ERR_DISABLED: &str = "ERR This instance has cluster support disabled";
+
+/// How long a node can go unheard from before it's reported as failing.
+pub const NODE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// The slot `key` hashes to. Only the part between the first `{` and the
+/// next `}` is hashed, if that's not empty, so keys can be kept together.
+pub fn key_slot(key: &[u8]) -> u16 {
+    let tag = key
+        .iter()
+        .position(|b| *b == b'{')
+        .and_then(|open| {
+            let rest = &key[open + 1..];
+            let close = rest.iter().position(|b| *b == b'}')?;
+            Some(&rest[..close])
+        })
+        .filter(|tag| !tag.is_empty());
+    crc16(tag.unwrap_or(key)) % SLOTS
+}
+
+/// A node of the cluster: where clients reach it, and where other nodes do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub id: Arc<str>,
+    pub host: String,
+    pub port: u16,
+    pub bus_port: u16,
+}
+
+impl Node {
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+/// Who serves a slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Owner {
+    Myself,
+    Node(Node),
+    Unassigned,
+}
+
+/// A slot on its way between two nodes, with the node at the other end.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Migration {
+    Migrating(Node),
+    Importing(Node),
+}
+
+/// Why a request can't be served here: the error to reply with instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Redirect {
+    Moved {
+        slot: u16,
+        address: String,
+    },
+    /// Try the node the slot is migrating to, for this request only.
+    Ask {
+        slot: u16,
+        address: String,
+    },
+    CrossSlot,
+    /// Some of a multi-key request's keys have moved and some haven't.
+    TryAgain,
+    Down,
+}
+
+impl fmt::Display for Redirect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Redirect::Moved { slot, address } => write!(f, "MOVED {} {}", slot, address),
+            Redirect::Ask { slot, address } => write!(f, "ASK {} {}", slot, address),
+            Redirect::CrossSlot => "CROSSSLOT Keys in request don't hash to the same slot".fmt(f),
+            Redirect::TryAgain => "TRYAGAIN Multiple keys request during rehashing of slot".fmt(f),
+            Redirect::Down => "CLUSTERDOWN Hash slot not served".fmt(f),
+        }
+    }
+}
+
+/// Another node as we know it.
+#[derive(Debug, Clone)]
+pub struct Peer {
+    pub node: Node,
+    pub epoch: u64,
+    /// When we last heard from it, directly.
+    pub last_seen: Instant,
+}
+
+impl Peer {
+    pub fn is_failing(&self) -> bool {
+        self.last_seen.elapsed() > NODE_TIMEOUT
+    }
+}
+
+/// What one node tells another on the bus: who it is, the slots it claims
+/// and the other nodes it knows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gossip {
+    pub sender: Node,
+    pub epoch: u64,
+    pub slots: Vec<(u16, u16)>,
+    pub nodes: Vec<Node>,
+}
+
+#[derive(Debug)]
+struct State {
+    /// The other nodes we know of, by id.
+    nodes: HashMap<Arc<str>, Peer>,
+    /// The id of each slot's owner, if it has one.
+    owners: Vec<Option<Arc<str>>>,
+    /// Slots being moved, by the id of the node at the other end.
+    migrating: HashMap<u16, Arc<str>>,
+    importing: HashMap<u16, Arc<str>>,
+    my_epoch: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cluster {
+    enabled: Arc<AtomicBool>,
+    /// This node's name among the others, 40 hex characters picked at
+    /// startup.
+    id: Arc<str>,
+    /// Where the bus listens, once it does.
+    bus_port: Arc<AtomicU16>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Default for Cluster {
+    fn default() -> Self {
+        let id: Arc<str> = random_id().into();
+        let state = State {
+            nodes: HashMap::new(),
+            owners: vec![Some(id.clone()); SLOTS.into()],
+            migrating: HashMap::new(),
+            importing: HashMap::new(),
+            my_epoch: 0,
+        };
+        Self {
+            enabled: Default::default(),
+            id,
+            bus_port: Default::default(),
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+}
+
+impl Cluster {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn bus_port(&self) -> u16 {
+        self.bus_port.load(Ordering::Relaxed)
+    }
+
+    pub fn set_bus_port(&self, port: u16) {
+        self.bus_port.store(port, Ordering::Relaxed);
+    }
+
+    pub fn my_epoch(&self) -> u64 {
+        self.state.lock().unwrap().my_epoch
+    }
+
+    /// The highest config epoch of any node we know.
+    pub fn current_epoch(&self) -> u64 {
+        let state = self.state.lock().unwrap();
+        let peers = state.nodes.values().map(|peer| peer.epoch);
+        peers.fold(state.my_epoch, u64::max)
+    }
+
+    /// Takes an epoch above every other node's, so that our claims win,
+    /// unless ours is already the highest. Returns whether it changed, and
+    /// the epoch.
+    pub fn bump_epoch(&self) -> (bool, u64) {
+        let current = self.current_epoch();
+        let mut state = self.state.lock().unwrap();
+        if state.my_epoch != 0 && state.my_epoch == current {
+            return (false, current);
+        }
+        state.my_epoch = current + 1;
+        (true, state.my_epoch)
+    }
+
+    /// Adds `node` to the nodes we know of, or updates its address.
+    pub fn add_node(&self, node: Node) {
+        let mut state = self.state.lock().unwrap();
+        state.add_node(node);
+    }
+
+    /// The other nodes we know of.
+    pub fn nodes(&self) -> Vec<Node> {
+        let state = self.state.lock().unwrap();
+        state.nodes.values().map(|peer| peer.node.clone()).collect()
+    }
+
+    /// The other nodes we know of, ordered by id, with what we know of them.
+    pub fn peers(&self) -> Vec<Peer> {
+        let state = self.state.lock().unwrap();
+        let mut peers: Vec<Peer> = state.nodes.values().cloned().collect();
+        peers.sort_by(|a, b| a.node.id.cmp(&b.node.id));
+        peers
+    }
+
+    /// What to tell other nodes, given the address clients reach us at.
+    pub fn gossip(&self, host: String, port: u16) -> Gossip {
+        let slots = self
+            .ranges()
+            .into_iter()
+            .filter(|(.., owner)| *owner == Owner::Myself)
+            .map(|(first, last, _)| (first, last))
+            .collect();
+        let state = self.state.lock().unwrap();
+        Gossip {
+            sender: Node {
+                id: self.id.clone(),
+                host,
+                port,
+                bus_port: self.bus_port(),
+            },
+            epoch: state.my_epoch,
+            slots,
+            nodes: state.nodes.values().map(|peer| peer.node.clone()).collect(),
+        }
+    }
+
+    /// Takes in what another node told us: it's alive, where it is, which
+    /// slots it claims and which nodes it knows, which we'll get in touch
+    /// with in turn.
+    pub fn receive(&self, gossip: Gossip) {
+        if gossip.sender.id == self.id {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        let sender = gossip.sender.id.clone();
+        state.add_node(gossip.sender);
+        let peer = state.nodes.get_mut(&sender).expect("just added");
+        peer.epoch = gossip.epoch;
+        peer.last_seen = Instant::now();
+        for (first, last) in gossip.slots {
+            for slot in first..=last.min(SLOTS - 1) {
+                let wins = match &state.owners[usize::from(slot)] {
+                    None => true,
+                    Some(owner) if *owner == sender => false,
+                    Some(owner) => {
+                        let epoch = match state.nodes.get(owner) {
+                            Some(peer) => peer.epoch,
+                            None => state.my_epoch,
+                        };
+                        gossip.epoch > epoch || (gossip.epoch == epoch && sender < *owner)
+                    }
+                };
+                if wins {
+                    state.owners[usize::from(slot)] = Some(sender.clone());
+                    state.migrating.remove(&slot);
+                    state.importing.remove(&slot);
+                }
+            }
+        }
+        for node in gossip.nodes {
+            if node.id != self.id && !state.nodes.contains_key(&node.id) {
+                state.add_node(node);
+            }
+        }
+    }
+
+    /// Hands `slots` to the node `id`, which is ours or one added with
+    /// `add_node`, or leaves them unassigned if None, ending any migration
+    /// of them. Returns false, changing nothing, if the node is unknown.
+    pub fn assign(&self, slots: impl IntoIterator<Item = u16>, id: Option<&str>) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let owner = match id {
+            Some(id) => match state.known_id(&self.id, id) {
+                Some(id) => Some(id),
+                None => return false,
+            },
+            None => None,
+        };
+        for slot in slots {
+            state.owners[usize::from(slot)] = owner.clone();
+            state.migrating.remove(&slot);
+            state.importing.remove(&slot);
+        }
+        true
+    }
+
+    /// Hands `slot` to the node `id`, ending its migration. Taking over a
+    /// slot we were importing moves our config epoch above every other
+    /// node's, so that the old owner gives way when it hears our claim.
+    pub fn set_node(&self, slot: u16, id: &str) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        let Some(id) = state.known_id(&self.id, id) else {
+            return Err(format!("ERR Unknown node {}", id));
+        };
+        let imported = id == self.id && state.importing.contains_key(&slot);
+        state.owners[usize::from(slot)] = Some(id);
+        state.migrating.remove(&slot);
+        state.importing.remove(&slot);
+        if imported {
+            let highest = state.nodes.values().map(|peer| peer.epoch).max();
+            if let Some(highest) = highest.filter(|highest| *highest >= state.my_epoch) {
+                state.my_epoch = highest + 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts moving `slot`, which we own, to the node `id`.
+    pub fn set_migrating(&self, slot: u16, id: &str) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        if state.owners[usize::from(slot)].as_ref() != Some(&self.id) {
+            return Err(format!("ERR I'm not the owner of hash slot {}", slot));
+        }
+        let Some(id) = state.known_id(&self.id, id).filter(|id| *id != self.id) else {
+            return Err(format!("ERR I don't know about node {}", id));
+        };
+        state.importing.remove(&slot);
+        state.migrating.insert(slot, id);
+        Ok(())
+    }
+
+    /// Starts taking `slot` over from the node `id`.
+    pub fn set_importing(&self, slot: u16, id: &str) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        if state.owners[usize::from(slot)].as_ref() == Some(&self.id) {
+            return Err(format!("ERR I'm already the owner of hash slot {}", slot));
+        }
+        let Some(id) = state.known_id(&self.id, id).filter(|id| *id != self.id) else {
+            return Err(format!("ERR I don't know about node {}", id));
+        };
+        state.migrating.remove(&slot);
+        state.importing.insert(slot, id);
+        Ok(())
+    }
+
+    /// Ends any migration of `slot`, leaving its owner as it is.
+    pub fn set_stable(&self, slot: u16) {
+        let mut state = self.state.lock().unwrap();
+        state.migrating.remove(&slot);
+        state.importing.remove(&slot);
+    }
+
+    /// Whether `slot` is on its way to or from another node.
+    pub fn migration(&self, slot: u16) -> Option<Migration> {
+        let state = self.state.lock().unwrap();
+        state.migration(slot)
+    }
+
+    /// Every slot on its way to or from another node, in slot order.
+    pub fn migrations(&self) -> Vec<(u16, Migration)> {
+        let state = self.state.lock().unwrap();
+        let mut slots: Vec<u16> = state
+            .migrating
+            .keys()
+            .chain(state.importing.keys())
+            .copied()
+            .collect();
+        slots.sort_unstable();
+        slots
+            .into_iter()
+            .filter_map(|slot| Some((slot, state.migration(slot)?)))
+            .collect()
+    }
+
+    pub fn owner(&self, slot: u16) -> Owner {
+        let state = self.state.lock().unwrap();
+        state.owner(&self.id, slot)
+    }
+
+    /// Runs of consecutive slots with the same owner, as first and last
+    /// slot, in slot order.
+    pub fn ranges(&self) -> Vec<(u16, u16, Owner)> {
+        let state = self.state.lock().unwrap();
+        let mut ranges: Vec<(u16, u16, Owner)> = vec![];
+        for slot in 0..SLOTS {
+            let owner = state.owner(&self.id, slot);
+            match ranges.last_mut() {
+                Some((_, last, previous)) if *previous == owner => *last = slot,
+                _ => ranges.push((slot, slot, owner)),
+            }
+        }
+        ranges
+    }
+
+    /// Checks that the keys of `request` are all in one slot served here,
+    /// see `route`. Anything goes while cluster mode is off.
+    pub fn check(
+        &self,
+        request: &Frame,
+        asking: bool,
+        exists: impl Fn(&[u8]) -> bool,
+    ) -> Result<(), Redirect> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        let args = request_args(request);
+        let Some(spec) = lookup(&args) else {
+            return Ok(());
+        };
+        // MIGRATE answers for keys that are already gone itself
+        let exists = |key: &[u8]| spec.name == "migrate" || exists(key);
+        self.route(&spec.keys.of(&args), asking, exists)
+    }
+
+    /// Checks that `keys` are all in one slot served here. While the slot
+    /// migrates away, only the keys that `exists` says are still here are;
+    /// while it's imported, keys are served to clients that sent ASKING.
+    pub fn route(
+        &self,
+        keys: &[&[u8]],
+        asking: bool,
+        exists: impl Fn(&[u8]) -> bool,
+    ) -> Result<(), Redirect> {
+        let Some((first, rest)) = keys.split_first() else {
+            return Ok(());
+        };
+        let slot = key_slot(first);
+        if rest.iter().any(|key| key_slot(key) != slot) {
+            return Err(Redirect::CrossSlot);
+        }
+        let (owner, migration) = {
+            let state = self.state.lock().unwrap();
+            (state.owner(&self.id, slot), state.migration(slot))
+        };
+        match (owner, migration) {
+            (Owner::Myself, Some(Migration::Migrating(node))) => {
+                let missing = keys.iter().filter(|key| !exists(key)).count();
+                if missing == 0 {
+                    Ok(())
+                } else if missing == keys.len() {
+                    Err(Redirect::Ask {
+                        slot,
+                        address: node.address(),
+                    })
+                } else {
+                    Err(Redirect::TryAgain)
+                }
+            }
+            (_, Some(Migration::Importing(_))) if asking => Ok(()),
+            (Owner::Myself, _) => Ok(()),
+            (Owner::Node(node), _) => Err(Redirect::Moved {
+                slot,
+                address: node.address(),
+            }),
+            (Owner::Unassigned, _) => Err(Redirect::Down),
+        }
+    }
+}
+
+impl State {
+    fn owner(&self, me: &Arc<str>, slot: u16) -> Owner {
+        match &self.owners[usize::from(slot)] {
+            Some(id) if id == me => Owner::Myself,
+            Some(id) => Owner::Node(self.nodes[id].node.clone()),
+            None => Owner::Unassigned,
+        }
+    }
+
+    fn migration(&self, slot: u16) -> Option<Migration> {
+        if let Some(id) = self.migrating.get(&slot) {
+            return Some(Migration::Migrating(self.nodes[id].node.clone()));
+        }
+        let id = self.importing.get(&slot)?;
+        Some(Migration::Importing(self.nodes[id].node.clone()))
+    }
+
+    /// `id` as we hold it, if it's ours or a node's we know.
+    fn known_id(&self, me: &Arc<str>, id: &str) -> Option<Arc<str>> {
+        if id == &**me {
+            return Some(me.clone());
+        }
+        self.nodes.get_key_value(id).map(|(id, _)| id.clone())
+    }
+
+    /// Adds `node`, or updates the address of the node with its id.
+    fn add_node(&mut self, node: Node) {
+        match self.nodes.get_mut(&node.id) {
+            Some(peer) => peer.node = node,
+            None => {
+                let peer = Peer {
+                    node: node.clone(),
+                    epoch: 0,
+                    last_seen: Instant::now(),
+                };
+                self.nodes.insert(node.id, peer);
+            }
+        }
+    }
+}
+
+/// 40 random hex characters, as node and replication ids are.
+pub(crate) fn random_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_nanos());
+    let seed: Vec<u8> = (0..4)
+        .flat_map(|i| {
+            RandomState::new()
+                .hash_one((i, nanos, std::process::id()))
+                .to_le_bytes()
+        })
+        .collect();
+    sha1_hex(&seed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nodes_get_distinct_hex_ids() {
+        let (a, b) = (Cluster::default(), Cluster::default());
+        assert_eq!(a.id().len(), 40);
+        assert!(a.id().bytes().all(|b| b.is_ascii_hexdigit()));
+        assert_ne!(a.id(), b.id());
+        assert_eq!(a.clone().id(), a.id());
+    }
+
+    #[test]
+    fn slots_match_redis_and_honour_hash_tags() {
+        assert_eq!(key_slot(b"foo"), 12182);
+        assert_eq!(key_slot(b"123456789"), 12739);
+        assert_eq!(key_slot(b"{user1000}.following"), key_slot(b"user1000"));
+        assert_eq!(key_slot(b"foo{}{bar}"), key_slot(b"foo{}{bar}"));
+        assert_ne!(key_slot(b"foo{}{bar}"), key_slot(b"bar"));
+        assert_eq!(key_slot(b"foo{{bar}}zap"), key_slot(b"{bar"));
+    }
+
+    #[test]
+    fn keys_elsewhere_are_redirected() {
+        let cluster = Cluster::default();
+        let other = Node {
+            id: "b".repeat(40).into(),
+            host: "10.0.0.2".to_string(),
+            port: 7001,
+            bus_port: 17001,
+        };
+        assert!(!cluster.assign(0..100, Some(&other.id)));
+        cluster.add_node(other.clone());
+        assert!(cluster.assign(key_slot(b"foo")..SLOTS, Some(&other.id)));
+        assert!(cluster.assign([0], None));
+
+        let route = |keys: &[&[u8]]| cluster.route(keys, false, |_| true);
+        assert_eq!(route(&[b"bar", b"{bar}x"]), Ok(()));
+        assert_eq!(
+            route(&[b"foo"]).unwrap_err().to_string(),
+            "MOVED 12182 10.0.0.2:7001"
+        );
+        assert_eq!(route(&[b"foo", b"bar"]), Err(Redirect::CrossSlot));
+        assert_eq!(cluster.owner(0), Owner::Unassigned);
+        assert_eq!(
+            cluster.ranges(),
+            [
+                (0, 0, Owner::Unassigned),
+                (1, 12181, Owner::Myself),
+                (12182, 16383, Owner::Node(other)),
+            ]
+        );
+    }
+
+    #[test]
+    fn migrating_slots_ask_for_missing_keys() {
+        let cluster = Cluster::default();
+        let other = Node {
+            id: "b".repeat(40).into(),
+            host: "10.0.0.2".to_string(),
+            port: 7001,
+            bus_port: 17001,
+        };
+        cluster.add_node(other.clone());
+        let slot = key_slot(b"{t}a");
+        assert!(cluster.set_migrating(slot, "nobody").is_err());
+        assert!(cluster.set_migrating(slot, cluster.id()).is_err());
+        assert!(cluster.set_importing(slot, &other.id).is_err());
+        cluster.set_migrating(slot, &other.id).unwrap();
+        assert_eq!(
+            cluster.migration(slot),
+            Some(Migration::Migrating(other.clone()))
+        );
+
+        let here = |key: &[u8]| key == b"{t}a";
+        assert_eq!(cluster.route(&[b"{t}a"], false, here), Ok(()));
+        assert_eq!(
+            cluster
+                .route(&[b"{t}b"], false, here)
+                .unwrap_err()
+                .to_string(),
+            format!("ASK {} 10.0.0.2:7001", slot)
+        );
+        assert_eq!(
+            cluster.route(&[b"{t}a", b"{t}b"], false, here),
+            Err(Redirect::TryAgain)
+        );
+
+        // once the slot is handed over, the other node takes the lot
+        assert!(cluster.assign([slot], Some(&other.id)));
+        assert_eq!(cluster.migration(slot), None);
+        assert!(matches!(
+            cluster.route(&[b"{t}a"], false, here),
+            Err(Redirect::Moved { .. })
+        ));
+
+        // and importing it back serves clients that ask
+        cluster.set_importing(slot, &other.id).unwrap();
+        assert_eq!(cluster.route(&[b"{t}a"], true, here), Ok(()));
+        assert!(cluster.route(&[b"{t}a"], false, here).is_err());
+        cluster.set_stable(slot);
+        assert!(cluster.route(&[b"{t}a"], true, here).is_err());
+    }
+
+    #[test]
+    fn claims_go_to_the_higher_epoch_then_the_lower_id() {
+        let cluster = Cluster::default();
+        let claim = |id: &str, epoch, slots: Vec<(u16, u16)>| Gossip {
+            sender: Node {
+                id: id.into(),
+                host: "10.0.0.2".to_string(),
+                port: 7001,
+                bus_port: 17001,
+            },
+            epoch,
+            slots,
+            nodes: vec![],
+        };
+        // every id sorts after "0..." and before "g..."
+        cluster.receive(claim(&"g".repeat(40), 0, vec![(0, SLOTS - 1)]));
+        assert_eq!(cluster.owner(0), Owner::Myself);
+        let low = "0".repeat(40);
+        cluster.receive(claim(&low, 0, vec![(0, 99)]));
+        assert!(matches!(cluster.owner(99), Owner::Node(node) if *node.id == *low));
+        assert_eq!(cluster.owner(100), Owner::Myself);
+
+        let high = "g".repeat(40);
+        cluster.receive(claim(&high, 1, vec![(50, 149)]));
+        assert!(matches!(cluster.owner(50), Owner::Node(node) if *node.id == *high));
+        assert!(matches!(cluster.owner(149), Owner::Node(node) if *node.id == *high));
+        assert_eq!(cluster.current_epoch(), 1);
+        assert_eq!(cluster.peers().len(), 2);
+
+        // gossip about a third node introduces it
+        let mut gossip = claim(&low, 0, vec![]);
+        gossip.nodes.push(claim(&"5".repeat(40), 0, vec![]).sender);
+        cluster.receive(gossip);
+        assert_eq!(cluster.nodes().len(), 3);
+    }
+}
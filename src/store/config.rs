@@ -0,0 +1,342 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use super::evict::Policy;
+use super::output::OutputLimit;
+use crate::frame::Limits;
+
+pub const DEFAULT_HOST: &str = "127.0.0.1";
+pub const DEFAULT_PORT: u16 = 6379;
+pub const DEFAULT_DIR: &str = ".";
+pub const DEFAULT_DBFILENAME: &str = "dump.rdb";
+pub const DEFAULT_SAVE: &str = "3600 1 300 100 60 10000";
+pub const DEFAULT_APPENDFILENAME: &str = "appendonly.aof";
+pub const DEFAULT_ENABLE_DEBUG_COMMAND: &str = "no";
+pub const DEFAULT_MAXMEMORY_SAMPLES: usize = 5;
+/// How long a script runs, in milliseconds, before other clients are told
+/// BUSY.
+pub const DEFAULT_LUA_TIME_LIMIT: u64 = 5000;
+/// By default the AOF is rewritten once it has doubled since the last
+/// rewrite, but not before it reaches 64mb.
+pub const DEFAULT_AUTO_AOF_REWRITE_PERCENTAGE: u64 = 100;
+pub const DEFAULT_AUTO_AOF_REWRITE_MIN_SIZE: u64 = 64 * 1024 * 1024;
+/// Redis's defaults for `proto-max-bulk-len` and `client-query-buffer-limit`;
+/// arrays are only held to what fits in an `int`, as in Redis.
+pub const DEFAULT_LIMITS: Limits = Limits {
+    bulk_len: 512 * 1024 * 1024,
+    multibulk_len: i32::MAX as u64,
+    query_buffer: 1024 * 1024 * 1024,
+};
+
+/// Snapshot once `changes` writes have happened and `secs` have passed since
+/// the last save.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SaveRule {
+    pub secs: u64,
+    pub changes: u64,
+}
+
+/// Parses save rules as `<seconds> <changes>` pairs; an empty string means none.
+pub fn parse_save_rules(value: &str) -> Option<Vec<SaveRule>> {
+    let numbers = value
+        .split_whitespace()
+        .map(|n| n.parse::<u64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    if numbers.len() % 2 != 0 {
+        return None;
+    }
+    let rules = numbers
+        .chunks(2)
+        .map(|pair| SaveRule {
+            secs: pair[0],
+            changes: pair[1],
+        })
+        .collect();
+    Some(rules)
+}
+
+pub fn format_save_rules(rules: &[SaveRule]) -> String {
+    rules
+        .iter()
+        .map(|rule| format!("{} {}", rule.secs, rule.changes))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Parses a memory value as Redis does: bytes, optionally with a k, kb, m,
+/// mb, g or gb suffix, where the "b" forms are powers of 1024.
+pub fn parse_memory(value: &str) -> Option<u64> {
+    let value = value.to_ascii_lowercase();
+    let digits = value.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit = match &value[digits.len()..] {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    digits.parse::<u64>().ok()?.checked_mul(unit)
+}
+
+#[derive(Debug)]
+struct Params {
+    /// The address and port this server listens on.
+    host: String,
+    port: u16,
+    dir: String,
+    dbfilename: String,
+    /// Bytes of data allowed, or 0 for no limit.
+    maxmemory: u64,
+    maxmemory_policy: Policy,
+    /// How many keys eviction picks between for each one it evicts.
+    maxmemory_samples: usize,
+    appendonly: bool,
+    appendfilename: String,
+    /// The file ACL SAVE and ACL LOAD use, or empty for none.
+    aclfile: String,
+    /// Who may run DEBUG: `yes` for anyone, `local` for loopback
+    /// connections only, or `no` for nobody.
+    enable_debug_command: String,
+    auto_aof_rewrite_percentage: u64,
+    auto_aof_rewrite_min_size: u64,
+    save: Vec<SaveRule>,
+    /// Seconds a client may sit idle before it is disconnected, or 0 for
+    /// no limit.
+    timeout: u64,
+    /// How large a request clients may send.
+    limits: Limits,
+    /// How much output normal clients may leave unread.
+    output_limit: OutputLimit,
+    /// Milliseconds a script may run before other clients get BUSY and it
+    /// may be killed.
+    lua_time_limit: u64,
+}
+
+/// Server parameters, set on the command line or with CONFIG SET.
+#[derive(Debug, Clone)]
+pub struct Config {
+    params: Arc<Mutex<Params>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            params: Arc::new(Mutex::new(Params {
+                host: DEFAULT_HOST.to_string(),
+                port: DEFAULT_PORT,
+                dir: DEFAULT_DIR.to_string(),
+                dbfilename: DEFAULT_DBFILENAME.to_string(),
+                maxmemory: 0,
+                maxmemory_policy: Policy::default(),
+                maxmemory_samples: DEFAULT_MAXMEMORY_SAMPLES,
+                appendonly: false,
+                appendfilename: DEFAULT_APPENDFILENAME.to_string(),
+                aclfile: String::new(),
+                enable_debug_command: DEFAULT_ENABLE_DEBUG_COMMAND.to_string(),
+                auto_aof_rewrite_percentage: DEFAULT_AUTO_AOF_REWRITE_PERCENTAGE,
+                auto_aof_rewrite_min_size: DEFAULT_AUTO_AOF_REWRITE_MIN_SIZE,
+                save: parse_save_rules(DEFAULT_SAVE).unwrap_or_default(),
+                timeout: 0,
+                limits: DEFAULT_LIMITS,
+                output_limit: OutputLimit::default(),
+                lua_time_limit: DEFAULT_LUA_TIME_LIMIT,
+            })),
+        }
+    }
+}
+
+impl Config {
+    pub fn host(&self) -> String {
+        self.params.lock().unwrap().host.clone()
+    }
+
+    pub fn set_host(&self, host: String) {
+        self.params.lock().unwrap().host = host;
+    }
+
+    pub fn port(&self) -> u16 {
+        self.params.lock().unwrap().port
+    }
+
+    pub fn set_port(&self, port: u16) {
+        self.params.lock().unwrap().port = port;
+    }
+
+    pub fn dir(&self) -> String {
+        self.params.lock().unwrap().dir.clone()
+    }
+
+    pub fn set_dir(&self, dir: String) {
+        self.params.lock().unwrap().dir = dir;
+    }
+
+    pub fn dbfilename(&self) -> String {
+        self.params.lock().unwrap().dbfilename.clone()
+    }
+
+    pub fn set_dbfilename(&self, dbfilename: String) {
+        self.params.lock().unwrap().dbfilename = dbfilename;
+    }
+
+    pub fn maxmemory(&self) -> u64 {
+        self.params.lock().unwrap().maxmemory
+    }
+
+    pub fn set_maxmemory(&self, maxmemory: u64) {
+        self.params.lock().unwrap().maxmemory = maxmemory;
+    }
+
+    pub fn maxmemory_policy(&self) -> Policy {
+        self.params.lock().unwrap().maxmemory_policy
+    }
+
+    pub fn set_maxmemory_policy(&self, policy: Policy) {
+        self.params.lock().unwrap().maxmemory_policy = policy;
+    }
+
+    pub fn maxmemory_samples(&self) -> usize {
+        self.params.lock().unwrap().maxmemory_samples
+    }
+
+    pub fn set_maxmemory_samples(&self, samples: usize) {
+        self.params.lock().unwrap().maxmemory_samples = samples;
+    }
+
+    pub fn timeout(&self) -> u64 {
+        self.params.lock().unwrap().timeout
+    }
+
+    pub fn set_timeout(&self, secs: u64) {
+        self.params.lock().unwrap().timeout = secs;
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.params.lock().unwrap().limits
+    }
+
+    pub fn set_limits(&self, limits: Limits) {
+        self.params.lock().unwrap().limits = limits;
+    }
+
+    pub fn output_limit(&self) -> OutputLimit {
+        self.params.lock().unwrap().output_limit
+    }
+
+    pub fn set_output_limit(&self, limit: OutputLimit) {
+        self.params.lock().unwrap().output_limit = limit;
+    }
+
+    pub fn lua_time_limit(&self) -> u64 {
+        self.params.lock().unwrap().lua_time_limit
+    }
+
+    pub fn set_lua_time_limit(&self, ms: u64) {
+        self.params.lock().unwrap().lua_time_limit = ms;
+    }
+
+    pub fn appendonly(&self) -> bool {
+        self.params.lock().unwrap().appendonly
+    }
+
+    pub fn set_appendonly(&self, appendonly: bool) {
+        self.params.lock().unwrap().appendonly = appendonly;
+    }
+
+    pub fn appendfilename(&self) -> String {
+        self.params.lock().unwrap().appendfilename.clone()
+    }
+
+    pub fn set_appendfilename(&self, appendfilename: String) {
+        self.params.lock().unwrap().appendfilename = appendfilename;
+    }
+
+    pub fn aclfile(&self) -> String {
+        self.params.lock().unwrap().aclfile.clone()
+    }
+
+    pub fn set_aclfile(&self, aclfile: String) {
+        self.params.lock().unwrap().aclfile = aclfile;
+    }
+
+    pub fn enable_debug_command(&self) -> String {
+        self.params.lock().unwrap().enable_debug_command.clone()
+    }
+
+    pub fn set_enable_debug_command(&self, enable: String) {
+        self.params.lock().unwrap().enable_debug_command = enable;
+    }
+
+    /// Growth over the size after the last rewrite, in percent, that triggers
+    /// another rewrite; 0 turns automatic rewrites off.
+    pub fn auto_aof_rewrite_percentage(&self) -> u64 {
+        self.params.lock().unwrap().auto_aof_rewrite_percentage
+    }
+
+    pub fn set_auto_aof_rewrite_percentage(&self, percentage: u64) {
+        self.params.lock().unwrap().auto_aof_rewrite_percentage = percentage;
+    }
+
+    pub fn auto_aof_rewrite_min_size(&self) -> u64 {
+        self.params.lock().unwrap().auto_aof_rewrite_min_size
+    }
+
+    pub fn set_auto_aof_rewrite_min_size(&self, size: u64) {
+        self.params.lock().unwrap().auto_aof_rewrite_min_size = size;
+    }
+
+    pub fn save(&self) -> Vec<SaveRule> {
+        self.params.lock().unwrap().save.clone()
+    }
+
+    pub fn set_save(&self, save: Vec<SaveRule>) {
+        self.params.lock().unwrap().save = save;
+    }
+
+    /// Where the RDB file is read from at startup.
+    pub fn rdb_path(&self) -> PathBuf {
+        let params = self.params.lock().unwrap();
+        PathBuf::from(&params.dir).join(&params.dbfilename)
+    }
+
+    pub fn aof_path(&self) -> PathBuf {
+        let params = self.params.lock().unwrap();
+        PathBuf::from(&params.dir).join(&params.appendfilename)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_values() {
+        assert_eq!(parse_memory("100"), Some(100));
+        assert_eq!(parse_memory("2k"), Some(2000));
+        assert_eq!(parse_memory("2KB"), Some(2048));
+        assert_eq!(parse_memory("1mb"), Some(1024 * 1024));
+        assert_eq!(parse_memory("1g"), Some(1_000_000_000));
+        assert_eq!(parse_memory("1tb"), None);
+        assert_eq!(parse_memory("-1"), None);
+        assert_eq!(parse_memory("mb"), None);
+    }
+
+    #[test]
+    fn save_rules() {
+        let rules = parse_save_rules(DEFAULT_SAVE).unwrap();
+        assert_eq!(rules.len(), 3);
+        assert_eq!(
+            rules[1],
+            SaveRule {
+                secs: 300,
+                changes: 100
+            }
+        );
+        assert_eq!(format_save_rules(&rules), DEFAULT_SAVE);
+        assert_eq!(parse_save_rules(""), Some(vec![]));
+        assert_eq!(parse_save_rules("60"), None);
+        assert_eq!(parse_save_rules("60 x"), None);
+    }
+}
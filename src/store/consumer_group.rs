@@ -0,0 +1,257 @@
+use bytes::Bytes;
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::stream::{Fields, StreamId};
+
+/// A delivered but not yet acknowledged entry in a group's pending entries list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingEntry {
+    pub consumer: Bytes,
+    /// Unix time in milliseconds of the last delivery.
+    pub delivery_time: u64,
+    pub delivery_count: u64,
+}
+
+/// XCLAIM's options beyond the IDs. XAUTOCLAIM uses `min_idle` and `just_id`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClaimOptions {
+    pub min_idle: u64,
+    /// Overrides the delivery time, which otherwise becomes now.
+    pub delivery_time: Option<u64>,
+    /// Overrides the delivery count, which otherwise goes up by one unless `just_id`.
+    pub retry_count: Option<u64>,
+    /// Creates the pending entry if it is missing but the stream entry exists.
+    pub force: bool,
+    pub just_id: bool,
+    /// Moves the group's last delivered ID forward to this one.
+    pub last_id: Option<StreamId>,
+}
+
+/// An entry that changed owner, with the delivery metadata it ended up with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Claimed {
+    pub id: StreamId,
+    pub fields: Fields,
+    pub delivery_time: u64,
+    pub delivery_count: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Consumer {
+    /// IDs this consumer owns in the group's pending entries list.
+    pub pending: BTreeSet<StreamId>,
+    /// Unix time in milliseconds the consumer last interacted with the group.
+    pub seen_time: u64,
+}
+
+/// Consumer group state: where the group is in the stream, which entries are
+/// pending, and who owns them. The group-wide `pending` map and each consumer's
+/// `pending` set are kept in sync by the methods here.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConsumerGroup {
+    pub last_delivered: StreamId,
+    pending: BTreeMap<StreamId, PendingEntry>,
+    consumers: BTreeMap<Bytes, Consumer>,
+}
+
+impl ConsumerGroup {
+    pub fn new(last_delivered: StreamId) -> Self {
+        Self {
+            last_delivered,
+            ..Default::default()
+        }
+    }
+
+    pub fn pending(&self) -> &BTreeMap<StreamId, PendingEntry> {
+        &self.pending
+    }
+
+    pub fn consumers(&self) -> &BTreeMap<Bytes, Consumer> {
+        &self.consumers
+    }
+
+    /// Creates `name` if needed, returning true if it is new.
+    pub fn create_consumer(&mut self, name: Bytes, now: u64) -> bool {
+        let mut created = false;
+        self.consumers.entry(name).or_insert_with(|| {
+            created = true;
+            Consumer {
+                seen_time: now,
+                ..Default::default()
+            }
+        });
+        created
+    }
+
+    /// Marks `name` as active, creating it if needed.
+    pub fn touch_consumer(&mut self, name: &Bytes, now: u64) {
+        self.create_consumer(name.clone(), now);
+        if let Some(consumer) = self.consumers.get_mut(name) {
+            consumer.seen_time = now;
+        }
+    }
+
+    /// Deletes `name` and its pending entries, returning how many were pending.
+    pub fn delete_consumer(&mut self, name: &[u8]) -> usize {
+        let Some(consumer) = self.consumers.remove(name) else {
+            return 0;
+        };
+        for id in &consumer.pending {
+            self.pending.remove(id);
+        }
+        consumer.pending.len()
+    }
+
+    /// Records a delivery of `id` to `consumer`, moving ownership if another
+    /// consumer held it and bumping the delivery count.
+    pub fn deliver(&mut self, id: StreamId, consumer: &Bytes, now: u64) {
+        self.touch_consumer(consumer, now);
+        let delivery_count = self.pending.get(&id).map_or(0, |p| p.delivery_count) + 1;
+        self.claim(id, consumer, now, delivery_count);
+    }
+
+    /// Assigns `id` to `consumer` with explicit delivery metadata.
+    pub fn claim(&mut self, id: StreamId, consumer: &Bytes, delivery_time: u64, count: u64) {
+        if let Some(previous) = self.pending.get(&id) {
+            if let Some(owner) = self.consumers.get_mut(&previous.consumer) {
+                owner.pending.remove(&id);
+            }
+        }
+        self.create_consumer(consumer.clone(), delivery_time);
+        if let Some(owner) = self.consumers.get_mut(consumer) {
+            owner.pending.insert(id);
+        }
+        self.pending.insert(
+            id,
+            PendingEntry {
+                consumer: consumer.clone(),
+                delivery_time,
+                delivery_count: count,
+            },
+        );
+    }
+
+    /// Transfers `id` to `consumer` if it has been idle for at least
+    /// `min_idle`, returning the new delivery time and count. An entry that is
+    /// gone from the stream is dropped from the pending entries list instead.
+    pub fn try_claim(
+        &mut self,
+        id: StreamId,
+        consumer: &Bytes,
+        options: &ClaimOptions,
+        now: u64,
+        in_stream: bool,
+    ) -> Option<(u64, u64)> {
+        if !in_stream {
+            self.ack(&id);
+            return None;
+        }
+        let (delivery_time, delivery_count) = match self.pending.get(&id) {
+            Some(entry) => (entry.delivery_time, entry.delivery_count),
+            None if options.force => (now, 1),
+            None => return None,
+        };
+        if now.saturating_sub(delivery_time) < options.min_idle {
+            return None;
+        }
+        let delivery_time = options.delivery_time.unwrap_or(now);
+        let delivery_count = match options.retry_count {
+            Some(count) => count,
+            None if options.just_id => delivery_count,
+            None => delivery_count + 1,
+        };
+        self.claim(id, consumer, delivery_time, delivery_count);
+        Some((delivery_time, delivery_count))
+    }
+
+    /// Acknowledges `id`, returning true if it was pending.
+    pub fn ack(&mut self, id: &StreamId) -> bool {
+        let Some(entry) = self.pending.remove(id) else {
+            return false;
+        };
+        if let Some(consumer) = self.consumers.get_mut(&entry.consumer) {
+            consumer.pending.remove(id);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deliver_and_ack_keep_consumer_in_sync() {
+        let mut group = ConsumerGroup::new(StreamId::MIN);
+        let alice = Bytes::from("alice");
+        group.deliver(StreamId::new(1, 1), &alice, 10);
+        group.deliver(StreamId::new(1, 2), &alice, 10);
+        assert_eq!(group.pending().len(), 2);
+        assert_eq!(group.consumers()[&alice].pending.len(), 2);
+
+        assert!(group.ack(&StreamId::new(1, 1)));
+        assert!(!group.ack(&StreamId::new(1, 1)));
+        assert_eq!(group.consumers()[&alice].pending.len(), 1);
+    }
+
+    #[test]
+    fn redelivery_moves_ownership() {
+        let mut group = ConsumerGroup::new(StreamId::MIN);
+        let (alice, bob) = (Bytes::from("alice"), Bytes::from("bob"));
+        group.deliver(StreamId::new(1, 1), &alice, 10);
+        group.deliver(StreamId::new(1, 1), &bob, 20);
+        let entry = &group.pending()[&StreamId::new(1, 1)];
+        assert_eq!(entry.consumer, bob);
+        assert_eq!(entry.delivery_count, 2);
+        assert!(group.consumers()[&alice].pending.is_empty());
+    }
+
+    #[test]
+    fn try_claim_respects_min_idle_and_force() {
+        let mut group = ConsumerGroup::new(StreamId::MIN);
+        let (alice, bob) = (Bytes::from("alice"), Bytes::from("bob"));
+        let id = StreamId::new(1, 1);
+        group.deliver(id, &alice, 100);
+
+        let options = ClaimOptions {
+            min_idle: 50,
+            ..Default::default()
+        };
+        assert_eq!(group.try_claim(id, &bob, &options, 120, true), None);
+        assert_eq!(
+            group.try_claim(id, &bob, &options, 150, true),
+            Some((150, 2))
+        );
+        assert_eq!(group.pending()[&id].consumer, bob);
+
+        let other = StreamId::new(1, 2);
+        assert_eq!(
+            group.try_claim(other, &bob, &Default::default(), 150, true),
+            None
+        );
+        let force = ClaimOptions {
+            force: true,
+            just_id: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            group.try_claim(other, &bob, &force, 150, true),
+            Some((150, 1))
+        );
+
+        // entries deleted from the stream are dropped rather than claimed
+        assert_eq!(group.try_claim(id, &alice, &force, 200, false), None);
+        assert!(!group.pending().contains_key(&id));
+    }
+
+    #[test]
+    fn delete_consumer_drops_its_pending_entries() {
+        let mut group = ConsumerGroup::new(StreamId::MIN);
+        let alice = Bytes::from("alice");
+        group.deliver(StreamId::new(1, 1), &alice, 10);
+        assert_eq!(group.delete_consumer(b"alice"), 1);
+        assert!(group.pending().is_empty());
+        assert!(group.consumers().is_empty());
+        assert_eq!(group.delete_consumer(b"alice"), 0);
+    }
+}
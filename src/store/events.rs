@@ -0,0 +1,114 @@
+//! Hooks called on every change to the keyspace, so whatever reacts to
+//! writes hangs off one place instead of each command calling it.
+//! Keyspace notifications and client-side caching invalidations hang off it.
+
+use bytes::Bytes;
+use std::fmt;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, RwLock};
+
+use super::notify::{EventClass, NotifyFlags};
+use super::pubsub::PubSub;
+
+/// A change to a key, named as its keyspace notification is, e.g. `set`,
+/// `del`, `expired` or `zadd`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event<'a> {
+    pub db: usize,
+    pub class: EventClass,
+    pub name: &'a str,
+    pub key: &'a Bytes,
+    /// The client whose command made the change, if a client's did.
+    pub client: Option<u64>,
+}
+
+type Hook = Box<dyn Fn(&Event) + Send + Sync>;
+
+/// The hooks registered with `Store::on_event`. They run in registration
+/// order on the task that made the change, which may still hold the key's
+/// shard, so they must be quick and mustn't call back into the keyspace.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    hooks: Arc<RwLock<Vec<Hook>>>,
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hooks = self.hooks.read().unwrap().len();
+        f.debug_struct("Hooks").field("hooks", &hooks).finish()
+    }
+}
+
+impl Hooks {
+    pub fn add(&self, hook: impl Fn(&Event) + Send + Sync + 'static) {
+        self.hooks.write().unwrap().push(Box::new(hook));
+    }
+
+    pub(super) fn emit(&self, event: &Event) {
+        for hook in self.hooks.read().unwrap().iter() {
+            hook(event);
+        }
+    }
+}
+
+/// Publishes events on the `__keyspace@<db>__` and `__keyevent@<db>__`
+/// channels, as `notify-keyspace-events` in `flags` enables them.
+pub(super) fn keyspace_notifications(
+    pubsub: PubSub,
+    flags: Arc<AtomicU16>,
+) -> impl Fn(&Event) + Send + Sync {
+    move |event| {
+        let flags = NotifyFlags::from_bits(flags.load(Ordering::Relaxed));
+        if !flags.enabled(event.class) {
+            return;
+        }
+        if flags.keyspace() {
+            let channel = [
+                format!("__keyspace@{}__:", event.db).as_bytes(),
+                &event.key[..],
+            ]
+            .concat();
+            pubsub.publish(
+                &channel.into(),
+                Bytes::copy_from_slice(event.name.as_bytes()),
+            );
+        }
+        if flags.keyevent() {
+            let channel = format!("__keyevent@{}__:{}", event.db, event.name);
+            pubsub.publish(&channel.into(), event.key.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn hooks_see_every_event_in_order() {
+        let hooks = Hooks::default();
+        let seen = Arc::new(Mutex::new(vec![]));
+        for tag in ["first", "second"] {
+            let seen = seen.clone();
+            hooks.add(move |event| {
+                seen.lock()
+                    .unwrap()
+                    .push(format!("{} {} {:?}", tag, event.name, event.key));
+            });
+        }
+
+        let key = Bytes::from("k");
+        hooks.emit(&Event {
+            db: 3,
+            class: EventClass::Generic,
+            name: "del",
+            key: &key,
+            client: None,
+        });
+        assert_eq!(
+            *seen.lock().unwrap(),
+            ["first del b\"k\"", "second del b\"k\""]
+        );
+    }
+}
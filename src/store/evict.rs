@@ -0,0 +1,145 @@
+//! What maxmemory eviction ranks keys by: the policy that chooses between
+//! them, and the access frequency counter LFU policies read.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant};
+
+use super::ValueWithExpiry;
+
+/// The counter a new key starts with, so it isn't evicted before it has had
+/// a chance to be read.
+pub(super) const LFU_INIT_VAL: u8 = 5;
+/// How much harder each increment of the counter gets as it grows.
+const LFU_LOG_FACTOR: f64 = 10.0;
+/// How long a key goes unread before its counter drops by one.
+const LFU_DECAY_TIME: Duration = Duration::from_secs(60);
+
+/// Which keys `maxmemory-policy` lets the server evict, and in what order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Policy {
+    #[default]
+    NoEviction,
+    AllKeysLru,
+    VolatileLru,
+    AllKeysLfu,
+    VolatileLfu,
+    AllKeysRandom,
+    VolatileRandom,
+    VolatileTtl,
+}
+
+const POLICIES: [(&str, Policy); 8] = [
+    ("noeviction", Policy::NoEviction),
+    ("allkeys-lru", Policy::AllKeysLru),
+    ("volatile-lru", Policy::VolatileLru),
+    ("allkeys-lfu", Policy::AllKeysLfu),
+    ("volatile-lfu", Policy::VolatileLfu),
+    ("allkeys-random", Policy::AllKeysRandom),
+    ("volatile-random", Policy::VolatileRandom),
+    ("volatile-ttl", Policy::VolatileTtl),
+];
+
+impl Policy {
+    pub fn parse(name: &str) -> Option<Policy> {
+        POLICIES
+            .iter()
+            .find(|(known, _)| known.eq_ignore_ascii_case(name))
+            .map(|(_, policy)| *policy)
+    }
+
+    pub fn name(self) -> &'static str {
+        POLICIES
+            .iter()
+            .find(|(_, policy)| *policy == self)
+            .map_or("noeviction", |(name, _)| name)
+    }
+
+    /// Whether only keys with a time to live may be evicted.
+    fn is_volatile(self) -> bool {
+        matches!(
+            self,
+            Policy::VolatileLru
+                | Policy::VolatileLfu
+                | Policy::VolatileRandom
+                | Policy::VolatileTtl
+        )
+    }
+
+    /// How good a candidate for eviction `entry` is, higher being better, or
+    /// None if the policy never evicts it.
+    pub(super) fn rank(self, entry: &ValueWithExpiry, now: Instant) -> Option<u64> {
+        if self.is_volatile() && entry.expiry.is_none() {
+            return None;
+        }
+        match self {
+            Policy::NoEviction => None,
+            Policy::AllKeysLru | Policy::VolatileLru => {
+                Some(now.saturating_duration_since(entry.accessed).as_millis() as u64)
+            }
+            Policy::AllKeysLfu | Policy::VolatileLfu => {
+                Some(255 - u64::from(entry.decayed_frequency()))
+            }
+            // ties go to whichever sample came first, itself random
+            Policy::AllKeysRandom | Policy::VolatileRandom => Some(0),
+            Policy::VolatileTtl => entry
+                .expiry
+                .map(|expiry| u64::MAX - expiry.saturating_duration_since(now).as_millis() as u64),
+        }
+    }
+}
+
+/// `counter` less one for every `LFU_DECAY_TIME` of `idle`.
+pub(super) fn lfu_decay(counter: u8, idle: Duration) -> u8 {
+    let periods = idle.as_secs() / LFU_DECAY_TIME.as_secs();
+    counter.saturating_sub(periods.min(u8::MAX as u64) as u8)
+}
+
+/// `counter` after an access: the higher it is, the less likely it grows, so
+/// eight bits can tell a handful of reads from millions.
+pub(super) fn lfu_increment(counter: u8) -> u8 {
+    if counter == u8::MAX {
+        return counter;
+    }
+    let base = counter.saturating_sub(LFU_INIT_VAL) as f64;
+    let odds = 1.0 / (base * LFU_LOG_FACTOR + 1.0);
+    if random_fraction() < odds {
+        counter + 1
+    } else {
+        counter
+    }
+}
+
+/// A random number, from the randomly keyed hasher std seeds for HashMap.
+pub(super) fn random() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+fn random_fraction() -> f64 {
+    (random() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policies_round_trip_through_their_names() {
+        for (name, policy) in POLICIES {
+            assert_eq!(Policy::parse(&name.to_uppercase()), Some(policy));
+            assert_eq!(policy.name(), name);
+        }
+        assert_eq!(Policy::parse("allkeys-fifo"), None);
+    }
+
+    #[test]
+    fn the_lfu_counter_grows_logarithmically_and_decays() {
+        let mut counter = LFU_INIT_VAL;
+        for _ in 0..1000 {
+            counter = lfu_increment(counter);
+        }
+        assert!(counter > LFU_INIT_VAL + 2 && counter < 50, "{}", counter);
+        assert_eq!(lfu_decay(10, Duration::from_secs(150)), 8);
+        assert_eq!(lfu_decay(3, Duration::from_secs(60 * 60)), 0);
+    }
+}
@@ -0,0 +1,105 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// A library loaded with FUNCTION LOAD: its source and the functions it
+/// registered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Library {
+    pub name: String,
+    pub code: Bytes,
+    pub functions: Vec<String>,
+}
+
+/// Function libraries, keyed by library name. Function names are unique
+/// across all libraries.
+#[derive(Debug, Clone, Default)]
+pub struct FunctionLibraries {
+    libraries: Arc<Mutex<BTreeMap<String, Library>>>,
+}
+
+impl FunctionLibraries {
+    /// Adds `library`, replacing one of the same name only when `replace` is set.
+    pub fn load(&self, library: Library, replace: bool) -> Result<(), String> {
+        let mut libraries = self.libraries.lock().unwrap();
+        if !replace && libraries.contains_key(&library.name) {
+            return Err(format!("ERR Library '{}' already exists", library.name));
+        }
+        let taken = libraries
+            .values()
+            .filter(|other| other.name != library.name)
+            .flat_map(|other| &other.functions)
+            .find(|function| library.functions.contains(function));
+        if let Some(function) = taken {
+            return Err(format!("ERR Function {} already exists", function));
+        }
+        libraries.insert(library.name.clone(), library);
+        Ok(())
+    }
+
+    /// The library that registered `function`.
+    pub fn find(&self, function: &str) -> Option<Library> {
+        let libraries = self.libraries.lock().unwrap();
+        libraries
+            .values()
+            .find(|library| library.functions.iter().any(|name| name == function))
+            .cloned()
+    }
+
+    pub fn list(&self) -> Vec<Library> {
+        self.libraries.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn delete(&self, name: &str) -> bool {
+        self.libraries.lock().unwrap().remove(name).is_some()
+    }
+
+    pub fn flush(&self) {
+        self.libraries.lock().unwrap().clear();
+    }
+
+    /// Every library's source, each preceded by its length and a newline.
+    pub fn dump(&self) -> Bytes {
+        let mut payload = BytesMut::new();
+        for library in self.libraries.lock().unwrap().values() {
+            payload.put(format!("{}\n", library.code.len()).as_bytes());
+            payload.put(library.code.clone());
+        }
+        payload.freeze()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library(name: &str, functions: &[&str]) -> Library {
+        Library {
+            name: name.to_string(),
+            code: Bytes::from(format!("#!lua name={}", name)),
+            functions: functions.iter().map(|f| f.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn function_names_are_unique_across_libraries() {
+        let libraries = FunctionLibraries::default();
+        libraries.load(library("a", &["f", "g"]), false).unwrap();
+
+        assert_eq!(
+            libraries.load(library("a", &["h"]), false),
+            Err("ERR Library 'a' already exists".to_string())
+        );
+        assert_eq!(
+            libraries.load(library("b", &["g"]), false),
+            Err("ERR Function g already exists".to_string())
+        );
+        libraries.load(library("a", &["g", "h"]), true).unwrap();
+        assert_eq!(
+            libraries.find("h").map(|library| library.name),
+            Some("a".into())
+        );
+        assert_eq!(libraries.find("f"), None);
+        assert_eq!(libraries.dump(), Bytes::from("12\n#!lua name=a"));
+    }
+}
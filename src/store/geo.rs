@@ -0,0 +1,196 @@
+//! Geospatial indexes, kept as sorted sets whose scores are 52-bit geohashes
+//! as Redis computes them, so members sort by area and the scores match.
+
+use bytes::Bytes;
+
+use super::sorted_set::SortedSet;
+
+const LONG_MIN: f64 = -180.0;
+const LONG_MAX: f64 = 180.0;
+/// The web mercator limits, past which the projection doesn't work.
+const LAT_MIN: f64 = -85.05112878;
+const LAT_MAX: f64 = 85.05112878;
+/// Bits per coordinate; interleaved they make a 52-bit hash, which a score
+/// holds exactly.
+const STEP: u32 = 26;
+
+const EARTH_RADIUS: f64 = 6372797.560856;
+
+pub const ERR_UNIT: &str = "ERR unsupported unit provided. please use M, KM, FT, MI";
+
+/// A longitude and latitude, in degrees.
+pub type Point = (f64, f64);
+
+pub fn is_valid(point: Point) -> bool {
+    (LONG_MIN..=LONG_MAX).contains(&point.0) && (LAT_MIN..=LAT_MAX).contains(&point.1)
+}
+
+/// Metres per `unit`, one of m, km, ft or mi.
+pub fn unit(unit: &str) -> Option<f64> {
+    match unit.to_lowercase().as_str() {
+        "m" => Some(1.0),
+        "km" => Some(1000.0),
+        "ft" => Some(0.3048),
+        "mi" => Some(1609.34),
+        _ => None,
+    }
+}
+
+/// The geohash of `point`, to be stored as its score. The latitude takes
+/// the even bits and the longitude the odd ones.
+pub fn encode(point: Point) -> u64 {
+    let scale = |value: f64, min: f64, max: f64| {
+        ((value - min) / (max - min) * (1u64 << STEP) as f64) as u32
+    };
+    let longitude = scale(point.0, LONG_MIN, LONG_MAX);
+    let latitude = scale(point.1, LAT_MIN, LAT_MAX);
+    spread(latitude) | spread(longitude) << 1
+}
+
+/// The centre of the area `hash` stands for.
+pub fn decode(hash: u64) -> Point {
+    let centre = |cell: u64, min: f64, max: f64| {
+        let cells = (1u64 << STEP) as f64;
+        let low = min + (cell as f64 / cells) * (max - min);
+        let high = min + ((cell + 1) as f64 / cells) * (max - min);
+        ((low + high) / 2.0).clamp(min, max)
+    };
+    (
+        centre(squash(hash >> 1), LONG_MIN, LONG_MAX),
+        centre(squash(hash), LAT_MIN, LAT_MAX),
+    )
+}
+
+/// `bits` moved to the even bits.
+fn spread(bits: u32) -> u64 {
+    (0..STEP).fold(0, |hash, i| hash | u64::from(bits >> i & 1) << (2 * i))
+}
+
+/// The even bits of `hash`, packed.
+fn squash(hash: u64) -> u64 {
+    (0..STEP).fold(0, |bits, i| bits | (hash >> (2 * i) & 1) << i)
+}
+
+/// The great circle distance in metres between `from` and `to`.
+pub fn distance(from: Point, to: Point) -> f64 {
+    let (lon1, lat1) = (from.0.to_radians(), from.1.to_radians());
+    let (lon2, lat2) = (to.0.to_radians(), to.1.to_radians());
+    let v = ((lon2 - lon1) / 2.0).sin();
+    if v == 0.0 {
+        return lat_distance(from.1, to.1);
+    }
+    let u = ((lat2 - lat1) / 2.0).sin();
+    let a = u * u + lat1.cos() * lat2.cos() * v * v;
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+fn lat_distance(from: f64, to: f64) -> f64 {
+    EARTH_RADIUS * (to.to_radians() - from.to_radians()).abs()
+}
+
+/// The area a search covers around its centre, in metres.
+#[derive(Debug, Clone, Copy)]
+pub enum Shape {
+    Radius(f64),
+    Box { width: f64, height: f64 },
+}
+
+impl Shape {
+    /// How far `point` is from `centre`, if it's inside the shape.
+    fn distance(&self, centre: Point, point: Point) -> Option<f64> {
+        match *self {
+            Shape::Radius(radius) => Some(distance(centre, point)).filter(|d| *d <= radius),
+            Shape::Box { width, height } => {
+                if lat_distance(point.1, centre.1) > height / 2.0
+                    || distance(point, (centre.0, point.1)) > width / 2.0
+                {
+                    return None;
+                }
+                Some(distance(centre, point))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Found {
+    pub member: Bytes,
+    pub hash: u64,
+    pub point: Point,
+    /// From the centre, in metres.
+    pub distance: f64,
+}
+
+/// The members of `zset` inside `shape` around `centre`, in score order,
+/// stopping at the first `limit` found.
+pub fn search(zset: &SortedSet, centre: Point, shape: Shape, limit: Option<usize>) -> Vec<Found> {
+    zset.iter()
+        .filter_map(|(member, score)| {
+            let hash = score as u64;
+            let point = decode(hash);
+            shape.distance(centre, point).map(|distance| Found {
+                member: member.clone(),
+                hash,
+                point,
+                distance,
+            })
+        })
+        .take(limit.unwrap_or(usize::MAX))
+        .collect()
+}
+
+/// Formats a coordinate the way Redis replies with it: to 17 places, less
+/// trailing zeros.
+pub fn format_coordinate(value: f64) -> String {
+    let formatted = format!("{:.17}", value);
+    formatted
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PALERMO: Point = (13.361389, 38.115556);
+    const CATANIA: Point = (15.087269, 37.502669);
+
+    #[test]
+    fn hashes_match_redis() {
+        assert_eq!(encode(PALERMO), 3479099956230698);
+        assert_eq!(encode(CATANIA), 3479447370796909);
+        let (longitude, latitude) = decode(encode(PALERMO));
+        assert_eq!(format_coordinate(longitude), "13.36138933897018433");
+        assert_eq!(format_coordinate(latitude), "38.11555639549629859");
+    }
+
+    #[test]
+    fn distances_match_redis() {
+        let between = distance(decode(encode(PALERMO)), decode(encode(CATANIA)));
+        assert_eq!(format!("{:.4}", between), "166274.1516");
+        assert_eq!(format!("{:.4}", between / unit("KM").unwrap()), "166.2742");
+        assert_eq!(unit("yd"), None);
+    }
+
+    #[test]
+    fn searches_keep_to_their_shape() {
+        let mut zset = SortedSet::new();
+        zset.insert("Palermo".into(), encode(PALERMO) as f64);
+        zset.insert("Catania".into(), encode(CATANIA) as f64);
+        let members = |shape| -> Vec<Bytes> {
+            search(&zset, (15.0, 37.0), shape, None)
+                .into_iter()
+                .map(|found| found.member)
+                .collect()
+        };
+        assert_eq!(members(Shape::Radius(100_000.0)), ["Catania"]);
+        assert_eq!(members(Shape::Radius(200_000.0)), ["Palermo", "Catania"]);
+        let square = Shape::Box {
+            width: 200_000.0,
+            height: 200_000.0,
+        };
+        assert_eq!(members(square), ["Catania"]);
+        assert!(!is_valid((181.0, 0.0)) && !is_valid((0.0, 86.0)));
+    }
+}
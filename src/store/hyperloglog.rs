@@ -0,0 +1,313 @@
+//! HyperLogLogs laid out as Redis lays them out: strings holding a header
+//! and 16384 six-bit registers, hashed and estimated the same way, so the
+//! counts match Redis's and the values survive a trip through an RDB file.
+//! New HLLs always use the dense encoding; sparse ones, which Redis writes
+//! for small sets, are read and turned dense on the first PFADD.
+
+use bytes::{Bytes, BytesMut};
+use std::fmt;
+
+const MAGIC: &[u8] = b"HYLL";
+const DENSE: u8 = 0;
+const SPARSE: u8 = 1;
+const HEADER: usize = 16;
+
+/// Bits of the hash that pick a register.
+const P: u32 = 14;
+/// Bits of the hash left to count leading zeros in.
+const Q: u32 = 64 - P;
+pub const REGISTERS: usize = 1 << P;
+const REGISTER_BITS: usize = 6;
+const REGISTER_MAX: u16 = (1 << REGISTER_BITS) - 1;
+const DENSE_SIZE: usize = HEADER + (REGISTERS * REGISTER_BITS).div_ceil(8);
+
+const SEED: u64 = 0xadc83b19;
+const ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+
+/// Why a string can't be used as an HLL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Invalid {
+    NotAnHll,
+    Corrupted,
+}
+
+impl fmt::Display for Invalid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Invalid::NotAnHll => "WRONGTYPE Key is not a valid HyperLogLog string value.".fmt(f),
+            Invalid::Corrupted => "INVALIDOBJ Corrupted HLL object detected".fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Invalid {}
+
+/// The encoding byte of `value`, if it has an HLL header.
+fn encoding(value: &[u8]) -> Result<u8, Invalid> {
+    if value.len() < HEADER || &value[..4] != MAGIC {
+        return Err(Invalid::NotAnHll);
+    }
+    match value[4] {
+        DENSE if value.len() == DENSE_SIZE => Ok(DENSE),
+        SPARSE => Ok(SPARSE),
+        _ => Err(Invalid::NotAnHll),
+    }
+}
+
+/// An HLL with every register 0.
+fn empty() -> BytesMut {
+    let mut value = BytesMut::zeroed(DENSE_SIZE);
+    value[..4].copy_from_slice(MAGIC);
+    value[4] = DENSE;
+    value
+}
+
+/// The register `element` lands in, and the run of zeros its hash counts.
+fn hash(element: &[u8]) -> (usize, u8) {
+    let hash = murmur_hash64a(element, SEED);
+    let index = (hash & (REGISTERS as u64 - 1)) as usize;
+    // the extra bit caps the count at Q + 1
+    let rest = (hash >> P) | (1 << Q);
+    (index, rest.trailing_zeros() as u8 + 1)
+}
+
+/// MurmurHash64A, as Redis hashes HLL elements.
+fn murmur_hash64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4a7935bd1e995;
+    const R: u32 = 47;
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+    let mut chunks = key.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().expect("eight bytes"));
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, byte) in tail.iter().enumerate() {
+            h ^= u64::from(*byte) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+/// Register `index` of packed `registers`, which run from the least
+/// significant bit of each byte.
+fn get_register(registers: &[u8], index: usize) -> u8 {
+    let bit = index * REGISTER_BITS;
+    let (byte, shift) = (bit / 8, bit % 8);
+    let window =
+        u16::from(registers[byte]) | u16::from(registers.get(byte + 1).copied().unwrap_or(0)) << 8;
+    ((window >> shift) & REGISTER_MAX) as u8
+}
+
+fn set_register(registers: &mut [u8], index: usize, value: u8) {
+    let bit = index * REGISTER_BITS;
+    let (byte, shift) = (bit / 8, bit % 8);
+    let next = registers.get(byte + 1).copied().unwrap_or(0);
+    let mut window = u16::from(registers[byte]) | u16::from(next) << 8;
+    window &= !(REGISTER_MAX << shift);
+    window |= u16::from(value) << shift;
+    registers[byte] = window as u8;
+    if let Some(next) = registers.get_mut(byte + 1) {
+        *next = (window >> 8) as u8;
+    }
+}
+
+/// Every register of the HLL in `value`, one per byte.
+pub fn registers(value: &[u8]) -> Result<Vec<u8>, Invalid> {
+    let packed = &value[HEADER.min(value.len())..];
+    match encoding(value)? {
+        DENSE => Ok((0..REGISTERS).map(|i| get_register(packed, i)).collect()),
+        _ => sparse_registers(packed),
+    }
+}
+
+/// Decodes the sparse encoding's runs: of zeros, of up to 64 or 16384 of
+/// them, or of up to 4 registers sharing a value below 33.
+fn sparse_registers(mut ops: &[u8]) -> Result<Vec<u8>, Invalid> {
+    let mut registers = Vec::with_capacity(REGISTERS);
+    while let Some(&op) = ops.first() {
+        let (value, run, len) = match op >> 6 {
+            0b00 => (0, usize::from(op & 0x3f) + 1, 1),
+            0b01 => {
+                let low = *ops.get(1).ok_or(Invalid::Corrupted)?;
+                (0, (usize::from(op & 0x3f) << 8 | usize::from(low)) + 1, 2)
+            }
+            _ => (((op >> 2) & 0x1f) + 1, usize::from(op & 0x03) + 1, 1),
+        };
+        if registers.len() + run > REGISTERS {
+            return Err(Invalid::Corrupted);
+        }
+        registers.resize(registers.len() + run, value);
+        ops = &ops[len..];
+    }
+    if registers.len() != REGISTERS {
+        return Err(Invalid::Corrupted);
+    }
+    Ok(registers)
+}
+
+/// The registers of the union of the HLLs in `values`: the highest of
+/// each. Empty values, which stand for missing keys, are skipped.
+pub fn merge(values: &[&[u8]]) -> Result<Vec<u8>, Invalid> {
+    let mut union = vec![0; REGISTERS];
+    for value in values.iter().filter(|value| !value.is_empty()) {
+        for (merged, register) in union.iter_mut().zip(registers(value)?) {
+            *merged = (*merged).max(register);
+        }
+    }
+    Ok(union)
+}
+
+/// `registers` packed into a dense HLL, its cached count unset.
+pub fn dense(registers: &[u8]) -> Bytes {
+    let mut value = empty();
+    for (index, register) in registers.iter().enumerate() {
+        set_register(&mut value[HEADER..], index, *register);
+    }
+    invalidate_count(&mut value);
+    value.freeze()
+}
+
+/// Adds `elements` to the HLL in `value`, which is created if empty.
+/// Returns whether that changed the estimate's inputs.
+pub fn add(value: &mut BytesMut, elements: &[Bytes]) -> Result<bool, Invalid> {
+    let created = value.is_empty();
+    if created {
+        *value = empty();
+    } else if encoding(value)? == SPARSE {
+        let registers = registers(value)?;
+        *value = BytesMut::from(&dense(&registers)[..]);
+    }
+    let mut changed = false;
+    for element in elements {
+        let (index, count) = hash(element);
+        if get_register(&value[HEADER..], index) < count {
+            set_register(&mut value[HEADER..], index, count);
+            changed = true;
+        }
+    }
+    if changed {
+        invalidate_count(value);
+    }
+    Ok(created || changed)
+}
+
+/// The estimate stored in the header by whoever last counted the HLL, if
+/// it hasn't changed since.
+pub fn cached_count(value: &[u8]) -> Option<u64> {
+    encoding(value).ok()?;
+    let cache = u64::from_le_bytes(value[8..16].try_into().expect("eight bytes"));
+    (cache >> 63 == 0).then_some(cache)
+}
+
+fn invalidate_count(value: &mut [u8]) {
+    value[15] |= 0x80;
+}
+
+/// The number of distinct elements `registers` have seen, by Ertl's
+/// improved estimator as Redis uses it.
+pub fn count(registers: &[u8]) -> u64 {
+    let m = REGISTERS as f64;
+    let mut histogram = [0u32; 1 << REGISTER_BITS];
+    for register in registers {
+        histogram[usize::from(*register)] += 1;
+    }
+    let mut z = m * tau((m - f64::from(histogram[Q as usize + 1])) / m);
+    for j in (1..=Q as usize).rev() {
+        z += f64::from(histogram[j]);
+        z *= 0.5;
+    }
+    z += m * sigma(f64::from(histogram[0]) / m);
+    (ALPHA_INF * m * m / z).round() as u64
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let (mut y, mut z) = (1.0, x);
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if z == previous {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let (mut y, mut z) = (1.0, 1.0 - x);
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if z == previous {
+            return z / 3.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registers_round_trip_through_the_dense_encoding() {
+        let registers: Vec<u8> = (0..REGISTERS).map(|i| (i % 64) as u8).collect();
+        let value = dense(&registers);
+        assert_eq!(value.len(), DENSE_SIZE);
+        assert_eq!(super::registers(&value), Ok(registers));
+        assert_eq!(cached_count(&value), None);
+    }
+
+    #[test]
+    fn estimates_stay_close_to_the_true_count() {
+        let mut value = BytesMut::new();
+        assert_eq!(add(&mut value, &[]), Ok(true));
+        assert_eq!(cached_count(&value), Some(0));
+        let elements: Vec<Bytes> = (0..10_000).map(|i| format!("e{}", i).into()).collect();
+        assert_eq!(add(&mut value, &elements), Ok(true));
+        assert_eq!(add(&mut value, &elements[..10]), Ok(false));
+        let estimate = count(&registers(&value).unwrap());
+        assert!((9_800..=10_200).contains(&estimate), "{}", estimate);
+    }
+
+    #[test]
+    fn reads_the_sparse_encoding() {
+        let mut value = b"HYLL\x01\0\0\0\0\0\0\0\0\0\0\0".to_vec();
+        // a run of one register at 3, then a run of 16383 zeros
+        value.extend([0x88, 0x7f, 0xfe]);
+        let registers = registers(&value).unwrap();
+        assert_eq!((registers[0], registers[1..].iter().max()), (3, Some(&0)));
+
+        value.push(0);
+        assert_eq!(super::registers(&value), Err(Invalid::Corrupted));
+        assert_eq!(super::registers(b"HYLL"), Err(Invalid::NotAnHll));
+    }
+
+    #[test]
+    fn small_sets_count_exactly() {
+        let mut value = BytesMut::new();
+        let elements: Vec<Bytes> = ["a", "b", "c", "d", "e", "f", "g"]
+            .into_iter()
+            .map(Bytes::from)
+            .collect();
+        add(&mut value, &elements).unwrap();
+        assert_eq!(count(&registers(&value).unwrap()), 7);
+    }
+}
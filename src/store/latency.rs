@@ -0,0 +1,144 @@
+//! The latency monitor: spikes over `latency-monitor-threshold`, kept per
+//! kind of event for LATENCY to report.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A command flagged `fast` that was slow anyway.
+pub const FAST_COMMAND: &str = "fast-command";
+/// Any other command.
+pub const COMMAND: &str = "command";
+/// A pass of the active expiry over the keyspace.
+pub const EXPIRE_CYCLE: &str = "expire-cycle";
+/// Copying the keyspace for a background save, which Redis does by forking.
+pub const FORK: &str = "fork";
+
+/// How many samples each event keeps, as Redis does.
+pub const HISTORY_LEN: usize = 160;
+
+/// One spike: when it happened, in Unix seconds, and how long it took in
+/// milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    pub time: u64,
+    pub latency: u64,
+}
+
+/// The spikes of one event, oldest first, along with the worst ever seen.
+#[derive(Debug, Clone, Default)]
+pub struct Series {
+    pub history: VecDeque<Sample>,
+    pub max: u64,
+}
+
+impl Series {
+    pub fn latest(&self) -> Option<Sample> {
+        self.history.back().copied()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Latency {
+    /// In milliseconds; 0 turns the monitor off.
+    threshold: Arc<AtomicU64>,
+    events: Arc<Mutex<BTreeMap<&'static str, Series>>>,
+}
+
+impl Latency {
+    pub fn threshold(&self) -> u64 {
+        self.threshold.load(Ordering::Relaxed)
+    }
+
+    pub fn set_threshold(&self, ms: u64) {
+        self.threshold.store(ms, Ordering::Relaxed);
+    }
+
+    /// Records that `event` took `took`, if that reaches the threshold.
+    pub fn record(&self, event: &'static str, took: Duration) {
+        let threshold = self.threshold();
+        let latency = took.as_millis().try_into().unwrap_or(u64::MAX);
+        if threshold == 0 || latency < threshold {
+            return;
+        }
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut events = self.events.lock().unwrap();
+        let series = events.entry(event).or_default();
+        series.max = series.max.max(latency);
+        // spikes within the same second are one sample, the worst of them
+        match series.history.back_mut() {
+            Some(last) if last.time == time => last.latency = last.latency.max(latency),
+            _ => {
+                if series.history.len() == HISTORY_LEN {
+                    series.history.pop_front();
+                }
+                series.history.push_back(Sample { time, latency });
+            }
+        }
+    }
+
+    /// Every event with a spike, by name.
+    pub fn events(&self) -> Vec<(&'static str, Series)> {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(event, series)| (*event, series.clone()))
+            .collect()
+    }
+
+    pub fn history(&self, event: &str) -> Vec<Sample> {
+        self.events
+            .lock()
+            .unwrap()
+            .get(event)
+            .map(|series| series.history.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Forgets the spikes of `events`, or of every event if none are named,
+    /// returning how many had any.
+    pub fn reset(&self, events: &[String]) -> usize {
+        let mut recorded = self.events.lock().unwrap();
+        if events.is_empty() {
+            let reset = recorded.len();
+            recorded.clear();
+            return reset;
+        }
+        events
+            .iter()
+            .filter(|event| recorded.remove(event.as_str()).is_some())
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spikes_under_the_threshold_are_ignored() {
+        let latency = Latency::default();
+        latency.record(COMMAND, Duration::from_secs(1));
+        assert!(latency.events().is_empty());
+
+        latency.set_threshold(100);
+        latency.record(COMMAND, Duration::from_millis(99));
+        latency.record(COMMAND, Duration::from_millis(150));
+        latency.record(COMMAND, Duration::from_millis(120));
+        let history = latency.history(COMMAND);
+        // spikes in the same second are merged, unless they straddle one
+        assert!(!history.is_empty() && history.len() <= 2);
+        assert_eq!(history[0].latency, 150);
+        assert_eq!(latency.events()[0].1.max, 150);
+
+        latency.record(FORK, Duration::from_millis(100));
+        assert_eq!(latency.reset(&["fork".to_string(), "nope".to_string()]), 1);
+        assert_eq!(latency.reset(&[]), 1);
+        assert!(latency.events().is_empty());
+    }
+}
@@ -0,0 +1,176 @@
+//! Approximate memory accounting, sized after what a 64-bit Redis allocates
+//! for each key and value. MEMORY USAGE, MEMORY STATS and INFO memory all
+//! report these figures.
+
+use bytes::Bytes;
+
+use super::{stream::StreamId, Value};
+
+/// A hash table entry: key and value pointers plus the chain pointer.
+const DICT_ENTRY: usize = 24;
+/// The object header every value is wrapped in.
+const OBJECT: usize = 16;
+/// A sorted set's dict and skiplist headers.
+const ZSET: usize = 96;
+/// A skiplist node with an average number of levels, next to its dict entry.
+const ZSET_NODE: usize = 56;
+/// A stream's header and radix tree.
+const STREAM: usize = 64;
+/// A consumer group, consumer or pending entry and its tree node.
+const STREAM_NODE: usize = 48;
+
+/// How many elements MEMORY USAGE looks at unless told otherwise.
+pub const DEFAULT_SAMPLES: usize = 5;
+
+/// A string with its length header and terminator.
+fn sds(len: usize) -> usize {
+    len + 4
+}
+
+/// The bytes a key takes in its database, the value included. Collections
+/// are sized from their first `samples` elements, or all of them for 0.
+pub fn key_usage(key: &Bytes, value: &Value, samples: usize) -> usize {
+    DICT_ENTRY + sds(key.len()) + value_usage(value, samples)
+}
+
+/// The bytes a database's keys take, kept up to date as keys are written
+/// rather than summed when asked for. Values are sized as MEMORY USAGE sizes
+/// them by default, from a few of their elements, so that keeping count
+/// costs a write the same however large the value. Expired keys count until
+/// they are removed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub keys: usize,
+    /// The main table's entries, keys included.
+    pub main: usize,
+    /// The expires table's entries.
+    pub expires: usize,
+    /// The values, without the tables that index them.
+    pub dataset: usize,
+}
+
+impl Usage {
+    /// What a key takes, with an expires table entry if it has a time to
+    /// live: what removing it frees.
+    pub(super) fn of(key: &[u8], value: &Value, expires: bool) -> Usage {
+        Usage {
+            keys: 1,
+            main: DICT_ENTRY + sds(key.len()),
+            expires: if expires { DICT_ENTRY } else { 0 },
+            dataset: value_usage(value, DEFAULT_SAMPLES),
+        }
+    }
+
+    pub(super) fn add(&mut self, other: &Usage) {
+        self.keys += other.keys;
+        self.main += other.main;
+        self.expires += other.expires;
+        self.dataset += other.dataset;
+    }
+
+    pub(super) fn remove(&mut self, other: &Usage) {
+        self.keys -= other.keys;
+        self.main -= other.main;
+        self.expires -= other.expires;
+        self.dataset -= other.dataset;
+    }
+
+    pub fn total(&self) -> usize {
+        self.main + self.expires + self.dataset
+    }
+}
+
+fn value_usage(value: &Value, samples: usize) -> usize {
+    match value {
+        Value::String(_) if value.encoding() == "int" => OBJECT,
+        Value::String(s) if value.encoding() == "embstr" => OBJECT + sds(s.len()),
+        Value::String(s) => OBJECT + sds(s.len()).next_power_of_two(),
+        Value::SortedSet(zset) => {
+            // a listpack packs each member next to its score
+            let entry: fn(&Bytes) -> usize = if value.encoding() == "listpack" {
+                |member| member.len() + 11
+            } else {
+                |member| ZSET_NODE + sds(member.len())
+            };
+            OBJECT
+                + ZSET
+                + sampled(
+                    zset.iter().map(|(member, _)| entry(member)),
+                    zset.len(),
+                    samples,
+                )
+        }
+        Value::Stream(stream) => {
+            let entries = sampled(
+                stream
+                    .range(StreamId::MIN, StreamId::MAX)
+                    .map(|(_, fields)| {
+                        16 + fields
+                            .iter()
+                            .map(|(field, value)| field.len() + value.len() + 2)
+                            .sum::<usize>()
+                    }),
+                stream.len(),
+                samples,
+            );
+            let groups: usize = stream
+                .groups()
+                .iter()
+                .map(|(name, group)| {
+                    let consumers: usize = group
+                        .consumers()
+                        .keys()
+                        .map(|name| STREAM_NODE + sds(name.len()))
+                        .sum();
+                    STREAM_NODE + sds(name.len()) + consumers + group.pending().len() * STREAM_NODE
+                })
+                .sum();
+            OBJECT + STREAM + entries + groups
+        }
+    }
+}
+
+/// The total of `sizes`, estimated from the first `samples` of its `len`
+/// elements when that's fewer than all of them.
+fn sampled(sizes: impl Iterator<Item = usize>, len: usize, samples: usize) -> usize {
+    if samples == 0 || samples >= len {
+        return sizes.sum();
+    }
+    let seen: usize = sizes.take(samples).sum();
+    seen * len / samples
+}
+
+/// What MEMORY STATS reports, from the counts the shards keep.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStats {
+    pub keys: usize,
+    /// The values, without the tables that index them.
+    pub dataset: usize,
+    /// Per database that holds keys: its index and the bytes its main and
+    /// expires tables take.
+    pub dbs: Vec<(usize, usize, usize)>,
+}
+
+impl MemoryStats {
+    /// Sums each database's usage, listing those that hold keys.
+    pub(super) fn new(usage: &[Usage]) -> MemoryStats {
+        let mut stats = MemoryStats::default();
+        for (db, usage) in usage.iter().enumerate().filter(|(_, usage)| usage.keys > 0) {
+            stats.keys += usage.keys;
+            stats.dataset += usage.dataset;
+            stats.dbs.push((db, usage.main, usage.expires));
+        }
+        stats
+    }
+
+    pub fn overhead(&self) -> usize {
+        self.dbs
+            .iter()
+            .map(|(_, main, expires)| main + expires)
+            .sum()
+    }
+
+    pub fn total(&self) -> usize {
+        self.overhead() + self.dataset
+    }
+}
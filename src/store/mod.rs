@@ -0,0 +1,1526 @@
+//! The keyspace and the server state around it.
+//!
+//! Locking contract: the keyspace lives in shards behind `std::sync::Mutex`,
+//! which must never be held across an `.await` or while doing work that
+//! grows with the size of the data set.
+//!
+//! - Short operations (a command on a few keys) lock the shards they touch,
+//!   do their work and release them before returning. They are plain `fn`s.
+//! - Long operations (RDB encoding for SAVE, BGSAVE and full resyncs, AOF
+//!   rewrites, loading an RDB) take a `snapshot` under the locks, which
+//!   shares values rather than copying them, then do the heavy lifting on
+//!   `spawn_blocking`. They are `async fn`s, or spawn a task and report
+//!   back through `Persistence`.
+//! - Work that must see every shard at once locks them all in index order,
+//!   see `shards::Shards::lock_all`.
+
+use anyhow::{bail, Context};
+use bytes::{Bytes, BytesMut};
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock};
+
+use crate::{
+    error::Error,
+    frame::Frame,
+    publisher::{Action, ReplicaSet},
+    rdb,
+    shutdown::Shutdown,
+    tls,
+};
+
+pub mod acl;
+use acl::{Acl, DEFAULT_USER};
+pub mod blocking;
+pub mod client_state;
+pub mod clients;
+use blocking::{WaitGuard, Waiters};
+use client_state::{ClientState, ReplyMode};
+use clients::Clients;
+pub mod cluster;
+use cluster::Cluster;
+pub mod aof;
+use aof::Aof;
+pub mod config;
+use config::Config;
+pub mod consumer_group;
+pub mod events;
+pub mod evict;
+use events::{Event, Hooks};
+pub mod memory;
+pub mod notify;
+pub mod output;
+pub mod persistence;
+use memory::{MemoryStats, Usage};
+use notify::{EventClass, NotifyFlags};
+use persistence::Persistence;
+pub mod pubsub;
+use pubsub::PubSub;
+pub mod role;
+use role::RoleState;
+mod scan;
+pub mod scripts;
+mod shards;
+use scripts::ScriptCache;
+use shards::{Db, KeyShards, Shards};
+pub mod sentinel;
+use sentinel::Sentinel;
+pub mod functions;
+pub mod geo;
+pub mod hyperloglog;
+use functions::FunctionLibraries;
+pub mod sorted_set;
+use sorted_set::SortedSet;
+pub mod stats;
+use stats::{DbStats, Stats, TypeStats};
+pub mod latency;
+use latency::Latency;
+pub mod stream;
+use stream::Stream;
+pub mod tracking;
+use tracking::Tracking;
+pub mod watch;
+use watch::{WatchedKeys, Watchers};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(Bytes),
+    SortedSet(SortedSet),
+    Stream(Stream),
+}
+
+impl Value {
+    /// The encoding Redis would hold the value in, as OBJECT ENCODING names it.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Value::String(s) if s.len() <= 20 && is_integer(s) => "int",
+            Value::String(s) if s.len() <= 44 => "embstr",
+            Value::String(_) => "raw",
+            Value::SortedSet(zset)
+                if zset.len() <= 128 && zset.iter().all(|(member, _)| member.len() <= 64) =>
+            {
+                "listpack"
+            }
+            Value::SortedSet(_) => "skiplist",
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    /// What the value is called by SCAN's TYPE option, one of `stats::TYPES`.
+    pub fn type_name(&self) -> &'static str {
+        stats::TYPES[stats::type_index(self)]
+    }
+
+    /// Bytes in a string, members in a sorted set or entries in a stream.
+    pub fn size(&self) -> usize {
+        match self {
+            Value::String(s) => s.len(),
+            Value::SortedSet(zset) => zset.len(),
+            Value::Stream(stream) => stream.len(),
+        }
+    }
+}
+
+/// Whether `s` is an integer written the one way Redis would write it.
+fn is_integer(s: &[u8]) -> bool {
+    std::str::from_utf8(s)
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok().filter(|n| n.to_string() == s))
+        .is_some()
+}
+
+#[derive(Debug)]
+struct ValueWithExpiry {
+    /// Shared with any snapshot taken since the last write, which copies it
+    /// on write, see `value_mut`.
+    value: Arc<Value>,
+    expiry: Option<Instant>,
+    /// When a client last read or wrote the key, for its idle time.
+    accessed: Instant,
+    /// How often the key is accessed, on the logarithmic scale of
+    /// `evict::lfu_increment`.
+    frequency: u8,
+    /// Stamped afresh whenever the value is set or changed, see
+    /// `Store::version`.
+    version: u64,
+}
+
+/// The last version stamped on a value. Versions are unique across every
+/// store in the process, so a replaced key never gets its old one back.
+static VERSION: AtomicU64 = AtomicU64::new(0);
+
+fn next_version() -> u64 {
+    VERSION.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+impl ValueWithExpiry {
+    /// The memory the entry takes at `key`, see `memory::Usage`.
+    fn usage(&self, key: &[u8]) -> Usage {
+        Usage::of(key, &self.value, self.expiry.is_some())
+    }
+
+    fn new(value: Value, expiry: Option<Instant>) -> Self {
+        Self {
+            value: Arc::new(value),
+            expiry,
+            accessed: Instant::now(),
+            frequency: evict::LFU_INIT_VAL,
+            version: next_version(),
+        }
+    }
+
+    /// The value, to change in place: copied first if a snapshot still
+    /// shares it.
+    fn value_mut(&mut self) -> &mut Value {
+        self.version = next_version();
+        Arc::make_mut(&mut self.value)
+    }
+
+    /// Records an access, for the LRU and LFU eviction policies.
+    fn touch(&mut self) {
+        self.frequency = evict::lfu_increment(self.decayed_frequency());
+        self.accessed = Instant::now();
+    }
+
+    /// The access counter less what it has decayed by since the last access.
+    fn decayed_frequency(&self) -> u8 {
+        evict::lfu_decay(self.frequency, self.accessed.elapsed())
+    }
+
+    fn is_expired(&self) -> bool {
+        self.expiry.is_some_and(|expiry| Instant::now() >= expiry)
+    }
+}
+
+/// A key in a given database.
+type DbKey = (usize, Bytes);
+
+/// How many logical databases SELECT can choose between.
+pub const DATABASES: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct Store {
+    data: Arc<Shards>,
+    /// The selected database, user and the like. Each client has its own,
+    /// see `for_client`.
+    client: Arc<ClientState>,
+    waiters: Waiters,
+    pubsub: PubSub,
+    tracking: Tracking,
+    watchers: Watchers,
+    scripts: ScriptCache,
+    functions: FunctionLibraries,
+    notify_flags: Arc<AtomicU16>,
+    hooks: Hooks,
+    config: Config,
+    persistence: Persistence,
+    aof: Aof,
+    stats: Stats,
+    latency: Latency,
+    /// Whether expired keys are swept out in the background, see
+    /// `run_active_expiry`.
+    active_expire: Arc<AtomicBool>,
+    /// How long a replica waits on its master, in milliseconds.
+    repl_timeout: Arc<AtomicU64>,
+    /// Set when the link to a master runs over TLS.
+    replication_tls: Arc<Mutex<Option<tls::Connector>>>,
+    replicas: ReplicaSet,
+    role: RoleState,
+    acl: Acl,
+    cluster: Cluster,
+    sentinel: Sentinel,
+    clients: Clients,
+    /// Held shared by every client command and exclusively by EXEC.
+    exec_lock: Arc<RwLock<()>>,
+    /// Tells the server and its connections to stop, see `begin_shutdown`.
+    shutdown: broadcast::Sender<()>,
+    shutting_down: Arc<AtomicBool>,
+}
+
+/// Returned when a typed operation targets a key holding another kind of value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WrongType;
+
+impl fmt::Display for WrongType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Error::WrongType.fmt(f)
+    }
+}
+
+impl std::error::Error for WrongType {}
+
+pub const DEFAULT_EXPIRY: u64 = 1000 * 60 * 60 * 24 * 7; // 1 week
+
+pub const DEFAULT_REPL_TIMEOUT: Duration = Duration::from_secs(60);
+
+impl Default for Store {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Store {
+    pub fn new() -> Self {
+        let pubsub = PubSub::default();
+        let notify_flags = Arc::new(AtomicU16::default());
+        let hooks = Hooks::default();
+        hooks.add(events::keyspace_notifications(
+            pubsub.clone(),
+            notify_flags.clone(),
+        ));
+        let tracking = Tracking::default();
+        hooks.add(tracking::invalidations(tracking.clone()));
+        Self {
+            data: Arc::new(Shards::new()),
+            client: Arc::new(ClientState::new(Some(DEFAULT_USER.to_string()))),
+            waiters: Default::default(),
+            pubsub,
+            tracking,
+            watchers: Default::default(),
+            scripts: Default::default(),
+            functions: Default::default(),
+            notify_flags,
+            hooks,
+            config: Default::default(),
+            persistence: Default::default(),
+            aof: Default::default(),
+            stats: Default::default(),
+            latency: Default::default(),
+            active_expire: Arc::new(AtomicBool::new(true)),
+            repl_timeout: Arc::new(AtomicU64::new(DEFAULT_REPL_TIMEOUT.as_millis() as u64)),
+            replication_tls: Default::default(),
+            replicas: Default::default(),
+            role: Default::default(),
+            acl: Default::default(),
+            cluster: Default::default(),
+            sentinel: Default::default(),
+            clients: Default::default(),
+            exec_lock: Default::default(),
+            shutdown: broadcast::channel(1).0,
+            shutting_down: Default::default(),
+        }
+    }
+
+    /// A handle on the same data for one client, starting out on database 0
+    /// as the default user, unless that user needs a password.
+    pub fn for_client(&self) -> Store {
+        Store {
+            client: Arc::new(ClientState::new(self.initial_user())),
+            ..self.clone()
+        }
+    }
+
+    /// Who a client is logged in as when it connects: the default user, unless
+    /// that user needs a password.
+    fn initial_user(&self) -> Option<String> {
+        self.acl.is_open().then(|| DEFAULT_USER.to_string())
+    }
+
+    /// Puts this handle's client state back as it was when the client
+    /// connected, as RESET does. The client keeps its id.
+    pub fn reset_client(&self) {
+        self.client.reset(self.initial_user());
+    }
+
+    /// The selected database.
+    pub fn db(&self) -> usize {
+        self.client.db()
+    }
+
+    /// Switches this handle, and every clone of it, to database `db`, which
+    /// must be below `DATABASES`.
+    pub fn select(&self, db: usize) {
+        self.client.select(db);
+    }
+
+    /// Exchanges the contents of two databases, so clients on one see the
+    /// other's keys from now on.
+    pub fn swap_db(&self, a: usize, b: usize) {
+        let mut shards = self.data.lock_all();
+        for dbs in shards.shards_mut() {
+            dbs.swap(a, b);
+            for (db, other) in [(a, b), (b, a)] {
+                for key in dbs[db].keys() {
+                    self.watchers.touch(db, key);
+                    self.waiters.wake(db, key);
+                }
+                // keys that went away changed too
+                for key in dbs[other].keys() {
+                    self.watchers.touch(db, key);
+                }
+            }
+        }
+    }
+
+    /// Moves `key` from the selected database to `to`, returning false if it
+    /// doesn't exist here or already exists there.
+    pub fn move_key(&self, key: &Bytes, to: usize) -> bool {
+        let from = self.db();
+        let mut dbs = self.data.lock(key);
+        if self.live_entry(&mut dbs[from], key).is_none() {
+            return false;
+        }
+        if dbs[to].get(key).is_some_and(|entry| !entry.is_expired()) {
+            return false;
+        }
+        let entry = dbs[from].remove(key).unwrap();
+        dbs[to].insert(key.clone(), entry);
+        self.watchers.touch(from, key);
+        self.watchers.touch(to, key);
+        self.waiters.wake(to, key);
+        self.notify_in(from, EventClass::Generic, "move_from", key);
+        self.notify_in(to, EventClass::Generic, "move_to", key);
+        true
+    }
+
+    /// `key`'s value as DUMP serializes it, with its expiry as unix time in
+    /// milliseconds. Looking doesn't count as an access.
+    pub fn dump(&self, key: &Bytes) -> Option<(Bytes, Option<u64>)> {
+        let mut dbs = self.data.lock(key);
+        let data = &mut dbs[self.db()];
+        let entry = self.unexpired_entry(data, key)?;
+        Some((rdb::dump(&entry.value), entry.expiry.map(unix_millis)))
+    }
+
+    /// Stores `value` at `key` to expire at `expires_at`, in unix
+    /// milliseconds, as RESTORE does. Returns false without storing it if
+    /// the key exists and `replace` isn't set.
+    pub fn restore(
+        &self,
+        key: Bytes,
+        value: Value,
+        expires_at: Option<u64>,
+        replace: bool,
+    ) -> bool {
+        let mut dbs = self.data.lock(&key);
+        let data = &mut dbs[self.db()];
+        if self.live_entry(data, &key).is_some() && !replace {
+            return false;
+        }
+        self.watchers.touch(self.db(), &key);
+        self.waiters.wake(self.db(), &key);
+        let expiry = expires_at.map(instant_from_unix_millis);
+        data.insert(key, ValueWithExpiry::new(value, expiry));
+        true
+    }
+
+    /// About `count` keys of the selected database from `cursor` on, of those
+    /// `filter` accepts, and the cursor to carry on from, as SCAN takes them;
+    /// see `scan`. Looking doesn't count as an access.
+    pub fn scan(
+        &self,
+        cursor: u64,
+        count: usize,
+        filter: impl Fn(&Bytes, &Value) -> bool,
+    ) -> (u64, Vec<Bytes>) {
+        let (db, count) = (self.db(), count.max(1));
+        let mut keys = vec![];
+        for shard in self.data.lock_each() {
+            // the page ends by `count` keys from the cursor, so no shard has
+            // more to give than its first `count` and those sharing a hash
+            // with the last of them
+            let mut last = None;
+            for (taken, (hash, key, entry)) in shard[db].in_hash_order(cursor).enumerate() {
+                if taken >= count && last != Some(hash) {
+                    break;
+                }
+                last = Some(hash);
+                let wanted = !entry.is_expired() && filter(key, &entry.value);
+                keys.push((hash, wanted.then(|| key.clone())));
+            }
+        }
+        let (next, keys) = scan::page(keys.into_iter(), cursor, count);
+        (next, keys.into_iter().flatten().collect())
+    }
+
+    /// About `count` members of the sorted set at `key` from `cursor` on, of
+    /// those `filter` accepts, with their scores, and the cursor to carry on
+    /// from, as ZSCAN takes them. Unlike the keyspace, a set isn't kept in
+    /// hash order, so this goes over the whole set as ZRANGE 0 -1 would.
+    pub fn zscan(
+        &self,
+        key: &Bytes,
+        cursor: u64,
+        count: usize,
+        filter: impl Fn(&Bytes) -> bool,
+    ) -> Result<(u64, Vec<(Bytes, f64)>), WrongType> {
+        self.with_sorted_set(key, |zset| {
+            let members = zset
+                .iter()
+                .map(|(member, score)| (self.data.hash(member), (member, score)));
+            let (next, members) = scan::page(members, cursor, count);
+            let members = members
+                .into_iter()
+                .filter(|(member, _)| filter(member))
+                .map(|(member, score)| (member.clone(), score))
+                .collect();
+            (next, members)
+        })
+    }
+
+    /// Up to `count` keys of the selected database that hash to `slot`.
+    pub fn keys_in_slot(&self, slot: u16, count: usize) -> Vec<Bytes> {
+        let db = self.db();
+        let mut keys = vec![];
+        for shard in self.data.lock_each() {
+            let live = shard[db].iter().filter(|(_, entry)| !entry.is_expired());
+            keys.extend(
+                live.map(|(key, _)| key)
+                    .filter(|key| cluster::key_slot(key) == slot)
+                    .take(count - keys.len())
+                    .cloned(),
+            );
+            if keys.len() == count {
+                break;
+            }
+        }
+        keys
+    }
+
+    pub fn set_with_default_expiry(&self, key: Bytes, value: Bytes) {
+        self.set(key, value, Some(Duration::from_millis(DEFAULT_EXPIRY)));
+    }
+
+    /// Sets `key` to the string `value`, expiring after `ttl`, or never for
+    /// None.
+    pub fn set(&self, key: Bytes, value: Bytes, ttl: Option<Duration>) {
+        let mut dbs = self.data.lock(&key);
+        let data = &mut dbs[self.db()];
+        let expiry = ttl.map(|ttl| Instant::now() + ttl);
+        self.watchers.touch(self.db(), &key);
+        data.insert(key, ValueWithExpiry::new(Value::String(value), expiry));
+    }
+
+    /// The string at `key`, or None if there's none.
+    pub fn get_string(&self, key: &Bytes) -> Result<Option<Bytes>, WrongType> {
+        let mut dbs = self.data.lock(key);
+        let data = &mut dbs[self.db()];
+        match self.read_entry(data, key).map(|entry| &*entry.value) {
+            Some(Value::String(value)) => Ok(Some(value.clone())),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Removes `key`, returning true if a live key was removed.
+    pub fn del(&self, key: Bytes) -> bool {
+        let mut dbs = self.data.lock(&key);
+        let data = &mut dbs[self.db()];
+        let removed = self.live_entry(data, &key).is_some() && data.remove(&key).is_some();
+        if removed {
+            self.watchers.touch(self.db(), &key);
+            self.notify(EventClass::Generic, "del", &key);
+        }
+        removed
+    }
+
+    /// When `key` expires: None if there's no such key, Some(None) if it
+    /// never does. Looking doesn't count as an access.
+    pub fn expiry(&self, key: &Bytes) -> Option<Option<Instant>> {
+        let mut dbs = self.data.lock(key);
+        let data = &mut dbs[self.db()];
+        self.unexpired_entry(data, key).map(|entry| entry.expiry)
+    }
+
+    /// Makes `key` expire at unix time `expires_at` in milliseconds, or never
+    /// for None. Returns false if there's no such key.
+    pub fn set_expiry(&self, key: &Bytes, expires_at: Option<u64>) -> bool {
+        let mut dbs = self.data.lock(key);
+        let data = &mut dbs[self.db()];
+        if self.live_entry(data, key).is_none() {
+            return false;
+        }
+        data.change(key, |entry| {
+            entry.expiry = expires_at.map(instant_from_unix_millis);
+            entry.version = next_version();
+        });
+        self.watchers.touch(self.db(), key);
+        true
+    }
+
+    /// Runs `f` against the string at `key`, or against an empty string if the
+    /// key does not exist.
+    pub fn with_string<T>(&self, key: &Bytes, f: impl FnOnce(&[u8]) -> T) -> Result<T, WrongType> {
+        let mut dbs = self.data.lock(key);
+        let data = &mut dbs[self.db()];
+        match self.read_entry(data, key).map(|entry| &*entry.value) {
+            Some(Value::String(value)) => Ok(f(value)),
+            Some(_) => Err(WrongType),
+            None => Ok(f(&[])),
+        }
+    }
+
+    /// Runs `f` against the string at `key` to change it in place, creating
+    /// an empty string without an expiry if needed. The buffer is reused
+    /// rather than copied unless something else still shares it.
+    pub fn with_string_mut<T>(
+        &self,
+        key: Bytes,
+        f: impl FnOnce(&mut BytesMut) -> T,
+    ) -> Result<T, WrongType> {
+        let mut dbs = self.data.lock(&key);
+        let data = &mut dbs[self.db()];
+        if self.live_entry(data, &key).is_none() {
+            data.insert(
+                key.clone(),
+                ValueWithExpiry::new(Value::String(Bytes::new()), None),
+            );
+        }
+        if !matches!(*data[&key].value, Value::String(_)) {
+            return Err(WrongType);
+        }
+        let result = data
+            .change(&key, |entry| {
+                let Value::String(value) = entry.value_mut() else {
+                    unreachable!("checked above");
+                };
+                let mut buffer = std::mem::take(value)
+                    .try_into_mut()
+                    .unwrap_or_else(|shared| BytesMut::from(&shared[..]));
+                let result = f(&mut buffer);
+                *value = buffer.freeze();
+                result
+            })
+            .expect("checked above");
+        self.watchers.touch(self.db(), &key);
+        Ok(result)
+    }
+
+    /// Runs `f` against the sorted set at `key`, or against an empty set if the
+    /// key does not exist.
+    pub fn with_sorted_set<T>(
+        &self,
+        key: &Bytes,
+        f: impl FnOnce(&SortedSet) -> T,
+    ) -> Result<T, WrongType> {
+        let mut dbs = self.data.lock(key);
+        let data = &mut dbs[self.db()];
+        match self.read_entry(data, key).map(|entry| &*entry.value) {
+            Some(Value::SortedSet(zset)) => Ok(f(zset)),
+            Some(_) => Err(WrongType),
+            None => Ok(f(&SortedSet::new())),
+        }
+    }
+
+    /// Runs `f` against the sorted set at `key`, creating it if needed. The key
+    /// is removed if the set is left empty, otherwise blocked clients are woken.
+    pub fn with_sorted_set_mut<T>(
+        &self,
+        key: Bytes,
+        f: impl FnOnce(&mut SortedSet) -> T,
+    ) -> Result<T, WrongType> {
+        let mut dbs = self.data.lock(&key);
+        let data = &mut dbs[self.db()];
+        let created = self.live_entry(data, &key).is_none();
+        if created {
+            data.insert(
+                key.clone(),
+                ValueWithExpiry::new(Value::SortedSet(SortedSet::new()), None),
+            );
+        }
+        if !matches!(*data[&key].value, Value::SortedSet(_)) {
+            return Err(WrongType);
+        }
+        let (result, emptied) = data
+            .change(&key, |entry| {
+                let Value::SortedSet(zset) = entry.value_mut() else {
+                    unreachable!("checked above");
+                };
+                let result = f(zset);
+                (result, zset.is_empty())
+            })
+            .expect("checked above");
+        if emptied {
+            data.remove(&key);
+            if !created {
+                self.watchers.touch(self.db(), &key);
+                self.notify(EventClass::Generic, "del", &key);
+            }
+        } else {
+            self.watchers.touch(self.db(), &key);
+            self.waiters.wake(self.db(), &key);
+        }
+        Ok(result)
+    }
+
+    /// Runs `f` against the value at `key`, if there is one, and the time
+    /// since it was last accessed. Looking doesn't count as an access.
+    pub fn with_value<T>(&self, key: &Bytes, f: impl FnOnce(&Value, Duration) -> T) -> Option<T> {
+        let mut dbs = self.data.lock(key);
+        let data = &mut dbs[self.db()];
+        self.unexpired_entry(data, key)
+            .map(|entry| f(&entry.value, entry.accessed.elapsed()))
+    }
+
+    /// Runs `f` against the stream at `key`, or against an empty stream if the
+    /// key does not exist.
+    pub fn with_stream<T>(
+        &self,
+        key: &Bytes,
+        f: impl FnOnce(&Stream) -> T,
+    ) -> Result<T, WrongType> {
+        let mut dbs = self.data.lock(key);
+        let data = &mut dbs[self.db()];
+        match self.read_entry(data, key).map(|entry| &*entry.value) {
+            Some(Value::Stream(stream)) => Ok(f(stream)),
+            Some(_) => Err(WrongType),
+            None => Ok(f(&Stream::new())),
+        }
+    }
+
+    /// Runs `f` against the stream at `key`. A missing stream is created first
+    /// when `create` is set, otherwise `f` is not called and `None` is returned.
+    /// Unlike other types, streams are kept even when they become empty. Blocked
+    /// clients are only woken when an entry was added, since group reads also
+    /// go through here and would otherwise wake each other forever.
+    pub fn with_stream_mut<T>(
+        &self,
+        key: Bytes,
+        create: bool,
+        f: impl FnOnce(&mut Stream) -> T,
+    ) -> Result<Option<T>, WrongType> {
+        let mut dbs = self.data.lock(&key);
+        let data = &mut dbs[self.db()];
+        let created = self.live_entry(data, &key).is_none();
+        if created {
+            if !create {
+                return Ok(None);
+            }
+            data.insert(
+                key.clone(),
+                ValueWithExpiry::new(Value::Stream(Stream::new()), None),
+            );
+        }
+        if !matches!(*data[&key].value, Value::Stream(_)) {
+            return Err(WrongType);
+        }
+        let (result, untouched, added) = data
+            .change(&key, |entry| {
+                let Value::Stream(stream) = entry.value_mut() else {
+                    unreachable!("checked above");
+                };
+                let last_id = stream.last_id();
+                let result = f(stream);
+                (
+                    result,
+                    *stream == Stream::new(),
+                    stream.last_id() != last_id,
+                )
+            })
+            .expect("checked above");
+        if created && untouched {
+            // nothing was ever added, e.g. XADD failed on a fresh key
+            data.remove(&key);
+        } else {
+            self.watchers.touch(self.db(), &key);
+            if added {
+                self.waiters.wake(self.db(), &key);
+            }
+        }
+        Ok(Some(result))
+    }
+
+    /// Builds a sorted set from the sorted sets at `sources` and stores it at
+    /// `destination`, all under the locks of their shards so the result is
+    /// computed atomically. An empty result deletes `destination`. Returns the
+    /// stored set.
+    pub fn store_sorted_set(
+        &self,
+        destination: Bytes,
+        sources: &[Bytes],
+        f: impl FnOnce(&[&SortedSet]) -> SortedSet,
+    ) -> Result<SortedSet, WrongType> {
+        let db = self.db();
+        let mut shards = self.data.lock_keys(sources.iter().chain([&destination]));
+        for key in sources.iter().chain([&destination]) {
+            self.live_entry(shards.db_mut(db, key), key);
+        }
+        let empty = SortedSet::new();
+        let inputs = sources
+            .iter()
+            .map(
+                |key| match shards.db(db, key).get(key).map(|entry| &*entry.value) {
+                    Some(Value::SortedSet(zset)) => Ok(zset),
+                    Some(_) => Err(WrongType),
+                    None => Ok(&empty),
+                },
+            )
+            .collect::<Result<Vec<_>, _>>()?;
+        let result = f(&inputs);
+        let data = shards.db_mut(db, &destination);
+        if result.is_empty() {
+            if data.remove(&destination).is_some() {
+                self.watchers.touch(self.db(), &destination);
+                self.notify(EventClass::Generic, "del", &destination);
+            }
+        } else {
+            data.insert(
+                destination.clone(),
+                ValueWithExpiry::new(Value::SortedSet(result.clone()), None),
+            );
+            self.watchers.touch(self.db(), &destination);
+            self.waiters.wake(self.db(), &destination);
+        }
+        Ok(result)
+    }
+
+    /// Runs `f` against the strings at `keys`, all under the locks of their
+    /// shards. Missing keys count as empty strings.
+    pub fn with_strings<T>(
+        &self,
+        keys: &[Bytes],
+        f: impl FnOnce(&[&[u8]]) -> T,
+    ) -> Result<T, WrongType> {
+        let db = self.db();
+        let mut shards = self.data.lock_keys(keys);
+        for key in keys {
+            self.read_entry(shards.db_mut(db, key), key);
+        }
+        Ok(f(&strings(&shards, db, keys)?))
+    }
+
+    /// Builds a string from the strings at `sources` and stores it at
+    /// `destination`, as `store_sorted_set` does for sorted sets. Missing
+    /// sources count as empty strings. Returns the stored string, or what
+    /// `f` failed with, in which case nothing is stored.
+    pub fn store_string<E>(
+        &self,
+        destination: Bytes,
+        sources: &[Bytes],
+        f: impl FnOnce(&[&[u8]]) -> Result<Bytes, E>,
+    ) -> Result<Result<Bytes, E>, WrongType> {
+        let db = self.db();
+        let mut shards = self.data.lock_keys(sources.iter().chain([&destination]));
+        for key in sources.iter().chain([&destination]) {
+            self.live_entry(shards.db_mut(db, key), key);
+        }
+        let result = match f(&strings(&shards, db, sources)?) {
+            Ok(result) => result,
+            Err(e) => return Ok(Err(e)),
+        };
+        let data = shards.db_mut(db, &destination);
+        if result.is_empty() {
+            if data.remove(&destination).is_some() {
+                self.watchers.touch(self.db(), &destination);
+                self.notify(EventClass::Generic, "del", &destination);
+            }
+        } else {
+            data.insert(
+                destination.clone(),
+                ValueWithExpiry::new(Value::String(result.clone()), None),
+            );
+            self.watchers.touch(self.db(), &destination);
+        }
+        Ok(Ok(result))
+    }
+
+    /// Registers interest in `keys` for a blocking command. Hold on to the guard
+    /// while re-checking the keys so no write is missed in between.
+    pub fn block_on(&self, keys: &[Bytes]) -> WaitGuard {
+        self.waiters.register(self.db(), keys)
+    }
+
+    /// Taken around a single client command so it cannot interleave with a
+    /// transaction. Blocking commands must not hold it while they wait.
+    pub async fn command_lock(&self) -> OwnedRwLockReadGuard<()> {
+        self.exec_lock.clone().read_owned().await
+    }
+
+    /// Taken by EXEC so its queued commands run without other clients'
+    /// commands in between.
+    pub async fn transaction_lock(&self) -> OwnedRwLockWriteGuard<()> {
+        self.exec_lock.clone().write_owned().await
+    }
+
+    /// `command_lock`, unless a script holding the lock runs past
+    /// `lua-time-limit` first, when the command is refused with BUSY instead.
+    pub async fn command_lock_unless_busy(&self) -> Result<OwnedRwLockReadGuard<()>, Error> {
+        tokio::select! {
+            lock = self.command_lock() => Ok(lock),
+            _ = self.scripts.busy() => Err(Error::Busy),
+        }
+    }
+
+    /// `transaction_lock`, unless a script runs too long, as for
+    /// `command_lock_unless_busy`.
+    pub async fn transaction_lock_unless_busy(&self) -> Result<OwnedRwLockWriteGuard<()>, Error> {
+        tokio::select! {
+            lock = self.transaction_lock() => Ok(lock),
+            _ = self.scripts.busy() => Err(Error::Busy),
+        }
+    }
+
+    pub fn watched_keys(&self) -> WatchedKeys {
+        self.watchers.watched_keys()
+    }
+
+    pub fn scripts(&self) -> &ScriptCache {
+        &self.scripts
+    }
+
+    pub fn functions(&self) -> &FunctionLibraries {
+        &self.functions
+    }
+
+    pub fn pubsub(&self) -> &PubSub {
+        &self.pubsub
+    }
+
+    pub fn tracking(&self) -> &Tracking {
+        &self.tracking
+    }
+
+    pub fn notify_keyspace_events(&self) -> NotifyFlags {
+        NotifyFlags::from_bits(self.notify_flags.load(Ordering::Relaxed))
+    }
+
+    pub fn set_notify_keyspace_events(&self, flags: NotifyFlags) {
+        self.notify_flags.store(flags.bits(), Ordering::Relaxed);
+    }
+
+    pub fn replicas(&self) -> &ReplicaSet {
+        &self.replicas
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn persistence(&self) -> &Persistence {
+        &self.persistence
+    }
+
+    pub fn aof(&self) -> &Aof {
+        &self.aof
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    pub fn latency(&self) -> &Latency {
+        &self.latency
+    }
+
+    pub fn set_active_expire(&self, active: bool) {
+        self.active_expire.store(active, Ordering::Relaxed);
+    }
+
+    /// The keys in each database, counting only those that haven't expired.
+    pub fn db_stats(&self) -> Vec<DbStats> {
+        let shards = self.data.lock_all();
+        let now = Instant::now();
+        let mut stats = vec![DbStats::default(); DATABASES];
+        let mut total_ttl = vec![0u128; DATABASES];
+        for (db, _, entry) in shards.live_entries() {
+            stats[db].keys += 1;
+            if let Some(expiry) = entry.expiry {
+                stats[db].expires += 1;
+                total_ttl[db] += expiry.saturating_duration_since(now).as_millis();
+            }
+        }
+        for (db, total) in stats.iter_mut().zip(total_ttl) {
+            if db.expires > 0 {
+                db.avg_ttl = (total / db.expires as u128) as u64;
+            }
+        }
+        stats
+    }
+
+    /// How many keys in each database hold each type, and their sizes. Reads
+    /// the counts the shards keep rather than going over the keys.
+    pub fn type_stats(&self) -> Vec<TypeStats> {
+        let mut stats = vec![TypeStats::default(); DATABASES];
+        for shard in self.data.lock_each() {
+            for (db, data) in shard.iter().enumerate() {
+                stats[db].merge(data.types());
+            }
+        }
+        stats
+    }
+
+    /// Roughly how many bytes the data takes, keys and their tables included.
+    /// Reads the counts the shards keep rather than going over the keys.
+    pub fn used_memory(&self) -> usize {
+        self.usage().iter().map(Usage::total).sum()
+    }
+
+    pub fn memory_stats(&self) -> MemoryStats {
+        MemoryStats::new(&self.usage())
+    }
+
+    /// The bytes `key` and its value take, sizing collections from `samples`
+    /// of their elements as `memory::key_usage` does. Doesn't count as an
+    /// access.
+    pub fn memory_usage(&self, key: &Bytes, samples: usize) -> Option<usize> {
+        self.with_value(key, |value, _| memory::key_usage(key, value, samples))
+    }
+
+    /// A stamp that changes whenever `key` is written, replaced or removed,
+    /// or 0 if it doesn't exist. Comparing two reads tells whether the key
+    /// changed in between. Doesn't count as an access.
+    pub fn version(&self, key: &Bytes) -> u64 {
+        let mut dbs = self.data.lock(key);
+        let data = &mut dbs[self.db()];
+        self.unexpired_entry(data, key)
+            .map_or(0, |entry| entry.version)
+    }
+
+    /// How many writes were made since the last successful save.
+    pub fn dirty(&self) -> u64 {
+        self.persistence.dirty()
+    }
+
+    pub fn repl_timeout(&self) -> Duration {
+        Duration::from_millis(self.repl_timeout.load(Ordering::Relaxed))
+    }
+
+    pub fn set_repl_timeout(&self, timeout: Duration) {
+        self.repl_timeout
+            .store(timeout.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn replication_tls(&self) -> Option<tls::Connector> {
+        self.replication_tls.lock().unwrap().clone()
+    }
+
+    /// Makes replicas connect to their master over TLS, or over plain TCP
+    /// again with None.
+    pub fn set_replication_tls(&self, connector: Option<tls::Connector>) {
+        *self.replication_tls.lock().unwrap() = connector;
+    }
+
+    pub fn acl(&self) -> &Acl {
+        &self.acl
+    }
+
+    pub fn cluster(&self) -> &Cluster {
+        &self.cluster
+    }
+
+    pub fn sentinel(&self) -> &Sentinel {
+        &self.sentinel
+    }
+
+    pub fn user(&self) -> Option<String> {
+        self.client.user()
+    }
+
+    /// Runs this handle's commands as `user` from now on.
+    pub fn set_user(&self, user: Option<String>) {
+        self.client.set_user(user);
+    }
+
+    pub fn clients(&self) -> &Clients {
+        &self.clients
+    }
+
+    /// The id of the client using this handle, if a client is.
+    pub fn client_id(&self) -> Option<u64> {
+        self.client.id()
+    }
+
+    pub fn set_client_id(&self, id: u64) {
+        self.client.set_id(id);
+    }
+
+    pub fn no_touch(&self) -> bool {
+        self.client.no_touch()
+    }
+
+    pub fn set_no_touch(&self, no_touch: bool) {
+        self.client.set_no_touch(no_touch);
+    }
+
+    pub fn resp3(&self) -> bool {
+        self.client.resp3()
+    }
+
+    pub fn set_resp3(&self, resp3: bool) {
+        self.client.set_resp3(resp3);
+    }
+
+    pub fn reply_mode(&self) -> ReplyMode {
+        self.client.reply_mode()
+    }
+
+    pub fn set_reply_mode(&self, mode: ReplyMode) {
+        self.client.set_reply_mode(mode);
+    }
+
+    /// See `ClientState::take_reply_skip`.
+    pub fn take_reply_skip(&self) -> bool {
+        self.client.take_reply_skip()
+    }
+
+    pub fn role(&self) -> &RoleState {
+        &self.role
+    }
+
+    /// Whether the store follows a master, which alone decides when keys
+    /// expire so replicas stay in step with it.
+    pub fn is_replica(&self) -> bool {
+        self.role.is_replica()
+    }
+
+    pub fn master_repl_offset(&self) -> u64 {
+        self.replicas.offset()
+    }
+
+    /// Raises `event` on `key` with the hooks, which publish it as a keyspace
+    /// notification if its class is enabled. The store raises the generic
+    /// `del` and `expired` events from its own mutation paths; commands raise
+    /// their type-specific events.
+    pub fn notify(&self, class: EventClass, event: &str, key: &Bytes) {
+        self.notify_in(self.db(), class, event, key)
+    }
+
+    fn notify_in(&self, db: usize, class: EventClass, event: &str, key: &Bytes) {
+        self.hooks.emit(&Event {
+            db,
+            class,
+            name: event,
+            key,
+            client: self.client_id(),
+        });
+    }
+
+    /// Calls `hook` on every change to the keyspace from now on, see
+    /// `events::Hooks`.
+    pub fn on_event(&self, hook: impl Fn(&Event) + Send + Sync + 'static) {
+        self.hooks.add(hook);
+    }
+
+    /// Looks up `key`, lazily evicting it if it has expired. The eviction is
+    /// replicated as a DEL, sent while the keyspace is still locked so it
+    /// lands in the stream in the same order it happened.
+    fn live_entry<'a>(&self, data: &'a mut Db, key: &Bytes) -> Option<&'a mut ValueWithExpiry> {
+        let entry = self.unexpired_entry(data, key)?;
+        if !self.no_touch() {
+            entry.touch();
+        }
+        Some(entry)
+    }
+
+    /// `live_entry` for a command that only reads the key, counted as a
+    /// keyspace hit or miss.
+    fn read_entry<'a>(&self, data: &'a mut Db, key: &Bytes) -> Option<&'a mut ValueWithExpiry> {
+        let entry = self.live_entry(data, key);
+        self.stats.lookup(entry.is_some());
+        entry
+    }
+
+    /// `live_entry` without recording an access.
+    fn unexpired_entry<'a>(
+        &self,
+        data: &'a mut Db,
+        key: &Bytes,
+    ) -> Option<&'a mut ValueWithExpiry> {
+        if !self.is_replica() && data.get(key).is_some_and(ValueWithExpiry::is_expired) {
+            data.remove(key);
+            self.stats.key_expired();
+            self.watchers.touch(self.db(), key);
+            self.notify(EventClass::Expired, "expired", key);
+            if !self.persistence.is_loading() {
+                // replicas never expire keys themselves
+                let propagated = Action::Del { key: key.clone() }
+                    .replication_frame()
+                    .and_then(|frame| self.propagate(frame, true));
+                if let Err(err) = propagated {
+                    log_warning!("failed to propagate the expiry of {:?}: {:?}", key, err);
+                }
+            }
+        }
+        data.get_mut(key)
+    }
+
+    /// Sends out the effect of a write the keyspace already holds: a durable
+    /// one is counted towards the save rules and logged to the AOF, then it is
+    /// queued for replicas, each preceded by a SELECT when the database differs
+    /// from the last write's. Replicas get it even if logging fails, as they
+    /// must hold what this server does; the failure is returned so the client
+    /// that wrote hears about it.
+    pub fn propagate(&self, frame: Frame, durable: bool) -> anyhow::Result<()> {
+        let logged = match durable {
+            true => {
+                self.persistence.add_dirty(1);
+                self.aof.append(self.db(), &frame)
+            }
+            false => Ok(()),
+        };
+        // replicas forward their master's stream instead, see `ReplicaSet::forward`
+        if !self.is_replica() {
+            self.replicas.publish_in(self.db(), frame)?;
+        }
+        logged.map_err(|err| aof::WriteFailed(err).into())
+    }
+
+    /// Every live key as of now, for RDB encoding and AOF rewrites to work
+    /// through without holding any lock. Values aren't copied: the keyspace
+    /// and the snapshot share them until a write copies the one it changes.
+    pub fn snapshot(&self) -> Snapshot {
+        self.snapshot_while(|| ()).0
+    }
+
+    /// `snapshot`, along with the result of `f`, which runs before the
+    /// keyspace can change again.
+    fn snapshot_while<T>(&self, f: impl FnOnce() -> T) -> (Snapshot, T) {
+        let shards = self.data.lock_all();
+        let entries = shards
+            .live_entries()
+            .map(|(db, key, entry)| {
+                (
+                    db,
+                    key.clone(),
+                    Arc::clone(&entry.value),
+                    entry.expiry.map(unix_millis),
+                )
+            })
+            .collect();
+        (Snapshot { entries }, f())
+    }
+
+    /// An RDB snapshot of every live key.
+    pub async fn as_rdb(&self) -> anyhow::Result<Bytes> {
+        Ok(self.as_rdb_while(|| ()).await?.0)
+    }
+
+    /// An RDB snapshot of every live key, along with the result of `f`, which
+    /// runs before the keyspace can change again.
+    pub async fn as_rdb_while<T>(&self, f: impl FnOnce() -> T) -> anyhow::Result<(Bytes, T)> {
+        let (snapshot, result) = self.snapshot_while(f);
+        let rdb = tokio::task::spawn_blocking(move || encode_snapshot(&snapshot)).await?;
+        Ok((rdb, result))
+    }
+
+    /// Writes an RDB snapshot to the configured file before returning, as
+    /// SAVE does.
+    pub async fn save(&self) -> anyhow::Result<()> {
+        let (snapshot, dirty) = self.snapshot_while(|| self.persistence.dirty());
+        let path = self.config.rdb_path();
+        tokio::task::spawn_blocking(move || {
+            persistence::write_rdb(&path, &encode_snapshot(&snapshot))
+        })
+        .await??;
+        self.persistence.saved(dirty);
+        Ok(())
+    }
+
+    /// Gets the data on disk before the server exits: an RDB snapshot if
+    /// `save` says so, or by default if any save rules are configured, and
+    /// whatever the AOF has been sent.
+    pub async fn prepare_shutdown(&self, save: Option<bool>) -> anyhow::Result<()> {
+        if save.unwrap_or_else(|| !self.config.save().is_empty()) {
+            log_notice!("Saving the final RDB snapshot before exiting.");
+            self.save().await.context("saving the final RDB snapshot")?;
+        }
+        self.aof.fsync().context("syncing the append only file")?;
+        Ok(())
+    }
+
+    /// Tells the server to stop accepting connections and every connection,
+    /// replicas' included, to close once its current command is done.
+    pub fn begin_shutdown(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        // nobody listening means nothing to stop
+        let _ = self.shutdown.send(());
+    }
+
+    pub fn shutdown_listener(&self) -> Shutdown {
+        // subscribed before checking, so a shutdown in between is still seen
+        let notify = self.shutdown.subscribe();
+        Shutdown::new(self.shutting_down.load(Ordering::SeqCst), notify)
+    }
+
+    /// Starts a background save whenever one of the configured save rules is
+    /// met, and an AOF rewrite whenever the file has grown enough, checking
+    /// once a second. Never returns.
+    pub async fn run_scheduled_saves(&self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            if let Some(rule) = self.persistence.due(&self.config.save()) {
+                log_notice!(
+                    "{} changes in {} seconds. Saving...",
+                    rule.changes,
+                    rule.secs
+                );
+                self.bgsave();
+            }
+            let percentage = self.config.auto_aof_rewrite_percentage();
+            if self
+                .aof
+                .rewrite_due(percentage, self.config.auto_aof_rewrite_min_size())
+            {
+                log_notice!("Starting automatic rewriting of AOF");
+                self.bgrewriteaof();
+            }
+        }
+    }
+
+    /// Removes expired keys once a second, so keys nobody reads again don't
+    /// hang around, unless DEBUG SET-ACTIVE-EXPIRE turned it off. Never
+    /// returns.
+    pub async fn run_active_expiry(&self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            if self.active_expire.load(Ordering::Relaxed) {
+                let started = Instant::now();
+                self.expire_keys();
+                self.latency
+                    .record(latency::EXPIRE_CYCLE, started.elapsed());
+            }
+        }
+    }
+
+    /// Removes every expired key, just as if a client had looked it up.
+    fn expire_keys(&self) {
+        for mut shard in self.data.lock_each() {
+            for (db, data) in shard.iter_mut().enumerate() {
+                let expired: Vec<Bytes> = data
+                    .iter()
+                    .filter(|(_, entry)| entry.is_expired())
+                    .map(|(key, _)| key.clone())
+                    .collect();
+                // expiring propagates and notifies in the key's own database
+                let handle = self.in_db(db);
+                for key in expired {
+                    handle.unexpired_entry(data, &key);
+                }
+            }
+        }
+    }
+
+    /// This handle, but reading and writing `db`.
+    fn in_db(&self, db: usize) -> Store {
+        Store {
+            client: Arc::new(self.client.in_db(db)),
+            ..self.clone()
+        }
+    }
+
+    /// The memory every database takes, read from the counts each shard
+    /// keeps.
+    fn usage(&self) -> Vec<Usage> {
+        let mut usage = vec![Usage::default(); DATABASES];
+        for shard in self.data.lock_each() {
+            for (db, data) in shard.iter().enumerate() {
+                usage[db].add(data.usage());
+            }
+        }
+        usage
+    }
+
+    /// Evicts keys as `maxmemory-policy` says until the data fits within
+    /// `maxmemory`, each chosen as the best of `maxmemory-samples` random
+    /// candidates. Returns false if it still doesn't fit, because the policy
+    /// evicts nothing or has run out of keys it may evict. Replicas leave
+    /// eviction to their master, whose deletes they are sent.
+    pub fn evict(&self) -> bool {
+        let maxmemory = self.config.maxmemory() as usize;
+        if maxmemory == 0 || self.is_replica() {
+            return true;
+        }
+        let mut used = self.used_memory();
+        if used <= maxmemory {
+            return true;
+        }
+        let mut shards = self.data.lock_all();
+        let policy = self.config.maxmemory_policy();
+        let samples = self.config.maxmemory_samples().max(1);
+        let now = Instant::now();
+        let mut candidates: Vec<(u64, usize, Bytes)> = shards
+            .live_entries()
+            .filter_map(|(db, key, entry)| Some((policy.rank(entry, now)?, db, key.clone())))
+            .collect();
+        while used > maxmemory && !candidates.is_empty() {
+            let best = (0..samples)
+                .map(|_| evict::random() as usize % candidates.len())
+                .max_by_key(|&i| candidates[i].0)
+                .expect("at least one sample");
+            let (_, db, key) = candidates.swap_remove(best);
+            let Some(entry) = shards.db_mut(db, &key).remove(&key) else {
+                continue;
+            };
+            used = used.saturating_sub(entry.usage(&key).total());
+            self.stats.key_evicted();
+            self.watchers.touch(db, &key);
+            let handle = self.in_db(db);
+            handle.notify(EventClass::Evicted, "evicted", &key);
+            let propagated = Action::Del { key: key.clone() }
+                .replication_frame()
+                .and_then(|frame| handle.propagate(frame, true));
+            if let Err(err) = propagated {
+                log_warning!("failed to propagate the eviction of {:?}: {:?}", key, err);
+            }
+        }
+        used <= maxmemory
+    }
+
+    /// Rewrites the AOF from the keyspace in the background, as BGREWRITEAOF
+    /// does. Returns false if a rewrite is already running.
+    pub fn bgrewriteaof(&self) -> bool {
+        aof::bgrewriteaof(self)
+    }
+
+    /// Copies the keyspace and writes it out on a background task, as BGSAVE
+    /// does. Returns false if a background save is already running.
+    pub fn bgsave(&self) -> bool {
+        if !self.persistence.start_bgsave() {
+            return false;
+        }
+        let started = Instant::now();
+        let snapshot = self.snapshot();
+        self.latency.record(latency::FORK, started.elapsed());
+        let path = self.config.rdb_path();
+        let persistence = self.persistence.clone();
+        tokio::task::spawn_blocking(move || {
+            let written = persistence::write_rdb(&path, &encode_snapshot(&snapshot));
+            if let Err(err) = &written {
+                log_warning!("background save failed: {:#}", err);
+            }
+            persistence.finish_bgsave(written.is_ok());
+        });
+        true
+    }
+
+    /// Loads the data saved by an earlier run at startup: the AOF when it is
+    /// on, which it then goes on appending to, and the RDB file otherwise.
+    pub async fn load(&self) -> anyhow::Result<()> {
+        if !self.config.appendonly() {
+            return self.load_rdb_file().await;
+        }
+        let path = self.config.aof_path();
+        aof::load(self, &path).await?;
+        self.aof
+            .open(&path)
+            .with_context(|| format!("opening {}", path.display()))
+    }
+
+    /// Loads the RDB file the config points at, if there is one.
+    pub async fn load_rdb_file(&self) -> anyhow::Result<()> {
+        let path = self.config.rdb_path();
+        match tokio::fs::read(&path).await {
+            Ok(rdb) => {
+                self.persistence.set_loading(true);
+                let loaded = self.load_rdb(rdb.into()).await;
+                self.persistence.set_loading(false);
+                loaded.with_context(|| format!("loading {}", path.display()))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err).with_context(|| format!("reading {}", path.display())),
+        }
+    }
+
+    /// Replaces the keyspace with an RDB snapshot, as a replica does on a full
+    /// resync. Keys that have already expired are skipped. The snapshot is
+    /// decoded, and the old keyspace freed, off the async runtime.
+    pub async fn load_rdb(&self, rdb: Bytes) -> anyhow::Result<()> {
+        let loaded = tokio::task::spawn_blocking(move || rdb::decode(&rdb)).await??;
+        if let Some(entry) = loaded.iter().find(|entry| entry.db >= DATABASES) {
+            bail!("database {} is out of range", entry.db);
+        }
+        let mut shards = self.data.lock_all();
+        let mut old = vec![];
+        for dbs in shards.shards_mut() {
+            for (db, data) in dbs.iter_mut().enumerate() {
+                for key in data.keys() {
+                    self.watchers.touch(db, key);
+                }
+                old.push(data.take());
+            }
+        }
+        for entry in loaded {
+            let expiry = entry.expires_at.map(instant_from_unix_millis);
+            if expiry.is_some_and(|expiry| expiry <= Instant::now()) {
+                continue;
+            }
+            self.watchers.touch(entry.db, &entry.key);
+            shards
+                .db_mut(entry.db, &entry.key)
+                .insert(entry.key, ValueWithExpiry::new(entry.value, expiry));
+        }
+        drop(shards);
+        tokio::task::spawn_blocking(move || drop(old));
+        Ok(())
+    }
+}
+
+/// The strings at `keys` in `db`, with empty ones for missing keys.
+fn strings<'a>(
+    shards: &'a KeyShards,
+    db: usize,
+    keys: &[Bytes],
+) -> Result<Vec<&'a [u8]>, WrongType> {
+    keys.iter()
+        .map(
+            |key| match shards.db(db, key).get(key).map(|entry| &*entry.value) {
+                Some(Value::String(value)) => Ok(&value[..]),
+                Some(_) => Err(WrongType),
+                None => Ok(&[][..]),
+            },
+        )
+        .collect()
+}
+
+/// An immutable view of the keyspace, see `Store::snapshot`. Cloning one
+/// is cheap.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    entries: Arc<[SnapshotEntry]>,
+}
+
+/// A key's database, the key, its value and its expiry in unix milliseconds.
+type SnapshotEntry = (usize, Bytes, Arc<Value>, Option<u64>);
+
+impl Snapshot {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Each key with its database, value and expiry as unix time in
+    /// milliseconds, ordered by database.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Bytes, &Value, Option<u64>)> {
+        self.entries
+            .iter()
+            .map(|(db, key, value, expires_at)| (*db, key, &**value, *expires_at))
+    }
+}
+
+fn encode_snapshot(snapshot: &Snapshot) -> Bytes {
+    rdb::encode(
+        snapshot
+            .iter()
+            .map(|(db, key, value, expires_at)| rdb::Entry {
+                db,
+                key,
+                value,
+                expires_at,
+            }),
+    )
+}
+
+/// `at` as unix time in milliseconds.
+fn unix_millis(at: Instant) -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let at = now + at.saturating_duration_since(Instant::now());
+    at.as_millis() as u64
+}
+
+/// The instant at unix time `millis`, or now if that has passed.
+fn instant_from_unix_millis(millis: u64) -> Instant {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Instant::now() + Duration::from_millis(millis).saturating_sub(now)
+}
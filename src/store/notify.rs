@@ -0,0 +1,143 @@
+use std::fmt;
+
+/// Which keyspace notifications are enabled, as configured by the
+/// `notify-keyspace-events` flag string (e.g. `KEA`, `Ex`, `Kz`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NotifyFlags(u16);
+
+/// The class an event belongs to; events are only emitted when their class is enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventClass {
+    Generic,
+    String,
+    List,
+    Set,
+    Hash,
+    SortedSet,
+    Expired,
+    Evicted,
+    Stream,
+    Module,
+    KeyMiss,
+    New,
+}
+
+const KEYSPACE: u16 = 1 << 0;
+const KEYEVENT: u16 = 1 << 1;
+
+impl EventClass {
+    fn bit(self) -> u16 {
+        1 << (self as u16 + 2)
+    }
+}
+
+/// Flag letters for the classes `A` stands for, in the order Redis prints them.
+const ALL_CLASSES: [(char, EventClass); 10] = [
+    ('g', EventClass::Generic),
+    ('$', EventClass::String),
+    ('l', EventClass::List),
+    ('s', EventClass::Set),
+    ('h', EventClass::Hash),
+    ('z', EventClass::SortedSet),
+    ('x', EventClass::Expired),
+    ('e', EventClass::Evicted),
+    ('t', EventClass::Stream),
+    ('d', EventClass::Module),
+];
+
+impl NotifyFlags {
+    pub fn from_bits(bits: u16) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(self) -> u16 {
+        self.0
+    }
+
+    /// Parses a flag string, returning `None` on an unknown letter.
+    pub fn parse(s: &str) -> Option<NotifyFlags> {
+        let mut bits = 0;
+        for c in s.chars() {
+            bits |= match c {
+                'A' => ALL_CLASSES
+                    .iter()
+                    .fold(0, |bits, (_, class)| bits | class.bit()),
+                'K' => KEYSPACE,
+                'E' => KEYEVENT,
+                'm' => EventClass::KeyMiss.bit(),
+                'n' => EventClass::New.bit(),
+                _ => ALL_CLASSES.iter().find(|(letter, _)| *letter == c)?.1.bit(),
+            };
+        }
+        Some(NotifyFlags(bits))
+    }
+
+    pub fn keyspace(self) -> bool {
+        self.0 & KEYSPACE != 0
+    }
+
+    pub fn keyevent(self) -> bool {
+        self.0 & KEYEVENT != 0
+    }
+
+    /// Whether events of `class` should be published on any channel.
+    pub fn enabled(self, class: EventClass) -> bool {
+        (self.keyspace() || self.keyevent()) && self.0 & class.bit() != 0
+    }
+}
+
+impl fmt::Display for NotifyFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let all = ALL_CLASSES
+            .iter()
+            .all(|(_, class)| self.0 & class.bit() != 0);
+        if all {
+            write!(f, "A")?;
+        } else {
+            for (letter, class) in ALL_CLASSES {
+                if self.0 & class.bit() != 0 {
+                    write!(f, "{}", letter)?;
+                }
+            }
+        }
+        for (letter, set) in [
+            ('K', self.keyspace()),
+            ('E', self.keyevent()),
+            ('m', self.0 & EventClass::KeyMiss.bit() != 0),
+            ('n', self.0 & EventClass::New.bit() != 0),
+        ] {
+            if set {
+                write!(f, "{}", letter)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_display() {
+        let flags = NotifyFlags::parse("KEA").unwrap();
+        assert!(flags.enabled(EventClass::Expired));
+        assert!(!flags.enabled(EventClass::KeyMiss));
+        assert_eq!(flags.to_string(), "AKE");
+
+        let flags = NotifyFlags::parse("Ez").unwrap();
+        assert!(flags.enabled(EventClass::SortedSet));
+        assert!(!flags.keyspace());
+        assert_eq!(flags.to_string(), "zE");
+
+        assert_eq!(NotifyFlags::parse("Kq"), None);
+        assert_eq!(NotifyFlags::parse("").unwrap().to_string(), "");
+    }
+
+    #[test]
+    fn classes_need_a_channel_type() {
+        assert!(!NotifyFlags::parse("A")
+            .unwrap()
+            .enabled(EventClass::Generic));
+    }
+}
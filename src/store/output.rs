@@ -0,0 +1,193 @@
+//! `client-output-buffer-limit`: how much output may pile up for a client
+//! that isn't reading it before the client is disconnected, set separately
+//! for normal clients, replicas and pub/sub subscribers.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use super::config::parse_memory;
+
+/// Redis's defaults for replicas and subscribers. Normal clients have no
+/// limit.
+pub const REPLICA_OUTPUT_LIMIT: OutputLimit = OutputLimit {
+    hard: 256 * 1024 * 1024,
+    soft: 64 * 1024 * 1024,
+    soft_secs: 60,
+};
+pub const PUBSUB_OUTPUT_LIMIT: OutputLimit = OutputLimit {
+    hard: 32 * 1024 * 1024,
+    soft: 8 * 1024 * 1024,
+    soft_secs: 60,
+};
+
+/// The kinds of client limited separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    Normal,
+    Replica,
+    PubSub,
+}
+
+impl Class {
+    fn parse(name: &str) -> Option<Class> {
+        match name.to_ascii_lowercase().as_str() {
+            "normal" => Some(Class::Normal),
+            "replica" | "slave" => Some(Class::Replica),
+            "pubsub" => Some(Class::PubSub),
+            _ => None,
+        }
+    }
+}
+
+/// A client is disconnected once its output reaches `hard` bytes, or stays
+/// at `soft` bytes or more for over `soft_secs`. A limit of 0 is no limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputLimit {
+    pub hard: u64,
+    pub soft: u64,
+    pub soft_secs: u64,
+}
+
+impl OutputLimit {
+    pub fn is_unlimited(&self) -> bool {
+        self.hard == 0 && self.soft == 0
+    }
+}
+
+impl fmt::Display for OutputLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.hard, self.soft, self.soft_secs)
+    }
+}
+
+/// Parses `<class> <hard> <soft> <soft-seconds>` groups, returning the
+/// reason CONFIG SET gives when they don't parse.
+pub fn parse_output_limits(value: &str) -> Result<Vec<(Class, OutputLimit)>, &'static str> {
+    let words: Vec<&str> = value.split_whitespace().collect();
+    if words.is_empty() || !words.len().is_multiple_of(4) {
+        return Err("Wrong number of arguments in buffer limit configuration.");
+    }
+    words
+        .chunks(4)
+        .map(|group| {
+            let class = Class::parse(group[0])
+                .ok_or("Invalid client class specified in buffer limit configuration.")?;
+            let (Some(hard), Some(soft), Ok(soft_secs)) = (
+                parse_memory(group[1]),
+                parse_memory(group[2]),
+                group[3].parse::<u64>(),
+            ) else {
+                return Err(
+                    "Error in hard, soft or soft_seconds setting in buffer limit configuration.",
+                );
+            };
+            Ok((
+                class,
+                OutputLimit {
+                    hard,
+                    soft,
+                    soft_secs,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Bytes queued for one client that it hasn't been sent yet.
+#[derive(Debug, Default)]
+pub struct Pending {
+    len: AtomicU64,
+    /// When the bytes last went over the soft limit, while they still are.
+    over_soft_since: Mutex<Option<Instant>>,
+}
+
+impl Pending {
+    /// Counts `len` more bytes, returning whether they take the client over
+    /// `limit`.
+    pub fn add(&self, len: u64, limit: &OutputLimit) -> bool {
+        let len = self.len.fetch_add(len, Ordering::SeqCst) + len;
+        if limit.hard > 0 && len >= limit.hard {
+            return true;
+        }
+        let mut since = self.over_soft_since.lock().unwrap();
+        if limit.soft == 0 || len < limit.soft {
+            *since = None;
+            return false;
+        }
+        // whole seconds, as Redis counts them
+        since.get_or_insert_with(Instant::now).elapsed().as_secs() > limit.soft_secs
+    }
+
+    /// Counts `len` bytes as sent.
+    pub fn remove(&self, len: u64) {
+        let _ = self
+            .len
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pending| {
+                Some(pending.saturating_sub(len))
+            });
+    }
+
+    pub fn clear(&self) {
+        self.len.store(0, Ordering::SeqCst);
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len.load(Ordering::SeqCst)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_limits_for_each_class() {
+        assert_eq!(
+            parse_output_limits("normal 0 0 0 slave 256mb 64mb 60"),
+            Ok(vec![
+                (Class::Normal, OutputLimit::default()),
+                (Class::Replica, REPLICA_OUTPUT_LIMIT),
+            ])
+        );
+        assert!(parse_output_limits("pubsub 32mb 8mb").is_err());
+        assert!(parse_output_limits("other 1 1 1").is_err());
+        assert!(parse_output_limits("normal 1 1 soon").is_err());
+    }
+
+    #[test]
+    fn pending_output_goes_over_the_hard_limit_at_once() {
+        let pending = Pending::default();
+        let limit = OutputLimit {
+            hard: 100,
+            soft: 50,
+            soft_secs: 60,
+        };
+        assert!(!pending.add(60, &limit));
+        pending.remove(60);
+        assert!(pending.is_empty());
+        assert!(!pending.add(99, &limit));
+        assert!(pending.add(1, &limit));
+    }
+
+    #[test]
+    fn pending_output_may_sit_over_the_soft_limit_for_a_while() {
+        let pending = Pending::default();
+        let limit = OutputLimit {
+            hard: 0,
+            soft: 50,
+            soft_secs: 0,
+        };
+        assert!(!pending.add(60, &limit));
+        assert!(!pending.add(1, &limit));
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        assert!(pending.add(1, &limit));
+        pending.clear();
+        assert!(!pending.add(1, &limit));
+    }
+}
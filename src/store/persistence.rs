@@ -0,0 +1,173 @@
+use anyhow::Context;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::config::SaveRule;
+
+/// How long after a failed background save the save rules may try again.
+const BGSAVE_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+struct State {
+    /// Unix time in seconds of the last successful save, or of startup.
+    lastsave: u64,
+    /// Writes since the last successful save.
+    dirty: u64,
+    /// `dirty` when the running background save took its copy.
+    bgsave_dirty: u64,
+    bgsave_started: Option<Instant>,
+    last_bgsave_attempt: Option<Instant>,
+    last_bgsave_ok: bool,
+    last_bgsave_duration: Option<Duration>,
+    /// Successful saves since startup.
+    saves: u64,
+    /// Set while the RDB file or AOF is read at startup.
+    loading: bool,
+}
+
+/// The state of RDB snapshots and of loading, as LASTSAVE and INFO
+/// persistence report it.
+#[derive(Debug, Clone)]
+pub struct Persistence {
+    state: Arc<Mutex<State>>,
+}
+
+impl Default for Persistence {
+    fn default() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                lastsave: unix_secs(),
+                dirty: 0,
+                bgsave_dirty: 0,
+                bgsave_started: None,
+                last_bgsave_attempt: None,
+                last_bgsave_ok: true,
+                last_bgsave_duration: None,
+                saves: 0,
+                loading: false,
+            })),
+        }
+    }
+}
+
+impl Persistence {
+    pub fn lastsave(&self) -> u64 {
+        self.state.lock().unwrap().lastsave
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.state.lock().unwrap().loading
+    }
+
+    pub fn set_loading(&self, loading: bool) {
+        self.state.lock().unwrap().loading = loading;
+    }
+
+    pub fn dirty(&self) -> u64 {
+        self.state.lock().unwrap().dirty
+    }
+
+    pub fn add_dirty(&self, writes: u64) {
+        self.state.lock().unwrap().dirty += writes;
+    }
+
+    /// Records a successful save of the keyspace as it was after `dirty` writes.
+    pub fn saved(&self, dirty: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.lastsave = unix_secs();
+        state.dirty = state.dirty.saturating_sub(dirty);
+        state.saves += 1;
+    }
+
+    /// Marks a background save as running, returning false if one already is.
+    pub fn start_bgsave(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.bgsave_started.is_some() {
+            return false;
+        }
+        let now = Instant::now();
+        state.bgsave_started = Some(now);
+        state.last_bgsave_attempt = Some(now);
+        state.bgsave_dirty = state.dirty;
+        true
+    }
+
+    pub fn finish_bgsave(&self, ok: bool) {
+        let mut state = self.state.lock().unwrap();
+        let started = state.bgsave_started.take();
+        state.last_bgsave_duration = started.map(|started| started.elapsed());
+        state.last_bgsave_ok = ok;
+        if ok {
+            state.lastsave = unix_secs();
+            state.dirty = state.dirty.saturating_sub(state.bgsave_dirty);
+            state.saves += 1;
+        }
+    }
+
+    /// The first of `rules` whose thresholds have been reached, unless a
+    /// background save is running or recently failed.
+    pub fn due(&self, rules: &[SaveRule]) -> Option<SaveRule> {
+        let state = self.state.lock().unwrap();
+        let retry_wait = state
+            .last_bgsave_attempt
+            .is_some_and(|attempt| attempt.elapsed() < BGSAVE_RETRY_DELAY);
+        if state.bgsave_started.is_some() || (!state.last_bgsave_ok && retry_wait) {
+            return None;
+        }
+        let since_save = unix_secs().saturating_sub(state.lastsave);
+        rules
+            .iter()
+            .find(|rule| state.dirty >= rule.changes && since_save > rule.secs)
+            .copied()
+    }
+
+    /// How long the running background save has taken so far, if there is one.
+    pub fn bgsave_running_for(&self) -> Option<Duration> {
+        let state = self.state.lock().unwrap();
+        state.bgsave_started.map(|started| started.elapsed())
+    }
+
+    pub fn last_bgsave_ok(&self) -> bool {
+        self.state.lock().unwrap().last_bgsave_ok
+    }
+
+    pub fn last_bgsave_duration(&self) -> Option<Duration> {
+        self.state.lock().unwrap().last_bgsave_duration
+    }
+
+    pub fn saves(&self) -> u64 {
+        self.state.lock().unwrap().saves
+    }
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Writes `rdb` to `path` through a temporary file in the same directory, so
+/// a failed save never leaves a truncated file in its place.
+pub fn write_rdb(path: &Path, rdb: &[u8]) -> anyhow::Result<()> {
+    static SAVES: AtomicU64 = AtomicU64::new(0);
+    let temp = path.with_file_name(format!(
+        "temp-{}-{}.rdb",
+        std::process::id(),
+        SAVES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let written = File::create(&temp)
+        .and_then(|mut file| {
+            file.write_all(rdb)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written.with_context(|| format!("writing {}", path.display()))
+}
@@ -0,0 +1,243 @@
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    Notify,
+};
+
+use super::output::{OutputLimit, Pending, PUBSUB_OUTPUT_LIMIT};
+
+/// A published `(channel, message)` pair.
+pub type Message = (Bytes, Bytes);
+
+type Channels = HashMap<Bytes, HashMap<u64, Arc<Subscriber>>>;
+
+/// Registry of pub/sub channels and the connections subscribed to them.
+///
+/// Every subscribed connection owns one unbounded queue, registered under each
+/// channel it listens on, so a slow subscriber never blocks PUBLISH. One that
+/// lets too much pile up is disconnected instead, as the pubsub class of
+/// `client-output-buffer-limit` says.
+#[derive(Debug, Clone)]
+pub struct PubSub {
+    channels: Arc<Mutex<Channels>>,
+    next_id: Arc<AtomicU64>,
+    output_limit: Arc<Mutex<OutputLimit>>,
+}
+
+impl Default for PubSub {
+    fn default() -> Self {
+        Self {
+            channels: Default::default(),
+            next_id: Default::default(),
+            output_limit: Arc::new(Mutex::new(PUBSUB_OUTPUT_LIMIT)),
+        }
+    }
+}
+
+/// A subscribed connection's queue, and the bytes waiting in it.
+#[derive(Debug)]
+struct Subscriber {
+    sender: UnboundedSender<Message>,
+    pending: Pending,
+    /// Notified once the queue goes over the output limit.
+    overflowed: Notify,
+}
+
+impl Subscriber {
+    /// Queues `message`, unless that takes the subscriber over `limit`.
+    fn deliver(&self, message: Message, limit: &OutputLimit) -> bool {
+        if self.pending.add(message_len(&message), limit) {
+            self.overflowed.notify_one();
+            return false;
+        }
+        self.sender.send(message).is_ok()
+    }
+}
+
+/// What a message counts for against the output limit.
+fn message_len((channel, message): &Message) -> u64 {
+    (channel.len() + message.len()) as u64
+}
+
+/// One connection's subscriptions. Dropping it unsubscribes from everything.
+#[derive(Debug)]
+pub struct Subscriptions {
+    pubsub: PubSub,
+    id: u64,
+    subscriber: Arc<Subscriber>,
+    receiver: UnboundedReceiver<Message>,
+    /// In subscription order, which is the order UNSUBSCRIBE replies in.
+    channels: Vec<Bytes>,
+}
+
+impl PubSub {
+    /// Delivers `message` to every subscriber of `channel`, returning how many
+    /// received it.
+    pub fn publish(&self, channel: &Bytes, message: Bytes) -> usize {
+        let channels = self.channels.lock().unwrap();
+        let Some(subscribers) = channels.get(channel) else {
+            return 0;
+        };
+        let limit = self.output_limit();
+        subscribers
+            .values()
+            .filter(|subscriber| subscriber.deliver((channel.clone(), message.clone()), &limit))
+            .count()
+    }
+
+    pub fn output_limit(&self) -> OutputLimit {
+        *self.output_limit.lock().unwrap()
+    }
+
+    pub fn set_output_limit(&self, limit: OutputLimit) {
+        *self.output_limit.lock().unwrap() = limit;
+    }
+
+    pub fn subscriptions(&self) -> Subscriptions {
+        let (sender, receiver) = unbounded_channel();
+        Subscriptions {
+            pubsub: self.clone(),
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            subscriber: Arc::new(Subscriber {
+                sender,
+                pending: Pending::default(),
+                overflowed: Notify::new(),
+            }),
+            receiver,
+            channels: vec![],
+        }
+    }
+}
+
+impl Subscriptions {
+    pub fn len(&self) -> usize {
+        self.channels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    pub fn channels(&self) -> &[Bytes] {
+        &self.channels
+    }
+
+    /// Subscribes to `channel` (a no-op if already subscribed), returning the
+    /// number of channels now subscribed to.
+    pub fn subscribe(&mut self, channel: Bytes) -> usize {
+        if !self.channels.contains(&channel) {
+            let mut channels = self.pubsub.channels.lock().unwrap();
+            channels
+                .entry(channel.clone())
+                .or_default()
+                .insert(self.id, self.subscriber.clone());
+            self.channels.push(channel);
+        }
+        self.len()
+    }
+
+    /// Unsubscribes from `channel`, returning the number of channels still
+    /// subscribed to.
+    pub fn unsubscribe(&mut self, channel: &Bytes) -> usize {
+        if let Some(position) = self.channels.iter().position(|c| c == channel) {
+            self.channels.remove(position);
+            let mut channels = self.pubsub.channels.lock().unwrap();
+            remove_subscriber(&mut channels, channel, self.id);
+        }
+        if self.is_empty() {
+            // drop anything still queued so a later SUBSCRIBE starts clean
+            while self.receiver.try_recv().is_ok() {}
+            self.subscriber.pending.clear();
+        }
+        self.len()
+    }
+
+    /// Waits for the next message on any subscribed channel.
+    pub async fn recv(&mut self) -> Option<Message> {
+        let message = self.receiver.recv().await?;
+        self.subscriber.pending.remove(message_len(&message));
+        Some(message)
+    }
+
+    /// Resolves once messages queued for this connection go over the output
+    /// limit, when it should be disconnected. The future doesn't borrow the
+    /// subscriptions, so it can be awaited alongside `recv`.
+    pub fn overflowed(&self) -> impl Future<Output = ()> {
+        let subscriber = self.subscriber.clone();
+        async move { subscriber.overflowed.notified().await }
+    }
+}
+
+impl Drop for Subscriptions {
+    fn drop(&mut self) {
+        let mut channels = self.pubsub.channels.lock().unwrap();
+        for channel in &self.channels {
+            remove_subscriber(&mut channels, channel, self.id);
+        }
+    }
+}
+
+fn remove_subscriber(channels: &mut Channels, channel: &Bytes, id: u64) {
+    if let Some(subscribers) = channels.get_mut(channel) {
+        subscribers.remove(&id);
+        if subscribers.is_empty() {
+            channels.remove(channel);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn publish_reaches_subscribers() {
+        let pubsub = PubSub::default();
+        let mut a = pubsub.subscriptions();
+        let mut b = pubsub.subscriptions();
+        let news = Bytes::from("news");
+        assert_eq!(a.subscribe(news.clone()), 1);
+        assert_eq!(a.subscribe(news.clone()), 1);
+        b.subscribe(news.clone());
+
+        assert_eq!(pubsub.publish(&news, "hi".into()), 2);
+        assert_eq!(a.recv().await, Some((news.clone(), "hi".into())));
+        assert_eq!(b.recv().await, Some((news.clone(), "hi".into())));
+
+        assert_eq!(b.unsubscribe(&news), 0);
+        assert_eq!(pubsub.publish(&news, "again".into()), 1);
+    }
+
+    #[test]
+    fn dropping_subscriptions_unregisters() {
+        let pubsub = PubSub::default();
+        let mut a = pubsub.subscriptions();
+        a.subscribe("news".into());
+        drop(a);
+        assert_eq!(pubsub.publish(&"news".into(), "hi".into()), 0);
+        assert!(pubsub.channels.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn subscribers_that_fall_behind_overflow() {
+        let pubsub = PubSub::default();
+        pubsub.set_output_limit(OutputLimit {
+            hard: 20,
+            ..Default::default()
+        });
+        let mut a = pubsub.subscriptions();
+        let news = Bytes::from("news");
+        a.subscribe(news.clone());
+
+        assert_eq!(pubsub.publish(&news, "01234".into()), 1);
+        assert_eq!(pubsub.publish(&news, "01234".into()), 1);
+        assert_eq!(a.recv().await, Some((news.clone(), "01234".into())));
+        assert_eq!(pubsub.publish(&news, "01234".into()), 1);
+        assert_eq!(pubsub.publish(&news, "01234".into()), 0);
+        a.overflowed().await;
+    }
+}
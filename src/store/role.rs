@@ -0,0 +1,161 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tokio::task::AbortHandle;
+
+/// Whether the server takes writes from clients or follows a master.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Role {
+    #[default]
+    Master,
+    Replica {
+        host: String,
+        port: u16,
+    },
+}
+
+/// How far a replica's link to its master has got: the handshake step it is
+/// waiting on, or connected once the stream is flowing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LinkState {
+    #[default]
+    Connect,
+    Ping,
+    ListeningPort,
+    Capa,
+    Psync,
+    Transfer,
+    Connected,
+}
+
+impl fmt::Display for LinkState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkState::Connect => "connect",
+            LinkState::Ping => "ping",
+            LinkState::ListeningPort => "listening-port",
+            LinkState::Capa => "capa",
+            LinkState::Psync => "psync",
+            LinkState::Transfer => "transfer",
+            LinkState::Connected => "connected",
+        }
+        .fmt(f)
+    }
+}
+
+/// How far a FAILOVER has got.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FailoverState {
+    #[default]
+    None,
+    /// Writes are paused until the chosen replica has all of them.
+    WaitingForSync,
+    /// The replica has been asked to take over.
+    InProgress,
+}
+
+impl fmt::Display for FailoverState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailoverState::None => "no-failover",
+            FailoverState::WaitingForSync => "waiting-for-sync",
+            FailoverState::InProgress => "failover-in-progress",
+        }
+        .fmt(f)
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    role: Role,
+    /// The task following the master, while there is one.
+    link: Option<AbortHandle>,
+    link_state: LinkState,
+    failover: FailoverState,
+    /// The task running the failover, while there is one.
+    failover_task: Option<AbortHandle>,
+}
+
+/// The server's current role, which REPLICAOF may change at any time.
+#[derive(Debug, Clone, Default)]
+pub struct RoleState {
+    state: Arc<Mutex<State>>,
+}
+
+impl RoleState {
+    pub fn get(&self) -> Role {
+        self.state.lock().unwrap().role.clone()
+    }
+
+    pub fn is_replica(&self) -> bool {
+        matches!(self.state.lock().unwrap().role, Role::Replica { .. })
+    }
+
+    /// Switches to `role`, stopping the link to any master followed so far.
+    pub fn set(&self, role: Role) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(link) = state.link.take() {
+            link.abort();
+        }
+        crate::log::set_replica(matches!(role, Role::Replica { .. }));
+        state.role = role;
+        state.link_state = LinkState::default();
+    }
+
+    pub fn link_state(&self) -> LinkState {
+        self.state.lock().unwrap().link_state
+    }
+
+    pub fn set_link_state(&self, link_state: LinkState) {
+        self.state.lock().unwrap().link_state = link_state;
+    }
+
+    pub fn failover_state(&self) -> FailoverState {
+        self.state.lock().unwrap().failover
+    }
+
+    /// Starts a failover, unless one is already under way.
+    pub fn begin_failover(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.failover != FailoverState::None {
+            return false;
+        }
+        state.failover = FailoverState::WaitingForSync;
+        true
+    }
+
+    pub fn set_failover_state(&self, failover: FailoverState) {
+        self.state.lock().unwrap().failover = failover;
+    }
+
+    /// Records the task running the failover, if it hasn't finished already.
+    pub fn set_failover_task(&self, task: AbortHandle) {
+        let mut state = self.state.lock().unwrap();
+        if state.failover != FailoverState::None {
+            state.failover_task = Some(task);
+        }
+    }
+
+    /// Marks the failover over, returning how far it got.
+    pub fn end_failover(&self) -> FailoverState {
+        let mut state = self.state.lock().unwrap();
+        state.failover_task = None;
+        std::mem::take(&mut state.failover)
+    }
+
+    /// Stops the failover task, if any, and marks the failover over.
+    pub fn abort_failover(&self) -> FailoverState {
+        let mut state = self.state.lock().unwrap();
+        if let Some(task) = state.failover_task.take() {
+            task.abort();
+        }
+        std::mem::take(&mut state.failover)
+    }
+
+    /// Records the task following the master, stopping any older one.
+    pub fn set_link(&self, link: AbortHandle) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(old) = state.link.replace(link) {
+            old.abort();
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! SCAN cursors. Keys are visited in the order of their hash, which depends
+//! only on the key, and a cursor is the hash to carry on from. So however
+//! the keyspace changes between calls, a key present for the whole
+//! iteration comes up exactly once, and a key added or removed meanwhile
+//! may or may not. Each shard keeps its keys in hash order, so a call
+//! only looks at the few past the cursor.
+
+/// One call's keys out of `keys`, given with their hashes: the `count` or
+/// so coming first from `cursor` on, and the cursor to pass next, 0 once
+/// every key has been returned. Keys sharing a hash are returned together,
+/// so the page may hold a few more than `count`.
+pub(super) fn page<T>(
+    keys: impl Iterator<Item = (u64, T)>,
+    cursor: u64,
+    count: usize,
+) -> (u64, Vec<T>) {
+    let mut ahead: Vec<(u64, T)> = keys.filter(|(hash, _)| *hash >= cursor).collect();
+    let count = count.max(1);
+    if ahead.len() <= count {
+        return (0, ahead.into_iter().map(|(_, key)| key).collect());
+    }
+    ahead.select_nth_unstable_by_key(count - 1, |(hash, _)| *hash);
+    let last = ahead[count - 1].0;
+    let keys = ahead
+        .into_iter()
+        .filter(|(hash, _)| *hash <= last)
+        .map(|(_, key)| key)
+        .collect();
+    (last.checked_add(1).unwrap_or(0), keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::evict::random;
+    use std::collections::{BTreeMap, HashSet};
+
+    /// Scans a keyspace that changes at random between calls, checking that
+    /// every key present throughout is returned exactly once.
+    fn scan_while_changing(hash_bits: u32, count: usize) {
+        let hash = |key: u64| (key.wrapping_mul(0x9e37_79b9_7f4a_7c15)) >> (64 - hash_bits);
+        let mut keys: BTreeMap<u64, u64> = (0..200).map(|key| (key, hash(key))).collect();
+        let mut next_key = 200;
+        let mut removed = HashSet::new();
+        let before: Vec<u64> = keys.keys().copied().collect();
+        let mut seen: Vec<u64> = vec![];
+        let mut cursor = 0;
+        loop {
+            let (next, page) = page(keys.iter().map(|(key, hash)| (*hash, *key)), cursor, count);
+            seen.extend(page);
+            for _ in 0..random() % 8 {
+                if random().is_multiple_of(2) {
+                    keys.insert(next_key, hash(next_key));
+                    next_key += 1;
+                } else if let Some(&key) = keys.keys().nth(random() as usize % keys.len()) {
+                    keys.remove(&key);
+                    removed.insert(key);
+                }
+            }
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        for key in before.iter().filter(|key| !removed.contains(key)) {
+            assert_eq!(
+                1,
+                seen.iter().filter(|seen| *seen == key).count(),
+                "key {} with {} hash bits, count {}",
+                key,
+                hash_bits,
+                count
+            );
+        }
+        assert!(seen.iter().all(|key| *key < next_key));
+    }
+
+    #[test]
+    fn keys_present_throughout_are_returned_once() {
+        for _ in 0..20 {
+            // few hash bits make for many keys sharing a hash
+            for hash_bits in [4, 16, 64] {
+                for count in [1, 10, 1000] {
+                    scan_while_changing(hash_bits, count);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn the_last_hash_ends_the_iteration() {
+        let keys = [(u64::MAX - 1, "a"), (u64::MAX, "b")];
+        assert_eq!((u64::MAX, vec!["a"]), page(keys.into_iter(), 0, 1));
+        assert_eq!((0, vec!["b"]), page(keys.into_iter(), u64::MAX, 1));
+        assert_eq!((0, Vec::<&str>::new()), page(std::iter::empty(), 0, 10));
+    }
+}
@@ -0,0 +1,160 @@
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+use crate::digest;
+
+/// Scripts loaded with SCRIPT LOAD or run with EVAL, keyed by the lowercase
+/// hex SHA1 of their body so EVALSHA can find them. Also tracks the script
+/// running now, so other clients can be told BUSY once it runs too long and
+/// SCRIPT KILL can stop it.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptCache {
+    scripts: Arc<Mutex<HashMap<String, Bytes>>>,
+    running: Arc<Mutex<Option<Running>>>,
+    /// Whether the running script is past `lua-time-limit`.
+    busy: Arc<watch::Sender<bool>>,
+}
+
+#[derive(Debug)]
+struct Running {
+    started: Instant,
+    /// Whether it has written, after which killing it would leave the data
+    /// half changed.
+    wrote: bool,
+    killed: bool,
+}
+
+/// Why SCRIPT KILL couldn't stop a script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KillError {
+    NotBusy,
+    Unkillable,
+}
+
+impl std::fmt::Display for KillError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KillError::NotBusy => "NOTBUSY No scripts in execution right now.".fmt(f),
+            KillError::Unkillable => "UNKILLABLE Sorry the script already executed write commands against the dataset. You can either wait the script termination or kill the server in a hard way using the SHUTDOWN NOSAVE command.".fmt(f),
+        }
+    }
+}
+
+/// Marks a script as running until dropped.
+pub struct RunningGuard<'a> {
+    cache: &'a ScriptCache,
+}
+
+impl Drop for RunningGuard<'_> {
+    fn drop(&mut self) {
+        self.cache.running.lock().unwrap().take();
+        self.cache.busy.send_replace(false);
+    }
+}
+
+impl ScriptCache {
+    /// Caches `body`, returning its SHA1.
+    pub fn load(&self, body: Bytes) -> String {
+        let sha = sha1_hex(&body);
+        self.scripts.lock().unwrap().insert(sha.clone(), body);
+        sha
+    }
+
+    pub fn get(&self, sha: &str) -> Option<Bytes> {
+        let scripts = self.scripts.lock().unwrap();
+        scripts.get(&sha.to_ascii_lowercase()).cloned()
+    }
+
+    pub fn exists(&self, sha: &str) -> bool {
+        self.get(sha).is_some()
+    }
+
+    pub fn flush(&self) {
+        self.scripts.lock().unwrap().clear();
+    }
+
+    /// Marks a script as started; it runs until the guard is dropped.
+    pub fn start(&self) -> RunningGuard<'_> {
+        *self.running.lock().unwrap() = Some(Running {
+            started: Instant::now(),
+            wrote: false,
+            killed: false,
+        });
+        RunningGuard { cache: self }
+    }
+
+    /// Called as the running script goes: marks the server busy once it has
+    /// run for longer than `limit`, and says whether it has been killed.
+    pub fn check(&self, limit: Duration) -> bool {
+        let running = self.running.lock().unwrap();
+        let Some(running) = running.as_ref() else {
+            return false;
+        };
+        if running.started.elapsed() > limit && !*self.busy.borrow() {
+            self.busy.send_replace(true);
+        }
+        running.killed
+    }
+
+    /// Notes that the running script has written.
+    pub fn wrote(&self) {
+        if let Some(running) = self.running.lock().unwrap().as_mut() {
+            running.wrote = true;
+        }
+    }
+
+    /// Stops the running script at its next check, unless it has written
+    /// and `force` isn't given.
+    pub fn kill(&self, force: bool) -> Result<(), KillError> {
+        match self.running.lock().unwrap().as_mut() {
+            None => Err(KillError::NotBusy),
+            Some(running) if running.wrote && !force => Err(KillError::Unkillable),
+            Some(running) => {
+                running.killed = true;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.lock().unwrap().is_some()
+    }
+
+    pub fn is_busy(&self) -> bool {
+        *self.busy.borrow()
+    }
+
+    /// Resolves once the running script is past `lua-time-limit`, which may
+    /// be straight away.
+    pub async fn busy(&self) {
+        let mut busy = self.busy.subscribe();
+        // the sender lives as long as the cache
+        let _ = busy.wait_for(|busy| *busy).await;
+    }
+}
+
+pub fn sha1_hex(body: &[u8]) -> String {
+    digest::sha1(body)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_and_get_by_sha() {
+        let cache = ScriptCache::default();
+        let sha = cache.load("return 1".into());
+        assert_eq!(sha, "e0e1f9fabfc9d4800c877a703b823ac0578ff8db");
+        assert_eq!(cache.get(&sha.to_uppercase()), Some("return 1".into()));
+
+        cache.flush();
+        assert!(!cache.exists(&sha));
+    }
+}
@@ -0,0 +1,166 @@
+//! Sentinel mode. With `--sentinel` the server watches one master, learning
+//! its replicas from INFO, and once the master has gone unanswered for
+//! `down-after-milliseconds` it promotes the replica furthest along and
+//! points the others at it. There is a single sentinel, so the master being
+//! down in its own view (subjectively down) is enough; there is no quorum.
+//!
+//! The watching is done by `crate::sentinel`; this is what it has learnt,
+//! for SENTINEL and INFO to report.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long the master may go unanswered unless configured otherwise.
+pub const DEFAULT_DOWN_AFTER: Duration = Duration::from_secs(30);
+
+pub const ERR_DISABLED: &str = "ERR This instance has sentinel support disabled";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Address {
+    pub host: String,
+    pub port: u16,
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.host, self.port)
+    }
+}
+
+/// A replica of the master, as the master's INFO lists it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replica {
+    pub address: Address,
+    /// The offset the replica last acknowledged to the master.
+    pub offset: u64,
+}
+
+/// The master being watched.
+#[derive(Debug, Clone)]
+pub struct Master {
+    pub name: String,
+    pub address: Address,
+    pub down_after: Duration,
+    pub replicas: Vec<Replica>,
+    /// When the master last answered a PING.
+    pub last_reply: Instant,
+    /// Set once the master has gone unanswered for `down_after`.
+    pub down: bool,
+    pub failovers: u64,
+    /// Masters failed over from, to be made replicas of the new one once they
+    /// are back.
+    pub demoted: Vec<Address>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Sentinel {
+    master: Arc<Mutex<Option<Master>>>,
+}
+
+impl Sentinel {
+    /// Whether the server runs as a sentinel, which it does once it has a
+    /// master to watch.
+    pub fn is_enabled(&self) -> bool {
+        self.master.lock().unwrap().is_some()
+    }
+
+    pub fn monitor(&self, name: String, address: Address, down_after: Duration) {
+        *self.master.lock().unwrap() = Some(Master {
+            name,
+            address,
+            down_after,
+            replicas: vec![],
+            last_reply: Instant::now(),
+            down: false,
+            failovers: 0,
+            demoted: vec![],
+        });
+    }
+
+    pub fn master(&self) -> Option<Master> {
+        self.master.lock().unwrap().clone()
+    }
+
+    /// The master, if it goes by `name`.
+    pub fn master_named(&self, name: &str) -> Option<Master> {
+        self.master().filter(|master| master.name == name)
+    }
+
+    /// Records that the master answered, listing `replicas`. Returns whether
+    /// it had been down.
+    pub fn replied(&self, replicas: Vec<Replica>) -> bool {
+        self.update(|master| {
+            master.last_reply = Instant::now();
+            master.replicas = replicas;
+            std::mem::take(&mut master.down)
+        })
+        .unwrap_or_default()
+    }
+
+    /// Marks the master down if it has gone unanswered for long enough,
+    /// returning whether it is down.
+    pub fn check_down(&self) -> bool {
+        self.update(|master| {
+            if master.last_reply.elapsed() >= master.down_after {
+                master.down = true;
+            }
+            master.down
+        })
+        .unwrap_or_default()
+    }
+
+    /// Watches `promoted` instead of the old master, which is set aside to be
+    /// demoted, along with the replicas that were told to follow it.
+    pub fn switch_master(&self, promoted: Address, replicas: Vec<Replica>) {
+        self.update(|master| {
+            let old = std::mem::replace(&mut master.address, promoted);
+            master.demoted.push(old);
+            master.replicas = replicas;
+            master.last_reply = Instant::now();
+            master.down = false;
+            master.failovers += 1;
+        });
+    }
+
+    /// Forgets a former master, now that it follows the new one.
+    pub fn demoted(&self, address: &Address) {
+        self.update(|master| master.demoted.retain(|old| old != address));
+    }
+
+    fn update<T>(&self, f: impl FnOnce(&mut Master) -> T) -> Option<T> {
+        self.master.lock().unwrap().as_mut().map(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_master_goes_down_once_unanswered_for_long_enough() {
+        let sentinel = Sentinel::default();
+        assert!(!sentinel.is_enabled());
+        let address = Address {
+            host: "127.0.0.1".to_string(),
+            port: 6379,
+        };
+        sentinel.monitor("mymaster".to_string(), address.clone(), Duration::ZERO);
+        assert!(sentinel.check_down());
+        assert!(sentinel.replied(vec![]));
+        assert!(!sentinel.replied(vec![]));
+
+        let promoted = Address {
+            host: "127.0.0.1".to_string(),
+            port: 6380,
+        };
+        sentinel.switch_master(promoted.clone(), vec![]);
+        let master = sentinel.master_named("mymaster").unwrap();
+        assert_eq!(promoted, master.address);
+        assert_eq!(vec![address.clone()], master.demoted);
+        assert_eq!(1, master.failovers);
+        sentinel.demoted(&address);
+        assert!(sentinel.master().unwrap().demoted.is_empty());
+        assert!(sentinel.master_named("other").is_none());
+    }
+}
@@ -0,0 +1,241 @@
+//! The keyspace split by key hash into shards, each behind its own lock, so
+//! commands on keys in different shards don't wait on each other.
+
+use bytes::Bytes;
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::BuildHasher;
+use std::ops::Deref;
+use std::sync::{Mutex, MutexGuard};
+
+use super::{memory::Usage, stats::TypeStats, ValueWithExpiry, DATABASES};
+
+const SHARDS: usize = 16;
+
+/// A database's keys within a shard, along with how many of them hold each
+/// type, the memory they take and the keys in hash order. Reads go straight
+/// to the map; writes go through the methods here so the rest stays in step.
+#[derive(Debug)]
+pub(super) struct Db {
+    entries: HashMap<Bytes, ValueWithExpiry>,
+    types: TypeStats,
+    usage: Usage,
+    /// Every key with its hash, for SCAN to carry on from a cursor without
+    /// going over the keys before it.
+    by_hash: BTreeSet<(u64, Bytes)>,
+    hasher: RandomState,
+}
+
+impl Deref for Db {
+    type Target = HashMap<Bytes, ValueWithExpiry>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.entries
+    }
+}
+
+impl Db {
+    fn new(hasher: RandomState) -> Self {
+        Self {
+            entries: HashMap::new(),
+            types: TypeStats::default(),
+            usage: Usage::default(),
+            by_hash: BTreeSet::new(),
+            hasher,
+        }
+    }
+
+    pub(super) fn insert(&mut self, key: Bytes, entry: ValueWithExpiry) -> Option<ValueWithExpiry> {
+        self.types.add(&entry.value);
+        self.usage.add(&entry.usage(&key));
+        let hash = self.hasher.hash_one(&key[..]);
+        let Some(old) = self.entries.insert(key.clone(), entry) else {
+            self.by_hash.insert((hash, key));
+            return None;
+        };
+        self.types.remove(&old.value);
+        self.usage.remove(&old.usage(&key));
+        Some(old)
+    }
+
+    pub(super) fn remove(&mut self, key: &[u8]) -> Option<ValueWithExpiry> {
+        let (key, old) = self.entries.remove_entry(key)?;
+        self.types.remove(&old.value);
+        self.usage.remove(&old.usage(&key));
+        self.by_hash.remove(&(self.hasher.hash_one(&key[..]), key));
+        Some(old)
+    }
+
+    /// Takes every key out, leaving the database empty.
+    pub(super) fn take(&mut self) -> Db {
+        std::mem::replace(self, Db::new(self.hasher.clone()))
+    }
+
+    /// The keys whose hash is `cursor` or more, in hash order, with their
+    /// hashes; see `Shards::hash`.
+    pub(super) fn in_hash_order(
+        &self,
+        cursor: u64,
+    ) -> impl Iterator<Item = (u64, &Bytes, &ValueWithExpiry)> + '_ {
+        self.by_hash
+            .range((cursor, Bytes::new())..)
+            .map(|(hash, key)| (*hash, key, &self.entries[key]))
+    }
+
+    /// The entry at `key`, to touch; values and expiries are changed with
+    /// `change`.
+    pub(super) fn get_mut(&mut self, key: &[u8]) -> Option<&mut ValueWithExpiry> {
+        self.entries.get_mut(key)
+    }
+
+    /// Runs `f` to change the value or expiry at `key` in place. The value
+    /// must keep its type.
+    pub(super) fn change<T>(
+        &mut self,
+        key: &[u8],
+        f: impl FnOnce(&mut ValueWithExpiry) -> T,
+    ) -> Option<T> {
+        let entry = self.entries.get_mut(key)?;
+        let before = entry.value.size();
+        self.usage.remove(&entry.usage(key));
+        let result = f(entry);
+        self.types.resize(&entry.value, before);
+        self.usage.add(&entry.usage(key));
+        Some(result)
+    }
+
+    pub(super) fn types(&self) -> &TypeStats {
+        &self.types
+    }
+
+    pub(super) fn usage(&self) -> &Usage {
+        &self.usage
+    }
+}
+
+/// A shard's part of every database. A key lands in the same shard whatever
+/// its database, so moving it between databases takes a single lock.
+pub(super) type Shard = Vec<Db>;
+
+#[derive(Debug)]
+pub(super) struct Shards {
+    shards: Box<[Mutex<Shard>]>,
+    hasher: RandomState,
+}
+
+impl Shards {
+    pub(super) fn new() -> Self {
+        let hasher = RandomState::new();
+        Self {
+            shards: (0..SHARDS)
+                .map(|_| Mutex::new((0..DATABASES).map(|_| Db::new(hasher.clone())).collect()))
+                .collect(),
+            hasher,
+        }
+    }
+
+    /// The hash `key` is placed by, which stays the same for as long as the
+    /// server runs.
+    pub(super) fn hash(&self, key: &[u8]) -> u64 {
+        self.hasher.hash_one(key)
+    }
+
+    fn index(&self, key: &[u8]) -> usize {
+        self.hash(key) as usize % SHARDS
+    }
+
+    /// The shard holding `key`, locked.
+    pub(super) fn lock(&self, key: &[u8]) -> MutexGuard<'_, Shard> {
+        self.shards[self.index(key)].lock().unwrap()
+    }
+
+    /// The shards holding `keys`, locked in index order so that callers
+    /// locking overlapping sets can't deadlock.
+    pub(super) fn lock_keys<'a>(&self, keys: impl IntoIterator<Item = &'a Bytes>) -> KeyShards<'_> {
+        let mut indexes: Vec<usize> = keys.into_iter().map(|key| self.index(key)).collect();
+        indexes.sort_unstable();
+        indexes.dedup();
+        KeyShards {
+            shards: self,
+            locked: indexes
+                .into_iter()
+                .map(|i| (i, self.shards[i].lock().unwrap()))
+                .collect(),
+        }
+    }
+
+    /// Every shard, locked in index order, for work that must see the whole
+    /// keyspace at once.
+    pub(super) fn lock_all(&self) -> AllShards<'_> {
+        AllShards {
+            shards: self,
+            locked: self
+                .shards
+                .iter()
+                .map(|shard| shard.lock().unwrap())
+                .collect(),
+        }
+    }
+
+    /// Each shard in turn, locked only while the caller holds it.
+    pub(super) fn lock_each(&self) -> impl Iterator<Item = MutexGuard<'_, Shard>> {
+        self.shards.iter().map(|shard| shard.lock().unwrap())
+    }
+}
+
+/// The shards holding a known set of keys, see `Shards::lock_keys`.
+pub(super) struct KeyShards<'a> {
+    shards: &'a Shards,
+    locked: Vec<(usize, MutexGuard<'a, Shard>)>,
+}
+
+impl KeyShards<'_> {
+    fn position(&self, key: &[u8]) -> usize {
+        let index = self.shards.index(key);
+        self.locked
+            .iter()
+            .position(|(i, _)| *i == index)
+            .expect("key's shard is locked")
+    }
+
+    /// The part of `db` holding `key`, which must be one of the locked keys.
+    pub(super) fn db(&self, db: usize, key: &[u8]) -> &Db {
+        &self.locked[self.position(key)].1[db]
+    }
+
+    pub(super) fn db_mut(&mut self, db: usize, key: &[u8]) -> &mut Db {
+        let position = self.position(key);
+        &mut self.locked[position].1[db]
+    }
+}
+
+/// Every shard, see `Shards::lock_all`.
+pub(super) struct AllShards<'a> {
+    shards: &'a Shards,
+    locked: Vec<MutexGuard<'a, Shard>>,
+}
+
+impl<'a> AllShards<'a> {
+    /// Every key that hasn't expired with its database, a database at a time.
+    pub(super) fn live_entries(
+        &self,
+    ) -> impl Iterator<Item = (usize, &Bytes, &ValueWithExpiry)> + '_ {
+        (0..DATABASES).flat_map(move |db| {
+            self.locked.iter().flat_map(move |shard| {
+                shard[db]
+                    .iter()
+                    .filter(|(_, entry)| !entry.is_expired())
+                    .map(move |(key, entry)| (db, key, entry))
+            })
+        })
+    }
+
+    pub(super) fn shards_mut(&mut self) -> &mut [MutexGuard<'a, Shard>] {
+        &mut self.locked
+    }
+
+    /// The part of `db` holding `key`.
+    pub(super) fn db_mut(&mut self, db: usize, key: &[u8]) -> &mut Db {
+        &mut self.locked[self.shards.index(key)][db]
+    }
+}
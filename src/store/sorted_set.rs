@@ -0,0 +1,531 @@
+use bytes::Bytes;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+
+/// A sorted set score. Wraps `f64` with a total ordering so it can be used as
+/// part of a `BTreeSet` key. NaN scores are rejected before they get here.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Score(pub f64);
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// `score`, with -0 made 0 so the two don't sort apart.
+fn normalize(score: f64) -> f64 {
+    if score == 0.0 {
+        0.0
+    } else {
+        score
+    }
+}
+
+/// Members ordered by `(score, member)`, with a side index from member to score
+/// so point lookups don't need to walk the ordered structure.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SortedSet {
+    scores: HashMap<Bytes, f64>,
+    ordered: BTreeSet<(Score, Bytes)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreBound {
+    value: f64,
+    exclusive: bool,
+}
+
+/// A `min max` score interval as accepted by ZCOUNT and friends, e.g. `(1 +inf`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreRange {
+    min: ScoreBound,
+    max: ScoreBound,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexBound {
+    NegInf,
+    PosInf,
+    Inclusive(Bytes),
+    Exclusive(Bytes),
+}
+
+/// A `min max` lexicographical interval as accepted by ZLEXCOUNT, e.g. `[a (c`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexRange {
+    min: LexBound,
+    max: LexBound,
+}
+
+/// How ZUNIONSTORE / ZINTERSTORE / ZDIFFSTORE combine their inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetOp {
+    Union,
+    Inter,
+    Diff,
+}
+
+/// How scores of a member present in several inputs are merged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Aggregate {
+    #[default]
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            // inf + -inf is defined as 0, as in Redis
+            Aggregate::Sum => zero_if_nan(a + b),
+            Aggregate::Min => a.min(b),
+            Aggregate::Max => a.max(b),
+        }
+    }
+}
+
+fn zero_if_nan(score: f64) -> f64 {
+    if score.is_nan() {
+        0.0
+    } else {
+        score
+    }
+}
+
+pub const ERR_NOT_A_FLOAT: &str = "ERR value is not a valid float";
+pub const ERR_SCORE_RANGE: &str = "ERR min or max is not a float";
+pub const ERR_LEX_RANGE: &str = "ERR min or max not valid string range item";
+pub const ERR_NAN_SCORE: &str = "ERR resulting score is not a number (NaN)";
+
+/// Parses a score the way Redis does: any float, plus `inf`, `+inf` and `-inf`.
+pub fn parse_score(s: &str) -> Option<f64> {
+    match s.to_lowercase().as_str() {
+        "inf" | "+inf" => Some(f64::INFINITY),
+        "-inf" => Some(f64::NEG_INFINITY),
+        other => other.parse::<f64>().ok().filter(|v| v.is_finite()),
+    }
+}
+
+/// Formats a score the way Redis replies with it, as C's `%.17g` does: 17
+/// significant digits without trailing zeros, in exponent form below 1e-4
+/// or from 1e17 up.
+pub fn format_score(score: f64) -> String {
+    if score == f64::INFINITY {
+        return "inf".to_string();
+    } else if score == f64::NEG_INFINITY {
+        return "-inf".to_string();
+    }
+    // the exponent after rounding to 17 digits picks the form
+    let scientific = format!("{:.16e}", score);
+    let (mantissa, exponent) = scientific.split_once('e').expect("an exponent");
+    let exponent: i32 = exponent.parse().expect("a decimal exponent");
+    if (-4..17).contains(&exponent) {
+        let decimals = (16 - exponent) as usize;
+        trim_zeros(&format!("{:.*}", decimals, score)).to_string()
+    } else {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", trim_zeros(mantissa), sign, exponent.abs())
+    }
+}
+
+/// `digits` without the zeros ending its fraction, or the point if nothing
+/// else is left of it.
+fn trim_zeros(digits: &str) -> &str {
+    if digits.contains('.') {
+        digits.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        digits
+    }
+}
+
+impl ScoreBound {
+    fn parse(s: &str) -> Option<ScoreBound> {
+        let (s, exclusive) = match s.strip_prefix('(') {
+            Some(rest) => (rest, true),
+            None => (s, false),
+        };
+        parse_score(s).map(|value| ScoreBound { value, exclusive })
+    }
+
+    fn below(&self, score: f64) -> bool {
+        if self.exclusive {
+            self.value < score
+        } else {
+            self.value <= score
+        }
+    }
+
+    fn above(&self, score: f64) -> bool {
+        if self.exclusive {
+            score < self.value
+        } else {
+            score <= self.value
+        }
+    }
+}
+
+impl ScoreRange {
+    pub fn parse(min: &str, max: &str) -> Option<ScoreRange> {
+        Some(ScoreRange {
+            min: ScoreBound::parse(min)?,
+            max: ScoreBound::parse(max)?,
+        })
+    }
+
+    pub fn contains(&self, score: f64) -> bool {
+        self.min.below(score) && self.max.above(score)
+    }
+}
+
+impl LexBound {
+    fn parse(s: &[u8]) -> Option<LexBound> {
+        match s.first() {
+            Some(b'-') if s.len() == 1 => Some(LexBound::NegInf),
+            Some(b'+') if s.len() == 1 => Some(LexBound::PosInf),
+            Some(b'[') => Some(LexBound::Inclusive(Bytes::copy_from_slice(&s[1..]))),
+            Some(b'(') => Some(LexBound::Exclusive(Bytes::copy_from_slice(&s[1..]))),
+            _ => None,
+        }
+    }
+
+    fn below(&self, member: &[u8]) -> bool {
+        match self {
+            LexBound::NegInf => true,
+            LexBound::PosInf => false,
+            LexBound::Inclusive(v) => &v[..] <= member,
+            LexBound::Exclusive(v) => &v[..] < member,
+        }
+    }
+
+    fn above(&self, member: &[u8]) -> bool {
+        match self {
+            LexBound::NegInf => false,
+            LexBound::PosInf => true,
+            LexBound::Inclusive(v) => member <= &v[..],
+            LexBound::Exclusive(v) => member < &v[..],
+        }
+    }
+}
+
+impl LexRange {
+    pub fn parse(min: &[u8], max: &[u8]) -> Option<LexRange> {
+        Some(LexRange {
+            min: LexBound::parse(min)?,
+            max: LexBound::parse(max)?,
+        })
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        self.min.below(member) && self.max.above(member)
+    }
+}
+
+impl SortedSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Sets the score of `member`, returning true if the member is new. A
+    /// score of -0 is kept as 0, which it equals but would sort before.
+    pub fn insert(&mut self, member: Bytes, score: f64) -> bool {
+        let score = normalize(score);
+        let previous = self.scores.insert(member.clone(), score);
+        if let Some(previous) = previous {
+            self.ordered.remove(&(Score(previous), member.clone()));
+        }
+        self.ordered.insert((Score(score), member));
+        previous.is_none()
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> Option<f64> {
+        let (member, score) = self.scores.remove_entry(member)?;
+        self.ordered.remove(&(Score(score), member));
+        Some(score)
+    }
+
+    /// Adds `delta` to the score of `member` (starting from 0 when absent) and
+    /// returns the new score. Fails without modifying the set if the result is NaN.
+    pub fn incr(&mut self, member: Bytes, delta: f64) -> Result<f64, &'static str> {
+        let score = normalize(self.score(&member).unwrap_or(0.0) + delta);
+        if score.is_nan() {
+            return Err(ERR_NAN_SCORE);
+        }
+        self.insert(member, score);
+        Ok(score)
+    }
+
+    /// Removes and returns up to `count` members with the lowest scores.
+    pub fn pop_min(&mut self, count: usize) -> Vec<(Bytes, f64)> {
+        let mut popped = Vec::with_capacity(count.min(self.len()));
+        while popped.len() < count {
+            let Some((score, member)) = self.ordered.pop_first() else {
+                break;
+            };
+            self.scores.remove(&member);
+            popped.push((member, score.0));
+        }
+        popped
+    }
+
+    /// Removes and returns up to `count` members with the highest scores.
+    pub fn pop_max(&mut self, count: usize) -> Vec<(Bytes, f64)> {
+        let mut popped = Vec::with_capacity(count.min(self.len()));
+        while popped.len() < count {
+            let Some((score, member)) = self.ordered.pop_last() else {
+                break;
+            };
+            self.scores.remove(&member);
+            popped.push((member, score.0));
+        }
+        popped
+    }
+
+    /// Iterates members in ascending `(score, member)` order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&Bytes, f64)> {
+        self.ordered.iter().map(|(score, member)| (member, score.0))
+    }
+
+    /// Iterates the members whose score falls within `range`, seeking straight
+    /// to the lower bound rather than scanning from the start.
+    pub fn range_by_score<'a>(
+        &'a self,
+        range: &'a ScoreRange,
+    ) -> impl Iterator<Item = (&'a Bytes, f64)> + 'a {
+        self.ordered
+            .range((Score(range.min.value), Bytes::new())..)
+            .skip_while(move |(score, _)| !range.min.below(score.0))
+            .take_while(move |(score, _)| range.max.above(score.0))
+            .map(|(score, member)| (member, score.0))
+    }
+
+    /// Iterates the members within the lexicographical `range`. As in Redis, the
+    /// result is only meaningful when every member shares the same score.
+    pub fn range_by_lex<'a>(
+        &'a self,
+        range: &'a LexRange,
+    ) -> impl Iterator<Item = (&'a Bytes, f64)> + 'a {
+        let start = match (&range.min, self.ordered.first()) {
+            (LexBound::Inclusive(v) | LexBound::Exclusive(v), Some((score, _))) => {
+                (*score, v.clone())
+            }
+            (_, Some((score, _))) => (*score, Bytes::new()),
+            (_, None) => (Score(f64::NEG_INFINITY), Bytes::new()),
+        };
+        self.ordered
+            .range(start..)
+            .skip_while(move |(_, member)| !range.min.below(member))
+            .take_while(move |(_, member)| range.max.above(member))
+            .map(|(score, member)| (member, score.0))
+    }
+
+    /// Combines `sources` (each paired with its weight) into a new set. Weights
+    /// are ignored for `SetOp::Diff`, which keeps the first set's scores.
+    pub fn combine(op: SetOp, sources: &[(&SortedSet, f64)], aggregate: Aggregate) -> SortedSet {
+        let mut result = SortedSet::new();
+        let Some(((first, first_weight), rest)) = sources.split_first() else {
+            return result;
+        };
+        match op {
+            SetOp::Union => {
+                let mut scores: HashMap<Bytes, f64> = HashMap::new();
+                for (zset, weight) in sources {
+                    for (member, score) in zset.iter() {
+                        let score = zero_if_nan(score * weight);
+                        scores
+                            .entry(member.clone())
+                            .and_modify(|s| *s = aggregate.apply(*s, score))
+                            .or_insert(score);
+                    }
+                }
+                for (member, score) in scores {
+                    result.insert(member, score);
+                }
+            }
+            SetOp::Inter => {
+                'members: for (member, score) in first.iter() {
+                    let mut acc = zero_if_nan(score * first_weight);
+                    for (zset, weight) in rest {
+                        match zset.score(member) {
+                            Some(score) => acc = aggregate.apply(acc, zero_if_nan(score * weight)),
+                            None => continue 'members,
+                        }
+                    }
+                    result.insert(member.clone(), acc);
+                }
+            }
+            SetOp::Diff => {
+                for (member, score) in first.iter() {
+                    if rest.iter().all(|(zset, _)| zset.score(member).is_none()) {
+                        result.insert(member.clone(), score);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    pub fn count_in_score_range(&self, range: &ScoreRange) -> usize {
+        self.range_by_score(range).count()
+    }
+
+    pub fn count_in_lex_range(&self, range: &LexRange) -> usize {
+        self.range_by_lex(range).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zset(members: &[(&'static str, f64)]) -> SortedSet {
+        let mut zset = SortedSet::new();
+        for (member, score) in members {
+            zset.insert(Bytes::from(*member), *score);
+        }
+        zset
+    }
+
+    #[test]
+    fn insert_and_update_score() {
+        let mut zset = SortedSet::new();
+        assert!(zset.insert("a".into(), 1.0));
+        assert!(!zset.insert("a".into(), 3.0));
+        assert_eq!(zset.len(), 1);
+        assert_eq!(zset.score(b"a"), Some(3.0));
+        assert_eq!(zset.iter().count(), 1);
+    }
+
+    #[test]
+    fn incr_creates_and_increments() {
+        let mut zset = SortedSet::new();
+        assert_eq!(zset.incr("a".into(), 2.5), Ok(2.5));
+        assert_eq!(zset.incr("a".into(), -1.0), Ok(1.5));
+    }
+
+    #[test]
+    fn incr_rejects_nan() {
+        let mut zset = zset(&[("a", f64::INFINITY)]);
+        assert_eq!(zset.incr("a".into(), f64::NEG_INFINITY), Err(ERR_NAN_SCORE));
+        assert_eq!(zset.score(b"a"), Some(f64::INFINITY));
+    }
+
+    #[test]
+    fn pop_min_and_max() {
+        let mut zset = zset(&[("a", 1.0), ("b", 2.0), ("c", 3.0)]);
+        assert_eq!(zset.pop_min(1), vec![(Bytes::from("a"), 1.0)]);
+        assert_eq!(
+            zset.pop_max(5),
+            vec![(Bytes::from("c"), 3.0), (Bytes::from("b"), 2.0)]
+        );
+        assert!(zset.is_empty());
+        assert_eq!(zset.score(b"b"), None);
+    }
+
+    #[test]
+    fn combine_union_with_weights() {
+        let a = zset(&[("x", 1.0), ("y", 2.0)]);
+        let b = zset(&[("y", 3.0), ("z", 4.0)]);
+        let union = SortedSet::combine(SetOp::Union, &[(&a, 1.0), (&b, 2.0)], Aggregate::Sum);
+        assert_eq!(union, zset(&[("x", 1.0), ("y", 8.0), ("z", 8.0)]));
+        let union = SortedSet::combine(SetOp::Union, &[(&a, 1.0), (&b, 1.0)], Aggregate::Max);
+        assert_eq!(union, zset(&[("x", 1.0), ("y", 3.0), ("z", 4.0)]));
+    }
+
+    #[test]
+    fn combine_inter_and_diff() {
+        let a = zset(&[("x", 1.0), ("y", 2.0)]);
+        let b = zset(&[("y", 3.0), ("z", 4.0)]);
+        let inter = SortedSet::combine(SetOp::Inter, &[(&a, 1.0), (&b, 1.0)], Aggregate::Min);
+        assert_eq!(inter, zset(&[("y", 2.0)]));
+        let diff = SortedSet::combine(SetOp::Diff, &[(&a, 1.0), (&b, 1.0)], Aggregate::Sum);
+        assert_eq!(diff, zset(&[("x", 1.0)]));
+    }
+
+    #[test]
+    fn count_in_score_range() {
+        let zset = zset(&[("a", 1.0), ("b", 2.0), ("c", 3.0), ("d", 4.0)]);
+        let count = |min, max| zset.count_in_score_range(&ScoreRange::parse(min, max).unwrap());
+        assert_eq!(count("-inf", "+inf"), 4);
+        assert_eq!(count("2", "3"), 2);
+        assert_eq!(count("(2", "3"), 1);
+        assert_eq!(count("(1", "(4"), 2);
+        assert_eq!(count("5", "10"), 0);
+    }
+
+    #[test]
+    fn negative_zero_scores_are_zero() {
+        let mut zset = zset(&[("a", -0.0), ("b", 0.0)]);
+        assert_eq!(zset.incr("c".into(), -0.0), Ok(0.0));
+        let range = ScoreRange::parse("0", "0").unwrap();
+        assert_eq!(zset.count_in_score_range(&range), 3);
+        assert_eq!(zset.range_by_score(&range).count(), 3);
+        assert!(zset.iter().all(|(_, score)| score.is_sign_positive()));
+    }
+
+    #[test]
+    fn format_score_like_printf_g() {
+        assert_eq!(format_score(1.5), "1.5");
+        assert_eq!(format_score(10.0), "10");
+        assert_eq!(format_score(-0.0), "-0");
+        assert_eq!(format_score(0.1), "0.10000000000000001");
+        assert_eq!(format_score(0.0001), "0.0001");
+        assert_eq!(format_score(0.00001), "1.0000000000000001e-05");
+        assert_eq!(format_score(1e-300), "1e-300");
+        assert_eq!(format_score(12345678901234567.0), "12345678901234568");
+        assert_eq!(format_score(1e17), "1e+17");
+        assert_eq!(format_score(1e20), "1e+20");
+        assert_eq!(format_score(-2.5e100), "-2.4999999999999999e+100");
+        assert_eq!(format_score(f64::INFINITY), "inf");
+        assert_eq!(format_score(f64::NEG_INFINITY), "-inf");
+    }
+
+    #[test]
+    fn count_in_lex_range() {
+        let zset = zset(&[("a", 0.0), ("b", 0.0), ("c", 0.0), ("d", 0.0)]);
+        let count = |min: &str, max: &str| {
+            zset.count_in_lex_range(&LexRange::parse(min.as_bytes(), max.as_bytes()).unwrap())
+        };
+        assert_eq!(count("-", "+"), 4);
+        assert_eq!(count("[b", "[c"), 2);
+        assert_eq!(count("(b", "[d"), 2);
+        assert_eq!(count("-", "(c"), 2);
+        assert_eq!(count("+", "-"), 0);
+    }
+
+    #[test]
+    fn parse_invalid_ranges() {
+        assert!(ScoreRange::parse("a", "1").is_none());
+        assert!(ScoreRange::parse("1", "nan").is_none());
+        assert!(LexRange::parse(b"a", b"+").is_none());
+    }
+
+    #[test]
+    fn format_scores() {
+        assert_eq!(format_score(3.0), "3");
+        assert_eq!(format_score(2.5), "2.5");
+        assert_eq!(format_score(f64::INFINITY), "inf");
+        assert_eq!(format_score(f64::NEG_INFINITY), "-inf");
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::Value;
+
+#[derive(Debug)]
+struct Counters {
+    started: Instant,
+    connections_received: AtomicU64,
+    rejected_connections: AtomicU64,
+    commands_processed: AtomicU64,
+    keyspace_hits: AtomicU64,
+    keyspace_misses: AtomicU64,
+    expired_keys: AtomicU64,
+    evicted_keys: AtomicU64,
+    /// Keyed by the command's lowercase name.
+    commands: Mutex<HashMap<String, CommandStats>>,
+}
+
+/// The upper bounds of the latency histogram's buckets, in microseconds.
+pub const LATENCY_BUCKETS: [u64; 13] = [
+    100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000,
+    1_000_000,
+];
+
+/// How often one command ran and how long it took.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandStats {
+    pub calls: u64,
+    pub usec: u64,
+    /// Calls that took at most each of `LATENCY_BUCKETS`, counted in the first
+    /// bucket they fit; slower calls are only in `calls`.
+    pub buckets: [u64; LATENCY_BUCKETS.len()],
+}
+
+/// Server-wide counters since startup, as INFO stats reports them.
+#[derive(Debug, Clone)]
+pub struct Stats {
+    counters: Arc<Counters>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            counters: Arc::new(Counters {
+                started: Instant::now(),
+                connections_received: Default::default(),
+                rejected_connections: Default::default(),
+                commands_processed: Default::default(),
+                keyspace_hits: Default::default(),
+                keyspace_misses: Default::default(),
+                expired_keys: Default::default(),
+                evicted_keys: Default::default(),
+                commands: Default::default(),
+            }),
+        }
+    }
+}
+
+impl Stats {
+    pub fn uptime(&self) -> Duration {
+        self.counters.started.elapsed()
+    }
+
+    pub fn connection_received(&self) {
+        self.counters
+            .connections_received
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connections_received(&self) -> u64 {
+        self.counters.connections_received.load(Ordering::Relaxed)
+    }
+
+    /// Counts a connection turned away for going over `maxclients`.
+    pub fn connection_rejected(&self) {
+        self.counters
+            .rejected_connections
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn rejected_connections(&self) -> u64 {
+        self.counters.rejected_connections.load(Ordering::Relaxed)
+    }
+
+    pub fn command_processed(&self) {
+        self.counters
+            .commands_processed
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn commands_processed(&self) -> u64 {
+        self.counters.commands_processed.load(Ordering::Relaxed)
+    }
+
+    /// Counts a read of a key, as a hit if it existed.
+    pub fn lookup(&self, hit: bool) {
+        let counter = match hit {
+            true => &self.counters.keyspace_hits,
+            false => &self.counters.keyspace_misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn keyspace_hits(&self) -> u64 {
+        self.counters.keyspace_hits.load(Ordering::Relaxed)
+    }
+
+    pub fn keyspace_misses(&self) -> u64 {
+        self.counters.keyspace_misses.load(Ordering::Relaxed)
+    }
+
+    pub fn key_expired(&self) {
+        self.counters.expired_keys.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn expired_keys(&self) -> u64 {
+        self.counters.expired_keys.load(Ordering::Relaxed)
+    }
+
+    pub fn key_evicted(&self) {
+        self.counters.evicted_keys.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn evicted_keys(&self) -> u64 {
+        self.counters.evicted_keys.load(Ordering::Relaxed)
+    }
+
+    /// Counts a run of the command `name` that took `took`.
+    pub fn command_run(&self, name: &str, took: Duration) {
+        let usec = took.as_micros().try_into().unwrap_or(u64::MAX);
+        let mut commands = self.counters.commands.lock().unwrap();
+        let stats = match commands.get_mut(name) {
+            Some(stats) => stats,
+            None => commands.entry(name.to_string()).or_default(),
+        };
+        stats.calls += 1;
+        stats.usec = stats.usec.saturating_add(usec);
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|&bound| usec <= bound) {
+            stats.buckets[bucket] += 1;
+        }
+    }
+
+    /// Every command that has run, by name.
+    pub fn commands(&self) -> Vec<(String, CommandStats)> {
+        let mut commands: Vec<_> = self
+            .counters
+            .commands
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, stats)| (name.clone(), stats.clone()))
+            .collect();
+        commands.sort_by(|a, b| a.0.cmp(&b.0));
+        commands
+    }
+}
+
+/// What INFO keyspace says about one database.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DbStats {
+    pub keys: usize,
+    /// Keys with a time to live.
+    pub expires: usize,
+    /// The average time to live of those keys, in milliseconds.
+    pub avg_ttl: u64,
+}
+
+/// The types of value a key can hold, as the keyspace stats name them.
+pub const TYPES: [&str; 3] = ["string", "zset", "stream"];
+
+/// How many keys in a database hold each type and their total size, see
+/// `Value::size`, indexed like `TYPES`. Kept up to date as keys are
+/// written, so they are read without going over the keys; expired keys
+/// count until they are removed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TypeStats {
+    pub keys: [usize; TYPES.len()],
+    pub sizes: [usize; TYPES.len()],
+}
+
+impl TypeStats {
+    pub(crate) fn add(&mut self, value: &Value) {
+        let i = type_index(value);
+        self.keys[i] += 1;
+        self.sizes[i] += value.size();
+    }
+
+    pub(crate) fn remove(&mut self, value: &Value) {
+        let i = type_index(value);
+        self.keys[i] -= 1;
+        self.sizes[i] -= value.size();
+    }
+
+    /// Accounts for `value` having changed in place from `before` in size.
+    pub(crate) fn resize(&mut self, value: &Value, before: usize) {
+        let i = type_index(value);
+        self.sizes[i] = self.sizes[i] - before + value.size();
+    }
+
+    pub(crate) fn merge(&mut self, other: &TypeStats) {
+        for i in 0..TYPES.len() {
+            self.keys[i] += other.keys[i];
+            self.sizes[i] += other.sizes[i];
+        }
+    }
+
+    /// Each type that any key holds, with its keys and size.
+    pub fn held(&self) -> impl Iterator<Item = (&'static str, usize, usize)> + '_ {
+        (0..TYPES.len())
+            .filter(|&i| self.keys[i] > 0)
+            .map(|i| (TYPES[i], self.keys[i], self.sizes[i]))
+    }
+}
+
+pub(super) fn type_index(value: &Value) -> usize {
+    match value {
+        Value::String(_) => 0,
+        Value::SortedSet(_) => 1,
+        Value::Stream(_) => 2,
+    }
+}
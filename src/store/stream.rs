@@ -0,0 +1,650 @@
+use bytes::Bytes;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::consumer_group::{ClaimOptions, Claimed, ConsumerGroup};
+
+/// A stream entry ID, `<milliseconds>-<sequence>`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+/// The ID argument of XADD: fully automatic, automatic sequence, or explicit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdSpec {
+    Auto,
+    AutoSeq(u64),
+    Explicit(StreamId),
+}
+
+pub type Fields = Vec<(Bytes, Bytes)>;
+
+/// What XTRIM (or XADD's trimming options) should keep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrimStrategy {
+    /// Keep at most this many entries.
+    MaxLen(u64),
+    /// Drop entries with an ID lower than this one.
+    MinId(StreamId),
+}
+
+/// A trim request. We have no radix-tree nodes to trim whole, so `~` is
+/// approximated by letting the trim stop early once `limit` entries are gone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trim {
+    pub strategy: TrimStrategy,
+    pub approximate: bool,
+    pub limit: Option<u64>,
+}
+
+/// Default LIMIT for approximate trims: 100 * stream-node-max-entries.
+pub const DEFAULT_TRIM_LIMIT: u64 = 100 * 100;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, Fields>,
+    last_id: StreamId,
+    groups: BTreeMap<Bytes, ConsumerGroup>,
+}
+
+pub const ERR_INVALID_ID: &str = "ERR Invalid stream ID specified as stream command argument";
+pub const ERR_ID_TOO_SMALL: &str =
+    "ERR The ID specified in XADD is equal or smaller than the target stream top item";
+pub const ERR_ID_ZERO: &str = "ERR The ID specified in XADD must be greater than 0-0";
+pub const ERR_INVALID_START: &str = "ERR invalid start ID for the interval";
+pub const ERR_INVALID_END: &str = "ERR invalid end ID for the interval";
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    pub fn new(ms: u64, seq: u64) -> Self {
+        Self { ms, seq }
+    }
+
+    /// Parses `ms-seq`, or a bare `ms` with the sequence defaulting to `default_seq`.
+    pub fn parse(s: &str, default_seq: u64) -> Option<StreamId> {
+        match s.split_once('-') {
+            Some((ms, seq)) => Some(StreamId::new(ms.parse().ok()?, seq.parse().ok()?)),
+            None => Some(StreamId::new(s.parse().ok()?, default_seq)),
+        }
+    }
+
+    /// Parses an XRANGE interval bound: `-`, `+`, a full or partial ID, or an
+    /// exclusive `(`-prefixed ID. A partial ID covers its whole millisecond, so
+    /// its missing sequence is 0 for a start bound and the maximum for an end.
+    pub fn parse_bound(s: &str, is_start: bool) -> Result<StreamId, &'static str> {
+        let (s, exclusive) = match s.strip_prefix('(') {
+            Some(rest) => (rest, true),
+            None => (s, false),
+        };
+        let id = match s {
+            "-" => StreamId::MIN,
+            "+" => StreamId::MAX,
+            _ => StreamId::parse(s, if is_start { 0 } else { u64::MAX }).ok_or(ERR_INVALID_ID)?,
+        };
+        match (exclusive, is_start) {
+            (false, _) => Ok(id),
+            (true, true) => id.next().ok_or(ERR_INVALID_START),
+            (true, false) => id.prev().ok_or(ERR_INVALID_END),
+        }
+    }
+
+    /// The smallest ID strictly greater than this one, if any.
+    pub fn next(&self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => self.ms.checked_add(1).map(|ms| StreamId::new(ms, 0)),
+        }
+    }
+
+    /// The largest ID strictly smaller than this one, if any.
+    pub fn prev(&self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => self.ms.checked_sub(1).map(|ms| StreamId::new(ms, u64::MAX)),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+impl IdSpec {
+    pub fn parse(s: &str) -> Option<IdSpec> {
+        if s == "*" {
+            return Some(IdSpec::Auto);
+        }
+        match s.split_once('-') {
+            Some((ms, "*")) => ms.parse().ok().map(IdSpec::AutoSeq),
+            _ => StreamId::parse(s, 0).map(IdSpec::Explicit),
+        }
+    }
+}
+
+pub(crate) fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+impl Stream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rebuilds a stream from its parts, as loaded from an RDB file.
+    pub fn restore(
+        entries: BTreeMap<StreamId, Fields>,
+        last_id: StreamId,
+        groups: BTreeMap<Bytes, ConsumerGroup>,
+    ) -> Self {
+        Self {
+            entries,
+            last_id,
+            groups,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The ID of the last entry ever added, even if it has since been deleted.
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    /// Resolves `spec` against the current top ID, enforcing that IDs only grow.
+    pub fn next_id(&self, spec: IdSpec) -> Result<StreamId, &'static str> {
+        let last = self.last_id;
+        let id = match spec {
+            IdSpec::Auto => {
+                let ms = now_ms();
+                if ms > last.ms {
+                    StreamId::new(ms, 0)
+                } else {
+                    last.next().ok_or(ERR_ID_TOO_SMALL)?
+                }
+            }
+            IdSpec::AutoSeq(ms) if ms == last.ms => last.next().ok_or(ERR_ID_TOO_SMALL)?,
+            IdSpec::AutoSeq(ms) => StreamId::new(ms, if ms == 0 { 1 } else { 0 }),
+            IdSpec::Explicit(id) => id,
+        };
+        if id == StreamId::MIN {
+            return Err(ERR_ID_ZERO);
+        }
+        if id <= last {
+            return Err(ERR_ID_TOO_SMALL);
+        }
+        Ok(id)
+    }
+
+    /// Appends an entry, returning the ID it was stored under.
+    pub fn add(&mut self, spec: IdSpec, fields: Fields) -> Result<StreamId, &'static str> {
+        let id = self.next_id(spec)?;
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        Ok(id)
+    }
+
+    pub fn get(&self, id: &StreamId) -> Option<&Fields> {
+        self.entries.get(id)
+    }
+
+    pub fn first_id(&self) -> Option<StreamId> {
+        self.entries.keys().next().copied()
+    }
+
+    /// Deletes the given entries, returning how many existed.
+    pub fn delete(&mut self, ids: &[StreamId]) -> usize {
+        ids.iter()
+            .filter(|id| self.entries.remove(id).is_some())
+            .count()
+    }
+
+    /// Evicts the oldest entries per `trim`, returning how many were removed.
+    pub fn trim(&mut self, trim: &Trim) -> usize {
+        let limit = match (trim.approximate, trim.limit) {
+            (false, _) | (true, Some(0)) => u64::MAX,
+            (true, limit) => limit.unwrap_or(DEFAULT_TRIM_LIMIT),
+        };
+        let mut removed = 0;
+        while (removed as u64) < limit {
+            let Some(first) = self.first_id() else {
+                break;
+            };
+            let evict = match trim.strategy {
+                TrimStrategy::MaxLen(max) => self.len() as u64 > max,
+                TrimStrategy::MinId(min) => first < min,
+            };
+            if !evict {
+                break;
+            }
+            self.entries.remove(&first);
+            removed += 1;
+        }
+        removed
+    }
+
+    /// Entries with IDs in `start..=end`, looked up through the ordered index.
+    pub fn range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+    ) -> impl DoubleEndedIterator<Item = (&StreamId, &Fields)> {
+        // BTreeMap::range panics on inverted bounds, so collapse those to a range
+        // that the filter then empties
+        let lower = start.min(end);
+        self.entries
+            .range(lower..=end)
+            .filter(move |(id, _)| start <= **id)
+    }
+
+    pub fn groups(&self) -> &BTreeMap<Bytes, ConsumerGroup> {
+        &self.groups
+    }
+
+    pub fn group(&self, name: &[u8]) -> Option<&ConsumerGroup> {
+        self.groups.get(name)
+    }
+
+    pub fn group_mut(&mut self, name: &[u8]) -> Option<&mut ConsumerGroup> {
+        self.groups.get_mut(name)
+    }
+
+    /// Creates a group that will deliver entries after `last_delivered`,
+    /// returning false if the name is taken.
+    pub fn create_group(&mut self, name: Bytes, last_delivered: StreamId) -> bool {
+        if self.groups.contains_key(&name) {
+            return false;
+        }
+        self.groups.insert(name, ConsumerGroup::new(last_delivered));
+        true
+    }
+
+    pub fn destroy_group(&mut self, name: &[u8]) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    /// Delivers up to `count` never-delivered entries to `consumer`, advancing
+    /// the group and, unless `noack`, adding them to its pending entries list.
+    /// Returns `None` if the group does not exist.
+    pub fn read_group_new(
+        &mut self,
+        group: &[u8],
+        consumer: &Bytes,
+        count: Option<usize>,
+        noack: bool,
+        now: u64,
+    ) -> Option<Vec<Claimed>> {
+        let state = self.groups.get_mut(group)?;
+        state.touch_consumer(consumer, now);
+        let Some(start) = state.last_delivered.next() else {
+            return Some(vec![]);
+        };
+        let mut delivered = vec![];
+        for (id, fields) in self
+            .entries
+            .range(start..)
+            .take(count.unwrap_or(usize::MAX))
+        {
+            state.last_delivered = *id;
+            let delivery_count = if noack {
+                0
+            } else {
+                state.deliver(*id, consumer, now);
+                state.pending()[id].delivery_count
+            };
+            delivered.push(Claimed {
+                id: *id,
+                fields: fields.clone(),
+                delivery_time: now,
+                delivery_count,
+            });
+        }
+        Some(delivered)
+    }
+
+    /// XCLAIM: transfers the given pending entries to `consumer`. Returns
+    /// `None` if the group does not exist.
+    pub fn claim(
+        &mut self,
+        group: &[u8],
+        consumer: &Bytes,
+        ids: &[StreamId],
+        options: &ClaimOptions,
+        now: u64,
+    ) -> Option<Vec<Claimed>> {
+        let state = self.groups.get_mut(group)?;
+        if let Some(last_id) = options.last_id {
+            state.last_delivered = state.last_delivered.max(last_id);
+        }
+        state.touch_consumer(consumer, now);
+        let mut claimed = vec![];
+        for id in ids {
+            let fields = self.entries.get(id);
+            if let Some((delivery_time, delivery_count)) =
+                state.try_claim(*id, consumer, options, now, fields.is_some())
+            {
+                claimed.push(Claimed {
+                    id: *id,
+                    fields: fields.cloned().unwrap_or_default(),
+                    delivery_time,
+                    delivery_count,
+                });
+            }
+        }
+        Some(claimed)
+    }
+
+    /// XAUTOCLAIM: scans the pending entries list from `start`, claiming up to
+    /// `count` entries idle for at least `options.min_idle` and dropping ones
+    /// deleted from the stream. Gives up after `count * 10` attempts. Returns
+    /// the cursor to continue from (`0-0` when done), the claimed entries and
+    /// the deleted IDs, or `None` if the group does not exist.
+    pub fn autoclaim(
+        &mut self,
+        group: &[u8],
+        consumer: &Bytes,
+        start: StreamId,
+        count: usize,
+        options: &ClaimOptions,
+        now: u64,
+    ) -> Option<(StreamId, Vec<Claimed>, Vec<StreamId>)> {
+        let state = self.groups.get_mut(group)?;
+        state.touch_consumer(consumer, now);
+        let attempts = count.saturating_mul(10);
+        let candidates = state
+            .pending()
+            .range(start..)
+            .map(|(id, _)| *id)
+            .take(attempts.saturating_add(1))
+            .collect::<Vec<_>>();
+
+        let (mut next, mut claimed, mut deleted) = (StreamId::MIN, vec![], vec![]);
+        for (attempt, id) in candidates.into_iter().enumerate() {
+            if claimed.len() == count || attempt == attempts {
+                next = id;
+                break;
+            }
+            let Some(fields) = self.entries.get(&id) else {
+                state.ack(&id);
+                deleted.push(id);
+                continue;
+            };
+            if let Some((delivery_time, delivery_count)) =
+                state.try_claim(id, consumer, options, now, true)
+            {
+                claimed.push(Claimed {
+                    id,
+                    fields: fields.clone(),
+                    delivery_time,
+                    delivery_count,
+                });
+            }
+        }
+        Some((next, claimed, deleted))
+    }
+
+    /// Returns up to `count` of `consumer`'s pending entries with IDs above
+    /// `after`. Entries deleted from the stream since delivery have no fields.
+    pub fn read_group_pending(
+        &mut self,
+        group: &[u8],
+        consumer: &Bytes,
+        after: StreamId,
+        count: Option<usize>,
+        now: u64,
+    ) -> Option<Vec<(StreamId, Option<Fields>)>> {
+        let state = self.groups.get_mut(group)?;
+        state.touch_consumer(consumer, now);
+        let Some(start) = after.next() else {
+            return Some(vec![]);
+        };
+        let owned = &state.consumers()[consumer].pending;
+        Some(
+            owned
+                .range(start..)
+                .take(count.unwrap_or(usize::MAX))
+                .map(|id| (*id, self.entries.get(id).cloned()))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields() -> Fields {
+        vec![("f".into(), "v".into())]
+    }
+
+    #[test]
+    fn parse_id_spec() {
+        assert_eq!(IdSpec::parse("*"), Some(IdSpec::Auto));
+        assert_eq!(IdSpec::parse("5-*"), Some(IdSpec::AutoSeq(5)));
+        assert_eq!(
+            IdSpec::parse("5-3"),
+            Some(IdSpec::Explicit(StreamId::new(5, 3)))
+        );
+        assert_eq!(
+            IdSpec::parse("5"),
+            Some(IdSpec::Explicit(StreamId::new(5, 0)))
+        );
+        assert_eq!(IdSpec::parse("a-1"), None);
+        assert_eq!(IdSpec::parse("1-"), None);
+    }
+
+    #[test]
+    fn explicit_ids_must_increase() {
+        let mut stream = Stream::new();
+        assert_eq!(
+            stream.add(IdSpec::parse("0-0").unwrap(), fields()),
+            Err(ERR_ID_ZERO)
+        );
+        assert_eq!(
+            stream.add(IdSpec::parse("1-1").unwrap(), fields()),
+            Ok(StreamId::new(1, 1))
+        );
+        assert_eq!(
+            stream.add(IdSpec::parse("1-1").unwrap(), fields()),
+            Err(ERR_ID_TOO_SMALL)
+        );
+        assert_eq!(
+            stream.add(IdSpec::parse("0-5").unwrap(), fields()),
+            Err(ERR_ID_TOO_SMALL)
+        );
+        assert_eq!(stream.len(), 1);
+    }
+
+    #[test]
+    fn auto_sequence() {
+        let mut stream = Stream::new();
+        assert_eq!(
+            stream.add(IdSpec::AutoSeq(0), fields()),
+            Ok(StreamId::new(0, 1))
+        );
+        assert_eq!(
+            stream.add(IdSpec::AutoSeq(0), fields()),
+            Ok(StreamId::new(0, 2))
+        );
+        assert_eq!(
+            stream.add(IdSpec::AutoSeq(7), fields()),
+            Ok(StreamId::new(7, 0))
+        );
+        assert_eq!(
+            stream.add(IdSpec::AutoSeq(6), fields()),
+            Err(ERR_ID_TOO_SMALL)
+        );
+    }
+
+    #[test]
+    fn parse_bounds() {
+        assert_eq!(StreamId::parse_bound("-", true), Ok(StreamId::MIN));
+        assert_eq!(StreamId::parse_bound("+", false), Ok(StreamId::MAX));
+        assert_eq!(StreamId::parse_bound("5", true), Ok(StreamId::new(5, 0)));
+        assert_eq!(
+            StreamId::parse_bound("5", false),
+            Ok(StreamId::new(5, u64::MAX))
+        );
+        assert_eq!(StreamId::parse_bound("(5-1", true), Ok(StreamId::new(5, 2)));
+        assert_eq!(
+            StreamId::parse_bound("(5-0", false),
+            Ok(StreamId::new(4, u64::MAX))
+        );
+        assert_eq!(StreamId::parse_bound("(+", true), Err(ERR_INVALID_START));
+        assert_eq!(StreamId::parse_bound("(-", false), Err(ERR_INVALID_END));
+        assert_eq!(StreamId::parse_bound("x", true), Err(ERR_INVALID_ID));
+    }
+
+    #[test]
+    fn range_is_inclusive_and_handles_inverted_bounds() {
+        let mut stream = Stream::new();
+        for seq in 1..=5 {
+            stream
+                .add(IdSpec::Explicit(StreamId::new(1, seq)), fields())
+                .unwrap();
+        }
+        let ids = |start, end| {
+            stream
+                .range(start, end)
+                .map(|(id, _)| id.seq)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(StreamId::new(1, 2), StreamId::new(1, 4)), vec![2, 3, 4]);
+        assert!(ids(StreamId::new(1, 4), StreamId::new(1, 2)).is_empty());
+    }
+
+    #[test]
+    fn delete_keeps_last_id() {
+        let mut stream = Stream::new();
+        stream
+            .add(IdSpec::Explicit(StreamId::new(1, 1)), fields())
+            .unwrap();
+        assert_eq!(
+            stream.delete(&[StreamId::new(1, 1), StreamId::new(9, 9)]),
+            1
+        );
+        assert!(stream.is_empty());
+        assert_eq!(stream.last_id(), StreamId::new(1, 1));
+    }
+
+    #[test]
+    fn trim_maxlen_minid_and_limit() {
+        let mut stream = Stream::new();
+        for seq in 1..=10 {
+            stream
+                .add(IdSpec::Explicit(StreamId::new(1, seq)), fields())
+                .unwrap();
+        }
+        let trim = |strategy, approximate, limit| Trim {
+            strategy,
+            approximate,
+            limit,
+        };
+        assert_eq!(stream.trim(&trim(TrimStrategy::MaxLen(8), false, None)), 2);
+        assert_eq!(
+            stream.trim(&trim(
+                TrimStrategy::MinId(StreamId::new(1, 6)),
+                true,
+                Some(1)
+            )),
+            1
+        );
+        assert_eq!(stream.first_id(), Some(StreamId::new(1, 4)));
+        assert_eq!(
+            stream.trim(&trim(TrimStrategy::MinId(StreamId::new(1, 6)), false, None)),
+            2
+        );
+        assert_eq!(stream.len(), 5);
+    }
+
+    #[test]
+    fn read_group_delivers_new_entries_once() {
+        let mut stream = Stream::new();
+        for seq in 1..=3 {
+            stream
+                .add(IdSpec::Explicit(StreamId::new(1, seq)), fields())
+                .unwrap();
+        }
+        let alice = Bytes::from("alice");
+        assert!(stream
+            .read_group_new(b"g", &alice, None, false, 0)
+            .is_none());
+        assert!(stream.create_group("g".into(), StreamId::MIN));
+        assert!(!stream.create_group("g".into(), StreamId::MIN));
+
+        let first = stream.read_group_new(b"g", &alice, Some(2), false, 0);
+        assert_eq!(first.map(|e| e.len()), Some(2));
+        let rest = stream.read_group_new(b"g", &alice, None, false, 0).unwrap();
+        assert_eq!(rest[0].id, StreamId::new(1, 3));
+        assert!(stream
+            .read_group_new(b"g", &alice, None, false, 0)
+            .unwrap()
+            .is_empty());
+
+        stream.delete(&[StreamId::new(1, 2)]);
+        let history = stream
+            .read_group_pending(b"g", &alice, StreamId::MIN, None, 0)
+            .unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[1], (StreamId::new(1, 2), None));
+    }
+
+    #[test]
+    fn autoclaim_pages_through_pending_entries() {
+        let mut stream = Stream::new();
+        for seq in 1..=5 {
+            stream
+                .add(IdSpec::Explicit(StreamId::new(1, seq)), fields())
+                .unwrap();
+        }
+        stream.create_group("g".into(), StreamId::MIN);
+        let (alice, bob) = (Bytes::from("alice"), Bytes::from("bob"));
+        stream.read_group_new(b"g", &alice, None, false, 0);
+        stream.delete(&[StreamId::new(1, 2)]);
+
+        let options = ClaimOptions::default();
+        let (next, claimed, deleted) = stream
+            .autoclaim(b"g", &bob, StreamId::MIN, 2, &options, 10)
+            .unwrap();
+        assert_eq!(next, StreamId::new(1, 4));
+        assert_eq!(
+            claimed.iter().map(|c| c.id.seq).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+        assert_eq!(deleted, vec![StreamId::new(1, 2)]);
+
+        let (next, claimed, _) = stream.autoclaim(b"g", &bob, next, 2, &options, 10).unwrap();
+        assert_eq!(next, StreamId::MIN);
+        assert_eq!(claimed.len(), 2);
+        assert_eq!(stream.group(b"g").unwrap().pending().len(), 4);
+    }
+
+    #[test]
+    fn auto_id_never_goes_backwards() {
+        let mut stream = Stream::new();
+        let future = StreamId::new(now_ms() + 60_000, 4);
+        stream.add(IdSpec::Explicit(future), fields()).unwrap();
+        assert_eq!(
+            stream.add(IdSpec::Auto, fields()),
+            Ok(StreamId::new(future.ms, 5))
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! Server-assisted client-side caching, as CLIENT TRACKING turns on: which
+//! clients may hold copies of which keys, and telling them once those keys
+//! change so they drop their copies.
+
+use bytes::Bytes;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use super::events::Event;
+
+/// How a client tracks keys, as CLIENT TRACKING ON sets it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Options {
+    /// The client told about changes in this one's place.
+    pub redirect: Option<u64>,
+    /// Told about every key starting with one of `prefixes`, read or not.
+    pub bcast: bool,
+    /// Only for BCAST, where none means every key.
+    pub prefixes: Vec<Bytes>,
+    /// Not told about changes the client made itself.
+    pub noloop: bool,
+}
+
+#[derive(Debug, Default)]
+struct Table {
+    trackers: HashMap<u64, Options>,
+    /// Keys read by clients tracking what they read, with those clients. A
+    /// key is dropped once they have been told it changed, until read again.
+    keys: HashMap<Bytes, HashSet<u64>>,
+    inboxes: HashMap<u64, UnboundedSender<Bytes>>,
+}
+
+impl Table {
+    fn untrack(&mut self, client: u64) -> bool {
+        if self.trackers.remove(&client).is_none() {
+            return false;
+        }
+        self.keys.retain(|_, readers| {
+            readers.remove(&client);
+            !readers.is_empty()
+        });
+        true
+    }
+}
+
+/// The tracking table every connection shares.
+#[derive(Debug, Clone, Default)]
+pub struct Tracking {
+    table: Arc<Mutex<Table>>,
+    /// Clients with tracking on, so that changes skip the table while none are.
+    trackers: Arc<AtomicUsize>,
+}
+
+/// Where one connection receives the keys it is told have changed. Dropping
+/// it turns the client's tracking off.
+#[derive(Debug)]
+pub struct Inbox {
+    tracking: Tracking,
+    client: u64,
+    receiver: UnboundedReceiver<Bytes>,
+}
+
+impl Tracking {
+    pub fn inbox(&self, client: u64) -> Inbox {
+        let (sender, receiver) = unbounded_channel();
+        self.table.lock().unwrap().inboxes.insert(client, sender);
+        Inbox {
+            tracking: self.clone(),
+            client,
+            receiver,
+        }
+    }
+
+    /// Turns tracking on for `client`, or changes how it tracks, returning
+    /// the error to reply with if the client to redirect to isn't connected.
+    pub fn enable(&self, client: u64, options: Options) -> Result<(), &'static str> {
+        let mut table = self.table.lock().unwrap();
+        if let Some(target) = options.redirect {
+            if !table.inboxes.contains_key(&target) {
+                return Err("ERR The client ID you want redirect to does not exist");
+            }
+        }
+        // keys read under the old options no longer apply
+        if table.untrack(client) {
+            self.trackers.fetch_sub(1, Ordering::Relaxed);
+        }
+        table.trackers.insert(client, options);
+        self.trackers.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn disable(&self, client: u64) {
+        if self.table.lock().unwrap().untrack(client) {
+            self.trackers.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether any client has tracking on.
+    pub fn is_active(&self) -> bool {
+        self.trackers.load(Ordering::Relaxed) > 0
+    }
+
+    /// How `client` tracks keys, if it does.
+    pub fn options(&self, client: u64) -> Option<Options> {
+        if !self.is_active() {
+            return None;
+        }
+        self.table.lock().unwrap().trackers.get(&client).cloned()
+    }
+
+    /// Records that `client` read `keys`, if it tracks the keys it reads.
+    pub fn record_reads<'a>(&self, client: u64, keys: impl IntoIterator<Item = &'a [u8]>) {
+        if !self.is_active() {
+            return;
+        }
+        let mut table = self.table.lock().unwrap();
+        // BCAST clients hear about keys by prefix instead
+        if table
+            .trackers
+            .get(&client)
+            .is_none_or(|options| options.bcast)
+        {
+            return;
+        }
+        for key in keys {
+            table
+                .keys
+                .entry(Bytes::copy_from_slice(key))
+                .or_default()
+                .insert(client);
+        }
+    }
+
+    /// Tells the clients tracking `key` that `origin` changed it.
+    fn invalidate(&self, key: &Bytes, origin: Option<u64>) {
+        if !self.is_active() {
+            return;
+        }
+        let mut table = self.table.lock().unwrap();
+        let Table {
+            trackers,
+            keys,
+            inboxes,
+        } = &mut *table;
+        let readers = keys.remove(key).unwrap_or_default();
+        for (client, options) in trackers.iter() {
+            let interested = if options.bcast {
+                options.prefixes.is_empty()
+                    || options
+                        .prefixes
+                        .iter()
+                        .any(|prefix| key.starts_with(prefix))
+            } else {
+                readers.contains(client)
+            };
+            if !interested || (options.noloop && origin == Some(*client)) {
+                continue;
+            }
+            if let Some(inbox) = inboxes.get(&options.redirect.unwrap_or(*client)) {
+                let _ = inbox.send(key.clone());
+            }
+        }
+    }
+}
+
+impl Inbox {
+    /// Waits for the next key this connection is told changed.
+    pub async fn recv(&mut self) -> Option<Bytes> {
+        self.receiver.recv().await
+    }
+}
+
+impl Drop for Inbox {
+    fn drop(&mut self) {
+        let mut table = self.tracking.table.lock().unwrap();
+        table.inboxes.remove(&self.client);
+        if table.untrack(self.client) {
+            self.tracking.trackers.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Invalidates keys for the clients tracking them as they change.
+pub(super) fn invalidations(tracking: Tracking) -> impl Fn(&Event) + Send + Sync {
+    move |event| tracking.invalidate(event.key, event.client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn readers_are_told_once_about_a_change() {
+        let tracking = Tracking::default();
+        let mut inbox = tracking.inbox(1);
+        tracking.enable(1, Options::default()).unwrap();
+        let key = Bytes::from("k");
+
+        tracking.record_reads(1, [&b"k"[..]]);
+        tracking.invalidate(&key, Some(2));
+        tracking.invalidate(&key, Some(2));
+        assert_eq!(inbox.recv().await, Some(key.clone()));
+        assert!(inbox.receiver.try_recv().is_err());
+
+        // nothing is recorded once tracking is off
+        tracking.disable(1);
+        tracking.record_reads(1, [&b"k"[..]]);
+        tracking.invalidate(&key, Some(2));
+        assert!(inbox.receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn broadcasts_go_to_the_redirect_target() {
+        let tracking = Tracking::default();
+        let _inbox = tracking.inbox(1);
+        let mut target = tracking.inbox(2);
+        let options = Options {
+            redirect: Some(2),
+            bcast: true,
+            prefixes: vec![Bytes::from("user:")],
+            noloop: true,
+        };
+        assert!(tracking
+            .enable(
+                1,
+                Options {
+                    redirect: Some(3),
+                    ..options.clone()
+                }
+            )
+            .is_err());
+        tracking.enable(1, options).unwrap();
+
+        tracking.invalidate(&Bytes::from("other"), Some(2));
+        tracking.invalidate(&Bytes::from("user:1"), Some(1));
+        tracking.invalidate(&Bytes::from("user:2"), Some(2));
+        assert_eq!(target.recv().await, Some(Bytes::from("user:2")));
+        assert!(target.receiver.try_recv().is_err());
+    }
+}
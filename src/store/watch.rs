@@ -0,0 +1,116 @@
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::DbKey;
+
+/// Registry of keys watched by clients with WATCH, by database and key.
+///
+/// Each client owns one dirty flag, registered under every key it watches.
+/// Writers call `touch` after changing a key, and EXEC refuses to run a
+/// transaction whose flag has been set.
+#[derive(Debug, Clone, Default)]
+pub struct Watchers {
+    keys: Arc<Mutex<HashMap<DbKey, Vec<Arc<AtomicBool>>>>>,
+}
+
+/// One connection's watched keys. Dropping it unwatches everything.
+#[derive(Debug)]
+pub struct WatchedKeys {
+    watchers: Watchers,
+    keys: Vec<DbKey>,
+    dirty: Arc<AtomicBool>,
+}
+
+impl Watchers {
+    pub fn watched_keys(&self) -> WatchedKeys {
+        WatchedKeys {
+            watchers: self.clone(),
+            keys: vec![],
+            dirty: Default::default(),
+        }
+    }
+
+    /// Marks every client watching `key` in database `db` as dirty.
+    pub fn touch(&self, db: usize, key: &Bytes) {
+        let map = self.keys.lock().unwrap();
+        if let Some(flags) = map.get(&(db, key.clone())) {
+            for flag in flags {
+                flag.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl WatchedKeys {
+    pub fn watch(&mut self, db: usize, keys: Vec<Bytes>) {
+        let mut map = self.watchers.keys.lock().unwrap();
+        for key in keys {
+            let key = (db, key);
+            if !self.keys.contains(&key) {
+                map.entry(key.clone()).or_default().push(self.dirty.clone());
+                self.keys.push(key);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Whether a watched key changed since it was watched.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.load(Ordering::Relaxed)
+    }
+
+    /// Forgets every watched key and clears the dirty flag.
+    pub fn unwatch(&mut self) {
+        let mut map = self.watchers.keys.lock().unwrap();
+        for key in self.keys.drain(..) {
+            if let Some(flags) = map.get_mut(&key) {
+                flags.retain(|flag| !Arc::ptr_eq(flag, &self.dirty));
+                if flags.is_empty() {
+                    map.remove(&key);
+                }
+            }
+        }
+        self.dirty.store(false, Ordering::Relaxed);
+    }
+}
+
+impl Drop for WatchedKeys {
+    fn drop(&mut self) {
+        self.unwatch();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn touch_marks_only_watchers_of_the_key() {
+        let watchers = Watchers::default();
+        let mut a = watchers.watched_keys();
+        let mut b = watchers.watched_keys();
+        a.watch(0, vec!["x".into(), "y".into()]);
+        b.watch(0, vec!["z".into()]);
+        b.watch(1, vec!["y".into()]);
+
+        watchers.touch(0, &"y".into());
+        assert!(a.is_dirty());
+        assert!(!b.is_dirty());
+
+        a.unwatch();
+        assert!(!a.is_dirty());
+        watchers.touch(0, &"y".into());
+        assert!(!a.is_dirty());
+        drop(b);
+        assert!(watchers.keys.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,182 @@
+//! TLS for client connections and the replication link, set up from the
+//! same `tls-*` options Redis takes. Without the `tls` feature the options
+//! still parse, but turning TLS on fails at startup.
+
+use anyhow::bail;
+#[cfg(not(feature = "tls"))]
+use std::convert::Infallible;
+use std::fmt;
+use std::io;
+use tokio::net::TcpStream;
+#[cfg(feature = "tls")]
+use {
+    anyhow::Context,
+    std::sync::Arc,
+    tokio_rustls::{
+        client,
+        rustls::{
+            pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName},
+            server::WebPkiClientVerifier,
+            ClientConfig, RootCertStore, ServerConfig,
+        },
+        server, TlsAcceptor, TlsConnector,
+    },
+};
+
+/// Whether clients on the TLS port must present a certificate signed by
+/// the CA, as `tls-auth-clients` says.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AuthClients {
+    #[default]
+    Yes,
+    No,
+    /// A certificate isn't needed, but one that is presented must check out.
+    Optional,
+}
+
+impl AuthClients {
+    pub fn parse(value: &str) -> Option<AuthClients> {
+        match value.to_ascii_lowercase().as_str() {
+            "yes" => Some(AuthClients::Yes),
+            "no" => Some(AuthClients::No),
+            "optional" => Some(AuthClients::Optional),
+            _ => None,
+        }
+    }
+}
+
+/// The PEM files TLS connections are made with.
+#[derive(Debug, Clone, Default)]
+pub struct TlsFiles {
+    /// This server's certificate chain and key, shown to clients and, on
+    /// the replication link, to the master.
+    pub cert_file: String,
+    pub key_file: String,
+    /// The CA client certificates and the master's certificate must be
+    /// signed by.
+    pub ca_cert_file: String,
+    pub auth_clients: AuthClients,
+}
+
+#[cfg(feature = "tls")]
+impl TlsFiles {
+    fn certificates(
+        &self,
+    ) -> anyhow::Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+        if self.cert_file.is_empty() || self.key_file.is_empty() {
+            bail!("TLS needs both tls-cert-file and tls-key-file");
+        }
+        let chain = CertificateDer::pem_file_iter(&self.cert_file)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .with_context(|| format!("can't read certificates from {}", self.cert_file))?;
+        let key = PrivateKeyDer::from_pem_file(&self.key_file)
+            .with_context(|| format!("can't read a private key from {}", self.key_file))?;
+        Ok((chain, key))
+    }
+
+    fn roots(&self) -> anyhow::Result<RootCertStore> {
+        if self.ca_cert_file.is_empty() {
+            bail!("TLS needs tls-ca-cert-file to check certificates against");
+        }
+        let mut roots = RootCertStore::empty();
+        for cert in CertificateDer::pem_file_iter(&self.ca_cert_file)
+            .with_context(|| format!("can't read certificates from {}", self.ca_cert_file))?
+        {
+            roots.add(cert?)?;
+        }
+        Ok(roots)
+    }
+
+    /// Accepts TLS clients, checking their certificates as `auth_clients`
+    /// says.
+    pub fn acceptor(&self) -> anyhow::Result<Acceptor> {
+        let (chain, key) = self.certificates()?;
+        let builder = ServerConfig::builder();
+        let builder = match self.auth_clients {
+            AuthClients::No => builder.with_no_client_auth(),
+            AuthClients::Yes => builder.with_client_cert_verifier(
+                WebPkiClientVerifier::builder(self.roots()?.into()).build()?,
+            ),
+            AuthClients::Optional => builder.with_client_cert_verifier(
+                WebPkiClientVerifier::builder(self.roots()?.into())
+                    .allow_unauthenticated()
+                    .build()?,
+            ),
+        };
+        Ok(Acceptor(TlsAcceptor::from(Arc::new(
+            builder.with_single_cert(chain, key)?,
+        ))))
+    }
+
+    /// Connects to a master that presents a certificate signed by the CA,
+    /// showing it this server's certificate in turn.
+    pub fn connector(&self) -> anyhow::Result<Connector> {
+        let (chain, key) = self.certificates()?;
+        let config = ClientConfig::builder()
+            .with_root_certificates(self.roots()?)
+            .with_client_auth_cert(chain, key)?;
+        Ok(Connector(TlsConnector::from(Arc::new(config))))
+    }
+}
+
+#[cfg(not(feature = "tls"))]
+impl TlsFiles {
+    pub fn acceptor(&self) -> anyhow::Result<Acceptor> {
+        bail!("this server was built without TLS support")
+    }
+
+    pub fn connector(&self) -> anyhow::Result<Connector> {
+        bail!("this server was built without TLS support")
+    }
+}
+
+/// Runs the server side of TLS handshakes, for the TLS port.
+#[derive(Clone)]
+pub struct Acceptor(
+    #[cfg(feature = "tls")] TlsAcceptor,
+    #[cfg(not(feature = "tls"))] Infallible,
+);
+
+impl Acceptor {
+    /// Runs the TLS handshake over a client's `socket`.
+    #[cfg(feature = "tls")]
+    pub async fn accept(&self, socket: TcpStream) -> io::Result<server::TlsStream<TcpStream>> {
+        self.0.accept(socket).await
+    }
+
+    #[cfg(not(feature = "tls"))]
+    pub async fn accept(&self, _: TcpStream) -> io::Result<TcpStream> {
+        match self.0 {}
+    }
+}
+
+/// Makes the replication link a TLS one, see `Store::set_replication_tls`.
+#[derive(Clone)]
+pub struct Connector(
+    #[cfg(feature = "tls")] TlsConnector,
+    #[cfg(not(feature = "tls"))] Infallible,
+);
+
+impl Connector {
+    /// Runs the TLS handshake over `socket` with the server at `host`.
+    #[cfg(feature = "tls")]
+    pub async fn connect(
+        &self,
+        host: &str,
+        socket: TcpStream,
+    ) -> anyhow::Result<client::TlsStream<TcpStream>> {
+        let name = ServerName::try_from(host.to_string())?;
+        Ok(self.0.connect(name, socket).await?)
+    }
+
+    #[cfg(not(feature = "tls"))]
+    pub async fn connect(&self, _: &str, _: TcpStream) -> anyhow::Result<TcpStream> {
+        match self.0 {}
+    }
+}
+
+impl fmt::Debug for Connector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Connector")
+    }
+}
@@ -0,0 +1,109 @@
+use redis_starter_rust::array_of_bulks;
+use tokio::net::TcpStream;
+mod common;
+use common::{roundtrip, start_server};
+
+#[tokio::test]
+async fn setbit_getbit_bitcount() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await?;
+
+    for (request, reply) in [
+        (array_of_bulks!("SETBIT", "b", "7", "1"), ":0\r\n"),
+        (array_of_bulks!("SETBIT", "b", "7", "1"), ":1\r\n"),
+        (array_of_bulks!("SETBIT", "b", "22", "1"), ":0\r\n"),
+        (array_of_bulks!("GET", "b"), "$3\r\n\x01\x00\x02\r\n"),
+        (array_of_bulks!("GETBIT", "b", "22"), ":1\r\n"),
+        (array_of_bulks!("GETBIT", "b", "100"), ":0\r\n"),
+        (array_of_bulks!("GETBIT", "nope", "0"), ":0\r\n"),
+        (array_of_bulks!("SET", "s", "foobar"), "+OK\r\n"),
+        (array_of_bulks!("BITCOUNT", "s"), ":26\r\n"),
+        (array_of_bulks!("BITCOUNT", "s", "1", "1"), ":6\r\n"),
+        (
+            array_of_bulks!("BITCOUNT", "s", "5", "30", "BIT"),
+            ":17\r\n",
+        ),
+        (
+            array_of_bulks!("BITCOUNT", "s", "-1", "-1", "byte"),
+            ":4\r\n",
+        ),
+        (array_of_bulks!("BITCOUNT", "nope"), ":0\r\n"),
+        (
+            array_of_bulks!("BITCOUNT", "s", "a", "1"),
+            "-ERR value is not an integer or out of range\r\n",
+        ),
+        (
+            array_of_bulks!("SETBIT", "b", "4294967296", "1"),
+            "-ERR bit offset is not an integer or out of range\r\n",
+        ),
+        (
+            array_of_bulks!("SETBIT", "b", "1", "2"),
+            "-ERR bit is not an integer or out of range\r\n",
+        ),
+        (array_of_bulks!("ZADD", "z", "1", "a"), ":1\r\n"),
+        (
+            array_of_bulks!("SETBIT", "z", "1", "1"),
+            "-WRONGTYPE Operation against a key holding the wrong kind of value\r\n",
+        ),
+        (
+            array_of_bulks!("BITCOUNT", "z"),
+            "-WRONGTYPE Operation against a key holding the wrong kind of value\r\n",
+        ),
+    ] {
+        assert_eq!(roundtrip(&mut stream, request).await, reply);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn bitop_and_bitpos() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await?;
+
+    for (request, reply) in [
+        (array_of_bulks!("SET", "a", "\x7f\x0f"), "+OK\r\n"),
+        (array_of_bulks!("SETBIT", "b", "0", "1"), ":0\r\n"),
+        (
+            array_of_bulks!("BITOP", "OR", "d", "a", "b", "nope"),
+            ":2\r\n",
+        ),
+        (array_of_bulks!("BITCOUNT", "d"), ":12\r\n"),
+        (array_of_bulks!("BITOP", "and", "d", "b", "nope"), ":1\r\n"),
+        (array_of_bulks!("GET", "d"), "$1\r\n\x00\r\n"),
+        (array_of_bulks!("BITOP", "XOR", "d", "nope"), ":0\r\n"),
+        (array_of_bulks!("GET", "d"), "$-1\r\n"),
+        (array_of_bulks!("BITOP", "NOT", "d", "b"), ":1\r\n"),
+        (array_of_bulks!("GET", "d"), "$1\r\n\x7f\r\n"),
+        (
+            array_of_bulks!("BITOP", "NOT", "d", "a", "b"),
+            "-ERR BITOP NOT must be called with a single source key.\r\n",
+        ),
+        (array_of_bulks!("SET", "p", "\x00\x0f"), "+OK\r\n"),
+        (array_of_bulks!("BITPOS", "p", "1"), ":12\r\n"),
+        (array_of_bulks!("BITPOS", "p", "0", "1"), ":8\r\n"),
+        (array_of_bulks!("BITPOS", "d", "0"), ":0\r\n"),
+        (array_of_bulks!("BITPOS", "d", "1", "1"), ":-1\r\n"),
+        (
+            array_of_bulks!("BITPOS", "d", "1", "3", "7", "BIT"),
+            ":3\r\n",
+        ),
+        // all set: past the end unless an end is given
+        (array_of_bulks!("BITOP", "OR", "f", "d", "b"), ":1\r\n"),
+        (array_of_bulks!("BITPOS", "f", "0"), ":8\r\n"),
+        (array_of_bulks!("BITPOS", "f", "0", "0", "-1"), ":-1\r\n"),
+        (array_of_bulks!("BITPOS", "nope", "0"), ":0\r\n"),
+        (array_of_bulks!("BITPOS", "nope", "1"), ":-1\r\n"),
+        (
+            array_of_bulks!("BITPOS", "p", "2"),
+            "-ERR The bit argument must be 1 or 0.\r\n",
+        ),
+    ] {
+        assert_eq!(
+            roundtrip(&mut stream, request).await,
+            reply,
+            "{:?}",
+            request
+        );
+    }
+    Ok(())
+}
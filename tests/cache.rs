@@ -0,0 +1,75 @@
+use redis_starter_rust::array_of_bulks;
+use redis_starter_rust::cache::Cache;
+use redis_starter_rust::store::WrongType;
+use tokio::net::TcpStream;
+mod common;
+use common::{assert_eventually, roundtrip, start_server, TestServer};
+use std::time::{Duration, SystemTime};
+
+#[tokio::test]
+async fn an_embedded_cache_shares_the_servers_keyspace() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+    let cache = Cache::from_store(&store);
+    let mut stream = TcpStream::connect(addr).await?;
+
+    cache.set("from-cache", "1")?;
+    // a key set without a time to live keeps
+    assert_eq!(cache.ttl("from-cache"), Some(None));
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("GET", "from-cache")).await,
+        "$1\r\n1\r\n"
+    );
+    roundtrip(&mut stream, array_of_bulks!("ZADD", "z", "2", "b")).await;
+    assert!(cache.zadd("z", "a", 1.0)?);
+    assert!(!cache.zadd("z", "a", 3.0)?);
+    assert_eq!(cache.zscore("z", "a"), Ok(Some(3.0)));
+    assert_eq!(cache.zrange("z")?, [("b".into(), 2.0), ("a".into(), 3.0)]);
+    assert_eq!(store.dirty(), 4);
+
+    let err = cache.zadd("from-cache", "a", 1.0).unwrap_err();
+    assert_eq!(err.downcast_ref::<WrongType>(), Some(&WrongType));
+    assert_eq!(cache.zrange("from-cache"), Err(WrongType));
+    assert_eq!(cache.get_str("z"), Err(WrongType));
+
+    let mut keys: Vec<_> = cache.keys().collect();
+    keys.sort();
+    assert_eq!(keys, ["from-cache", "z"]);
+
+    assert!(cache.persist("z")?);
+    assert_eq!(cache.ttl("z"), Some(None));
+    assert!(cache.del("z")?);
+    assert!(!cache.del("z")?);
+    assert_eq!(cache.ttl("z"), None);
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("ZCARD", "z")).await,
+        ":0\r\n"
+    );
+
+    cache.select(1);
+    assert!(!cache.exists("from-cache"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn expiry_changes_reach_replicas() -> anyhow::Result<()> {
+    let (master, replica) = TestServer::start_pair().await;
+    let cache = Cache::from_store(master.store());
+    let replica_cache = Cache::from_store(replica.store());
+
+    cache.set_ex("k", "v", Duration::from_secs(600))?;
+    assert!(cache.persist("k")?);
+    assert_eventually("k to never expire", || replica_cache.ttl("k") == Some(None)).await;
+
+    assert!(cache.expire_at("k", SystemTime::now() + Duration::from_secs(60))?);
+    assert_eventually("k to expire in a minute", || {
+        replica_cache
+            .ttl("k")
+            .flatten()
+            .is_some_and(|ttl| ttl > Duration::from_secs(50))
+    })
+    .await;
+
+    assert!(cache.expire_at("k", SystemTime::now() - Duration::from_secs(1))?);
+    assert_eventually("k to expire", || !replica_cache.exists("k")).await;
+    Ok(())
+}
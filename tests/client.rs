@@ -0,0 +1,53 @@
+use redis_starter_rust::client::{Client, Message};
+use redis_starter_rust::frame::Frame;
+mod common;
+use common::start_server;
+
+#[tokio::test]
+async fn get_and_set() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut client = Client::connect(addr).await?;
+
+    assert_eq!(client.get("k").await?, None);
+    client.set("k", "v").await?;
+    assert_eq!(client.get("k").await?, Some("v".into()));
+
+    // error replies come back as errors, and the client carries on
+    let wrong_type = "WRONGTYPE Operation against a key holding the wrong kind of value";
+    assert_eq!(
+        client.call(&["ZADD", "k", "1", "a"]).await?,
+        Frame::Error(wrong_type.to_string())
+    );
+    client.call(&["ZADD", "z", "1", "a"]).await?;
+    let err = client.get("z").await.unwrap_err();
+    assert_eq!(err.to_string(), wrong_type);
+    assert_eq!(
+        client.call(&["ECHO", "hi"]).await?,
+        Frame::Bulk("hi".into())
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn subscribers_receive_published_messages() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut subscriber = Client::connect(addr)
+        .await?
+        .subscribe(&["news", "sport"])
+        .await?;
+    assert_eq!(subscriber.channels(), ["news", "sport"]);
+
+    let mut publisher = Client::connect(addr).await?;
+    assert_eq!(
+        publisher.call(&["PUBLISH", "sport", "goal"]).await?,
+        Frame::Integer(1)
+    );
+    assert_eq!(
+        subscriber.next_message().await?,
+        Some(Message {
+            channel: "sport".to_string(),
+            content: "goal".into(),
+        })
+    );
+    Ok(())
+}
@@ -0,0 +1,341 @@
+use redis_starter_rust::array_of_bulks;
+use redis_starter_rust::cluster;
+use redis_starter_rust::store::cluster::{key_slot, Node, Owner, SLOTS};
+use redis_starter_rust::store::Store;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::net::{TcpListener, TcpStream};
+mod common;
+use common::{roundtrip, start_server};
+
+#[tokio::test]
+async fn cluster_commands_need_cluster_mode() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await?;
+
+    for request in [
+        array_of_bulks!("CLUSTER", "INFO"),
+        array_of_bulks!("CLUSTER", "SLOTS"),
+        array_of_bulks!("READONLY"),
+    ] {
+        assert_eq!(
+            roundtrip(&mut stream, request).await,
+            "-ERR This instance has cluster support disabled\r\n"
+        );
+    }
+    let info = roundtrip(&mut stream, array_of_bulks!("INFO", "cluster")).await;
+    assert!(info.contains("cluster_enabled:0\r\n"), "{}", info);
+    Ok(())
+}
+
+#[tokio::test]
+async fn a_lone_node_owns_every_slot() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+    store.cluster().set_enabled(true);
+    let id = store.cluster().id().to_string();
+    let (host, port) = (store.config().host(), store.config().port());
+    let mut stream = TcpStream::connect(addr).await?;
+
+    let info = roundtrip(&mut stream, array_of_bulks!("CLUSTER", "INFO")).await;
+    for line in [
+        "cluster_state:ok\r\n",
+        "cluster_slots_assigned:16384\r\n",
+        "cluster_known_nodes:1\r\n",
+    ] {
+        assert!(info.contains(line), "{}", info);
+    }
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("CLUSTER", "MYID")).await,
+        format!("$40\r\n{}\r\n", id)
+    );
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("CLUSTER", "SLOTS")).await,
+        format!(
+            "*1\r\n*3\r\n:0\r\n:16383\r\n*4\r\n${}\r\n{}\r\n:{}\r\n$40\r\n{}\r\n*0\r\n",
+            host.len(),
+            host,
+            port,
+            id
+        )
+    );
+    let shards = roundtrip(&mut stream, array_of_bulks!("CLUSTER", "SHARDS")).await;
+    assert!(
+        shards.starts_with("*1\r\n*4\r\n$5\r\nslots\r\n*2\r\n:0\r\n:16383\r\n$5\r\nnodes\r\n"),
+        "{}",
+        shards
+    );
+    assert!(shards.contains(&id), "{}", shards);
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("READONLY")).await,
+        "+OK\r\n"
+    );
+    let info = roundtrip(&mut stream, array_of_bulks!("INFO")).await;
+    assert!(info.contains("redis_mode:cluster\r\n"), "{}", info);
+    assert!(
+        info.contains("# Cluster\r\ncluster_enabled:1\r\n"),
+        "{}",
+        info
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn keys_in_slots_owned_elsewhere_are_moved() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+    let cluster = store.cluster();
+    cluster.set_enabled(true);
+    let other = Node {
+        id: "b".repeat(40).into(),
+        host: "10.0.0.2".to_string(),
+        port: 7001,
+        bus_port: 17001,
+    };
+    cluster.add_node(other.clone());
+    cluster.assign(8192..SLOTS, Some(&other.id));
+    let mut stream = TcpStream::connect(addr).await?;
+
+    for (request, reply) in [
+        (array_of_bulks!("CLUSTER", "KEYSLOT", "foo"), ":12182\r\n"),
+        (
+            array_of_bulks!("CLUSTER", "KEYSLOT", "{bar}.foo"),
+            ":5061\r\n",
+        ),
+        (
+            array_of_bulks!("SET", "foo", "v"),
+            "-MOVED 12182 10.0.0.2:7001\r\n",
+        ),
+        (array_of_bulks!("SET", "bar", "v"), "+OK\r\n"),
+        (array_of_bulks!("SET", "{bar}.foo", "v"), "+OK\r\n"),
+        (
+            array_of_bulks!("DEL", "bar", "baz"),
+            "-CROSSSLOT Keys in request don't hash to the same slot\r\n",
+        ),
+        (array_of_bulks!("DEL", "bar", "{bar}.foo"), ":2\r\n"),
+        (array_of_bulks!("PING"), "+PONG\r\n"),
+    ] {
+        assert_eq!(
+            roundtrip(&mut stream, request).await,
+            reply,
+            "{:?}",
+            request
+        );
+    }
+
+    let slots = roundtrip(&mut stream, array_of_bulks!("CLUSTER", "SLOTS")).await;
+    assert!(
+        slots.starts_with("*2\r\n*3\r\n:0\r\n:8191\r\n"),
+        "{}",
+        slots
+    );
+    assert!(
+        slots.contains(":8192\r\n:16383\r\n*4\r\n$8\r\n10.0.0.2\r\n:7001\r\n"),
+        "{}",
+        slots
+    );
+    let info = roundtrip(&mut stream, array_of_bulks!("CLUSTER", "INFO")).await;
+    assert!(
+        info.contains("cluster_known_nodes:2\r\ncluster_size:2\r\n"),
+        "{}",
+        info
+    );
+
+    cluster.assign([key_slot(b"{a}")], None);
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("GET", "{a}")).await,
+        "-CLUSTERDOWN Hash slot not served\r\n"
+    );
+    Ok(())
+}
+
+/// A cluster-enabled server with its bus on a port of its own.
+async fn start_node() -> (SocketAddr, u16, Store) {
+    let (addr, store) = start_server().await;
+    store.cluster().set_enabled(true);
+    store.config().set_port(addr.port());
+    let bus = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let bus_port = bus.local_addr().unwrap().port();
+    tokio::spawn(cluster::run(bus, store.clone()));
+    (addr, bus_port, store)
+}
+
+#[tokio::test]
+async fn nodes_meet_and_agree_on_slot_owners() -> anyhow::Result<()> {
+    let (a_addr, _, a) = start_node().await;
+    let (_, _, b) = start_node().await;
+    let (c_addr, c_bus, c) = start_node().await;
+    // b gets in touch with c by itself, and c then serves part of the slots
+    b.cluster().assign(0..SLOTS, None);
+    c.cluster().assign(0..SLOTS, None);
+    c.cluster().assign(0..100, Some(c.cluster().id()));
+    let mut stream = TcpStream::connect(a_addr).await?;
+    let mut c_stream = TcpStream::connect(c_addr).await?;
+    assert_eq!(
+        roundtrip(&mut c_stream, array_of_bulks!("CLUSTER", "BUMPEPOCH")).await,
+        "+BUMPED 1\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut c_stream, array_of_bulks!("CLUSTER", "BUMPEPOCH")).await,
+        "+STILL 1\r\n"
+    );
+    assert_eq!(
+        roundtrip(
+            &mut stream,
+            array_of_bulks!("CLUSTER", "MEET", "127.0.0.1", "1", c_bus.to_string())
+        )
+        .await,
+        "+OK\r\n"
+    );
+    cluster::meet(&b, "127.0.0.1", c_bus).await?;
+
+    // a hears of b through c's gossip, then they all ping each other
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while [&a, &b, &c].iter().any(|node| {
+        node.cluster().nodes().len() < 2 || node.cluster().owner(100) == Owner::Unassigned
+    }) {
+        assert!(Instant::now() < deadline, "nodes never found each other");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    for node in [&a, &b] {
+        assert!(matches!(node.cluster().owner(0), Owner::Node(n) if *n.id == *c.cluster().id()));
+    }
+    assert_eq!(a.cluster().owner(100), Owner::Myself);
+    assert_eq!(b.cluster().owner(100), Owner::Node(node_of(&a)));
+
+    let nodes = roundtrip(&mut stream, array_of_bulks!("CLUSTER", "NODES")).await;
+    let mine = nodes
+        .lines()
+        .find(|line| line.starts_with(a.cluster().id()))
+        .unwrap();
+    assert!(
+        mine.ends_with(" myself,master - 0 0 0 connected 100-16383"),
+        "{}",
+        nodes
+    );
+    assert!(nodes.contains(" 1 connected 0-99\n"), "{}", nodes);
+    Ok(())
+}
+
+fn node_of(store: &Store) -> Node {
+    Node {
+        id: store.cluster().id().into(),
+        host: store.config().host(),
+        port: store.config().port(),
+        bus_port: store.cluster().bus_port(),
+    }
+}
+
+#[tokio::test]
+async fn slots_migrate_key_by_key_with_ask_redirects() -> anyhow::Result<()> {
+    let (a_addr, _, a) = start_node().await;
+    let (b_addr, _, b) = start_node().await;
+    a.cluster().add_node(node_of(&b));
+    b.cluster().add_node(node_of(&a));
+    b.cluster().assign(0..SLOTS, Some(a.cluster().id()));
+    let (a_id, b_id) = (a.cluster().id().to_string(), b.cluster().id().to_string());
+    let slot = key_slot(b"{t}").to_string();
+    let mut a_stream = TcpStream::connect(a_addr).await?;
+    let mut b_stream = TcpStream::connect(b_addr).await?;
+
+    roundtrip(&mut a_stream, array_of_bulks!("SET", "{t}1", "one")).await;
+    roundtrip(&mut a_stream, array_of_bulks!("SET", "{t}2", "two")).await;
+    for (node, request, reply) in [
+        (
+            'b',
+            array_of_bulks!("CLUSTER", "SETSLOT", slot, "IMPORTING", a_id),
+            "+OK\r\n".to_string(),
+        ),
+        (
+            'a',
+            array_of_bulks!("CLUSTER", "SETSLOT", slot, "MIGRATING", b_id),
+            "+OK\r\n".to_string(),
+        ),
+        (
+            'a',
+            array_of_bulks!("CLUSTER", "COUNTKEYSINSLOT", slot),
+            ":2\r\n".to_string(),
+        ),
+        (
+            'a',
+            array_of_bulks!(
+                "MIGRATE",
+                "127.0.0.1",
+                b_addr.port().to_string(),
+                "{t}1",
+                "0",
+                "1000"
+            ),
+            "+OK\r\n".to_string(),
+        ),
+        (
+            'a',
+            array_of_bulks!("GET", "{t}1"),
+            format!("-ASK {} 127.0.0.1:{}\r\n", slot, b_addr.port()),
+        ),
+        (
+            'a',
+            array_of_bulks!("GET", "{t}2"),
+            "$3\r\ntwo\r\n".to_string(),
+        ),
+        (
+            'b',
+            array_of_bulks!("GET", "{t}1"),
+            format!("-MOVED {} 127.0.0.1:{}\r\n", slot, a_addr.port()),
+        ),
+        ('b', array_of_bulks!("ASKING"), "+OK\r\n".to_string()),
+        (
+            'b',
+            array_of_bulks!("GET", "{t}1"),
+            "$3\r\none\r\n".to_string(),
+        ),
+        (
+            'a',
+            array_of_bulks!("CLUSTER", "SETSLOT", slot, "NODE", b_id),
+            format!("-ERR Can't assign hashslot {} to a different node while I still hold keys for this hash slot.\r\n", slot),
+        ),
+    ] {
+        let stream = if node == 'a' {
+            &mut a_stream
+        } else {
+            &mut b_stream
+        };
+        assert_eq!(roundtrip(stream, request).await, reply, "{:?}", request);
+    }
+    let nodes = roundtrip(&mut a_stream, array_of_bulks!("CLUSTER", "NODES")).await;
+    assert!(
+        nodes.contains(&format!(" [{}->-{}]\n", slot, b_id)),
+        "{}",
+        nodes
+    );
+
+    // with the last key over, the slot changes hands
+    let migrate = array_of_bulks!(
+        "MIGRATE",
+        "127.0.0.1",
+        b_addr.port().to_string(),
+        "{t}2",
+        "0",
+        "1000"
+    )
+    .to_vec();
+    assert_eq!(roundtrip(&mut a_stream, &migrate).await, "+OK\r\n");
+    for stream in [&mut b_stream, &mut a_stream] {
+        assert_eq!(
+            roundtrip(
+                stream,
+                array_of_bulks!("CLUSTER", "SETSLOT", slot, "NODE", b_id)
+            )
+            .await,
+            "+OK\r\n"
+        );
+    }
+    assert_eq!(
+        roundtrip(&mut a_stream, array_of_bulks!("GET", "{t}2")).await,
+        format!("-MOVED {} 127.0.0.1:{}\r\n", slot, b_addr.port())
+    );
+    assert_eq!(
+        roundtrip(&mut b_stream, array_of_bulks!("GET", "{t}2")).await,
+        "$3\r\ntwo\r\n"
+    );
+    assert_eq!(b.cluster().my_epoch(), 1);
+    Ok(())
+}
@@ -1,20 +1,162 @@
-use redis_starter_rust::server;
+// each test crate uses its own share of the harness
+#![allow(dead_code)]
+
+use redis_starter_rust::client::Client;
+use redis_starter_rust::info::Info;
+use redis_starter_rust::server::{self, Server};
 use redis_starter_rust::store::Store;
 use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpListener;
 
 pub const TEST_SERVER_HOST: &str = "127.0.0.1";
 pub const TEST_SERVER_PORT: u16 = 0;
 
+/// How long the `assert_*` helpers wait for something to happen.
+pub const EVENTUALLY: Duration = Duration::from_secs(2);
+
 pub async fn start_server() -> (SocketAddr, Store) {
-    let listener = TcpListener::bind(format!("{}:{}", TEST_SERVER_HOST, TEST_SERVER_PORT))
-        .await
-        .unwrap();
+    let listener = bind().await;
     let addr = listener.local_addr().unwrap();
     let store = redis_starter_rust::store::Store::new();
     let return_store = store.clone();
 
-    tokio::spawn(async move { server::run(listener, store.clone()).await });
+    tokio::spawn(async move {
+        server::run(listener, store.clone(), std::future::pending::<()>()).await
+    });
 
     (addr, return_store)
 }
+
+/// A server on a port of its own, running until `shutdown` or the end of
+/// the test.
+pub struct TestServer(Server);
+
+impl TestServer {
+    pub async fn start() -> TestServer {
+        TestServer::start_with(Store::new()).await
+    }
+
+    /// Starts a server on `store`, which may be set up beforehand.
+    pub async fn start_with(store: Store) -> TestServer {
+        TestServer::serve(bind().await, store)
+    }
+
+    /// Starts a server replicating from `master`.
+    pub async fn start_replica_of(master: &TestServer) -> TestServer {
+        let listener = bind().await;
+        let store = Store::new();
+        Info::builder()
+            .self_port(Some(listener.local_addr().unwrap().port()))
+            .replication_role(Some("slave".to_string()))
+            .replication_of_host(Some(master.addr().ip().to_string()))
+            .replication_of_port(Some(master.addr().port()))
+            .build()
+            .write(&store)
+            .unwrap();
+        TestServer::serve(listener, store)
+    }
+
+    /// Starts a master and a replica of it, once the replica has synced.
+    pub async fn start_pair() -> (TestServer, TestServer) {
+        let master = TestServer::start().await;
+        let replica = TestServer::start_replica_of(&master).await;
+        assert_eventually("the replica to sync", || {
+            master.store().replicas().len() == 1
+        })
+        .await;
+        (master, replica)
+    }
+
+    fn serve(listener: TcpListener, store: Store) -> TestServer {
+        TestServer(Server::start(listener, store).unwrap())
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.0.local_addr()
+    }
+
+    pub fn store(&self) -> &Store {
+        self.0.store()
+    }
+
+    pub async fn client(&self) -> Client {
+        Client::connect(self.addr()).await.unwrap()
+    }
+
+    /// Stops the server, waiting for it to finish.
+    pub async fn shutdown(self) {
+        tokio::time::timeout(EVENTUALLY, self.0.shutdown())
+            .await
+            .expect("the server didn't shut down")
+            .unwrap();
+    }
+}
+
+async fn bind() -> TcpListener {
+    TcpListener::bind(format!("{}:{}", TEST_SERVER_HOST, TEST_SERVER_PORT))
+        .await
+        .unwrap()
+}
+
+/// Polls `condition` until it holds, panicking with `what` if it doesn't
+/// within `EVENTUALLY`.
+pub async fn assert_eventually(what: &str, mut condition: impl FnMut() -> bool) {
+    let deadline = tokio::time::Instant::now() + EVENTUALLY;
+    while !condition() {
+        if tokio::time::Instant::now() > deadline {
+            panic!("timed out waiting for {}", what);
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+/// Waits for `key` to hold `value` on `server`, or to be gone if `value` is
+/// None, as writes replicate to it.
+pub async fn assert_replicated(server: &TestServer, key: &str, value: Option<&str>) {
+    let expected = value.map(|value| value.to_string().into());
+    assert_eventually(&format!("{} to become {:?}", key, value), || {
+        server.store().get_string(&key.to_string().into()) == Ok(expected.clone())
+    })
+    .await;
+}
+
+/// Sends `request` and reads back one whole reply, as it came over the wire.
+/// Nothing past the reply is read, so what follows is left for the next call.
+pub async fn roundtrip<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    request: &[u8],
+) -> String {
+    stream.write_all(request).await.unwrap();
+    read_reply(stream).await
+}
+
+/// Reads one whole reply off `stream`, a byte at a time up to each line end
+/// and in one go for bulk payloads.
+pub async fn read_reply<S: AsyncRead + Unpin>(stream: &mut S) -> String {
+    let mut reply = vec![];
+    let mut frames = 1;
+    while frames > 0 {
+        frames -= 1;
+        let start = reply.len();
+        while !reply.ends_with(b"\r\n") || reply.len() - start < 3 {
+            reply.push(stream.read_u8().await.unwrap());
+        }
+        let line = String::from_utf8_lossy(&reply[start + 1..reply.len() - 2]).into_owned();
+        let len: i64 = line.parse().unwrap_or(0);
+        match reply[start] {
+            b'*' | b'~' | b'>' => frames += len.max(0),
+            b'%' => frames += 2 * len,
+            // an attribute map comes ahead of the reply it annotates
+            b'|' => frames += 2 * len + 1,
+            b'$' | b'=' | b'!' if len >= 0 => {
+                let mut payload = vec![0; len as usize + 2];
+                stream.read_exact(&mut payload).await.unwrap();
+                reply.extend(payload);
+            }
+            _ => {}
+        }
+    }
+    String::from_utf8_lossy(&reply).into_owned()
+}
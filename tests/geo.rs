@@ -0,0 +1,157 @@
+use redis_starter_rust::array_of_bulks;
+use tokio::net::TcpStream;
+mod common;
+use common::{roundtrip, start_server};
+
+#[tokio::test]
+async fn geoadd_geopos_geodist() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await?;
+
+    for (request, reply) in [
+        (
+            array_of_bulks!(
+                "GEOADD", "Sicily", "13.361389", "38.115556", "Palermo", "15.087269", "37.502669",
+                "Catania"
+            ),
+            ":2\r\n",
+        ),
+        (
+            array_of_bulks!("GEOADD", "Sicily", "NX", "0", "0", "Palermo"),
+            ":0\r\n",
+        ),
+        (
+            array_of_bulks!("ZRANGE", "Sicily", "0", "-1", "WITHSCORES"),
+            "*4\r\n$7\r\nPalermo\r\n$16\r\n3479099956230698\r\n$7\r\nCatania\r\n$16\r\n3479447370796909\r\n",
+        ),
+        (
+            array_of_bulks!("GEOPOS", "Sicily", "Palermo", "nowhere"),
+            "*2\r\n*2\r\n$20\r\n13.36138933897018433\r\n$20\r\n38.11555639549629859\r\n*-1\r\n",
+        ),
+        (
+            array_of_bulks!("GEODIST", "Sicily", "Palermo", "Catania"),
+            "$11\r\n166274.1516\r\n",
+        ),
+        (
+            array_of_bulks!("GEODIST", "Sicily", "Palermo", "Catania", "km"),
+            "$8\r\n166.2742\r\n",
+        ),
+        (
+            array_of_bulks!("GEODIST", "Sicily", "Palermo", "Catania", "MI"),
+            "$8\r\n103.3182\r\n",
+        ),
+        (
+            array_of_bulks!("GEODIST", "Sicily", "Palermo", "nowhere"),
+            "$-1\r\n",
+        ),
+        (
+            array_of_bulks!("GEODIST", "Sicily", "Palermo", "Catania", "yd"),
+            "-ERR unsupported unit provided. please use M, KM, FT, MI\r\n",
+        ),
+        (
+            array_of_bulks!("GEOADD", "Sicily", "XX", "CH", "13.5", "38", "Palermo", "1", "1", "new"),
+            ":1\r\n",
+        ),
+        (
+            array_of_bulks!("GEOADD", "Sicily", "200", "0", "bad"),
+            "-ERR invalid longitude,latitude pair 200.000000,0.000000\r\n",
+        ),
+        (array_of_bulks!("SET", "s", "v"), "+OK\r\n"),
+        (
+            array_of_bulks!("GEOPOS", "s", "m"),
+            "-WRONGTYPE Operation against a key holding the wrong kind of value\r\n",
+        ),
+    ] {
+        assert_eq!(roundtrip(&mut stream, request).await, reply, "{:?}", request);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn geosearch_by_radius_and_box() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await?;
+
+    roundtrip(
+        &mut stream,
+        array_of_bulks!(
+            "GEOADD",
+            "Sicily",
+            "13.361389",
+            "38.115556",
+            "Palermo",
+            "15.087269",
+            "37.502669",
+            "Catania",
+            "12.758489",
+            "38.788135",
+            "edge1",
+            "17.241510",
+            "38.788135",
+            "edge2"
+        ),
+    )
+    .await;
+    for (request, reply) in [
+        (
+            array_of_bulks!(
+                "GEOSEARCH", "Sicily", "FROMLONLAT", "15", "37", "BYRADIUS", "200", "km", "ASC"
+            ),
+            "*2\r\n$7\r\nCatania\r\n$7\r\nPalermo\r\n",
+        ),
+        (
+            array_of_bulks!(
+                "GEOSEARCH", "Sicily", "FROMLONLAT", "15", "37", "BYBOX", "400", "400", "km", "ASC",
+                "WITHCOORD", "WITHDIST"
+            ),
+            concat!(
+                "*4\r\n",
+                "*3\r\n$7\r\nCatania\r\n$7\r\n56.4413\r\n",
+                "*2\r\n$20\r\n15.08726745843887329\r\n$20\r\n37.50266842333162032\r\n",
+                "*3\r\n$7\r\nPalermo\r\n$8\r\n190.4424\r\n",
+                "*2\r\n$20\r\n13.36138933897018433\r\n$20\r\n38.11555639549629859\r\n",
+                "*3\r\n$5\r\nedge2\r\n$8\r\n279.7403\r\n",
+                "*2\r\n$20\r\n17.24151045083999634\r\n$20\r\n38.78813451624225195\r\n",
+                "*3\r\n$5\r\nedge1\r\n$8\r\n279.7405\r\n",
+                "*2\r\n$19\r\n12.7584877610206604\r\n$20\r\n38.78813451624225195\r\n",
+            ),
+        ),
+        (
+            array_of_bulks!(
+                "GEOSEARCH", "Sicily", "FROMMEMBER", "Palermo", "BYRADIUS", "500", "km", "COUNT",
+                "2", "WITHHASH"
+            ),
+            "*2\r\n*2\r\n$7\r\nPalermo\r\n:3479099956230698\r\n*2\r\n$5\r\nedge1\r\n:3479273021651468\r\n",
+        ),
+        (
+            array_of_bulks!(
+                "GEOSEARCH", "Sicily", "FROMLONLAT", "15", "37", "BYRADIUS", "500", "km", "DESC",
+                "COUNT", "1"
+            ),
+            "*1\r\n$5\r\nedge1\r\n",
+        ),
+        (
+            array_of_bulks!("GEOSEARCH", "nope", "FROMLONLAT", "15", "37", "BYRADIUS", "1", "m"),
+            "*0\r\n",
+        ),
+        (
+            array_of_bulks!("GEOSEARCH", "Sicily", "FROMMEMBER", "nowhere", "BYRADIUS", "1", "m"),
+            "-ERR could not decode requested zset member\r\n",
+        ),
+        (
+            array_of_bulks!("GEOSEARCH", "Sicily", "BYRADIUS", "1", "m", "ASC", "WITHDIST"),
+            "-ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH\r\n",
+        ),
+        (
+            array_of_bulks!("GEOSEARCH", "Sicily", "FROMLONLAT", "15", "37", "ASC", "WITHDIST"),
+            "-ERR exactly one of BYRADIUS and BYBOX can be specified for GEOSEARCH\r\n",
+        ),
+        (
+            array_of_bulks!("GEOSEARCH", "Sicily", "FROMLONLAT", "15", "37", "BYRADIUS", "1", "m", "ANY"),
+            "-ERR the ANY argument requires COUNT argument\r\n",
+        ),
+    ] {
+        assert_eq!(roundtrip(&mut stream, request).await, reply, "{:?}", request);
+    }
+    Ok(())
+}
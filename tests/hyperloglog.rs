@@ -0,0 +1,69 @@
+use redis_starter_rust::array_of_bulks;
+use tokio::net::TcpStream;
+mod common;
+use common::{roundtrip, start_server};
+
+#[tokio::test]
+async fn pfadd_pfcount_pfmerge() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await?;
+
+    for (request, reply) in [
+        (array_of_bulks!("PFADD", "h1", "a", "b", "c"), ":1\r\n"),
+        (array_of_bulks!("PFADD", "h1", "a", "b"), ":0\r\n"),
+        (array_of_bulks!("PFCOUNT", "h1"), ":3\r\n"),
+        (array_of_bulks!("PFADD", "h2", "c", "d"), ":1\r\n"),
+        (array_of_bulks!("PFCOUNT", "h1", "h2", "missing"), ":4\r\n"),
+        (array_of_bulks!("PFCOUNT", "missing"), ":0\r\n"),
+        (array_of_bulks!("PFADD", "empty"), ":1\r\n"),
+        (array_of_bulks!("PFADD", "empty"), ":0\r\n"),
+        (array_of_bulks!("PFMERGE", "h3", "h1", "h2"), "+OK\r\n"),
+        (array_of_bulks!("PFCOUNT", "h3"), ":4\r\n"),
+        (array_of_bulks!("PFMERGE", "h1", "h2"), "+OK\r\n"),
+        (array_of_bulks!("PFCOUNT", "h1"), ":4\r\n"),
+        (array_of_bulks!("SET", "s", "plain"), "+OK\r\n"),
+        (
+            array_of_bulks!("PFADD", "s", "a"),
+            "-WRONGTYPE Key is not a valid HyperLogLog string value.\r\n",
+        ),
+        (
+            array_of_bulks!("PFCOUNT", "h1", "s"),
+            "-WRONGTYPE Key is not a valid HyperLogLog string value.\r\n",
+        ),
+        (
+            array_of_bulks!("PFMERGE", "h1", "s"),
+            "-WRONGTYPE Key is not a valid HyperLogLog string value.\r\n",
+        ),
+        (array_of_bulks!("ZADD", "z", "1", "m"), ":1\r\n"),
+        (
+            array_of_bulks!("PFCOUNT", "z"),
+            "-WRONGTYPE Operation against a key holding the wrong kind of value\r\n",
+        ),
+    ] {
+        assert_eq!(
+            roundtrip(&mut stream, request).await,
+            reply,
+            "{:?}",
+            request
+        );
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn hlls_are_dense_strings_with_a_cached_count() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await?;
+
+    roundtrip(&mut stream, array_of_bulks!("PFADD", "h", "x", "y")).await;
+    // a 16 byte header and 16384 six-bit registers, the count not cached yet
+    let value = store.get_string(&"h".into()).unwrap().unwrap();
+    assert_eq!((&value[..5], value.len()), (&b"HYLL\0"[..], 12304));
+    assert_eq!(value[15] & 0x80, 0x80);
+
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("PFCOUNT", "h")).await,
+        ":2\r\n"
+    );
+    Ok(())
+}
@@ -0,0 +1,46 @@
+use redis_starter_rust::metrics;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+mod common;
+use common::TestServer;
+
+async fn scrape(addr: std::net::SocketAddr, path: &str) -> String {
+    let mut socket = TcpStream::connect(addr).await.unwrap();
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+    socket.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    socket.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[tokio::test]
+async fn metrics_count_commands_and_keys() -> anyhow::Result<()> {
+    let server = TestServer::start().await;
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let metrics_addr = listener.local_addr()?;
+    tokio::spawn(metrics::run(listener, server.store().clone()));
+
+    let mut client = server.client().await;
+    client.set("k", "v").await?;
+    client.set("k", "w").await?;
+    client.get("k").await?;
+
+    let response = scrape(metrics_addr, "/metrics").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    for line in [
+        "redis_connected_clients 1",
+        "redis_db_keys{db=\"db0\"} 1",
+        "redis_commands_total{cmd=\"set\"} 2",
+        "redis_commands_total{cmd=\"get\"} 1",
+        "redis_command_duration_seconds_bucket{cmd=\"set\",le=\"+Inf\"} 2",
+        "redis_command_duration_seconds_count{cmd=\"get\"} 1",
+    ] {
+        assert!(response.contains(line), "no {} in\n{}", line, response);
+    }
+
+    assert!(scrape(metrics_addr, "/")
+        .await
+        .starts_with("HTTP/1.1 404 Not Found\r\n"));
+    Ok(())
+}
@@ -0,0 +1,518 @@
+use bytes::Bytes;
+use redis_starter_rust::{
+    array_of_bulks,
+    rdb::{decode, encode, Entry},
+    server,
+    store::{Store, Value},
+};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+mod common;
+use common::{roundtrip, start_server};
+
+/// A fresh directory under the system temp dir, unique to `name`.
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("redis-rust-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn now_millis() -> u64 {
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH);
+    now.unwrap().as_millis() as u64
+}
+
+#[tokio::test]
+async fn loads_the_rdb_file_at_startup() -> anyhow::Result<()> {
+    let dir = temp_dir("load");
+    let (foo, bar, stale) = (Bytes::from("foo"), Bytes::from("bar"), Bytes::from("stale"));
+    let value = Value::String("value".into());
+    let rdb = encode([
+        Entry {
+            db: 0,
+            key: &foo,
+            value: &value,
+            expires_at: None,
+        },
+        Entry {
+            db: 0,
+            key: &bar,
+            value: &value,
+            expires_at: Some(now_millis() + 60_000),
+        },
+        Entry {
+            db: 0,
+            key: &stale,
+            value: &value,
+            expires_at: Some(1),
+        },
+    ]);
+    std::fs::write(dir.join("data.rdb"), rdb)?;
+
+    let store = Store::new();
+    store.config().set_dir(dir.display().to_string());
+    store.config().set_dbfilename("data.rdb".to_string());
+    store.load_rdb_file().await?;
+
+    assert_eq!(store.get_string(&foo), Ok(Some("value".into())));
+    assert_eq!(store.get_string(&bar), Ok(Some("value".into())));
+    assert_eq!(store.get_string(&stale), Ok(None));
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn starts_empty_without_an_rdb_file() -> anyhow::Result<()> {
+    let dir = temp_dir("missing");
+    let store = Store::new();
+    store.config().set_dir(dir.display().to_string());
+    store.load_rdb_file().await?;
+
+    assert_eq!(store.get_string(&"foo".into()), Ok(None));
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn refuses_a_corrupt_rdb_file() -> anyhow::Result<()> {
+    let dir = temp_dir("corrupt");
+    std::fs::write(dir.join("dump.rdb"), "not an rdb file")?;
+    let store = Store::new();
+    store.config().set_dir(dir.display().to_string());
+
+    let err = store.load_rdb_file().await.unwrap_err();
+    assert!(format!("{:#}", err).starts_with("loading "), "{:#}", err);
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+/// Sends `request` and returns the reply, which must arrive in one read.
+#[test]
+fn a_snapshot_keeps_its_values_through_later_writes() {
+    let store = Store::new();
+    store.set_with_default_expiry("foo".into(), "before".into());
+    store
+        .with_sorted_set_mut("zset".into(), |zset| zset.insert("a".into(), 1.0))
+        .unwrap();
+
+    let snapshot = store.snapshot();
+    let copy = snapshot.clone();
+    store.set_with_default_expiry("foo".into(), "after".into());
+    store
+        .with_sorted_set_mut("zset".into(), |zset| zset.insert("b".into(), 2.0))
+        .unwrap();
+    store.del("zset".into());
+    store.set_with_default_expiry("new".into(), "key".into());
+
+    for snapshot in [snapshot, copy] {
+        let mut entries: Vec<_> = snapshot.iter().collect();
+        entries.sort_by(|a, b| a.1.cmp(b.1));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].2, &Value::String("before".into()));
+        let Value::SortedSet(zset) = entries[1].2 else {
+            panic!("expected a sorted set, got {:?}", entries[1].2);
+        };
+        assert_eq!(zset.len(), 1);
+    }
+    assert_eq!(store.get_string(&"foo".into()), Ok(Some("after".into())));
+}
+
+#[tokio::test]
+async fn save_writes_every_value_type() -> anyhow::Result<()> {
+    let dir = temp_dir("save");
+    let (addr, store) = start_server().await;
+    store.config().set_dir(dir.display().to_string());
+    let mut stream = TcpStream::connect(addr).await?;
+
+    roundtrip(&mut stream, array_of_bulks!("SET", "s", "1")).await;
+    roundtrip(
+        &mut stream,
+        array_of_bulks!("ZADD", "z", "1", "a", "2", "b"),
+    )
+    .await;
+    roundtrip(&mut stream, array_of_bulks!("XADD", "x", "1-1", "f", "v")).await;
+    roundtrip(
+        &mut stream,
+        array_of_bulks!("XGROUP", "CREATE", "x", "g", "0"),
+    )
+    .await;
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("SAVE")).await,
+        "+OK\r\n"
+    );
+
+    let mut loaded = decode(&std::fs::read(dir.join("dump.rdb"))?)?;
+    loaded.sort_by(|a, b| a.key.cmp(&b.key));
+    let keys: Vec<_> = loaded.iter().map(|entry| entry.key.clone()).collect();
+    assert_eq!(keys, ["s", "x", "z"]);
+    assert_eq!(loaded[0].value, Value::String("1".into()));
+    let Value::Stream(x) = &loaded[1].value else {
+        panic!("x isn't a stream: {:?}", loaded[1].value);
+    };
+    assert_eq!((x.len(), x.groups().len()), (1, 1));
+    let Value::SortedSet(z) = &loaded[2].value else {
+        panic!("z isn't a sorted set: {:?}", loaded[2].value);
+    };
+    assert_eq!(z.len(), 2);
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn writes_bump_key_versions_and_the_dirty_count() -> anyhow::Result<()> {
+    let dir = temp_dir("versions");
+    let (addr, store) = start_server().await;
+    store.config().set_dir(dir.display().to_string());
+    let mut stream = TcpStream::connect(addr).await?;
+    let (s, z) = (Bytes::from("s"), Bytes::from("z"));
+    assert_eq!(store.version(&s), 0);
+
+    roundtrip(&mut stream, array_of_bulks!("SET", "s", "1")).await;
+    roundtrip(&mut stream, array_of_bulks!("ZADD", "z", "1", "a")).await;
+    let (s1, z1) = (store.version(&s), store.version(&z));
+    assert!(s1 != 0 && z1 != 0 && s1 != z1);
+    assert_eq!(store.dirty(), 2);
+
+    roundtrip(&mut stream, array_of_bulks!("GET", "s")).await;
+    roundtrip(&mut stream, array_of_bulks!("ZSCORE", "z", "a")).await;
+    assert_eq!((store.version(&s), store.version(&z)), (s1, z1));
+
+    roundtrip(&mut stream, array_of_bulks!("SET", "s", "1")).await;
+    roundtrip(&mut stream, array_of_bulks!("ZADD", "z", "2", "b")).await;
+    assert!(store.version(&s) > s1 && store.version(&z) > z1);
+    roundtrip(&mut stream, array_of_bulks!("DEL", "s")).await;
+    assert_eq!(store.version(&s), 0);
+    assert_eq!(store.dirty(), 5);
+
+    roundtrip(&mut stream, array_of_bulks!("SAVE")).await;
+    assert_eq!(store.dirty(), 0);
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn bgsave_reports_through_lastsave_and_info() -> anyhow::Result<()> {
+    let dir = temp_dir("bgsave");
+    let (addr, store) = start_server().await;
+    store.config().set_dir(dir.display().to_string());
+    store.config().set_dbfilename("bg.rdb".to_string());
+    let mut stream = TcpStream::connect(addr).await?;
+    let started = store.persistence().lastsave();
+
+    roundtrip(&mut stream, array_of_bulks!("SET", "foo", "bar")).await;
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("BGSAVE")).await,
+        "+Background saving started\r\n"
+    );
+    for _ in 0..100 {
+        if store.persistence().bgsave_running_for().is_none() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let lastsave = roundtrip(&mut stream, array_of_bulks!("LASTSAVE")).await;
+    let lastsave: u64 = lastsave.trim_start_matches(':').trim_end().parse()?;
+    assert!(lastsave >= started);
+    let info = roundtrip(&mut stream, array_of_bulks!("INFO", "persistence")).await;
+    assert!(info.contains("rdb_bgsave_in_progress:0\r\n"), "{}", info);
+    assert!(info.contains("rdb_last_bgsave_status:ok\r\n"), "{}", info);
+    assert!(info.contains("loading:0\r\n"), "{}", info);
+    assert!(info.contains("rdb_saves:1\r\n"), "{}", info);
+    assert!(
+        info.contains(&format!("rdb_last_save_time:{}\r\n", lastsave)),
+        "{}",
+        info
+    );
+
+    let reloaded = Store::new();
+    reloaded.config().set_dir(dir.display().to_string());
+    reloaded.config().set_dbfilename("bg.rdb".to_string());
+    reloaded.load_rdb_file().await?;
+    assert_eq!(reloaded.get_string(&"foo".into()), Ok(Some("bar".into())));
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn save_rules_trigger_background_saves() -> anyhow::Result<()> {
+    let dir = temp_dir("rules");
+    let (addr, store) = start_server().await;
+    store.config().set_dir(dir.display().to_string());
+    let mut stream = TcpStream::connect(addr).await?;
+
+    roundtrip(&mut stream, array_of_bulks!("CONFIG", "SET", "save", "1 2")).await;
+    roundtrip(&mut stream, array_of_bulks!("SET", "a", "1")).await;
+    roundtrip(&mut stream, array_of_bulks!("DEL", "a")).await;
+    let info = roundtrip(&mut stream, array_of_bulks!("INFO", "persistence")).await;
+    assert!(
+        info.contains("rdb_changes_since_last_save:2\r\n"),
+        "{}",
+        info
+    );
+
+    for _ in 0..50 {
+        if dir.join("dump.rdb").exists() && store.persistence().dirty() == 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(dir.join("dump.rdb").exists());
+    let info = roundtrip(&mut stream, array_of_bulks!("INFO", "persistence")).await;
+    assert!(
+        info.contains("rdb_changes_since_last_save:0\r\n"),
+        "{}",
+        info
+    );
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+/// Turns AOF on over `stream` and waits for the first rewrite to open the file.
+async fn enable_aof(stream: &mut TcpStream, store: &Store) {
+    let reply = roundtrip(
+        stream,
+        array_of_bulks!("CONFIG", "SET", "appendonly", "yes"),
+    )
+    .await;
+    assert_eq!(reply, "+OK\r\n");
+    for _ in 0..100 {
+        if store.aof().is_open() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("the AOF was never opened");
+}
+
+#[tokio::test]
+async fn aof_replays_writes_at_startup() -> anyhow::Result<()> {
+    let dir = temp_dir("aof");
+    let (addr, store) = start_server().await;
+    store.config().set_dir(dir.display().to_string());
+    let mut stream = TcpStream::connect(addr).await?;
+
+    roundtrip(&mut stream, array_of_bulks!("SET", "before", "1")).await;
+    enable_aof(&mut stream, &store).await;
+    roundtrip(&mut stream, array_of_bulks!("SET", "after", "2")).await;
+    roundtrip(&mut stream, array_of_bulks!("ZADD", "z", "1", "a")).await;
+    roundtrip(&mut stream, array_of_bulks!("XADD", "x", "1-1", "f", "v")).await;
+    roundtrip(&mut stream, array_of_bulks!("DEL", "before")).await;
+    roundtrip(&mut stream, array_of_bulks!("SELECT", "2")).await;
+    roundtrip(&mut stream, array_of_bulks!("SET", "after", "3")).await;
+
+    let reloaded = Store::new();
+    reloaded.config().set_dir(dir.display().to_string());
+    reloaded.config().set_appendonly(true);
+    reloaded.load().await?;
+    assert_eq!(reloaded.get_string(&"before".into()), Ok(None));
+    assert_eq!(reloaded.get_string(&"after".into()), Ok(Some("2".into())));
+    let db2 = reloaded.for_client();
+    db2.select(2);
+    assert_eq!(db2.get_string(&"after".into()), Ok(Some("3".into())));
+    assert_eq!(reloaded.with_sorted_set(&"z".into(), |z| z.len())?, 1);
+    assert_eq!(reloaded.with_stream(&"x".into(), |x| x.len())?, 1);
+    assert!(reloaded.aof().is_open());
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn bgrewriteaof_compacts_the_file() -> anyhow::Result<()> {
+    let dir = temp_dir("rewrite");
+    let (addr, store) = start_server().await;
+    store.config().set_dir(dir.display().to_string());
+    let mut stream = TcpStream::connect(addr).await?;
+    enable_aof(&mut stream, &store).await;
+
+    for value in 0..20 {
+        let value = value.to_string();
+        roundtrip(&mut stream, array_of_bulks!("SET", "foo", &value)).await;
+    }
+    let grown = store.aof().size();
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("BGREWRITEAOF")).await,
+        "+Background append only file rewriting started\r\n"
+    );
+    for _ in 0..100 {
+        if store.aof().rewrite_running_for().is_none() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(store.aof().size() < grown);
+    let info = roundtrip(&mut stream, array_of_bulks!("INFO", "persistence")).await;
+    assert!(info.contains("aof_enabled:1\r\n"), "{}", info);
+    // the first rewrite started the file when AOF was turned on
+    assert!(info.contains("aof_rewrites:2\r\n"), "{}", info);
+    assert!(
+        info.contains("aof_last_bgrewrite_status:ok\r\n"),
+        "{}",
+        info
+    );
+
+    let reloaded = Store::new();
+    reloaded.config().set_dir(dir.display().to_string());
+    reloaded.config().set_appendonly(true);
+    reloaded.load().await?;
+    assert_eq!(reloaded.get_string(&"foo".into()), Ok(Some("19".into())));
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn drops_an_incomplete_command_from_the_aof() -> anyhow::Result<()> {
+    let dir = temp_dir("truncated");
+    let complete = "*3\r\n$3\r\nSET\r\n$3\r\nfoo\r\n$3\r\nbar\r\n";
+    std::fs::write(
+        dir.join("appendonly.aof"),
+        format!("{}*3\r\n$3\r\nSET\r\n$3\r\nba", complete),
+    )?;
+
+    let store = Store::new();
+    store.config().set_dir(dir.display().to_string());
+    store.config().set_appendonly(true);
+    store.load().await?;
+    assert_eq!(store.get_string(&"foo".into()), Ok(Some("bar".into())));
+    assert_eq!(
+        std::fs::read_to_string(dir.join("appendonly.aof"))?,
+        complete
+    );
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn a_failed_aof_write_is_reported_to_the_client() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+    // every write to /dev/full fails with ENOSPC
+    store.aof().open(std::path::Path::new("/dev/full"))?;
+    let mut stream = TcpStream::connect(addr).await?;
+
+    let reply = roundtrip(&mut stream, array_of_bulks!("SET", "foo", "bar")).await;
+    assert!(
+        reply.starts_with("-MISCONF Errors writing to the AOF file: "),
+        "{}",
+        reply
+    );
+    // the write still happened, and the connection is still usable
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("GET", "foo")).await,
+        "$3\r\nbar\r\n"
+    );
+    let info = roundtrip(&mut stream, array_of_bulks!("INFO", "persistence")).await;
+    assert!(info.contains("aof_last_write_status:err\r\n"), "{}", info);
+    Ok(())
+}
+
+#[tokio::test]
+async fn debug_reload_round_trips_the_keyspace() -> anyhow::Result<()> {
+    let dir = temp_dir("reload");
+    let (addr, store) = start_server().await;
+    store.config().set_dir(dir.display().to_string());
+    store.config().set_enable_debug_command("local".to_string());
+    let mut stream = TcpStream::connect(addr).await?;
+
+    roundtrip(&mut stream, array_of_bulks!("SET", "s", "bar")).await;
+    roundtrip(&mut stream, array_of_bulks!("SET", "n", "12345")).await;
+    roundtrip(
+        &mut stream,
+        array_of_bulks!("ZADD", "z", "1", "a", "2", "b"),
+    )
+    .await;
+    roundtrip(&mut stream, array_of_bulks!("XADD", "x", "1-1", "f", "v")).await;
+    roundtrip(
+        &mut stream,
+        array_of_bulks!("XGROUP", "CREATE", "x", "g", "0"),
+    )
+    .await;
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("DEBUG", "RELOAD")).await,
+        "+OK\r\n"
+    );
+
+    assert_eq!(store.get_string(&"s".into()), Ok(Some("bar".into())));
+    assert_eq!(store.with_sorted_set(&"z".into(), |z| z.len())?, 2);
+    assert_eq!(
+        store.with_stream(&"x".into(), |x| (x.len(), x.groups().len()))?,
+        (1, 1)
+    );
+    let reply = roundtrip(&mut stream, array_of_bulks!("DEBUG", "OBJECT", "s")).await;
+    assert!(
+        reply.contains(" encoding:embstr serializedlength:4"),
+        "{}",
+        reply
+    );
+    let reply = roundtrip(&mut stream, array_of_bulks!("DEBUG", "OBJECT", "n")).await;
+    assert!(reply.contains(" encoding:int "), "{}", reply);
+    let reply = roundtrip(&mut stream, array_of_bulks!("DEBUG", "OBJECT", "z")).await;
+    assert!(reply.contains(" encoding:listpack "), "{}", reply);
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("DEBUG", "OBJECT", "nope")).await,
+        "-ERR no such key\r\n"
+    );
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+/// Starts a server on `store` that also stops once `stop` is sent to.
+async fn start_stoppable(
+    store: &Store,
+) -> (
+    std::net::SocketAddr,
+    tokio::sync::oneshot::Sender<()>,
+    tokio::task::JoinHandle<anyhow::Result<()>>,
+) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (stop, stopped) = tokio::sync::oneshot::channel();
+    let server = tokio::spawn(server::run(listener, store.clone(), stopped));
+    (addr, stop, server)
+}
+
+#[tokio::test]
+async fn shutdown_saves_and_closes_every_connection() -> anyhow::Result<()> {
+    let dir = temp_dir("shutdown");
+    let store = Store::new();
+    store.config().set_dir(dir.display().to_string());
+    store.config().set_save(vec![]);
+    let (addr, _stop, server) = start_stoppable(&store).await;
+    let mut stream = TcpStream::connect(addr).await?;
+    let mut idle = TcpStream::connect(addr).await?;
+
+    roundtrip(&mut stream, array_of_bulks!("SET", "k", "v")).await;
+    stream
+        .write_all(array_of_bulks!("SHUTDOWN", "SAVE"))
+        .await?;
+    let mut buf = [0; 16];
+    assert_eq!(stream.read(&mut buf).await?, 0);
+    assert_eq!(idle.read(&mut buf).await?, 0);
+    tokio::time::timeout(Duration::from_secs(1), server).await???;
+
+    let loaded = decode(&std::fs::read(dir.join("dump.rdb"))?)?;
+    assert_eq!(loaded.len(), 1);
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn a_shutdown_signal_skips_the_save_without_save_rules() -> anyhow::Result<()> {
+    let dir = temp_dir("signal");
+    let store = Store::new();
+    store.config().set_dir(dir.display().to_string());
+    store.config().set_save(vec![]);
+    let (addr, stop, server) = start_stoppable(&store).await;
+    let mut stream = TcpStream::connect(addr).await?;
+    roundtrip(&mut stream, array_of_bulks!("SET", "k", "v")).await;
+
+    stop.send(()).unwrap();
+    let mut buf = [0; 16];
+    assert_eq!(stream.read(&mut buf).await?, 0);
+    tokio::time::timeout(Duration::from_secs(1), server).await???;
+    assert!(!dir.join("dump.rdb").exists());
+    assert!(TcpStream::connect(addr).await.is_err());
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
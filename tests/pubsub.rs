@@ -0,0 +1,214 @@
+use redis_starter_rust::array_of_bulks;
+use redis_starter_rust::store::notify::NotifyFlags;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+mod common;
+use common::start_server;
+
+#[tokio::test]
+async fn subscribe_publish_unsubscribe() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+
+    let mut subscriber = TcpStream::connect(addr).await.unwrap();
+    let mut publisher = TcpStream::connect(addr).await.unwrap();
+
+    subscriber
+        .write_all(array_of_bulks!("SUBSCRIBE", "news", "sport"))
+        .await
+        .unwrap();
+    let expected = b"*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n*3\r\n$9\r\nsubscribe\r\n$5\r\nsport\r\n:2\r\n";
+    let mut response = [0; 67];
+    subscriber.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    publisher
+        .write_all(array_of_bulks!("PUBLISH", "news", "hello"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    publisher.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":1\r\n", &response);
+
+    let expected = b"*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$5\r\nhello\r\n";
+    let mut response = [0; 38];
+    subscriber.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    publisher
+        .write_all(array_of_bulks!("PUBLISH", "nobody", "hello"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    publisher.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":0\r\n", &response);
+
+    // only pub/sub commands are allowed while subscribed
+    subscriber
+        .write_all(array_of_bulks!("GET", "key"))
+        .await
+        .unwrap();
+    let expected = b"-ERR Can't execute 'get': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context\r\n";
+    let mut response = [0; 116];
+    subscriber.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    subscriber.write_all(array_of_bulks!("PING")).await.unwrap();
+    let expected = b"*2\r\n$4\r\npong\r\n$0\r\n\r\n";
+    let mut response = [0; 20];
+    subscriber.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    subscriber
+        .write_all(array_of_bulks!("UNSUBSCRIBE"))
+        .await
+        .unwrap();
+    let expected = b"*3\r\n$11\r\nunsubscribe\r\n$4\r\nnews\r\n:1\r\n*3\r\n$11\r\nunsubscribe\r\n$5\r\nsport\r\n:0\r\n";
+    let mut response = [0; 73];
+    subscriber.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    // back to normal mode
+    subscriber.write_all(array_of_bulks!("PING")).await.unwrap();
+    let mut response = [0; 7];
+    subscriber.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"+PONG\r\n", &response);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn keyspace_notifications() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+    store.set_notify_keyspace_events(NotifyFlags::parse("KEzx").unwrap());
+
+    let mut subscriber = TcpStream::connect(addr).await.unwrap();
+    let mut client = TcpStream::connect(addr).await.unwrap();
+
+    subscriber
+        .write_all(array_of_bulks!(
+            "SUBSCRIBE",
+            "__keyspace@0__:k",
+            "__keyevent@0__:expired"
+        ))
+        .await
+        .unwrap();
+    let mut response = [0; 98];
+    subscriber.read_exact(&mut response).await.unwrap();
+
+    client
+        .write_all(array_of_bulks!("ZADD", "k", "1", "a"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    client.read_exact(&mut response).await.unwrap();
+
+    let expected = b"*3\r\n$7\r\nmessage\r\n$16\r\n__keyspace@0__:k\r\n$4\r\nzadd\r\n";
+    let mut response = [0; 50];
+    subscriber.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    // string events are not enabled, but the expiry is
+    client
+        .write_all(array_of_bulks!("SET", "e", "v", "PX", "1"))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    client.read_exact(&mut response).await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    client.write_all(array_of_bulks!("GET", "e")).await.unwrap();
+    let mut response = [0; 5];
+    client.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"$-1\r\n", &response);
+
+    let expected = b"*3\r\n$7\r\nmessage\r\n$22\r\n__keyevent@0__:expired\r\n$1\r\ne\r\n";
+    let mut response = [0; 53];
+    subscriber.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn store_hooks_see_changes_whatever_the_notification_flags() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+    let seen = Arc::new(Mutex::new(vec![]));
+    let hook_seen = seen.clone();
+    store.on_event(move |event| {
+        let key = String::from_utf8_lossy(event.key).into_owned();
+        hook_seen
+            .lock()
+            .unwrap()
+            .push((event.db, event.name.to_string(), key));
+    });
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    for (request, reply) in [
+        (array_of_bulks!("SET", "s", "v"), &b"+OK\r\n"[..]),
+        (array_of_bulks!("SELECT", "2"), b"+OK\r\n"),
+        (array_of_bulks!("ZADD", "z", "1", "a"), b":1\r\n"),
+        (array_of_bulks!("DEL", "z"), b":1\r\n"),
+    ] {
+        client.write_all(request).await.unwrap();
+        let mut response = vec![0; reply.len()];
+        client.read_exact(&mut response).await.unwrap();
+        assert_eq!(reply, &response[..]);
+    }
+
+    let expected = [
+        (0, "set".to_string(), "s".to_string()),
+        (2, "zadd".to_string(), "z".to_string()),
+        (2, "del".to_string(), "z".to_string()),
+    ];
+    assert_eq!(*seen.lock().unwrap(), expected);
+    Ok(())
+}
+
+#[tokio::test]
+async fn subscribers_that_stop_reading_are_disconnected() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut subscriber = TcpStream::connect(addr).await?;
+    let mut publisher = TcpStream::connect(addr).await?;
+
+    let set = array_of_bulks!(
+        "CONFIG",
+        "SET",
+        "client-output-buffer-limit",
+        "pubsub 64kb 0 0"
+    )
+    .to_vec();
+    publisher.write_all(&set).await?;
+    let mut response = [0; 5];
+    publisher.read_exact(&mut response).await?;
+    assert_eq!(b"+OK\r\n", &response);
+
+    subscriber
+        .write_all(array_of_bulks!("SUBSCRIBE", "news"))
+        .await?;
+    let mut response = [0; 33];
+    subscriber.read_exact(&mut response).await?;
+
+    // once the socket's buffers fill, messages pile up until the limit
+    let message = "x".repeat(64 * 1024);
+    let publish = array_of_bulks!("PUBLISH", "news", &message).to_vec();
+    let mut receivers = Vec::new();
+    for _ in 0..1000 {
+        publisher.write_all(&publish).await?;
+        let mut response = [0; 4];
+        publisher.read_exact(&mut response).await?;
+        receivers = response.to_vec();
+        if receivers == b":0\r\n" {
+            break;
+        }
+    }
+    assert_eq!(receivers, b":0\r\n");
+
+    // what was sent before the limit is still there, then the connection ends
+    let mut received = Vec::new();
+    tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        subscriber.read_to_end(&mut received),
+    )
+    .await??;
+    Ok(())
+}
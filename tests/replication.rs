@@ -0,0 +1,823 @@
+use redis_starter_rust::{
+    array_of_bulks,
+    info::Info,
+    server,
+    store::{
+        role::{FailoverState, LinkState, Role},
+        Store,
+    },
+};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+mod common;
+use common::{assert_eventually, assert_replicated, start_server, TestServer, TEST_SERVER_HOST};
+
+/// Starts a server replicating from `master`.
+async fn start_replica(master: SocketAddr) -> (SocketAddr, Store) {
+    let listener = TcpListener::bind(format!("{}:0", TEST_SERVER_HOST))
+        .await
+        .unwrap();
+    let store = Store::new();
+    Info::builder()
+        .self_port(Some(listener.local_addr().unwrap().port()))
+        .replication_role(Some("slave".to_string()))
+        .replication_of_host(Some(master.ip().to_string()))
+        .replication_of_port(Some(master.port()))
+        .build()
+        .write(&store)
+        .unwrap();
+
+    let addr = listener.local_addr().unwrap();
+    let server_store = store.clone();
+    tokio::spawn(
+        async move { server::run(listener, server_store, std::future::pending::<()>()).await },
+    );
+    (addr, store)
+}
+
+/// Polls `store` until `key` holds `value`, or is gone if `value` is None,
+/// for up to a second.
+async fn wait_for_key(store: &Store, key: &str, value: Option<&str>) {
+    let value = value.map(|value| value.to_string().into());
+    for _ in 0..100 {
+        if store.get_string(&key.to_string().into()) == Ok(value.clone()) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("{} never became {:?}", key, value);
+}
+
+#[tokio::test]
+async fn master_repl_offset_counts_propagated_bytes() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    stream
+        .write_all(array_of_bulks!("SET", "foo", "bar"))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    stream.read_exact(&mut response).await.unwrap();
+    // *3\r\n$3\r\nset\r\n$3\r\nfoo\r\n$3\r\nbar\r\n
+    assert_eq!(store.master_repl_offset(), 31);
+
+    stream
+        .write_all(array_of_bulks!("info", "replication"))
+        .await
+        .unwrap();
+    let replid = store.replicas().replid();
+    let expected = format!(
+        "role:master\r\nconnected_slaves:0\r\nmaster_failover_state:no-failover\r\nmaster_replid:{}\r\nmaster_replid2:{}\r\nmaster_repl_offset:31\r\nsecond_repl_offset:-1\r\n",
+        replid,
+        "0".repeat(40)
+    );
+    let expected = format!("${}\r\n{}\r\n", expected.len(), expected);
+    let mut response = vec![0; expected.len()];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected.as_bytes(), &response);
+
+    stream
+        .write_all(array_of_bulks!("PSYNC", "?", "-1"))
+        .await
+        .unwrap();
+    let expected = format!("+FULLRESYNC {} 31\r\n", replid);
+    let mut response = [0; 57];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected.as_bytes(), &response);
+
+    Ok(())
+}
+
+/// Reads the `+FULLRESYNC` line and returns the RDB snapshot that follows it.
+async fn read_full_resync(stream: &mut TcpStream) -> Vec<u8> {
+    let mut line = vec![];
+    for _ in 0..2 {
+        line.clear();
+        while !line.ends_with(b"\r\n") {
+            line.push(stream.read_u8().await.unwrap());
+        }
+    }
+    let len: usize = std::str::from_utf8(&line[1..line.len() - 2])
+        .unwrap()
+        .parse()
+        .unwrap();
+    let mut rdb = vec![0; len];
+    stream.read_exact(&mut rdb).await.unwrap();
+    rdb
+}
+
+#[tokio::test]
+async fn wait_counts_acknowledging_replicas() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+
+    let mut replica = TcpStream::connect(addr).await.unwrap();
+    replica
+        .write_all(array_of_bulks!("PSYNC", "?", "-1"))
+        .await
+        .unwrap();
+    read_full_resync(&mut replica).await;
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+
+    // nothing written yet, so the replica is already up to date
+    client
+        .write_all(array_of_bulks!("WAIT", "1", "500"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    client.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":1\r\n", &response);
+
+    client
+        .write_all(array_of_bulks!("SET", "foo", "bar"))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    client.read_exact(&mut response).await.unwrap();
+    let mut propagated = [0; 31];
+    replica.read_exact(&mut propagated).await.unwrap();
+    assert_eq!(array_of_bulks!("set", "foo", "bar"), &propagated);
+
+    client
+        .write_all(array_of_bulks!("WAIT", "1", "5000"))
+        .await
+        .unwrap();
+    let mut getack = [0; 37];
+    replica.read_exact(&mut getack).await.unwrap();
+    assert_eq!(array_of_bulks!("REPLCONF", "GETACK", "*"), &getack);
+    replica
+        .write_all(array_of_bulks!("replconf", "FACK", "0", "ack", "31"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    client.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":1\r\n", &response);
+
+    // the replica never acknowledges the next write
+    client
+        .write_all(array_of_bulks!("SET", "foo", "baz"))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    client.read_exact(&mut response).await.unwrap();
+    client
+        .write_all(array_of_bulks!("WAIT", "1", "100"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    client.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":0\r\n", &response);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn replicas_belong_to_their_own_server() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+    let (other_addr, other_store) = start_server().await;
+
+    let mut replica = TcpStream::connect(addr).await.unwrap();
+    replica
+        .write_all(array_of_bulks!("PSYNC", "?", "-1"))
+        .await
+        .unwrap();
+    read_full_resync(&mut replica).await;
+
+    let mut client = TcpStream::connect(other_addr).await.unwrap();
+    client
+        .write_all(array_of_bulks!("SET", "foo", "bar"))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    client.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"+OK\r\n", &response);
+
+    assert_eq!(other_store.master_repl_offset(), 31);
+    assert_eq!(store.master_repl_offset(), 0);
+    let read = tokio::time::timeout(Duration::from_millis(100), replica.read_u8()).await;
+    assert!(read.is_err(), "the replica was sent another server's write");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn writes_succeed_after_a_replica_disconnects() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+
+    let mut replica = TcpStream::connect(addr).await.unwrap();
+    replica
+        .write_all(array_of_bulks!("PSYNC", "?", "-1"))
+        .await
+        .unwrap();
+    read_full_resync(&mut replica).await;
+    drop(replica);
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    for _ in 0..2 {
+        client
+            .write_all(array_of_bulks!("SET", "foo", "bar"))
+            .await
+            .unwrap();
+        let mut response = [0; 5];
+        client.read_exact(&mut response).await.unwrap();
+        assert_eq!(b"+OK\r\n", &response);
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(store.replicas().is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn psync_sends_a_snapshot_of_the_keyspace() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client
+        .write_all(array_of_bulks!("SET", "foo", "bar"))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    client.read_exact(&mut response).await.unwrap();
+    client
+        .write_all(array_of_bulks!("ZADD", "zset", "1.5", "member"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    client.read_exact(&mut response).await.unwrap();
+
+    let mut replica = TcpStream::connect(addr).await.unwrap();
+    replica
+        .write_all(array_of_bulks!("PSYNC", "?", "-1"))
+        .await
+        .unwrap();
+    let rdb = read_full_resync(&mut replica).await;
+
+    assert!(rdb.starts_with(b"REDIS0011"));
+    let contains = |needle: &[u8]| rdb.windows(needle.len()).any(|window| window == needle);
+    assert!(contains(b"\x00\x03foo\x03bar"));
+    assert!(contains(
+        &[
+            b"\x05\x04zset\x01\x06member".as_slice(),
+            &1.5f64.to_le_bytes()
+        ]
+        .concat()
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn replica_loads_the_snapshot_then_follows_the_stream() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client
+        .write_all(array_of_bulks!("SET", "foo", "bar"))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    client.read_exact(&mut response).await.unwrap();
+
+    let (_, replica) = start_replica(addr).await;
+    wait_for_key(&replica, "foo", Some("bar")).await;
+
+    client
+        .write_all(array_of_bulks!("SET", "foo", "baz"))
+        .await
+        .unwrap();
+    client.read_exact(&mut response).await.unwrap();
+    wait_for_key(&replica, "foo", Some("baz")).await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn writes_through_a_client_reach_the_replica() -> anyhow::Result<()> {
+    let (master, replica) = TestServer::start_pair().await;
+    let mut client = master.client().await;
+
+    client.set("foo", "bar").await?;
+    assert_replicated(&replica, "foo", Some("bar")).await;
+    client.call(&["DEL", "foo"]).await?;
+    assert_replicated(&replica, "foo", None).await;
+
+    // the replica reads the same keyspace to its own clients
+    client.set("foo", "baz").await?;
+    assert_replicated(&replica, "foo", Some("baz")).await;
+    assert_eq!(replica.client().await.get("foo").await?, Some("baz".into()));
+
+    replica.shutdown().await;
+    master.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn stored_sorted_sets_reach_the_replica_whole() -> anyhow::Result<()> {
+    let (master, replica) = TestServer::start_pair().await;
+    let mut client = master.client().await;
+    let members = |server: &TestServer| {
+        server
+            .store()
+            .with_sorted_set(&"out".into(), |zset| {
+                zset.iter()
+                    .map(|(member, score)| (member.clone(), score))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap()
+    };
+
+    client.call(&["ZADD", "out", "9", "stale"]).await?;
+    client.call(&["ZADD", "a", "1", "x", "2", "y"]).await?;
+    client.call(&["ZADD", "b", "3", "y"]).await?;
+    client.call(&["ZUNIONSTORE", "out", "2", "a", "b"]).await?;
+    let expected = vec![("x".into(), 1.0), ("y".into(), 5.0)];
+    assert_eventually("the union to replace out", || members(&replica) == expected).await;
+
+    client
+        .call(&["ZINTERSTORE", "out", "2", "a", "missing"])
+        .await?;
+    assert_eventually("the empty result to delete out", || {
+        members(&replica).is_empty()
+    })
+    .await;
+
+    replica.shutdown().await;
+    master.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn replicas_expire_keys_when_the_master_does() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+    let (_, replica) = start_replica(addr).await;
+    // so the key arrives over the stream rather than in the snapshot
+    while store.replicas().is_empty() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client
+        .write_all(array_of_bulks!("SET", "foo", "bar", "PX", "50"))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    client.read_exact(&mut response).await.unwrap();
+    wait_for_key(&replica, "foo", Some("bar")).await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(replica.get_string(&"foo".into()), Ok(Some("bar".into())));
+
+    // the master notices the expiry on access and replicates it
+    client
+        .write_all(array_of_bulks!("GET", "foo"))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    client.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"$-1\r\n", &response);
+    wait_for_key(&replica, "foo", None).await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn psync_continues_from_the_backlog() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+    let replid = store.replicas().replid();
+
+    let mut replica = TcpStream::connect(addr).await.unwrap();
+    replica
+        .write_all(array_of_bulks!("PSYNC", "?", "-1"))
+        .await
+        .unwrap();
+    read_full_resync(&mut replica).await;
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client
+        .write_all(array_of_bulks!("SET", "foo", "bar"))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    client.read_exact(&mut response).await.unwrap();
+    let mut propagated = [0; 31];
+    replica.read_exact(&mut propagated).await.unwrap();
+    drop(replica);
+
+    // written while the replica is away
+    client
+        .write_all(array_of_bulks!("SET", "foo", "baz"))
+        .await
+        .unwrap();
+    client.read_exact(&mut response).await.unwrap();
+
+    let mut replica = TcpStream::connect(addr).await.unwrap();
+    replica
+        .write_all(array_of_bulks!("PSYNC", &replid, "31"))
+        .await
+        .unwrap();
+    let expected = format!("+CONTINUE {}\r\n", replid);
+    let mut response = vec![0; expected.len()];
+    replica.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected.as_bytes(), &response);
+    replica.read_exact(&mut propagated).await.unwrap();
+    assert_eq!(array_of_bulks!("set", "foo", "baz"), &propagated);
+
+    // an offset the backlog never had needs a full resync
+    let mut replica = TcpStream::connect(addr).await.unwrap();
+    replica
+        .write_all(array_of_bulks!("PSYNC", &replid, "30"))
+        .await
+        .unwrap();
+    read_full_resync(&mut replica).await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn replicaof_switches_roles_at_runtime() -> anyhow::Result<()> {
+    let (master, _master_store) = start_server().await;
+    let (addr, store) = start_server().await;
+
+    let mut master_client = TcpStream::connect(master).await.unwrap();
+    master_client
+        .write_all(array_of_bulks!("SET", "foo", "bar"))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    master_client.read_exact(&mut response).await.unwrap();
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    let port = master.port().to_string();
+    client
+        .write_all(array_of_bulks!("REPLICAOF", TEST_SERVER_HOST, port))
+        .await
+        .unwrap();
+    client.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"+OK\r\n", &response);
+    wait_for_key(&store, "foo", Some("bar")).await;
+    while store.role().link_state() != LinkState::Connected {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    client
+        .write_all(array_of_bulks!("SLAVEOF", TEST_SERVER_HOST, port))
+        .await
+        .unwrap();
+    let expected = b"+OK Already connected to specified master\r\n";
+    let mut response = [0; 43];
+    client.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    client
+        .write_all(array_of_bulks!("INFO", "replication"))
+        .await
+        .unwrap();
+    let expected = format!(
+        "role:slave\r\nmaster_host:{}\r\nmaster_port:{}\r\nmaster_link_status:up\r\nmaster_link_state:connected\r\nconnected_slaves:0\r\n",
+        TEST_SERVER_HOST, port
+    );
+    let expected = format!("${}\r\n{}\r\n", expected.len(), expected);
+    let mut response = vec![0; expected.len()];
+    client.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected.as_bytes(), &response);
+
+    client
+        .write_all(array_of_bulks!("REPLICAOF", "no", "one"))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    client.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"+OK\r\n", &response);
+
+    // promoted, so writes to the old master no longer arrive
+    master_client
+        .write_all(array_of_bulks!("SET", "foo", "baz"))
+        .await
+        .unwrap();
+    master_client.read_exact(&mut response).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(store.get_string(&"foo".into()), Ok(Some("bar".into())));
+
+    client
+        .write_all(array_of_bulks!("REPLICAOF", "localhost", "nope"))
+        .await
+        .unwrap();
+    let mut response = [0; 26];
+    client.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"-ERR Invalid master port\r\n", &response);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn replicas_reject_writes_from_clients() -> anyhow::Result<()> {
+    let (master, _master_store) = start_server().await;
+    let (addr, store) = start_replica(master).await;
+    let readonly = b"-READONLY You can't write against a read only replica.\r\n";
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client
+        .write_all(array_of_bulks!("SET", "foo", "bar"))
+        .await
+        .unwrap();
+    let mut response = [0; 56];
+    client.read_exact(&mut response).await.unwrap();
+    assert_eq!(readonly, &response);
+
+    #[cfg(feature = "scripting")]
+    {
+        client
+            .write_all(array_of_bulks!(
+                "EVAL",
+                "return redis.call('DEL', 'foo')",
+                "0"
+            ))
+            .await
+            .unwrap();
+        client.read_exact(&mut response).await.unwrap();
+        assert_eq!(readonly, &response);
+    }
+
+    // a rejected write dooms the transaction it was queued in
+    client.write_all(array_of_bulks!("MULTI")).await.unwrap();
+    let mut ok = [0; 5];
+    client.read_exact(&mut ok).await.unwrap();
+    client
+        .write_all(array_of_bulks!("ZADD", "z", "1", "a"))
+        .await
+        .unwrap();
+    client.read_exact(&mut response).await.unwrap();
+    assert_eq!(readonly, &response);
+    client.write_all(array_of_bulks!("EXEC")).await.unwrap();
+    let mut response = [0; 62];
+    client.read_exact(&mut response).await.unwrap();
+    assert_eq!(
+        b"-EXECABORT Transaction discarded because of previous errors.\r\n",
+        &response
+    );
+
+    // while writes from the master still apply
+    let mut master_client = TcpStream::connect(master).await.unwrap();
+    master_client
+        .write_all(array_of_bulks!("SET", "foo", "bar"))
+        .await
+        .unwrap();
+    master_client.read_exact(&mut ok).await.unwrap();
+    wait_for_key(&store, "foo", Some("bar")).await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn replicas_forward_the_stream_to_their_own_replicas() -> anyhow::Result<()> {
+    let (master, master_store) = start_server().await;
+    let (middle, middle_store) = start_replica(master).await;
+    while master_store.replicas().is_empty() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let mut client = TcpStream::connect(master).await.unwrap();
+    client
+        .write_all(array_of_bulks!("SET", "foo", "bar"))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    client.read_exact(&mut response).await.unwrap();
+    wait_for_key(&middle_store, "foo", Some("bar")).await;
+
+    let (_, leaf_store) = start_replica(middle).await;
+    wait_for_key(&leaf_store, "foo", Some("bar")).await;
+    client
+        .write_all(array_of_bulks!("SET", "foo", "baz"))
+        .await
+        .unwrap();
+    client.read_exact(&mut response).await.unwrap();
+    wait_for_key(&leaf_store, "foo", Some("baz")).await;
+
+    // the middle replica's stream carries the master's id and offsets
+    let mut replica = TcpStream::connect(middle).await.unwrap();
+    replica
+        .write_all(array_of_bulks!("PSYNC", "?", "-1"))
+        .await
+        .unwrap();
+    let expected = format!("+FULLRESYNC {} 62\r\n", master_store.replicas().replid());
+    let mut response = vec![0; expected.len()];
+    replica.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected.as_bytes(), &response);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn replicas_refuse_psync_before_syncing() -> anyhow::Result<()> {
+    // nothing listens on the master's port, so the replica never syncs
+    let unused = TcpListener::bind(format!("{}:0", TEST_SERVER_HOST))
+        .await
+        .unwrap();
+    let master = unused.local_addr().unwrap();
+    drop(unused);
+    let (addr, _store) = start_replica(master).await;
+
+    let mut replica = TcpStream::connect(addr).await.unwrap();
+    replica
+        .write_all(array_of_bulks!("PSYNC", "?", "-1"))
+        .await
+        .unwrap();
+    let expected = b"-NOMASTERLINK Can't SYNC while not connected with my master\r\n";
+    let mut response = [0; 61];
+    replica.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn info_lists_connected_replicas() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+
+    let mut replica = TcpStream::connect(addr).await.unwrap();
+    replica
+        .write_all(array_of_bulks!("REPLCONF", "listening-port", "6380"))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    replica.read_exact(&mut response).await.unwrap();
+    replica
+        .write_all(array_of_bulks!("PSYNC", "?", "-1"))
+        .await
+        .unwrap();
+    read_full_resync(&mut replica).await;
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client
+        .write_all(array_of_bulks!("INFO", "replication"))
+        .await
+        .unwrap();
+    let expected = format!(
+        "role:master\r\nconnected_slaves:1\r\nslave0:ip=127.0.0.1,port=6380,state=online,offset=0,lag=0\r\nmaster_failover_state:no-failover\r\nmaster_replid:{}\r\nmaster_replid2:{}\r\nmaster_repl_offset:0\r\nsecond_repl_offset:-1\r\n",
+        store.replicas().replid(),
+        "0".repeat(40)
+    );
+    let expected = format!("${}\r\n{}\r\n", expected.len(), expected);
+    let mut response = vec![0; expected.len()];
+    client.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, String::from_utf8_lossy(&response));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn promoted_replicas_keep_their_old_replid_for_partial_resyncs() -> anyhow::Result<()> {
+    let (master, master_store) = start_server().await;
+    let (replica, replica_store) = start_replica(master).await;
+    let master_replid = master_store.replicas().replid();
+    while replica_store.replicas().replid() != master_replid {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let mut client = TcpStream::connect(replica).await.unwrap();
+    client
+        .write_all(array_of_bulks!("REPLICAOF", "NO", "ONE"))
+        .await
+        .unwrap();
+    let mut ok = [0; 5];
+    client.read_exact(&mut ok).await.unwrap();
+    let replid = replica_store.replicas().replid();
+    assert_ne!(master_replid, replid);
+    let offset = replica_store.master_repl_offset();
+    assert_eq!(
+        Some((master_replid.clone(), offset)),
+        replica_store.replicas().replid2()
+    );
+
+    // a sibling replica of the old master carries on with the promoted one
+    let mut sibling = TcpStream::connect(replica).await.unwrap();
+    sibling
+        .write_all(array_of_bulks!(
+            "PSYNC",
+            &master_replid,
+            &offset.to_string()
+        ))
+        .await
+        .unwrap();
+    let expected = format!("+CONTINUE {}\r\n", replid);
+    let mut response = vec![0; expected.len()];
+    sibling.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected.as_bytes(), &response);
+
+    // until the id changes again, forgetting the old one
+    replica_store
+        .config()
+        .set_enable_debug_command("local".to_string());
+    client
+        .write_all(array_of_bulks!("DEBUG", "CHANGE-REPL-ID"))
+        .await
+        .unwrap();
+    client.read_exact(&mut ok).await.unwrap();
+    assert_eq!(b"+OK\r\n", &ok);
+    assert_ne!(replid, replica_store.replicas().replid());
+    assert_eq!(None, replica_store.replicas().replid2());
+    let mut sibling = TcpStream::connect(replica).await.unwrap();
+    sibling
+        .write_all(array_of_bulks!(
+            "PSYNC",
+            &master_replid,
+            &offset.to_string()
+        ))
+        .await
+        .unwrap();
+    read_full_resync(&mut sibling).await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn failover_hands_the_master_role_to_a_replica() -> anyhow::Result<()> {
+    let (master, master_store) = start_server().await;
+    let (replica, replica_store) = start_replica(master).await;
+    while replica_store.replicas().replid() != master_store.replicas().replid() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    async fn expect(stream: &mut TcpStream, request: &[u8], expected: &str) {
+        stream.write_all(request).await.unwrap();
+        let mut response = vec![0; expected.len()];
+        stream.read_exact(&mut response).await.unwrap();
+        assert_eq!(expected, String::from_utf8_lossy(&response));
+    }
+
+    let mut replica_client = TcpStream::connect(replica).await.unwrap();
+    expect(
+        &mut replica_client,
+        array_of_bulks!("FAILOVER"),
+        "-ERR FAILOVER is not valid when server is a replica.\r\n",
+    )
+    .await;
+    let mut client = TcpStream::connect(master).await.unwrap();
+    expect(
+        &mut client,
+        array_of_bulks!("FAILOVER", "TO", "127.0.0.1", "1"),
+        "-ERR FAILOVER target HOST and PORT is not a replica.\r\n",
+    )
+    .await;
+    expect(
+        &mut client,
+        array_of_bulks!("FAILOVER", "ABORT"),
+        "-ERR No failover in progress.\r\n",
+    )
+    .await;
+
+    expect(&mut client, array_of_bulks!("SET", "foo", "bar"), "+OK\r\n").await;
+    let port = replica.port().to_string();
+    expect(
+        &mut client,
+        array_of_bulks!("FAILOVER", "TO", "127.0.0.1", &port, "TIMEOUT", "5000"),
+        "+OK\r\n",
+    )
+    .await;
+    let new_master = Role::Replica {
+        host: "127.0.0.1".to_string(),
+        port: replica.port(),
+    };
+    for _ in 0..100 {
+        if master_store.role().get() == new_master
+            && master_store.role().failover_state() == FailoverState::None
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(new_master, master_store.role().get());
+    assert_eq!(LinkState::Connected, master_store.role().link_state());
+    assert!(!replica_store.is_replica());
+    assert_eq!(
+        Ok(Some("bar".into())),
+        replica_store.get_string(&"foo".into())
+    );
+    // the old master carried on with the stream rather than resyncing
+    assert_eq!(
+        replica_store.replicas().replid(),
+        master_store.replicas().replid()
+    );
+
+    expect(
+        &mut client,
+        array_of_bulks!("SET", "foo", "qux"),
+        "-READONLY You can't write against a read only replica.\r\n",
+    )
+    .await;
+    expect(
+        &mut replica_client,
+        array_of_bulks!("SET", "foo", "baz"),
+        "+OK\r\n",
+    )
+    .await;
+    wait_for_key(&master_store, "foo", Some("baz")).await;
+
+    Ok(())
+}
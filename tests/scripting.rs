@@ -0,0 +1,316 @@
+#![cfg(feature = "scripting")]
+
+use redis_starter_rust::array_of_bulks;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+mod common;
+use common::{assert_eventually, read_reply, roundtrip, start_server};
+
+#[tokio::test]
+async fn eval_calls_commands() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    stream
+        .write_all(array_of_bulks!(
+            "EVAL",
+            "redis.call('SET', KEYS[1], ARGV[1]); return {KEYS[1], redis.call('GET', KEYS[1]), 7}",
+            "1",
+            "foo",
+            "bar"
+        ))
+        .await
+        .unwrap();
+    let mut response = [0; 26];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"*3\r\n$3\r\nfoo\r\n$3\r\nbar\r\n:7\r\n", &response);
+    assert_eq!(store.get_string(&"foo".into()), Ok(Some("bar".into())));
+
+    // errors from redis.call reach the client unchanged
+    stream
+        .write_all(array_of_bulks!(
+            "EVAL",
+            "return redis.call('ZADD', KEYS[1], 1, 'a')",
+            "1",
+            "foo"
+        ))
+        .await
+        .unwrap();
+    let expected = b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n";
+    let mut response = [0; 68];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn script_load_and_evalsha() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    stream
+        .write_all(array_of_bulks!("SCRIPT", "LOAD", "return 1"))
+        .await
+        .unwrap();
+    let mut response = [0; 47];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(
+        b"$40\r\ne0e1f9fabfc9d4800c877a703b823ac0578ff8db\r\n",
+        &response
+    );
+
+    stream
+        .write_all(array_of_bulks!(
+            "EVALSHA",
+            "e0e1f9fabfc9d4800c877a703b823ac0578ff8db",
+            "0"
+        ))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":1\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("SCRIPT", "FLUSH"))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    stream.read_exact(&mut response).await.unwrap();
+    stream
+        .write_all(array_of_bulks!(
+            "EVALSHA",
+            "e0e1f9fabfc9d4800c877a703b823ac0578ff8db",
+            "0"
+        ))
+        .await
+        .unwrap();
+    let expected = b"-NOSCRIPT No matching script. Please use EVAL.\r\n";
+    let mut response = [0; 48];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn function_load_and_fcall() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    let library = "#!lua name=mylib
+redis.register_function('myset', function(keys, args)
+  return redis.call('SET', keys[1], args[1])
+end)
+redis.register_function{function_name='myget', callback=function(keys)
+  return redis.call('GET', keys[1])
+end}";
+    stream
+        .write_all(array_of_bulks!("FUNCTION", "LOAD", library))
+        .await
+        .unwrap();
+    let mut response = [0; 11];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"$5\r\nmylib\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("FCALL", "myset", "1", "foo", "bar"))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"+OK\r\n", &response);
+    assert_eq!(store.get_string(&"foo".into()), Ok(Some("bar".into())));
+
+    stream
+        .write_all(array_of_bulks!("FCALL", "myget", "1", "foo"))
+        .await
+        .unwrap();
+    let mut response = [0; 9];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"$3\r\nbar\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("FUNCTION", "LOAD", library))
+        .await
+        .unwrap();
+    let expected = b"-ERR Library 'mylib' already exists\r\n";
+    let mut response = [0; 37];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    stream
+        .write_all(array_of_bulks!("FUNCTION", "FLUSH"))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    stream.read_exact(&mut response).await.unwrap();
+    stream
+        .write_all(array_of_bulks!("FCALL", "myget", "1", "foo"))
+        .await
+        .unwrap();
+    let expected = b"-ERR Function not found\r\n";
+    let mut response = [0; 25];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn scripts_cannot_reach_the_host() {
+    let (addr, _store) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    for library in [
+        "os",
+        "io",
+        "package",
+        "debug",
+        "dofile",
+        "loadfile",
+        "require",
+        "loadstring",
+    ] {
+        let script = format!("return type({})", library);
+        assert_eq!(
+            roundtrip(&mut stream, array_of_bulks!("EVAL", script, "0")).await,
+            "$3\r\nnil\r\n",
+            "{}",
+            library
+        );
+    }
+    assert_eq!(
+        roundtrip(
+            &mut stream,
+            array_of_bulks!("EVAL", "return string.upper('ok')", "0")
+        )
+        .await,
+        "$2\r\nOK\r\n"
+    );
+}
+
+#[tokio::test]
+async fn a_script_past_its_time_limit_can_be_killed() {
+    let (addr, store) = start_server().await;
+    let mut scripted = TcpStream::connect(addr).await.unwrap();
+    let mut other = TcpStream::connect(addr).await.unwrap();
+    assert_eq!(
+        roundtrip(
+            &mut other,
+            array_of_bulks!("CONFIG", "SET", "lua-time-limit", "50")
+        )
+        .await,
+        "+OK\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut other, array_of_bulks!("SCRIPT", "KILL")).await,
+        "-NOTBUSY No scripts in execution right now.\r\n"
+    );
+
+    scripted
+        .write_all(array_of_bulks!("EVAL", "while true do end", "0"))
+        .await
+        .unwrap();
+    assert_eventually("the script to start", || store.scripts().is_running()).await;
+    // a client already waiting is told too, once the limit has passed
+    assert_eq!(
+        roundtrip(&mut other, array_of_bulks!("PING")).await,
+        "-BUSY Redis is busy running a script. You can only call SCRIPT KILL or SHUTDOWN NOSAVE.\r\n"
+    );
+    let mut late = TcpStream::connect(addr).await.unwrap();
+    assert!(roundtrip(&mut late, array_of_bulks!("GET", "k"))
+        .await
+        .starts_with("-BUSY "));
+
+    assert_eq!(
+        roundtrip(&mut other, array_of_bulks!("SCRIPT", "KILL")).await,
+        "+OK\r\n"
+    );
+    assert_eq!(
+        read_reply(&mut scripted).await,
+        "-ERR Script killed by user with SCRIPT KILL...\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut late, array_of_bulks!("PING")).await,
+        "+PONG\r\n"
+    );
+}
+
+#[tokio::test]
+async fn a_script_that_wrote_is_unkillable() {
+    let (addr, store) = start_server().await;
+    store.config().set_lua_time_limit(50);
+    let mut scripted = TcpStream::connect(addr).await.unwrap();
+    let mut other = TcpStream::connect(addr).await.unwrap();
+
+    let script = "redis.call('SET', KEYS[1], 'v') while true do end";
+    scripted
+        .write_all(array_of_bulks!("EVAL", script, "1", "k"))
+        .await
+        .unwrap();
+    assert_eventually("the script to start", || store.scripts().is_running()).await;
+    assert!(roundtrip(&mut other, array_of_bulks!("PING"))
+        .await
+        .starts_with("-BUSY "));
+    assert!(roundtrip(&mut other, array_of_bulks!("SCRIPT", "KILL"))
+        .await
+        .starts_with("-UNKILLABLE "));
+
+    // SHUTDOWN NOSAVE stops it anyway, on the way out
+    other
+        .write_all(array_of_bulks!("SHUTDOWN", "NOSAVE"))
+        .await
+        .unwrap();
+    assert_eq!(
+        read_reply(&mut scripted).await,
+        "-ERR Script killed by user with SCRIPT KILL...\r\n"
+    );
+}
+
+#[tokio::test]
+async fn functions_run_sandboxed_and_within_limits() {
+    let (addr, store) = start_server().await;
+    store.config().set_lua_time_limit(50);
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    let library = "#!lua name=host\nos.execute('true')";
+    assert!(
+        roundtrip(&mut stream, array_of_bulks!("FUNCTION", "LOAD", library))
+            .await
+            .starts_with("-ERR Error registering functions: "),
+    );
+    let library = "#!lua name=spin\nwhile true do end";
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("FUNCTION", "LOAD", library)).await,
+        "-ERR FUNCTION LOAD timeout\r\n"
+    );
+
+    let library = "#!lua name=spin
+redis.register_function('spin', function() while true do end end)";
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("FUNCTION", "LOAD", library)).await,
+        "$4\r\nspin\r\n"
+    );
+    stream
+        .write_all(array_of_bulks!("FCALL", "spin", "0"))
+        .await
+        .unwrap();
+    assert_eventually("the function to start", || store.scripts().is_running()).await;
+    let mut other = TcpStream::connect(addr).await.unwrap();
+    assert!(roundtrip(&mut other, array_of_bulks!("PING"))
+        .await
+        .starts_with("-BUSY "));
+    assert_eq!(
+        roundtrip(&mut other, array_of_bulks!("FUNCTION", "KILL")).await,
+        "+OK\r\n"
+    );
+    assert_eq!(
+        read_reply(&mut stream).await,
+        "-ERR Script killed by user with SCRIPT KILL...\r\n"
+    );
+}
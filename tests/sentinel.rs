@@ -0,0 +1,84 @@
+use redis_starter_rust::{
+    frame::Frame,
+    sentinel,
+    store::{sentinel::Address, Store},
+};
+use std::time::Duration;
+
+mod common;
+use common::{assert_eventually, TestServer};
+
+fn master_addr(port: u16) -> Frame {
+    Frame::Array(vec![
+        Frame::Bulk("127.0.0.1".into()),
+        Frame::Bulk(port.to_string().into()),
+    ])
+}
+
+#[tokio::test]
+async fn a_sentinel_promotes_the_replica_of_a_master_that_went_down() -> anyhow::Result<()> {
+    let (master, replica) = TestServer::start_pair().await;
+    let store = Store::new();
+    let address = Address {
+        host: "127.0.0.1".to_string(),
+        port: master.addr().port(),
+    };
+    store
+        .sentinel()
+        .monitor("mymaster".to_string(), address, Duration::from_millis(200));
+    let server = TestServer::start_with(store.clone()).await;
+    tokio::spawn(sentinel::run(store.clone()));
+
+    assert_eventually("the sentinel to learn of the replica", || {
+        store.sentinel().master().unwrap().replicas.len() == 1
+    })
+    .await;
+    let mut client = server.client().await;
+    assert_eq!(
+        client
+            .call(&["SENTINEL", "GET-MASTER-ADDR-BY-NAME", "mymaster"])
+            .await?,
+        master_addr(master.addr().port())
+    );
+    assert_eq!(
+        client
+            .call(&["SENTINEL", "GET-MASTER-ADDR-BY-NAME", "other"])
+            .await?,
+        Frame::Null
+    );
+
+    master.shutdown().await;
+    assert_eventually("the replica to be promoted", || {
+        !replica.store().is_replica()
+    })
+    .await;
+    assert_eq!(
+        client
+            .call(&["SENTINEL", "GET-MASTER-ADDR-BY-NAME", "mymaster"])
+            .await?,
+        master_addr(replica.addr().port())
+    );
+    let Frame::Array(fields) = client.call(&["SENTINEL", "MASTER", "mymaster"]).await? else {
+        panic!("SENTINEL MASTER isn't an array");
+    };
+    let count = fields
+        .chunks(2)
+        .find(|pair| pair[0] == Frame::Bulk("failover-count".into()))
+        .map(|pair| pair[1].clone());
+    assert_eq!(count, Some(Frame::Bulk("1".into())));
+
+    let mut promoted = replica.client().await;
+    promoted.set("foo", "bar").await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn sentinel_needs_sentinel_mode() -> anyhow::Result<()> {
+    let server = TestServer::start().await;
+    let mut client = server.client().await;
+    assert_eq!(
+        client.call(&["SENTINEL", "MASTERS"]).await?,
+        Frame::Error("ERR This instance has sentinel support disabled".to_string())
+    );
+    Ok(())
+}
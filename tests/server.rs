@@ -1,9 +1,12 @@
 use redis_starter_rust::array_of_bulks;
-use redis_starter_rust::info::DEFAULT_MASTER_REPLID;
+use redis_starter_rust::cache::Cache;
+use redis_starter_rust::frame::Frame;
+use redis_starter_rust::server::Server;
+use redis_starter_rust::store::Store;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
+use tokio::net::{TcpListener, TcpStream};
 mod common;
-use common::start_server;
+use common::{roundtrip, start_server};
 
 #[tokio::test]
 async fn send_error_unknown_command() {
@@ -16,11 +19,45 @@ async fn send_error_unknown_command() {
         .await
         .unwrap();
 
-    let mut response = [0; 28];
+    let expected = b"-ERR unknown command 'FOO', with args beginning with: 'hello' \r\n";
+    let mut response = [0; 64];
 
     stream.read_exact(&mut response).await.unwrap();
 
-    assert_eq!(b"-ERR unknown command \'foo\'\r\n", &response);
+    assert_eq!(expected, &response);
+
+    // the connection stays open for the next command
+    stream.write_all(array_of_bulks!("PING")).await.unwrap();
+    let mut response = [0; 7];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"+PONG\r\n", &response);
+}
+
+#[tokio::test]
+async fn bad_arguments_are_answered_without_closing_the_connection() {
+    let (addr, _store) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("GET")).await,
+        "-ERR wrong number of arguments for 'get' command\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("SET", "k")).await,
+        "-ERR wrong number of arguments for 'set' command\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("GET", "a", "b")).await,
+        "-ERR wrong number of arguments for 'get' command\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("SHUTDOWN", "LATER")).await,
+        "-ERR syntax error\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("PING")).await,
+        "+PONG\r\n"
+    );
 }
 
 #[tokio::test]
@@ -165,8 +202,52 @@ async fn set_expired() -> anyhow::Result<()> {
 }
 
 #[tokio::test]
-async fn info() -> anyhow::Result<()> {
+async fn set_options() -> anyhow::Result<()> {
     let (addr, _store) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await?;
+
+    for (request, reply) in [
+        (array_of_bulks!("SET", "k", "v", "xx"), "$-1\r\n"),
+        (
+            array_of_bulks!("SET", "k", "v", "nx", "EX", "100"),
+            "+OK\r\n",
+        ),
+        (array_of_bulks!("SET", "k", "w", "NX"), "$-1\r\n"),
+        (
+            array_of_bulks!("SET", "k", "w", "px", "100000", "XX"),
+            "+OK\r\n",
+        ),
+        (array_of_bulks!("GET", "k"), "$1\r\nw\r\n"),
+        (
+            array_of_bulks!("SET", "k", "v", "NX", "XX"),
+            "-ERR syntax error\r\n",
+        ),
+        (
+            array_of_bulks!("SET", "k", "v", "EX", "1", "PX", "1"),
+            "-ERR syntax error\r\n",
+        ),
+        (
+            array_of_bulks!("SET", "k", "v", "EX", "0"),
+            "-ERR invalid expire time in 'set' command\r\n",
+        ),
+        (
+            array_of_bulks!("SET", "k", "v", "PX", "soon"),
+            "-ERR value is not an integer or out of range\r\n",
+        ),
+    ] {
+        assert_eq!(
+            roundtrip(&mut stream, request).await,
+            reply,
+            "{:?}",
+            request
+        );
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn info() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
 
     let mut stream = TcpStream::connect(addr).await.unwrap();
 
@@ -175,13 +256,15 @@ async fn info() -> anyhow::Result<()> {
         .await
         .unwrap();
 
-    let mut response = [0; 94];
-
-    stream.read_exact(&mut response).await.unwrap();
     let expected = format!(
-        "$91\r\nrole:master\r\nmaster_replid:{}\r\nmaster_repl_offset:0",
-        DEFAULT_MASTER_REPLID
+        "role:master\r\nconnected_slaves:0\r\nmaster_failover_state:no-failover\r\nmaster_replid:{}\r\nmaster_replid2:{}\r\nmaster_repl_offset:0\r\nsecond_repl_offset:-1\r\n",
+        store.replicas().replid(),
+        "0".repeat(40)
     );
+    let expected = format!("${}\r\n{}\r\n", expected.len(), expected);
+    let mut response = vec![0; expected.len()];
+
+    stream.read_exact(&mut response).await.unwrap();
 
     assert_eq!(expected.as_bytes(), &response);
 
@@ -190,7 +273,7 @@ async fn info() -> anyhow::Result<()> {
 
 #[tokio::test]
 async fn repl_conf_listening_port() -> anyhow::Result<()> {
-    let (addr, store) = start_server().await;
+    let (addr, _store) = start_server().await;
 
     let mut stream = TcpStream::connect(addr).await.unwrap();
 
@@ -225,6 +308,22 @@ async fn repl_conf_capabilities() -> anyhow::Result<()> {
 
     assert_eq!(b"+OK\r\n", &response);
 
+    // options this server doesn't know are skipped with their values
+    stream
+        .write_all(array_of_bulks!(
+            "replconf",
+            "ip-address",
+            "10.0.0.1",
+            "CAPA",
+            "psync2",
+            "rdb-only",
+            "0"
+        ))
+        .await
+        .unwrap();
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"+OK\r\n", &response);
+
     Ok(())
 }
 
@@ -250,7 +349,7 @@ async fn repl_get_ack() -> anyhow::Result<()> {
 
 #[tokio::test]
 async fn test_psync() -> anyhow::Result<()> {
-    let (addr, _store) = start_server().await;
+    let (addr, store) = start_server().await;
 
     let mut stream = TcpStream::connect(addr).await.unwrap();
 
@@ -259,7 +358,7 @@ async fn test_psync() -> anyhow::Result<()> {
         .await
         .unwrap();
 
-    let expected = format!("+FULLRESYNC {} {}\r\n", DEFAULT_MASTER_REPLID, 0);
+    let expected = format!("+FULLRESYNC {} {}\r\n", store.replicas().replid(), 0);
 
     let mut response = [0; 56];
 
@@ -268,3 +367,1470 @@ async fn test_psync() -> anyhow::Result<()> {
     assert_eq!(expected, response_str);
     Ok(())
 }
+
+#[tokio::test]
+async fn config_get_and_set() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await?;
+
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("CONFIG", "GET", "dbfilename")).await,
+        "*2\r\n$10\r\ndbfilename\r\n$8\r\ndump.rdb\r\n"
+    );
+    assert_eq!(
+        roundtrip(
+            &mut stream,
+            array_of_bulks!(
+                "CONFIG",
+                "SET",
+                "maxmemory",
+                "1mb",
+                "dbfilename",
+                "data.rdb"
+            )
+        )
+        .await,
+        "+OK\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("CONFIG", "GET", "MAX*", "db*")).await,
+        "*10\r\n$10\r\ndbfilename\r\n$8\r\ndata.rdb\r\n$9\r\nmaxmemory\r\n$7\r\n1048576\r\n$16\r\nmaxmemory-policy\r\n$10\r\nnoeviction\r\n$17\r\nmaxmemory-samples\r\n$1\r\n5\r\n$10\r\nmaxclients\r\n$5\r\n10000\r\n"
+    );
+    // an invalid value leaves every parameter in the call unchanged
+    assert_eq!(
+        roundtrip(
+            &mut stream,
+            array_of_bulks!("CONFIG", "SET", "dbfilename", "other.rdb", "maxmemory", "lots"),
+        )
+        .await,
+        "-ERR CONFIG SET failed (possibly related to argument 'maxmemory') - argument must be a memory value\r\n"
+    );
+    assert_eq!(
+        roundtrip(
+            &mut stream,
+            array_of_bulks!("CONFIG", "SET", "no-such-param", "1")
+        )
+        .await,
+        "-ERR Unknown option or number of arguments for CONFIG SET - 'no-such-param'\r\n"
+    );
+    assert_eq!(
+        roundtrip(
+            &mut stream,
+            array_of_bulks!("CONFIG", "GET", "dbfilename", "nothing*")
+        )
+        .await,
+        "*2\r\n$10\r\ndbfilename\r\n$8\r\ndata.rdb\r\n"
+    );
+    // RESP3 clients get a map
+    roundtrip(&mut stream, array_of_bulks!("HELLO", "3")).await;
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("CONFIG", "GET", "dbfilename")).await,
+        "%1\r\n$10\r\ndbfilename\r\n$8\r\ndata.rdb\r\n"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn select_move_and_swapdb() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut first = TcpStream::connect(addr).await?;
+    let mut second = TcpStream::connect(addr).await?;
+
+    assert_eq!(
+        roundtrip(&mut first, array_of_bulks!("SET", "foo", "0")).await,
+        "+OK\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut first, array_of_bulks!("SELECT", "1")).await,
+        "+OK\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut first, array_of_bulks!("GET", "foo")).await,
+        "$-1\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut first, array_of_bulks!("SET", "bar", "1")).await,
+        "+OK\r\n"
+    );
+    // each connection selects for itself
+    assert_eq!(
+        roundtrip(&mut second, array_of_bulks!("GET", "foo")).await,
+        "$1\r\n0\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut second, array_of_bulks!("GET", "bar")).await,
+        "$-1\r\n"
+    );
+
+    assert_eq!(
+        roundtrip(&mut first, array_of_bulks!("MOVE", "bar", "0")).await,
+        ":1\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut first, array_of_bulks!("MOVE", "bar", "0")).await,
+        ":0\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut second, array_of_bulks!("GET", "bar")).await,
+        "$1\r\n1\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut first, array_of_bulks!("MOVE", "bar", "1")).await,
+        "-ERR source and destination objects are the same\r\n"
+    );
+
+    assert_eq!(
+        roundtrip(&mut second, array_of_bulks!("SWAPDB", "0", "1")).await,
+        "+OK\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut second, array_of_bulks!("GET", "foo")).await,
+        "$-1\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut first, array_of_bulks!("GET", "foo")).await,
+        "$1\r\n0\r\n"
+    );
+
+    assert_eq!(
+        roundtrip(&mut first, array_of_bulks!("SELECT", "16")).await,
+        "-ERR DB index is out of range\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut first, array_of_bulks!("SELECT", "one")).await,
+        "-ERR value is not an integer or out of range\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut first, array_of_bulks!("SWAPDB", "x", "1")).await,
+        "-ERR invalid first DB index\r\n"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn acl_users_are_limited_to_their_commands_and_keys() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut admin = TcpStream::connect(addr).await?;
+    let mut client = TcpStream::connect(addr).await?;
+
+    assert_eq!(
+        roundtrip(
+            &mut admin,
+            array_of_bulks!(
+                "ACL",
+                "SETUSER",
+                "alice",
+                "on",
+                ">pw",
+                "~app:*",
+                "+@read",
+                "+set",
+                "+acl|whoami"
+            )
+        )
+        .await,
+        "+OK\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut client, array_of_bulks!("AUTH", "alice", "nope")).await,
+        "-WRONGPASS invalid username-password pair or user is disabled.\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut client, array_of_bulks!("AUTH", "alice", "pw")).await,
+        "+OK\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut client, array_of_bulks!("ACL", "WHOAMI")).await,
+        "$5\r\nalice\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut client, array_of_bulks!("SET", "app:1", "v")).await,
+        "+OK\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut client, array_of_bulks!("GET", "app:1")).await,
+        "$1\r\nv\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut client, array_of_bulks!("GET", "other")).await,
+        "-NOPERM No permissions to access a key\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut client, array_of_bulks!("DEL", "app:1")).await,
+        "-NOPERM User alice has no permissions to run the 'del' command\r\n"
+    );
+
+    // once the default user has a password, new connections must AUTH
+    assert_eq!(
+        roundtrip(
+            &mut admin,
+            array_of_bulks!("ACL", "SETUSER", "default", ">secret")
+        )
+        .await,
+        "+OK\r\n"
+    );
+    let mut late = TcpStream::connect(addr).await?;
+    assert_eq!(
+        roundtrip(&mut late, array_of_bulks!("GET", "app:1")).await,
+        "-NOAUTH Authentication required.\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut late, array_of_bulks!("AUTH", "secret")).await,
+        "+OK\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut late, array_of_bulks!("DEL", "app:1")).await,
+        ":1\r\n"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn client_list_and_info_describe_each_connection() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut first = TcpStream::connect(addr).await?;
+    let mut second = TcpStream::connect(addr).await?;
+
+    assert_eq!(
+        roundtrip(&mut first, array_of_bulks!("SELECT", "2")).await,
+        "+OK\r\n"
+    );
+    let info = roundtrip(&mut second, array_of_bulks!("CLIENT", "INFO")).await;
+    let id = info
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("id="))
+        .unwrap()
+        .to_string();
+    assert!(info.contains(" flags=N db=0 "), "{}", info);
+    assert!(
+        info.ends_with(" user=default cmd=client|info\n\r\n"),
+        "{}",
+        info
+    );
+
+    let list = roundtrip(&mut second, array_of_bulks!("CLIENT", "LIST")).await;
+    let lines: Vec<_> = list
+        .lines()
+        .filter(|line| line.starts_with("id="))
+        .collect();
+    assert_eq!(lines.len(), 2, "{}", list);
+    assert!(
+        lines[0].contains(" db=2 ") && lines[0].ends_with("cmd=select"),
+        "{}",
+        list
+    );
+    assert!(lines[1].starts_with(&format!("id={} ", id)), "{}", list);
+
+    let only = roundtrip(
+        &mut first,
+        array_of_bulks!("CLIENT", "LIST", "ID", id.as_str()),
+    )
+    .await;
+    let lines: Vec<_> = only
+        .lines()
+        .filter(|line| line.starts_with("id="))
+        .collect();
+    assert_eq!(lines.len(), 1, "{}", only);
+    assert!(lines[0].starts_with(&format!("id={} ", id)), "{}", only);
+    Ok(())
+}
+
+#[tokio::test]
+async fn client_names_and_ids() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut first = TcpStream::connect(addr).await?;
+    let mut second = TcpStream::connect(addr).await?;
+
+    assert_eq!(
+        roundtrip(&mut first, array_of_bulks!("CLIENT", "ID")).await,
+        ":1\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut second, array_of_bulks!("CLIENT", "ID")).await,
+        ":2\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut first, array_of_bulks!("CLIENT", "GETNAME")).await,
+        "$-1\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut first, array_of_bulks!("CLIENT", "SETNAME", "worker 1")).await,
+        "-ERR Client names cannot contain spaces, newlines or special characters.\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut first, array_of_bulks!("CLIENT", "SETNAME", "worker-1")).await,
+        "+OK\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut first, array_of_bulks!("CLIENT", "GETNAME")).await,
+        "$8\r\nworker-1\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut second, array_of_bulks!("CLIENT", "GETNAME")).await,
+        "$-1\r\n"
+    );
+
+    let list = roundtrip(&mut second, array_of_bulks!("CLIENT", "LIST", "ID", "1")).await;
+    assert!(list.contains(" name=worker-1 "), "{}", list);
+    Ok(())
+}
+
+#[tokio::test]
+async fn pexpireat_and_persist_change_a_keys_expiry() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+    let mut client = redis_starter_rust::client::Client::connect(addr).await?;
+    client.call(&["SET", "k", "v"]).await?;
+
+    let in_a_minute = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+    let millis = in_a_minute
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_millis();
+    let request = ["PEXPIREAT", "k", &millis.to_string()];
+    assert_eq!(client.call(&request).await?, Frame::Integer(1));
+    let ttl = store.expiry(&"k".into()).flatten().unwrap() - std::time::Instant::now();
+    assert!(ttl > std::time::Duration::from_secs(59), "{:?}", ttl);
+    assert_eq!(
+        client.call(&["PEXPIREAT", "missing", "1"]).await?,
+        Frame::Integer(0)
+    );
+
+    assert_eq!(client.call(&["PERSIST", "k"]).await?, Frame::Integer(1));
+    assert_eq!(client.call(&["PERSIST", "k"]).await?, Frame::Integer(0));
+    assert_eq!(store.expiry(&"k".into()), Some(None));
+
+    assert_eq!(
+        client.call(&["PEXPIREAT", "k", "1"]).await?,
+        Frame::Integer(1)
+    );
+    assert_eq!(client.call(&["GET", "k"]).await?, Frame::Null);
+    Ok(())
+}
+
+#[tokio::test]
+async fn typed_commands_refuse_keys_of_another_type() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut client = redis_starter_rust::client::Client::connect(addr).await?;
+    client.call(&["SET", "string", "1"]).await?;
+    client.call(&["ZADD", "zset", "1", "a"]).await?;
+    client.call(&["XADD", "stream", "1-1", "f", "v"]).await?;
+    client
+        .call(&["XGROUP", "CREATE", "stream", "group", "0"])
+        .await?;
+
+    let wrong_type = [
+        &["GET", "zset"][..],
+        &["GETBIT", "zset", "0"],
+        &["SETBIT", "stream", "0", "1"],
+        &["BITCOUNT", "zset"],
+        &["BITPOS", "zset", "1"],
+        &["BITOP", "AND", "dest", "string", "zset"],
+        &["PFADD", "zset", "a"],
+        &["PFCOUNT", "stream"],
+        &["PFMERGE", "dest", "zset"],
+        &["ZADD", "string", "1", "a"],
+        &["ZINCRBY", "stream", "1", "a"],
+        &["ZCARD", "string"],
+        &["ZCOUNT", "string", "-inf", "+inf"],
+        &["ZLEXCOUNT", "stream", "-", "+"],
+        &["ZRANGE", "string", "0", "-1"],
+        &["ZRANGESTORE", "dest", "string", "0", "-1"],
+        &["ZPOPMIN", "string"],
+        &["BZPOPMIN", "string", "0"],
+        &["ZUNIONSTORE", "dest", "2", "zset", "string"],
+        &["GEOADD", "string", "13.36", "38.11", "palermo"],
+        &["GEOPOS", "stream", "palermo"],
+        &["GEODIST", "string", "a", "b"],
+        &["XADD", "zset", "*", "f", "v"],
+        &["XLEN", "string"],
+        &["XRANGE", "zset", "-", "+"],
+        &["XDEL", "string", "1-1"],
+        &["XTRIM", "zset", "MAXLEN", "0"],
+        &["XGROUP", "CREATE", "string", "group", "0"],
+        &["XREADGROUP", "GROUP", "group", "c", "STREAMS", "zset", ">"],
+        &["XACK", "string", "group", "1-1"],
+        &["XPENDING", "zset", "group"],
+        &["XCLAIM", "string", "group", "c", "0", "1-1"],
+        &["XAUTOCLAIM", "zset", "group", "c", "0", "0"],
+    ];
+    for request in wrong_type {
+        assert_eq!(
+            client.call(request).await?,
+            Frame::Error(
+                "WRONGTYPE Operation against a key holding the wrong kind of value".to_string()
+            ),
+            "{:?}",
+            request
+        );
+    }
+    // SET replaces whatever was there
+    client.call(&["SET", "zset", "v"]).await?;
+    assert_eq!(
+        client.call(&["GET", "zset"]).await?,
+        Frame::Bulk("v".into())
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn container_commands_list_their_subcommands() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut client = redis_starter_rust::client::Client::connect(addr).await?;
+    for command in ["CLIENT", "config", "Memory"] {
+        let Frame::Array(lines) = client.call(&[command, "help"]).await? else {
+            panic!("{} HELP is not an array", command);
+        };
+        let lines: Vec<String> = lines
+            .into_iter()
+            .map(|line| match line {
+                Frame::Simple(line) => line,
+                line => panic!("{:?} is not a simple string", line),
+            })
+            .collect();
+        assert_eq!(
+            lines[0],
+            format!(
+                "{} <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                command.to_uppercase()
+            )
+        );
+        assert_eq!(lines[lines.len() - 2..], ["HELP", "    Print this help."]);
+    }
+    let Frame::Array(lines) = client.call(&["CONFIG", "HELP"]).await? else {
+        panic!("CONFIG HELP is not an array");
+    };
+    assert!(lines.contains(&Frame::Simple("GET <pattern> [<pattern> ...]".to_string())));
+    // HELP takes no arguments, so this one is left to CLIENT
+    assert!(matches!(
+        client.call(&["CLIENT", "HELP", "me"]).await?,
+        Frame::Error(error) if error.contains("CLIENT HELP")
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn client_kill_and_pause() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut victim = TcpStream::connect(addr).await?;
+    let mut admin = TcpStream::connect(addr).await?;
+    let mut other = TcpStream::connect(addr).await?;
+
+    assert_eq!(
+        roundtrip(&mut victim, array_of_bulks!("CLIENT", "ID")).await,
+        ":1\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut admin, array_of_bulks!("CLIENT", "KILL", "ID", "1")).await,
+        ":1\r\n"
+    );
+    let mut buf = [0; 16];
+    assert_eq!(victim.read(&mut buf).await?, 0);
+    assert_eq!(
+        roundtrip(&mut admin, array_of_bulks!("CLIENT", "KILL", "127.0.0.1:1")).await,
+        "-ERR No such client\r\n"
+    );
+
+    assert_eq!(
+        roundtrip(
+            &mut admin,
+            array_of_bulks!("CLIENT", "PAUSE", "300", "WRITE")
+        )
+        .await,
+        "+OK\r\n"
+    );
+    let started = std::time::Instant::now();
+    assert_eq!(
+        roundtrip(&mut other, array_of_bulks!("GET", "k")).await,
+        "$-1\r\n"
+    );
+    assert!(started.elapsed() < std::time::Duration::from_millis(200));
+    assert_eq!(
+        roundtrip(&mut other, array_of_bulks!("SET", "k", "v")).await,
+        "+OK\r\n"
+    );
+    assert!(started.elapsed() >= std::time::Duration::from_millis(250));
+
+    assert_eq!(
+        roundtrip(&mut admin, array_of_bulks!("CLIENT", "PAUSE", "60000")).await,
+        "+OK\r\n"
+    );
+    other.write_all(array_of_bulks!("GET", "k")).await?;
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert_eq!(
+        roundtrip(&mut admin, array_of_bulks!("CLIENT", "UNPAUSE")).await,
+        "+OK\r\n"
+    );
+    let mut response = [0; 7];
+    other.read_exact(&mut response).await?;
+    assert_eq!(&response, b"$1\r\nv\r\n");
+    Ok(())
+}
+
+#[tokio::test]
+async fn client_no_touch_leaves_idle_times_alone() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+    store.config().set_enable_debug_command("local".to_string());
+    let mut reader = TcpStream::connect(addr).await?;
+    let mut other = TcpStream::connect(addr).await?;
+
+    let idle = |reply: String| -> String {
+        reply
+            .split_whitespace()
+            .find_map(|field| field.strip_prefix("lru_seconds_idle:"))
+            .unwrap()
+            .to_string()
+    };
+
+    assert_eq!(
+        roundtrip(&mut reader, array_of_bulks!("SET", "k", "v")).await,
+        "+OK\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut reader, array_of_bulks!("CLIENT", "NO-TOUCH", "on")).await,
+        "+OK\r\n"
+    );
+    assert!(roundtrip(&mut reader, array_of_bulks!("CLIENT", "INFO"))
+        .await
+        .contains(" flags=T "));
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    assert_eq!(
+        roundtrip(&mut reader, array_of_bulks!("GET", "k")).await,
+        "$1\r\nv\r\n"
+    );
+    let reply = roundtrip(&mut reader, array_of_bulks!("DEBUG", "OBJECT", "k")).await;
+    assert_eq!(idle(reply), "1");
+
+    roundtrip(&mut other, array_of_bulks!("GET", "k")).await;
+    let reply = roundtrip(&mut reader, array_of_bulks!("DEBUG", "OBJECT", "k")).await;
+    assert_eq!(idle(reply), "0");
+
+    assert_eq!(
+        roundtrip(&mut reader, array_of_bulks!("CLIENT", "NO-EVICT", "on")).await,
+        "+OK\r\n"
+    );
+    assert!(roundtrip(&mut reader, array_of_bulks!("CLIENT", "INFO"))
+        .await
+        .contains(" flags=eT "));
+    Ok(())
+}
+
+#[tokio::test]
+async fn command_describes_the_command_table() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await?;
+
+    let count = roundtrip(&mut stream, array_of_bulks!("COMMAND", "COUNT")).await;
+    let count: usize = count
+        .strip_prefix(':')
+        .and_then(|n| n.trim_end().parse().ok())
+        .unwrap();
+    assert!(count > 50);
+
+    assert_eq!(
+        roundtrip(
+            &mut stream,
+            array_of_bulks!("COMMAND", "INFO", "GET", "nope")
+        )
+        .await,
+        "*2\r\n*10\r\n$3\r\nget\r\n:2\r\n*2\r\n+readonly\r\n+fast\r\n:1\r\n:1\r\n:1\r\n\
+         *2\r\n+@read\r\n+@string\r\n*0\r\n*0\r\n*0\r\n$-1\r\n"
+    );
+    let zunionstore = roundtrip(
+        &mut stream,
+        array_of_bulks!("COMMAND", "INFO", "zunionstore"),
+    )
+    .await;
+    assert!(zunionstore.contains(":-4\r\n"));
+    assert!(zunionstore.contains("+movablekeys\r\n"));
+
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("COMMAND", "DOCS", "echo")).await,
+        "*2\r\n$4\r\necho\r\n*4\r\n$7\r\nsummary\r\n$25\r\nReturns the given string.\r\n\
+         $5\r\ngroup\r\n$10\r\nconnection\r\n"
+    );
+    let client = roundtrip(&mut stream, array_of_bulks!("COMMAND", "DOCS", "client")).await;
+    assert!(client.contains("$11\r\nsubcommands\r\n"));
+    assert!(client.contains("$14\r\nclient|setname\r\n"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn info_reports_server_clients_stats_and_keyspace() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await?;
+    let mut other = TcpStream::connect(addr).await?;
+
+    roundtrip(&mut other, array_of_bulks!("PING")).await;
+    roundtrip(&mut stream, array_of_bulks!("SET", "a", "1")).await;
+    roundtrip(&mut stream, array_of_bulks!("SET", "b", "2", "PX", "60000")).await;
+    roundtrip(&mut stream, array_of_bulks!("SET", "gone", "3", "PX", "1")).await;
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    roundtrip(&mut stream, array_of_bulks!("GET", "a")).await;
+    roundtrip(&mut stream, array_of_bulks!("GET", "gone")).await;
+    roundtrip(&mut stream, array_of_bulks!("GET", "missing")).await;
+
+    let info = roundtrip(&mut stream, array_of_bulks!("INFO")).await;
+    for header in ["# Server", "# Clients", "# Memory", "# Stats", "# Keyspace"] {
+        assert!(info.contains(header), "{}", info);
+    }
+    assert!(
+        info.contains(&format!("process_id:{}\r\n", std::process::id())),
+        "{}",
+        info
+    );
+    assert!(info.contains("connected_clients:2\r\n"), "{}", info);
+    assert!(
+        info.contains("total_connections_received:2\r\n"),
+        "{}",
+        info
+    );
+    assert!(info.contains("total_commands_processed:8\r\n"), "{}", info);
+    assert!(info.contains("expired_keys:1\r\n"), "{}", info);
+    assert!(info.contains("keyspace_hits:1\r\n"), "{}", info);
+    assert!(info.contains("keyspace_misses:2\r\n"), "{}", info);
+    assert!(info.contains("db0:keys=2,expires=1,avg_ttl="), "{}", info);
+
+    let keyspace = roundtrip(&mut stream, array_of_bulks!("INFO", "keyspace")).await;
+    assert!(!keyspace.contains('#'), "{}", keyspace);
+    Ok(())
+}
+
+#[tokio::test]
+async fn maxmemory_evicts_by_policy_or_refuses_writes() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await?;
+
+    let value = "v".repeat(100);
+    for i in 0..10 {
+        let key = format!("key-{}", i);
+        roundtrip(&mut stream, array_of_bulks!("SET", key, value)).await;
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+
+    roundtrip(
+        &mut stream,
+        array_of_bulks!("CONFIG", "SET", "maxmemory", "1000"),
+    )
+    .await;
+    assert!(roundtrip(&mut stream, array_of_bulks!("SET", "more", "1"))
+        .await
+        .starts_with("-OOM command not allowed"));
+    // reads are still served
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("GET", "key-0")).await,
+        format!("$100\r\n{}\r\n", value)
+    );
+
+    roundtrip(
+        &mut stream,
+        array_of_bulks!("CONFIG", "SET", "maxmemory-policy", "allkeys-lru"),
+    )
+    .await;
+    roundtrip(
+        &mut stream,
+        array_of_bulks!("CONFIG", "SET", "maxmemory-samples", "64"),
+    )
+    .await;
+    // only commands that may grow the data make room
+    roundtrip(&mut stream, array_of_bulks!("GET", "key-1")).await;
+    assert!(roundtrip(&mut stream, array_of_bulks!("INFO", "stats"))
+        .await
+        .contains("evicted_keys:0\r\n"));
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("SET", "more", "1")).await,
+        "+OK\r\n"
+    );
+    let info = roundtrip(&mut stream, array_of_bulks!("INFO")).await;
+    let field = |name: &str| -> u64 {
+        info.split("\r\n")
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|value| value.strip_prefix(':'))
+            .unwrap()
+            .parse()
+            .unwrap()
+    };
+    assert!(field("used_memory") <= 1000, "{}", info);
+    assert!(field("evicted_keys") > 0, "{}", info);
+    assert!(
+        info.contains("maxmemory_policy:allkeys-lru\r\n"),
+        "{}",
+        info
+    );
+    // the key read last is the last to go
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("GET", "key-0")).await,
+        format!("$100\r\n{}\r\n", value)
+    );
+    assert_eq!(
+        roundtrip(
+            &mut stream,
+            array_of_bulks!("CONFIG", "SET", "maxmemory-policy", "oldest")
+        )
+        .await,
+        "-ERR CONFIG SET failed (possibly related to argument 'maxmemory-policy') - argument(s) must be one of the following: volatile-lru, allkeys-lru, volatile-lfu, allkeys-lfu, volatile-random, allkeys-random, volatile-ttl, noeviction\r\n"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn zscan_returns_every_member_once() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut client = redis_starter_rust::client::Client::connect(addr).await?;
+    for i in 0..50 {
+        client
+            .call(&["ZADD", "z", &i.to_string(), &format!("m:{}", i)])
+            .await?;
+    }
+
+    let (mut cursor, mut members) = ("0".to_string(), vec![]);
+    loop {
+        let reply = client.call(&["ZSCAN", "z", &cursor, "COUNT", "7"]).await?;
+        let Frame::Array(reply) = reply else {
+            panic!("{:?}", reply);
+        };
+        let [Frame::Bulk(next), Frame::Array(page)] = &reply[..] else {
+            panic!("{:?}", reply);
+        };
+        for pair in page.chunks(2) {
+            let [Frame::Bulk(member), Frame::Bulk(score)] = pair else {
+                panic!("{:?}", pair);
+            };
+            let member = String::from_utf8_lossy(member).to_string();
+            assert_eq!(&member[2..], &String::from_utf8_lossy(score));
+            members.push(member);
+        }
+        cursor = String::from_utf8_lossy(next).to_string();
+        if cursor == "0" {
+            break;
+        }
+    }
+    members.sort();
+    let mut expected: Vec<String> = (0..50).map(|i| format!("m:{}", i)).collect();
+    expected.sort();
+    assert_eq!(members, expected);
+
+    let reply = client
+        .call(&[
+            "ZSCAN", "z", "0", "MATCH", "m:4?", "COUNT", "100", "NOSCORES",
+        ])
+        .await?;
+    let Frame::Array(reply) = reply else {
+        panic!("{:?}", reply);
+    };
+    let [Frame::Bulk(next), Frame::Array(page)] = &reply[..] else {
+        panic!("{:?}", reply);
+    };
+    assert_eq!(next, "0");
+    let mut page = page.clone();
+    page.sort_by_key(|member| format!("{:?}", member));
+    let expected: Vec<Frame> = (40..50)
+        .map(|i| Frame::Bulk(format!("m:{}", i).into()))
+        .collect();
+    assert_eq!(page, expected);
+
+    assert_eq!(
+        client.call(&["ZSCAN", "missing", "0"]).await?,
+        Frame::Array(vec![Frame::Bulk("0".into()), Frame::Array(vec![])])
+    );
+    client.call(&["SET", "s", "v"]).await?;
+    assert_eq!(
+        client.call(&["ZSCAN", "s", "0"]).await?,
+        Frame::Error(
+            "WRONGTYPE Operation against a key holding the wrong kind of value".to_string()
+        )
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn scan_returns_every_key_once() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut client = redis_starter_rust::client::Client::connect(addr).await?;
+    for i in 0..100 {
+        client.call(&["SET", &format!("key:{}", i), "v"]).await?;
+    }
+    client.call(&["ZADD", "zset", "1", "a"]).await?;
+
+    async fn scan(
+        client: &mut redis_starter_rust::client::Client,
+        args: &[&str],
+        mut between: impl FnMut(usize) -> Option<String>,
+    ) -> anyhow::Result<Vec<String>> {
+        let (mut cursor, mut keys, mut calls) = ("0".to_string(), vec![], 0);
+        loop {
+            let request: Vec<&str> = ["SCAN", cursor.as_str()]
+                .into_iter()
+                .chain(args.iter().copied())
+                .collect();
+            let Frame::Array(reply) = client.call(&request).await? else {
+                panic!("SCAN didn't reply with an array");
+            };
+            let [Frame::Bulk(next), Frame::Array(page)] = &reply[..] else {
+                panic!("{:?}", reply);
+            };
+            for key in page {
+                let Frame::Bulk(key) = key else {
+                    panic!("{:?}", key);
+                };
+                keys.push(String::from_utf8_lossy(key).to_string());
+            }
+            cursor = String::from_utf8_lossy(next).to_string();
+            if cursor == "0" {
+                return Ok(keys);
+            }
+            calls += 1;
+            if let Some(key) = between(calls) {
+                client.call(&["SET", &key, "v"]).await?;
+            }
+        }
+    }
+
+    // keys added along the way may or may not come up, the others exactly once
+    let mut keys = scan(&mut client, &["COUNT", "7"], |calls| {
+        Some(format!("new:{}", calls))
+    })
+    .await?;
+    keys.retain(|key| !key.starts_with("new:"));
+    keys.sort();
+    let mut expected: Vec<String> = (0..100).map(|i| format!("key:{}", i)).collect();
+    expected.push("zset".to_string());
+    expected.sort();
+    assert_eq!(keys, expected);
+
+    let keys = scan(&mut client, &["MATCH", "key:1?", "COUNT", "3"], |_| None).await?;
+    assert_eq!(keys.len(), 10, "{:?}", keys);
+    let keys = scan(&mut client, &["type", "ZSET"], |_| None).await?;
+    assert_eq!(keys, ["zset"]);
+    client.call(&["DEL", "zset"]).await?;
+    let keys = scan(&mut client, &["TYPE", "zset"], |_| None).await?;
+    assert!(keys.is_empty(), "{:?}", keys);
+
+    assert_eq!(
+        client.call(&["SCAN", "x"]).await?,
+        Frame::Error("ERR invalid cursor".to_string())
+    );
+    assert_eq!(
+        client.call(&["SCAN", "0", "COUNT", "0"]).await?,
+        Frame::Error("ERR syntax error".to_string())
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn type_stats_follow_every_write() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+    let mut client = redis_starter_rust::client::Client::connect(addr).await?;
+    let held = |db: usize| -> Vec<(&str, usize, usize)> { store.type_stats()[db].held().collect() };
+
+    client.call(&["SET", "a", "abc"]).await?;
+    client.call(&["SETBIT", "b", "15", "1"]).await?;
+    client.call(&["ZADD", "z", "1", "x", "2", "y"]).await?;
+    client.call(&["XADD", "s", "1-1", "f", "v"]).await?;
+    client.call(&["XADD", "s", "1-2", "f", "v"]).await?;
+    assert_eq!(
+        held(0),
+        [("string", 2, 5), ("zset", 1, 2), ("stream", 1, 2)]
+    );
+
+    // changed in place, replaced by another type, and removed
+    client.call(&["SETBIT", "a", "63", "1"]).await?;
+    client.call(&["XTRIM", "s", "MAXLEN", "1"]).await?;
+    client.call(&["SET", "z", "v"]).await?;
+    assert_eq!(held(0), [("string", 3, 11), ("stream", 1, 1)]);
+    client.call(&["ZADD", "z2", "1", "x"]).await?;
+    client.call(&["ZPOPMIN", "z2"]).await?;
+    client.call(&["DEL", "b"]).await?;
+    assert_eq!(held(0), [("string", 2, 9), ("stream", 1, 1)]);
+
+    client.call(&["MOVE", "s", "1"]).await?;
+    assert_eq!(held(1), [("stream", 1, 1)]);
+    client.call(&["SWAPDB", "0", "1"]).await?;
+    assert_eq!(held(0), [("stream", 1, 1)]);
+    assert_eq!(held(1), [("string", 2, 9)]);
+
+    let info = client.call(&["INFO", "keyspace"]).await?;
+    let Frame::Bulk(info) = info else {
+        panic!("{:?}", info);
+    };
+    let info = String::from_utf8_lossy(&info);
+    assert!(
+        info.contains("db0:keys=1,expires=0,avg_ttl=0,stream=1\r\n"),
+        "{}",
+        info
+    );
+    assert!(info.contains(",string=2\r\n"), "{}", info);
+    Ok(())
+}
+
+#[tokio::test]
+async fn memory_usage_and_stats_account_for_each_key() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await?;
+
+    fn integer(reply: &str) -> u64 {
+        reply.trim_start_matches(':').trim_end().parse().unwrap()
+    }
+
+    roundtrip(&mut stream, array_of_bulks!("SET", "short", "abc")).await;
+    roundtrip(&mut stream, array_of_bulks!("SET", "long", "x".repeat(100))).await;
+    let short = integer(&roundtrip(&mut stream, array_of_bulks!("MEMORY", "USAGE", "short")).await);
+    let long = integer(&roundtrip(&mut stream, array_of_bulks!("MEMORY", "USAGE", "long")).await);
+    assert!(short > 3 && long > short + 100, "{} {}", short, long);
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("MEMORY", "USAGE", "missing")).await,
+        "$-1\r\n"
+    );
+
+    for i in 0..200 {
+        let member = format!("member-{}", i);
+        roundtrip(&mut stream, array_of_bulks!("ZADD", "zset", "1", member)).await;
+    }
+    let sampled = integer(
+        &roundtrip(
+            &mut stream,
+            array_of_bulks!("MEMORY", "USAGE", "zset", "SAMPLES", "5"),
+        )
+        .await,
+    );
+    let exact = integer(
+        &roundtrip(
+            &mut stream,
+            array_of_bulks!("MEMORY", "USAGE", "zset", "SAMPLES", "0"),
+        )
+        .await,
+    );
+    assert!(exact > 200 * 8, "{}", exact);
+    assert!(
+        sampled.abs_diff(exact) < exact / 10,
+        "{} {}",
+        sampled,
+        exact
+    );
+
+    let stats = roundtrip(&mut stream, array_of_bulks!("MEMORY", "STATS")).await;
+    assert!(stats.contains("$10\r\nkeys.count\r\n:3\r\n"), "{}", stats);
+    assert!(stats.contains("$4\r\ndb.0\r\n"), "{}", stats);
+    assert!(
+        stats.contains("$11\r\nkeys.string\r\n:2\r\n$11\r\nsize.string\r\n:103\r\n"),
+        "{}",
+        stats
+    );
+    assert!(
+        stats.contains("$9\r\nkeys.zset\r\n:1\r\n$9\r\nsize.zset\r\n:200\r\n"),
+        "{}",
+        stats
+    );
+    let info = roundtrip(&mut stream, array_of_bulks!("INFO", "memory")).await;
+    let used = info
+        .split("\r\n")
+        .find_map(|line| line.strip_prefix("used_memory:"))
+        .unwrap();
+    assert!(
+        stats.contains(&format!("$15\r\ntotal.allocated\r\n:{}\r\n", used)),
+        "{} {}",
+        stats,
+        info
+    );
+    // collections are counted as MEMORY USAGE samples them by default
+    assert!(used.parse::<u64>()? >= short + long + sampled, "{}", used);
+
+    // the counts follow expiries and removals as they happen
+    let stats = roundtrip(&mut stream, array_of_bulks!("MEMORY", "STATS")).await;
+    assert!(
+        stats.contains("$26\r\noverhead.hashtable.expires\r\n:0\r\n"),
+        "{}",
+        stats
+    );
+    roundtrip(
+        &mut stream,
+        array_of_bulks!("PEXPIREAT", "short", "99999999999999"),
+    )
+    .await;
+    let stats = roundtrip(&mut stream, array_of_bulks!("MEMORY", "STATS")).await;
+    assert!(
+        stats.contains("$26\r\noverhead.hashtable.expires\r\n:24\r\n"),
+        "{}",
+        stats
+    );
+    roundtrip(&mut stream, array_of_bulks!("DEL", "short", "long", "zset")).await;
+    let info = roundtrip(&mut stream, array_of_bulks!("INFO", "memory")).await;
+    assert!(info.contains("\r\nused_memory:0\r\n"), "{}", info);
+    Ok(())
+}
+
+#[tokio::test]
+async fn debug_sleeps_and_toggles_active_expiry_when_enabled() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await?;
+
+    let refused = roundtrip(&mut stream, array_of_bulks!("DEBUG", "SLEEP", "0")).await;
+    assert!(refused.starts_with("-ERR DEBUG command not allowed."));
+    store.config().set_enable_debug_command("local".to_string());
+
+    let started = std::time::Instant::now();
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("DEBUG", "SLEEP", "0.2")).await,
+        "+OK\r\n"
+    );
+    assert!(started.elapsed() >= std::time::Duration::from_millis(200));
+
+    assert_eq!(
+        roundtrip(
+            &mut stream,
+            array_of_bulks!("DEBUG", "SET-ACTIVE-EXPIRE", "0")
+        )
+        .await,
+        "+OK\r\n"
+    );
+    roundtrip(&mut stream, array_of_bulks!("SET", "k", "v", "PX", "1")).await;
+    tokio::time::sleep(std::time::Duration::from_millis(1200)).await;
+    assert_eq!(store.stats().expired_keys(), 0);
+
+    roundtrip(
+        &mut stream,
+        array_of_bulks!("DEBUG", "SET-ACTIVE-EXPIRE", "1"),
+    )
+    .await;
+    tokio::time::sleep(std::time::Duration::from_millis(1200)).await;
+    assert_eq!(store.stats().expired_keys(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn dump_restore_and_migrate() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+    let (target_addr, _target) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await?;
+    let mut target_stream = TcpStream::connect(target_addr).await?;
+
+    // payloads aren't UTF-8, so these requests are built by hand
+    fn request(args: &[&[u8]]) -> Vec<u8> {
+        let mut request = format!("*{}\r\n", args.len()).into_bytes();
+        for arg in args {
+            request.extend(format!("${}\r\n", arg.len()).as_bytes());
+            request.extend(*arg);
+            request.extend(b"\r\n");
+        }
+        request
+    }
+
+    roundtrip(
+        &mut stream,
+        array_of_bulks!("ZADD", "z", "1", "a", "2", "b"),
+    )
+    .await;
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("DUMP", "missing")).await,
+        "$-1\r\n"
+    );
+    let (payload, _) = store.dump(&"z".into()).unwrap();
+    let dumped = roundtrip(&mut stream, array_of_bulks!("DUMP", "z")).await;
+    assert!(
+        dumped.starts_with(&format!("${}\r\n", payload.len())),
+        "{}",
+        dumped
+    );
+
+    let restore = request(&[b"RESTORE", b"copy", b"0", &payload]);
+    assert_eq!(roundtrip(&mut stream, &restore).await, "+OK\r\n");
+    assert_eq!(
+        roundtrip(&mut stream, &restore).await,
+        "-BUSYKEY Target key name already exists.\r\n"
+    );
+    let replace = request(&[b"RESTORE", b"copy", b"60000", &payload, b"REPLACE"]);
+    assert_eq!(roundtrip(&mut stream, &replace).await, "+OK\r\n");
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("ZRANGE", "copy", "0", "-1")).await,
+        "*2\r\n$1\r\na\r\n$1\r\nb\r\n"
+    );
+    let ttl = Cache::from_store(&store).ttl("copy").flatten().unwrap();
+    assert!(ttl > std::time::Duration::from_secs(59), "{:?}", ttl);
+    let corrupted = request(&[b"RESTORE", b"bad", b"0", &payload[1..]]);
+    assert_eq!(
+        roundtrip(&mut stream, &corrupted).await,
+        "-ERR DUMP payload version or checksum are wrong\r\n"
+    );
+
+    let port = target_addr.port().to_string();
+    let migrate = &array_of_bulks!("MIGRATE", "127.0.0.1", port, "z", "3", "1000").to_vec();
+    assert_eq!(roundtrip(&mut stream, migrate).await, "+OK\r\n");
+    assert_eq!(roundtrip(&mut stream, migrate).await, "+NOKEY\r\n");
+    roundtrip(&mut target_stream, array_of_bulks!("SELECT", "3")).await;
+    assert_eq!(
+        roundtrip(
+            &mut target_stream,
+            array_of_bulks!("ZRANGE", "z", "0", "-1")
+        )
+        .await,
+        "*2\r\n$1\r\na\r\n$1\r\nb\r\n"
+    );
+
+    // COPY keeps the key here, and REPLACE overwrites it there
+    let copy = &array_of_bulks!("MIGRATE", "127.0.0.1", port, "copy", "3", "1000", "COPY").to_vec();
+    assert_eq!(roundtrip(&mut stream, copy).await, "+OK\r\n");
+    assert_eq!(
+        roundtrip(&mut stream, copy).await,
+        "-ERR Target instance replied with error: BUSYKEY Target key name already exists.\r\n"
+    );
+    let replace =
+        &array_of_bulks!("MIGRATE", "127.0.0.1", port, "copy", "3", "1000", "REPLACE").to_vec();
+    assert_eq!(roundtrip(&mut stream, replace).await, "+OK\r\n");
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("ZRANGE", "copy", "0", "-1")).await,
+        "*0\r\n"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn a_server_handle_shuts_the_server_down() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let store = Store::new();
+    store.config().set_save(vec![]);
+    let server = Server::start(listener, store)?;
+    let addr = server.local_addr();
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(array_of_bulks!("SET", "k", "v")).await?;
+    let mut response = [0; 5];
+    stream.read_exact(&mut response).await?;
+    assert_eq!(&response, b"+OK\r\n");
+    assert_eq!(server.store().get_string(&"k".into()), Ok(Some("v".into())));
+
+    tokio::time::timeout(std::time::Duration::from_secs(5), server.shutdown()).await??;
+    assert_eq!(stream.read(&mut response).await?, 0);
+    assert!(TcpStream::connect(addr).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn idle_clients_are_disconnected_after_the_timeout() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut idle = TcpStream::connect(addr).await?;
+    let mut subscriber = TcpStream::connect(addr).await?;
+
+    assert_eq!(
+        roundtrip(&mut idle, array_of_bulks!("CONFIG", "SET", "timeout", "-1")).await,
+        "-ERR CONFIG SET failed (possibly related to argument 'timeout') - argument must be between 0 and 2147483647 inclusive\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut idle, array_of_bulks!("CONFIG", "SET", "timeout", "1")).await,
+        "+OK\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut idle, array_of_bulks!("CONFIG", "GET", "timeout")).await,
+        "*2\r\n$7\r\ntimeout\r\n$1\r\n1\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut subscriber, array_of_bulks!("SUBSCRIBE", "news")).await,
+        "*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n"
+    );
+
+    let mut buf = [0; 1];
+    let closed =
+        tokio::time::timeout(std::time::Duration::from_secs(5), idle.read(&mut buf)).await?;
+    assert_eq!(closed?, 0);
+    // subscribers wait on messages, not commands, so they are never idle
+    assert_eq!(
+        roundtrip(&mut subscriber, array_of_bulks!("PING")).await,
+        "*2\r\n$4\r\npong\r\n$0\r\n\r\n"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn maxclients_turns_away_connections_over_the_limit() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await?;
+
+    async fn refused(addr: std::net::SocketAddr) -> bool {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        response == b"-ERR max number of clients reached\r\n"
+    }
+
+    let set = roundtrip(
+        &mut stream,
+        array_of_bulks!("CONFIG", "SET", "maxclients", "2"),
+    )
+    .await;
+    assert_eq!(set, "+OK\r\n");
+    let mut other = TcpStream::connect(addr).await?;
+    assert_eq!(
+        roundtrip(&mut other, array_of_bulks!("PING")).await,
+        "+PONG\r\n"
+    );
+    assert!(refused(addr).await);
+
+    // lowering the limit keeps both clients, but the one leaving gives up its seat
+    roundtrip(
+        &mut stream,
+        array_of_bulks!("CONFIG", "SET", "maxclients", "1"),
+    )
+    .await;
+    drop(other);
+    while !roundtrip(&mut stream, array_of_bulks!("INFO", "clients"))
+        .await
+        .contains("connected_clients:1\r\n")
+    {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert!(refused(addr).await);
+
+    roundtrip(
+        &mut stream,
+        array_of_bulks!("CONFIG", "SET", "maxclients", "2"),
+    )
+    .await;
+    let mut other = TcpStream::connect(addr).await?;
+    assert_eq!(
+        roundtrip(&mut other, array_of_bulks!("PING")).await,
+        "+PONG\r\n"
+    );
+
+    let info = roundtrip(&mut stream, array_of_bulks!("INFO")).await;
+    assert!(info.contains("maxclients:2\r\n"), "{}", info);
+    assert!(info.contains("rejected_connections:2\r\n"), "{}", info);
+    Ok(())
+}
+
+#[tokio::test]
+async fn pipelined_replies_are_sent_before_a_blocking_command_waits() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await?;
+
+    let mut pipeline = Vec::new();
+    for _ in 0..100 {
+        pipeline.extend_from_slice(array_of_bulks!("ZADD", "scores", "1", "x"));
+    }
+    pipeline.extend_from_slice(array_of_bulks!("BZPOPMIN", "empty", "0"));
+    stream.write_all(&pipeline).await?;
+
+    let expected = format!(":1\r\n{}", ":0\r\n".repeat(99));
+    let mut response = vec![0; expected.len()];
+    tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        stream.read_exact(&mut response),
+    )
+    .await??;
+    assert_eq!(String::from_utf8_lossy(&response), expected);
+    Ok(())
+}
+
+#[tokio::test]
+async fn oversized_request_headers_are_refused() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await?;
+
+    async fn refused(addr: std::net::SocketAddr, request: &[u8]) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request).await.unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        String::from_utf8_lossy(&response).to_string()
+    }
+
+    assert_eq!(
+        roundtrip(
+            &mut stream,
+            array_of_bulks!("CONFIG", "SET", "proto-max-bulk-len", "1kb"),
+        )
+        .await,
+        "-ERR CONFIG SET failed (possibly related to argument 'proto-max-bulk-len') - argument must be between 1048576 and 9223372036854775807 inclusive\r\n"
+    );
+    let set = array_of_bulks!(
+        "CONFIG",
+        "SET",
+        "proto-max-bulk-len",
+        "1mb",
+        "proto-max-multibulk-len",
+        "3"
+    )
+    .to_vec();
+    assert_eq!(roundtrip(&mut stream, &set).await, "+OK\r\n");
+    assert_eq!(
+        roundtrip(
+            &mut stream,
+            array_of_bulks!("CONFIG", "GET", "proto-max-*"),
+        )
+        .await,
+        "*4\r\n$18\r\nproto-max-bulk-len\r\n$7\r\n1048576\r\n$23\r\nproto-max-multibulk-len\r\n$1\r\n3\r\n"
+    );
+
+    // the header alone is enough to refuse, with nothing buffered for it
+    assert_eq!(
+        refused(addr, b"*2\r\n$3\r\nGET\r\n$99999999999\r\n").await,
+        "-ERR Protocol error: invalid bulk length\r\n"
+    );
+    assert_eq!(
+        refused(addr, array_of_bulks!("MSET", "a", "1", "b", "2")).await,
+        "-ERR Protocol error: invalid multibulk length\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("SET", "a", "1")).await,
+        "+OK\r\n"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn clients_over_the_output_buffer_limit_are_disconnected() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await?;
+
+    assert_eq!(
+        roundtrip(
+            &mut stream,
+            array_of_bulks!("CONFIG", "GET", "client-output-buffer-limit"),
+        )
+        .await,
+        "*2\r\n$26\r\nclient-output-buffer-limit\r\n$67\r\nnormal 0 0 0 slave 268435456 67108864 60 pubsub 33554432 8388608 60\r\n"
+    );
+    assert_eq!(
+        roundtrip(
+            &mut stream,
+            array_of_bulks!("CONFIG", "SET", "client-output-buffer-limit", "other 1 1 1"),
+        )
+        .await,
+        "-ERR CONFIG SET failed (possibly related to argument 'client-output-buffer-limit') - Invalid client class specified in buffer limit configuration.\r\n"
+    );
+    let set = array_of_bulks!(
+        "CONFIG",
+        "SET",
+        "client-output-buffer-limit",
+        "normal 1kb 0 0"
+    )
+    .to_vec();
+    assert_eq!(roundtrip(&mut stream, &set).await, "+OK\r\n");
+
+    let value = "x".repeat(2000);
+    let set = array_of_bulks!("SET", "big", &value).to_vec();
+    assert_eq!(roundtrip(&mut stream, &set).await, "+OK\r\n");
+    // the reply would take the client over 1kb, so it is dropped instead
+    stream.write_all(array_of_bulks!("GET", "big")).await?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    assert!(response.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn latency_spikes_over_the_threshold_are_recorded() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+    store.config().set_enable_debug_command("local".to_string());
+    let mut stream = TcpStream::connect(addr).await?;
+
+    // the monitor is off until a threshold is set
+    roundtrip(&mut stream, array_of_bulks!("DEBUG", "SLEEP", "0.1")).await;
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("LATENCY", "LATEST")).await,
+        "*0\r\n"
+    );
+    assert_eq!(
+        roundtrip(
+            &mut stream,
+            array_of_bulks!("CONFIG", "SET", "latency-monitor-threshold", "50")
+        )
+        .await,
+        "+OK\r\n"
+    );
+    roundtrip(&mut stream, array_of_bulks!("DEBUG", "SLEEP", "0.1")).await;
+    roundtrip(&mut stream, array_of_bulks!("PING")).await;
+
+    let latest = roundtrip(&mut stream, array_of_bulks!("LATENCY", "LATEST")).await;
+    assert!(
+        latest.starts_with("*1\r\n*4\r\n$7\r\ncommand\r\n:"),
+        "{}",
+        latest
+    );
+    let history = roundtrip(
+        &mut stream,
+        array_of_bulks!("LATENCY", "HISTORY", "command"),
+    )
+    .await;
+    assert!(history.starts_with("*1\r\n*2\r\n:"), "{}", history);
+    let doctor = roundtrip(&mut stream, array_of_bulks!("LATENCY", "DOCTOR")).await;
+    assert!(
+        doctor.contains("1. command: 1 latency spikes"),
+        "{}",
+        doctor
+    );
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("LATENCY", "RESET")).await,
+        ":1\r\n"
+    );
+    assert_eq!(
+        roundtrip(
+            &mut stream,
+            array_of_bulks!("LATENCY", "HISTORY", "command")
+        )
+        .await,
+        "*0\r\n"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn reset_returns_the_connection_to_its_initial_state() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await?;
+
+    roundtrip(&mut stream, array_of_bulks!("HELLO", "3")).await;
+    roundtrip(&mut stream, array_of_bulks!("SELECT", "1")).await;
+    roundtrip(&mut stream, array_of_bulks!("CLIENT", "TRACKING", "ON")).await;
+    roundtrip(&mut stream, array_of_bulks!("WATCH", "k")).await;
+    roundtrip(&mut stream, array_of_bulks!("MULTI")).await;
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("RESET")).await,
+        "+RESET\r\n"
+    );
+    // out of MULTI, on database 0, under RESP2
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("SET", "k", "v")).await,
+        "+OK\r\n"
+    );
+    assert_eq!(store.get_string(&"k".into()), Ok(Some("v".into())));
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("CONFIG", "GET", "timeout")).await,
+        "*2\r\n$7\r\ntimeout\r\n$1\r\n0\r\n"
+    );
+    let info = roundtrip(&mut stream, array_of_bulks!("CLIENT", "INFO")).await;
+    assert!(info.contains(" flags=N "), "{}", info);
+
+    roundtrip(&mut stream, array_of_bulks!("SUBSCRIBE", "news")).await;
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("RESET")).await,
+        "+RESET\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut stream, array_of_bulks!("GET", "k")).await,
+        "$1\r\nv\r\n"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn client_reply_holds_back_replies() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await?;
+
+    let pipeline = [
+        array_of_bulks!("CLIENT", "REPLY", "OFF").to_vec(),
+        array_of_bulks!("SET", "a", "1").to_vec(),
+        array_of_bulks!("NOSUCHCOMMAND").to_vec(),
+        array_of_bulks!("CLIENT", "REPLY", "SKIP").to_vec(),
+        array_of_bulks!("CLIENT", "REPLY", "ON").to_vec(),
+    ]
+    .concat();
+    assert_eq!(roundtrip(&mut stream, &pipeline).await, "+OK\r\n");
+    assert_eq!(store.get_string(&"a".into()), Ok(Some("1".into())));
+
+    let pipeline = [
+        array_of_bulks!("CLIENT", "REPLY", "SKIP").to_vec(),
+        array_of_bulks!("GET", "a").to_vec(),
+        array_of_bulks!("PING").to_vec(),
+    ]
+    .concat();
+    assert_eq!(roundtrip(&mut stream, &pipeline).await, "+PONG\r\n");
+    Ok(())
+}
@@ -0,0 +1,428 @@
+use redis_starter_rust::array_of_bulks;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+mod common;
+use common::{roundtrip, start_server};
+
+#[tokio::test]
+async fn zincrby_zcard_zcount() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    stream
+        .write_all(array_of_bulks!("ZINCRBY", "zs", "1.5", "a"))
+        .await
+        .unwrap();
+    let mut response = [0; 9];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"$3\r\n1.5\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("ZINCRBY", "zs", "2", "a"))
+        .await
+        .unwrap();
+    let mut response = [0; 9];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"$3\r\n3.5\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("ZINCRBY", "zs", "10", "b"))
+        .await
+        .unwrap();
+    let mut response = [0; 8];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"$2\r\n10\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("ZCARD", "zs"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":2\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("ZCOUNT", "zs", "(3.5", "+inf"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":1\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("ZLEXCOUNT", "zs", "-", "+"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":2\r\n", &response);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn zincrby_wrong_type() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+    store.set_with_default_expiry("hello".into(), "world".into());
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    stream
+        .write_all(array_of_bulks!("ZINCRBY", "hello", "1", "a"))
+        .await
+        .unwrap();
+    let expected = b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n";
+    let mut response = [0; 68];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn zpopmin_zpopmax() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    for (score, member) in [("1", "a"), ("2", "b"), ("3", "c")] {
+        stream
+            .write_all(array_of_bulks!("ZINCRBY", "zs", score, member))
+            .await
+            .unwrap();
+        let mut response = [0; 7];
+        stream.read_exact(&mut response).await.unwrap();
+    }
+
+    stream
+        .write_all(array_of_bulks!("ZPOPMIN", "zs", "2"))
+        .await
+        .unwrap();
+    let expected = b"*4\r\n$1\r\na\r\n$1\r\n1\r\n$1\r\nb\r\n$1\r\n2\r\n";
+    let mut response = [0; 32];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    stream
+        .write_all(array_of_bulks!("ZPOPMAX", "zs"))
+        .await
+        .unwrap();
+    let expected = b"*2\r\n$1\r\nc\r\n$1\r\n3\r\n";
+    let mut response = [0; 18];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    stream
+        .write_all(array_of_bulks!("ZPOPMAX", "zs"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"*0\r\n", &response);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn bzpopmin_wakes_on_write() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+
+    let mut blocked = TcpStream::connect(addr).await.unwrap();
+    let mut writer = TcpStream::connect(addr).await.unwrap();
+
+    blocked
+        .write_all(array_of_bulks!("BZPOPMIN", "zs1", "zs2", "0"))
+        .await
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    writer
+        .write_all(array_of_bulks!("ZINCRBY", "zs2", "5", "m"))
+        .await
+        .unwrap();
+    let mut response = [0; 7];
+    writer.read_exact(&mut response).await.unwrap();
+
+    let expected = b"*3\r\n$3\r\nzs2\r\n$1\r\nm\r\n$1\r\n5\r\n";
+    let mut response = [0; 27];
+    blocked.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn bzpopmax_times_out() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    stream
+        .write_all(array_of_bulks!("BZPOPMAX", "zs", "0.1"))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"*-1\r\n", &response);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn zunionstore_with_weights_and_aggregate() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    stream
+        .write_all(array_of_bulks!("ZADD", "a", "1", "x", "2", "y"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":2\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("ZADD", "b", "3", "y", "4", "z"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+
+    stream
+        .write_all(array_of_bulks!(
+            "ZUNIONSTORE",
+            "out",
+            "2",
+            "a",
+            "b",
+            "WEIGHTS",
+            "1",
+            "2",
+            "AGGREGATE",
+            "MAX"
+        ))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":3\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("ZCOUNT", "out", "6", "8"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":2\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("ZINTERSTORE", "out", "2", "a", "b"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":1\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("ZPOPMIN", "out"))
+        .await
+        .unwrap();
+    let expected = b"*2\r\n$1\r\ny\r\n$1\r\n5\r\n";
+    let mut response = [0; 18];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    stream
+        .write_all(array_of_bulks!("ZDIFFSTORE", "out", "2", "b", "a"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":1\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("ZDIFFSTORE", "out", "2", "a", "a"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":0\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("DEL", "out", "a"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":1\r\n", &response);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn zrange_and_zrangestore() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    stream
+        .write_all(array_of_bulks!(
+            "ZADD", "zs", "1", "a", "2", "b", "3", "c", "4", "d"
+        ))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":4\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("ZRANGE", "zs", "-2", "-1", "WITHSCORES"))
+        .await
+        .unwrap();
+    let expected = b"*4\r\n$1\r\nc\r\n$1\r\n3\r\n$1\r\nd\r\n$1\r\n4\r\n";
+    let mut response = [0; 32];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    stream
+        .write_all(array_of_bulks!(
+            "ZRANGE", "zs", "+inf", "(1", "BYSCORE", "REV", "LIMIT", "1", "2"
+        ))
+        .await
+        .unwrap();
+    let expected = b"*2\r\n$1\r\nc\r\n$1\r\nb\r\n";
+    let mut response = [0; 18];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    stream
+        .write_all(array_of_bulks!(
+            "ZRANGESTORE",
+            "dst",
+            "zs",
+            "[b",
+            "[c",
+            "BYLEX"
+        ))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":2\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("ZRANGESTORE", "dst", "zs", "0", "2", "REV"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":3\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("ZRANGE", "dst", "0", "-1"))
+        .await
+        .unwrap();
+    let expected = b"*3\r\n$1\r\nb\r\n$1\r\nc\r\n$1\r\nd\r\n";
+    let mut response = [0; 25];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    stream
+        .write_all(array_of_bulks!(
+            "ZRANGESTORE",
+            "dst",
+            "zs",
+            "x",
+            "1",
+            "BYSCORE"
+        ))
+        .await
+        .unwrap();
+    let expected = b"-ERR min or max is not a float\r\n";
+    let mut response = [0; 32];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn zadd_flags() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut stream = TcpStream::connect(addr).await?;
+
+    for (request, reply) in [
+        (array_of_bulks!("ZADD", "z", "1", "a", "2", "b"), ":2\r\n"),
+        (
+            array_of_bulks!("ZADD", "z", "nx", "5", "a", "3", "c"),
+            ":1\r\n",
+        ),
+        (
+            array_of_bulks!("ZADD", "z", "XX", "5", "a", "4", "d"),
+            ":0\r\n",
+        ),
+        (array_of_bulks!("ZCARD", "z"), ":3\r\n"),
+        (
+            array_of_bulks!("ZADD", "z", "XX", "CH", "6", "a", "2", "b"),
+            ":1\r\n",
+        ),
+        (
+            array_of_bulks!("ZADD", "z", "GT", "CH", "1", "a", "7", "b"),
+            ":1\r\n",
+        ),
+        (
+            array_of_bulks!("ZADD", "z", "LT", "CH", "1", "a", "9", "b"),
+            ":1\r\n",
+        ),
+        (
+            array_of_bulks!("ZRANGE", "z", "0", "-1", "WITHSCORES"),
+            "*6\r\n$1\r\na\r\n$1\r\n1\r\n$1\r\nc\r\n$1\r\n3\r\n$1\r\nb\r\n$1\r\n7\r\n",
+        ),
+        (
+            array_of_bulks!("ZADD", "z", "INCR", "2.5", "a"),
+            "$3\r\n3.5\r\n",
+        ),
+        (
+            array_of_bulks!("ZADD", "z", "INCR", "0", "a"),
+            "$3\r\n3.5\r\n",
+        ),
+        (
+            array_of_bulks!("ZADD", "z", "GT", "INCR", "-1", "a"),
+            "$-1\r\n",
+        ),
+        (
+            array_of_bulks!("ZADD", "z", "NX", "INCR", "1", "a"),
+            "$-1\r\n",
+        ),
+        (
+            array_of_bulks!("ZADD", "z", "XX", "INCR", "1", "e"),
+            "$-1\r\n",
+        ),
+        (
+            array_of_bulks!("ZADD", "z", "NX", "XX", "1", "a"),
+            "-ERR XX and NX options at the same time are not compatible\r\n",
+        ),
+        (
+            array_of_bulks!("ZADD", "z", "GT", "LT", "1", "a"),
+            "-ERR GT, LT, and/or NX options at the same time are not compatible\r\n",
+        ),
+        (
+            array_of_bulks!("ZADD", "z", "INCR", "1", "a", "2", "b"),
+            "-ERR INCR option supports a single increment-element pair\r\n",
+        ),
+        (
+            array_of_bulks!("ZADD", "z", "1", "a", "2"),
+            "-ERR syntax error\r\n",
+        ),
+        (
+            array_of_bulks!("ZADD", "z", "one", "a"),
+            "-ERR value is not a valid float\r\n",
+        ),
+    ] {
+        assert_eq!(roundtrip(&mut stream, request).await, reply);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,504 @@
+use redis_starter_rust::array_of_bulks;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+mod common;
+use common::start_server;
+
+#[tokio::test]
+async fn xadd_ids() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    stream
+        .write_all(array_of_bulks!("XADD", "s", "1-1", "f", "v"))
+        .await
+        .unwrap();
+    let mut response = [0; 9];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"$3\r\n1-1\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("XADD", "s", "1-*", "f", "v"))
+        .await
+        .unwrap();
+    let mut response = [0; 9];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"$3\r\n1-2\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("XADD", "s", "1-2", "f", "v"))
+        .await
+        .unwrap();
+    let expected =
+        b"-ERR The ID specified in XADD is equal or smaller than the target stream top item\r\n";
+    let mut response = [0; 83];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    stream
+        .write_all(array_of_bulks!("XADD", "s", "0-0", "f", "v"))
+        .await
+        .unwrap();
+    let expected = b"-ERR The ID specified in XADD must be greater than 0-0\r\n";
+    let mut response = [0; 56];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    stream
+        .write_all(array_of_bulks!("XADD", "s", "x-1", "f", "v"))
+        .await
+        .unwrap();
+    let expected = b"-ERR Invalid stream ID specified as stream command argument\r\n";
+    let mut response = [0; 61];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    stream
+        .write_all(array_of_bulks!(
+            "XADD",
+            "missing",
+            "NOMKSTREAM",
+            "*",
+            "f",
+            "v"
+        ))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"$-1\r\n", &response);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn xadd_auto_id() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    stream
+        .write_all(array_of_bulks!("XADD", "s", "*", "f", "v"))
+        .await
+        .unwrap();
+    // $<len>\r\n<13 digit ms>-0\r\n
+    let mut response = [0; 22];
+    stream.read_exact(&mut response).await.unwrap();
+    let response = String::from_utf8(response.to_vec())?;
+    assert!(response.starts_with("$15\r\n"));
+    assert!(response.ends_with("-0\r\n"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn xrange_and_xrevrange() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    for id in ["1-1", "1-2", "2-1"] {
+        stream
+            .write_all(array_of_bulks!("XADD", "s", id, "f", "v"))
+            .await
+            .unwrap();
+        let mut response = [0; 9];
+        stream.read_exact(&mut response).await.unwrap();
+    }
+
+    stream
+        .write_all(array_of_bulks!("XRANGE", "s", "(1-1", "+"))
+        .await
+        .unwrap();
+    let expected = b"*2\r\n*2\r\n$3\r\n1-2\r\n*2\r\n$1\r\nf\r\n$1\r\nv\r\n*2\r\n$3\r\n2-1\r\n*2\r\n$1\r\nf\r\n$1\r\nv\r\n";
+    let mut response = [0; 66];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    stream
+        .write_all(array_of_bulks!("XREVRANGE", "s", "1", "-", "COUNT", "1"))
+        .await
+        .unwrap();
+    let expected = b"*1\r\n*2\r\n$3\r\n1-2\r\n*2\r\n$1\r\nf\r\n$1\r\nv\r\n";
+    let mut response = [0; 35];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    stream
+        .write_all(array_of_bulks!("XRANGE", "s", "3", "+"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"*0\r\n", &response);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn xlen_xdel_xtrim() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    for id in ["1-1", "1-2", "1-3", "1-4"] {
+        stream
+            .write_all(array_of_bulks!("XADD", "s", id, "f", "v"))
+            .await
+            .unwrap();
+        let mut response = [0; 9];
+        stream.read_exact(&mut response).await.unwrap();
+    }
+
+    stream
+        .write_all(array_of_bulks!(
+            "XADD", "s", "MAXLEN", "=", "3", "1-5", "f", "v"
+        ))
+        .await
+        .unwrap();
+    let mut response = [0; 9];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"$3\r\n1-5\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("XLEN", "s"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":3\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("XDEL", "s", "1-3", "1-1"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":1\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!(
+            "XTRIM", "s", "MINID", "~", "1-5", "LIMIT", "10"
+        ))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":1\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("XTRIM", "s", "MAXLEN", "-1"))
+        .await
+        .unwrap();
+    let expected = b"-ERR The MAXLEN argument must be >= 0.\r\n";
+    let mut response = [0; 40];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    stream
+        .write_all(array_of_bulks!("XLEN", "s"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":1\r\n", &response);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn xgroup_xreadgroup_xack() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    stream
+        .write_all(array_of_bulks!(
+            "XGROUP", "CREATE", "s", "g", "$", "MKSTREAM"
+        ))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"+OK\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("XGROUP", "CREATE", "s", "g", "$"))
+        .await
+        .unwrap();
+    let expected = b"-BUSYGROUP Consumer Group name already exists\r\n";
+    let mut response = [0; 47];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    for id in ["1-1", "1-2"] {
+        stream
+            .write_all(array_of_bulks!("XADD", "s", id, "f", "v"))
+            .await
+            .unwrap();
+        let mut response = [0; 9];
+        stream.read_exact(&mut response).await.unwrap();
+    }
+
+    let first = b"*1\r\n*2\r\n$1\r\ns\r\n*1\r\n*2\r\n$3\r\n1-1\r\n*2\r\n$1\r\nf\r\n$1\r\nv\r\n";
+    stream
+        .write_all(array_of_bulks!(
+            "XREADGROUP",
+            "GROUP",
+            "g",
+            "alice",
+            "COUNT",
+            "1",
+            "STREAMS",
+            "s",
+            ">"
+        ))
+        .await
+        .unwrap();
+    let mut response = [0; 50];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(first, &response);
+
+    // reading history returns what is pending for the consumer
+    stream
+        .write_all(array_of_bulks!(
+            "XREADGROUP",
+            "GROUP",
+            "g",
+            "alice",
+            "STREAMS",
+            "s",
+            "0"
+        ))
+        .await
+        .unwrap();
+    let mut response = [0; 50];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(first, &response);
+
+    stream
+        .write_all(array_of_bulks!("XACK", "s", "g", "1-1", "1-1"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":1\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!(
+            "XREADGROUP",
+            "GROUP",
+            "g",
+            "bob",
+            "STREAMS",
+            "s",
+            ">"
+        ))
+        .await
+        .unwrap();
+    let expected = b"*1\r\n*2\r\n$1\r\ns\r\n*1\r\n*2\r\n$3\r\n1-2\r\n*2\r\n$1\r\nf\r\n$1\r\nv\r\n";
+    let mut response = [0; 50];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    stream
+        .write_all(array_of_bulks!(
+            "XREADGROUP",
+            "GROUP",
+            "g",
+            "bob",
+            "STREAMS",
+            "s",
+            ">"
+        ))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"*-1\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!(
+            "XREADGROUP",
+            "GROUP",
+            "nope",
+            "bob",
+            "STREAMS",
+            "s",
+            ">"
+        ))
+        .await
+        .unwrap();
+    let expected =
+        b"-NOGROUP No such key 's' or consumer group 'nope' in XREADGROUP with GROUP option\r\n";
+    let mut response = [0; 83];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    stream
+        .write_all(array_of_bulks!("XGROUP", "DESTROY", "s", "g"))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b":1\r\n", &response);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn xreadgroup_blocks_until_xadd() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+
+    let mut reader = TcpStream::connect(addr).await.unwrap();
+    let mut writer = TcpStream::connect(addr).await.unwrap();
+
+    reader
+        .write_all(array_of_bulks!(
+            "XGROUP", "CREATE", "s", "g", "0", "MKSTREAM"
+        ))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    reader.read_exact(&mut response).await.unwrap();
+
+    reader
+        .write_all(array_of_bulks!(
+            "XREADGROUP",
+            "GROUP",
+            "g",
+            "alice",
+            "BLOCK",
+            "0",
+            "STREAMS",
+            "s",
+            ">"
+        ))
+        .await
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+    writer
+        .write_all(array_of_bulks!("XADD", "s", "1-1", "f", "v"))
+        .await
+        .unwrap();
+    let mut response = [0; 9];
+    writer.read_exact(&mut response).await.unwrap();
+
+    let expected = b"*1\r\n*2\r\n$1\r\ns\r\n*1\r\n*2\r\n$3\r\n1-1\r\n*2\r\n$1\r\nf\r\n$1\r\nv\r\n";
+    let mut response = [0; 50];
+    reader.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn xpending_xclaim_xautoclaim() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    stream
+        .write_all(array_of_bulks!(
+            "XGROUP", "CREATE", "s", "g", "0", "MKSTREAM"
+        ))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    stream.read_exact(&mut response).await.unwrap();
+
+    for id in ["1-1", "1-2"] {
+        stream
+            .write_all(array_of_bulks!("XADD", "s", id, "f", "v"))
+            .await
+            .unwrap();
+        let mut response = [0; 9];
+        stream.read_exact(&mut response).await.unwrap();
+    }
+
+    stream
+        .write_all(array_of_bulks!(
+            "XREADGROUP",
+            "GROUP",
+            "g",
+            "alice",
+            "STREAMS",
+            "s",
+            ">"
+        ))
+        .await
+        .unwrap();
+    let mut response = [0; 81];
+    stream.read_exact(&mut response).await.unwrap();
+
+    stream
+        .write_all(array_of_bulks!("XPENDING", "s", "g"))
+        .await
+        .unwrap();
+    let expected = b"*4\r\n:2\r\n$3\r\n1-1\r\n$3\r\n1-2\r\n*1\r\n*2\r\n$5\r\nalice\r\n$1\r\n2\r\n";
+    let mut response = [0; 52];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    stream
+        .write_all(array_of_bulks!(
+            "XCLAIM", "s", "g", "bob", "0", "1-1", "JUSTID"
+        ))
+        .await
+        .unwrap();
+    let mut response = [0; 13];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"*1\r\n$3\r\n1-1\r\n", &response);
+
+    // not idle for long enough to be claimed
+    stream
+        .write_all(array_of_bulks!(
+            "XCLAIM", "s", "g", "bob", "3600000", "1-2", "JUSTID"
+        ))
+        .await
+        .unwrap();
+    let mut response = [0; 4];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"*0\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!(
+            "XAUTOCLAIM",
+            "s",
+            "g",
+            "bob",
+            "0",
+            "0",
+            "COUNT",
+            "1",
+            "JUSTID"
+        ))
+        .await
+        .unwrap();
+    let expected = b"*3\r\n$3\r\n1-2\r\n*1\r\n$3\r\n1-1\r\n*0\r\n";
+    let mut response = [0; 30];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    stream
+        .write_all(array_of_bulks!("XPENDING", "s", "g"))
+        .await
+        .unwrap();
+    let expected = b"*4\r\n:2\r\n$3\r\n1-1\r\n$3\r\n1-2\r\n*2\r\n*2\r\n$5\r\nalice\r\n$1\r\n1\r\n*2\r\n$3\r\nbob\r\n$1\r\n1\r\n";
+    let mut response = [0; 72];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    stream
+        .write_all(array_of_bulks!("XPENDING", "s", "nope"))
+        .await
+        .unwrap();
+    let expected = b"-NOGROUP No such key 's' or consumer group 'nope'\r\n";
+    let mut response = [0; 51];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    Ok(())
+}
@@ -0,0 +1,143 @@
+#![cfg(feature = "tls")]
+
+use redis_starter_rust::{
+    array_of_bulks,
+    info::Info,
+    server::{self, TlsListener},
+    store::Store,
+    tls::{AuthClients, TlsFiles},
+};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::rustls::{
+    pki_types::{pem::PemObject, CertificateDer},
+    ClientConfig, RootCertStore,
+};
+use tokio_rustls::TlsConnector;
+mod common;
+use common::roundtrip;
+
+fn fixture(name: &str) -> String {
+    format!("{}/tests/tls/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+/// The server's certificate, which it also shows its master as a replica.
+fn server_files() -> TlsFiles {
+    TlsFiles {
+        cert_file: fixture("server.crt"),
+        key_file: fixture("server.key"),
+        ca_cert_file: fixture("ca.crt"),
+        auth_clients: AuthClients::Yes,
+    }
+}
+
+fn client_files() -> TlsFiles {
+    TlsFiles {
+        cert_file: fixture("client.crt"),
+        key_file: fixture("client.key"),
+        ..server_files()
+    }
+}
+
+/// Starts a server with a TLS listener, returning its plain and TLS addresses.
+async fn start_tls_server(store: Store) -> (SocketAddr, SocketAddr) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let tls = TlsListener {
+        listener: TcpListener::bind("127.0.0.1:0").await.unwrap(),
+        acceptor: server_files().acceptor().unwrap(),
+    };
+    let addresses = (
+        listener.local_addr().unwrap(),
+        tls.listener.local_addr().unwrap(),
+    );
+    tokio::spawn(async move {
+        server::run_with_tls(listener, Some(tls), store, std::future::pending::<()>()).await
+    });
+    addresses
+}
+
+#[tokio::test]
+async fn tls_clients_must_present_a_certificate_from_the_ca() -> anyhow::Result<()> {
+    let (addr, tls_addr) = start_tls_server(Store::new()).await;
+
+    let socket = TcpStream::connect(tls_addr).await?;
+    let mut client = client_files()
+        .connector()?
+        .connect("localhost", socket)
+        .await?;
+    assert_eq!(
+        roundtrip(&mut client, array_of_bulks!("SET", "k", "v")).await,
+        "+OK\r\n"
+    );
+
+    // the plain port serves the same keyspace
+    let mut plain = TcpStream::connect(addr).await?;
+    assert_eq!(
+        roundtrip(&mut plain, array_of_bulks!("GET", "k")).await,
+        "$1\r\nv\r\n"
+    );
+
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_file_iter(fixture("ca.crt"))? {
+        roots.add(cert?)?;
+    }
+    let anonymous = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let socket = TcpStream::connect(tls_addr).await?;
+    // with TLS 1.3 the refusal only shows once the client reads
+    let refused = match TlsConnector::from(Arc::new(anonymous))
+        .connect("localhost".try_into()?, socket)
+        .await
+    {
+        Ok(mut stream) => {
+            stream.write_all(array_of_bulks!("PING")).await?;
+            let mut response = [0; 16];
+            !matches!(stream.read(&mut response).await, Ok(n) if n > 0)
+        }
+        Err(_) => true,
+    };
+    assert!(refused);
+    Ok(())
+}
+
+#[tokio::test]
+async fn replicas_follow_a_master_over_tls() -> anyhow::Result<()> {
+    let master = Store::new();
+    let (_, master_tls) = start_tls_server(master.clone()).await;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let replica = Store::new();
+    replica.set_replication_tls(Some(server_files().connector()?));
+    Info::builder()
+        .self_port(Some(listener.local_addr()?.port()))
+        .replication_role(Some("slave".to_string()))
+        .replication_of_host(Some(master_tls.ip().to_string()))
+        .replication_of_port(Some(master_tls.port()))
+        .build()
+        .write(&replica)?;
+    let server_store = replica.clone();
+    tokio::spawn(
+        async move { server::run(listener, server_store, std::future::pending::<()>()).await },
+    );
+
+    let socket = TcpStream::connect(master_tls).await?;
+    let mut client = client_files()
+        .connector()?
+        .connect("localhost", socket)
+        .await?;
+    assert_eq!(
+        roundtrip(&mut client, array_of_bulks!("SET", "k", "v")).await,
+        "+OK\r\n"
+    );
+    for _ in 0..200 {
+        if replica.get_string(&"k".into()) == Ok(Some("v".into())) {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("the replica never got k");
+}
@@ -0,0 +1,107 @@
+use redis_starter_rust::array_of_bulks;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+mod common;
+use common::{roundtrip, start_server};
+
+async fn read_exactly(stream: &mut TcpStream, expected: &str) -> String {
+    let mut response = vec![0; expected.len()];
+    stream.read_exact(&mut response).await.unwrap();
+    String::from_utf8_lossy(&response).to_string()
+}
+
+#[tokio::test]
+async fn resp3_clients_are_pushed_invalidations() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut client = TcpStream::connect(addr).await?;
+    let mut writer = TcpStream::connect(addr).await?;
+
+    assert_eq!(
+        roundtrip(&mut client, array_of_bulks!("HELLO", "4")).await,
+        "-NOPROTO unsupported protocol version\r\n"
+    );
+    let hello = roundtrip(&mut client, array_of_bulks!("HELLO", "3")).await;
+    assert!(
+        hello.starts_with("%7\r\n$6\r\nserver\r\n$5\r\nredis\r\n"),
+        "{}",
+        hello
+    );
+    assert!(hello.contains("$5\r\nproto\r\n:3\r\n"), "{}", hello);
+    assert_eq!(
+        roundtrip(&mut client, array_of_bulks!("CLIENT", "TRACKING", "ON")).await,
+        "+OK\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut client, array_of_bulks!("GET", "k")).await,
+        "$-1\r\n"
+    );
+
+    assert_eq!(
+        roundtrip(&mut writer, array_of_bulks!("SET", "k", "v")).await,
+        "+OK\r\n"
+    );
+    let expected = ">2\r\n$10\r\ninvalidate\r\n*1\r\n$1\r\nk\r\n";
+    assert_eq!(read_exactly(&mut client, expected).await, expected);
+
+    // the key has to be read again before another change is sent
+    assert_eq!(
+        roundtrip(&mut writer, array_of_bulks!("SET", "k", "w")).await,
+        "+OK\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut client, array_of_bulks!("GET", "k")).await,
+        "$1\r\nw\r\n"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn resp2_clients_redirect_invalidations_to_a_subscriber() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+    let mut subscriber = TcpStream::connect(addr).await?;
+    let mut client = TcpStream::connect(addr).await?;
+
+    let id = roundtrip(&mut subscriber, array_of_bulks!("CLIENT", "ID")).await;
+    let id = id.trim_start_matches(':').trim_end().to_string();
+    let expected = "*3\r\n$9\r\nsubscribe\r\n$20\r\n__redis__:invalidate\r\n:1\r\n";
+    subscriber
+        .write_all(array_of_bulks!("SUBSCRIBE", "__redis__:invalidate"))
+        .await?;
+    assert_eq!(read_exactly(&mut subscriber, expected).await, expected);
+
+    assert_eq!(
+        roundtrip(
+            &mut client,
+            array_of_bulks!("CLIENT", "TRACKING", "ON", "PREFIX", "user:"),
+        )
+        .await,
+        "-ERR PREFIX option requires BCAST mode to be enabled\r\n"
+    );
+    let track = array_of_bulks!("CLIENT", "TRACKING", "ON", "REDIRECT", &id).to_vec();
+    assert_eq!(roundtrip(&mut client, &track).await, "+OK\r\n");
+    assert_eq!(
+        roundtrip(&mut client, array_of_bulks!("CLIENT", "GETREDIR")).await,
+        format!(":{}\r\n", id)
+    );
+    assert_eq!(
+        roundtrip(&mut client, array_of_bulks!("GET", "k")).await,
+        "$-1\r\n"
+    );
+    // without NOLOOP a client hears about its own writes too
+    assert_eq!(
+        roundtrip(&mut client, array_of_bulks!("SET", "k", "v")).await,
+        "+OK\r\n"
+    );
+    let expected = "*3\r\n$7\r\nmessage\r\n$20\r\n__redis__:invalidate\r\n*1\r\n$1\r\nk\r\n";
+    assert_eq!(read_exactly(&mut subscriber, expected).await, expected);
+
+    assert_eq!(
+        roundtrip(&mut client, array_of_bulks!("CLIENT", "TRACKING", "OFF")).await,
+        "+OK\r\n"
+    );
+    assert_eq!(
+        roundtrip(&mut client, array_of_bulks!("CLIENT", "GETREDIR")).await,
+        ":-1\r\n"
+    );
+    Ok(())
+}
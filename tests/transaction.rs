@@ -0,0 +1,178 @@
+use redis_starter_rust::array_of_bulks;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+mod common;
+use common::start_server;
+
+#[tokio::test]
+async fn multi_exec() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    stream.write_all(array_of_bulks!("MULTI")).await.unwrap();
+    let mut response = [0; 5];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"+OK\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("SET", "foo", "bar"))
+        .await
+        .unwrap();
+    let mut response = [0; 9];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"+QUEUED\r\n", &response);
+
+    stream
+        .write_all(array_of_bulks!("GET", "foo"))
+        .await
+        .unwrap();
+    let mut response = [0; 9];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"+QUEUED\r\n", &response);
+
+    stream.write_all(array_of_bulks!("EXEC")).await.unwrap();
+    let mut response = [0; 18];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"*2\r\n+OK\r\n$3\r\nbar\r\n", &response);
+
+    stream.write_all(array_of_bulks!("EXEC")).await.unwrap();
+    let mut response = [0; 25];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"-ERR EXEC without MULTI\r\n", &response);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn multi_discard_and_execabort() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    stream.write_all(array_of_bulks!("MULTI")).await.unwrap();
+    let mut response = [0; 5];
+    stream.read_exact(&mut response).await.unwrap();
+    stream
+        .write_all(array_of_bulks!("SET", "foo", "bar"))
+        .await
+        .unwrap();
+    let mut response = [0; 9];
+    stream.read_exact(&mut response).await.unwrap();
+    stream.write_all(array_of_bulks!("DISCARD")).await.unwrap();
+    let mut response = [0; 5];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"+OK\r\n", &response);
+    assert_eq!(store.get_string(&"foo".into()), Ok(None));
+
+    // a command that fails to parse aborts the transaction
+    stream.write_all(array_of_bulks!("MULTI")).await.unwrap();
+    let mut response = [0; 5];
+    stream.read_exact(&mut response).await.unwrap();
+    stream
+        .write_all(array_of_bulks!("SET", "foo"))
+        .await
+        .unwrap();
+    let mut response = [0; 50];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(
+        b"-ERR wrong number of arguments for 'set' command\r\n",
+        &response
+    );
+    stream
+        .write_all(array_of_bulks!("SET", "foo", "bar"))
+        .await
+        .unwrap();
+    let mut response = [0; 9];
+    stream.read_exact(&mut response).await.unwrap();
+    stream.write_all(array_of_bulks!("EXEC")).await.unwrap();
+    let mut response = [0; 62];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(
+        b"-EXECABORT Transaction discarded because of previous errors.\r\n",
+        &response
+    );
+    assert_eq!(store.get_string(&"foo".into()), Ok(None));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn watch_fails_exec_after_change() -> anyhow::Result<()> {
+    let (addr, store) = start_server().await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let mut other = TcpStream::connect(addr).await.unwrap();
+
+    for (command, expected) in [
+        (array_of_bulks!("WATCH", "foo").to_vec(), &b"+OK\r\n"[..]),
+        (array_of_bulks!("MULTI").to_vec(), b"+OK\r\n"),
+        (array_of_bulks!("SET", "foo", "a").to_vec(), b"+QUEUED\r\n"),
+    ] {
+        stream.write_all(&command).await.unwrap();
+        let mut response = vec![0; expected.len()];
+        stream.read_exact(&mut response).await.unwrap();
+        assert_eq!(expected, &response);
+    }
+
+    other
+        .write_all(array_of_bulks!("SET", "foo", "b"))
+        .await
+        .unwrap();
+    let mut response = [0; 5];
+    other.read_exact(&mut response).await.unwrap();
+
+    stream.write_all(array_of_bulks!("EXEC")).await.unwrap();
+    let mut response = [0; 5];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"*-1\r\n", &response);
+    assert_eq!(store.get_string(&"foo".into()), Ok(Some("b".into())));
+
+    // EXEC unwatched the key, so the next transaction goes through
+    stream.write_all(array_of_bulks!("MULTI")).await.unwrap();
+    let mut response = [0; 5];
+    stream.read_exact(&mut response).await.unwrap();
+    stream
+        .write_all(array_of_bulks!("SET", "foo", "c"))
+        .await
+        .unwrap();
+    let mut response = [0; 9];
+    stream.read_exact(&mut response).await.unwrap();
+    stream.write_all(array_of_bulks!("EXEC")).await.unwrap();
+    let mut response = [0; 9];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(b"*1\r\n+OK\r\n", &response);
+    assert_eq!(store.get_string(&"foo".into()), Ok(Some("c".into())));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn exec_reports_errors_per_command() -> anyhow::Result<()> {
+    let (addr, _store) = start_server().await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    for command in [
+        array_of_bulks!("MULTI").to_vec(),
+        array_of_bulks!("SET", "foo", "bar").to_vec(),
+        array_of_bulks!("ZADD", "foo", "1", "a").to_vec(),
+        array_of_bulks!("GET", "foo").to_vec(),
+    ] {
+        stream.write_all(&command).await.unwrap();
+    }
+    let expected = b"+OK\r\n+QUEUED\r\n+QUEUED\r\n+QUEUED\r\n";
+    let mut response = [0; 32];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    // the failed ZADD doesn't stop the GET after it
+    stream.write_all(array_of_bulks!("EXEC")).await.unwrap();
+    let expected =
+        b"*3\r\n+OK\r\n-WRONGTYPE Operation against a key holding the wrong kind of value\r\n$3\r\nbar\r\n";
+    let mut response = [0; 86];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(expected, &response);
+
+    Ok(())
+}